@@ -0,0 +1,167 @@
+//! Model aliases
+//!
+//! An alias is a name such as `"fast"` or `"claude-sonnet-latest"` that
+//! stands for a concrete model. Code asks for the alias, and configuration
+//! decides which model it means, so a deployment can move to a newer model
+//! without a code change. An alias points at a model ID or at another alias.
+//!
+//! A few aliases are built in (see [`BUILT_IN`]); more can be added at
+//! startup with [`register_alias`] or loaded from a JSON config with
+//! [`load_aliases`]. A registered alias replaces a built-in one of the same
+//! name.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::alias::{load_aliases, resolve_model};
+//! use language_barrier_core::model::{AnyModel, OpenAi};
+//!
+//! load_aliases(r#"{ "summarizer": "fast", "fast": "gpt-4o-mini" }"#).unwrap();
+//!
+//! assert_eq!(resolve_model("summarizer").unwrap(), AnyModel::OpenAi(OpenAi::GPT4oMini));
+//! assert_eq!(resolve_model("gpt-4o").unwrap(), AnyModel::OpenAi(OpenAi::GPT4o));
+//!
+//! let model: OpenAi = resolve_model("summarizer").unwrap().try_into().unwrap();
+//! assert_eq!(model, OpenAi::GPT4oMini);
+//! ```
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use tracing::debug;
+
+use crate::error::{ChatConfigError, Error, Result};
+use crate::model::AnyModel;
+
+/// The built-in aliases and the names they point at
+pub const BUILT_IN: &[(&str, &str)] = &[
+    ("claude-sonnet-latest", "claude-3-7-sonnet-latest"),
+    ("claude-haiku-latest", "claude-3-5-haiku-latest"),
+    ("gpt-latest", "gpt-4o"),
+    ("gemini-flash-latest", "gemini-2.0-flash"),
+    ("mistral-latest", "mistral-large-latest"),
+    ("fast", "gpt-4o-mini"),
+    ("smart", "claude-sonnet-latest"),
+];
+
+static ALIASES: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// Points `alias` at `target`, a model ID or another alias
+pub fn register_alias(alias: impl Into<String>, target: impl Into<String>) {
+    ALIASES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(alias.into(), target.into());
+}
+
+/// Registers every alias in a JSON object mapping aliases to targets
+///
+/// # Errors
+///
+/// Returns `Error::Serialization` if `json` isn't such an object; no
+/// aliases are registered in that case.
+pub fn load_aliases(json: &str) -> Result<()> {
+    let aliases: HashMap<String, String> = serde_json::from_str(json)?;
+    for (alias, target) in aliases {
+        register_alias(alias, target);
+    }
+    Ok(())
+}
+
+/// Returns what `alias` points at, if it's a registered or built-in alias
+pub fn alias(alias: &str) -> Option<String> {
+    let registered = ALIASES
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .and_then(|aliases| aliases.get(alias).cloned());
+
+    registered.or_else(|| {
+        BUILT_IN
+            .iter()
+            .find(|(name, _)| *name == alias)
+            .map(|(_, target)| (*target).to_string())
+    })
+}
+
+/// Resolves an alias or model ID to a model
+///
+/// Aliases are followed until they reach a model ID. A name that is both
+/// an alias and a model ID resolves as the alias.
+///
+/// # Errors
+///
+/// Returns `ChatConfigError::UnknownModel` if the name, or the end of its
+/// alias chain, isn't a known model ID, and
+/// `ChatConfigError::ModelAliasCycle` if the aliases form a loop.
+pub fn resolve_model(name: &str) -> Result<AnyModel> {
+    let mut current = name.to_string();
+    let mut seen = vec![current.clone()];
+
+    while let Some(target) = alias(&current) {
+        if seen.contains(&target) {
+            return Err(Error::ChatConfig(ChatConfigError::ModelAliasCycle {
+                alias: name.to_string(),
+            }));
+        }
+        seen.push(target.clone());
+        current = target;
+    }
+
+    let model = AnyModel::from_id(&current).ok_or_else(|| {
+        Error::ChatConfig(ChatConfigError::UnknownModel {
+            name: current.clone(),
+        })
+    })?;
+    debug!("Resolved model {} to {}", name, model);
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Claude, Gemini};
+
+    #[test]
+    fn test_built_in_aliases_resolve() {
+        for (alias, _) in BUILT_IN {
+            assert!(resolve_model(alias).is_ok(), "{alias} doesn't resolve");
+        }
+        assert_eq!(
+            resolve_model("smart").unwrap(),
+            AnyModel::Claude(Claude::Sonnet37 {
+                use_extended_thinking: false
+            })
+        );
+    }
+
+    #[test]
+    fn test_registered_aliases_override_and_fail_cleanly() {
+        register_alias("test-flash", "gemini-flash-latest");
+        register_alias("gemini-flash-latest", "gemini-2.0-flash-lite");
+        assert_eq!(
+            resolve_model("test-flash").unwrap(),
+            AnyModel::Gemini(Gemini::Flash20Lite)
+        );
+
+        register_alias("test-loop-a", "test-loop-b");
+        register_alias("test-loop-b", "test-loop-a");
+        assert!(matches!(
+            resolve_model("test-loop-a"),
+            Err(Error::ChatConfig(ChatConfigError::ModelAliasCycle { alias })) if alias == "test-loop-a"
+        ));
+
+        register_alias("test-typo", "gpt-5o");
+        assert!(matches!(
+            resolve_model("test-typo"),
+            Err(Error::ChatConfig(ChatConfigError::UnknownModel { name })) if name == "gpt-5o"
+        ));
+        assert!(matches!(
+            Claude::try_from(resolve_model("test-flash").unwrap()),
+            Err(Error::ChatConfig(
+                ChatConfigError::WrongModelProvider { .. }
+            ))
+        ));
+    }
+}
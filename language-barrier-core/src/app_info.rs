@@ -0,0 +1,140 @@
+//! Identifying the application that makes provider calls
+//!
+//! Register an [`AppInfo`] once at startup with [`set_app_info`] and every
+//! request sent through [`HTTPLlmService`] carries it: as a `User-Agent` for
+//! all providers, plus the `X-Title` and `HTTP-Referer` headers that
+//! OpenAI-compatible gateways such as OpenRouter use for attribution (and in
+//! some cases require).
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::app_info::{AppInfo, app_info, set_app_info};
+//!
+//! set_app_info(AppInfo::new("recipe-bot", "1.4.0").with_url("https://recipes.example.com"));
+//! assert_eq!(app_info().unwrap().name, "recipe-bot");
+//! ```
+//!
+//! [`HTTPLlmService`]: crate::HTTPLlmService
+
+use std::sync::RwLock;
+
+use http::header::{HeaderName, USER_AGENT};
+use tracing::warn;
+
+use crate::transport::{HeaderValue, HttpRequest};
+
+static APP_INFO: RwLock<Option<AppInfo>> = RwLock::new(None);
+
+/// The name, version and homepage of the application using this crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppInfo {
+    /// The application's name
+    pub name: String,
+    /// The application's version
+    pub version: String,
+    /// The application's homepage, sent as `HTTP-Referer` where supported
+    pub url: Option<String>,
+}
+
+impl AppInfo {
+    /// Creates app info without a URL
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            url: None,
+        }
+    }
+
+    /// Sets the application's homepage
+    #[must_use]
+    pub fn with_url(self, url: impl Into<String>) -> Self {
+        Self {
+            url: Some(url.into()),
+            ..self
+        }
+    }
+
+    /// The `User-Agent` value: the application followed by this crate
+    pub fn user_agent(&self) -> String {
+        let app = match &self.url {
+            Some(url) => format!("{}/{} (+{url})", self.name, self.version),
+            None => format!("{}/{}", self.name, self.version),
+        };
+        format!("{app} language-barrier/{}", env!("CARGO_PKG_VERSION"))
+    }
+
+    /// Adds the `User-Agent` header to `request`, plus `X-Title` and
+    /// `HTTP-Referer` if `attribution` is set
+    ///
+    /// Values that aren't valid in a header are logged and skipped.
+    pub fn apply(&self, request: &mut HttpRequest, attribution: bool) {
+        let mut headers = vec![(USER_AGENT, self.user_agent())];
+        if attribution {
+            headers.push((HeaderName::from_static("x-title"), self.name.clone()));
+            if let Some(url) = &self.url {
+                headers.push((HeaderName::from_static("http-referer"), url.clone()));
+            }
+        }
+
+        for (name, value) in headers {
+            match HeaderValue::from_str(&value) {
+                Ok(value) => {
+                    request.headers.insert(name, value);
+                }
+                Err(e) => warn!("Skipping invalid {} header {:?}: {}", name, value, e),
+            }
+        }
+    }
+}
+
+/// Registers the application for every subsequent provider request
+pub fn set_app_info(info: AppInfo) {
+    *APP_INFO
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(info);
+}
+
+/// Stops sending application headers
+pub fn clear_app_info() {
+    *APP_INFO
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+/// Returns the registered application, if any
+pub fn app_info() -> Option<AppInfo> {
+    APP_INFO
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Method;
+    use url::Url;
+
+    #[test]
+    fn test_headers_follow_attribution() {
+        let app = AppInfo::new("recipe-bot", "1.4.0").with_url("https://recipes.example.com");
+        let url = Url::parse("https://openrouter.ai/api/v1/chat/completions").unwrap();
+
+        let mut request = HttpRequest::new(Method::POST, url.clone());
+        app.apply(&mut request, true);
+        let user_agent = request.headers["user-agent"].to_str().unwrap();
+        assert!(user_agent.starts_with("recipe-bot/1.4.0 (+https://recipes.example.com) "));
+        assert_eq!(request.headers["x-title"], "recipe-bot");
+        assert_eq!(
+            request.headers["http-referer"],
+            "https://recipes.example.com"
+        );
+
+        let mut request = HttpRequest::new(Method::POST, url);
+        app.apply(&mut request, false);
+        assert!(request.headers.contains_key("user-agent"));
+        assert!(!request.headers.contains_key("x-title"));
+    }
+}
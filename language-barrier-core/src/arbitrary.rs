@@ -0,0 +1,246 @@
+//! Property-testing support for messages and provider conversions
+//!
+//! Provides [`proptest`] `Arbitrary` implementations for [`Message`],
+//! [`Content`], [`ContentPart`] and [`ToolCall`], plus [`round_trip`], which
+//! sends a message through a provider's request format and back through its
+//! response parser. Comparing the result with [`assert_lossless`] finds
+//! conversions that drop tool calls, text or arguments.
+//!
+//! This module is only available with the `proptest` cargo feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::arbitrary::{assert_lossless, response_message, round_trip};
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::OpenAi;
+//! use proptest::prelude::*;
+//! use serde_json::json;
+//!
+//! proptest!(|(message in response_message())| {
+//!     let returned = round_trip(&OpenAIProvider::default(), OpenAi::GPT4o, &message, "messages", |message| {
+//!         json!({"id": "1", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+//!                "choices": [{"index": 0, "message": message, "finish_reason": "stop"}]})
+//!     })
+//!     .unwrap();
+//!     assert_lossless(&message, &returned, true);
+//! });
+//! ```
+
+use std::collections::HashMap;
+
+use proptest::prelude::*;
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+use crate::message::{Content, ContentPart, Function, Message, ToolCall};
+use crate::provider::HTTPProvider;
+use crate::{Chat, ModelInfo};
+
+/// Short, non-blank text, including quotes and non-ASCII characters
+pub fn text() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9éü日][A-Za-z0-9 .,!?'\"\\\\éü日本-]{0,39}"
+}
+
+/// A JSON object with a few scalar fields, serialized compactly
+pub fn arguments() -> impl Strategy<Value = String> {
+    let scalar = prop_oneof![
+        text().prop_map(Value::from),
+        any::<i32>().prop_map(Value::from),
+        any::<bool>().prop_map(Value::from),
+    ];
+    prop::collection::btree_map("[a-z][a-z_]{0,11}", scalar, 0..4)
+        .prop_map(|fields| Value::Object(fields.into_iter().collect()).to_string())
+}
+
+impl Arbitrary for ToolCall {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        ("call_[A-Za-z0-9]{8}", "[a-z][a-z_]{0,15}", arguments())
+            .prop_map(|(id, name, arguments)| ToolCall {
+                id,
+                tool_type: "function".to_string(),
+                function: Function { name, arguments },
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for ContentPart {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    #[cfg(feature = "multimodal")]
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        let bytes = || prop::collection::vec(any::<u8>(), 1..32);
+        prop_oneof![
+            3 => text().prop_map(ContentPart::text),
+            1 => "[a-z]{1,10}".prop_map(|name| {
+                ContentPart::image_url(format!("https://example.com/{name}.png"))
+            }),
+            1 => bytes().prop_map(|bytes| ContentPart::image_bytes(bytes, "image/png")),
+            1 => bytes().prop_map(|bytes| ContentPart::document(bytes, "application/pdf")),
+            1 => bytes().prop_map(|bytes| ContentPart::audio(bytes, "wav")),
+        ]
+        .boxed()
+    }
+
+    #[cfg(not(feature = "multimodal"))]
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        text().prop_map(ContentPart::text).boxed()
+    }
+}
+
+impl Arbitrary for Content {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        prop_oneof![
+            text().prop_map(Content::Text),
+            prop::collection::vec(any::<ContentPart>(), 1..4).prop_map(Content::Parts),
+        ]
+        .boxed()
+    }
+}
+
+impl Arbitrary for Message {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        let assistant = (
+            prop::option::of(any::<Content>()),
+            prop::collection::vec(any::<ToolCall>(), 0..3),
+        )
+            .prop_map(|(content, tool_calls)| Message::Assistant {
+                content,
+                tool_calls,
+                metadata: HashMap::new(),
+            });
+        prop_oneof![
+            text().prop_map(Message::system),
+            (any::<Content>(), prop::option::of("[a-z]{1,8}")).prop_map(|(content, name)| {
+                Message::User {
+                    content,
+                    name,
+                    metadata: HashMap::new(),
+                }
+            }),
+            assistant,
+            ("call_[A-Za-z0-9]{8}", text()).prop_map(|(id, content)| Message::tool(id, content)),
+        ]
+        .boxed()
+    }
+}
+
+/// An assistant message of the kind providers send back: text, tool calls,
+/// or both
+///
+/// Responses don't carry images, documents or audio, so those only appear
+/// in the general `Message` strategy.
+pub fn response_message() -> impl Strategy<Value = Message> {
+    (
+        prop::option::of(text()),
+        prop::collection::vec(any::<ToolCall>(), 0..3),
+    )
+        .prop_filter("a response needs text or a tool call", |(text, calls)| {
+            text.is_some() || !calls.is_empty()
+        })
+        .prop_map(|(text, tool_calls)| Message::Assistant {
+            content: text.map(Content::Text),
+            tool_calls,
+            metadata: HashMap::new(),
+        })
+}
+
+/// Sends `message` through a provider's request format and back through its
+/// response parser
+///
+/// The message is appended to a one-turn chat and serialized with
+/// [`HTTPProvider::accept`]. The last entry of the request's `history_key`
+/// array (`"messages"` or `"contents"`) is then passed to `wrap_response`,
+/// which embeds it in a response body for [`HTTPProvider::parse`].
+///
+/// # Errors
+///
+/// Returns an error if the provider rejects the chat, the request has no
+/// history entries, or the wrapped response doesn't parse.
+pub fn round_trip<M: ModelInfo>(
+    provider: &dyn HTTPProvider<M>,
+    model: M,
+    message: &Message,
+    history_key: &str,
+    wrap_response: impl Fn(Value) -> Value,
+) -> Result<Message> {
+    let chat = Chat::default()
+        .add_message(Message::user("Hello"))
+        .add_message(message.clone());
+    let request = provider.accept(model, &chat)?;
+    let mut body: Value = serde_json::from_slice(&request.body)?;
+
+    let sent = body
+        .get_mut(history_key)
+        .and_then(Value::as_array_mut)
+        .and_then(Vec::pop)
+        .ok_or_else(|| Error::Other(format!("Request has no {history_key} entries")))?;
+    provider.parse(wrap_response(sent).to_string())
+}
+
+/// Asserts that a round trip kept a message's text and tool calls
+///
+/// Tool-call arguments are compared as JSON, so key order and whitespace
+/// don't matter. Pass `compare_ids: false` for providers that don't echo
+/// tool-call IDs and generate new ones when parsing.
+///
+/// # Panics
+///
+/// Panics if the text, the number of tool calls, or any call's name,
+/// arguments or (optionally) ID differs.
+pub fn assert_lossless(original: &Message, returned: &Message, compare_ids: bool) {
+    assert_eq!(
+        original.text_content(),
+        returned.text_content(),
+        "text changed"
+    );
+
+    let (original_calls, returned_calls) = (tool_calls(original), tool_calls(returned));
+    assert_eq!(
+        original_calls.len(),
+        returned_calls.len(),
+        "tool calls dropped or added: {original_calls:?} vs {returned_calls:?}"
+    );
+    for (sent, received) in original_calls.iter().zip(returned_calls) {
+        assert_eq!(sent.function.name, received.function.name);
+        let sent_args: Value = serde_json::from_str(&sent.function.arguments).unwrap();
+        let received_args: Value = serde_json::from_str(&received.function.arguments)
+            .unwrap_or_else(|_| panic!("unparseable arguments: {}", received.function.arguments));
+        assert_eq!(sent_args, received_args, "arguments changed");
+        if compare_ids {
+            assert_eq!(sent.id, received.id, "tool call id changed");
+        }
+    }
+}
+
+fn tool_calls(message: &Message) -> &[ToolCall] {
+    match message {
+        Message::Assistant { tool_calls, .. } => tool_calls,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_messages_survive_serde(message in any::<Message>()) {
+            let json = serde_json::to_string(&message).unwrap();
+            let parsed: Message = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(parsed, message);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+use url::Url;
+
+use crate::error::Result;
+use crate::filter::FilterEvent;
+use crate::message::Message;
+use crate::transport::{HeaderMap, HttpRequest};
+use crate::usage::{Latency, Pricing, Usage};
+
+/// Replaces redacted header values and query parameters
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers carrying credentials, whose values are never written to an audit log
+pub(crate) const CREDENTIAL_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    "api-key",
+    "cookie",
+];
+
+/// Query parameters whose values are never written to an audit log
+const SENSITIVE_PARAMS: &[&str] = &["key", "api_key", "access_token"];
+
+/// An outbound provider request with credentials removed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRequest {
+    /// The HTTP method
+    pub method: String,
+    /// The URL, with credential query parameters redacted
+    pub url: String,
+    /// The request headers, with credential headers redacted
+    pub headers: BTreeMap<String, String>,
+    /// The JSON payload, if the body was JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+impl AuditRequest {
+    /// Captures a request, redacting API keys in headers and the URL
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::audit::{AuditRequest, REDACTED};
+    /// use language_barrier_core::transport::{HttpRequest, Method};
+    /// use url::Url;
+    ///
+    /// let url = Url::parse("https://example.com/v1/generate?key=secret&alt=json").unwrap();
+    /// let mut request = HttpRequest::new(Method::POST, url);
+    /// request.headers.insert("x-api-key", "secret".parse().unwrap());
+    ///
+    /// let audited = AuditRequest::redacted(&request);
+    /// assert_eq!(audited.url, "https://example.com/v1/generate?key=%5BREDACTED%5D&alt=json");
+    /// assert_eq!(audited.headers["x-api-key"], REDACTED);
+    /// ```
+    pub fn redacted(request: &HttpRequest) -> Self {
+        Self {
+            method: request.method.to_string(),
+            url: redacted_url(&request.url),
+            headers: redacted_headers(&request.headers),
+            body: serde_json::from_slice(&request.body).ok(),
+        }
+    }
+}
+
+/// Returns `headers` as strings, with credential headers redacted
+pub(crate) fn redacted_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if CREDENTIAL_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.as_str().to_string(), value)
+        })
+        .collect()
+}
+
+/// Returns `url` with credential query parameters redacted
+pub(crate) fn redacted_url(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.query().is_some() {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| {
+                let value = if SENSITIVE_PARAMS.contains(&name.as_ref()) {
+                    REDACTED.to_string()
+                } else {
+                    value.into_owned()
+                };
+                (name.into_owned(), value)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+/// One provider call as written to an audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When the call finished, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// The model the call was made to
+    pub model: String,
+    /// The tenant the call was made for, if the chat named one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// The redacted request
+    pub request: AuditRequest,
+    /// The parsed response, if the call succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<Message>,
+    /// The error, if the call failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Token usage reported by the provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// How long the call took
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<Latency>,
+    /// What the call cost in US dollars, if pricing was configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// What the content filter did to the request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filter_events: Vec<FilterEvent>,
+}
+
+impl AuditRecord {
+    /// Builds the record of a finished call
+    pub fn new(
+        model: impl Into<String>,
+        request: AuditRequest,
+        result: &Result<Message>,
+        pricing: Option<&Pricing>,
+    ) -> Self {
+        let (response, error) = match result {
+            Ok(message) => (Some(message.clone()), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let usage = response.as_ref().and_then(Message::usage);
+        let latency = response.as_ref().and_then(Message::latency);
+        let cost_usd = usage
+            .zip(pricing)
+            .map(|(usage, pricing)| usage.cost(pricing));
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self {
+            timestamp_ms,
+            model: model.into(),
+            tenant: None,
+            request,
+            response,
+            error,
+            usage,
+            latency,
+            cost_usd,
+            filter_events: Vec::new(),
+        }
+    }
+
+    /// Sets the time of the call, instead of when the record was built
+    #[must_use]
+    pub fn at(self, time: SystemTime) -> Self {
+        let timestamp_ms = time
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            timestamp_ms,
+            ..self
+        }
+    }
+
+    /// Records the tenant the call was made for
+    #[must_use]
+    pub fn with_tenant(self, tenant: Option<String>) -> Self {
+        Self { tenant, ..self }
+    }
+
+    /// Records what the content filter did to the request
+    #[must_use]
+    pub fn with_filter_events(self, filter_events: Vec<FilterEvent>) -> Self {
+        Self {
+            filter_events,
+            ..self
+        }
+    }
+}
+
+/// A destination for audit records
+///
+/// Attach a sink to a service with
+/// [`HTTPLlmService::with_audit_sink`](crate::llm_service::HTTPLlmService::with_audit_sink)
+/// and every provider call it makes is recorded, whether it succeeded or
+/// not. Implement this trait to write records to a database or log service.
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    /// Stores one record
+    async fn record(&self, record: &AuditRecord) -> Result<()>;
+}
+
+/// An audit sink that appends one JSON object per line to a file
+#[derive(Debug)]
+pub struct JsonlAuditSink {
+    path: PathBuf,
+    file: tokio::sync::Mutex<tokio::fs::File>,
+}
+
+impl JsonlAuditSink {
+    /// Opens `path` for appending, creating it if needed
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        debug!("Writing audit log to {}", path.display());
+        Ok(Self {
+            path,
+            file: tokio::sync::Mutex::new(file),
+        })
+    }
+
+    /// Returns the path of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[async_trait]
+impl AuditSink for JsonlAuditSink {
+    async fn record(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// An audit sink that keeps records in memory, for tests and inspection
+#[derive(Debug, Default)]
+pub struct MemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemoryAuditSink {
+    /// Creates an empty sink
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of every record so far
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl AuditSink for MemoryAuditSink {
+    async fn record(&self, record: &AuditRecord) -> Result<()> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(record.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::transport::Method;
+    use url::Url;
+
+    fn request() -> HttpRequest {
+        let url = Url::parse("https://api.example.com/v1/messages").unwrap();
+        let mut request = HttpRequest::new(Method::POST, url);
+        request
+            .headers
+            .insert("authorization", "Bearer sk-secret".parse().unwrap());
+        request
+            .headers
+            .insert("content-type", "application/json".parse().unwrap());
+        request.body = br#"{"model":"m","max_tokens":10}"#.to_vec();
+        request
+    }
+
+    #[test]
+    fn test_redaction_keeps_payload_and_hides_keys() {
+        let audited = AuditRequest::redacted(&request());
+
+        assert_eq!(audited.headers["authorization"], REDACTED);
+        assert_eq!(audited.headers["content-type"], "application/json");
+        assert_eq!(audited.body.unwrap()["max_tokens"], 10);
+        assert!(
+            !serde_json::to_string(&AuditRequest::redacted(&request()))
+                .unwrap()
+                .contains("sk-secret")
+        );
+    }
+
+    #[test]
+    fn test_record_includes_usage_and_cost() {
+        let message = Message::assistant("Hi")
+            .with_metadata(Usage::METADATA_KEY, Usage::new(1000, 500).to_metadata());
+        let pricing = Pricing::new(1.0, 2.0);
+
+        let record = AuditRecord::new(
+            "claude",
+            AuditRequest::redacted(&request()),
+            &Ok(message),
+            Some(&pricing),
+        );
+
+        assert_eq!(record.usage, Some(Usage::new(1000, 500)));
+        assert!((record.cost_usd.unwrap() - 0.002).abs() < 1e-12);
+        assert!(record.error.is_none());
+
+        let failed = AuditRecord::new(
+            "claude",
+            AuditRequest::redacted(&request()),
+            &Err(Error::RateLimit("slow down".to_string())),
+            Some(&pricing),
+        );
+        assert!(failed.response.is_none());
+        assert!(failed.error.unwrap().contains("slow down"));
+        assert!(failed.cost_usd.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_sink_appends_lines() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("audit-{}-{nanos}.jsonl", std::process::id()));
+        let record = AuditRecord::new(
+            "claude",
+            AuditRequest::redacted(&request()),
+            &Ok(Message::assistant("Hi")),
+            None,
+        );
+
+        let sink = JsonlAuditSink::open(&path).await.unwrap();
+        sink.record(&record).await.unwrap();
+        sink.record(&record).await.unwrap();
+        drop(sink);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let parsed: AuditRecord = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(parsed, record);
+    }
+}
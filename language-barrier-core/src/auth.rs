@@ -0,0 +1,450 @@
+//! Pluggable authentication for provider requests
+//!
+//! Most providers take a fixed API key, which is what each provider config's
+//! `api_key` field holds. Deployments behind Vertex AI, Azure Entra ID or an
+//! enterprise gateway instead authenticate with bearer tokens that expire,
+//! typically hourly. Setting a config's `auth` to an [`AuthProvider`] (or
+//! giving it one with `with_auth` or the builder's `auth`) makes
+//! [`HTTPLlmService`] fetch a credential before every call and hand it to the
+//! provider, which places it where its API expects.
+//!
+//! Three strategies are included:
+//!
+//! - [`StaticKey`]: a fixed API key, equivalent to setting `api_key`
+//! - [`RefreshingToken`]: a bearer token fetched by a callback and refreshed
+//!   shortly before it expires
+//! - [`ClientCredentials`]: the OAuth2 client-credentials flow
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use language_barrier_core::auth::ClientCredentials;
+//! use language_barrier_core::provider::openai::{OpenAIConfig, OpenAIProvider};
+//!
+//! let entra = ClientCredentials::new(
+//!     "https://login.microsoftonline.com/my-tenant/oauth2/v2.0/token",
+//!     "client-id",
+//!     "client-secret",
+//! )
+//! .with_scope("https://cognitiveservices.azure.com/.default");
+//!
+//! let provider = OpenAIProvider::with_config(OpenAIConfig {
+//!     base_url: "https://my-resource.openai.azure.com/openai/v1".to_string(),
+//!     auth: Some(Arc::new(entra)),
+//!     ..OpenAIConfig::default()
+//! });
+//! ```
+//!
+//! [`HTTPLlmService`]: crate::HTTPLlmService
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, error};
+use url::Url;
+
+use crate::error::{Error, Result};
+use crate::secret::Secret;
+use crate::transport::{HeaderValue, HttpRequest, Method, ReqwestTransport, Transport};
+
+/// Tokens are refreshed this long before they expire, so a request never
+/// leaves with a token that lapses in flight
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// A secret that authenticates a request
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// A provider API key, placed where the provider expects its keys
+    ApiKey(Secret<String>),
+    /// An OAuth2 access token, sent as `Authorization: Bearer`
+    Bearer(Secret<String>),
+}
+
+/// Supplies credentials for provider requests
+///
+/// Implementations are called before every request, so they should cache
+/// whatever they fetch.
+#[async_trait]
+pub trait AuthProvider: Send + Sync + fmt::Debug {
+    /// Returns a currently valid credential
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Authentication` if no credential can be obtained.
+    async fn credential(&self) -> Result<Credential>;
+}
+
+/// A fixed API key
+#[derive(Debug, Clone)]
+pub struct StaticKey(Secret<String>);
+
+impl StaticKey {
+    /// Creates a strategy that always returns `key`
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(Secret::new(key.into()))
+    }
+}
+
+#[async_trait]
+impl AuthProvider for StaticKey {
+    async fn credential(&self) -> Result<Credential> {
+        Ok(Credential::ApiKey(self.0.clone()))
+    }
+}
+
+/// An access token and how long it stays valid
+#[derive(Debug, Clone)]
+pub struct Token {
+    /// The bearer token
+    pub value: Secret<String>,
+    /// How long the token is valid from when it was issued; `None` if it
+    /// never expires
+    pub expires_in: Option<Duration>,
+}
+
+impl Token {
+    /// Creates a token valid for `expires_in`, or forever if `None`
+    pub fn new(value: impl Into<String>, expires_in: Option<Duration>) -> Self {
+        Self {
+            value: Secret::new(value.into()),
+            expires_in,
+        }
+    }
+}
+
+type TokenFuture = Pin<Box<dyn Future<Output = Result<Token>> + Send>>;
+type TokenFetcher = Arc<dyn Fn() -> TokenFuture + Send + Sync>;
+
+/// A cached token and when to fetch the next one
+struct Cached {
+    value: Secret<String>,
+    refresh_at: Option<Instant>,
+}
+
+/// A bearer token fetched by a callback and refreshed before it expires
+///
+/// The callback runs on first use and again once the token is within a
+/// minute of expiring. Concurrent requests share one refresh.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use language_barrier_core::auth::{RefreshingToken, Token};
+///
+/// let auth = RefreshingToken::new(|| async {
+///     // e.g. ask the metadata server or a sidecar for a fresh token
+///     Ok(Token::new("ya29.fresh-token", Some(Duration::from_secs(3600))))
+/// });
+/// ```
+#[derive(Clone)]
+pub struct RefreshingToken {
+    fetch: TokenFetcher,
+    cached: Arc<Mutex<Option<Cached>>>,
+}
+
+impl RefreshingToken {
+    /// Creates a strategy that gets tokens from `fetch`
+    pub fn new<F, Fut>(fetch: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Token>> + Send + 'static,
+    {
+        Self {
+            fetch: Arc::new(move || Box::pin(fetch())),
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl fmt::Debug for RefreshingToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefreshingToken").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthProvider for RefreshingToken {
+    async fn credential(&self) -> Result<Credential> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref()
+            && token.refresh_at.is_none_or(|at| Instant::now() < at)
+        {
+            return Ok(Credential::Bearer(token.value.clone()));
+        }
+
+        debug!("Fetching a new access token");
+        let fetched_at = Instant::now();
+        let token = (self.fetch)().await?;
+        let refresh_at = token
+            .expires_in
+            .map(|ttl| fetched_at + ttl.saturating_sub(REFRESH_MARGIN));
+        let value = token.value.clone();
+        *cached = Some(Cached {
+            value: token.value,
+            refresh_at,
+        });
+        Ok(Credential::Bearer(value))
+    }
+}
+
+/// The OAuth2 client-credentials flow
+///
+/// Exchanges a client ID and secret for an access token at the token
+/// endpoint, and fetches a new one shortly before it expires.
+#[derive(Clone)]
+pub struct ClientCredentials {
+    request: TokenRequest,
+    token: RefreshingToken,
+}
+
+/// The token endpoint's response
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// Where and how to request tokens
+#[derive(Clone)]
+struct TokenRequest {
+    token_url: String,
+    client_id: String,
+    client_secret: Secret<String>,
+    scopes: Vec<String>,
+    transport: Arc<dyn Transport>,
+}
+
+impl TokenRequest {
+    async fn send(self) -> Result<Token> {
+        let url = Url::parse(&self.token_url)?;
+        let body = {
+            let mut form = url::form_urlencoded::Serializer::new(String::new());
+            form.append_pair("grant_type", "client_credentials")
+                .append_pair("client_id", &self.client_id)
+                .append_pair("client_secret", self.client_secret.inner());
+            if !self.scopes.is_empty() {
+                form.append_pair("scope", &self.scopes.join(" "));
+            }
+            form.finish()
+        };
+
+        let mut request = HttpRequest::new(Method::POST, url);
+        request.headers.insert(
+            "Content-Type",
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        request.body = body.into_bytes();
+
+        let response = self.transport.send(request).await?;
+        if !(200..300).contains(&response.status) {
+            error!("Token endpoint returned status {}", response.status);
+            return Err(Error::Authentication(format!(
+                "Token request failed with status {}: {}",
+                response.status, response.body
+            )));
+        }
+        let body: TokenResponse = serde_json::from_str(&response.body).map_err(|e| {
+            Error::Authentication(format!("Unexpected token endpoint response: {e}"))
+        })?;
+        Ok(Token::new(
+            body.access_token,
+            body.expires_in.map(Duration::from_secs),
+        ))
+    }
+}
+
+impl ClientCredentials {
+    /// Creates a flow against `token_url` with the given client
+    pub fn new(
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self::from_request(TokenRequest {
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: Secret::new(client_secret.into()),
+            scopes: Vec::new(),
+            transport: Arc::new(ReqwestTransport::new()),
+        })
+    }
+
+    /// Requests `scope` in addition to any scopes already set
+    pub fn with_scope(self, scope: impl Into<String>) -> Self {
+        let mut request = self.request;
+        request.scopes.push(scope.into());
+        Self::from_request(request)
+    }
+
+    /// Requests tokens through a custom [`Transport`]
+    pub fn with_transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self::from_request(TokenRequest {
+            transport,
+            ..self.request
+        })
+    }
+
+    fn from_request(request: TokenRequest) -> Self {
+        let fetch = request.clone();
+        Self {
+            request,
+            token: RefreshingToken::new(move || fetch.clone().send()),
+        }
+    }
+}
+
+impl fmt::Debug for ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCredentials")
+            .field("token_url", &self.request.token_url)
+            .field("client_id", &self.request.client_id)
+            .field("scopes", &self.request.scopes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ClientCredentials {
+    async fn credential(&self) -> Result<Credential> {
+        self.token.credential().await
+    }
+}
+
+/// Sets `Authorization: Bearer <token>` on `request`
+///
+/// # Errors
+///
+/// Returns `Error::Authentication` if the token isn't a valid header value.
+pub fn set_bearer(request: &mut HttpRequest, token: &Secret<String>) -> Result<()> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {}", token.inner()))
+        .map_err(|_| Error::Authentication("Invalid token format".into()))?;
+    value.set_sensitive(true);
+    request.headers.insert("Authorization", value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn token_of(credential: Credential) -> String {
+        match credential {
+            Credential::Bearer(token) => token.inner().clone(),
+            Credential::ApiKey(_) => panic!("expected a bearer token"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refreshing_token_refetches_before_expiry() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let auth = RefreshingToken::new(move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                Ok(Token::new(
+                    format!("token-{n}"),
+                    Some(Duration::from_secs(120)),
+                ))
+            }
+        });
+
+        assert_eq!(token_of(auth.credential().await.unwrap()), "token-1");
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(token_of(auth.credential().await.unwrap()), "token-1");
+        // Within a minute of expiring
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert_eq!(token_of(auth.credential().await.unwrap()), "token-2");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_posts_form_and_caches_token() {
+        let transport = Arc::new(MockTransport::new().with_response(MockResponse::ok(
+            r#"{"access_token":"entra-token","expires_in":3600,"token_type":"Bearer"}"#,
+        )));
+        let auth = ClientCredentials::new("https://login.example.com/token", "app", "s3cret")
+            .with_scope("api://llm/.default")
+            .with_transport(transport.clone());
+
+        assert_eq!(token_of(auth.credential().await.unwrap()), "entra-token");
+        assert_eq!(token_of(auth.credential().await.unwrap()), "entra-token");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        let body = String::from_utf8(requests[0].body.clone()).unwrap();
+        assert_eq!(
+            body,
+            "grant_type=client_credentials&client_id=app&client_secret=s3cret&scope=api%3A%2F%2Fllm%2F.default"
+        );
+        assert!(!format!("{auth:?}").contains("s3cret"));
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials_rejection_is_an_auth_error() {
+        let transport = Arc::new(
+            MockTransport::new()
+                .with_response(MockResponse::status(401, r#"{"error":"invalid_client"}"#)),
+        );
+        let auth = ClientCredentials::new("https://login.example.com/token", "app", "wrong")
+            .with_transport(transport);
+
+        assert!(matches!(
+            auth.credential().await,
+            Err(Error::Authentication(_))
+        ));
+    }
+
+    #[cfg(all(feature = "anthropic", feature = "gemini"))]
+    #[tokio::test]
+    async fn test_providers_place_tokens_in_place_of_keys() {
+        use crate::llm_service::{HTTPLlmService, LLMService};
+        use crate::provider::anthropic::{AnthropicConfig, AnthropicProvider};
+        use crate::provider::gemini::{GeminiConfig, GeminiProvider};
+        use crate::{Chat, Claude, Gemini, Message};
+        let token = || RefreshingToken::new(|| async { Ok(Token::new("vertex-token", None)) });
+        let transport = Arc::new(MockTransport::new().with_fallback(MockResponse::status(500, "")));
+        let chat = Chat::default().add_message(Message::user("Hello"));
+
+        let gemini = GeminiProvider::with_config(GeminiConfig {
+            api_key: "static-key".to_string(),
+            auth: Some(Arc::new(token())),
+            ..GeminiConfig::default()
+        });
+        let service = HTTPLlmService::new_with_transport(
+            Gemini::Flash20,
+            Arc::new(gemini),
+            transport.clone(),
+        );
+        assert!(service.generate_next_message(&chat).await.is_err());
+
+        let anthropic = AnthropicProvider::with_config(AnthropicConfig {
+            api_key: "static-key".to_string(),
+            auth: Some(Arc::new(token())),
+            ..AnthropicConfig::default()
+        });
+        let service = HTTPLlmService::new_with_transport(
+            Claude::Haiku3,
+            Arc::new(anthropic),
+            transport.clone(),
+        );
+        assert!(service.generate_next_message(&chat).await.is_err());
+
+        for request in transport.requests() {
+            assert_eq!(request.headers["Authorization"], "Bearer vertex-token");
+            assert!(!request.headers.contains_key("x-api-key"));
+            assert!(!request.url.as_str().contains("static-key"));
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Running many chats as one batch job
+//!
+//! A [`BatchExecutor`] sends a set of independent chats through one service
+//! with bounded parallelism. Await [`execute`](BatchExecutor::execute) for
+//! the results, or [`spawn`](BatchExecutor::spawn) the batch as a
+//! [`BatchJob`] that runs in the background. A job handle is a `Future` of
+//! the results, and it reports [`BatchProgress`] through a `watch` channel,
+//! so a job scheduler can await completion or poll progress without
+//! holding the batch's own task.
+//!
+//! For per-item notifications, register a callback with
+//! [`on_event`](BatchExecutor::on_event). It receives a [`BatchEvent`] as
+//! each chat finishes and once the whole batch is done, e.g. to call a
+//! webhook or update a job table.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::stream::{self, StreamExt};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::error::Error;
+use crate::{Chat, Message, ModelInfo, Result, llm_service::LLMService};
+
+/// How far a batch has got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchProgress {
+    /// How many chats the batch has
+    pub total: usize,
+    /// How many chats have finished, successfully or not
+    pub completed: usize,
+    /// How many of the finished chats failed
+    pub failed: usize,
+}
+
+impl BatchProgress {
+    /// Returns true once every chat has finished
+    pub fn is_done(&self) -> bool {
+        self.completed == self.total
+    }
+}
+
+/// A notification from a running batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchEvent {
+    /// One chat finished
+    ItemFinished {
+        /// The chat's position in the input
+        index: usize,
+        /// Why the chat failed, if it did
+        error: Option<String>,
+    },
+    /// Every chat finished
+    Finished(BatchProgress),
+}
+
+type EventCallback = Arc<dyn Fn(&BatchEvent) + Send + Sync>;
+
+/// Runs many independent chats against one service with bounded parallelism
+///
+/// Useful for offline pipelines that need the next message for a large set of
+/// conversations. At most `concurrency` requests are in flight at once, and
+/// results come back in the same order as the input chats. A failing chat
+/// doesn't affect the others; its slot simply holds the error.
+///
+/// # Examples
+///
+#[cfg_attr(feature = "anthropic", doc = "```no_run")]
+#[cfg_attr(not(feature = "anthropic"), doc = "```ignore")]
+/// use language_barrier_core::batch::BatchExecutor;
+/// use language_barrier_core::llm_service::HTTPLlmService;
+/// use language_barrier_core::provider::anthropic::AnthropicProvider;
+/// use language_barrier_core::{Chat, Message, model::Claude};
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let service = HTTPLlmService::new(Claude::Haiku35, Arc::new(AnthropicProvider::new()));
+///     let executor = BatchExecutor::new(service).with_concurrency(8);
+///
+///     let chats = ["Paris", "Rome", "Berlin"]
+///         .iter()
+///         .map(|city| Chat::default().add_message(Message::user(format!("Describe {city}"))))
+///         .collect();
+///
+///     for (i, result) in executor.execute(chats).await.into_iter().enumerate() {
+///         match result {
+///             Ok(message) => println!("{i}: {:?}", message),
+///             Err(e) => eprintln!("{i} failed: {e}"),
+///         }
+///     }
+/// }
+/// ```
+pub struct BatchExecutor<S> {
+    service: S,
+    concurrency: usize,
+    on_event: Option<EventCallback>,
+}
+
+impl<S> BatchExecutor<S> {
+    /// The number of requests in flight when no limit is set
+    pub const DEFAULT_CONCURRENCY: usize = 4;
+
+    /// Creates an executor that sends requests through `service`
+    pub fn new(service: S) -> Self {
+        Self {
+            service,
+            concurrency: Self::DEFAULT_CONCURRENCY,
+            on_event: None,
+        }
+    }
+
+    /// Sets the maximum number of requests in flight at once
+    ///
+    /// A limit of zero is treated as one.
+    #[must_use]
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+            ..self
+        }
+    }
+
+    /// Calls `callback` as each chat finishes and when the batch is done
+    ///
+    /// The callback runs on the batch's task, so it should hand slow work
+    /// (like an HTTP call) to another task.
+    #[must_use]
+    pub fn on_event(self, callback: impl Fn(&BatchEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            on_event: Some(Arc::new(callback)),
+            ..self
+        }
+    }
+
+    /// Returns the maximum number of requests in flight at once
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Generates the next message for every chat
+    ///
+    /// The returned vector has one entry per input chat, in input order.
+    pub async fn execute<M>(&self, chats: Vec<Chat>) -> Vec<Result<Message>>
+    where
+        M: ModelInfo,
+        S: LLMService<M>,
+    {
+        let (progress, _) = watch::channel(BatchProgress::default());
+        self.run(chats, &progress).await
+    }
+
+    /// Starts generating the next message for every chat in the background
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn<M>(self, chats: Vec<Chat>) -> BatchJob
+    where
+        M: ModelInfo + 'static,
+        S: LLMService<M> + Send + Sync + 'static,
+    {
+        let (sender, progress) = watch::channel(BatchProgress {
+            total: chats.len(),
+            ..BatchProgress::default()
+        });
+        let handle = tokio::spawn(async move { self.run(chats, &sender).await });
+        BatchJob { handle, progress }
+    }
+
+    async fn run<M>(
+        &self,
+        chats: Vec<Chat>,
+        progress: &watch::Sender<BatchProgress>,
+    ) -> Vec<Result<Message>>
+    where
+        M: ModelInfo,
+        S: LLMService<M>,
+    {
+        debug!(
+            "Executing batch of {} chats with concurrency {}",
+            chats.len(),
+            self.concurrency
+        );
+        progress.send_replace(BatchProgress {
+            total: chats.len(),
+            ..BatchProgress::default()
+        });
+
+        // Chats finish in any order, so events go out as they do; the
+        // results are put back in input order afterwards
+        let service = &self.service;
+        let mut results: Vec<(usize, Result<Message>)> = stream::iter(
+            chats.into_iter().enumerate(),
+        )
+        .map(|(index, chat)| async move { (index, service.generate_next_message(&chat).await) })
+        .buffer_unordered(self.concurrency)
+        .inspect(|(index, result)| {
+            progress.send_modify(|progress| {
+                progress.completed += 1;
+                progress.failed += usize::from(result.is_err());
+            });
+            self.notify(&BatchEvent::ItemFinished {
+                index: *index,
+                error: result.as_ref().err().map(ToString::to_string),
+            });
+        })
+        .collect()
+        .await;
+        results.sort_by_key(|(index, _)| *index);
+
+        let done = *progress.borrow();
+        if done.failed > 0 {
+            warn!("{} of {} batch requests failed", done.failed, done.total);
+        }
+        self.notify(&BatchEvent::Finished(done));
+
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    fn notify(&self, event: &BatchEvent) {
+        if let Some(callback) = &self.on_event {
+            callback(event);
+        }
+    }
+}
+
+/// A batch running in the background
+///
+/// Await the job for its results, in input order. Dropping the handle
+/// doesn't stop the batch; [`abort`](Self::abort) does.
+pub struct BatchJob {
+    handle: JoinHandle<Vec<Result<Message>>>,
+    progress: watch::Receiver<BatchProgress>,
+}
+
+impl BatchJob {
+    /// Returns how far the batch has got
+    pub fn progress(&self) -> BatchProgress {
+        *self.progress.borrow()
+    }
+
+    /// Returns a channel that's updated as chats finish
+    ///
+    /// Await `changed()` on it to be woken on progress, e.g. from a
+    /// scheduler's poll loop.
+    pub fn subscribe(&self) -> watch::Receiver<BatchProgress> {
+        self.progress.clone()
+    }
+
+    /// Stops the batch; requests in flight are dropped
+    pub fn abort(&self) {
+        self.handle.abort();
+    }
+}
+
+impl Future for BatchJob {
+    /// The results, or an error if the batch was aborted
+    type Output = Result<Vec<Result<Message>>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.handle).poll(cx).map(|joined| {
+            joined.map_err(|e| {
+                if e.is_panic() {
+                    std::panic::resume_unwind(e.into_panic());
+                }
+                Error::Other("Batch job was aborted".to_string())
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::message::Content;
+    use crate::model::Claude;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Echoes the last user message back, failing on "fail"
+    #[derive(Default)]
+    struct EchoService {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LLMService<Claude> for EchoService {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+
+            let text = match chat.most_recent_message() {
+                Some(Message::User {
+                    content: Content::Text(text),
+                    ..
+                }) => text.clone(),
+                _ => String::new(),
+            };
+            // Later chats finish first, so ordering can't come from completion
+            let delay = 20u64.saturating_sub(text.len() as u64);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            if text == "fail" {
+                Err(Error::Other("boom".to_string()))
+            } else {
+                Ok(Message::assistant(text))
+            }
+        }
+    }
+
+    fn chat(text: &str) -> Chat {
+        Chat::default().add_message(Message::user(text))
+    }
+
+    #[tokio::test]
+    async fn test_results_in_input_order_with_per_item_errors() {
+        let executor = BatchExecutor::new(EchoService::default()).with_concurrency(3);
+        let inputs = ["a", "bb", "fail", "dddd", "eeeee"];
+
+        let results = executor
+            .execute(inputs.iter().map(|text| chat(text)).collect())
+            .await;
+
+        assert_eq!(results.len(), inputs.len());
+        for (text, result) in inputs.iter().zip(&results) {
+            match result {
+                Ok(message) => assert_eq!(*message, Message::assistant(*text)),
+                Err(_) => assert_eq!(*text, "fail"),
+            }
+        }
+        assert!(results[2].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_is_bounded() {
+        let executor = BatchExecutor::new(EchoService::default()).with_concurrency(2);
+        let chats = (0..8).map(|i| chat(&"x".repeat(i))).collect();
+
+        executor.execute(chats).await;
+
+        let max = executor.service.max_in_flight.load(Ordering::SeqCst);
+        assert!((1..=2).contains(&max), "max in flight was {max}");
+        assert_eq!(BatchExecutor::new(()).with_concurrency(0).concurrency(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_spawned_job_reports_progress_and_events() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let executor = BatchExecutor::new(EchoService::default())
+            .with_concurrency(3)
+            .on_event({
+                let events = events.clone();
+                move |event| events.lock().unwrap().push(event.clone())
+            });
+
+        let job = executor.spawn(
+            ["a", "fail", "cccccc"]
+                .iter()
+                .map(|text| chat(text))
+                .collect(),
+        );
+        let mut updates = job.subscribe();
+        let results = job.await.unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[1].is_err());
+        let done = *updates.borrow_and_update();
+        assert_eq!((done.total, done.completed, done.failed), (3, 3, 1));
+        assert!(done.is_done());
+
+        let events = events.lock().unwrap();
+        // The longest text finishes first
+        assert_eq!(
+            events[0],
+            BatchEvent::ItemFinished {
+                index: 2,
+                error: None
+            }
+        );
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[3], BatchEvent::Finished(done));
+    }
+}
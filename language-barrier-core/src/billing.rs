@@ -0,0 +1,367 @@
+//! Usage totals for billing, in OpenAI's usage shape
+//!
+//! A [`UsageReport`] adds up the calls in [audit records](crate::audit) by
+//! UTC day, tenant and model, including the cost worked out from the
+//! service's [`Pricing`](crate::Pricing). Build one from an existing audit
+//! log with [`UsageReport::from_records`], or collect it as calls are made
+//! by giving the service a [`UsageCollector`] as its audit sink.
+//!
+//! The report is written as JSON Lines ([`write_jsonl`](UsageReport::write_jsonl))
+//! or CSV ([`write_csv`](UsageReport::write_csv)). Each line or row is one
+//! [`UsageRow`], shaped like a result bucket of OpenAI's completions usage
+//! API so a pipeline that reads those reads these too:
+//!
+//! | Field | Meaning |
+//! |---|---|
+//! | `object` | Always `organization.usage.completions.result` |
+//! | `start_time` | Start of the UTC day, in seconds since the Unix epoch |
+//! | `end_time` | Start of the next UTC day |
+//! | `project_id` | The tenant ([`Chat::with_tenant`](crate::Chat::with_tenant)), or null/empty for calls without one |
+//! | `model` | The model, as named in the audit record |
+//! | `num_model_requests` | Calls that got a reply |
+//! | `input_tokens` | Input tokens, including cached ones |
+//! | `output_tokens` | Output tokens, including reasoning ones |
+//! | `input_cached_tokens` | Input tokens served from the prompt cache |
+//! | `output_reasoning_tokens` | Output tokens spent on hidden reasoning (not in OpenAI's shape) |
+//! | `cost_usd` | Cost of the priced calls in US dollars, or null/empty if none were priced (not in OpenAI's shape) |
+//!
+//! Rows come out ordered by day, then tenant, then model. CSV files start
+//! with a header row naming the fields in this order.
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use language_barrier_core::billing::UsageCollector;
+//! use language_barrier_core::llm_service::{HTTPLlmService, LLMService};
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::transport::mock::{MockResponse, MockTransport};
+//! use language_barrier_core::{Chat, Message, OpenAi, Pricing};
+//!
+//! # tokio_test::block_on(async {
+//! let reply = r#"{"id":"1","object":"chat.completion","created":0,"model":"gpt-4o-mini",
+//!     "choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}],
+//!     "usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#;
+//! let transport = MockTransport::new().with_fallback(MockResponse::ok(reply));
+//! let collector = Arc::new(UsageCollector::new());
+//! let service = HTTPLlmService::new_with_transport(
+//!     OpenAi::GPT4oMini,
+//!     Arc::new(OpenAIProvider::new()),
+//!     Arc::new(transport),
+//! )
+//! .with_pricing(Pricing::new(0.15, 0.6))
+//! .with_audit_sink(collector.clone());
+//!
+//! let chat = Chat::default().with_tenant("acme").add_message(Message::user("Hello"));
+//! service.generate_next_message(&chat).await.unwrap();
+//! service.generate_next_message(&chat).await.unwrap();
+//!
+//! let mut jsonl = Vec::new();
+//! collector.report().write_jsonl(&mut jsonl).unwrap();
+//! let row: serde_json::Value = serde_json::from_slice(&jsonl).unwrap();
+//! assert_eq!(row["project_id"], "acme");
+//! assert_eq!(row["num_model_requests"], 2);
+//! assert_eq!(row["input_tokens"], 24);
+//! # });
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{AuditRecord, AuditSink};
+use crate::error::Result;
+
+/// The `object` of every row, as in OpenAI's completions usage results
+pub const USAGE_OBJECT: &str = "organization.usage.completions.result";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The CSV header, in the order [`UsageRow`] fields are written
+const CSV_HEADER: &str = "object,start_time,end_time,project_id,model,num_model_requests,\
+input_tokens,output_tokens,input_cached_tokens,output_reasoning_tokens,cost_usd";
+
+/// One tenant's usage of one model on one UTC day
+///
+/// See the [module docs](self) for the meaning of each field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRow {
+    /// Always [`USAGE_OBJECT`]
+    pub object: String,
+    /// Start of the day, in seconds since the Unix epoch
+    pub start_time: u64,
+    /// Start of the next day
+    pub end_time: u64,
+    /// The tenant
+    pub project_id: Option<String>,
+    /// The model
+    pub model: String,
+    /// Calls that got a reply
+    pub num_model_requests: u64,
+    /// Input tokens, including cached ones
+    pub input_tokens: u64,
+    /// Output tokens, including reasoning ones
+    pub output_tokens: u64,
+    /// Input tokens served from the prompt cache
+    pub input_cached_tokens: u64,
+    /// Output tokens spent on hidden reasoning
+    pub output_reasoning_tokens: u64,
+    /// Cost of the priced calls in US dollars
+    pub cost_usd: Option<f64>,
+}
+
+impl UsageRow {
+    /// Writes the row as CSV fields, without the line ending
+    fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        write!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            self.object,
+            self.start_time,
+            self.end_time,
+            csv_field(self.project_id.as_deref().unwrap_or_default()),
+            csv_field(&self.model),
+            self.num_model_requests,
+            self.input_tokens,
+            self.output_tokens,
+            self.input_cached_tokens,
+            self.output_reasoning_tokens,
+            self.cost_usd
+                .map(|cost| cost.to_string())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Quotes a CSV field if it holds a comma, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Usage added up by day, tenant and model
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageReport {
+    rows: BTreeMap<(u64, Option<String>, String), UsageRow>,
+}
+
+impl UsageReport {
+    /// Creates an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a report from audit records, such as the lines of a
+    /// [`JsonlAuditSink`](crate::audit::JsonlAuditSink) log
+    pub fn from_records<'a>(records: impl IntoIterator<Item = &'a AuditRecord>) -> Self {
+        let mut report = Self::new();
+        for record in records {
+            report.add(record);
+        }
+        report
+    }
+
+    /// Adds one call to the report
+    ///
+    /// Calls that failed, or whose reply reported no usage, add nothing.
+    pub fn add(&mut self, record: &AuditRecord) {
+        let Some(usage) = record.usage else {
+            return;
+        };
+        let start_time = record.timestamp_ms / 1000 / SECONDS_PER_DAY * SECONDS_PER_DAY;
+        let key = (start_time, record.tenant.clone(), record.model.clone());
+        let row = self.rows.entry(key).or_insert_with(|| UsageRow {
+            object: USAGE_OBJECT.to_string(),
+            start_time,
+            end_time: start_time + SECONDS_PER_DAY,
+            project_id: record.tenant.clone(),
+            model: record.model.clone(),
+            num_model_requests: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            input_cached_tokens: 0,
+            output_reasoning_tokens: 0,
+            cost_usd: None,
+        });
+        row.num_model_requests += 1;
+        row.input_tokens += usage.input_tokens;
+        row.output_tokens += usage.output_tokens;
+        row.input_cached_tokens += usage.cached_tokens;
+        row.output_reasoning_tokens += usage.reasoning_tokens;
+        if let Some(cost) = record.cost_usd {
+            *row.cost_usd.get_or_insert(0.0) += cost;
+        }
+    }
+
+    /// Returns the rows, ordered by day, tenant and model
+    pub fn rows(&self) -> Vec<UsageRow> {
+        self.rows.values().cloned().collect()
+    }
+
+    /// Writes one JSON object per row, each on its own line
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `out` can't be written to.
+    pub fn write_jsonl(&self, mut out: impl Write) -> Result<()> {
+        for row in self.rows.values() {
+            serde_json::to_writer(&mut out, row)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Writes a header row and then one CSV row per row
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `out` can't be written to.
+    pub fn write_csv(&self, mut out: impl Write) -> Result<()> {
+        writeln!(out, "{CSV_HEADER}")?;
+        for row in self.rows.values() {
+            row.write_csv(&mut out)?;
+            writeln!(out)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// An audit sink that adds every call to a [`UsageReport`]
+///
+/// A service has one audit sink; to keep the audit log as well, pass the
+/// log's sink to [`with_sink`](Self::with_sink) and every record is
+/// forwarded to it.
+#[derive(Default)]
+pub struct UsageCollector {
+    report: Mutex<UsageReport>,
+    sink: Option<Arc<dyn AuditSink>>,
+}
+
+impl UsageCollector {
+    /// Creates a collector with an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forwards every record to `sink` after adding it up
+    #[must_use]
+    pub fn with_sink(self, sink: Arc<dyn AuditSink>) -> Self {
+        Self {
+            sink: Some(sink),
+            ..self
+        }
+    }
+
+    /// Returns a copy of the report so far
+    pub fn report(&self) -> UsageReport {
+        self.current().clone()
+    }
+
+    /// Returns the report so far and starts a new one, e.g. after exporting
+    /// a billing period
+    pub fn take(&self) -> UsageReport {
+        std::mem::take(&mut *self.current())
+    }
+
+    fn current(&self) -> MutexGuard<'_, UsageReport> {
+        self.report
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl AuditSink for UsageCollector {
+    async fn record(&self, record: &AuditRecord) -> Result<()> {
+        self.current().add(record);
+        match &self.sink {
+            Some(sink) => sink.record(record).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditRequest;
+    use crate::transport::{HttpRequest, Method};
+    use crate::{Message, Pricing, Usage};
+    use std::time::{Duration, UNIX_EPOCH};
+    use url::Url;
+
+    #[test]
+    fn test_report_groups_by_day_tenant_and_model() {
+        let request = AuditRequest::redacted(&HttpRequest::new(
+            Method::POST,
+            Url::parse("https://api.example.com/v1/chat").unwrap(),
+        ));
+        let call = |seconds: u64, tenant: Option<&str>, model: &str, usage: Usage| {
+            let reply =
+                Message::assistant("Hi").with_metadata(Usage::METADATA_KEY, usage.to_metadata());
+            AuditRecord::new(
+                model,
+                request.clone(),
+                &Ok(reply),
+                Some(&Pricing::new(1.0, 2.0)),
+            )
+            .at(UNIX_EPOCH + Duration::from_secs(seconds))
+            .with_tenant(tenant.map(String::from))
+        };
+        let day = 1_760_659_200;
+        let records = [
+            call(day + 10, Some("acme"), "gpt-4o", Usage::new(1_000_000, 0)),
+            call(day + 20, Some("acme"), "gpt-4o", Usage::new(0, 500_000)),
+            call(day + 30, Some("acme, inc."), "gpt-4o", Usage::new(10, 5)),
+            call(day + SECONDS_PER_DAY, None, "gpt-4o", Usage::new(7, 3)),
+            AuditRecord::new(
+                "gpt-4o",
+                request.clone(),
+                &Err(crate::Error::Other("x".into())),
+                None,
+            ),
+        ];
+
+        let report = UsageReport::from_records(&records);
+        let rows = report.rows();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].start_time, day);
+        assert_eq!(rows[0].end_time, day + SECONDS_PER_DAY);
+        assert_eq!(rows[0].project_id.as_deref(), Some("acme"));
+        assert_eq!(rows[0].num_model_requests, 2);
+        assert_eq!(rows[0].input_tokens, 1_000_000);
+        assert_eq!(rows[0].output_tokens, 500_000);
+        assert_eq!(rows[0].cost_usd, Some(2.0));
+        assert_eq!(rows[2].project_id, None);
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[2].contains(",\"acme, inc.\",gpt-4o,1,10,5,0,0,"));
+        assert!(lines[3].starts_with(&format!(
+            "{USAGE_OBJECT},{},{},,gpt-4o,1,7,3,0,0,",
+            day + SECONDS_PER_DAY,
+            day + 2 * SECONDS_PER_DAY
+        )));
+
+        let mut jsonl = Vec::new();
+        report.write_jsonl(&mut jsonl).unwrap();
+        let parsed: Vec<UsageRow> = String::from_utf8(jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed, rows);
+    }
+}
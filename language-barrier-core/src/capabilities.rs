@@ -0,0 +1,129 @@
+//! What each provider and model supports
+//!
+//! [`capabilities`] answers, for one model, the questions that decide
+//! whether it fits a job: can it stream, call tools (several at once), see
+//! images, hear audio, follow a JSON schema or cache prompts, and how much
+//! can it read and write. [`matrix`] returns the answers for every hosted
+//! model, ready to serialize wherever a table is wanted.
+//!
+//! Per-model facts come from [`ModelInfo`]; the rest are properties of the
+//! provider's API, kept here.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::capabilities::{capabilities, matrix};
+//! use language_barrier_core::model::{OpenAi, Provider};
+//!
+//! let gpt = capabilities(OpenAi::GPT4oMini);
+//! assert_eq!(gpt.provider, Provider::OpenAi);
+//! assert!(gpt.parallel_tools && gpt.json_schema && gpt.prompt_caching);
+//! assert_eq!(gpt.max_context_tokens, 128_000);
+//!
+//! let mut anthropic = matrix().into_iter().filter(|row| row.provider == Provider::Anthropic);
+//! assert!(anthropic.all(|row| row.prompt_caching));
+//! ```
+
+use serde::Serialize;
+
+use crate::model::{AnyModel, Gemini, ModelCapability, ModelInfo, OpenAi, Provider};
+
+/// What one model supports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModelCapabilities {
+    /// The provider serving the model
+    pub provider: Provider,
+    /// The ID the provider knows the model by
+    pub model: String,
+    /// Replies can be streamed as they're generated
+    pub streaming: bool,
+    /// The model can call tools
+    pub tools: bool,
+    /// The model can make several tool calls in one reply, and the request
+    /// can control whether it does
+    pub parallel_tools: bool,
+    /// The model accepts images and documents
+    pub vision: bool,
+    /// The model accepts audio and can reply with speech
+    pub audio: bool,
+    /// Replies can be constrained to a JSON schema
+    pub json_schema: bool,
+    /// Repeated prompt prefixes are cached and billed at a discount
+    pub prompt_caching: bool,
+    /// The model reasons before answering
+    pub thinking: bool,
+    /// The context window, in tokens
+    pub max_context_tokens: usize,
+    /// The most tokens one reply can hold
+    pub max_output_tokens: usize,
+}
+
+/// Returns what `model` supports
+pub fn capabilities(model: impl Into<AnyModel>) -> ModelCapabilities {
+    let model = model.into();
+    let supports = |capability| model.supports(capability);
+    let tools = supports(ModelCapability::Tools);
+
+    let parallel_tools = tools
+        && match model {
+            // Reasoning models reject `parallel_tool_calls`
+            AnyModel::OpenAi(_) => !supports(ModelCapability::Thinking),
+            AnyModel::Claude(_) | AnyModel::Gemini(_) | AnyModel::Mistral(_) => true,
+        };
+
+    let prompt_caching = match model {
+        AnyModel::Claude(_) => true,
+        // Caching is automatic from GPT-4o on
+        AnyModel::OpenAi(model) => !matches!(model, OpenAi::GPT4Turbo | OpenAi::GPT35Turbo),
+        AnyModel::Gemini(model) => !matches!(model, Gemini::Flash20Lite | Gemini::Flash20Live),
+        AnyModel::Mistral(_) => false,
+    };
+
+    ModelCapabilities {
+        provider: model.provider(),
+        model: model.id(),
+        // Embedding models don't generate replies at all
+        streaming: !model.capabilities().is_empty(),
+        tools,
+        parallel_tools,
+        vision: supports(ModelCapability::Vision),
+        audio: supports(ModelCapability::Audio),
+        json_schema: supports(ModelCapability::JsonMode),
+        prompt_caching,
+        thinking: supports(ModelCapability::Thinking),
+        max_context_tokens: model.context_window(),
+        max_output_tokens: model.max_output_tokens(),
+    }
+}
+
+/// Returns the capabilities of every hosted model, in [`AnyModel::ALL`] order
+pub fn matrix() -> Vec<ModelCapabilities> {
+    AnyModel::ALL.iter().copied().map(capabilities).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Claude, Mistral};
+
+    #[test]
+    fn test_matrix_covers_every_model() {
+        let matrix = matrix();
+        assert_eq!(matrix.len(), AnyModel::ALL.len());
+        assert!(
+            matrix
+                .iter()
+                .all(|row| row.max_output_tokens <= row.max_context_tokens)
+        );
+
+        let o3 = capabilities(OpenAi::O3Mini);
+        assert!(o3.tools && !o3.parallel_tools && o3.thinking);
+
+        let embed = capabilities(Mistral::Embed);
+        assert!(!embed.streaming && !embed.tools);
+
+        let haiku = serde_json::to_value(capabilities(Claude::Haiku35)).unwrap();
+        assert_eq!(haiku["provider"], "Anthropic");
+        assert_eq!(haiku["model"], "claude-3-5-haiku-latest");
+    }
+}
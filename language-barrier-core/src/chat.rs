@@ -1,8 +1,215 @@
-use crate::compactor::{ChatHistoryCompactor, DropOldestCompactor};
+use crate::compactor::{ChatHistoryCompactor, DropOldestCompactor, ObservedCompactor};
+use crate::error::{ChatConfigError, Error};
+use crate::feedback::{self, Feedback, FeedbackRecord};
+use crate::filter::ContentFilter;
+use crate::idempotency::Submission;
+use crate::ids::{IdGenerator, RandomIds};
+use crate::injection::InjectionDetector;
+use crate::llm_service::LLMService;
+use crate::locale::Locale;
+#[cfg(feature = "multimodal")]
+use crate::message::ContentPart;
 use crate::message::{Content, Message};
-use crate::token::TokenCounter;
+use crate::minify::SchemaMinifier;
+use crate::model::{ModelCapability, ModelInfo, Provider};
+use crate::moderation::Moderation;
+use crate::priority::Priority;
+use crate::profile;
+use crate::prompts::{self, PromptRef};
+use crate::replay::{self, ChatDiff};
+use crate::salvage;
+use crate::token::{MessageTokens, TokenCounter, TokenReport, Tokenizer};
 use crate::tool::{LlmToolInfo, ToolChoice};
-use crate::{Result, ToolDefinition};
+use crate::usage::Usage;
+use crate::Result;
+#[cfg(feature = "tools")]
+use crate::ToolDefinition;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// A single segment of a structured system prompt.
+///
+/// Frameworks often build the system prompt from several independent pieces
+/// (base instructions, a persona, retrieved context). Segments keep those
+/// pieces separate so each provider can serialize them in its native shape,
+/// and so a tagged piece can be swapped out without rebuilding the rest.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::chat::SystemSegment;
+///
+/// let segment = SystemSegment::new("Relevant documents: ...")
+///     .with_tag("context")
+///     .cacheable();
+///
+/// assert_eq!(segment.tag.as_deref(), Some("context"));
+/// assert!(segment.cacheable);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SystemSegment {
+    /// The text of this segment
+    pub text: String,
+    /// Optional label used to replace or remove the segment later
+    pub tag: Option<String>,
+    /// Whether providers that support prompt caching may cache this segment
+    pub cacheable: bool,
+}
+
+impl SystemSegment {
+    /// Creates an untagged, non-cacheable segment
+    #[must_use]
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            tag: None,
+            cacheable: false,
+        }
+    }
+
+    /// Sets the tag and returns a new instance
+    #[must_use]
+    pub fn with_tag(self, tag: impl Into<String>) -> Self {
+        Self {
+            tag: Some(tag.into()),
+            ..self
+        }
+    }
+
+    /// Marks the segment as cacheable and returns a new instance
+    #[must_use]
+    pub fn cacheable(self) -> Self {
+        Self {
+            cacheable: true,
+            ..self
+        }
+    }
+}
+
+/// What to do when `max_output_tokens` is above the model's output limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputTokenPolicy {
+    /// Lower the request to the model's limit and log a warning (default)
+    #[default]
+    Clamp,
+    /// Refuse to build the request with `Error::ChatConfig`
+    Reject,
+}
+
+/// Asks the model to reply with speech as well as text
+///
+/// Only models with the [`ModelCapability::Audio`] capability can speak. The
+/// spoken reply comes back as a `ContentPart::Audio` alongside a text part
+/// holding its transcript.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::chat::AudioOutput;
+///
+/// let audio = AudioOutput::new("alloy", "wav");
+/// assert_eq!(audio.voice, "alloy");
+/// ```
+#[cfg(feature = "multimodal")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioOutput {
+    /// The voice to speak with (e.g. `alloy`)
+    pub voice: String,
+    /// The audio encoding to return (e.g. `wav`, `mp3`)
+    pub format: String,
+}
+
+#[cfg(feature = "multimodal")]
+impl AudioOutput {
+    /// Creates an audio output setting
+    #[must_use]
+    pub fn new(voice: impl Into<String>, format: impl Into<String>) -> Self {
+        Self {
+            voice: voice.into(),
+            format: format.into(),
+        }
+    }
+}
+
+/// Constrains the model's reply to JSON matching a schema
+///
+/// Read the reply back with [`Message::structured_output`], or with
+/// [`Message::choice`] for a schema made by [`OutputSchema::choice`].
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::chat::OutputSchema;
+///
+/// let schema = OutputSchema::choice(["approve", "reject"]);
+/// assert_eq!(schema.schema["properties"]["choice"]["enum"][1], "reject");
+/// ```
+///
+/// [`Message::structured_output`]: crate::Message::structured_output
+/// [`Message::choice`]: crate::Message::choice
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputSchema {
+    /// A name for the schema, which some providers require
+    pub name: String,
+    /// The JSON schema the reply must match
+    pub schema: serde_json::Value,
+}
+
+impl OutputSchema {
+    /// The field holding the answer in a [`choice`](Self::choice) schema
+    pub const CHOICE_FIELD: &'static str = "choice";
+
+    /// Creates an output schema
+    #[must_use]
+    pub fn new(name: impl Into<String>, schema: serde_json::Value) -> Self {
+        Self {
+            name: name.into(),
+            schema,
+        }
+    }
+
+    /// Creates a schema for a reply that picks exactly one of `choices`
+    ///
+    /// Providers only constrain objects, so the pick is wrapped as
+    /// `{"choice": "..."}`.
+    #[must_use]
+    pub fn choice<I, S>(choices: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let choices: Vec<String> = choices.into_iter().map(Into::into).collect();
+        Self::new(
+            Self::CHOICE_FIELD,
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    Self::CHOICE_FIELD: { "type": "string", "enum": choices }
+                },
+                "required": [Self::CHOICE_FIELD],
+                "additionalProperties": false
+            }),
+        )
+    }
+}
+
+/// Merges `extra` into `target`, recursing into objects present in both
+pub(crate) fn merge_json(
+    target: &mut serde_json::Map<String, serde_json::Value>,
+    extra: serde_json::Map<String, serde_json::Value>,
+) {
+    for (key, value) in extra {
+        match (target.get_mut(&key), value) {
+            (Some(serde_json::Value::Object(existing)), serde_json::Value::Object(value)) => {
+                merge_json(existing, value);
+            }
+            (_, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
 
 /// The main Chat client that users will interact with.
 /// All methods return a new instance rather than mutating the existing one,
@@ -11,7 +218,10 @@ use crate::{Result, ToolDefinition};
 pub struct Chat {
     // Tunable knobs / state
     pub system_prompt: String,
+    pub system_segments: Vec<SystemSegment>,
     pub max_output_tokens: usize,
+    pub output_token_policy: OutputTokenPolicy,
+    pub temperature: Option<f32>,
 
     // History and token tracking
     pub history: Vec<Message>,
@@ -22,22 +232,117 @@ pub struct Chat {
 
     // Tool execution settings
     pub tool_choice: Option<ToolChoice>,
+    pub parallel_tool_calls: Option<bool>,
+
+    // Output modalities
+    #[cfg(feature = "multimodal")]
+    pub audio_output: Option<AudioOutput>,
+    pub output_schema: Option<OutputSchema>,
+    pub logprobs: Option<u8>,
+
+    // Scope for the idempotency keys of requests made from this chat
+    pub idempotency_key: Option<String>,
+
+    // Prompt template that produced the system prompt
+    pub prompt: Option<PromptRef>,
+
+    // Language and formatting conventions for replies
+    pub locale: Option<Locale>,
+
+    // Screens outbound user messages, overriding the global filter
+    pub content_filter: Option<Arc<ContentFilter>>,
+
+    // Flags likely prompt injection in tool results
+    pub injection_detector: Option<Arc<InjectionDetector>>,
+
+    // Shrinks tool schemas as requests are built
+    pub schema_minifier: Option<SchemaMinifier>,
+
+    // Checks replies before they're returned
+    pub moderation: Option<Moderation>,
+
+    // Text every reply must start with
+    pub assistant_prefix: Option<String>,
+
+    // Raw fields merged into each provider's request payload
+    pub provider_extras: HashMap<Provider, serde_json::Map<String, serde_json::Value>>,
+
+    // Customer the chat's calls are made and billed for
+    pub tenant: Option<String>,
+
+    // Place of the chat's calls in a rate-limit queue
+    pub priority: Priority,
+
+    // Identifies the conversation to external systems
+    pub conversation_id: String,
+
+    // The ChatStore version this chat was loaded or last saved at, or 0 if
+    // it hasn't been stored
+    pub version: u64,
+
+    // Makes the conversation and message IDs
+    ids: Arc<dyn IdGenerator>,
+
+    // The conversation ID last taken from `ids`, so an ID set by hand or
+    // restored from a store can be told apart from a generated one
+    generated_conversation_id: String,
 }
 
 impl Default for Chat {
     fn default() -> Self {
+        let conversation_id = RandomIds.conversation_id();
         Self {
             system_prompt: String::new(),
+            system_segments: Vec::new(),
             max_output_tokens: 2048,
+            output_token_policy: OutputTokenPolicy::default(),
+            temperature: None,
             history: Vec::new(),
             token_counter: TokenCounter::default(),
             tools: None,
             tool_choice: None,
+            parallel_tool_calls: None,
+            #[cfg(feature = "multimodal")]
+            audio_output: None,
+            output_schema: None,
+            logprobs: None,
+            idempotency_key: None,
+            prompt: None,
+            locale: None,
+            content_filter: None,
+            injection_detector: None,
+            schema_minifier: None,
+            moderation: None,
+            assistant_prefix: None,
+            provider_extras: HashMap::new(),
+            tenant: None,
+            priority: Priority::default(),
+            generated_conversation_id: conversation_id.clone(),
+            conversation_id,
+            version: 0,
+            ids: Arc::new(RandomIds),
         }
     }
 }
 
 impl Chat {
+    /// Starts a [`ChatBuilder`] that validates the chat against `model`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Chat, Claude, Message};
+    ///
+    /// let chat = Chat::builder(Claude::Haiku35)
+    ///     .with_system_prompt("You are a helpful assistant.")
+    ///     .add_message(Message::user("Hello!"))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder<M: ModelInfo>(model: M) -> ChatBuilder<M> {
+        ChatBuilder::new(model)
+    }
+
     /// Sets system prompt and returns a new instance
     #[must_use]
     pub fn with_system_prompt(self, prompt: impl Into<String>) -> Self {
@@ -55,6 +360,101 @@ impl Chat {
         new_chat
     }
 
+    /// Appends a system prompt segment and returns a new instance
+    ///
+    /// Segments are sent after `system_prompt`, in the order they were added.
+    /// A tagged segment replaces any existing segment with the same tag in
+    /// place, which keeps e.g. retrieved context up to date without
+    /// disturbing the order of the other segments.
+    ///
+    /// Providers serialize segments as follows:
+    /// - Anthropic sends a `system` array of text blocks, marking cacheable
+    ///   segments with `cache_control`
+    /// - OpenAI sends one system message per segment
+    /// - Gemini sends one `system_instruction` part per segment
+    /// - Mistral and Ollama join the segments with blank lines
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::Chat;
+    /// use language_barrier_core::chat::SystemSegment;
+    ///
+    /// let chat = Chat::default()
+    ///     .with_system_prompt("You are a helpful assistant.")
+    ///     .with_system_segment(SystemSegment::new("Speak like a pirate.").with_tag("persona"))
+    ///     .with_system_segment(SystemSegment::new("Speak like a poet.").with_tag("persona"));
+    ///
+    /// assert_eq!(chat.system_segments.len(), 1);
+    /// assert_eq!(chat.system_text(), "You are a helpful assistant.\n\nSpeak like a poet.");
+    /// ```
+    #[must_use]
+    pub fn with_system_segment(self, segment: SystemSegment) -> Self {
+        let mut system_segments = self.system_segments.clone();
+
+        let existing = segment
+            .tag
+            .as_ref()
+            .and_then(|tag| system_segments.iter().position(|s| s.tag.as_ref() == Some(tag)));
+        match existing {
+            Some(index) => system_segments[index] = segment,
+            None => system_segments.push(segment),
+        }
+
+        // Recount from scratch since a replaced segment may have shrunk
+        let history = self.history.clone();
+        Self {
+            system_segments,
+            ..self
+        }
+        .with_history(history)
+    }
+
+    /// Removes the system segments with the given tag and returns a new instance
+    #[must_use]
+    pub fn without_system_segment(self, tag: &str) -> Self {
+        let system_segments = self
+            .system_segments
+            .iter()
+            .filter(|s| s.tag.as_deref() != Some(tag))
+            .cloned()
+            .collect();
+
+        let history = self.history.clone();
+        Self {
+            system_segments,
+            ..self
+        }
+        .with_history(history)
+    }
+
+    /// Returns the full system prompt as an ordered list of segments
+    ///
+    /// The plain `system_prompt`, if set, comes first as an untagged segment.
+    #[must_use]
+    pub fn system_blocks(&self) -> Vec<SystemSegment> {
+        let base = (!self.system_prompt.is_empty())
+            .then(|| SystemSegment::new(self.system_prompt.clone()));
+
+        base.into_iter()
+            .chain(self.system_segments.iter().cloned())
+            .filter(|s| !s.text.is_empty())
+            .collect()
+    }
+
+    /// Returns the full system prompt flattened into a single string
+    ///
+    /// Segments are joined with blank lines, for providers that only accept
+    /// one system string.
+    #[must_use]
+    pub fn system_text(&self) -> String {
+        self.system_blocks()
+            .into_iter()
+            .map(|s| s.text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
     /// Sets max output tokens and returns a new instance
     #[must_use]
     pub fn with_max_output_tokens(self, n: usize) -> Self {
@@ -64,14 +464,234 @@ impl Chat {
         }
     }
 
+    /// Sets the sampling temperature and returns a new instance
+    ///
+    /// Left unset, each provider uses its own default.
+    #[must_use]
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        Self {
+            temperature: Some(temperature),
+            ..self
+        }
+    }
+
+    /// Sets the system prompt from a registered template and returns a new instance
+    ///
+    /// `id` is `name@version`, or a bare `name` for the latest version.
+    /// The chat remembers which prompt it used, and replies generated from
+    /// it record that under [`PromptRef::METADATA_KEY`]. See [`prompts`] for
+    /// registering templates.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::UnknownPrompt` if no such prompt is
+    /// registered, or `ChatConfigError::MissingPromptVariable` if `vars`
+    /// leaves a placeholder unfilled.
+    pub fn with_prompt<K, V>(self, id: &str, vars: impl IntoIterator<Item = (K, V)>) -> Result<Self>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let vars: HashMap<String, String> = vars
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        let (prompt, text) = prompts::render_prompt(id, &vars)?;
+        Ok(Self {
+            prompt: Some(prompt),
+            ..self.with_system_prompt(text)
+        })
+    }
+
+    /// Applies a named [`GenerationProfile`] and returns a new instance
+    ///
+    /// `name` is one of the built-in profiles (`"precise"`, `"balanced"`,
+    /// `"creative"`) or one registered with [`profile::register_profile`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::UnknownProfile` if there's no such profile.
+    ///
+    /// [`GenerationProfile`]: crate::profile::GenerationProfile
+    /// [`profile::register_profile`]: crate::profile::register_profile
+    pub fn with_profile(self, name: &str) -> Result<Self> {
+        Ok(profile::require_profile(name)?.apply(self))
+    }
+
+    /// Sets how an over-limit `max_output_tokens` is handled and returns a new instance
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Chat, OpenAi};
+    /// use language_barrier_core::chat::OutputTokenPolicy;
+    ///
+    /// let chat = Chat::default().with_max_output_tokens(100_000);
+    /// assert_eq!(chat.max_output_tokens_for(&OpenAi::GPT4o).unwrap(), 16_384);
+    ///
+    /// let chat = chat.with_output_token_policy(OutputTokenPolicy::Reject);
+    /// assert!(chat.max_output_tokens_for(&OpenAi::GPT4o).is_err());
+    /// ```
+    #[must_use]
+    pub fn with_output_token_policy(self, policy: OutputTokenPolicy) -> Self {
+        Self {
+            output_token_policy: policy,
+            ..self
+        }
+    }
+
+    /// Returns the output token budget to request from `model`
+    ///
+    /// This is `max_output_tokens`, checked against the model's limit
+    /// according to `output_token_policy`. Providers call this when
+    /// building their payloads. Models with no known limit get
+    /// `max_output_tokens` as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ChatConfig` if the budget is over the limit and the
+    /// policy is [`OutputTokenPolicy::Reject`].
+    pub fn max_output_tokens_for<M: ModelInfo>(&self, model: &M) -> Result<usize> {
+        let Some(limit) = model.output_token_limit() else {
+            return Ok(self.max_output_tokens);
+        };
+        if self.max_output_tokens <= limit {
+            return Ok(self.max_output_tokens);
+        }
+
+        match self.output_token_policy {
+            OutputTokenPolicy::Clamp => {
+                warn!(
+                    "max_output_tokens of {} exceeds the {} token limit of model {:?}, clamping",
+                    self.max_output_tokens, limit, model
+                );
+                Ok(limit)
+            }
+            OutputTokenPolicy::Reject => Err(ChatConfigError::MaxOutputTokensExceeded {
+                model: format!("{model:?}"),
+                requested: self.max_output_tokens,
+                limit,
+            }
+            .into()),
+        }
+    }
+
+    /// Breaks the prompt this chat would send down by section and by
+    /// message, counted with `tokenizer`
+    ///
+    /// The sections are the system prompt, the tool definitions (minified,
+    /// if the chat has a schema minifier), the history's text (tool calls and results included) and its
+    /// attachments. A message's cached count for the tokenizer is used when
+    /// it's current. Counts cover content only: the few tokens providers add
+    /// per message for roles and formatting aren't included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Chat, Message, TokenCounter};
+    ///
+    /// let chat = Chat::default()
+    ///     .with_system_prompt("You are a terse assistant.")
+    ///     .add_message(Message::user("Summarize the attached report, please."));
+    ///
+    /// let report = chat.token_report(&TokenCounter::new());
+    /// assert_eq!(report.system, 5);
+    /// assert_eq!(report.history, 5);
+    /// assert_eq!(report.messages[0].role, "user");
+    /// println!("{report}");
+    /// ```
+    #[must_use]
+    pub fn token_report(&self, tokenizer: &impl Tokenizer) -> TokenReport {
+        let system = tokenizer.count(&self.system_prompt)
+            + self
+                .system_segments
+                .iter()
+                .map(|segment| tokenizer.count(&segment.text))
+                .sum::<usize>();
+        let tools = self.sent_tools();
+        let tools = tools.iter().map(|tool| {
+            tokenizer.count(&tool.name)
+                + tokenizer.count(&tool.description)
+                + tokenizer.count(&tool.parameters.to_string())
+        });
+
+        let messages: Vec<MessageTokens> = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(index, msg)| {
+                let mut text = msg
+                    .cached_token_count(tokenizer.name())
+                    .unwrap_or_else(|| tokenizer.count(&msg.text_content()));
+                let mut attachments = 0;
+                match msg {
+                    Message::User {
+                        content: Content::Parts(parts),
+                        ..
+                    }
+                    | Message::Assistant {
+                        content: Some(Content::Parts(parts)),
+                        ..
+                    } => {
+                        attachments = parts
+                            .iter()
+                            .filter(|part| part.as_text().is_none())
+                            .map(|part| tokenizer.count_attachment(part))
+                            .sum();
+                    }
+                    _ => {}
+                }
+                if let Message::Assistant { tool_calls, .. } = msg {
+                    text += tool_calls
+                        .iter()
+                        .map(|call| {
+                            tokenizer.count(&call.function.name)
+                                + tokenizer.count(&call.function.arguments)
+                        })
+                        .sum::<usize>();
+                }
+                MessageTokens {
+                    index,
+                    role: msg.role_str(),
+                    text,
+                    attachments,
+                }
+            })
+            .collect();
+
+        TokenReport {
+            tokenizer: tokenizer.name().to_string(),
+            system,
+            tools: tools.sum(),
+            history: messages.iter().map(|message| message.text).sum(),
+            attachments: messages.iter().map(|message| message.attachments).sum(),
+            messages,
+        }
+    }
+
     /// Sets history and returns a new instance
     #[must_use]
     pub fn with_history(self, history: Vec<Message>) -> Self {
+        self.with_untrimmed_history(history)
+            .trim_to_context_window()
+    }
+
+    /// Sets history without trimming it to the context window, as when
+    /// restoring a stored conversation
+    pub(crate) fn with_untrimmed_history(self, history: Vec<Message>) -> Self {
+        let history: Vec<Message> = history
+            .into_iter()
+            .map(|msg| self.identified(msg))
+            .collect();
+
         // Create a new token counter from scratch
         let mut token_counter = TokenCounter::default();
 
         // Count tokens in system prompt
         token_counter.observe(&self.system_prompt);
+        for segment in &self.system_segments {
+            token_counter.observe(&segment.text);
+        }
 
         // Count tokens in message history
         for msg in &history {
@@ -92,19 +712,57 @@ impl Chat {
             }
         }
 
-        let mut new_chat = Self {
+        Self {
             history,
             token_counter,
             ..self
-        };
+        }
+    }
 
-        new_chat = new_chat.trim_to_context_window();
-        new_chat
+    /// Adds a turn the client may submit more than once and returns a new
+    /// instance
+    ///
+    /// A message with a [client ID](Message::with_client_id) that's already
+    /// in the history is ignored, so a retried webhook doesn't add its
+    /// message twice. Messages without a client ID are always added.
+    #[must_use]
+    pub fn add_turn(self, msg: Message) -> Self {
+        if let Some(id) = msg.client_id()
+            && self.submission(id).is_some()
+        {
+            debug!("Ignoring resubmitted turn {}", id);
+            return self;
+        }
+        self.add_message(msg)
+    }
+
+    /// Returns what became of the turn submitted with `client_id`, or
+    /// `None` if it isn't in the history
+    ///
+    /// The turn is answered by the last assistant message before the next
+    /// user message, if that one doesn't call tools.
+    pub fn submission(&self, client_id: &str) -> Option<Submission> {
+        let index = self
+            .history
+            .iter()
+            .position(|msg| msg.client_id() == Some(client_id))?;
+        let reply = self.history[index + 1..]
+            .iter()
+            .take_while(|msg| !matches!(msg, Message::User { .. }))
+            .filter(|msg| matches!(msg, Message::Assistant { .. }))
+            .last();
+        Some(match reply {
+            Some(reply @ Message::Assistant { tool_calls, .. }) if tool_calls.is_empty() => {
+                Submission::Answered(reply.clone())
+            }
+            _ => Submission::Pending,
+        })
     }
 
     /// Adds a message to the conversation history and returns a new instance
     #[must_use]
     pub fn add_message(self, msg: Message) -> Self {
+        let msg = self.identified(msg);
         let mut token_counter = self.token_counter.clone();
         let mut history = self.history.clone();
 
@@ -154,7 +812,7 @@ impl Chat {
         // Create a fresh compactor of the same default type
         // Note: In a real implementation, you would want a way to clone the compactor
         // or to properly reconstruct the specific type that was being used.
-        let new_compactor = Box::<DropOldestCompactor>::default();
+        let new_compactor = ObservedCompactor::new(DropOldestCompactor::default());
 
         // Use the compactor to trim history
         new_compactor.compact(&mut history, &mut token_counter, MAX_TOKENS);
@@ -166,12 +824,60 @@ impl Chat {
         }
     }
 
+    /// Compacts the history toward `max_tokens` with `compactor` and returns a new instance
+    #[must_use]
+    pub fn compact_with<C: ChatHistoryCompactor>(self, compactor: &C, max_tokens: usize) -> Self {
+        let mut history = self.history.clone();
+        let mut token_counter = self.token_counter.clone();
+        compactor.compact(&mut history, &mut token_counter, max_tokens);
+
+        Self {
+            history,
+            token_counter,
+            ..self
+        }
+    }
+
     /// Gets the current token count
     pub fn tokens_used(&self) -> usize {
         self.token_counter.total()
     }
 
+    /// Sums the usage reported by the provider across all assistant messages
+    ///
+    /// Unlike [`Chat::tokens_used`], which is a local estimate of the history
+    /// size, this is what the provider actually billed for each response.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Chat, Message, Usage};
+    ///
+    /// let reply = |text: &str, usage: Usage| {
+    ///     Message::assistant(text).with_metadata(Usage::METADATA_KEY, usage.to_metadata())
+    /// };
+    ///
+    /// let chat = Chat::default()
+    ///     .add_message(Message::user("Hi"))
+    ///     .add_message(reply("Hello!", Usage::new(10, 2)))
+    ///     .add_message(Message::user("How are you?"))
+    ///     .add_message(reply("Great.", Usage::new(15, 3).with_cached_tokens(10)));
+    ///
+    /// let totals = chat.usage_totals();
+    /// assert_eq!(totals.input_tokens, 25);
+    /// assert_eq!(totals.output_tokens, 5);
+    /// assert_eq!(totals.cached_tokens, 10);
+    /// ```
+    pub fn usage_totals(&self) -> Usage {
+        self.history
+            .iter()
+            .filter(|msg| matches!(msg, Message::Assistant { .. }))
+            .filter_map(Message::usage)
+            .sum()
+    }
+
     /// Add a tool and returns a new instance with the tool added
+    #[cfg(feature = "tools")]
     #[must_use = "This returns a new Chat with the tool added"]
     pub fn with_tool(self, tool: impl ToolDefinition) -> Result<Self> {
         let info = LlmToolInfo {
@@ -262,8 +968,1162 @@ impl Chat {
         }
     }
 
-    /// Return the most recent message in the chat.
-    pub fn most_recent_message(&self) -> Option<&Message> {
-        self.history.last()
+    /// Sets whether the model may request several tool calls in one response
+    /// and returns a new instance
+    ///
+    /// Pass `false` when your tools are not safe to run concurrently, so the
+    /// model asks for them one at a time. When unset, the provider's default
+    /// (parallel calls allowed) applies.
+    ///
+    /// Providers map this as follows:
+    /// - OpenAI/Mistral send `parallel_tool_calls`
+    /// - Anthropic sends `disable_parallel_tool_use` on the tool choice
+    /// - Gemini and Ollama have no equivalent and ignore it
+    ///
+    /// The setting is only sent when the chat has tools.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::Chat;
+    ///
+    /// let chat = Chat::default().with_parallel_tool_calls(false);
+    /// assert_eq!(chat.parallel_tool_calls, Some(false));
+    /// ```
+    #[must_use]
+    pub fn with_parallel_tool_calls(self, enabled: bool) -> Self {
+        Self {
+            parallel_tool_calls: Some(enabled),
+            ..self
+        }
+    }
+
+    /// Asks for a spoken reply in addition to text and returns a new instance
+    ///
+    /// Providers map this as follows:
+    /// - OpenAI sends `modalities: ["text", "audio"]` and the `audio` options
+    /// - Other providers have no equivalent and ignore it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::Chat;
+    /// use language_barrier_core::chat::AudioOutput;
+    ///
+    /// let chat = Chat::default().with_audio_output(AudioOutput::new("alloy", "wav"));
+    /// assert_eq!(chat.audio_output.unwrap().format, "wav");
+    /// ```
+    #[cfg(feature = "multimodal")]
+    #[must_use]
+    pub fn with_audio_output(self, audio: AudioOutput) -> Self {
+        Self {
+            audio_output: Some(audio),
+            ..self
+        }
+    }
+
+    /// Constrains the reply to JSON matching `schema` and returns a new instance
+    ///
+    /// Providers map this as follows:
+    /// - OpenAI and Mistral send a strict `json_schema` response format
+    /// - Gemini sends `response_schema` with a JSON response MIME type
+    /// - Ollama sends the schema as `format`
+    /// - Anthropic has no response format, so the schema becomes a tool the
+    ///   model is forced to call; its arguments are the reply
+    ///
+    /// On models without native JSON mode, `HTTPLlmService` emulates it; see
+    /// [`json_mode`](crate::json_mode).
+    #[must_use]
+    pub fn with_output_schema(self, schema: OutputSchema) -> Self {
+        Self {
+            output_schema: Some(schema),
+            ..self
+        }
+    }
+
+    /// Constrains the reply to exactly one of `choices` and returns a new instance
+    ///
+    /// Shorthand for [`with_output_schema`](Self::with_output_schema) with
+    /// [`OutputSchema::choice`]. Read the pick with [`Message::choice`],
+    /// typically into an enum of your own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Chat, Message};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize)]
+    /// #[serde(rename_all = "lowercase")]
+    /// enum Verdict {
+    ///     Approve,
+    ///     Reject,
+    ///     Escalate,
+    /// }
+    ///
+    /// let chat = Chat::default().with_choice_output(["approve", "reject", "escalate"]);
+    /// assert!(chat.output_schema.is_some());
+    ///
+    /// let reply = Message::assistant(r#"{"choice":"escalate"}"#);
+    /// assert_eq!(reply.choice::<Verdict>().unwrap(), Verdict::Escalate);
+    /// ```
+    #[must_use]
+    pub fn with_choice_output<I, S>(self, choices: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.with_output_schema(OutputSchema::choice(choices))
+    }
+
+    /// Asks for the log probability of each generated token and returns a new instance
+    ///
+    /// `top_alternatives` (at most 20) is how many of the likeliest tokens to
+    /// report at each position besides the one generated. The logprobs are
+    /// recorded on the reply; see the [`logprobs`](crate::logprobs) module.
+    ///
+    /// Providers map this as follows:
+    /// - OpenAI sends `logprobs: true` and `top_logprobs`
+    /// - Other providers ignore it
+    #[must_use]
+    pub fn with_logprobs(self, top_alternatives: u8) -> Self {
+        Self {
+            logprobs: Some(top_alternatives),
+            ..self
+        }
+    }
+
+    /// Sets the idempotency key scope and returns a new instance
+    ///
+    /// Providers that accept an `Idempotency-Key` header (OpenAI and
+    /// Anthropic) get a key derived from this scope and the request payload,
+    /// so resending the same chat with the same scope, as a retry does, can't
+    /// produce a second billed generation. Adding a message changes the
+    /// payload and therefore the key. Left unset, every call gets a fresh
+    /// random key.
+    ///
+    /// Set a new scope (for example from [`idempotency::new_key`]) for each
+    /// logical request; reusing one to deliberately regenerate a reply would
+    /// return the provider's stored answer instead.
+    ///
+    /// [`idempotency::new_key`]: crate::idempotency::new_key
+    #[must_use]
+    pub fn with_idempotency_key(self, key: impl Into<String>) -> Self {
+        Self {
+            idempotency_key: Some(key.into()),
+            ..self
+        }
+    }
+
+    /// Sets the conversation locale and returns a new instance
+    ///
+    /// Takes a BCP 47 tag such as `"de-DE"`, or a [`Locale`] with explicit
+    /// formats. The instructions go in a system segment tagged
+    /// [`Locale::SEGMENT_TAG`], replacing any earlier locale. See [`locale`]
+    /// for details.
+    ///
+    /// [`locale`]: crate::locale
+    #[must_use]
+    pub fn with_locale(self, locale: impl Into<Locale>) -> Self {
+        let locale = locale.into();
+        let segment = SystemSegment::new(locale.instructions()).with_tag(Locale::SEGMENT_TAG);
+        Self {
+            locale: Some(locale),
+            ..self
+        }
+        .with_system_segment(segment)
+    }
+
+    /// Screens this chat's user messages with `filter` and returns a new instance
+    ///
+    /// The filter replaces any set with [`filter::set_content_filter`] for
+    /// this chat.
+    ///
+    /// [`filter::set_content_filter`]: crate::filter::set_content_filter
+    #[must_use]
+    pub fn with_content_filter(self, filter: ContentFilter) -> Self {
+        Self {
+            content_filter: Some(Arc::new(filter)),
+            ..self
+        }
+    }
+
+    /// Checks tool results with `detector` before they're sent and returns a
+    /// new instance
+    ///
+    /// See [`injection`](crate::injection).
+    #[must_use]
+    pub fn with_injection_detector(self, detector: InjectionDetector) -> Self {
+        Self {
+            injection_detector: Some(Arc::new(detector)),
+            ..self
+        }
+    }
+
+    /// Minifies this chat's tool schemas as requests are built and returns
+    /// a new instance
+    ///
+    /// The chat keeps the full schemas; see [`minify`](crate::minify).
+    #[must_use]
+    pub fn with_schema_minifier(self, minifier: SchemaMinifier) -> Self {
+        Self {
+            schema_minifier: Some(minifier),
+            ..self
+        }
+    }
+
+    /// Moderates this chat's replies before they're returned and returns a
+    /// new instance
+    ///
+    /// See [`moderation`](crate::moderation).
+    #[must_use]
+    pub fn with_moderation(self, moderation: Moderation) -> Self {
+        Self {
+            moderation: Some(moderation),
+            ..self
+        }
+    }
+
+    /// The tool definitions as they're sent, minified if the chat has a
+    /// schema minifier
+    pub(crate) fn sent_tools(&self) -> Vec<LlmToolInfo> {
+        let tools = self.tools.iter().flatten();
+        match &self.schema_minifier {
+            Some(minifier) => tools.map(|tool| minifier.minify(tool)).collect(),
+            None => tools.cloned().collect(),
+        }
+    }
+
+    /// Starts every reply with `prefix` and returns a new instance
+    ///
+    /// Useful for forcing an output format, such as `{"result":` for JSON.
+    /// Providers that support prefilled assistant turns continue from the
+    /// prefix; others are told to start with it. Either way the prefix is
+    /// part of the returned reply. See [`prefill`](crate::prefill).
+    #[must_use]
+    pub fn with_assistant_prefix(self, prefix: impl Into<String>) -> Self {
+        Self {
+            assistant_prefix: Some(prefix.into()),
+            ..self
+        }
+    }
+
+    /// Merges raw fields into the request payload sent to `provider` and
+    /// returns a new instance
+    ///
+    /// This is an escape hatch for provider features the crate doesn't
+    /// support yet. Nested objects are merged key by key, so
+    /// `{"generationConfig": {"seed": 7}}` adds to Gemini's generation
+    /// config; anything else replaces the field. Extras for other providers
+    /// are ignored, and repeated calls merge in the same way.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::ProviderExtraNotObject` if `extra` isn't a
+    /// JSON object.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::model::Provider;
+    /// use language_barrier_core::Chat;
+    /// use serde_json::json;
+    ///
+    /// let chat = Chat::default()
+    ///     .with_provider_extra(Provider::OpenAi, json!({ "service_tier": "flex" }))
+    ///     .unwrap();
+    /// assert_eq!(chat.provider_extras[&Provider::OpenAi]["service_tier"], "flex");
+    ///
+    /// assert!(Chat::default().with_provider_extra(Provider::OpenAi, json!([1])).is_err());
+    /// ```
+    pub fn with_provider_extra(self, provider: Provider, extra: serde_json::Value) -> Result<Self> {
+        let serde_json::Value::Object(extra) = extra else {
+            return Err(ChatConfigError::ProviderExtraNotObject {
+                provider: provider.to_string(),
+            }
+            .into());
+        };
+        let mut provider_extras = self.provider_extras;
+        merge_json(provider_extras.entry(provider).or_default(), extra);
+        Ok(Self {
+            provider_extras,
+            ..self
+        })
+    }
+
+    /// Sets the tenant this chat's calls are made for and returns a new
+    /// instance
+    ///
+    /// A [`TenantService`](crate::tenancy::TenantService) picks the
+    /// tenant's service, limits and budget by this ID.
+    #[must_use]
+    pub fn with_tenant(self, tenant: impl Into<String>) -> Self {
+        Self {
+            tenant: Some(tenant.into()),
+            ..self
+        }
+    }
+
+    /// Sets the priority of this chat's calls and returns a new instance
+    ///
+    /// Chats are [`Priority::Interactive`] by default. A
+    /// [`PriorityLimiter`](crate::priority::PriorityLimiter) serves queued
+    /// interactive calls before background ones.
+    #[must_use]
+    pub fn with_priority(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// Sets the conversation's ID and returns a new instance
+    ///
+    /// Chats get a random ID when they're created; use this to give a
+    /// restored conversation back its own.
+    #[must_use]
+    pub fn with_conversation_id(self, id: impl Into<String>) -> Self {
+        Self {
+            conversation_id: id.into(),
+            ..self
+        }
+    }
+
+    /// Takes conversation and message IDs from `ids` and returns a new
+    /// instance
+    ///
+    /// A chat still using its generated conversation ID gets a new one from
+    /// `ids`. An ID given with [`with_conversation_id`] or restored from a
+    /// store is kept, whichever order the calls come in. Messages already
+    /// in the history keep their IDs too. See [`ids`](crate::ids).
+    ///
+    /// [`with_conversation_id`]: Chat::with_conversation_id
+    #[must_use]
+    pub fn with_id_generator(self, ids: Arc<dyn IdGenerator>) -> Self {
+        if self.conversation_id != self.generated_conversation_id {
+            return Self { ids, ..self };
+        }
+        let conversation_id = ids.conversation_id();
+        Self {
+            generated_conversation_id: conversation_id.clone(),
+            conversation_id,
+            ids,
+            ..self
+        }
+    }
+
+    /// Returns the message in the history with `id`
+    pub fn message(&self, id: &str) -> Option<&Message> {
+        self.history.iter().find(|msg| msg.message_id() == Some(id))
+    }
+
+    /// Adds `feedback` to the message with `id` and returns a new instance
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::UnknownMessage` if no message in the
+    /// history has that ID.
+    pub fn with_feedback(self, id: &str, feedback: Feedback) -> Result<Self> {
+        let index = self.index_of(id)?;
+        let mut history = self.history.clone();
+        history[index] = history[index].clone().with_feedback(feedback);
+        Ok(Self { history, ..self })
+    }
+
+    /// Returns a record of each piece of feedback given on the history's
+    /// messages, for export
+    pub fn feedback(&self) -> Vec<FeedbackRecord> {
+        feedback::records(&self.conversation_id, &self.history)
+    }
+
+    /// Replaces the text of the message with `id`, drops the history after
+    /// it and returns a new instance
+    ///
+    /// This is edit-and-resubmit: whatever followed the old text no longer
+    /// applies, so the next call answers the edited message. The message
+    /// keeps its ID but loses its feedback, which was about the old text,
+    /// and its finish reason, truncation flag and usage, so an edited reply
+    /// is never taken for one that was cut off. An edited assistant message loses its tool calls along with their
+    /// results; an edited tool result keeps the other results of its turn,
+    /// so every call still has an answer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::UnknownMessage` if no message in the
+    /// history has that ID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Chat, Message};
+    ///
+    /// let chat = Chat::default()
+    ///     .add_message(Message::user("Whats 2 + 2?").with_message_id("q"))
+    ///     .add_message(Message::assistant("Did you mean \"what's\"?"))
+    ///     .edit_message("q", "What's 2 + 2?")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(chat.history.len(), 1);
+    /// assert_eq!(chat.history[0].text_content(), "What's 2 + 2?");
+    /// ```
+    pub fn edit_message(self, id: &str, text: impl Into<String>) -> Result<Self> {
+        let index = self.index_of(id)?;
+        let mut history = self.history[..self.turn_end(index)].to_vec();
+        history[index] = history[index].clone().edited(text);
+        Ok(self.with_history(history))
+    }
+
+    /// Drops the message with `id` and the history after it, so the next
+    /// call generates a new reply in its place, and returns a new instance
+    ///
+    /// `id` is normally an assistant reply. For a user message or a tool
+    /// result, the history is cut after it instead (and after the other
+    /// results of a tool result's turn), so the next call answers it again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::UnknownMessage` if no message in the
+    /// history has that ID.
+    pub fn regenerate_from(self, id: &str) -> Result<Self> {
+        let index = self.index_of(id)?;
+        let end = match self.history[index] {
+            Message::Assistant { .. } => index,
+            _ => self.turn_end(index),
+        };
+        let history = self.history[..end].to_vec();
+        Ok(self.with_history(history))
+    }
+
+    /// Returns the position of the message with `id` in the history
+    fn index_of(&self, id: &str) -> Result<usize> {
+        self.history
+            .iter()
+            .position(|msg| msg.message_id() == Some(id))
+            .ok_or_else(|| ChatConfigError::UnknownMessage { id: id.to_string() }.into())
+    }
+
+    /// Returns the position after the message at `index`, or after the
+    /// tool results following it if it's a tool result itself
+    fn turn_end(&self, index: usize) -> usize {
+        let siblings = match self.history[index] {
+            Message::Tool { .. } => self.history[index + 1..]
+                .iter()
+                .take_while(|msg| matches!(msg, Message::Tool { .. }))
+                .count(),
+            _ => 0,
+        };
+        index + 1 + siblings
+    }
+
+    /// Gives `msg` a message ID if it doesn't have one
+    fn identified(&self, msg: Message) -> Message {
+        if msg.message_id().is_some() {
+            msg
+        } else {
+            msg.with_message_id(self.ids.message_id())
+        }
+    }
+
+    /// Returns the model capabilities this chat relies on
+    ///
+    /// Tools count once any are registered; vision counts once any message
+    /// carries an image or document part; audio counts once a spoken reply
+    /// is requested or a user message carries an audio part; JSON mode
+    /// counts once an output schema is set.
+    #[must_use]
+    pub fn required_capabilities(&self) -> Vec<ModelCapability> {
+        let mut capabilities = Vec::new();
+
+        if self.tools.as_ref().is_some_and(|tools| !tools.is_empty()) {
+            capabilities.push(ModelCapability::Tools);
+        }
+
+        #[cfg(feature = "multimodal")]
+        self.push_multimodal_capabilities(&mut capabilities);
+
+        if self.output_schema.is_some() {
+            capabilities.push(ModelCapability::JsonMode);
+        }
+
+        capabilities
+    }
+
+    /// Adds the capabilities the chat's attachments and audio need
+    #[cfg(feature = "multimodal")]
+    fn push_multimodal_capabilities(&self, capabilities: &mut Vec<ModelCapability>) {
+        let has_attachments = self.history.iter().any(|msg| {
+            let content = match msg {
+                Message::User { content, .. } => Some(content),
+                Message::Assistant { content, .. } => content.as_ref(),
+                Message::System { .. } | Message::Tool { .. } => None,
+            };
+            matches!(content, Some(Content::Parts(parts)) if parts.iter().any(|part| {
+                matches!(part, ContentPart::ImageUrl { .. } | ContentPart::Document { .. })
+            }))
+        });
+        if has_attachments {
+            capabilities.push(ModelCapability::Vision);
+        }
+
+        let has_audio_input = self.history.iter().any(|msg| {
+            matches!(msg, Message::User { content: Content::Parts(parts), .. }
+                if parts.iter().any(|part| matches!(part, ContentPart::Audio { .. })))
+        });
+        if self.audio_output.is_some() || has_audio_input {
+            capabilities.push(ModelCapability::Audio);
+        }
+    }
+
+    /// Checks that `model` supports every capability this chat relies on
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnsupportedCapability` for the first missing capability.
+    ///
+    /// # Examples
+    ///
+    #[cfg_attr(all(feature = "openai", feature = "multimodal"), doc = "```")]
+    #[cfg_attr(not(all(feature = "openai", feature = "multimodal")), doc = "```ignore")]
+    /// use language_barrier_core::{Chat, Error, Message, ModelCapability, OpenAi};
+    ///
+    /// let chat = Chat::default().add_message(
+    ///     Message::user("What's in this picture?").with_image_url("https://example.com/cat.jpg"),
+    /// );
+    ///
+    /// assert!(chat.check_capabilities(&OpenAi::GPT4o).is_ok());
+    /// assert!(matches!(
+    ///     chat.check_capabilities(&OpenAi::GPT35Turbo),
+    ///     Err(Error::UnsupportedCapability { capability: ModelCapability::Vision, .. })
+    /// ));
+    /// ```
+    pub fn check_capabilities<M: ModelInfo>(&self, model: &M) -> Result<()> {
+        match self
+            .required_capabilities()
+            .into_iter()
+            .find(|capability| !model.supports(*capability))
+        {
+            Some(capability) => Err(Error::UnsupportedCapability {
+                model: format!("{model:?}"),
+                capability,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns how `other` differs from this chat
+    ///
+    /// See [`replay`] for how messages are compared.
+    pub fn diff(&self, other: &Chat) -> ChatDiff {
+        replay::diff(self, other)
+    }
+
+    /// Finishes the reply at the end of the chat, which was cut off by the
+    /// output token limit or a dropped stream
+    ///
+    /// The service is asked to continue in the way its provider supports
+    /// (see [`Continuation`](crate::salvage::Continuation)), and the
+    /// continuation is stitched onto the reply, dropping any text the model
+    /// repeated. The stitched reply takes the continuation's metadata, such
+    /// as its usage and finish reason, so it can be continued again if the
+    /// continuation was cut off too.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::NothingToContinue` if the last message
+    /// isn't an assistant reply with [`FinishReason::Length`] or marked
+    /// [`truncated`](Message::truncated), or any error from the service.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Chat, FinishReason, LLMService, Message, OpenAi, Result};
+    ///
+    /// struct Finisher;
+    ///
+    /// #[async_trait::async_trait]
+    /// impl LLMService<OpenAi> for Finisher {
+    ///     async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+    ///         Ok(Message::assistant("cities are Tokyo, Delhi and Shanghai."))
+    ///     }
+    /// }
+    ///
+    /// # tokio_test::block_on(async {
+    /// let cut_off = Message::assistant("The three largest cities are")
+    ///     .with_metadata(FinishReason::METADATA_KEY, FinishReason::Length.to_metadata());
+    /// let chat = Chat::default()
+    ///     .add_message(Message::user("Name the three largest cities."))
+    ///     .add_message(cut_off);
+    ///
+    /// let chat = chat.continue_last(&Finisher).await.unwrap();
+    /// assert_eq!(chat.history.len(), 2);
+    /// assert_eq!(
+    ///     chat.history[1].text_content(),
+    ///     "The three largest cities are Tokyo, Delhi and Shanghai."
+    /// );
+    /// assert_eq!(chat.history[1].finish_reason(), None);
+    /// # });
+    /// ```
+    ///
+    /// [`FinishReason::Length`]: crate::message::FinishReason::Length
+    pub async fn continue_last<M, S>(self, service: &S) -> Result<Chat>
+    where
+        M: ModelInfo,
+        S: LLMService<M> + Sync,
+    {
+        salvage::continue_last(service, self).await
+    }
+
+    /// Return the most recent message in the chat.
+    pub fn most_recent_message(&self) -> Option<&Message> {
+        self.history.last()
+    }
+}
+
+/// Builds a [`Chat`] for a specific model, validating the configuration
+///
+/// `Chat` itself is model-agnostic and accepts any configuration. The
+/// builder knows which model the chat is meant for, so `build()` can catch
+/// mistakes that would otherwise only surface as a provider error:
+///
+/// - tools configured for a model that can't call them
+/// - `max_output_tokens` above the model's output limit, if it has a known one
+/// - an empty history
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::{Chat, ChatConfigError, Error, Message, OpenAi};
+///
+/// let result = Chat::builder(OpenAi::GPT4o)
+///     .with_max_output_tokens(1_000_000)
+///     .add_message(Message::user("Write me a novel."))
+///     .build();
+///
+/// assert!(matches!(
+///     result,
+///     Err(Error::ChatConfig(ChatConfigError::MaxOutputTokensExceeded { limit: 16_384, .. }))
+/// ));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ChatBuilder<M: ModelInfo> {
+    model: M,
+    chat: Chat,
+}
+
+impl<M: ModelInfo> ChatBuilder<M> {
+    /// Creates a builder for a chat with `model`
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            chat: Chat::default(),
+        }
+    }
+
+    /// Sets the system prompt
+    #[must_use]
+    pub fn with_system_prompt(self, prompt: impl Into<String>) -> Self {
+        self.map(|chat| chat.with_system_prompt(prompt))
+    }
+
+    /// Adds a system prompt segment
+    #[must_use]
+    pub fn with_system_segment(self, segment: SystemSegment) -> Self {
+        self.map(|chat| chat.with_system_segment(segment))
+    }
+
+    /// Sets the maximum number of output tokens
+    #[must_use]
+    pub fn with_max_output_tokens(self, n: usize) -> Self {
+        self.map(|chat| chat.with_max_output_tokens(n))
+    }
+
+    /// Sets the sampling temperature
+    #[must_use]
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        self.map(|chat| chat.with_temperature(temperature))
+    }
+
+    /// Sets the system prompt from a registered template
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the prompt isn't registered or a placeholder
+    /// has no value.
+    pub fn with_prompt<K, V>(self, id: &str, vars: impl IntoIterator<Item = (K, V)>) -> Result<Self>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        Ok(Self {
+            chat: self.chat.with_prompt(id, vars)?,
+            ..self
+        })
+    }
+
+    /// Applies a named generation profile
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there's no such profile.
+    pub fn with_profile(self, name: &str) -> Result<Self> {
+        Ok(Self {
+            chat: self.chat.with_profile(name)?,
+            ..self
+        })
+    }
+
+    /// Replaces the conversation history
+    #[must_use]
+    pub fn with_history(self, history: Vec<Message>) -> Self {
+        self.map(|chat| chat.with_history(history))
+    }
+
+    /// Adds a message to the conversation history
+    #[must_use]
+    pub fn add_message(self, msg: Message) -> Self {
+        self.map(|chat| chat.add_message(msg))
+    }
+
+    /// Adds a tool
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tool's schema can't be generated.
+    #[cfg(feature = "tools")]
+    pub fn with_tool(self, tool: impl ToolDefinition) -> Result<Self> {
+        Ok(Self {
+            chat: self.chat.with_tool(tool)?,
+            ..self
+        })
+    }
+
+    /// Adds several tools at once
+    #[must_use]
+    pub fn with_tools(self, tools: Vec<LlmToolInfo>) -> Self {
+        self.map(|chat| chat.with_tools(tools))
+    }
+
+    /// Sets the tool choice strategy
+    #[must_use]
+    pub fn with_tool_choice(self, choice: ToolChoice) -> Self {
+        self.map(|chat| chat.with_tool_choice(choice))
+    }
+
+    /// Sets whether the model may make several tool calls in one response
+    #[must_use]
+    pub fn with_parallel_tool_calls(self, enabled: bool) -> Self {
+        self.map(|chat| chat.with_parallel_tool_calls(enabled))
+    }
+
+    /// Asks for a spoken reply in addition to text
+    #[cfg(feature = "multimodal")]
+    #[must_use]
+    pub fn with_audio_output(self, audio: AudioOutput) -> Self {
+        self.map(|chat| chat.with_audio_output(audio))
+    }
+
+    /// Constrains the reply to JSON matching `schema`
+    #[must_use]
+    pub fn with_output_schema(self, schema: OutputSchema) -> Self {
+        self.map(|chat| chat.with_output_schema(schema))
+    }
+
+    /// Constrains the reply to exactly one of `choices`
+    #[must_use]
+    pub fn with_choice_output<I, S>(self, choices: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.map(|chat| chat.with_choice_output(choices))
+    }
+
+    /// Asks for the log probability of each generated token
+    #[must_use]
+    pub fn with_logprobs(self, top_alternatives: u8) -> Self {
+        self.map(|chat| chat.with_logprobs(top_alternatives))
+    }
+
+    /// Sets the conversation locale
+    #[must_use]
+    pub fn with_locale(self, locale: impl Into<Locale>) -> Self {
+        self.map(|chat| chat.with_locale(locale))
+    }
+
+    /// Sets the idempotency key scope
+    #[must_use]
+    pub fn with_idempotency_key(self, key: impl Into<String>) -> Self {
+        self.map(|chat| chat.with_idempotency_key(key))
+    }
+
+    /// Screens user messages with a content filter
+    #[must_use]
+    pub fn with_content_filter(self, filter: ContentFilter) -> Self {
+        self.map(|chat| chat.with_content_filter(filter))
+    }
+
+    /// Checks tool results for likely prompt injection
+    #[must_use]
+    pub fn with_injection_detector(self, detector: InjectionDetector) -> Self {
+        self.map(|chat| chat.with_injection_detector(detector))
+    }
+
+    /// Minifies tool schemas as requests are built
+    #[must_use]
+    pub fn with_schema_minifier(self, minifier: SchemaMinifier) -> Self {
+        self.map(|chat| chat.with_schema_minifier(minifier))
+    }
+
+    /// Moderates replies before they're returned
+    #[must_use]
+    pub fn with_moderation(self, moderation: Moderation) -> Self {
+        self.map(|chat| chat.with_moderation(moderation))
+    }
+
+    /// Starts every reply with a prefix
+    #[must_use]
+    pub fn with_assistant_prefix(self, prefix: impl Into<String>) -> Self {
+        self.map(|chat| chat.with_assistant_prefix(prefix))
+    }
+
+    /// Merges raw fields into the request payload sent to a provider
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `extra` isn't a JSON object.
+    pub fn with_provider_extra(self, provider: Provider, extra: serde_json::Value) -> Result<Self> {
+        Ok(Self {
+            chat: self.chat.with_provider_extra(provider, extra)?,
+            ..self
+        })
+    }
+
+    /// Sets the tenant this chat's calls are made for
+    #[must_use]
+    pub fn with_tenant(self, tenant: impl Into<String>) -> Self {
+        self.map(|chat| chat.with_tenant(tenant))
+    }
+
+    /// Sets the priority of this chat's calls
+    #[must_use]
+    pub fn with_priority(self, priority: Priority) -> Self {
+        self.map(|chat| chat.with_priority(priority))
+    }
+
+    /// Sets the conversation's ID
+    #[must_use]
+    pub fn with_conversation_id(self, id: impl Into<String>) -> Self {
+        self.map(|chat| chat.with_conversation_id(id))
+    }
+
+    /// Takes conversation and message IDs from `ids`
+    #[must_use]
+    pub fn with_id_generator(self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.map(|chat| chat.with_id_generator(ids))
+    }
+
+    /// Validates the configuration and returns the finished `Chat`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ChatConfig` describing the first problem found.
+    pub fn build(self) -> Result<Chat> {
+        let model = format!("{:?}", self.model);
+
+        if let Some(tools) = &self.chat.tools
+            && !tools.is_empty()
+            && !self.model.supports(ModelCapability::Tools)
+        {
+            return Err(ChatConfigError::ToolsNotSupported {
+                model,
+                tool_count: tools.len(),
+            }
+            .into());
+        }
+
+        if let Some(limit) = self.model.output_token_limit()
+            && self.chat.max_output_tokens > limit
+        {
+            return Err(ChatConfigError::MaxOutputTokensExceeded {
+                model,
+                requested: self.chat.max_output_tokens,
+                limit,
+            }
+            .into());
+        }
+
+        if self.chat.history.is_empty() {
+            return Err(ChatConfigError::EmptyHistory.into());
+        }
+
+        Ok(self.chat)
+    }
+
+    fn map(self, f: impl FnOnce(Chat) -> Chat) -> Self {
+        Self {
+            chat: f(self.chat),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Mistral, OpenAi};
+
+    fn weather_tool() -> LlmToolInfo {
+        LlmToolInfo {
+            name: "get_weather".to_string(),
+            description: "Get the weather".to_string(),
+            parameters: serde_json::json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    #[test]
+    fn test_builder_builds_valid_chat() {
+        let chat = ChatBuilder::new(OpenAi::GPT4o)
+            .with_system_prompt("You are a helpful assistant.")
+            .with_max_output_tokens(1024)
+            .with_tools(vec![weather_tool()])
+            .add_message(Message::user("Hello"))
+            .build()
+            .unwrap();
+
+        assert_eq!(chat.system_prompt, "You are a helpful assistant.");
+        assert_eq!(chat.max_output_tokens, 1024);
+        assert_eq!(chat.history.len(), 1);
+        assert_eq!(chat.tools.map(|t| t.len()), Some(1));
+    }
+
+    #[test]
+    fn test_builder_rejects_tools_for_toolless_model() {
+        let result = ChatBuilder::new(Mistral::Embed)
+            .with_max_output_tokens(1024)
+            .with_tools(vec![weather_tool()])
+            .add_message(Message::user("Hello"))
+            .build();
+
+        match result {
+            Err(Error::ChatConfig(ChatConfigError::ToolsNotSupported { model, tool_count })) => {
+                assert_eq!(model, "Embed");
+                assert_eq!(tool_count, 1);
+            }
+            other => panic!("Expected ToolsNotSupported, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_builder_rejects_excessive_max_output_tokens() {
+        let result = ChatBuilder::new(OpenAi::GPT4o)
+            .with_max_output_tokens(20_000)
+            .add_message(Message::user("Hello"))
+            .build();
+
+        match result {
+            Err(Error::ChatConfig(ChatConfigError::MaxOutputTokensExceeded {
+                requested,
+                limit,
+                ..
+            })) => {
+                assert_eq!(requested, 20_000);
+                assert_eq!(limit, 16_384);
+            }
+            other => panic!("Expected MaxOutputTokensExceeded, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_models_without_a_known_limit_are_not_clamped() {
+        use crate::model::Ollama;
+
+        let chat = Chat::default()
+            .with_max_output_tokens(50_000)
+            .with_output_token_policy(OutputTokenPolicy::Reject);
+        assert_eq!(chat.max_output_tokens_for(&Mistral::Large).unwrap(), 50_000);
+        let custom = Ollama::Custom { name: "qwen3" };
+        assert_eq!(chat.max_output_tokens_for(&custom).unwrap(), 50_000);
+        assert!(chat.max_output_tokens_for(&OpenAi::GPT4o).is_err());
+    }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_required_capabilities() {
+        let chat = Chat::default().add_message(Message::user("Hello"));
+        assert!(chat.required_capabilities().is_empty());
+
+        let chat = chat
+            .with_tools(vec![weather_tool()])
+            .add_message(Message::user("Summarize").with_document(b"%PDF", "application/pdf"));
+        assert_eq!(
+            chat.required_capabilities(),
+            vec![ModelCapability::Tools, ModelCapability::Vision]
+        );
+
+        assert!(chat.check_capabilities(&OpenAi::GPT4o).is_ok());
+        assert!(matches!(
+            chat.check_capabilities(&OpenAi::O1Mini),
+            Err(Error::UnsupportedCapability {
+                capability: ModelCapability::Tools,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_max_output_tokens_policy() {
+        let chat = Chat::default().with_max_output_tokens(1024);
+        assert_eq!(chat.max_output_tokens_for(&OpenAi::GPT4o).unwrap(), 1024);
+
+        let chat = chat.with_max_output_tokens(20_000);
+        assert_eq!(chat.max_output_tokens_for(&OpenAi::GPT4o).unwrap(), 16_384);
+
+        let chat = chat.with_output_token_policy(OutputTokenPolicy::Reject);
+        assert!(matches!(
+            chat.max_output_tokens_for(&OpenAi::GPT4o),
+            Err(Error::ChatConfig(ChatConfigError::MaxOutputTokensExceeded {
+                requested: 20_000,
+                limit: 16_384,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_builder_rejects_empty_history() {
+        let result = ChatBuilder::new(OpenAi::GPT4o).build();
+        assert!(matches!(
+            result,
+            Err(Error::ChatConfig(ChatConfigError::EmptyHistory))
+        ));
+    }
+
+    #[test]
+    fn test_token_report_sections() {
+        use crate::message::{Function, ToolCall};
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "get_weather".to_string(),
+                arguments: r#"{"city": "Paris"}"#.to_string(),
+            },
+        };
+        let mut question = Message::user("Weather in Paris?");
+        question.cache_token_count(TokenCounter::TOKENIZER, 40);
+        let chat = Chat::default()
+            .with_system_prompt("Be brief.")
+            .with_tools(vec![weather_tool()])
+            .add_message(question)
+            .add_message(Message::assistant_with_tool_calls(vec![call]))
+            .add_message(Message::tool("call_1", "Sunny and warm"));
+
+        let report = chat.token_report(&TokenCounter::new());
+
+        assert_eq!(report.system, 2);
+        // Name, description and the schema's JSON text
+        assert_eq!(report.tools, 1 + 3 + 1);
+        // The cached count, the tool call's name and arguments, the result
+        let texts: Vec<usize> = report.messages.iter().map(|m| m.text).collect();
+        assert_eq!(texts, [40, 1 + 2, 3]);
+        assert_eq!(report.history, 46);
+        assert_eq!(report.total(), 53);
+        assert_eq!(report.largest_messages(1)[0].index, 0);
+        assert!(report.to_string().contains("history"));
+    }
+
+    #[test]
+    fn test_edit_and_regenerate_keep_tool_calls_answered() {
+        use crate::message::{Function, ToolCall};
+
+        let call = |id: &str| ToolCall {
+            id: id.to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "get_weather".to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+        let chat = Chat::default()
+            .add_message(Message::user("Weather?").with_message_id("q"))
+            .add_message(
+                Message::assistant_with_tool_calls(vec![call("call_1"), call("call_2")])
+                    .with_message_id("calls"),
+            )
+            .add_message(Message::tool("call_1", "Sunny").with_message_id("r1"))
+            .add_message(Message::tool("call_2", "Warm").with_message_id("r2"))
+            .add_message(Message::assistant("Sunny and warm.").with_message_id("a"));
+
+        let regenerated = chat.clone().regenerate_from("a").unwrap();
+        assert_eq!(regenerated.history.len(), 4);
+        let regenerated = chat.clone().regenerate_from("r1").unwrap();
+        assert_eq!(regenerated.history.len(), 4);
+        let regenerated = chat.clone().regenerate_from("calls").unwrap();
+        assert_eq!(regenerated.history.len(), 1);
+
+        let edited = chat.clone().edit_message("r1", "Rainy").unwrap();
+        assert_eq!(edited.history.len(), 4);
+        assert_eq!(edited.history[2].text_content(), "Rainy");
+        assert_eq!(edited.history[2].message_id(), Some("r1"));
+
+        let edited = chat
+            .clone()
+            .with_feedback("calls", crate::feedback::Feedback::down())
+            .unwrap()
+            .edit_message("calls", "Take an umbrella.")
+            .unwrap();
+        assert_eq!(edited.history.len(), 2);
+        assert!(
+            matches!(&edited.history[1], Message::Assistant { tool_calls, .. } if tool_calls.is_empty())
+        );
+        assert!(edited.history[1].feedback().is_empty());
+
+        assert!(matches!(
+            chat.regenerate_from("missing"),
+            Err(Error::ChatConfig(ChatConfigError::UnknownMessage { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_edited_reply_is_not_truncated() {
+        use crate::message::FinishReason;
+        use crate::usage::Usage;
+
+        let chat = Chat::default()
+            .add_message(Message::user("Write a haiku."))
+            .add_message(
+                Message::assistant("Autumn moonlight,")
+                    .with_message_id("a")
+                    .with_metadata(
+                        FinishReason::METADATA_KEY,
+                        FinishReason::Length.to_metadata(),
+                    )
+                    .with_metadata(Usage::METADATA_KEY, Usage::new(5, 4).to_metadata())
+                    .truncated(),
+            );
+        assert!(chat.history[1].is_truncated());
+
+        let edited = chat
+            .edit_message("a", "Autumn moonlight, a worm digs silently.")
+            .unwrap();
+        let reply = &edited.history[1];
+        assert!(!reply.is_truncated());
+        assert_eq!(reply.finish_reason(), None);
+        assert_eq!(reply.usage(), None);
+    }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_token_report_counts_attachments() {
+        use crate::message::ContentPart;
+        use crate::token::ATTACHMENT_ESTIMATE;
+
+        let photo = Message::user("What is this?")
+            .with_part(ContentPart::image_url("https://example.com/a.png"));
+        let chat = Chat::default().add_message(photo);
+
+        let report = chat.token_report(&TokenCounter::new());
+
+        assert_eq!(report.history, 3);
+        assert_eq!(report.attachments, ATTACHMENT_ESTIMATE);
+        assert_eq!(report.messages[0].total(), 3 + ATTACHMENT_ESTIMATE);
     }
 }
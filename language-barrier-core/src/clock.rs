@@ -0,0 +1,121 @@
+//! Where services and providers get the time
+//!
+//! Responses are stamped with their creation time, audit records with the
+//! time of the call, and model retirement is checked against today's date.
+//! All of these come from a [`Clock`], which is the real
+//! [`SystemClock`] unless one is set with
+//! [`HTTPLlmService::with_clock`](crate::llm_service::HTTPLlmService::with_clock)
+//! or a provider's `with_clock`. Tests use a [`ManualClock`] to get the same
+//! timestamps, and IDs derived from them, on every run.
+//!
+//! Rate limiters and timeouts measure durations with tokio's clock instead;
+//! pause it with `tokio::time::pause` to control them.
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "anthropic", doc = "```")]
+#![cfg_attr(not(feature = "anthropic"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use language_barrier_core::clock::{Clock, ManualClock};
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::provider::anthropic::AnthropicProvider;
+//! use language_barrier_core::Claude;
+//!
+//! let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+//! let service = HTTPLlmService::new(Claude::Haiku35, Arc::new(AnthropicProvider::new()))
+//!     .with_clock(clock.clone());
+//!
+//! clock.advance(Duration::from_secs(60));
+//! assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(1_700_000_060));
+//! ```
+
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+/// A source of the current wall-clock time
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current time
+    fn now(&self) -> SystemTime;
+}
+
+/// The system's clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at `now`
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Sets the clock to `now`
+    pub fn set(&self, now: SystemTime) {
+        *self.time() = now;
+    }
+
+    /// Moves the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        *self.time() += by;
+    }
+
+    fn time(&self) -> MutexGuard<'_, SystemTime> {
+        self.now
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.time()
+    }
+}
+
+#[cfg(all(test, feature = "openai"))]
+mod tests {
+    use super::*;
+    use crate::audit::MemoryAuditSink;
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    use crate::provider::openai::OpenAIProvider;
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use crate::{Chat, Message, OpenAi};
+    use std::sync::Arc;
+    use std::time::UNIX_EPOCH;
+
+    #[tokio::test]
+    async fn test_service_stamps_replies_and_audits_with_its_clock() {
+        let reply = r#"{"id":"1","object":"chat.completion","created":0,"model":"gpt-4o",
+            "choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#;
+        let transport = Arc::new(MockTransport::new().with_response(MockResponse::ok(reply)));
+        let sink = Arc::new(MemoryAuditSink::new());
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::new()),
+            transport,
+        )
+        .with_audit_sink(sink.clone())
+        .with_clock(Arc::new(ManualClock::new(at)));
+
+        let chat = Chat::default().add_message(Message::user("Hello"));
+        let message = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(message.created_at(), Some(at));
+        assert_eq!(sink.records()[0].timestamp_ms, 1_700_000_000_000);
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+use crate::audit::CREDENTIAL_HEADERS;
+use crate::error::{Error, Result};
+use crate::message::Message;
+use crate::signing::RequestSigner;
+use crate::transport::HttpRequest;
+
+type Slot = Arc<OnceCell<std::result::Result<Message, String>>>;
+
+/// Shares one in-flight provider call among identical concurrent requests
+///
+/// When several callers send the same request at the same time (for example
+/// retries arriving at different replicas of a frontend), only the first one
+/// reaches the provider; the rest wait for and receive a copy of its response.
+/// Requests are only coalesced while one is in flight; nothing is cached once
+/// it completes.
+///
+/// The coalescer is cheap to clone and clones share their in-flight map, so
+/// one coalescer can be attached to several services with
+/// [`HTTPLlmService::with_coalescer`], which keys requests by a hash of the
+/// provider payload, the credentials and the chat's tenant.
+///
+/// If the shared call fails, the caller that made it gets the original error
+/// and every other caller gets an `Error::Other` describing it.
+///
+/// [`HTTPLlmService::with_coalescer`]: crate::llm_service::HTTPLlmService::with_coalescer
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::Message;
+/// use language_barrier_core::coalesce::RequestCoalescer;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # tokio_test::block_on(async {
+/// let coalescer = RequestCoalescer::new();
+/// let calls = AtomicUsize::new(0);
+/// let call = || async {
+///     calls.fetch_add(1, Ordering::SeqCst);
+///     tokio::task::yield_now().await;
+///     Ok(Message::assistant("Hi!"))
+/// };
+///
+/// let (a, b) = tokio::join!(coalescer.run(42, call), coalescer.run(42, call));
+/// assert_eq!(a.unwrap(), b.unwrap());
+/// assert_eq!(calls.load(Ordering::SeqCst), 1);
+/// # });
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RequestCoalescer {
+    in_flight: Arc<Mutex<HashMap<u64, Slot>>>,
+}
+
+impl RequestCoalescer {
+    /// Creates a coalescer with nothing in flight
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of distinct requests currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Runs `call` unless a call with the same key is already in flight, in
+    /// which case its result is awaited and shared instead
+    pub async fn run<F, Fut>(&self, key: u64, call: F) -> Result<Message>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Message>>,
+    {
+        let slot = self.lock().entry(key).or_default().clone();
+
+        let mut led = false;
+        let mut own_error = None;
+        let shared = slot
+            .get_or_init(|| async {
+                led = true;
+                call().await.map_err(|e| {
+                    let description = e.to_string();
+                    own_error = Some(e);
+                    description
+                })
+            })
+            .await
+            .clone();
+
+        {
+            let mut in_flight = self.lock();
+            if in_flight
+                .get(&key)
+                .is_some_and(|current| Arc::ptr_eq(current, &slot))
+            {
+                in_flight.remove(&key);
+            }
+        }
+
+        if let Some(e) = own_error {
+            return Err(e);
+        }
+        if !led {
+            debug!("Shared in-flight response for request {:016x}", key);
+        }
+
+        shared
+            .map_err(|description| Error::Other(format!("Coalesced request failed: {description}")))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Slot>> {
+        // The map is never left inconsistent, so a poisoned lock is still usable
+        self.in_flight
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Hashes the parts of a request that determine the provider's response
+pub(crate) fn payload_key(request: &HttpRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    request.method.as_str().hash(&mut hasher);
+    request.url.as_str().hash(&mut hasher);
+    request.body.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hashes the request's payload together with who it's made for
+///
+/// Besides the payload this covers the credential headers, the tenant and
+/// the signer, so a coalescer shared by services with different keys never
+/// hands one caller's response to another. Per-request headers such as
+/// `traceparent` and the idempotency key are left out, as are the headers a
+/// signer adds: they change with every request, so the signer itself stands
+/// in for them.
+pub(crate) fn request_key(
+    request: &HttpRequest,
+    tenant: Option<&str>,
+    signer: Option<&Arc<dyn RequestSigner>>,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    payload_key(request).hash(&mut hasher);
+    for name in CREDENTIAL_HEADERS {
+        for value in request.headers.get_all(*name) {
+            name.hash(&mut hasher);
+            value.as_bytes().hash(&mut hasher);
+        }
+    }
+    tenant.hash(&mut hasher);
+    signer
+        .map(|signer| Arc::as_ptr(signer).cast::<()>() as usize)
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Method;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_identical_requests_share_one_call() {
+        let coalescer = RequestCoalescer::new();
+        let calls = AtomicUsize::new(0);
+        let call = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            Ok(Message::assistant("shared"))
+        };
+
+        let (a, b, c) = tokio::join!(
+            coalescer.run(1, call),
+            coalescer.run(1, call),
+            coalescer.run(2, call)
+        );
+
+        assert_eq!(a.unwrap(), Message::assistant("shared"));
+        assert_eq!(b.unwrap(), Message::assistant("shared"));
+        assert!(c.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(coalescer.in_flight(), 0);
+
+        // Completed requests aren't cached
+        coalescer.run(1, call).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_errors_reach_every_caller() {
+        let coalescer = RequestCoalescer::new();
+        let call = || async {
+            tokio::task::yield_now().await;
+            Err(Error::ProviderUnavailable("down".to_string()))
+        };
+
+        let (leader, follower) = tokio::join!(coalescer.run(7, call), coalescer.run(7, call));
+
+        assert!(matches!(leader, Err(Error::ProviderUnavailable(_))));
+        assert!(matches!(follower, Err(Error::Other(msg)) if msg.contains("down")));
+    }
+
+    #[test]
+    fn test_request_key_depends_on_payload() {
+        let url = url::Url::parse("https://api.example.com/v1/chat").unwrap();
+        let request = |body: &str| {
+            let mut request = HttpRequest::new(Method::POST, url.clone());
+            request.body = body.as_bytes().to_vec();
+            request
+        };
+
+        assert_eq!(
+            request_key(&request("a"), None, None),
+            request_key(&request("a"), None, None)
+        );
+        assert_ne!(
+            request_key(&request("a"), None, None),
+            request_key(&request("b"), None, None)
+        );
+        assert_ne!(
+            request_key(&request("a"), Some("acme"), None),
+            request_key(&request("a"), Some("globex"), None)
+        );
+    }
+
+    #[test]
+    fn test_request_key_ignores_per_request_headers() {
+        let url = url::Url::parse("https://api.example.com/v1/chat").unwrap();
+        let request = |trace: &str| {
+            let mut request = HttpRequest::new(Method::POST, url.clone());
+            request
+                .headers
+                .insert("traceparent", trace.parse().unwrap());
+            request
+                .headers
+                .insert("Idempotency-Key", trace.parse().unwrap());
+            request
+        };
+
+        assert_eq!(
+            request_key(&request("one"), None, None),
+            request_key(&request("two"), None, None)
+        );
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test(start_paused = true)]
+    async fn test_services_with_different_keys_get_their_own_responses() {
+        use crate::llm_service::{HTTPLlmService, LLMService};
+        use crate::provider::openai::{OpenAIConfig, OpenAIProvider};
+        use crate::transport::mock::{MockResponse, MockTransport};
+        use crate::{Chat, OpenAi};
+        use std::time::Duration;
+
+        let reply = |text: &str| {
+            MockResponse::ok(
+                serde_json::json!({
+                    "id": "chatcmpl-1",
+                    "object": "chat.completion",
+                    "created": 1,
+                    "model": "gpt-4o",
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": text },
+                        "finish_reason": "stop"
+                    }]
+                })
+                .to_string(),
+            )
+            .with_delay(Duration::from_millis(100))
+        };
+        let coalescer = RequestCoalescer::new();
+        let service = |api_key: &str, text: &str| {
+            HTTPLlmService::new_with_transport(
+                OpenAi::GPT4o,
+                Arc::new(OpenAIProvider::with_config(OpenAIConfig {
+                    api_key: api_key.to_string(),
+                    ..OpenAIConfig::default()
+                })),
+                Arc::new(MockTransport::new().with_response(reply(text))),
+            )
+            .with_coalescer(coalescer.clone())
+        };
+        let acme = service("sk-acme", "for acme");
+        let globex = service("sk-globex", "for globex");
+        let chat = Chat::default().add_message(Message::user("Hello"));
+
+        let (a, b) = tokio::join!(
+            acme.generate_next_message(&chat),
+            globex.generate_next_message(&chat)
+        );
+
+        assert_eq!(a.unwrap().text_content(), "for acme");
+        assert_eq!(b.unwrap().text_content(), "for globex");
+    }
+}
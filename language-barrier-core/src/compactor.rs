@@ -1,3 +1,9 @@
+use std::fmt;
+use std::sync::Arc;
+
+use tracing::{debug, info};
+
+use crate::message::{Content, Message, ToolCall};
 use crate::token::TokenCounter;
 
 /// Trait for compacting chat history
@@ -34,47 +40,764 @@ impl ChatHistoryCompactor for DropOldestCompactor {
         while !counter.under_budget(max_tokens) && history.len() > 1 {
             // Remove the oldest message
             let removed_msg = history.remove(0);
+            counter.subtract_message(&removed_msg);
+        }
+    }
+}
 
-            // Update the token count based on message type
-            match &removed_msg {
-                crate::message::Message::User { content, .. } => {
-                    match content {
-                        crate::message::Content::Text(text) => {
-                            counter.subtract(text);
-                        }
-                        crate::message::Content::Parts(parts) => {
-                            // For multimodal content, count all text parts
-                            for part in parts {
-                                if let crate::message::ContentPart::Text { text } = part {
-                                    counter.subtract(text);
-                                }
-                            }
-                        }
-                    }
-                }
-                crate::message::Message::Assistant { content, .. } => {
-                    if let Some(content_data) = content {
-                        match content_data {
-                            crate::message::Content::Text(text) => {
-                                counter.subtract(text);
-                            }
-                            crate::message::Content::Parts(parts) => {
-                                // For multimodal content, count all text parts
-                                for part in parts {
-                                    if let crate::message::ContentPart::Text { text } = part {
-                                        counter.subtract(text);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                crate::message::Message::System { content, .. }
-                | crate::message::Message::Tool { content, .. } => {
-                    counter.subtract(content);
+/// Folds messages that leave a [`SlidingWindowCompactor`]'s window into its summary
+pub trait Summarizer: Send + Sync + Clone {
+    /// Returns `summary` updated with the `evicted` messages
+    ///
+    /// `overlap` holds the oldest messages still kept verbatim. They aren't
+    /// part of the summary, but let the summarizer see where it leaves off.
+    fn update(&self, summary: &str, evicted: &[Message], overlap: &[Message]) -> String;
+}
+
+/// A summarizer that keeps the first sentence of each evicted message
+///
+/// Lines are `role: sentence`. Once the summary passes its token budget,
+/// its oldest lines are dropped.
+#[derive(Debug, Clone)]
+pub struct ExtractiveSummarizer {
+    max_tokens: usize,
+}
+
+impl Default for ExtractiveSummarizer {
+    fn default() -> Self {
+        Self { max_tokens: 512 }
+    }
+}
+
+impl ExtractiveSummarizer {
+    /// Creates a summarizer with a 512-token budget
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the summary's token budget
+    #[must_use]
+    pub fn with_max_tokens(self, max_tokens: usize) -> Self {
+        Self { max_tokens }
+    }
+}
+
+impl Summarizer for ExtractiveSummarizer {
+    fn update(&self, summary: &str, evicted: &[Message], _overlap: &[Message]) -> String {
+        let mut lines: Vec<String> = summary.lines().map(str::to_string).collect();
+        for msg in evicted {
+            let text = msg.text_content();
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let sentence = text
+                .find(['.', '!', '?', '\n'])
+                .map_or(text, |end| &text[..=end])
+                .trim();
+            lines.push(format!("{}: {}", msg.role_str(), sentence));
+        }
+
+        let mut total: usize = lines
+            .iter()
+            .map(|line| TokenCounter::count_tokens(line))
+            .sum();
+        let mut start = 0;
+        while total > self.max_tokens && start < lines.len() {
+            total -= TokenCounter::count_tokens(&lines[start]);
+            start += 1;
+        }
+        lines[start..].join("\n")
+    }
+}
+
+/// Compactor that keeps recent messages verbatim and summarizes the rest
+///
+/// The newest messages that fit in `window_tokens` are kept as they are.
+/// Older messages are folded into a rolling summary, held in a system
+/// message at the front of the history (see [`SUMMARY_KEY`](SlidingWindowCompactor::SUMMARY_KEY)).
+/// Each compaction only passes the newly evicted messages to the
+/// [`Summarizer`], together with the previous summary, so the summary is
+/// updated incrementally rather than rebuilt from the whole conversation.
+///
+/// Pinned messages (see [`Message::pinned`]) are never evicted.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::compactor::SlidingWindowCompactor;
+/// use language_barrier_core::{Chat, Message};
+///
+/// let chat = Chat::default()
+///     .add_message(Message::user("My name is Ada. I work on compilers."))
+///     .add_message(Message::assistant("Nice to meet you, Ada!"))
+///     .add_message(Message::user("What should I read next?"));
+///
+/// let compacted = chat.compact_with(&SlidingWindowCompactor::new(6), 12);
+/// assert_eq!(compacted.history.len(), 2);
+/// assert!(compacted.history[0].text_content().contains("user: My name is Ada."));
+/// assert_eq!(compacted.history[1].text_content(), "What should I read next?");
+/// ```
+#[derive(Debug, Clone)]
+pub struct SlidingWindowCompactor<S = ExtractiveSummarizer> {
+    window_tokens: usize,
+    overlap: usize,
+    summarizer: S,
+}
+
+impl SlidingWindowCompactor {
+    /// The metadata key marking the summary message
+    pub const SUMMARY_KEY: &'static str = "summary";
+
+    /// The text the summary message starts with
+    pub const SUMMARY_HEADER: &'static str = "Summary of the earlier conversation:";
+
+    /// Keeps the last `window_tokens` verbatim, summarizing extractively
+    pub fn new(window_tokens: usize) -> Self {
+        Self {
+            window_tokens,
+            overlap: 0,
+            summarizer: ExtractiveSummarizer::default(),
+        }
+    }
+
+    /// Returns the rolling summary in `history`, if there is one
+    pub fn summary(history: &[Message]) -> Option<&str> {
+        history.first()?.metadata().get(Self::SUMMARY_KEY)?.as_str()
+    }
+}
+
+impl<S: Summarizer> SlidingWindowCompactor<S> {
+    /// Shows the summarizer the `n` oldest messages still in the window
+    #[must_use]
+    pub fn with_overlap(self, n: usize) -> Self {
+        Self { overlap: n, ..self }
+    }
+
+    /// Replaces the summarizer
+    #[must_use]
+    pub fn with_summarizer<T: Summarizer>(self, summarizer: T) -> SlidingWindowCompactor<T> {
+        SlidingWindowCompactor {
+            window_tokens: self.window_tokens,
+            overlap: self.overlap,
+            summarizer,
+        }
+    }
+
+    /// Builds the system message holding `summary`
+    fn header(&self, summary: String) -> Message {
+        Message::system(format!(
+            "{}\n{summary}",
+            SlidingWindowCompactor::SUMMARY_HEADER
+        ))
+        .with_metadata(
+            SlidingWindowCompactor::SUMMARY_KEY,
+            serde_json::Value::String(summary),
+        )
+    }
+}
+
+impl<S: Summarizer> ChatHistoryCompactor for SlidingWindowCompactor<S> {
+    fn compact(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        if history.is_empty() || counter.under_budget(max_tokens) {
+            return;
+        }
+
+        let previous = SlidingWindowCompactor::summary(history).map(str::to_string);
+        if previous.is_some() {
+            let header = history.remove(0);
+            counter.subtract(&header.text_content());
+        }
+
+        // The newest messages that fit the window, always at least one
+        let window = self.window_tokens.min(max_tokens);
+        let mut used = 0;
+        let mut window_start = history.len();
+        while window_start > 0 {
+            let tokens = TokenCounter::count_message(&mut history[window_start - 1]);
+            if window_start < history.len() && used + tokens > window {
+                break;
+            }
+            used += tokens;
+            window_start -= 1;
+        }
+
+        if !history[..window_start].iter().any(|msg| !msg.is_pinned()) {
+            // Nothing new to summarize; keep the current summary
+            if let Some(summary) = previous {
+                let header = self.header(summary);
+                counter.observe(&header.text_content());
+                history.insert(0, header);
+            }
+            return;
+        }
+
+        let (evicted, kept): (Vec<Message>, Vec<Message>) = history
+            .drain(..window_start)
+            .partition(|msg| !msg.is_pinned());
+        for msg in &evicted {
+            counter.subtract_message(msg);
+        }
+
+        let overlap = &history[..self.overlap.min(history.len())];
+        let summary =
+            self.summarizer
+                .update(previous.as_deref().unwrap_or_default(), &evicted, overlap);
+        debug!(
+            "Summarized {} messages, keeping {} verbatim",
+            evicted.len(),
+            history.len() + kept.len()
+        );
+
+        history.splice(0..0, kept);
+        if !summary.is_empty() {
+            let header = self.header(summary);
+            counter.observe(&header.text_content());
+            history.insert(0, header);
+        }
+    }
+}
+
+/// Compactor that drops ephemeral messages, oldest first
+///
+/// Messages marked with [`Message::ephemeral`] are removed until the history
+/// fits; pinned messages are kept even if ephemeral. Nothing else is
+/// touched, so the history may stay over budget.
+#[derive(Debug, Default, Clone)]
+pub struct DropEphemeralCompactor;
+
+impl ChatHistoryCompactor for DropEphemeralCompactor {
+    fn compact(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        let mut index = 0;
+        while index < history.len() && !counter.under_budget(max_tokens) {
+            if history[index].is_ephemeral() && !history[index].is_pinned() {
+                let removed = history.remove(index);
+                counter.subtract_message(&removed);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Compactor that shortens old tool outputs, oldest first
+///
+/// Tool results are often large and only matter until the model has used
+/// them. All but the `keep_recent` most recent tool messages are cut to
+/// their first `max_chars` characters plus a note of how much was removed.
+#[derive(Debug, Clone)]
+pub struct ToolOutputCompactor {
+    keep_recent: usize,
+    max_chars: usize,
+}
+
+impl Default for ToolOutputCompactor {
+    fn default() -> Self {
+        Self {
+            keep_recent: 2,
+            max_chars: 200,
+        }
+    }
+}
+
+impl ToolOutputCompactor {
+    /// Keeps the last two tool outputs and cuts older ones to 200 characters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leaves the `n` most recent tool outputs intact
+    #[must_use]
+    pub fn with_keep_recent(self, n: usize) -> Self {
+        Self {
+            keep_recent: n,
+            ..self
+        }
+    }
+
+    /// Sets how many characters of an old tool output are kept
+    #[must_use]
+    pub fn with_max_chars(self, max_chars: usize) -> Self {
+        Self { max_chars, ..self }
+    }
+}
+
+impl ChatHistoryCompactor for ToolOutputCompactor {
+    fn compact(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        let tool_messages: Vec<usize> = history
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| matches!(msg, Message::Tool { .. }) && !msg.is_pinned())
+            .map(|(index, _)| index)
+            .collect();
+        let old = tool_messages.len().saturating_sub(self.keep_recent);
+
+        for &index in &tool_messages[..old] {
+            if counter.under_budget(max_tokens) {
+                break;
+            }
+            let Message::Tool { content, .. } = &mut history[index] else {
+                continue;
+            };
+            let Some((cut, _)) = content.char_indices().nth(self.max_chars) else {
+                continue;
+            };
+
+            let removed = content.len() - cut;
+            let shortened = format!(
+                "{}… [{removed} bytes of tool output removed]",
+                &content[..cut]
+            );
+            counter.subtract(content);
+            counter.observe(&shortened);
+            *content = shortened;
+        }
+    }
+}
+
+/// Compactor that collapses old tool interactions into one-line notes
+///
+/// Once the model has used a tool's result, the call and result rarely
+/// matter verbatim. For each resolved interaction (an assistant message
+/// whose every tool call has a result), oldest first, the tool calls and
+/// results are replaced by notes on the assistant message such as
+/// `called get_weather(Paris) → 10C`. User and assistant text is left
+/// intact, as are the `keep_recent` most recent interactions, unresolved
+/// ones and pinned messages.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::compactor::ToolCallCollapser;
+/// use language_barrier_core::message::{Function, ToolCall};
+/// use language_barrier_core::{Chat, Message};
+///
+/// let call = ToolCall {
+///     id: "call_1".into(),
+///     tool_type: "function".into(),
+///     function: Function {
+///         name: "get_weather".into(),
+///         arguments: r#"{"city":"Paris"}"#.into(),
+///     },
+/// };
+/// let chat = Chat::default()
+///     .add_message(Message::user("Weather in Paris?"))
+///     .add_message(Message::assistant_with_tool_calls(vec![call]))
+///     .add_message(Message::tool("call_1", r#"{"temp":"10C","sky":"clear"}"#))
+///     .add_message(Message::assistant("It's 10C and clear."))
+///     .add_message(Message::user("Thanks!"));
+///
+/// let compacted = chat.compact_with(&ToolCallCollapser::new().with_keep_recent(0), 5);
+/// assert_eq!(compacted.history.len(), 4);
+/// assert_eq!(
+///     compacted.history[1].text_content(),
+///     r#"called get_weather(Paris) → {"temp":"10C","sky":"clear"}"#
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct ToolCallCollapser {
+    keep_recent: usize,
+    max_chars: usize,
+}
+
+impl Default for ToolCallCollapser {
+    fn default() -> Self {
+        Self {
+            keep_recent: 1,
+            max_chars: 80,
+        }
+    }
+}
+
+impl ToolCallCollapser {
+    /// Keeps the latest interaction and cuts notes to 80 characters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leaves the `n` most recent tool interactions intact
+    #[must_use]
+    pub fn with_keep_recent(self, n: usize) -> Self {
+        Self {
+            keep_recent: n,
+            ..self
+        }
+    }
+
+    /// Sets how many characters of arguments and results a note keeps
+    #[must_use]
+    pub fn with_max_chars(self, max_chars: usize) -> Self {
+        Self { max_chars, ..self }
+    }
+
+    /// Describes one call and its result
+    fn note(&self, call: &ToolCall, result: &str) -> String {
+        let arguments = match serde_json::from_str(&call.function.arguments) {
+            Ok(serde_json::Value::Object(fields)) => fields
+                .values()
+                .map(|value| match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => call.function.arguments.clone(),
+        };
+        let result = result.lines().next().unwrap_or_default().trim();
+        format!(
+            "called {}({}) → {}",
+            call.function.name,
+            truncate(&arguments, self.max_chars),
+            truncate(result, self.max_chars)
+        )
+    }
+}
+
+/// Cuts `text` to `max_chars` characters, marking the cut
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text.to_string(),
+    }
+}
+
+impl ChatHistoryCompactor for ToolCallCollapser {
+    fn compact(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        let interactions: Vec<usize> = history
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| {
+                matches!(msg, Message::Assistant { tool_calls, .. } if !tool_calls.is_empty())
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let old = interactions.len().saturating_sub(self.keep_recent);
+
+        let mut collapsed = Vec::new();
+        for &index in &interactions[..old] {
+            let Message::Assistant { tool_calls, .. } = &history[index] else {
+                continue;
+            };
+            if history[index].is_pinned() {
+                continue;
+            }
+            let results: Vec<Option<usize>> = tool_calls
+                .iter()
+                .map(|call| {
+                    history.iter().position(|msg| {
+                        matches!(msg, Message::Tool { tool_call_id, .. } if *tool_call_id == call.id)
+                    })
+                })
+                .collect();
+            let Some(results) = results.into_iter().collect::<Option<Vec<usize>>>() else {
+                continue;
+            };
+            if results.iter().any(|&result| history[result].is_pinned()) {
+                continue;
+            }
+            collapsed.push((index, results));
+        }
+
+        // Collapse oldest first until the history fits, removing the tool
+        // results afterwards so indices stay valid
+        let mut removed = Vec::new();
+        for (index, results) in collapsed {
+            if counter.under_budget(max_tokens) {
+                break;
+            }
+            let Message::Assistant { tool_calls, .. } = &history[index] else {
+                continue;
+            };
+
+            let mut lines = Vec::new();
+            let text = history[index].text_content();
+            if !text.is_empty() {
+                lines.push(text);
+            }
+            for (call, &result) in tool_calls.iter().zip(&results) {
+                let output = history[result].text_content();
+                counter.subtract_message(&history[result]);
+                lines.push(self.note(call, &output));
+            }
+            let text = lines.join("\n");
+
+            counter.subtract_message(&history[index]);
+            counter.observe(&text);
+            if let Message::Assistant {
+                content,
+                tool_calls,
+                ..
+            } = &mut history[index]
+            {
+                *content = Some(Content::Text(text));
+                tool_calls.clear();
+            }
+            removed.extend(results);
+        }
+
+        removed.sort_unstable();
+        for index in removed.into_iter().rev() {
+            history.remove(index);
+        }
+    }
+}
+
+/// A compaction step inside a [`CompositeCompactor`]
+///
+/// `ChatHistoryCompactor` requires `Clone`, so it can't be boxed; this is
+/// its object-safe counterpart.
+trait CompactionStage: Send + Sync {
+    fn run(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize);
+}
+
+impl<C: ChatHistoryCompactor> CompactionStage for C {
+    fn run(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        ChatHistoryCompactor::compact(self, history, counter, max_tokens);
+    }
+}
+
+/// Compactor that applies several strategies in order
+///
+/// Every stage works toward the same token budget, and later stages only
+/// run while the history is still over it. This allows layered policies
+/// that try the least damaging strategies first.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::compactor::CompositeCompactor;
+/// use language_barrier_core::{Chat, Message};
+///
+/// let chat = Chat::default()
+///     .add_message(Message::user("Search results: a b c d e f").ephemeral())
+///     .add_message(Message::user("Summarize the latest results"));
+///
+/// // Dropping the ephemeral message is enough; nothing else is removed
+/// let compacted = chat.compact_with(&CompositeCompactor::layered(), 6);
+/// assert_eq!(compacted.history.len(), 1);
+/// assert_eq!(compacted.history[0].text_content(), "Summarize the latest results");
+/// ```
+#[derive(Clone, Default)]
+pub struct CompositeCompactor {
+    stages: Vec<Arc<dyn CompactionStage>>,
+}
+
+impl fmt::Debug for CompositeCompactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompositeCompactor")
+            .field("stages", &self.stages.len())
+            .finish()
+    }
+}
+
+impl CompositeCompactor {
+    /// Creates a compactor with no stages
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops ephemeral messages, then shortens old tool outputs, then drops
+    /// the oldest messages
+    pub fn layered() -> Self {
+        Self::new()
+            .then(DropEphemeralCompactor)
+            .then(ToolOutputCompactor::default())
+            .then(DropOldestCompactor::default())
+    }
+
+    /// Adds a stage that runs after the existing ones
+    #[must_use]
+    pub fn then(self, compactor: impl ChatHistoryCompactor + 'static) -> Self {
+        let mut stages = self.stages;
+        stages.push(Arc::new(compactor));
+        Self { stages }
+    }
+}
+
+impl ChatHistoryCompactor for CompositeCompactor {
+    fn compact(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        for (index, stage) in self.stages.iter().enumerate() {
+            if counter.under_budget(max_tokens) {
+                break;
+            }
+            debug!("Running compaction stage {}", index);
+            stage.run(history, counter, max_tokens);
+        }
+    }
+}
+
+/// What one compaction did to a history
+///
+/// Messages are compared by value, so a message a compactor rewrote, for
+/// example a shortened tool output, shows up once in `removed` (as it was)
+/// and once in `added` (as it is now). Token counts a compactor cached on
+/// a message don't count as a change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionEvent {
+    /// The compactor's type name
+    pub compactor: String,
+    /// Messages in the history before compaction
+    pub messages_before: usize,
+    /// Messages in the history after compaction
+    pub messages_after: usize,
+    /// The token count before compaction
+    pub tokens_before: usize,
+    /// The token count after compaction
+    pub tokens_after: usize,
+    /// Messages that are no longer in the history as they were
+    pub removed: Vec<Message>,
+    /// Messages that weren't in the history before, such as summaries
+    pub added: Vec<Message>,
+}
+
+impl CompactionEvent {
+    /// Compares a history before and after compaction
+    pub fn between(
+        compactor: impl Into<String>,
+        before: &[Message],
+        after: &[Message],
+        tokens_before: usize,
+        tokens_after: usize,
+    ) -> Self {
+        let mut remaining: Vec<&Message> = after.iter().collect();
+        let mut removed = Vec::new();
+        for msg in before {
+            match remaining.iter().position(|kept| kept.same_as(msg)) {
+                Some(index) => {
+                    remaining.remove(index);
                 }
+                None => removed.push(msg.clone()),
             }
         }
+
+        Self {
+            compactor: compactor.into(),
+            messages_before: before.len(),
+            messages_after: after.len(),
+            tokens_before,
+            tokens_after,
+            removed,
+            added: remaining.into_iter().cloned().collect(),
+        }
+    }
+
+    /// Returns true if the compaction changed nothing
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+
+    /// Returns how many tokens the compaction freed
+    pub fn tokens_reclaimed(&self) -> usize {
+        self.tokens_before.saturating_sub(self.tokens_after)
+    }
+
+    /// Returns the rolling summary the compaction wrote, if any
+    pub fn summary(&self) -> Option<&str> {
+        self.added.iter().find_map(|msg| {
+            msg.metadata()
+                .get(SlidingWindowCompactor::SUMMARY_KEY)?
+                .as_str()
+        })
+    }
+}
+
+type CompactionListener = Arc<dyn Fn(&CompactionEvent) + Send + Sync>;
+
+/// Compactor that reports what another compactor did
+///
+/// Every compaction that changes the history is logged at `info` level
+/// with its counts and passed to the listener set with
+/// [`on_event`](Self::on_event), which can record the removed messages
+/// for later inspection.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::compactor::{ObservedCompactor, CompactionEvent};
+/// use language_barrier_core::{Chat, DropOldestCompactor, Message};
+/// use std::sync::{Arc, Mutex};
+///
+/// let events = Arc::new(Mutex::new(Vec::<CompactionEvent>::new()));
+/// let sink = events.clone();
+/// let compactor = ObservedCompactor::new(DropOldestCompactor::default())
+///     .on_event(move |event| sink.lock().unwrap().push(event.clone()));
+///
+/// let chat = Chat::default()
+///     .add_message(Message::user("My name is Ada."))
+///     .add_message(Message::user("What is my name?"))
+///     .compact_with(&compactor, 5);
+///
+/// let events = events.lock().unwrap();
+/// assert_eq!(events[0].removed, vec![Message::user("My name is Ada.")]);
+/// assert_eq!(events[0].tokens_reclaimed(), 4);
+/// ```
+#[derive(Clone)]
+pub struct ObservedCompactor<C> {
+    inner: C,
+    listener: Option<CompactionListener>,
+}
+
+impl<C: fmt::Debug> fmt::Debug for ObservedCompactor<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObservedCompactor")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: ChatHistoryCompactor> ObservedCompactor<C> {
+    /// Wraps `inner`, logging its compactions
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            listener: None,
+        }
+    }
+
+    /// Also passes every compaction event to `listener`
+    #[must_use]
+    pub fn on_event(self, listener: impl Fn(&CompactionEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            listener: Some(Arc::new(listener)),
+            ..self
+        }
+    }
+}
+
+impl<C: ChatHistoryCompactor> ChatHistoryCompactor for ObservedCompactor<C> {
+    fn compact(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        if history.is_empty() || counter.under_budget(max_tokens) {
+            return;
+        }
+
+        let before = history.clone();
+        let tokens_before = counter.total();
+        self.inner.compact(history, counter, max_tokens);
+
+        let event = CompactionEvent::between(
+            std::any::type_name::<C>(),
+            &before,
+            history,
+            tokens_before,
+            counter.total(),
+        );
+        if event.is_empty() {
+            return;
+        }
+
+        info!(
+            compactor = %event.compactor,
+            removed = event.removed.len(),
+            added = event.added.len(),
+            messages_after = event.messages_after,
+            tokens_reclaimed = event.tokens_reclaimed(),
+            tokens_after = event.tokens_after,
+            summary = event.summary().is_some(),
+            "Compacted chat history"
+        );
+        if let Some(listener) = &self.listener {
+            listener(&event);
+        }
     }
 }
 
@@ -82,8 +805,6 @@ impl ChatHistoryCompactor for DropOldestCompactor {
 mod tests {
     use super::*;
 
-    use crate::message::Message;
-
     #[test]
     fn test_drop_oldest_compactor() {
         let compactor = DropOldestCompactor::default();
@@ -108,4 +829,168 @@ mod tests {
         assert!(history.len() < 3);
         assert!(counter.total() <= 5);
     }
+
+    #[test]
+    fn test_sliding_window_updates_summary_incrementally() {
+        let compactor = SlidingWindowCompactor::new(4).with_overlap(1);
+        let mut history = Vec::new();
+        let mut counter = TokenCounter::default();
+        let add = |history: &mut Vec<Message>, counter: &mut TokenCounter, msg: Message| {
+            counter.observe(&msg.text_content());
+            history.push(msg);
+        };
+
+        add(
+            &mut history,
+            &mut counter,
+            Message::user("Deploy to eu-west-1. Thanks."),
+        );
+        add(&mut history, &mut counter, Message::assistant("Done."));
+        add(&mut history, &mut counter, Message::user("Check the logs"));
+        compactor.compact(&mut history, &mut counter, 7);
+
+        let summary = SlidingWindowCompactor::summary(&history);
+        assert_eq!(summary, Some("user: Deploy to eu-west-1."));
+        assert_eq!(history.len(), 3);
+
+        add(
+            &mut history,
+            &mut counter,
+            Message::user("Pinned: budget is $5").pinned(),
+        );
+        add(
+            &mut history,
+            &mut counter,
+            Message::assistant("Logs look clean today"),
+        );
+        compactor.compact(&mut history, &mut counter, 7);
+
+        let summary = SlidingWindowCompactor::summary(&history).unwrap();
+        assert_eq!(
+            summary,
+            "user: Deploy to eu-west-1.\nassistant: Done.\nuser: Check the logs"
+        );
+        assert!(history[1].is_pinned());
+        assert_eq!(
+            history.last().unwrap().text_content(),
+            "Logs look clean today"
+        );
+        let total: usize = history
+            .iter()
+            .map(|msg| TokenCounter::count_tokens(&msg.text_content()))
+            .sum();
+        assert_eq!(counter.total(), total);
+    }
+
+    #[test]
+    fn test_composite_runs_stages_until_under_budget() {
+        let mut history = vec![
+            Message::tool("call_1", "row ".repeat(50)),
+            Message::user("cached search results").ephemeral(),
+            Message::assistant("Here is the answer"),
+            Message::tool("call_2", "latest rows"),
+        ];
+        let mut counter = TokenCounter::default();
+        for msg in &history {
+            counter.observe(&msg.text_content());
+        }
+
+        let compactor = CompositeCompactor::new()
+            .then(DropEphemeralCompactor)
+            .then(
+                ToolOutputCompactor::new()
+                    .with_keep_recent(1)
+                    .with_max_chars(8),
+            )
+            .then(DropOldestCompactor::default());
+        compactor.compact(&mut history, &mut counter, 20);
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history[0].text_content(),
+            "row row … [192 bytes of tool output removed]"
+        );
+        assert_eq!(history[2].text_content(), "latest rows");
+        assert_eq!(counter.total(), 15);
+    }
+
+    #[test]
+    fn test_events_report_rewrites_and_summaries() {
+        let before = vec![
+            Message::user("Deploy to eu-west-1."),
+            Message::tool("call_1", "a b c d e f"),
+            Message::user("Check the logs"),
+        ];
+        let mut history = before.clone();
+        let mut counter = TokenCounter::default();
+        for msg in &history {
+            counter.observe(&msg.text_content());
+        }
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let compactor = ObservedCompactor::new(SlidingWindowCompactor::new(3))
+            .on_event(move |event| sink.lock().unwrap().push(event.clone()));
+        compactor.compact(&mut history, &mut counter, 10);
+
+        let events = events.lock().unwrap();
+        let event = &events[0];
+        assert!(event.compactor.ends_with("SlidingWindowCompactor"));
+        assert_eq!(event.removed, before[..2]);
+        assert_eq!(event.added.len(), 1);
+        assert_eq!(
+            event.summary(),
+            Some("user: Deploy to eu-west-1.\ntool: a b c d e f")
+        );
+        assert_eq!((event.messages_before, event.messages_after), (3, 2));
+        assert_eq!(event.tokens_after, counter.total());
+
+        // Under budget, nothing happens and nothing is reported
+        compactor.compact(&mut history, &mut counter, 100);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_collapser_keeps_recent_and_unresolved_calls() {
+        use crate::message::Function;
+
+        let call = |id: &str, city: &str| ToolCall {
+            id: id.to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "get_weather".to_string(),
+                arguments: format!(r#"{{"city":"{city}"}}"#),
+            },
+        };
+        let mut history = vec![
+            Message::assistant_with_tool_calls(vec![call("a", "Paris"), call("b", "Oslo")]),
+            Message::tool("a", "10C\nsunny all day"),
+            Message::tool("b", "-3C"),
+            Message::assistant_with_tool_calls(vec![call("c", "Rome")]),
+            Message::user("never mind"),
+            Message::assistant_with_tool_calls(vec![call("d", "Lima")]),
+            Message::tool("d", "18C"),
+        ];
+        let mut counter = TokenCounter::default();
+        for msg in &history {
+            counter.observe(&msg.text_content());
+        }
+
+        ToolCallCollapser::new().compact(&mut history, &mut counter, 0);
+
+        assert_eq!(history.len(), 5);
+        assert_eq!(
+            history[0].text_content(),
+            "called get_weather(Paris) → 10C\ncalled get_weather(Oslo) → -3C"
+        );
+        assert!(
+            matches!(&history[1], Message::Assistant { tool_calls, .. } if tool_calls.len() == 1)
+        );
+        assert!(matches!(&history[4], Message::Tool { tool_call_id, .. } if tool_call_id == "d"));
+        let total: usize = history
+            .iter()
+            .map(|msg| TokenCounter::count_tokens(&msg.text_content()))
+            .sum();
+        assert_eq!(counter.total(), total);
+    }
 }
@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet};
+
+use tracing::debug;
+
+use crate::chat::Chat;
+use crate::compactor::ChatHistoryCompactor;
+use crate::message::{Content, Message};
+use crate::token::TokenCounter;
+
+/// Replaces a message whose every sentence was dropped
+const OMITTED: &str = "[omitted]";
+
+/// Shrinks long conversation histories by dropping their least informative sentences
+///
+/// Unlike [`DropOldestCompactor`](crate::compactor::DropOldestCompactor),
+/// which removes whole messages, this keeps every turn and trims *inside*
+/// messages, in the spirit of LLMLingua-style prompt compression:
+///
+/// 1. The text of user, assistant and tool messages is split into sentences.
+/// 2. Repeated sentences score zero. Every other sentence scores the average
+///    rarity (inverse document frequency) of its words across the history,
+///    weighted so older sentences score lower.
+/// 3. The lowest-scoring sentences are dropped until the history fits the
+///    target token budget.
+///
+/// Pinned messages (see [`Message::pinned`]), the most recent messages
+/// (see [`with_keep_recent`](Self::with_keep_recent)), the system prompt and
+/// non-text content are never touched, so the result may stay over budget
+/// if they alone exceed it.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::compression::PromptCompressor;
+/// use language_barrier_core::{Chat, Message, TokenCounter};
+///
+/// let chat = Chat::default()
+///     .add_message(Message::user("Our deploy target is eu-west-1.").pinned())
+///     .add_message(Message::assistant(
+///         "Sure. Sure. I can help with that. The latest build failed on the lint step.",
+///     ))
+///     .add_message(Message::user("Why did it fail?"));
+///
+/// let compressed = chat.compress_prompt(&PromptCompressor::new(), 20);
+///
+/// assert!(compressed.tokens_used() <= 20);
+/// assert_eq!(compressed.history[0], Message::user("Our deploy target is eu-west-1.").pinned());
+/// assert!(compressed.history[1].text_content().contains("lint step"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PromptCompressor {
+    keep_recent: usize,
+}
+
+impl Default for PromptCompressor {
+    fn default() -> Self {
+        Self { keep_recent: 1 }
+    }
+}
+
+impl PromptCompressor {
+    /// Creates a compressor that protects only the most recent message
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Protects the `n` most recent messages from compression
+    #[must_use]
+    pub fn with_keep_recent(self, n: usize) -> Self {
+        Self { keep_recent: n }
+    }
+
+    /// Returns `history` compressed toward `target_tokens`
+    pub fn compress(&self, history: &[Message], target_tokens: usize) -> Vec<Message> {
+        self.compress_with_removed(history, target_tokens).0
+    }
+
+    /// Compresses the history, also returning the text that was removed
+    fn compress_with_removed(
+        &self,
+        history: &[Message],
+        target_tokens: usize,
+    ) -> (Vec<Message>, Vec<String>) {
+        let mut total: usize = history.iter().map(counted_tokens).sum();
+        if total <= target_tokens {
+            return (history.to_vec(), Vec::new());
+        }
+
+        let protected_from = history.len().saturating_sub(self.keep_recent);
+        let sentences: Vec<Sentence> = history
+            .iter()
+            .enumerate()
+            .filter(|(index, msg)| *index < protected_from && !msg.is_pinned())
+            .filter_map(|(index, msg)| compressible_text(msg).map(|text| (index, text)))
+            .flat_map(|(index, text)| {
+                split_sentences(text).into_iter().map(move |text| Sentence {
+                    message: index,
+                    text,
+                })
+            })
+            .collect();
+
+        let scores = score(&sentences, history.len());
+        let mut order: Vec<usize> = (0..sentences.len()).collect();
+        order.sort_by(|&a, &b| scores[a].total_cmp(&scores[b]).then(a.cmp(&b)));
+
+        let mut dropped = HashSet::new();
+        for index in order {
+            if total <= target_tokens {
+                break;
+            }
+            total = total.saturating_sub(TokenCounter::count_tokens(sentences[index].text));
+            dropped.insert(index);
+        }
+        debug!(
+            "Dropped {} of {} sentences to fit {} tokens",
+            dropped.len(),
+            sentences.len(),
+            target_tokens
+        );
+
+        let mut kept: HashMap<usize, Vec<&str>> = HashMap::new();
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        for (index, sentence) in sentences.iter().enumerate() {
+            if dropped.contains(&index) {
+                touched.insert(sentence.message);
+                removed.push(sentence.text.to_string());
+            } else {
+                kept.entry(sentence.message)
+                    .or_default()
+                    .push(sentence.text);
+            }
+        }
+
+        let compressed = history
+            .iter()
+            .enumerate()
+            .map(|(index, msg)| {
+                if !touched.contains(&index) {
+                    return msg.clone();
+                }
+                let text = kept.get(&index).map(|kept| kept.join(" "));
+                with_text(msg, text.unwrap_or_else(|| OMITTED.to_string()))
+            })
+            .collect();
+
+        (compressed, removed)
+    }
+}
+
+impl ChatHistoryCompactor for PromptCompressor {
+    fn compact(&self, history: &mut Vec<Message>, counter: &mut TokenCounter, max_tokens: usize) {
+        if history.is_empty() || counter.under_budget(max_tokens) {
+            return;
+        }
+
+        let (compressed, removed) = self.compress_with_removed(history, max_tokens);
+        for text in &removed {
+            counter.subtract(text);
+        }
+        let placeholders = compressed
+            .iter()
+            .zip(history.iter())
+            .filter(|(new, old)| new != old && new.text_content() == OMITTED)
+            .count();
+        for _ in 0..placeholders {
+            counter.observe(OMITTED);
+        }
+
+        *history = compressed;
+    }
+}
+
+impl Chat {
+    /// Returns a new instance whose history is compressed toward `target_tokens`
+    ///
+    /// See [`PromptCompressor`] for what is kept.
+    #[must_use]
+    pub fn compress_prompt(self, compressor: &PromptCompressor, target_tokens: usize) -> Self {
+        let history = compressor.compress(&self.history, target_tokens);
+        self.with_history(history)
+    }
+}
+
+struct Sentence<'a> {
+    message: usize,
+    text: &'a str,
+}
+
+/// Scores sentences by word rarity and recency; repeats score zero
+fn score(sentences: &[Sentence<'_>], message_count: usize) -> Vec<f64> {
+    let words: Vec<HashSet<String>> = sentences
+        .iter()
+        .map(|sentence| {
+            sentence
+                .text
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect()
+        })
+        .collect();
+
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for word in words.iter().flatten() {
+        *document_frequency.entry(word.as_str()).or_default() += 1;
+    }
+
+    let n = sentences.len() as f64;
+    let mut seen = HashSet::new();
+    sentences
+        .iter()
+        .zip(&words)
+        .map(|(sentence, words)| {
+            if words.is_empty() || !seen.insert(sentence.text.trim().to_lowercase()) {
+                return 0.0;
+            }
+            let rarity = words
+                .iter()
+                .map(|word| (n / document_frequency[word.as_str()] as f64).ln() + 1.0)
+                .sum::<f64>()
+                / words.len() as f64;
+            let recency = (sentence.message + 1) as f64 / message_count as f64;
+            rarity * (0.5 + 0.5 * recency)
+        })
+        .collect()
+}
+
+/// Splits text after sentence-ending punctuation or line breaks
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let at_boundary = match c {
+            '\n' => true,
+            '.' | '!' | '?' => chars.peek().is_none_or(|(_, next)| next.is_whitespace()),
+            _ => false,
+        };
+        if at_boundary {
+            let end = index + c.len_utf8();
+            let sentence = text[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+/// Returns the plain text of messages the compressor may rewrite
+fn compressible_text(msg: &Message) -> Option<&str> {
+    match msg {
+        Message::User {
+            content: Content::Text(text),
+            ..
+        }
+        | Message::Assistant {
+            content: Some(Content::Text(text)),
+            ..
+        }
+        | Message::Tool { content: text, .. } => Some(text),
+        _ => None,
+    }
+}
+
+/// Counts tokens the same way `Chat` does
+fn counted_tokens(msg: &Message) -> usize {
+    match msg {
+        Message::System { .. } | Message::Tool { .. } => {
+            TokenCounter::count_tokens(&msg.text_content())
+        }
+        _ => compressible_text(msg).map_or(0, TokenCounter::count_tokens),
+    }
+}
+
+/// Returns a copy of a compressible message with its text replaced
+fn with_text(msg: &Message, text: String) -> Message {
+    match msg.clone() {
+        Message::User { name, metadata, .. } => Message::User {
+            content: Content::Text(text),
+            name,
+            metadata,
+        },
+        Message::Assistant {
+            tool_calls,
+            metadata,
+            ..
+        } => Message::Assistant {
+            content: Some(Content::Text(text)),
+            tool_calls,
+            metadata,
+        },
+        Message::Tool {
+            tool_call_id,
+            metadata,
+            ..
+        } => Message::Tool {
+            tool_call_id,
+            content: text,
+            metadata,
+        },
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_sentences() {
+        assert_eq!(
+            split_sentences("Version 1.5 shipped. Did it work? Yes!\nNext line"),
+            vec!["Version 1.5 shipped.", "Did it work?", "Yes!", "Next line"]
+        );
+    }
+
+    #[test]
+    fn test_under_budget_history_is_unchanged() {
+        let history = vec![Message::user("Hello there."), Message::assistant("Hi!")];
+        assert_eq!(PromptCompressor::new().compress(&history, 100), history);
+    }
+
+    #[test]
+    fn test_repeats_and_old_sentences_go_first() {
+        let history = vec![
+            Message::user("Please review the payment service. It is slow."),
+            Message::assistant(
+                "Okay. Okay. The payment service queries the ledger twice per request.",
+            ),
+            Message::user("How do we fix it?"),
+        ];
+
+        let compressed = PromptCompressor::new().compress(&history, 16);
+        let total: usize = compressed.iter().map(counted_tokens).sum();
+
+        assert!(total <= 16, "compressed to {total} tokens");
+        assert_eq!(compressed[2], history[2]);
+        assert!(compressed[1].text_content().contains("ledger twice"));
+        assert_eq!(compressed[1].text_content().matches("Okay.").count(), 0);
+    }
+
+    #[test]
+    fn test_pinned_and_structural_content_is_preserved() {
+        let history = vec![
+            Message::user("The account id is 12345. Remember it.").pinned(),
+            Message::tool("call_1", "Lots of noisy tool output here."),
+            Message::user("What was the id?"),
+        ];
+
+        let compressed = PromptCompressor::new().compress(&history, 1);
+
+        assert_eq!(compressed[0], history[0]);
+        assert_eq!(compressed[1], Message::tool("call_1", OMITTED));
+        assert_eq!(compressed[2], history[2]);
+    }
+
+    #[test]
+    fn test_compactor_keeps_counter_in_sync() {
+        let chat = Chat::default()
+            .add_message(Message::user("One two three. Four five six."))
+            .add_message(Message::assistant("Seven eight nine."));
+        let mut history = chat.history.clone();
+        let mut counter = TokenCounter::default();
+        for msg in &history {
+            counter.observe(&msg.text_content());
+        }
+
+        PromptCompressor::new().compact(&mut history, &mut counter, 6);
+
+        let recounted: usize = history.iter().map(counted_tokens).sum();
+        assert_eq!(counter.total(), recounted);
+        assert!(counter.under_budget(6));
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{debug, warn};
+
+use crate::batch::BatchExecutor;
+use crate::error::{Error, Result};
+use crate::llm_service::LLMService;
+use crate::{Chat, Message, ModelInfo};
+
+/// Picks the best of several candidate answers to the same chat
+///
+/// Implement this to plug a custom selection rule into
+/// [`Selection::Judge`]; [`LlmJudge`] asks a model to choose.
+#[async_trait]
+pub trait Judge: Send + Sync {
+    /// Returns the index of the chosen candidate
+    async fn pick(&self, chat: &Chat, candidates: &[Message]) -> Result<usize>;
+}
+
+/// A [`Judge`] that asks a language model to choose the best candidate
+///
+/// The judge sees the original conversation's last user message and the
+/// numbered candidates, and is asked to reply with the number of the most
+/// accurate and consistent one.
+pub struct LlmJudge<M> {
+    service: Arc<dyn LLMService<M> + Send + Sync>,
+}
+
+impl<M: ModelInfo> LlmJudge<M> {
+    /// Creates a judge that sends its prompt through `service`
+    pub fn new(service: Arc<dyn LLMService<M> + Send + Sync>) -> Self {
+        Self { service }
+    }
+
+    fn prompt(chat: &Chat, candidates: &[Message]) -> Chat {
+        let question = chat
+            .history
+            .iter()
+            .rev()
+            .find(|msg| matches!(msg, Message::User { .. }))
+            .map(Message::text_content)
+            .unwrap_or_default();
+
+        let mut prompt = format!("Question:\n{question}\n\nCandidate answers:\n");
+        for (i, candidate) in candidates.iter().enumerate() {
+            prompt.push_str(&format!("\n[{}]\n{}\n", i + 1, candidate.text_content()));
+        }
+        prompt.push_str("\nReply with only the number of the best answer.");
+
+        Chat::default()
+            .with_system_prompt(
+                "You compare candidate answers to the same question and pick the one that \
+                 is most accurate and most consistent with the other candidates.",
+            )
+            .with_max_output_tokens(16)
+            .add_message(Message::user(prompt))
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo> Judge for LlmJudge<M> {
+    async fn pick(&self, chat: &Chat, candidates: &[Message]) -> Result<usize> {
+        let verdict = self
+            .service
+            .generate_next_message(&Self::prompt(chat, candidates))
+            .await?
+            .text_content();
+
+        let choice = verdict
+            .split(|c: char| !c.is_ascii_digit())
+            .find(|digits| !digits.is_empty())
+            .and_then(|digits| digits.parse::<usize>().ok());
+
+        match choice {
+            Some(n) if (1..=candidates.len()).contains(&n) => Ok(n - 1),
+            _ => Err(Error::Other(format!(
+                "Judge reply {verdict:?} does not name one of {} candidates",
+                candidates.len()
+            ))),
+        }
+    }
+}
+
+/// How [`SelfConsistency`] chooses among its samples
+#[derive(Clone)]
+pub enum Selection {
+    /// The answer given by the most samples wins; ties go to the earliest
+    MajorityVote,
+    /// A judge picks the answer
+    Judge(Arc<dyn Judge>),
+}
+
+impl fmt::Debug for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::MajorityVote => f.write_str("MajorityVote"),
+            Selection::Judge(_) => f.write_str("Judge(<judge>)"),
+        }
+    }
+}
+
+/// The result of a self-consistency run
+#[derive(Debug, Clone)]
+pub struct ConsistencyOutcome {
+    /// The chosen response
+    pub answer: Message,
+    /// Every successful sample, in request order
+    pub samples: Vec<Message>,
+    /// How many samples gave the same answer as the chosen one
+    pub agreement: usize,
+}
+
+type AnswerKey = Arc<dyn Fn(&Message) -> String + Send + Sync>;
+
+/// Samples several responses to one chat and keeps the most consistent
+///
+/// Sends the same chat `samples` times in parallel at a non-zero temperature,
+/// then picks a response by [`Selection`]. Samples are compared by an answer
+/// key, which defaults to the trimmed message text; supply your own with
+/// [`with_answer_key`](Self::with_answer_key) to vote on, say, just the final
+/// line of a chain-of-thought answer.
+///
+/// Failed samples are dropped; the run only fails if every sample does.
+///
+/// # Examples
+///
+#[cfg_attr(feature = "openai", doc = "```no_run")]
+#[cfg_attr(not(feature = "openai"), doc = "```ignore")]
+/// use language_barrier_core::consistency::SelfConsistency;
+/// use language_barrier_core::llm_service::HTTPLlmService;
+/// use language_barrier_core::provider::openai::OpenAIProvider;
+/// use language_barrier_core::{Chat, Message, OpenAi};
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() -> language_barrier_core::Result<()> {
+///     let service = HTTPLlmService::new(OpenAi::GPT4o, Arc::new(OpenAIProvider::new()));
+///     let chat = Chat::default().add_message(Message::user("What is 17 * 23? Reply with the number."));
+///
+///     let outcome = SelfConsistency::new(5).run(&service, &chat).await?;
+///     println!("{} ({} of 5 agreed)", outcome.answer.text_content(), outcome.agreement);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct SelfConsistency {
+    samples: usize,
+    temperature: f32,
+    concurrency: usize,
+    selection: Selection,
+    answer_key: AnswerKey,
+}
+
+impl fmt::Debug for SelfConsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SelfConsistency")
+            .field("samples", &self.samples)
+            .field("temperature", &self.temperature)
+            .field("concurrency", &self.concurrency)
+            .field("selection", &self.selection)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SelfConsistency {
+    /// The temperature used when the chat doesn't set a non-zero one
+    pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
+    /// Creates a majority-vote sampler drawing `samples` responses
+    ///
+    /// At least one sample is always drawn.
+    pub fn new(samples: usize) -> Self {
+        let samples = samples.max(1);
+        Self {
+            samples,
+            temperature: Self::DEFAULT_TEMPERATURE,
+            concurrency: samples,
+            selection: Selection::MajorityVote,
+            answer_key: Arc::new(|msg: &Message| msg.text_content().trim().to_string()),
+        }
+    }
+
+    /// Sets the temperature used when the chat's own is unset or zero
+    #[must_use]
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        Self {
+            temperature,
+            ..self
+        }
+    }
+
+    /// Limits how many samples are requested at once (default: all)
+    #[must_use]
+    pub fn with_concurrency(self, concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+            ..self
+        }
+    }
+
+    /// Sets how the winning sample is chosen
+    #[must_use]
+    pub fn with_selection(self, selection: Selection) -> Self {
+        Self { selection, ..self }
+    }
+
+    /// Sets the function that reduces a sample to the answer compared across samples
+    #[must_use]
+    pub fn with_answer_key(
+        self,
+        answer_key: impl Fn(&Message) -> String + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            answer_key: Arc::new(answer_key),
+            ..self
+        }
+    }
+
+    /// Samples responses to `chat` through `service` and selects one
+    ///
+    /// # Errors
+    ///
+    /// Returns the first sample's error if every sample failed, or the
+    /// judge's error if it couldn't pick.
+    pub async fn run<M, S>(&self, service: &S, chat: &Chat) -> Result<ConsistencyOutcome>
+    where
+        M: ModelInfo,
+        S: LLMService<M> + Sync,
+    {
+        let sampled_chat = match chat.temperature {
+            Some(temperature) if temperature > 0.0 => chat.clone(),
+            _ => chat.clone().with_temperature(self.temperature),
+        };
+
+        debug!("Drawing {} self-consistency samples", self.samples);
+        let executor = BatchExecutor::new(service).with_concurrency(self.concurrency);
+        let mut first_error = None;
+        let mut samples = Vec::with_capacity(self.samples);
+        for result in executor.execute(vec![sampled_chat; self.samples]).await {
+            match result {
+                Ok(message) => samples.push(message),
+                Err(e) => {
+                    warn!("Self-consistency sample failed: {}", e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        if samples.is_empty() {
+            return Err(first_error
+                .unwrap_or_else(|| Error::Other("No self-consistency samples".to_string())));
+        }
+
+        let keys: Vec<String> = samples.iter().map(|msg| (self.answer_key)(msg)).collect();
+        let chosen = match &self.selection {
+            Selection::MajorityVote => majority(&keys),
+            Selection::Judge(judge) => judge.pick(chat, &samples).await?,
+        };
+        let agreement = keys.iter().filter(|key| **key == keys[chosen]).count();
+        debug!(
+            "Selected sample {} with agreement {}/{}",
+            chosen,
+            agreement,
+            samples.len()
+        );
+
+        Ok(ConsistencyOutcome {
+            answer: samples[chosen].clone(),
+            samples,
+            agreement,
+        })
+    }
+}
+
+/// Returns the index of the first sample with the most common key
+fn majority(keys: &[String]) -> usize {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for key in keys {
+        *counts.entry(key.as_str()).or_default() += 1;
+    }
+
+    let best = counts.values().copied().max().unwrap_or(0);
+    keys.iter()
+        .position(|key| counts[key.as_str()] == best)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Claude;
+    use std::sync::Mutex;
+
+    /// Returns scripted replies in order and records the chats it saw
+    struct Scripted {
+        replies: Mutex<Vec<&'static str>>,
+        seen_temperatures: Mutex<Vec<Option<f32>>>,
+    }
+
+    impl Scripted {
+        fn new(replies: &[&'static str]) -> Self {
+            Self {
+                replies: Mutex::new(replies.iter().rev().copied().collect()),
+                seen_temperatures: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LLMService<Claude> for Scripted {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            self.seen_temperatures
+                .lock()
+                .unwrap()
+                .push(chat.temperature);
+            match self.replies.lock().unwrap().pop() {
+                Some("error") | None => Err(Error::Other("sample failed".to_string())),
+                Some(reply) => Ok(Message::assistant(reply)),
+            }
+        }
+    }
+
+    fn question() -> Chat {
+        Chat::default().add_message(Message::user("What is 6 * 7?"))
+    }
+
+    #[tokio::test]
+    async fn test_majority_vote_ignores_failed_samples() {
+        let service = Scripted::new(&["41", "42 ", "error", "42", "43"]);
+
+        let outcome = SelfConsistency::new(5)
+            .with_concurrency(1)
+            .run(&service, &question())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.answer, Message::assistant("42 "));
+        assert_eq!(outcome.agreement, 2);
+        assert_eq!(outcome.samples.len(), 4);
+        assert!(
+            service
+                .seen_temperatures
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|t| *t == Some(SelfConsistency::DEFAULT_TEMPERATURE))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_samples_failing_is_an_error() {
+        let service = Scripted::new(&["error", "error"]);
+
+        let result = SelfConsistency::new(2).run(&service, &question()).await;
+        assert!(matches!(result, Err(Error::Other(_))));
+    }
+
+    #[tokio::test]
+    async fn test_llm_judge_picks_candidate() {
+        let samples = Scripted::new(&["Paris", "Lyon", "Lyon"]);
+        let judge_service: Arc<dyn LLMService<Claude> + Send + Sync> =
+            Arc::new(Scripted::new(&["The best answer is [1]."]));
+        let judge = Arc::new(LlmJudge::new(judge_service));
+
+        let outcome = SelfConsistency::new(3)
+            .with_concurrency(1)
+            .with_selection(Selection::Judge(judge))
+            .run(&samples, &question())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.answer, Message::assistant("Paris"));
+        assert_eq!(outcome.agreement, 1);
+    }
+}
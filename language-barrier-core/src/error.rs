@@ -1,3 +1,7 @@
+use crate::model::{ModelCapability, Provider};
+use http::HeaderMap;
+use std::fmt;
+use std::time::Duration;
 use thiserror::Error;
 
 /// Errors that can occur when working with tools
@@ -28,6 +32,95 @@ pub enum ToolError {
     OutputTypeMismatch(String),
 }
 
+/// Errors found when validating a chat configuration before it is sent
+#[derive(Error, Debug)]
+pub enum ChatConfigError {
+    /// Tools were configured for a model that can't call them
+    #[error("Model {model} does not support tool calling, but {tool_count} tool(s) were configured")]
+    ToolsNotSupported { model: String, tool_count: usize },
+
+    /// The requested output budget is larger than the model can produce
+    #[error("max_output_tokens of {requested} exceeds the {limit} token limit of model {model}")]
+    MaxOutputTokensExceeded {
+        model: String,
+        requested: usize,
+        limit: usize,
+    },
+
+    /// There is nothing to send to the model
+    #[error("Chat history is empty; add at least one message")]
+    EmptyHistory,
+
+    /// A tool message answers no assistant tool call in the history
+    #[error("Tool result {tool_call_id} does not answer any assistant tool call")]
+    OrphanToolResult { tool_call_id: String },
+
+    /// An assistant tool call was never answered before the conversation moved on
+    #[error("Tool call {tool_call_id} has no tool result before the next message")]
+    MissingToolResult { tool_call_id: String },
+
+    /// No generation profile is registered under the requested name
+    #[error("Unknown generation profile: {name}")]
+    UnknownProfile { name: String },
+
+    /// No prompt template is registered under the requested ID
+    #[error("Unknown prompt: {id}")]
+    UnknownPrompt { id: String },
+
+    /// A prompt template placeholder was given no value
+    #[error("Prompt {prompt} needs a value for {{{{{variable}}}}}")]
+    MissingPromptVariable { prompt: String, variable: String },
+
+    /// A content filter pattern isn't a valid regular expression
+    #[error("Invalid content filter pattern {pattern:?}: {reason}")]
+    InvalidFilterPattern { pattern: String, reason: String },
+
+    /// A model name is neither a registered alias nor a known model ID
+    #[error("Unknown model: {name}")]
+    UnknownModel { name: String },
+
+    /// Model aliases refer to each other in a loop
+    #[error("Model alias {alias} refers back to itself")]
+    ModelAliasCycle { alias: String },
+
+    /// A resolved model belongs to a different provider than expected
+    #[error("Model {model} is not a {expected} model")]
+    WrongModelProvider { model: String, expected: String },
+
+    /// A continuation was requested, but the last message isn't a cut-off reply
+    #[error("The last message is not a truncated assistant reply")]
+    NothingToContinue,
+
+    /// Extra payload fields for a provider weren't a JSON object
+    #[error("Extra payload fields for {provider} must be a JSON object")]
+    ProviderExtraNotObject { provider: String },
+
+    /// A tenant service was called with a chat that names no tenant
+    #[error("Chat has no tenant; set one with Chat::with_tenant")]
+    MissingTenant,
+
+    /// A tenant service has no configuration for the chat's tenant
+    #[error("Unknown tenant: {tenant}")]
+    UnknownTenant { tenant: String },
+
+    /// No message in the chat's history has the given ID
+    #[error("No message with ID {id} in the history")]
+    UnknownMessage { id: String },
+}
+
+/// A provider config field that can't work, found before any request is
+/// built
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid {provider} config: `{field}` {problem}")]
+pub struct ProviderConfigError {
+    /// The provider the config is for
+    pub provider: String,
+    /// The config field at fault
+    pub field: &'static str,
+    /// What's wrong and what was expected
+    pub problem: String,
+}
+
 /// Represents errors that can occur in the language-barrier library
 #[derive(Error, Debug)]
 pub enum Error {
@@ -46,6 +139,26 @@ pub enum Error {
     #[error("Rate limit exceeded: {0}")]
     RateLimit(String),
 
+    /// The provider is temporarily out of capacity
+    #[error("Provider overloaded: {0}")]
+    Overloaded(String),
+
+    /// The provider didn't respond in time
+    #[error("Request timed out: {0}")]
+    Timeout(String),
+
+    /// A request body is larger than the configured limit
+    #[error("Request body of {size} bytes exceeds the {limit} byte limit")]
+    RequestTooLarge { size: usize, limit: usize },
+
+    /// A response grew larger than the configured limit and was abandoned
+    #[error("Response exceeds the {limit} byte limit")]
+    ResponseTooLarge { limit: usize },
+
+    /// The provider answered with a non-2xx status
+    #[error("{0}")]
+    Api(Box<ApiError>),
+
     /// Authentication error
     #[error("Authentication error: {0}")]
     Authentication(String),
@@ -82,6 +195,65 @@ pub enum Error {
     #[error("Tool error: {0}")]
     Tool(#[from] ToolError),
 
+    /// I/O error, e.g. while reading an attachment from disk
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The chat uses a feature the model doesn't support
+    #[error("Model {model} does not support {capability}")]
+    UnsupportedCapability {
+        model: String,
+        capability: ModelCapability,
+    },
+
+    /// The model has been retired by its provider
+    #[error(
+        "Model {model} was retired on {retired_on}{}",
+        replacement.as_ref().map(|id| format!("; use {id} instead")).unwrap_or_default()
+    )]
+    ModelRetired {
+        model: String,
+        retired_on: String,
+        replacement: Option<String>,
+    },
+
+    /// A content filter rule stopped the request before it was sent
+    #[error("Request blocked by content filter rule {rule}")]
+    ContentBlocked { rule: String },
+
+    /// Moderation flagged the reply and its policy is to block it
+    #[error("Reply blocked by moderation: {}", categories.join(", "))]
+    ReplyBlocked { categories: Vec<String> },
+
+    /// A stored conversation was saved by someone else since it was loaded
+    #[error("Conversation {id} is at version {actual}, not {expected}")]
+    VersionConflict {
+        id: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// A tenant has used up its token or cost budget
+    #[error("Tenant {tenant} has used up its {budget} budget")]
+    BudgetExceeded { tenant: String, budget: String },
+
+    /// The reply doesn't match the requested output schema
+    #[error("Structured output doesn't match the schema: {0}")]
+    InvalidStructuredOutput(String),
+
+    /// Invalid chat configuration
+    #[error("Invalid chat configuration: {0}")]
+    ChatConfig(#[from] ChatConfigError),
+
+    /// Invalid provider configuration
+    #[error("{0}")]
+    ProviderConfig(#[from] ProviderConfigError),
+
+    /// WebSocket error in a realtime session
+    #[cfg(feature = "realtime")]
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+
     /// Provider feature not supported
     #[error("Provider feature not supported: {0}")]
     ProviderFeatureNotSupported(String),
@@ -91,5 +263,266 @@ pub enum Error {
     Other(String),
 }
 
+// Boxed because tungstenite's error would otherwise bloat every `Result`
+#[cfg(feature = "realtime")]
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl Error {
+    /// Returns the details of a failed provider response, if this is one
+    pub fn api(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api(api) => Some(api),
+            _ => None,
+        }
+    }
+}
+
+/// What went wrong in a failed provider call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ApiErrorKind {
+    /// The request was malformed or used unsupported options
+    InvalidRequest,
+    /// The API key is missing, invalid, or lacks permission
+    Authentication,
+    /// The model or endpoint doesn't exist
+    NotFound,
+    /// Too many requests, or the quota is used up
+    RateLimit,
+    /// The provider is out of capacity for everyone, such as Anthropic's
+    /// 529 `overloaded_error`; worth waiting longer than for a plain
+    /// server error
+    Overloaded,
+    /// The prompt doesn't fit in the model's context window
+    ContextLengthExceeded,
+    /// The provider or a gateway gave up waiting
+    Timeout,
+    /// The provider failed on its side
+    Server,
+    /// Any other status
+    Other,
+}
+
+impl ApiErrorKind {
+    /// Classifies a response by its status code alone
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            400 | 422 => ApiErrorKind::InvalidRequest,
+            401 | 403 => ApiErrorKind::Authentication,
+            404 => ApiErrorKind::NotFound,
+            408 | 504 => ApiErrorKind::Timeout,
+            413 => ApiErrorKind::ContextLengthExceeded,
+            429 => ApiErrorKind::RateLimit,
+            529 => ApiErrorKind::Overloaded,
+            500..=599 => ApiErrorKind::Server,
+            _ => ApiErrorKind::Other,
+        }
+    }
+
+    /// Whether sending the same request again may succeed
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ApiErrorKind::RateLimit
+                | ApiErrorKind::Overloaded
+                | ApiErrorKind::Timeout
+                | ApiErrorKind::Server
+        )
+    }
+}
+
+impl fmt::Display for ApiErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ApiErrorKind::InvalidRequest => "Invalid request",
+            ApiErrorKind::Authentication => "Authentication error",
+            ApiErrorKind::NotFound => "Not found",
+            ApiErrorKind::RateLimit => "Rate limit exceeded",
+            ApiErrorKind::Overloaded => "Provider overloaded",
+            ApiErrorKind::ContextLengthExceeded => "Context length exceeded",
+            ApiErrorKind::Timeout => "Request timed out",
+            ApiErrorKind::Server => "Provider error",
+            ApiErrorKind::Other => "Unexpected response",
+        })
+    }
+}
+
+/// A non-2xx response from a provider
+///
+/// Keeps the status, the raw body and the headers that help diagnose or
+/// retry the call (request IDs, `Retry-After`, rate-limit counters), so a
+/// failure can be reported without re-running it.
+#[derive(Error, Debug, Clone)]
+#[error("{kind} (HTTP {status}): {message}")]
+pub struct ApiError {
+    /// The classified cause
+    pub kind: ApiErrorKind,
+    /// The HTTP status code
+    pub status: u16,
+    /// The provider's error message, or the body if it had none
+    pub message: String,
+    /// The raw response body
+    pub body: String,
+    /// Diagnostic headers, with lowercase names
+    pub headers: Vec<(String, String)>,
+    /// The provider that answered, when known
+    pub provider: Option<Provider>,
+    /// The quotas the request ran into, if the provider listed them
+    pub quota: Vec<QuotaViolation>,
+    /// How long the body said to wait before retrying, if it did
+    pub retry_delay: Option<Duration>,
+}
+
+impl ApiError {
+    /// Creates an error classified by `status`, keeping the diagnostic
+    /// headers from `headers`
+    pub fn new(status: u16, headers: &HeaderMap, body: impl Into<String>) -> Self {
+        let body = body.into();
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| is_diagnostic_header(name.as_str()))
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        Self {
+            kind: ApiErrorKind::from_status(status),
+            status,
+            message: body.clone(),
+            body,
+            headers,
+            provider: None,
+            quota: Vec::new(),
+            retry_delay: None,
+        }
+    }
+
+    /// Records which provider answered
+    pub fn with_provider(self, provider: Option<Provider>) -> Self {
+        Self { provider, ..self }
+    }
+
+    /// Records the quotas the request ran into
+    pub fn with_quota(self, quota: Vec<QuotaViolation>) -> Self {
+        Self { quota, ..self }
+    }
+
+    /// Records a retry delay given in the body rather than a header
+    pub fn with_retry_delay(self, retry_delay: Option<Duration>) -> Self {
+        Self {
+            retry_delay,
+            ..self
+        }
+    }
+
+    /// Overrides the classified cause
+    pub fn with_kind(self, kind: ApiErrorKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    /// Overrides the message
+    pub fn with_message(self, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..self
+        }
+    }
+
+    /// Returns a kept header's value; `name` is matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The provider's ID for the failed request, for support tickets
+    pub fn request_id(&self) -> Option<&str> {
+        self.header("x-request-id")
+            .or_else(|| self.header("request-id"))
+    }
+
+    /// How long the provider asked us to wait, from a `Retry-After` header
+    /// given in seconds or else from the body
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.header("retry-after")
+            .and_then(|seconds| seconds.trim().parse().ok())
+            .and_then(|seconds: f64| Duration::try_from_secs_f64(seconds).ok())
+            .or(self.retry_delay)
+    }
+
+    /// Whether a quota that won't reset within minutes is used up, so
+    /// retrying soon is pointless
+    ///
+    /// A per-minute quota, or a rate limit with no quota details, is
+    /// throttling that a short wait fixes; a daily quota isn't.
+    pub fn is_quota_exhausted(&self) -> bool {
+        self.quota
+            .iter()
+            .any(|violation| violation.period() == QuotaPeriod::Day)
+    }
+}
+
+/// How often a quota resets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuotaPeriod {
+    /// Every minute
+    Minute,
+    /// Every day
+    Day,
+    /// Some other period, or one the quota's ID doesn't say
+    Other,
+}
+
+/// One quota a request ran into
+///
+/// Gemini lists these in a `QuotaFailure` detail of a `RESOURCE_EXHAUSTED`
+/// error, with one entry per quota that was exceeded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuotaViolation {
+    /// The metric the quota counts, such as
+    /// `generativelanguage.googleapis.com/generate_content_free_tier_requests`
+    pub metric: String,
+    /// The quota's ID, such as `GenerateRequestsPerDayPerProjectPerModel`
+    pub id: String,
+    /// The quota's limit, if given
+    pub limit: Option<u64>,
+    /// What the quota is counted per, such as the model and location
+    pub dimensions: Vec<(String, String)>,
+}
+
+impl QuotaViolation {
+    /// How often the quota resets, read from its ID
+    pub fn period(&self) -> QuotaPeriod {
+        if self.id.contains("PerMinute") {
+            QuotaPeriod::Minute
+        } else if self.id.contains("PerDay") {
+            QuotaPeriod::Day
+        } else {
+            QuotaPeriod::Other
+        }
+    }
+
+    /// Returns a dimension's value, such as the `model` the quota is for
+    pub fn dimension(&self, name: &str) -> Option<&str> {
+        self.dimensions
+            .iter()
+            .find(|(dimension, _)| dimension == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Headers worth keeping on an error: request IDs, retry hints and
+/// rate-limit counters
+fn is_diagnostic_header(name: &str) -> bool {
+    matches!(
+        name,
+        "retry-after" | "request-id" | "x-request-id" | "content-type"
+    ) || name.contains("ratelimit")
+}
+
 /// A Result type that uses our Error type
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,186 @@
+//! Ratings and corrections on individual messages
+//!
+//! Feedback is attached to a message by its [ID](crate::ids) with
+//! [`Chat::with_feedback`](crate::Chat::with_feedback) and kept in the
+//! message's metadata. It's stored with the history, so it goes through a
+//! [`ChatStore`](crate::store::ChatStore) like any other change to the
+//! conversation (compare-and-swap included), and comes back out with it.
+//!
+//! [`Chat::feedback`](crate::Chat::feedback) exports one
+//! [`FeedbackRecord`] per piece of feedback, holding the rated message and
+//! the history before it: a row of preference or fine-tuning data.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::feedback::{Feedback, Rating};
+//! use language_barrier_core::store::{ChatStore, InMemoryChatStore};
+//! use language_barrier_core::{Chat, Message};
+//!
+//! # tokio_test::block_on(async {
+//! let store = InMemoryChatStore::new();
+//! let mut chat = Chat::default()
+//!     .with_conversation_id("conv-1")
+//!     .add_message(Message::user("What's the capital of Australia?"))
+//!     .add_message(Message::assistant("Sydney.").with_message_id("reply-1"));
+//! store.save_chat(&mut chat).await.unwrap();
+//!
+//! // Later, the user marks the reply as wrong
+//! let stored = store.load("conv-1").await.unwrap().unwrap();
+//! let mut chat = stored
+//!     .restore(Chat::default())
+//!     .with_feedback("reply-1", Feedback::down().with_correction("Canberra."))
+//!     .unwrap();
+//! store.save_chat(&mut chat).await.unwrap();
+//!
+//! let chat = store.load("conv-1").await.unwrap().unwrap().restore(Chat::default());
+//! let records = chat.feedback();
+//! assert_eq!(records[0].message_id, "reply-1");
+//! assert_eq!(records[0].feedback.rating, Some(Rating::Down));
+//! assert_eq!(records[0].context.len(), 1);
+//! # });
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::Message;
+
+/// A thumbs up or down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+    /// The message was good
+    Up,
+    /// The message was bad
+    Down,
+}
+
+/// What someone thought of a message
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Feedback {
+    /// The rating, if one was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<Rating>,
+    /// What the message should have said
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correction: Option<String>,
+    /// Free-text remarks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Who gave the feedback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub by: Option<String>,
+}
+
+impl Feedback {
+    /// A thumbs up
+    pub fn up() -> Self {
+        Self {
+            rating: Some(Rating::Up),
+            ..Self::default()
+        }
+    }
+
+    /// A thumbs down
+    pub fn down() -> Self {
+        Self {
+            rating: Some(Rating::Down),
+            ..Self::default()
+        }
+    }
+
+    /// Adds what the message should have said
+    #[must_use]
+    pub fn with_correction(self, correction: impl Into<String>) -> Self {
+        Self {
+            correction: Some(correction.into()),
+            ..self
+        }
+    }
+
+    /// Adds a free-text remark
+    #[must_use]
+    pub fn with_comment(self, comment: impl Into<String>) -> Self {
+        Self {
+            comment: Some(comment.into()),
+            ..self
+        }
+    }
+
+    /// Records who gave the feedback
+    #[must_use]
+    pub fn by(self, user: impl Into<String>) -> Self {
+        Self {
+            by: Some(user.into()),
+            ..self
+        }
+    }
+}
+
+/// One piece of feedback with the conversation it was given in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedbackRecord {
+    /// The conversation the message is in
+    pub conversation_id: String,
+    /// The rated message's ID
+    pub message_id: String,
+    /// The history before the rated message
+    pub context: Vec<Message>,
+    /// The rated message
+    pub message: Message,
+    /// The feedback
+    pub feedback: Feedback,
+}
+
+/// Returns a record for each piece of feedback on the messages of `history`
+pub(crate) fn records(conversation_id: &str, history: &[Message]) -> Vec<FeedbackRecord> {
+    history
+        .iter()
+        .enumerate()
+        .flat_map(|(index, message)| {
+            let message_id = message.message_id().unwrap_or_default();
+            message
+                .feedback()
+                .into_iter()
+                .map(move |feedback| FeedbackRecord {
+                    conversation_id: conversation_id.to_string(),
+                    message_id: message_id.to_string(),
+                    context: history[..index].to_vec(),
+                    message: message.clone(),
+                    feedback,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chat;
+    use crate::error::{ChatConfigError, Error};
+
+    #[test]
+    fn test_feedback_accumulates_and_needs_a_known_message() {
+        let chat = Chat::default()
+            .with_conversation_id("conv-1")
+            .add_message(Message::user("Hi").with_message_id("m1"))
+            .add_message(Message::assistant("Hello!").with_message_id("m2"))
+            .with_feedback("m2", Feedback::up().by("ada"))
+            .unwrap()
+            .with_feedback("m2", Feedback::default().with_comment("Friendly"))
+            .unwrap();
+
+        assert_eq!(chat.history[1].feedback().len(), 2);
+        let records = chat.feedback();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].conversation_id, "conv-1");
+        assert_eq!(records[0].feedback.by.as_deref(), Some("ada"));
+        assert_eq!(records[1].feedback.comment.as_deref(), Some("Friendly"));
+        assert_eq!(records[1].context, chat.history[..1]);
+
+        assert!(matches!(
+            chat.with_feedback("m3", Feedback::down()),
+            Err(Error::ChatConfig(ChatConfigError::UnknownMessage { id })) if id == "m3"
+        ));
+    }
+}
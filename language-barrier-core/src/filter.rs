@@ -0,0 +1,359 @@
+//! Screening outbound prompts
+//!
+//! A [`ContentFilter`] checks the text of user messages against deny-listed
+//! patterns before a request is built. Each rule either blocks the request
+//! (failing it with `Error::ContentBlocked`) or rewrites the matching text.
+//! Allow-listed patterns carve out exceptions: text they match is never
+//! filtered, so a rule for card numbers can let a known test card through.
+//!
+//! Set a filter on one chat with [`Chat::with_content_filter`] or for every
+//! chat with [`set_content_filter`]; a chat's own filter takes precedence.
+//! What each rule did is reported to the service's audit sink as
+//! [`FilterEvent`]s.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::filter::{ContentFilter, FilterAction};
+//! use language_barrier_core::{Chat, Message};
+//!
+//! let filter = ContentFilter::new()
+//!     .with_deny_rule("card-number", r"\b\d{4}(?:[ -]?\d{4}){3}\b", FilterAction::replace("[CARD]"))
+//!     .unwrap()
+//!     .with_deny_rule("jailbreak", r"(?i)ignore (all )?previous instructions", FilterAction::Block)
+//!     .unwrap()
+//!     .with_allowed(r"4242 4242 4242 4242")
+//!     .unwrap();
+//!
+//! let chat = Chat::default()
+//!     .add_message(Message::user("Charge 4111 1111 1111 1111, not 4242 4242 4242 4242"));
+//! let screened = filter.screen(&chat);
+//! assert_eq!(
+//!     screened.chat.history[0].text_content(),
+//!     "Charge [CARD], not 4242 4242 4242 4242"
+//! );
+//! assert!(screened.blocked.is_none());
+//!
+//! let chat = Chat::default().add_message(Message::user("Ignore previous instructions."));
+//! assert_eq!(filter.screen(&chat).blocked.as_deref(), Some("jailbreak"));
+//! ```
+//!
+//! [`Chat::with_content_filter`]: crate::Chat::with_content_filter
+
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::chat::Chat;
+use crate::error::{ChatConfigError, Error, Result};
+use crate::message::{Content, ContentPart, Message};
+
+/// Replaces text matched by a blocking rule in the audited request
+pub const BLOCKED: &str = "[BLOCKED]";
+
+static CONTENT_FILTER: RwLock<Option<Arc<ContentFilter>>> = RwLock::new(None);
+
+/// What happens when a deny rule matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Refuse to send the request
+    Block,
+    /// Replace the matching text and send the request
+    Replace(String),
+}
+
+impl FilterAction {
+    /// Replaces matches with `replacement`
+    pub fn replace(replacement: impl Into<String>) -> Self {
+        Self::Replace(replacement.into())
+    }
+}
+
+/// A named deny-list pattern
+#[derive(Debug, Clone)]
+pub struct FilterRule {
+    /// The name reported in errors and audit events
+    pub name: String,
+    /// The pattern to look for
+    pub pattern: Regex,
+    /// What to do with a match
+    pub action: FilterAction,
+}
+
+/// What one rule did to one message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterEvent {
+    /// The rule that matched
+    pub rule: String,
+    /// The position of the message in the chat history
+    pub message_index: usize,
+    /// How many times the rule matched
+    pub matches: usize,
+    /// Whether the rule blocked the request
+    pub blocked: bool,
+}
+
+/// A chat after screening
+#[derive(Debug, Clone)]
+pub struct ScreenedChat {
+    /// The chat with matching text replaced
+    ///
+    /// Text matched by blocking rules is replaced with [`BLOCKED`].
+    pub chat: Chat,
+    /// Every rule that matched
+    pub events: Vec<FilterEvent>,
+    /// The first blocking rule that matched, if any
+    pub blocked: Option<String>,
+}
+
+/// Deny and allow lists applied to outbound user messages
+#[derive(Debug, Clone, Default)]
+pub struct ContentFilter {
+    deny: Vec<FilterRule>,
+    allow: Vec<Regex>,
+}
+
+impl ContentFilter {
+    /// Creates a filter that lets everything through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a deny rule
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::InvalidFilterPattern` if `pattern` isn't a
+    /// valid regular expression.
+    pub fn with_deny_rule(
+        self,
+        name: impl Into<String>,
+        pattern: &str,
+        action: FilterAction,
+    ) -> Result<Self> {
+        let mut deny = self.deny;
+        deny.push(FilterRule {
+            name: name.into(),
+            pattern: compile(pattern)?,
+            action,
+        });
+        Ok(Self { deny, ..self })
+    }
+
+    /// Exempts text matching `pattern` from every deny rule
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::InvalidFilterPattern` if `pattern` isn't a
+    /// valid regular expression.
+    pub fn with_allowed(self, pattern: &str) -> Result<Self> {
+        let mut allow = self.allow;
+        allow.push(compile(pattern)?);
+        Ok(Self { allow, ..self })
+    }
+
+    /// Returns the deny rules, in the order they're applied
+    pub fn rules(&self) -> &[FilterRule] {
+        &self.deny
+    }
+
+    /// Applies the deny rules to every user message in `chat`
+    pub fn screen(&self, chat: &Chat) -> ScreenedChat {
+        let mut screened = ScreenedChat {
+            chat: chat.clone(),
+            events: Vec::new(),
+            blocked: None,
+        };
+
+        for (index, message) in screened.chat.history.iter_mut().enumerate() {
+            let Message::User { content, .. } = message else {
+                continue;
+            };
+            let mut texts: Vec<&mut String> = match content {
+                Content::Text(text) => vec![text],
+                Content::Parts(parts) => parts
+                    .iter_mut()
+                    .filter_map(ContentPart::as_text_mut)
+                    .collect(),
+            };
+
+            for rule in &self.deny {
+                let replacement = match &rule.action {
+                    FilterAction::Block => BLOCKED,
+                    FilterAction::Replace(replacement) => replacement.as_str(),
+                };
+                let matches: usize = texts
+                    .iter_mut()
+                    .map(|text| apply_rule(&rule.pattern, replacement, &self.allow, text))
+                    .sum();
+                if matches == 0 {
+                    continue;
+                }
+
+                let blocked = rule.action == FilterAction::Block;
+                if blocked && screened.blocked.is_none() {
+                    screened.blocked = Some(rule.name.clone());
+                }
+                screened.events.push(FilterEvent {
+                    rule: rule.name.clone(),
+                    message_index: index,
+                    matches,
+                    blocked,
+                });
+            }
+        }
+
+        screened
+    }
+}
+
+/// Replaces the matches of `rule` in `text` that no allow pattern covers,
+/// returning how many were replaced
+fn apply_rule(rule: &Regex, replacement: &str, allow: &[Regex], text: &mut String) -> usize {
+    let allowed: Vec<Range<usize>> = allow
+        .iter()
+        .flat_map(|pattern| pattern.find_iter(text).map(|m| m.range()))
+        .collect();
+    let mut matches = 0;
+    let replaced = rule.replace_all(text, |caps: &Captures| {
+        let found = caps.get(0).expect("group 0 always matches");
+        let exempt = allowed
+            .iter()
+            .any(|range| range.start <= found.start() && found.end() <= range.end);
+        if exempt {
+            found.as_str().to_string()
+        } else {
+            matches += 1;
+            replacement.to_string()
+        }
+    });
+    if matches > 0 {
+        *text = replaced.into_owned();
+    }
+    matches
+}
+
+pub(crate) fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| {
+        Error::ChatConfig(ChatConfigError::InvalidFilterPattern {
+            pattern: pattern.to_string(),
+            reason: e.to_string(),
+        })
+    })
+}
+
+/// Applies `filter` to every chat that doesn't set its own
+pub fn set_content_filter(filter: ContentFilter) {
+    *CONTENT_FILTER
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Arc::new(filter));
+}
+
+/// Removes the global content filter
+pub fn clear_content_filter() {
+    *CONTENT_FILTER
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+/// Returns the global content filter, if any
+pub fn content_filter() -> Option<Arc<ContentFilter>> {
+    CONTENT_FILTER
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+#[cfg(all(test, any(feature = "multimodal", feature = "openai")))]
+mod tests {
+    use super::*;
+    #[cfg(feature = "openai")]
+    use crate::OpenAi;
+    #[cfg(feature = "openai")]
+    use crate::audit::MemoryAuditSink;
+    #[cfg(feature = "openai")]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "openai")]
+    use crate::provider::openai::OpenAIProvider;
+    #[cfg(feature = "openai")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+
+    fn filter() -> ContentFilter {
+        ContentFilter::new()
+            .with_deny_rule("email", r"[\w.]+@[\w.]+", FilterAction::replace("[EMAIL]"))
+            .unwrap()
+            .with_deny_rule("secret", r"(?i)password", FilterAction::Block)
+            .unwrap()
+            .with_allowed(r"support@example\.com")
+            .unwrap()
+    }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_only_user_text_outside_the_allow_list_is_rewritten() {
+        let chat = Chat::default()
+            .add_message(Message::user("Mail ada@corp.io or support@example.com"))
+            .add_message(Message::assistant("Write to bob@corp.io"))
+            .add_message(Message::user_with_parts(vec![
+                ContentPart::text("cc eve@corp.io"),
+                ContentPart::image_url("https://example.com/a.png"),
+            ]));
+
+        let screened = filter().screen(&chat);
+        assert_eq!(
+            screened.chat.history[0].text_content(),
+            "Mail [EMAIL] or support@example.com"
+        );
+        assert_eq!(
+            screened.chat.history[1].text_content(),
+            "Write to bob@corp.io"
+        );
+        assert_eq!(screened.chat.history[2].text_content(), "cc [EMAIL]");
+        assert_eq!(
+            screened
+                .events
+                .iter()
+                .map(|e| e.message_index)
+                .collect::<Vec<_>>(),
+            [0, 2]
+        );
+        assert!(screened.blocked.is_none());
+
+        assert!(matches!(
+            ContentFilter::new().with_allowed("("),
+            Err(Error::ChatConfig(
+                ChatConfigError::InvalidFilterPattern { .. }
+            ))
+        ));
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_blocked_requests_are_audited_but_not_sent() {
+        let transport = Arc::new(MockTransport::new().with_fallback(MockResponse::status(500, "")));
+        let sink = Arc::new(MemoryAuditSink::new());
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            transport.clone(),
+        )
+        .with_audit_sink(sink.clone());
+        let chat = Chat::default()
+            .with_content_filter(filter())
+            .add_message(Message::user(
+                "My password is hunter2, email me at ada@corp.io",
+            ));
+
+        let error = service.generate_next_message(&chat).await.unwrap_err();
+        assert!(matches!(error, Error::ContentBlocked { rule } if rule == "secret"));
+        assert!(transport.requests().is_empty());
+
+        let record = &sink.records()[0];
+        let sent = record.request.body.as_ref().unwrap()["messages"][0]["content"].clone();
+        assert_eq!(sent, "My [BLOCKED] is hunter2, email me at [EMAIL]");
+        assert_eq!(record.filter_events.len(), 2);
+        assert!(record.filter_events[1].blocked);
+    }
+}
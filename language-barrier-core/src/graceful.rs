@@ -0,0 +1,254 @@
+//! Replying gracefully when every attempt fails
+//!
+//! Retries, [`FallbackService`](crate::health::FallbackService) and
+//! recovery middleware make failures rare, but not impossible. A
+//! user-facing app then has to turn each kind of error into something to
+//! show. A [`GracefulService`] does that in one place: when the service it
+//! wraps fails, it returns an assistant message built from a
+//! [`FallbackReply`] ("I'm having trouble right now...") instead of the
+//! error.
+//!
+//! The message carries a [`ReplyFailure`] under
+//! [`ReplyFailure::METADATA_KEY`], so the app can still tell it apart (see
+//! [`Message::is_fallback`]), e.g. to offer a retry button or to keep it
+//! out of the history sent next time. Wrap the outermost service, so the
+//! fallback is only used once everything else has given up.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use async_trait::async_trait;
+//! use language_barrier_core::graceful::{FallbackReply, GracefulService};
+//! use language_barrier_core::llm_service::LLMService;
+//! use language_barrier_core::{Chat, Claude, Error, Message, Result};
+//!
+//! struct Down;
+//!
+//! #[async_trait]
+//! impl LLMService<Claude> for Down {
+//!     async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+//!         Err(Error::ProviderUnavailable("overloaded".into()))
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let reply = FallbackReply::template("Sorry, I can't answer right now ({{error}}).").unwrap();
+//! let service = GracefulService::new(Arc::new(Down), reply);
+//!
+//! let chat = Chat::default().add_message(Message::user("Hi"));
+//! let message = service.generate_next_message(&chat).await.unwrap();
+//! assert!(message.is_fallback());
+//! assert_eq!(
+//!     message.text_content(),
+//!     "Sorry, I can't answer right now (Provider not available: overloaded)."
+//! );
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::llm_service::LLMService;
+use crate::prompts::PromptTemplate;
+use crate::reload::SharedService;
+use crate::salvage::Continuation;
+use crate::{Chat, Message, ModelInfo};
+
+/// The variables a [`FallbackReply::template`] may use
+pub const TEMPLATE_VARIABLES: [&str; 2] = ["error", "request_id"];
+
+/// The text of a fallback message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FallbackReply {
+    /// The same text for every failure
+    Static(String),
+    /// Text with `{{variable}}` placeholders for the failure's details
+    Template(PromptTemplate),
+}
+
+impl FallbackReply {
+    /// A fixed message
+    pub fn fixed(text: impl Into<String>) -> Self {
+        Self::Static(text.into())
+    }
+
+    /// A message with placeholders for the failure
+    ///
+    /// `{{error}}` is the error's message and `{{request_id}}` the
+    /// provider's request ID, or empty if it didn't send one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::MissingPromptVariable` if the template uses
+    /// any other placeholder.
+    pub fn template(text: impl Into<String>) -> Result<Self> {
+        let template = PromptTemplate::new("fallback_reply", "v1", text);
+        let vars = TEMPLATE_VARIABLES
+            .iter()
+            .map(|name| (name.to_string(), String::new()))
+            .collect();
+        template.render(&vars)?;
+        Ok(Self::Template(template))
+    }
+
+    /// Builds the message for `failure`
+    pub fn message(&self, failure: &ReplyFailure) -> Message {
+        let text = match self {
+            Self::Static(text) => text.clone(),
+            Self::Template(template) => {
+                let vars = HashMap::from([
+                    ("error".to_string(), failure.error.clone()),
+                    (
+                        "request_id".to_string(),
+                        failure.request_id.clone().unwrap_or_default(),
+                    ),
+                ]);
+                // Checked when the template was created
+                template
+                    .render(&vars)
+                    .unwrap_or_else(|_| template.template.clone())
+            }
+        };
+        Message::assistant(text)
+            .with_metadata(ReplyFailure::METADATA_KEY, failure.to_metadata())
+            .timestamped()
+    }
+}
+
+impl Default for FallbackReply {
+    fn default() -> Self {
+        Self::fixed("I'm having trouble right now. Please try again in a moment.")
+    }
+}
+
+/// The error a fallback message stands in for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplyFailure {
+    /// The error's message
+    pub error: String,
+    /// The provider's request ID, if the error came from a response
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub request_id: Option<String>,
+}
+
+impl ReplyFailure {
+    /// The metadata key fallback messages store their failure under
+    pub const METADATA_KEY: &'static str = "failure";
+
+    /// Describes `error`
+    pub fn new(error: &Error) -> Self {
+        Self {
+            error: error.to_string(),
+            request_id: error
+                .api()
+                .and_then(|api| api.request_id())
+                .map(String::from),
+        }
+    }
+
+    /// Converts this failure into a metadata value
+    #[must_use]
+    pub fn to_metadata(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// A service that replies with a fallback message when its inner service
+/// fails
+///
+/// See the [module docs](self).
+pub struct GracefulService<M> {
+    inner: SharedService<M>,
+    reply: FallbackReply,
+}
+
+impl<M: ModelInfo> GracefulService<M> {
+    /// Wraps `inner`, replying with `reply` whenever it fails
+    pub fn new(inner: SharedService<M>, reply: FallbackReply) -> Self {
+        Self { inner, reply }
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo> LLMService<M> for GracefulService<M> {
+    /// Returns the inner service's reply, or the fallback message if it
+    /// fails
+    ///
+    /// This never returns an error.
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        match self.inner.generate_next_message(chat).await {
+            Ok(message) => Ok(message),
+            Err(e) => {
+                warn!("Replying with the fallback message: {}", e);
+                Ok(self.reply.message(&ReplyFailure::new(&e)))
+            }
+        }
+    }
+
+    fn continuation(&self) -> Continuation {
+        self.inner.continuation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "openai")]
+    use crate::OpenAi;
+    use crate::error::ChatConfigError;
+    #[cfg(feature = "openai")]
+    use crate::llm_service::HTTPLlmService;
+    #[cfg(feature = "openai")]
+    use crate::provider::openai::OpenAIProvider;
+    #[cfg(feature = "openai")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+    #[cfg(feature = "openai")]
+    use std::sync::Arc;
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_api_failures_become_flagged_fallback_messages() {
+        let transport = Arc::new(
+            MockTransport::new()
+                .with_fallback(MockResponse::status(503, "").with_header("x-request-id", "req_42")),
+        );
+        let inner = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            transport,
+        );
+        let reply = FallbackReply::template("Trouble ({{request_id}})").unwrap();
+        let service = GracefulService::new(Arc::new(inner), reply);
+        let chat = Chat::default().add_message(Message::user("Hi"));
+
+        let message = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(message.text_content(), "Trouble (req_42)");
+        let failure = message.failure().unwrap();
+        assert_eq!(failure.request_id.as_deref(), Some("req_42"));
+        assert!(failure.error.contains("503"), "{}", failure.error);
+    }
+
+    #[test]
+    fn test_templates_may_only_use_known_variables() {
+        assert!(matches!(
+            FallbackReply::template("Hi {{name}}"),
+            Err(Error::ChatConfig(
+                ChatConfigError::MissingPromptVariable { .. }
+            ))
+        ));
+
+        let failure = ReplyFailure {
+            error: "boom".into(),
+            request_id: None,
+        };
+        let message = FallbackReply::default().message(&failure);
+        assert!(message.is_fallback());
+        assert!(!Message::assistant("Hi").is_fallback());
+    }
+}
@@ -0,0 +1,545 @@
+//! Tracking provider health and falling back between providers
+//!
+//! A [`HealthMonitor`] keeps a rolling window of recent call outcomes for
+//! each provider it's told about, and judges a provider unhealthy once too
+//! many of those calls failed, or, optionally, once they got too slow. Only
+//! failures that are the provider's fault count: server errors, timeouts,
+//! rate limits and transport errors. A rejected request would fail anywhere.
+//!
+//! A [`FallbackService`] tries its providers in order until one answers,
+//! using a monitor to skip ahead: unhealthy providers are moved behind the
+//! healthy ones and only tried as a last resort. Once every probe interval,
+//! an unhealthy provider gets one call in its usual place, as a probe; if it
+//! succeeds, the provider's window is cleared and it's healthy again.
+//!
+//! Give the monitor a store with [`HealthMonitor::with_store`] (see
+//! [`limit_state`](crate::limit_state)) to keep windows and probe times
+//! across restarts, so a restarted process doesn't send its first calls to
+//! a provider it had just given up on.
+//!
+//! # Examples
+//!
+#![cfg_attr(all(feature = "anthropic", feature = "openai"), doc = "```")]
+#![cfg_attr(not(all(feature = "anthropic", feature = "openai")), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use language_barrier_core::health::{FallbackService, HealthMonitor};
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::provider::anthropic::AnthropicProvider;
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::{Claude, OpenAi};
+//!
+//! let monitor = Arc::new(
+//!     HealthMonitor::new()
+//!         .with_error_threshold(0.3)
+//!         .with_latency_threshold(Duration::from_secs(20)),
+//! );
+//! let service = FallbackService::new(monitor.clone())
+//!     .with_provider(
+//!         "openai",
+//!         HTTPLlmService::new(OpenAi::GPT4o, Arc::new(OpenAIProvider::new())),
+//!     )
+//!     .with_provider(
+//!         "anthropic",
+//!         HTTPLlmService::new(Claude::Haiku35, Arc::new(AnthropicProvider::new())),
+//!     );
+//!
+//! assert!(monitor.health("openai").healthy);
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::error::{Error, Result};
+use crate::limit_state::{self, LimitStore, Persistence};
+use crate::llm_service::LLMService;
+use crate::{Chat, Message, ModelInfo};
+
+/// Returns true if `error` says something about the provider rather than
+/// the request
+pub fn is_provider_failure(error: &Error) -> bool {
+    match error {
+        Error::Api(api) => api.kind.is_retryable(),
+        Error::Request(_)
+        | Error::Timeout(_)
+        | Error::RateLimit(_)
+        | Error::Overloaded(_)
+        | Error::ProviderUnavailable(_) => true,
+        _ => false,
+    }
+}
+
+/// A provider's recent record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProviderHealth {
+    /// Calls in the window
+    pub samples: usize,
+    /// The share of those calls that failed, from 0 to 1
+    pub error_rate: f64,
+    /// Their average latency, if there were any
+    pub mean_latency: Option<Duration>,
+    /// Whether the provider should be preferred
+    pub healthy: bool,
+}
+
+#[derive(Debug, Default)]
+struct Record {
+    /// Latency and success of recent calls, oldest first
+    outcomes: VecDeque<(Duration, bool)>,
+    last_probe: Option<Instant>,
+}
+
+/// A provider's record as stored
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedRecord {
+    outcomes: Vec<(Duration, bool)>,
+    last_probe: Option<SystemTime>,
+}
+
+/// Rolling error rates and latencies per provider
+///
+/// Providers are named by the caller. A provider with fewer samples than
+/// the minimum is always healthy, so one early failure doesn't condemn it.
+#[derive(Debug)]
+pub struct HealthMonitor {
+    window: usize,
+    min_samples: usize,
+    error_threshold: f64,
+    latency_threshold: Option<Duration>,
+    probe_interval: Duration,
+    records: Mutex<HashMap<String, Record>>,
+    persistence: Option<Persistence>,
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            min_samples: 5,
+            error_threshold: 0.5,
+            latency_threshold: None,
+            probe_interval: Duration::from_secs(30),
+            records: Mutex::default(),
+            persistence: None,
+        }
+    }
+}
+
+impl HealthMonitor {
+    /// Creates a monitor that judges the last 20 calls, calling a provider
+    /// unhealthy once half of them failed, and probing every 30 seconds
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many recent calls are kept per provider
+    #[must_use]
+    pub fn with_window(self, window: usize) -> Self {
+        Self { window, ..self }
+    }
+
+    /// Sets how many calls a provider needs before it can be unhealthy
+    #[must_use]
+    pub fn with_min_samples(self, min_samples: usize) -> Self {
+        Self {
+            min_samples,
+            ..self
+        }
+    }
+
+    /// Sets the error rate, from 0 to 1, at which a provider is unhealthy
+    #[must_use]
+    pub fn with_error_threshold(self, error_threshold: f64) -> Self {
+        Self {
+            error_threshold,
+            ..self
+        }
+    }
+
+    /// Also calls a provider unhealthy once its average latency passes
+    /// `latency`
+    #[must_use]
+    pub fn with_latency_threshold(self, latency: Duration) -> Self {
+        Self {
+            latency_threshold: Some(latency),
+            ..self
+        }
+    }
+
+    /// Sets how often an unhealthy provider is probed
+    #[must_use]
+    pub fn with_probe_interval(self, probe_interval: Duration) -> Self {
+        Self {
+            probe_interval,
+            ..self
+        }
+    }
+
+    /// Keeps every provider's record in `store` under `key`, so it
+    /// survives restarts
+    ///
+    /// A [`FallbackService`] loads the records on its first call and saves
+    /// them after each call.
+    #[must_use]
+    pub fn with_store(self, store: Arc<dyn LimitStore>, key: impl Into<String>) -> Self {
+        Self {
+            persistence: Some(Persistence::new(store, key)),
+            ..self
+        }
+    }
+
+    /// Loads the stored records, the first time only
+    ///
+    /// Providers already recorded in this process keep their own record.
+    pub async fn restore(&self) {
+        let Some(persistence) = &self.persistence else {
+            return;
+        };
+        persistence
+            .restore(|saved: HashMap<String, SavedRecord>| {
+                let mut records = self.records();
+                for (provider, saved) in saved {
+                    records.entry(provider).or_insert_with(|| Record {
+                        outcomes: saved.outcomes.into_iter().collect(),
+                        last_probe: saved.last_probe.and_then(limit_state::monotonic),
+                    });
+                }
+            })
+            .await;
+    }
+
+    /// Stores every provider's record
+    pub async fn save(&self) {
+        let Some(persistence) = &self.persistence else {
+            return;
+        };
+        let saved: HashMap<String, SavedRecord> = self
+            .records()
+            .iter()
+            .map(|(provider, record)| {
+                (
+                    provider.clone(),
+                    SavedRecord {
+                        outcomes: record.outcomes.iter().copied().collect(),
+                        last_probe: record.last_probe.map(limit_state::wall_clock),
+                    },
+                )
+            })
+            .collect();
+        persistence.save(&saved).await;
+    }
+
+    fn records(&self) -> MutexGuard<'_, HashMap<String, Record>> {
+        self.records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Records a call to `provider` that took `latency`
+    ///
+    /// A success while the provider is unhealthy is a recovered probe, and
+    /// clears its window.
+    pub fn record(&self, provider: &str, latency: Duration, success: bool) {
+        let healthy = self.health(provider).healthy;
+        let mut records = self.records();
+        let record = records.entry(provider.to_string()).or_default();
+        if success && !healthy {
+            info!("Provider {} recovered", provider);
+            record.outcomes.clear();
+        }
+        record.outcomes.push_back((latency, success));
+        while record.outcomes.len() > self.window {
+            record.outcomes.pop_front();
+        }
+    }
+
+    /// Returns `provider`'s recent record
+    pub fn health(&self, provider: &str) -> ProviderHealth {
+        let records = self.records();
+        let outcomes = records
+            .get(provider)
+            .map(|record| &record.outcomes)
+            .filter(|outcomes| !outcomes.is_empty());
+        let Some(outcomes) = outcomes else {
+            return ProviderHealth {
+                samples: 0,
+                error_rate: 0.0,
+                mean_latency: None,
+                healthy: true,
+            };
+        };
+
+        let samples = outcomes.len();
+        let failures = outcomes.iter().filter(|(_, success)| !success).count();
+        let error_rate = failures as f64 / samples as f64;
+        let total: Duration = outcomes.iter().map(|(latency, _)| *latency).sum();
+        let mean_latency = total / samples as u32;
+        let slow = self
+            .latency_threshold
+            .is_some_and(|threshold| mean_latency > threshold);
+        ProviderHealth {
+            samples,
+            error_rate,
+            mean_latency: Some(mean_latency),
+            healthy: samples < self.min_samples || (error_rate < self.error_threshold && !slow),
+        }
+    }
+
+    /// Returns true if `provider` is unhealthy and due a probe, counting
+    /// this as the probe
+    fn take_probe(&self, provider: &str) -> bool {
+        let now = Instant::now();
+        let mut records = self.records();
+        let record = records.entry(provider.to_string()).or_default();
+        let due = record
+            .last_probe
+            .is_none_or(|last| now.duration_since(last) >= self.probe_interval);
+        if due {
+            record.last_probe = Some(now);
+        }
+        due
+    }
+}
+
+/// A service of any model type, behind one interface
+#[async_trait]
+trait Route: Send + Sync {
+    async fn generate(&self, chat: &Chat) -> Result<Message>;
+}
+
+struct Typed<M, S> {
+    service: S,
+    model: PhantomData<fn() -> M>,
+}
+
+#[async_trait]
+impl<M: ModelInfo, S: LLMService<M> + Send + Sync> Route for Typed<M, S> {
+    async fn generate(&self, chat: &Chat) -> Result<Message> {
+        self.service.generate_next_message(chat).await
+    }
+}
+
+/// A service that tries providers in order, preferring healthy ones
+///
+/// A call fails over to the next provider only on a provider failure (see
+/// [`is_provider_failure`]); any other error is returned at once. If every
+/// provider fails, the last error is returned.
+pub struct FallbackService {
+    providers: Vec<(String, Box<dyn Route>)>,
+    monitor: Arc<HealthMonitor>,
+}
+
+impl FallbackService {
+    /// Creates a fallback service with no providers, judged by `monitor`
+    pub fn new(monitor: Arc<HealthMonitor>) -> Self {
+        Self {
+            providers: Vec::new(),
+            monitor,
+        }
+    }
+
+    /// Adds a provider after the ones added so far
+    ///
+    /// `name` identifies it to the monitor; providers may use different
+    /// model types.
+    #[must_use]
+    pub fn with_provider<M, S>(self, name: impl Into<String>, service: S) -> Self
+    where
+        M: ModelInfo + 'static,
+        S: LLMService<M> + Send + Sync + 'static,
+    {
+        let mut providers = self.providers;
+        providers.push((
+            name.into(),
+            Box::new(Typed {
+                service,
+                model: PhantomData,
+            }),
+        ));
+        Self { providers, ..self }
+    }
+
+    /// Returns the monitor this service reports to
+    pub fn monitor(&self) -> &Arc<HealthMonitor> {
+        &self.monitor
+    }
+
+    /// The order to try providers in for the next call
+    fn order(&self) -> Vec<&(String, Box<dyn Route>)> {
+        let (mut preferred, mut last_resort) = (Vec::new(), Vec::new());
+        for provider in &self.providers {
+            let name = provider.0.as_str();
+            if self.monitor.health(name).healthy {
+                preferred.push(provider);
+            } else if self.monitor.take_probe(name) {
+                debug!("Probing unhealthy provider {}", name);
+                preferred.push(provider);
+            } else {
+                last_resort.push(provider);
+            }
+        }
+        preferred.append(&mut last_resort);
+        preferred
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo> LLMService<M> for FallbackService {
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        self.monitor.restore().await;
+        let mut last_error = None;
+        for (name, route) in self.order() {
+            let started = Instant::now();
+            let result = route.generate(chat).await;
+            let latency = started.elapsed();
+            match result {
+                Ok(message) => {
+                    self.monitor.record(name, latency, true);
+                    self.monitor.save().await;
+                    return Ok(message);
+                }
+                Err(e) if is_provider_failure(&e) => {
+                    warn!("Provider {} failed, falling back: {}", name, e);
+                    self.monitor.record(name, latency, false);
+                    self.monitor.save().await;
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::Other("No providers configured".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Claude, OpenAi};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Replies with its name, or fails while `down` is set
+    struct Flaky {
+        name: &'static str,
+        down: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Flaky {
+        fn new(name: &'static str) -> Self {
+            Self {
+                name,
+                down: Arc::default(),
+                calls: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LLMService<Claude> for Flaky {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Err(Error::ProviderUnavailable("down".to_string()));
+            }
+            Ok(Message::assistant(self.name))
+        }
+    }
+
+    /// Rejects every request as invalid
+    struct Rejecting;
+
+    #[async_trait]
+    impl LLMService<OpenAi> for Rejecting {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            Err(Error::Other("bad request".to_string()))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unhealthy_providers_are_skipped_until_probed() {
+        let primary = Flaky::new("primary");
+        let (down, calls) = (primary.down.clone(), primary.calls.clone());
+        let monitor = Arc::new(
+            HealthMonitor::new()
+                .with_min_samples(2)
+                .with_probe_interval(Duration::from_secs(30)),
+        );
+        let service = FallbackService::new(monitor.clone())
+            .with_provider("primary", primary)
+            .with_provider("secondary", Flaky::new("secondary"));
+        let chat = Chat::default().add_message(Message::user("Hi"));
+        let generate = || LLMService::<Claude>::generate_next_message(&service, &chat);
+
+        down.store(true, Ordering::SeqCst);
+        for _ in 0..2 {
+            assert_eq!(generate().await.unwrap().text_content(), "secondary");
+        }
+        assert!(!monitor.health("primary").healthy);
+        assert_eq!(monitor.health("primary").error_rate, 1.0);
+
+        // The first call after the primary turned unhealthy is a probe
+        generate().await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        generate().await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        down.store(false, Ordering::SeqCst);
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(generate().await.unwrap().text_content(), "primary");
+        assert!(monitor.health("primary").healthy);
+        assert_eq!(monitor.health("primary").samples, 1);
+
+        let rejecting = FallbackService::new(Arc::new(HealthMonitor::new()))
+            .with_provider("rejecting", Rejecting)
+            .with_provider("secondary", Flaky::new("secondary"));
+        assert!(
+            LLMService::<Claude>::generate_next_message(&rejecting, &chat)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stored_records_keep_a_provider_cooling_down() {
+        let store = Arc::new(crate::limit_state::InMemoryLimitStore::new());
+        let monitor = || {
+            Arc::new(
+                HealthMonitor::new()
+                    .with_min_samples(2)
+                    .with_store(store.clone(), "health"),
+            )
+        };
+        let chat = Chat::default().add_message(Message::user("Hi"));
+
+        let primary = Flaky::new("primary");
+        primary.down.store(true, Ordering::SeqCst);
+        let service = FallbackService::new(monitor())
+            .with_provider("primary", primary)
+            .with_provider("secondary", Flaky::new("secondary"));
+        for _ in 0..3 {
+            LLMService::<Claude>::generate_next_message(&service, &chat)
+                .await
+                .unwrap();
+        }
+
+        // After a restart the primary is still unhealthy and was just probed
+        let primary = Flaky::new("primary");
+        let calls = primary.calls.clone();
+        let restarted = FallbackService::new(monitor())
+            .with_provider("primary", primary)
+            .with_provider("secondary", Flaky::new("secondary"));
+        let reply = LLMService::<Claude>::generate_next_message(&restarted, &chat)
+            .await
+            .unwrap();
+        assert_eq!(reply.text_content(), "secondary");
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(restarted.monitor().health("primary").samples, 3);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::chat::Chat;
+use crate::error::{ChatConfigError, Result};
+use crate::message::Message;
+use tracing::{debug, warn};
+
+/// Repairs the placement of tool results in a conversation history
+///
+/// Providers such as OpenAI reject a history unless every tool message
+/// directly follows the assistant message whose `tool_calls` it answers.
+/// Histories assembled by hand, or merged from several sources, easily get
+/// this wrong. The normalizer moves each tool result to sit right after its
+/// originating assistant message (in the order the calls were made) and
+/// reports anything it can't repair as a descriptive error.
+///
+/// Tool results are matched to calls by ID. Because some providers reuse
+/// call IDs across turns (Gemini synthesizes them per response), each
+/// assistant message claims the *first unclaimed* result with a matching ID.
+///
+/// Normalization is opt-in: call it directly, via [`Chat::normalize_tool_order`],
+/// or through the runtime's normalization middleware.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::history::ToolOrderNormalizer;
+/// use language_barrier_core::message::{Function, Message, ToolCall};
+///
+/// let call = ToolCall {
+///     id: "call_1".to_string(),
+///     tool_type: "function".to_string(),
+///     function: Function {
+///         name: "get_weather".to_string(),
+///         arguments: "{}".to_string(),
+///     },
+/// };
+///
+/// // The tool result was recorded before the assistant message that asked for it
+/// let history = vec![
+///     Message::user("What's the weather?"),
+///     Message::tool("call_1", "10C"),
+///     Message::assistant_with_tool_calls(vec![call]),
+/// ];
+///
+/// let normalized = ToolOrderNormalizer::new().normalize(&history).unwrap();
+/// let roles: Vec<_> = normalized.iter().map(Message::role_str).collect();
+/// assert_eq!(roles, vec!["user", "assistant", "tool"]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ToolOrderNormalizer {
+    missing_result_placeholder: Option<String>,
+}
+
+impl ToolOrderNormalizer {
+    /// Creates a normalizer that errors on tool calls without results
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fills in missing tool results with `placeholder` instead of erroring
+    ///
+    /// Only calls that are followed by further conversation are affected;
+    /// calls on the final assistant message are still awaiting execution and
+    /// are left alone.
+    #[must_use]
+    pub fn with_missing_result_placeholder(self, placeholder: impl Into<String>) -> Self {
+        Self {
+            missing_result_placeholder: Some(placeholder.into()),
+        }
+    }
+
+    /// Returns the history with every tool result placed after its call
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ChatConfig` with
+    /// - `ChatConfigError::OrphanToolResult` for a tool message that matches
+    ///   no assistant tool call
+    /// - `ChatConfigError::MissingToolResult` for a tool call with no result
+    ///   that is followed by further conversation, unless a placeholder is set
+    pub fn normalize(&self, history: &[Message]) -> Result<Vec<Message>> {
+        let tool_messages: Vec<(usize, &str)> = history
+            .iter()
+            .enumerate()
+            .filter_map(|(index, msg)| match msg {
+                Message::Tool { tool_call_id, .. } => Some((index, tool_call_id.as_str())),
+                _ => None,
+            })
+            .collect();
+        let mut claimed = vec![false; tool_messages.len()];
+
+        let last_conversational = history
+            .iter()
+            .rposition(|msg| !matches!(msg, Message::Tool { .. }));
+
+        let mut normalized = Vec::with_capacity(history.len());
+        for (index, msg) in history.iter().enumerate() {
+            if matches!(msg, Message::Tool { .. }) {
+                continue;
+            }
+            normalized.push(msg.clone());
+
+            let Message::Assistant { tool_calls, .. } = msg else {
+                continue;
+            };
+
+            for call in tool_calls {
+                let result = tool_messages
+                    .iter()
+                    .zip(claimed.iter_mut())
+                    .find(|((_, id), taken)| !**taken && *id == call.id);
+
+                match result {
+                    Some(((tool_index, _), taken)) => {
+                        *taken = true;
+                        normalized.push(history[*tool_index].clone());
+                    }
+                    // The final assistant turn's calls simply haven't run yet
+                    None if Some(index) == last_conversational => {}
+                    None => match &self.missing_result_placeholder {
+                        Some(placeholder) => {
+                            warn!("Tool call {} has no result, inserting placeholder", call.id);
+                            normalized.push(Message::tool_from_call(call, placeholder.clone()));
+                        }
+                        None => {
+                            return Err(ChatConfigError::MissingToolResult {
+                                tool_call_id: call.id.clone(),
+                            }
+                            .into());
+                        }
+                    },
+                }
+            }
+        }
+
+        if let Some(((_, id), _)) = tool_messages
+            .iter()
+            .zip(claimed.iter())
+            .find(|(_, taken)| !**taken)
+        {
+            return Err(ChatConfigError::OrphanToolResult {
+                tool_call_id: (*id).to_string(),
+            }
+            .into());
+        }
+
+        if normalized != history {
+            debug!("Reordered tool messages in history");
+        }
+
+        Ok(normalized)
+    }
+}
+
+impl Chat {
+    /// Returns a new instance whose history has tool results placed directly
+    /// after their calls
+    ///
+    /// See [`ToolOrderNormalizer`] for the rules applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ChatConfig` if the history can't be repaired.
+    pub fn normalize_tool_order(self) -> Result<Self> {
+        let history = ToolOrderNormalizer::new().normalize(&self.history)?;
+        Ok(self.with_history(history))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::message::{Function, ToolCall};
+
+    fn call(id: &str, name: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: name.to_string(),
+                arguments: "{}".to_string(),
+            },
+        }
+    }
+
+    fn roles(history: &[Message]) -> Vec<&'static str> {
+        history.iter().map(Message::role_str).collect()
+    }
+
+    #[test]
+    fn test_well_ordered_history_is_unchanged() {
+        let history = vec![
+            Message::user("Weather in Paris and Rome?"),
+            Message::assistant_with_tool_calls(vec![call("a", "weather"), call("b", "weather")]),
+            Message::tool("a", "10C"),
+            Message::tool("b", "20C"),
+            Message::assistant("Paris is 10C, Rome is 20C."),
+        ];
+
+        let normalized = ToolOrderNormalizer::new().normalize(&history).unwrap();
+        assert_eq!(normalized, history);
+    }
+
+    #[test]
+    fn test_results_follow_call_order() {
+        let history = vec![
+            Message::user("Weather in Paris and Rome?"),
+            Message::assistant_with_tool_calls(vec![call("a", "weather"), call("b", "weather")]),
+            Message::tool("b", "20C"),
+            Message::user("Any news?"),
+            Message::tool("a", "10C"),
+        ];
+
+        let normalized = ToolOrderNormalizer::new().normalize(&history).unwrap();
+        assert_eq!(
+            roles(&normalized),
+            vec!["user", "assistant", "tool", "tool", "user"]
+        );
+        assert_eq!(normalized[2], Message::tool("a", "10C"));
+        assert_eq!(normalized[3], Message::tool("b", "20C"));
+    }
+
+    #[test]
+    fn test_reused_call_ids_are_claimed_in_order() {
+        let history = vec![
+            Message::user("Weather?"),
+            Message::assistant_with_tool_calls(vec![call("gemini_call_1", "weather")]),
+            Message::tool("gemini_call_1", "10C"),
+            Message::assistant_with_tool_calls(vec![call("gemini_call_1", "forecast")]),
+            Message::tool("gemini_call_1", "rain"),
+        ];
+
+        let normalized = ToolOrderNormalizer::new().normalize(&history).unwrap();
+        assert_eq!(normalized, history);
+    }
+
+    #[test]
+    fn test_orphan_tool_result_is_an_error() {
+        let history = vec![Message::user("Hi"), Message::tool("nope", "10C")];
+
+        let result = ToolOrderNormalizer::new().normalize(&history);
+        assert!(matches!(
+            result,
+            Err(Error::ChatConfig(ChatConfigError::OrphanToolResult { tool_call_id }))
+                if tool_call_id == "nope"
+        ));
+    }
+
+    #[test]
+    fn test_missing_result() {
+        let history = vec![
+            Message::user("Weather?"),
+            Message::assistant_with_tool_calls(vec![call("a", "weather")]),
+            Message::user("Never mind."),
+        ];
+
+        let result = ToolOrderNormalizer::new().normalize(&history);
+        assert!(matches!(
+            result,
+            Err(Error::ChatConfig(ChatConfigError::MissingToolResult { .. }))
+        ));
+
+        let normalized = ToolOrderNormalizer::new()
+            .with_missing_result_placeholder("(cancelled)")
+            .normalize(&history)
+            .unwrap();
+        assert_eq!(
+            roles(&normalized),
+            vec!["user", "assistant", "tool", "user"]
+        );
+        assert_eq!(normalized[2], Message::tool("a", "(cancelled)"));
+
+        // Calls on the last assistant message are pending, not missing
+        let pending = &history[..2];
+        let normalized = ToolOrderNormalizer::new().normalize(pending).unwrap();
+        assert_eq!(normalized, pending);
+    }
+}
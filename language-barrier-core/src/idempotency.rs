@@ -0,0 +1,137 @@
+//! Idempotency keys that survive retries
+//!
+//! OpenAI and Anthropic deduplicate requests carrying the same
+//! `Idempotency-Key` header, so a retry after a dropped connection returns
+//! the original generation instead of running (and billing) a second one.
+//! [`HTTPLlmService`] adds the header for providers that support it (see
+//! [`HTTPProvider::idempotency_header`]); a chat's
+//! [`idempotency_key`](crate::Chat::with_idempotency_key) scope decides
+//! which calls count as the same logical request.
+//!
+//! One level up, a client can resubmit a whole turn, as when a webhook is
+//! retried. User messages given an ID with [`Message::with_client_id`] are
+//! added with [`Chat::add_turn`], which ignores a message already in the
+//! history, and [`Chat::submission`] says whether the turn was answered, so
+//! the reply can be returned again instead of generating a second one.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::idempotency::Submission;
+//! use language_barrier_core::{Chat, Message};
+//!
+//! let turn = Message::user("What's my balance?").with_client_id("msg-7");
+//! let chat = Chat::default().add_turn(turn.clone());
+//! assert_eq!(chat.submission("msg-7"), Some(Submission::Pending));
+//!
+//! let chat = chat.add_message(Message::assistant("$42."));
+//! // The webhook is retried: nothing is added, and the reply is known
+//! let chat = chat.add_turn(turn);
+//! assert_eq!(chat.history.len(), 2);
+//! assert_eq!(
+//!     chat.submission("msg-7"),
+//!     Some(Submission::Answered(Message::assistant("$42.")))
+//! );
+//! ```
+//!
+//! [`HTTPLlmService`]: crate::HTTPLlmService
+//! [`HTTPProvider::idempotency_header`]: crate::provider::HTTPProvider::idempotency_header
+//! [`Message::with_client_id`]: crate::Message::with_client_id
+//! [`Chat::add_turn`]: crate::Chat::add_turn
+//! [`Chat::submission`]: crate::Chat::submission
+
+use uuid::Uuid;
+
+use crate::Message;
+use crate::coalesce::payload_key;
+use crate::transport::HttpRequest;
+
+/// What became of a turn submitted with a client ID
+#[derive(Debug, Clone, PartialEq)]
+pub enum Submission {
+    /// The turn is in the history but has no final reply yet, because its
+    /// generation is running, failed, or stopped at a tool call
+    Pending,
+    /// The turn was answered with this message
+    Answered(Message),
+}
+
+/// Returns a fresh random key, suitable as a chat's idempotency scope
+pub fn new_key() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Returns the key sent with `request` for a chat scoped to `scope`
+///
+/// The key combines the scope with a hash of the request payload, so it is
+/// stable across retries of one request but differs between turns of a
+/// conversation that share a scope.
+pub fn request_idempotency_key(scope: &str, request: &HttpRequest) -> String {
+    format!("{scope}-{:016x}", payload_key(request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Method;
+    use url::Url;
+
+    fn request(body: &str) -> HttpRequest {
+        let url = Url::parse("https://api.example.com/v1/chat").unwrap();
+        HttpRequest {
+            body: body.as_bytes().to_vec(),
+            ..HttpRequest::new(Method::POST, url)
+        }
+    }
+
+    #[test]
+    fn test_keys_follow_scope_and_payload() {
+        let first = request_idempotency_key("scope", &request(r#"{"turn":1}"#));
+        assert_eq!(
+            first,
+            request_idempotency_key("scope", &request(r#"{"turn":1}"#))
+        );
+        assert_ne!(
+            first,
+            request_idempotency_key("scope", &request(r#"{"turn":2}"#))
+        );
+        assert_ne!(
+            first,
+            request_idempotency_key("other", &request(r#"{"turn":1}"#))
+        );
+        assert_ne!(new_key(), new_key());
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_retries_of_a_scoped_chat_reuse_the_key() {
+        use crate::llm_service::{HTTPLlmService, LLMService};
+        use crate::provider::openai::OpenAIProvider;
+        use crate::transport::mock::{MockResponse, MockTransport};
+        use crate::{Chat, Message, OpenAi};
+        use std::sync::Arc;
+
+        let transport =
+            Arc::new(MockTransport::new().with_fallback(MockResponse::status(500, "server error")));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            transport.clone(),
+        );
+        let chat = Chat::default().add_message(Message::user("Hello"));
+        let scoped = chat.clone().with_idempotency_key("turn-1");
+
+        for chat in [&scoped, &scoped, &chat, &chat] {
+            assert!(service.generate_next_message(chat).await.is_err());
+        }
+
+        let keys: Vec<_> = transport
+            .requests()
+            .iter()
+            .map(|request| request.headers["Idempotency-Key"].clone())
+            .collect();
+        assert_eq!(keys[0], keys[1]);
+        assert!(keys[0].to_str().unwrap().starts_with("turn-1-"));
+        assert_ne!(keys[2], keys[3]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Stable IDs for conversations and messages
+//!
+//! Every [`Chat`](crate::Chat) has a conversation ID, and every message
+//! added to one is given a message ID unless it already has one. Message
+//! IDs live in the message's metadata, so they survive serialization and
+//! a round trip through a [`ChatStore`](crate::store::ChatStore); an
+//! external system can use them to refer to one message for feedback, an
+//! edit or a deletion.
+//!
+//! IDs come from an [`IdGenerator`]. The default, [`RandomIds`], makes
+//! random ones; tests use [`SequentialIds`] to get the same IDs on every
+//! run.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use language_barrier_core::ids::SequentialIds;
+//! use language_barrier_core::{Chat, Message};
+//!
+//! let chat = Chat::default()
+//!     .with_id_generator(Arc::new(SequentialIds::new()))
+//!     .add_message(Message::user("Hi"))
+//!     .add_message(Message::assistant("Hello!"));
+//!
+//! assert_eq!(chat.conversation_id, "conv_1");
+//! assert_eq!(chat.history[0].message_id(), Some("msg_2"));
+//! assert_eq!(chat.message("msg_3").unwrap().text_content(), "Hello!");
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use uuid::Uuid;
+
+/// A source of conversation and message IDs
+pub trait IdGenerator: fmt::Debug + Send + Sync {
+    /// Returns an ID for a new conversation
+    fn conversation_id(&self) -> String;
+
+    /// Returns an ID for a new message
+    fn message_id(&self) -> String;
+}
+
+/// Random IDs, unique without coordination between processes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {
+    fn conversation_id(&self) -> String {
+        format!("conv_{}", Uuid::new_v4().simple())
+    }
+
+    fn message_id(&self) -> String {
+        format!("msg_{}", Uuid::new_v4().simple())
+    }
+}
+
+/// IDs numbered from 1, shared between conversations and messages
+///
+/// Only unique within one generator, so meant for tests.
+#[derive(Debug, Default)]
+pub struct SequentialIds {
+    next: AtomicU64,
+}
+
+impl SequentialIds {
+    /// Creates a generator starting at 1
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+impl IdGenerator for SequentialIds {
+    fn conversation_id(&self) -> String {
+        format!("conv_{}", self.next())
+    }
+
+    fn message_id(&self) -> String {
+        format!("msg_{}", self.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{ChatStore, InMemoryChatStore, StoredChat};
+    use crate::{Chat, Message};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_ids_survive_serialization_and_storage() {
+        let chat = Chat::default()
+            .with_id_generator(Arc::new(SequentialIds::new()))
+            .add_message(Message::user("Hi").with_message_id("client-given"))
+            .add_message(Message::assistant("Hello!"));
+        assert_eq!(chat.history[0].message_id(), Some("client-given"));
+        assert_eq!(chat.history[1].message_id(), Some("msg_2"));
+
+        let json = serde_json::to_string(&chat.history[1]).unwrap();
+        let parsed: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.message_id(), Some("msg_2"));
+
+        let store = InMemoryChatStore::new();
+        store.save(&StoredChat::from(&chat)).await.unwrap();
+        let restored = store
+            .load("conv_1")
+            .await
+            .unwrap()
+            .unwrap()
+            .restore(Chat::default());
+        assert_eq!(restored.conversation_id, "conv_1");
+        assert_eq!(restored.message("msg_2"), chat.message("msg_2"));
+    }
+
+    #[test]
+    fn test_generator_keeps_a_given_conversation_id() {
+        let generated = Chat::default().with_id_generator(Arc::new(SequentialIds::new()));
+        assert_eq!(generated.conversation_id, "conv_1");
+
+        let restored = Chat::default()
+            .with_conversation_id("conv_restored")
+            .with_id_generator(Arc::new(SequentialIds::new()))
+            .add_message(Message::user("Hi"));
+        assert_eq!(restored.conversation_id, "conv_restored");
+        assert_eq!(restored.history[0].message_id(), Some("msg_1"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Capturing raw provider traffic for debugging
+//!
+//! Trace logging shows requests as they go out, but only if it was on at
+//! the time. With [`HTTPLlmService::with_raw_capture`], a service keeps the
+//! last request it sent and the response it got, exactly as they crossed
+//! the wire, for [`HTTPLlmService::last_exchange`] to return. Credentials
+//! in headers and the URL are redacted as in audit records, and bodies are
+//! cut to a size limit so a large exchange doesn't stay in memory.
+//!
+//! [`HTTPLlmService::dry_run`] goes the other way: it builds the request a
+//! call would send and returns it as a [`DryRun`] without sending anything,
+//! for checking payloads against golden files in CI or reading a prompt
+//! without paying for it.
+//!
+//! [`HTTPLlmService::dry_run`]: crate::HTTPLlmService::dry_run
+//! [`HTTPLlmService::with_raw_capture`]: crate::HTTPLlmService::with_raw_capture
+//! [`HTTPLlmService::last_exchange`]: crate::HTTPLlmService::last_exchange
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::audit::{AuditRequest, redacted_headers, redacted_url};
+use crate::model::Provider;
+use crate::transport::{HttpRequest, HttpResponse};
+
+/// How many bytes of each body are kept by default
+pub const DEFAULT_BODY_LIMIT: usize = 64 * 1024;
+
+/// One raw request and its response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawExchange {
+    /// The HTTP method
+    pub method: String,
+    /// The URL, with credential query parameters redacted
+    pub url: String,
+    /// The request headers, with credential headers redacted
+    pub request_headers: BTreeMap<String, String>,
+    /// The serialized request body
+    pub request_body: String,
+    /// The response status, or `None` if no response arrived
+    pub status: Option<u16>,
+    /// The response body as received, or `None` if no response arrived
+    pub response_body: Option<String>,
+}
+
+/// A request built but not sent
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DryRun {
+    /// The hosted provider it's for, or `None` for a local or custom endpoint
+    pub provider: Option<Provider>,
+    /// The model, as its `Debug` name
+    pub model: String,
+    /// The request, with credentials redacted
+    pub request: AuditRequest,
+}
+
+/// Keeps the last exchange a service made
+#[derive(Debug, Clone)]
+pub(crate) struct ExchangeCapture {
+    body_limit: usize,
+    last: Arc<Mutex<Option<RawExchange>>>,
+}
+
+impl ExchangeCapture {
+    pub(crate) fn new(body_limit: usize) -> Self {
+        Self {
+            body_limit,
+            last: Arc::default(),
+        }
+    }
+
+    /// Records `request` and what came back for it
+    pub(crate) fn record(&self, request: &HttpRequest, response: Option<&HttpResponse>) {
+        let exchange = RawExchange {
+            method: request.method.to_string(),
+            url: redacted_url(&request.url),
+            request_headers: redacted_headers(&request.headers),
+            request_body: clip(&String::from_utf8_lossy(&request.body), self.body_limit),
+            status: response.map(|response| response.status),
+            response_body: response.map(|response| clip(&response.body, self.body_limit)),
+        };
+        *self
+            .last
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(exchange);
+    }
+
+    pub(crate) fn last(&self) -> Option<RawExchange> {
+        self.last
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+/// Cuts `text` to at most `limit` bytes, noting how much was dropped
+fn clip(text: &str, limit: usize) -> String {
+    if text.len() <= limit {
+        return text.to_string();
+    }
+    let end = (0..=limit)
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    format!("{}... [{} more bytes]", &text[..end], text.len() - end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "gemini")]
+    use crate::filter::{ContentFilter, FilterAction};
+    #[cfg(feature = "gemini")]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "gemini")]
+    use crate::provider::gemini::{GeminiConfig, GeminiProvider};
+    #[cfg(feature = "gemini")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+    #[cfg(feature = "gemini")]
+    use crate::{Chat, Error, Gemini, Message};
+    #[cfg(feature = "gemini")]
+    use serde_json::json;
+
+    #[test]
+    fn test_clip_keeps_char_boundaries() {
+        assert_eq!(clip("héllo", 10), "héllo");
+        assert_eq!(clip("héllo", 2), "h... [5 more bytes]");
+    }
+
+    #[cfg(feature = "gemini")]
+    #[tokio::test]
+    async fn test_last_exchange_is_redacted_and_clipped() {
+        let transport = Arc::new(
+            MockTransport::new().with_response(MockResponse::status(500, "x".repeat(100))),
+        );
+        let service = HTTPLlmService::new_with_transport(
+            Gemini::Flash20,
+            Arc::new(GeminiProvider::with_config(GeminiConfig {
+                api_key: "secret-key".to_string(),
+                ..GeminiConfig::default()
+            })),
+            transport,
+        )
+        .with_raw_capture(40);
+        assert_eq!(service.last_exchange(), None);
+
+        let chat = Chat::default().add_message(Message::user("Hi"));
+        assert!(service.generate_next_message(&chat).await.is_err());
+
+        let exchange = service.last_exchange().unwrap();
+        assert_eq!(exchange.method, "POST");
+        assert!(exchange.url.ends_with("?key=%5BREDACTED%5D"));
+        assert!(exchange.request_body.starts_with("{\"contents\":"));
+        assert_eq!(exchange.status, Some(500));
+        assert_eq!(
+            exchange.response_body.as_deref(),
+            Some(format!("{}... [60 more bytes]", "x".repeat(40)).as_str())
+        );
+    }
+
+    #[cfg(feature = "gemini")]
+    #[test]
+    fn test_dry_run_sends_nothing() {
+        let transport = Arc::new(MockTransport::new());
+        let service = HTTPLlmService::new_with_transport(
+            Gemini::Flash20,
+            Arc::new(GeminiProvider::with_config(GeminiConfig {
+                api_key: "secret-key".to_string(),
+                ..GeminiConfig::default()
+            })),
+            transport.clone(),
+        );
+        let chat = Chat::default().add_message(Message::user("Hi"));
+
+        let dry_run = service.dry_run(&chat).unwrap();
+        assert!(transport.requests().is_empty());
+        assert_eq!(dry_run.provider, Some(Provider::Gemini));
+        assert_eq!(dry_run.model, "Flash20");
+        assert!(dry_run.request.url.ends_with("?key=%5BREDACTED%5D"));
+        assert_eq!(
+            dry_run.request.body.unwrap()["contents"],
+            json!([{ "role": "user", "parts": [{ "text": "Hi" }] }])
+        );
+        assert_eq!(
+            service.dry_run(&chat).unwrap(),
+            service.dry_run(&chat).unwrap()
+        );
+
+        let filter = ContentFilter::new()
+            .with_deny_rule("no-secrets", "secret", FilterAction::Block)
+            .unwrap();
+        let blocked = chat
+            .with_content_filter(filter)
+            .add_message(Message::user("a secret"));
+        assert!(matches!(
+            service.dry_run(&blocked),
+            Err(Error::ContentBlocked { rule }) if rule == "no-secrets"
+        ));
+    }
+}
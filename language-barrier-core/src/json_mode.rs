@@ -0,0 +1,291 @@
+//! Emulated JSON mode
+//!
+//! Not every model can be constrained to a schema natively (see
+//! [`ModelCapability::JsonMode`]). When a chat with an output schema goes to
+//! one that can't, `HTTPLlmService` emulates it instead of failing: the
+//! schema is put in the system prompt, the reply is checked against it with
+//! [`validate`], and a reply that doesn't match is sent back with the error
+//! for another try, up to the service's retry limit (see
+//! [`HTTPLlmService::with_schema_retries`]). Callers get the same JSON text
+//! either way, so [`Chat::with_output_schema`] works on every model.
+//!
+//! [`validate`] understands the common subset of JSON Schema: `type`,
+//! `enum`, `const`, `properties`, `required`, `additionalProperties` and
+//! `items`. Other keywords are ignored.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::chat::OutputSchema;
+//! use language_barrier_core::json_mode::validate;
+//! use serde_json::json;
+//!
+//! let schema = OutputSchema::choice(["approve", "reject"]).schema;
+//! assert!(validate(&schema, &json!({ "choice": "approve" })).is_ok());
+//! assert_eq!(
+//!     validate(&schema, &json!({ "choice": "maybe" })).unwrap_err(),
+//!     r#"choice: "maybe" is not one of ["approve","reject"]"#
+//! );
+//! ```
+//!
+//! [`ModelCapability::JsonMode`]: crate::ModelCapability::JsonMode
+//! [`HTTPLlmService::with_schema_retries`]: crate::HTTPLlmService::with_schema_retries
+//! [`Chat::with_output_schema`]: crate::Chat::with_output_schema
+
+use serde_json::Value;
+
+use crate::chat::{Chat, OutputSchema, SystemSegment};
+use crate::message::{Content, Message};
+
+/// The tag of the system segment holding the emulated schema instructions
+pub const SEGMENT_TAG: &str = "output_schema";
+
+/// Checks `value` against `schema`, returning the first mismatch
+///
+/// # Errors
+///
+/// Returns a description of the first part of `value` that doesn't match,
+/// prefixed with its path.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), String> {
+    validate_at(schema, value, "")
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    let fail = |reason: String| {
+        Err(if path.is_empty() {
+            reason
+        } else {
+            format!("{path}: {reason}")
+        })
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            return fail(format!("expected {}, found {value}", types.join(" or ")));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return fail(format!(
+            "{value} is not one of {}",
+            Value::from(allowed.clone())
+        ));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != value
+    {
+        return fail(format!("expected {expected}, found {value}"));
+    }
+
+    if let Value::Object(fields) = value {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for name in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !fields.contains_key(name) {
+                return fail(format!("missing required field {name:?}"));
+            }
+        }
+        for (name, field) in fields {
+            let field_path = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{path}.{name}")
+            };
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(field_schema) => validate_at(field_schema, field, &field_path)?,
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        return fail(format!("unexpected field {name:?}"));
+                    }
+                    Some(extra) if extra.is_object() => validate_at(extra, field, &field_path)?,
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            validate_at(item_schema, item, &format!("{path}[{index}]"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Returns `chat` with its output schema moved into the system prompt
+pub(crate) fn emulated_chat(chat: &Chat, output: &OutputSchema) -> Chat {
+    let schema = serde_json::to_string_pretty(&output.schema).unwrap_or_default();
+    let instructions = format!(
+        "Reply with only a JSON value matching this JSON schema, without any other text \
+         or code fences:\n{schema}"
+    );
+    let mut emulated = chat.clone();
+    emulated.output_schema = None;
+    emulated.with_system_segment(SystemSegment::new(instructions).with_tag(SEGMENT_TAG))
+}
+
+/// Checks a reply against `output`, returning it with its text reduced to
+/// the JSON
+pub(crate) fn check_reply(output: &OutputSchema, reply: Message) -> Result<Message, String> {
+    let text = reply.text_content();
+    let json = extract_json(&text).ok_or_else(|| "the reply isn't JSON".to_string())?;
+    let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    validate(&output.schema, &value)?;
+
+    Ok(match reply {
+        Message::Assistant {
+            tool_calls,
+            metadata,
+            ..
+        } => Message::Assistant {
+            content: Some(Content::Text(json.to_string())),
+            tool_calls,
+            metadata,
+        },
+        other => other,
+    })
+}
+
+/// Returns the message asking the model to fix a reply that didn't match
+pub(crate) fn correction(reason: &str) -> Message {
+    Message::user(format!(
+        "That reply doesn't match the schema ({reason}). Reply again with only the JSON."
+    ))
+}
+
+/// Finds the JSON in a reply, which models like to wrap in prose or fences
+fn extract_json(text: &str) -> Option<&str> {
+    let start = text.find(['{', '['])?;
+    let end = text.rfind(['}', ']'])?;
+    (start < end).then(|| &text[start..=end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "openai")]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "openai")]
+    use crate::provider::openai::OpenAIProvider;
+    #[cfg(feature = "openai")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+    #[cfg(feature = "openai")]
+    use crate::{Error, OpenAi};
+    use serde_json::json;
+    #[cfg(feature = "openai")]
+    use std::sync::Arc;
+
+    #[cfg(feature = "openai")]
+    fn reply(content: &str) -> MockResponse {
+        MockResponse::ok(
+            json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o-audio-preview",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+        )
+    }
+
+    #[test]
+    fn test_validate_reports_the_path() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "items": { "type": "array", "items": { "type": "integer" } }
+            },
+            "required": ["items"],
+            "additionalProperties": false
+        });
+        assert!(validate(&schema, &json!({ "items": [1, 2] })).is_ok());
+        assert_eq!(
+            validate(&schema, &json!({ "items": [1, "2"] })).unwrap_err(),
+            r#"items[1]: expected integer, found "2""#
+        );
+        assert_eq!(
+            validate(&schema, &json!({})).unwrap_err(),
+            r#"missing required field "items""#
+        );
+        assert_eq!(
+            validate(&schema, &json!({ "items": [], "extra": 1 })).unwrap_err(),
+            r#"unexpected field "extra""#
+        );
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_models_without_json_mode_retry_until_valid() {
+        let transport = Arc::new(MockTransport::new().with_responses([
+            reply("I'd say approve!"),
+            reply("```json\n{\"choice\": \"approve\"}\n```"),
+        ]));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4oAudio,
+            Arc::new(OpenAIProvider::default()),
+            transport.clone(),
+        );
+        let chat = Chat::default()
+            .with_choice_output(["approve", "reject"])
+            .add_message(Message::user("Refund the damaged parcel?"));
+
+        let message = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(message.text_content(), r#"{"choice": "approve"}"#);
+
+        let requests = transport.requests();
+        let first: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert!(first.get("response_format").is_none());
+        assert!(
+            first["messages"][0]["content"]
+                .as_str()
+                .unwrap()
+                .contains("\"enum\"")
+        );
+        let second: Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(second["messages"].as_array().unwrap().len(), 4);
+
+        let transport = Arc::new(MockTransport::new().with_fallback(reply("no")));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4oAudio,
+            Arc::new(OpenAIProvider::default()),
+            transport.clone(),
+        )
+        .with_schema_retries(1);
+        assert!(matches!(
+            service.generate_next_message(&chat).await,
+            Err(Error::InvalidStructuredOutput(_))
+        ));
+        assert_eq!(transport.requests().len(), 2);
+    }
+}
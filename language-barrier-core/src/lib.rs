@@ -1,24 +1,87 @@
 // This is the main library file that re-exports the public API
 // and defines the module structure.
 
+pub mod alias;
+pub mod app_info;
+#[cfg(any(test, feature = "proptest"))]
+pub mod arbitrary;
+pub mod audit;
+pub mod auth;
+pub mod batch;
+pub mod billing;
+pub mod capabilities;
 pub mod chat;
+pub mod clock;
+pub mod coalesce;
 pub mod compactor;
+pub mod compression;
+pub mod consistency;
 pub mod error;
+pub mod feedback;
+pub mod filter;
+pub mod graceful;
+pub mod health;
+pub mod history;
+pub mod idempotency;
+pub mod ids;
+pub mod injection;
+pub mod inspect;
+pub mod json_mode;
+pub mod lifecycle;
+pub mod limit_state;
+pub mod locale;
+pub mod logprobs;
+pub mod memory;
 pub mod message;
+pub mod minify;
 pub mod model;
+pub mod moderation;
+pub mod prefill;
+pub mod preflight;
+pub mod priority;
+pub mod profile;
+pub mod prompts;
 pub mod provider;
+pub mod rate_limit;
+#[cfg(feature = "realtime")]
+pub mod realtime;
+pub mod reload;
+pub mod replay;
+pub mod salvage;
 pub mod secret;
+pub mod signing;
+pub mod speculate;
+pub mod store;
+pub mod tenancy;
 pub mod token;
 pub mod tool;
+#[cfg(feature = "tools")]
+pub mod tool_emulation;
+pub mod trace_context;
+pub mod transport;
+pub mod usage;
 
 // Re-export the main types for convenient usage
-pub use chat::Chat;
-pub use compactor::{ChatHistoryCompactor, DropOldestCompactor};
-pub use error::{Error, Result, ToolError};
-pub use llm_service::{HTTPLlmService, LLMService};
-pub use message::{Content, Message, ToolCall};
-pub use model::{Claude, Gemini, Mistral, ModelInfo, OpenAi};
+pub use app_info::{AppInfo, set_app_info};
+pub use batch::BatchExecutor;
+pub use chat::{Chat, ChatBuilder, SystemSegment};
+pub use compactor::{
+    ChatHistoryCompactor, CompositeCompactor, DropOldestCompactor, SlidingWindowCompactor,
+};
+pub use compression::PromptCompressor;
+pub use error::{
+    ApiError, ApiErrorKind, ChatConfigError, Error, ProviderConfigError, QuotaPeriod, QuotaViolation,
+    Result, ToolError,
+};
+pub use history::ToolOrderNormalizer;
+pub use llm_service::{HTTPLlmService, HttpClientConfig, LLMService};
+pub use message::{Content, FinishReason, Message, ToolCall};
+pub use model::{AnyModel, Claude, Gemini, Mistral, ModelCapability, ModelInfo, OpenAi};
+pub use profile::GenerationProfile;
 pub use secret::Secret;
 pub use token::TokenCounter;
-pub use tool::{LlmToolInfo, Tool, ToolDefinition};
+pub use tool::LlmToolInfo;
+#[cfg(feature = "tools")]
+pub use tool::{Tool, ToolDefinition};
+pub use usage::{Latency, Pricing, StreamTiming, Usage};
 pub mod llm_service;
@@ -0,0 +1,334 @@
+//! Model deprecation and retirement
+//!
+//! Providers deprecate models months before retiring them, after which
+//! requests for them fail. [`ModelInfo::lifecycle`] reports the dates for
+//! each model, and `HTTPLlmService` acts on them: it logs a warning the
+//! first time a deprecated model is used, and refuses to call a retired one
+//! with `Error::ModelRetired` rather than sending a request that will fail
+//! in a less obvious way.
+//!
+//! The built-in dates (see [`BUILT_IN`]) are a snapshot. Announcements made
+//! since can be applied at startup with [`register_lifecycle`] or from a
+//! JSON feed with [`load_lifecycles`], keyed by model ID.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::lifecycle::{Date, load_lifecycles};
+//! use language_barrier_core::{ModelInfo, OpenAi};
+//!
+//! load_lifecycles(r#"{
+//!     "gpt-4-turbo": { "deprecated_on": "2026-01-15", "retires_on": "2099-07-01", "replacement": "gpt-4o" }
+//! }"#)
+//! .unwrap();
+//!
+//! let lifecycle = OpenAi::GPT4Turbo.lifecycle();
+//! assert!(lifecycle.is_deprecated(Date::new(2026, 3, 1)));
+//! assert!(!lifecycle.is_retired(Date::new(2026, 3, 1)));
+//! assert_eq!(lifecycle.replacement.as_deref(), Some("gpt-4o"));
+//! ```
+//!
+//! [`ModelInfo::lifecycle`]: crate::ModelInfo::lifecycle
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::model::ModelInfo;
+
+static LIFECYCLES: RwLock<Option<HashMap<String, ModelLifecycle>>> = RwLock::new(None);
+static WARNED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// A calendar date, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Date {
+    year: u16,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    /// Creates a date
+    ///
+    /// # Panics
+    ///
+    /// Panics if `month` or `day` is out of range.
+    pub const fn new(year: u16, month: u8, day: u8) -> Self {
+        assert!(month >= 1 && month <= 12 && day >= 1 && day <= 31);
+        Self { year, month, day }
+    }
+
+    /// Returns the current date
+    pub fn today() -> Self {
+        Self::at(SystemTime::now())
+    }
+
+    /// Returns the date (UTC) at `time`
+    pub fn at(time: SystemTime) -> Self {
+        let days = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() / 86_400);
+        Self::from_days(i64::try_from(days).unwrap_or(i64::MAX))
+    }
+
+    /// Converts days since 1970-01-01 to a date
+    fn from_days(days: i64) -> Self {
+        // Howard Hinnant's civil_from_days
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year: u16::try_from(year).unwrap_or(u16::MAX),
+            month: u8::try_from(month).unwrap_or(1),
+            day: u8::try_from(day).unwrap_or(1),
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("invalid date {s:?}, expected YYYY-MM-DD");
+        let mut parts = s.splitn(3, '-');
+        let mut next = || parts.next().ok_or_else(invalid);
+        let (year, month, day) = (next()?, next()?, next()?);
+
+        let year = year.parse().map_err(|_| invalid())?;
+        let month: u8 = month.parse().map_err(|_| invalid())?;
+        let day: u8 = day.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(invalid());
+        }
+        Ok(Self { year, month, day })
+    }
+}
+
+impl TryFrom<String> for Date {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Date> for String {
+    fn from(date: Date) -> Self {
+        date.to_string()
+    }
+}
+
+/// When a model is deprecated and retired
+///
+/// A deprecated model still works but will be retired; a retired model no
+/// longer works at all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelLifecycle {
+    /// When the provider announced the model's retirement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated_on: Option<Date>,
+    /// When requests for the model start failing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retires_on: Option<Date>,
+    /// The model ID the provider recommends moving to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+}
+
+impl ModelLifecycle {
+    /// A model with no announced retirement
+    pub const ACTIVE: Self = Self {
+        deprecated_on: None,
+        retires_on: None,
+        replacement: None,
+    };
+
+    /// Returns true if the model is deprecated or retired on `date`
+    pub fn is_deprecated(&self, date: Date) -> bool {
+        self.deprecated_on.is_some_and(|on| on <= date) || self.is_retired(date)
+    }
+
+    /// Returns true if the model is retired on `date`
+    pub fn is_retired(&self, date: Date) -> bool {
+        self.retires_on.is_some_and(|on| on <= date)
+    }
+}
+
+/// The built-in lifecycles, by model ID
+pub const BUILT_IN: &[(&str, Date, Date, &str)] = &[
+    (
+        "claude-3-5-sonnet-20240620",
+        Date::new(2025, 8, 13),
+        Date::new(2025, 10, 22),
+        "claude-3-7-sonnet-latest",
+    ),
+    (
+        "claude-3-5-sonnet-20241022",
+        Date::new(2025, 8, 13),
+        Date::new(2025, 10, 22),
+        "claude-3-7-sonnet-latest",
+    ),
+    (
+        "claude-3-opus-latest",
+        Date::new(2025, 6, 30),
+        Date::new(2026, 1, 5),
+        "claude-3-7-sonnet-latest",
+    ),
+    (
+        "o1-mini-2024-09-12",
+        Date::new(2025, 4, 28),
+        Date::new(2025, 10, 27),
+        "o3-mini-2025-01-31",
+    ),
+    (
+        "gemini-1.5-flash",
+        Date::new(2025, 4, 29),
+        Date::new(2025, 9, 24),
+        "gemini-2.0-flash",
+    ),
+    (
+        "gemini-2.5-flash-preview-04-17",
+        Date::new(2025, 6, 17),
+        Date::new(2025, 7, 15),
+        "gemini-2.5-flash",
+    ),
+];
+
+/// Sets the lifecycle of the model with `id`, replacing any built-in one
+pub fn register_lifecycle(id: impl Into<String>, lifecycle: ModelLifecycle) {
+    LIFECYCLES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(id.into(), lifecycle);
+}
+
+/// Registers every lifecycle in a JSON object mapping model IDs to lifecycles
+///
+/// # Errors
+///
+/// Returns `Error::Serialization` if `json` isn't such an object; no
+/// lifecycles are registered in that case.
+pub fn load_lifecycles(json: &str) -> Result<()> {
+    let lifecycles: HashMap<String, ModelLifecycle> = serde_json::from_str(json)?;
+    for (id, lifecycle) in lifecycles {
+        register_lifecycle(id, lifecycle);
+    }
+    Ok(())
+}
+
+/// Looks up the lifecycle of the model with `id`
+///
+/// Models with no registered or built-in lifecycle are
+/// [`ModelLifecycle::ACTIVE`].
+pub fn lifecycle(id: &str) -> ModelLifecycle {
+    let registered = LIFECYCLES
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .and_then(|lifecycles| lifecycles.get(id).cloned());
+
+    registered.unwrap_or_else(|| {
+        BUILT_IN.iter().find(|(model, ..)| *model == id).map_or(
+            ModelLifecycle::ACTIVE,
+            |(_, deprecated, retires, replacement)| ModelLifecycle {
+                deprecated_on: Some(*deprecated),
+                retires_on: Some(*retires),
+                replacement: Some((*replacement).to_string()),
+            },
+        )
+    })
+}
+
+/// Fails if `model` is retired on `today`, warning once per model if it's
+/// deprecated
+pub(crate) fn check_model<M: ModelInfo>(model: &M, today: Date) -> Result<()> {
+    let lifecycle = model.lifecycle();
+    let name = format!("{model:?}");
+
+    if let Some(retired_on) = lifecycle.retires_on.filter(|_| lifecycle.is_retired(today)) {
+        return Err(Error::ModelRetired {
+            model: name,
+            retired_on: retired_on.to_string(),
+            replacement: lifecycle.replacement,
+        });
+    }
+
+    if lifecycle.is_deprecated(today) {
+        let first = WARNED
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get_or_insert_with(HashSet::new)
+            .insert(name.clone());
+        if first {
+            let retires = lifecycle
+                .retires_on
+                .map_or_else(|| "soon".to_string(), |on| format!("on {on}"));
+            let replacement = lifecycle
+                .replacement
+                .map(|id| format!("; consider {id}"))
+                .unwrap_or_default();
+            warn!(
+                "Model {} is deprecated and will be retired {}{}",
+                name, retires, replacement
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Claude, Gemini};
+
+    #[test]
+    fn test_dates_parse_and_convert() {
+        assert_eq!("2025-10-22".parse::<Date>(), Ok(Date::new(2025, 10, 22)));
+        assert!("2025-13-01".parse::<Date>().is_err());
+        assert!("tomorrow".parse::<Date>().is_err());
+        assert_eq!(Date::from_days(0), Date::new(1970, 1, 1));
+        assert_eq!(Date::from_days(20_742), Date::new(2026, 10, 16));
+        assert_eq!(Date::from_days(11_016), Date::new(2000, 2, 29));
+    }
+
+    #[test]
+    fn test_retired_models_are_refused() {
+        assert!(matches!(
+            check_model(&Claude::Opus3, Date::today()),
+            Err(Error::ModelRetired { replacement: Some(id), .. }) if id == "claude-3-7-sonnet-latest"
+        ));
+        assert!(check_model(&Claude::Haiku35, Date::today()).is_ok());
+
+        register_lifecycle(
+            "gemini-2.0-flash-lite",
+            ModelLifecycle {
+                deprecated_on: Some(Date::new(2020, 1, 1)),
+                ..ModelLifecycle::ACTIVE
+            },
+        );
+        assert!(Gemini::Flash20Lite.lifecycle().is_deprecated(Date::today()));
+        assert!(check_model(&Gemini::Flash20Lite, Date::today()).is_ok());
+    }
+}
@@ -0,0 +1,249 @@
+//! Keeping rate-limit state across restarts
+//!
+//! A [`PriorityLimiter`](crate::priority::PriorityLimiter) remembers which
+//! calls started inside its window, and a
+//! [`HealthMonitor`](crate::health::HealthMonitor) remembers which providers
+//! failed and when they were last probed. Both live in memory, so after a
+//! deploy every process starts with an empty window and healthy providers,
+//! and sends a burst of calls at a provider that was rate-limiting or down a
+//! moment ago.
+//!
+//! Give either one a [`LimitStore`] and a key, and it loads its state from
+//! the store on its first call and saves it after every call it lets
+//! through. Times are stored as wall-clock times, so a restarted process
+//! picks up the rest of the window and the rest of a provider's cool-down.
+//! A store that fails is logged and otherwise ignored: losing the state is
+//! what happened without one.
+//!
+//! Implement `LimitStore` to keep the state in Redis or a database;
+//! [`FileLimitStore`] keeps it in a directory and [`InMemoryLimitStore`] in
+//! the process.
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use language_barrier_core::health::HealthMonitor;
+//! use language_barrier_core::limit_state::FileLimitStore;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::priority::PriorityLimiter;
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::OpenAi;
+//!
+//! let store = Arc::new(FileLimitStore::new(std::env::temp_dir().join("limits")));
+//!
+//! let service = HTTPLlmService::new(OpenAi::GPT4oMini, Arc::new(OpenAIProvider::new()));
+//! let limited = PriorityLimiter::new(service, 500, Duration::from_secs(60))
+//!     .with_store(store.clone(), "openai-limiter");
+//! let monitor = HealthMonitor::new().with_store(store, "provider-health");
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::error::Result;
+
+/// Where limiters and health monitors keep their state
+///
+/// State is JSON, stored under the key the limiter or monitor was given.
+#[async_trait]
+pub trait LimitStore: Send + Sync {
+    /// Returns the state stored under `key`, if there is any
+    async fn load(&self, key: &str) -> Result<Option<serde_json::Value>>;
+
+    /// Stores `state` under `key`, replacing what was there
+    async fn save(&self, key: &str, state: &serde_json::Value) -> Result<()>;
+}
+
+/// A limit store held in process memory
+///
+/// Useful for tests, and for sharing state between limiters that are
+/// rebuilt without restarting the process, such as on a config reload.
+#[derive(Debug, Default)]
+pub struct InMemoryLimitStore {
+    states: Mutex<HashMap<String, serde_json::Value>>,
+}
+
+impl InMemoryLimitStore {
+    /// Creates an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl LimitStore for InMemoryLimitStore {
+    async fn load(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let states = self
+            .states
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(states.get(key).cloned())
+    }
+
+    async fn save(&self, key: &str, state: &serde_json::Value) -> Result<()> {
+        self.states
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(key.to_string(), state.clone());
+        Ok(())
+    }
+}
+
+/// A limit store that keeps each key's state in `<key>.json` in a directory
+///
+/// Keys must be valid file names. Each save writes a temporary file and
+/// renames it over the old one, so a crash mid-save leaves the previous
+/// state.
+#[derive(Debug, Clone)]
+pub struct FileLimitStore {
+    dir: PathBuf,
+}
+
+impl FileLimitStore {
+    /// Keeps state in `dir`, which is created on the first save
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the directory the state is kept in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+#[async_trait]
+impl LimitStore for FileLimitStore {
+    async fn load(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, key: &str, state: &serde_json::Value) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.path(key);
+        let temporary = self.dir.join(format!("{key}.json.tmp"));
+        tokio::fs::write(&temporary, serde_json::to_vec(state)?).await?;
+        tokio::fs::rename(&temporary, &path).await?;
+        Ok(())
+    }
+}
+
+/// A limiter's or monitor's link to its store
+pub(crate) struct Persistence {
+    store: Arc<dyn LimitStore>,
+    key: String,
+    restored: OnceCell<()>,
+}
+
+impl fmt::Debug for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Persistence")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Persistence {
+    pub(crate) fn new(store: Arc<dyn LimitStore>, key: impl Into<String>) -> Self {
+        Self {
+            store,
+            key: key.into(),
+            restored: OnceCell::new(),
+        }
+    }
+
+    /// Loads the stored state and passes it to `apply`, the first time
+    /// only
+    pub(crate) async fn restore<T: DeserializeOwned>(&self, apply: impl FnOnce(T)) {
+        self.restored
+            .get_or_init(|| async {
+                match self.store.load(&self.key).await {
+                    Ok(Some(state)) => match serde_json::from_value(state) {
+                        Ok(state) => {
+                            debug!("Restored rate-limit state {}", self.key);
+                            apply(state);
+                        }
+                        Err(e) => warn!("Ignoring unreadable rate-limit state {}: {}", self.key, e),
+                    },
+                    Ok(None) => {}
+                    Err(e) => warn!("Couldn't load rate-limit state {}: {}", self.key, e),
+                }
+            })
+            .await;
+    }
+
+    /// Stores `state`, logging any failure
+    pub(crate) async fn save<T: Serialize>(&self, state: &T) {
+        let result = match serde_json::to_value(state) {
+            Ok(state) => self.store.save(&self.key, &state).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            warn!("Couldn't save rate-limit state {}: {}", self.key, e);
+        }
+    }
+}
+
+/// Converts a monotonic time to the wall-clock time it stands for
+pub(crate) fn wall_clock(instant: Instant) -> SystemTime {
+    SystemTime::now() - Instant::now().saturating_duration_since(instant)
+}
+
+/// Converts a stored wall-clock time back to a monotonic one, if it isn't
+/// older than the process's clock can represent
+pub(crate) fn monotonic(time: SystemTime) -> Option<Instant> {
+    let age = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or(Duration::ZERO);
+    Instant::now().checked_sub(age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store_round_trips_and_misses_unknown_keys() {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("limits-{}-{nanos}", std::process::id()));
+        let store = FileLimitStore::new(&dir);
+
+        assert!(store.load("limiter").await.unwrap().is_none());
+        store
+            .save("limiter", &serde_json::json!({"recent": [1]}))
+            .await
+            .unwrap();
+        store
+            .save("limiter", &serde_json::json!({"recent": [1, 2]}))
+            .await
+            .unwrap();
+        let state = store.load("limiter").await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(state, Some(serde_json::json!({"recent": [1, 2]})));
+    }
+}
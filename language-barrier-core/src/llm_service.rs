@@ -1,9 +1,33 @@
 use async_trait::async_trait;
 use reqwest::Client;
 use std::sync::Arc;
-use tracing::{debug, error, info, trace};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::{Chat, Message, ModelInfo, Result, provider::HTTPProvider};
+use crate::app_info::app_info;
+use crate::audit::{AuditRecord, AuditRequest, AuditSink, redacted_url};
+use crate::chat::OutputSchema;
+use crate::clock::{Clock, SystemClock};
+use crate::coalesce::{RequestCoalescer, request_key};
+use crate::error::{ApiError, ApiErrorKind};
+use crate::filter::{FilterEvent, content_filter};
+use crate::idempotency::{new_key, request_idempotency_key};
+use crate::inspect::{DryRun, ExchangeCapture, RawExchange};
+use crate::json_mode;
+use crate::lifecycle::{Date, check_model};
+use crate::locale::Locale;
+use crate::model::ModelCapability;
+use crate::prefill;
+use crate::preflight::{CLOCK_SKEW_TOLERANCE, Preflight, PreflightCheck, clock_skew};
+use crate::prompts::PromptRef;
+use crate::salvage::Continuation;
+use crate::trace_context;
+#[cfg(feature = "tools")]
+use crate::tool_emulation;
+use crate::transport::rewrite::{RequestRewriter, RewritingTransport};
+use crate::transport::{HeaderValue, HttpRequest, HttpResponse, ReqwestTransport, Transport};
+use crate::usage::{Latency, Pricing};
+use crate::{Chat, Error, Message, ModelInfo, Result, provider::HTTPProvider};
 
 /// This is anything that can generate the next message.
 ///
@@ -16,6 +40,24 @@ pub trait LLMService<M: ModelInfo> {
     ///
     /// Takes a Chat instance and returns a Result containing the next message.
     async fn generate_next_message(&self, chat: &Chat) -> Result<Message>;
+
+    /// How this service asks the model to continue a cut-off reply
+    fn continuation(&self) -> Continuation {
+        Continuation::Prompt
+    }
+}
+
+/// A shared reference to a service is itself a service, so helpers that take
+/// a service by value (like `BatchExecutor`) can borrow one instead.
+#[async_trait]
+impl<M: ModelInfo, S: LLMService<M> + Sync> LLMService<M> for &S {
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        (**self).generate_next_message(chat).await
+    }
+
+    fn continuation(&self) -> Continuation {
+        (**self).continuation()
+    }
 }
 
 /// An LLM service implementation that sends requests over HTTP.
@@ -24,9 +66,19 @@ pub trait LLMService<M: ModelInfo> {
 /// It replaces the previous SingleRequestExecutor, providing the same functionality
 /// but with a more flexible trait-based design.
 ///
+/// Every response is stamped with its creation time (see [`Message::created_at`])
+/// and the request's [`Latency`] (see [`Message::latency`]), and, for chats
+/// built from a prompt template, the prompt version (see [`Message::prompt`]).
+/// Calls to a retired model fail with `Error::ModelRetired` before anything
+/// is sent; see [`lifecycle`](crate::lifecycle). Output schemas are emulated
+/// on models without a native JSON mode; see [`json_mode`](crate::json_mode).
+/// Replies to chats with a moderation are checked before they're returned;
+/// see [`moderation`](crate::moderation).
+///
 /// # Examples
 ///
-/// ```no_run
+#[cfg_attr(feature = "anthropic", doc = "```no_run")]
+#[cfg_attr(not(feature = "anthropic"), doc = "```ignore")]
 /// use language_barrier_core::{Chat, Message, model::Claude};
 /// use language_barrier_core::llm_service::{HTTPLlmService, LLMService};
 /// use language_barrier_core::provider::anthropic::AnthropicProvider;
@@ -39,7 +91,7 @@ pub trait LLMService<M: ModelInfo> {
 ///
 ///     // Create a service with the model and provider
 ///     let service = HTTPLlmService::new(
-///         Claude::Opus3,
+///         Claude::Haiku35,
 ///         Arc::new(provider)
 ///     );
 ///
@@ -56,27 +108,652 @@ pub trait LLMService<M: ModelInfo> {
 pub struct HTTPLlmService<M: ModelInfo> {
     model: M,
     provider: Arc<dyn HTTPProvider<M>>,
+    transport: Arc<dyn Transport>,
+    coalescer: Option<RequestCoalescer>,
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    pricing: Option<Pricing>,
+    schema_retries: usize,
+    #[cfg(feature = "tools")]
+    tool_emulation: bool,
+    capture: Option<ExchangeCapture>,
+    clock: Arc<dyn Clock>,
 }
 
 impl<M: ModelInfo> HTTPLlmService<M> {
+    /// How many times an emulated JSON reply is retried by default
+    pub const DEFAULT_SCHEMA_RETRIES: usize = 2;
+
+    /// Creates a service with its own default HTTP client
+    ///
+    /// The client (and its connection pool) is reused for every request this
+    /// service makes.
     pub fn new(model: M, provider: Arc<dyn HTTPProvider<M>>) -> Self {
-        HTTPLlmService { model, provider }
+        Self::new_with_client(model, provider, Client::new())
+    }
+
+    /// Creates a service that sends requests through an existing client
+    ///
+    /// `reqwest::Client` is cheap to clone and clones share one connection
+    /// pool, so passing the same client to the services for several models
+    /// or providers avoids re-establishing connections at high concurrency.
+    /// Use [`HttpClientConfig`] to tune the pool.
+    pub fn new_with_client(model: M, provider: Arc<dyn HTTPProvider<M>>, client: Client) -> Self {
+        Self::new_with_transport(model, provider, Arc::new(ReqwestTransport::from(client)))
+    }
+
+    /// Creates a service that sends requests through a custom [`Transport`]
+    ///
+    /// Use this to run providers over an HTTP stack other than `reqwest`, or
+    /// to answer requests from a stub in tests.
+    pub fn new_with_transport(
+        model: M,
+        provider: Arc<dyn HTTPProvider<M>>,
+        transport: Arc<dyn Transport>,
+    ) -> Self {
+        HTTPLlmService {
+            model,
+            provider,
+            transport,
+            coalescer: None,
+            audit_sink: None,
+            pricing: None,
+            schema_retries: Self::DEFAULT_SCHEMA_RETRIES,
+            #[cfg(feature = "tools")]
+            tool_emulation: false,
+            capture: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Shares in-flight calls among identical concurrent requests
+    ///
+    /// Requests are keyed by a hash of the provider payload, credentials and
+    /// tenant, so two chats coalesce only if they produce exactly the same
+    /// request for the same caller. Pass clones of one [`RequestCoalescer`]
+    /// to several services to coalesce across them.
+    pub fn with_coalescer(self, coalescer: RequestCoalescer) -> Self {
+        Self {
+            coalescer: Some(coalescer),
+            ..self
+        }
+    }
+
+    /// Records every provider call this service makes to `sink`
+    ///
+    /// Records hold the request with API keys redacted, the response or
+    /// error, usage, latency and, if [`with_pricing`](Self::with_pricing)
+    /// was set, cost. A sink that fails to write is logged and doesn't fail
+    /// the call.
+    pub fn with_audit_sink(self, sink: Arc<dyn AuditSink>) -> Self {
+        Self {
+            audit_sink: Some(sink),
+            ..self
+        }
+    }
+
+    /// Rewrites every request just before it's sent, e.g. to route it
+    /// through an API gateway
+    ///
+    /// Wraps the service's transport in a [`RewritingTransport`]. Audit
+    /// records show the request as the provider built it, before rewriting.
+    pub fn with_request_rewriter(self, rewriter: impl RequestRewriter + 'static) -> Self {
+        Self {
+            transport: Arc::new(RewritingTransport::new(self.transport.clone(), rewriter)),
+            ..self
+        }
+    }
+
+    /// Sets the model's prices, used to cost audit records
+    pub fn with_pricing(self, pricing: Pricing) -> Self {
+        Self {
+            pricing: Some(pricing),
+            ..self
+        }
+    }
+
+    /// Sets how many times a reply that doesn't match an emulated output
+    /// schema is sent back for another try
+    ///
+    /// Only applies to models without native JSON mode; see
+    /// [`json_mode`](crate::json_mode).
+    pub fn with_schema_retries(self, retries: usize) -> Self {
+        Self {
+            schema_retries: retries,
+            ..self
+        }
+    }
+
+    /// Lets models without native function calling call the chat's tools
+    /// through the prompt
+    ///
+    /// Without this, a chat with tools fails on such models with
+    /// [`Error::UnsupportedCapability`]. See
+    /// [`tool_emulation`](crate::tool_emulation).
+    #[cfg(feature = "tools")]
+    pub fn with_tool_emulation(self) -> Self {
+        Self {
+            tool_emulation: true,
+            ..self
+        }
+    }
+
+    /// Keeps the raw request and response of the last call for
+    /// [`last_exchange`](Self::last_exchange)
+    ///
+    /// Each body is cut to `body_limit` bytes;
+    /// [`DEFAULT_BODY_LIMIT`](crate::inspect::DEFAULT_BODY_LIMIT) suits most
+    /// chats. See [`inspect`](crate::inspect).
+    pub fn with_raw_capture(self, body_limit: usize) -> Self {
+        Self {
+            capture: Some(ExchangeCapture::new(body_limit)),
+            ..self
+        }
+    }
+
+    /// Takes the time from `clock` instead of the system clock
+    ///
+    /// The clock stamps responses' creation times and audit records, and
+    /// gives the date model retirement is checked against. See
+    /// [`clock`](crate::clock).
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Returns the last request this service sent and the response it got,
+    /// if raw capture is on and a request has been sent
+    ///
+    /// The request is shown before any
+    /// [`with_request_rewriter`](Self::with_request_rewriter) rewriting.
+    /// Coalesced calls that shared another call's response sent nothing and
+    /// aren't captured.
+    pub fn last_exchange(&self) -> Option<RawExchange> {
+        self.capture.as_ref().and_then(ExchangeCapture::last)
+    }
+
+    /// Builds the request a call with `chat` would send, without sending it
+    ///
+    /// Screening and tool or JSON emulation apply as for a real call; a
+    /// blocked chat fails with [`Error::ContentBlocked`]. The credentials
+    /// are redacted, and no auth provider is asked for a token and no
+    /// idempotency key is generated, so the same chat always gives the same
+    /// result. See [`inspect`](crate::inspect).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error a real call would fail with before sending.
+    pub fn dry_run(&self, chat: &Chat) -> Result<DryRun> {
+        check_model(&self.model, Date::at(self.clock.now()))?;
+
+        #[cfg(feature = "tools")]
+        let emulated = self
+            .emulates_tools(chat)
+            .then(|| tool_emulation::emulated_chat(chat));
+        #[cfg(feature = "tools")]
+        let chat = emulated.as_ref().unwrap_or(chat);
+        let emulated = self
+            .emulated_output(chat)
+            .map(|output| json_mode::emulated_chat(chat, output));
+        let chat = emulated.as_ref().unwrap_or(chat);
+
+        let (mut request, _, blocked) = self.prepare(chat)?;
+        if let Some(rule) = blocked {
+            return Err(Error::ContentBlocked { rule });
+        }
+        if let Some(app) = app_info() {
+            app.apply(&mut request, self.provider.sends_attribution_headers());
+        }
+
+        Ok(DryRun {
+            provider: self.provider.provider(),
+            model: format!("{:?}", self.model),
+            request: AuditRequest::redacted(&request),
+        })
+    }
+
+    /// Checks that the provider is reachable, accepts this service's
+    /// credentials, and agrees with our clock
+    ///
+    /// Sends the provider's [`preflight_request`], authenticated and signed
+    /// as a real call would be, through this service's transport. Never
+    /// fails; problems are listed in the result. See
+    /// [`preflight`](crate::preflight).
+    ///
+    /// [`preflight_request`]: HTTPProvider::preflight_request
+    pub async fn preflight(&self) -> PreflightCheck {
+        let mut check = PreflightCheck {
+            provider: self.provider.provider(),
+            model: format!("{:?}", self.model),
+            ..PreflightCheck::default()
+        };
+        if let Err(e) = check_model(&self.model, Date::at(self.clock.now())) {
+            check.problems.push(e.to_string());
+        }
+
+        let mut request = match self.provider.preflight_request() {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                debug!("Provider has no preflight request, skipping");
+                return check;
+            }
+            Err(e) => {
+                check
+                    .problems
+                    .push(format!("Couldn't build the check request: {e}"));
+                return check;
+            }
+        };
+        let endpoint = redacted_url(&request.url);
+        check.endpoint = Some(endpoint.clone());
+
+        if let Some(auth) = self.provider.auth() {
+            let authorized = match auth.credential().await {
+                Ok(credential) => self.provider.authorize(&mut request, &credential),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = authorized {
+                check.credentials_valid = Some(false);
+                check
+                    .problems
+                    .push(format!("Couldn't get credentials: {e}"));
+                return check;
+            }
+        }
+        trace_context::propagate(&mut request);
+        if let Some(signer) = self.provider.signer()
+            && let Err(e) = signer.sign(&mut request)
+        {
+            check
+                .problems
+                .push(format!("Couldn't sign the request: {e}"));
+            return check;
+        }
+
+        let started = Instant::now();
+        let response = match self.transport.send(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                check.reachable = Some(false);
+                check
+                    .problems
+                    .push(format!("Couldn't reach {endpoint}: {e}"));
+                return check;
+            }
+        };
+        check.reachable = Some(true);
+        check.latency_ms = Some(started.elapsed().as_millis() as u64);
+
+        match response.status {
+            200..=299 => check.credentials_valid = Some(true),
+            401 | 403 => {
+                check.credentials_valid = Some(false);
+                check.problems.push(format!(
+                    "Credentials were rejected (HTTP {})",
+                    response.status
+                ));
+            }
+            404 => check
+                .problems
+                .push(format!("{endpoint} was not found; check the base URL")),
+            status => check
+                .problems
+                .push(format!("Unexpected HTTP {status} from {endpoint}")),
+        }
+
+        check.clock_skew_secs = clock_skew(&response.headers, self.clock.now());
+        if let Some(skew) = check.clock_skew_secs
+            && skew.unsigned_abs() > CLOCK_SKEW_TOLERANCE.as_secs()
+        {
+            warn!("Local clock is {}s off from the provider's", skew);
+            check
+                .problems
+                .push(format!("Local clock is {skew}s off from the provider's"));
+        }
+        check
+    }
+}
+
+/// Connection pool and keep-alive settings for the HTTP client
+///
+/// Every setting left unset keeps reqwest's default. Build one client from
+/// this config and share it between services with
+/// [`HTTPLlmService::new_with_client`].
+///
+/// # Examples
+///
+#[cfg_attr(feature = "anthropic", doc = "```")]
+#[cfg_attr(not(feature = "anthropic"), doc = "```ignore")]
+/// use language_barrier_core::llm_service::{HTTPLlmService, HttpClientConfig};
+/// use language_barrier_core::model::Claude;
+/// use language_barrier_core::provider::anthropic::AnthropicProvider;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let client = HttpClientConfig::new()
+///     .with_pool_max_idle_per_host(64)
+///     .with_pool_idle_timeout(Duration::from_secs(30))
+///     .with_tcp_keepalive(Duration::from_secs(60))
+///     .build()
+///     .unwrap();
+///
+/// let provider = Arc::new(AnthropicProvider::new());
+/// let opus = HTTPLlmService::new_with_client(Claude::Opus3, provider.clone(), client.clone());
+/// let haiku = HTTPLlmService::new_with_client(Claude::Haiku3, provider, client);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct HttpClientConfig {
+    /// Maximum idle connections kept per host
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long an idle pooled connection is kept before being closed
+    pub pool_idle_timeout: Option<Duration>,
+    /// Speak HTTP/2 without negotiating it first
+    pub http2_prior_knowledge: bool,
+    /// Interval between HTTP/2 keep-alive pings
+    pub http2_keep_alive_interval: Option<Duration>,
+    /// Interval between TCP keep-alive probes
+    pub tcp_keepalive: Option<Duration>,
+    /// Timeout for establishing a new connection
+    pub connect_timeout: Option<Duration>,
+}
+
+impl HttpClientConfig {
+    /// Creates a config that keeps all of reqwest's defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of idle connections kept per host
+    #[must_use]
+    pub fn with_pool_max_idle_per_host(self, max: usize) -> Self {
+        Self {
+            pool_max_idle_per_host: Some(max),
+            ..self
+        }
+    }
+
+    /// Sets how long idle pooled connections are kept alive
+    #[must_use]
+    pub fn with_pool_idle_timeout(self, timeout: Duration) -> Self {
+        Self {
+            pool_idle_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Uses HTTP/2 for every connection without negotiation
+    ///
+    /// Only enable this if every provider endpoint the client talks to
+    /// supports HTTP/2; all major hosted providers do.
+    #[must_use]
+    pub fn with_http2_prior_knowledge(self) -> Self {
+        Self {
+            http2_prior_knowledge: true,
+            ..self
+        }
+    }
+
+    /// Sends HTTP/2 keep-alive pings at the given interval
+    #[must_use]
+    pub fn with_http2_keep_alive_interval(self, interval: Duration) -> Self {
+        Self {
+            http2_keep_alive_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Sends TCP keep-alive probes at the given interval
+    #[must_use]
+    pub fn with_tcp_keepalive(self, interval: Duration) -> Self {
+        Self {
+            tcp_keepalive: Some(interval),
+            ..self
+        }
+    }
+
+    /// Limits how long establishing a new connection may take
+    #[must_use]
+    pub fn with_connect_timeout(self, timeout: Duration) -> Self {
+        Self {
+            connect_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Builds a client with these settings
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Request` if the client can't be initialized, for
+    /// example when the TLS backend fails to load.
+    pub fn build(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(interval) = self.http2_keep_alive_interval {
+            builder = builder.http2_keep_alive_interval(interval);
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        debug!("Building HTTP client with {:?}", self);
+        Ok(builder.build()?)
     }
 }
 
 #[async_trait]
 impl<M: ModelInfo> LLMService<M> for HTTPLlmService<M> {
     async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
-        let client = Client::new();
+        // Fail fast rather than paying for a request the provider will reject
+        check_model(&self.model, Date::at(self.clock.now()))?;
+
+        #[cfg(feature = "tools")]
+        let reply = if self.emulates_tools(chat) {
+            debug!("Emulating tool calls for {:?}", self.model);
+            let reply = self
+                .generate_structured(&tool_emulation::emulated_chat(chat))
+                .await?;
+            tool_emulation::parse_reply(reply)
+        } else {
+            self.generate_structured(chat).await?
+        };
+        #[cfg(not(feature = "tools"))]
+        let reply = self.generate_structured(chat).await?;
+
+        match &chat.moderation {
+            Some(moderation) => moderation.review(reply).await,
+            None => Ok(reply),
+        }
+    }
+
+    fn continuation(&self) -> Continuation {
+        self.provider.continuation()
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo> Preflight for HTTPLlmService<M> {
+    async fn preflight(&self) -> PreflightCheck {
+        HTTPLlmService::preflight(self).await
+    }
+}
+
+impl<M: ModelInfo> HTTPLlmService<M> {
+    /// Whether the chat's tools are called through the prompt
+    #[cfg(feature = "tools")]
+    fn emulates_tools(&self, chat: &Chat) -> bool {
+        self.tool_emulation
+            && !self.model.supports(ModelCapability::Tools)
+            && chat.tools.as_ref().is_some_and(|tools| !tools.is_empty())
+    }
+
+    /// The output schema to emulate JSON mode for, if the model lacks it
+    fn emulated_output<'a>(&self, chat: &'a Chat) -> Option<&'a OutputSchema> {
+        chat.output_schema
+            .as_ref()
+            .filter(|_| !self.model.supports(ModelCapability::JsonMode))
+    }
+
+    /// Generates a reply, emulating JSON mode if the model lacks it
+    async fn generate_structured(&self, chat: &Chat) -> Result<Message> {
+        match self.emulated_output(chat) {
+            Some(output) => self.generate_emulated_json(chat, output).await,
+            None => self.generate(chat).await,
+        }
+    }
+
+    /// Builds, sends and records one request for `chat`
+    async fn generate(&self, chat: &Chat) -> Result<Message> {
+        let (mut request, filter_events, blocked) = self.prepare(chat)?;
+
+        if let Some(rule) = blocked {
+            warn!("Content filter rule {} blocked the request", rule);
+            let result = Err(Error::ContentBlocked { rule });
+            self.audit(chat, AuditRequest::redacted(&request), &result, filter_events)
+                .await;
+            return result;
+        }
+
+        if let Some(preparer) = self.provider.preparer() {
+            preparer.prepare(&mut request).await.inspect_err(|e| {
+                error!("Failed to prepare request: {}", e);
+            })?;
+        }
+
+        if let Some(auth) = self.provider.auth() {
+            let credential = auth.credential().await.inspect_err(|e| {
+                error!("Failed to get credentials: {}", e);
+            })?;
+            self.provider.authorize(&mut request, &credential)?;
+        }
+
+        if let Some(app) = app_info() {
+            app.apply(&mut request, self.provider.sends_attribution_headers());
+        }
+        trace_context::propagate(&mut request);
+
+        if let Some(header) = self.provider.idempotency_header() {
+            let key = match &chat.idempotency_key {
+                Some(scope) => request_idempotency_key(scope, &request),
+                None => new_key(),
+            };
+            match HeaderValue::from_str(&key) {
+                Ok(value) => {
+                    request.headers.insert(header, value);
+                }
+                Err(e) => warn!("Skipping invalid idempotency key {:?}: {}", key, e),
+            }
+        }
+
+        let signer = self.provider.signer();
+        let key = self
+            .coalescer
+            .as_ref()
+            .map(|_| request_key(&request, chat.tenant.as_deref(), signer.as_ref()));
+        if let Some(signer) = signer {
+            signer.sign(&mut request).inspect_err(|e| {
+                error!("Failed to sign request: {}", e);
+            })?;
+        }
+
+        let audited = self
+            .audit_sink
+            .as_ref()
+            .map(|_| AuditRequest::redacted(&request));
+
+        let result = match (&self.coalescer, key) {
+            (Some(coalescer), Some(key)) => coalescer.run(key, || self.send(request)).await,
+            _ => self.send(request).await,
+        };
+        let prefill = self.provider.supports_prefill();
+        let result = match &chat.assistant_prefix {
+            Some(prefix) => result.map(|msg| prefill::complete_reply(msg, prefix, prefill)),
+            None => result,
+        };
+        let result = match &chat.prompt {
+            Some(prompt) => {
+                result.map(|msg| msg.with_metadata(PromptRef::METADATA_KEY, prompt.to_metadata()))
+            }
+            None => result,
+        };
+        let result = match &chat.locale {
+            Some(locale) => {
+                result.map(|msg| msg.with_metadata(Locale::METADATA_KEY, locale.to_metadata()))
+            }
+            None => result,
+        };
+
+        if let Some(audited) = audited {
+            self.audit(chat, audited, &result, filter_events).await;
+        }
+
+        result
+    }
+
+    /// Screens `chat` and builds the unauthenticated request for it,
+    /// returning the filter events and the rule that blocked it, if any
+    fn prepare(&self, chat: &Chat) -> Result<(HttpRequest, Vec<FilterEvent>, Option<String>)> {
+        if let Err(e) = chat.check_capabilities(&self.model) {
+            error!("Chat is not compatible with model: {}", e);
+            return Err(e);
+        }
+
+        // Screen user messages before they're serialized. A blocked chat is
+        // still built, with the offending text replaced, so the audit log
+        // can show what was stopped.
+        let screened = chat
+            .content_filter
+            .clone()
+            .or_else(content_filter)
+            .map(|filter| filter.screen(chat));
+        let chat = screened.as_ref().map_or(chat, |screened| &screened.chat);
+        let prefixed = chat
+            .assistant_prefix
+            .as_deref()
+            .map(|prefix| prefill::request_chat(chat, prefix, self.provider.supports_prefill()));
+        let chat = prefixed.as_ref().unwrap_or(chat);
+        let minified = chat
+            .schema_minifier
+            .as_ref()
+            .filter(|_| chat.tools.is_some())
+            .map(|_| {
+                let mut minified = chat.clone();
+                minified.tools = Some(chat.sent_tools());
+                minified
+            });
+        let chat = minified.as_ref().unwrap_or(chat);
+        let (mut filter_events, mut blocked) = screened
+            .as_ref()
+            .map(|screened| (screened.events.clone(), screened.blocked.clone()))
+            .unwrap_or_default();
+
+        // Tool results are checked for injected instructions the same way
+        if let Some(detector) = &chat.injection_detector {
+            for event in detector.scan(chat) {
+                warn!(
+                    "Possible prompt injection in message {}: {}",
+                    event.message_index, event.rule
+                );
+                if event.blocked && blocked.is_none() {
+                    blocked = Some(event.rule.clone());
+                }
+                filter_events.push(event);
+            }
+        }
 
         let request = match self.provider.accept(self.model, chat) {
             Ok(req) => {
-                debug!(
-                    "Request created successfully: {} {}",
-                    req.method(),
-                    req.url()
-                );
-                trace!("Request headers: {:#?}", req.headers());
+                debug!("Request created successfully: {} {}", req.method, req.url);
+                trace!("Request headers: {:#?}", req.headers);
                 req
             }
             Err(e) => {
@@ -85,36 +762,124 @@ impl<M: ModelInfo> LLMService<M> for HTTPLlmService<M> {
             }
         };
 
+        Ok((request, filter_events, blocked))
+    }
+
+    /// Generates a reply matching `output` on a model without JSON mode
+    async fn generate_emulated_json(&self, chat: &Chat, output: &OutputSchema) -> Result<Message> {
+        debug!("Emulating JSON mode for {:?}", self.model);
+        let mut attempt = json_mode::emulated_chat(chat, output);
+        let mut retries = 0;
+        loop {
+            let reply = self.generate(&attempt).await?;
+            let reason = match json_mode::check_reply(output, reply.clone()) {
+                Ok(message) => return Ok(message),
+                Err(reason) => reason,
+            };
+            if retries == self.schema_retries {
+                error!("Reply still doesn't match the schema: {}", reason);
+                return Err(Error::InvalidStructuredOutput(reason));
+            }
+            retries += 1;
+            warn!(
+                "Reply doesn't match the schema ({}), retry {} of {}",
+                reason, retries, self.schema_retries
+            );
+            attempt = attempt
+                .add_message(reply)
+                .add_message(json_mode::correction(&reason));
+        }
+    }
+
+    /// Writes a call to the audit sink, if there is one
+    async fn audit(
+        &self,
+        chat: &Chat,
+        request: AuditRequest,
+        result: &Result<Message>,
+        filter_events: Vec<FilterEvent>,
+    ) {
+        let Some(sink) = &self.audit_sink else {
+            return;
+        };
+        let model = format!("{:?}", self.model);
+        let record = AuditRecord::new(model, request, result, self.pricing.as_ref())
+            .at(self.clock.now())
+            .with_tenant(chat.tenant.clone())
+            .with_filter_events(filter_events);
+        if let Err(e) = sink.record(&record).await {
+            warn!("Failed to write audit record: {}", e);
+        }
+    }
+
+    /// Builds the error for a non-2xx response
+    ///
+    /// The status code gives a first classification; the provider's parser,
+    /// which knows its error format, refines it and supplies the message.
+    fn api_error(&self, response: HttpResponse) -> Error {
+        let error = ApiError::new(response.status, &response.headers, response.body)
+            .with_provider(self.provider.provider());
+        let error = self.provider.error_details(error);
+        let error = match self.provider.parse(error.body.clone()) {
+            Err(Error::RateLimit(message)) => error
+                .with_kind(ApiErrorKind::RateLimit)
+                .with_message(message),
+            Err(Error::Overloaded(message)) => error
+                .with_kind(ApiErrorKind::Overloaded)
+                .with_message(message),
+            Err(Error::Authentication(message)) => error
+                .with_kind(ApiErrorKind::Authentication)
+                .with_message(message),
+            Err(Error::ContextLengthExceeded(message)) => error
+                .with_kind(ApiErrorKind::ContextLengthExceeded)
+                .with_message(message),
+            Err(Error::ProviderUnavailable(message)) => error.with_message(message),
+            _ => error,
+        };
+        error!("Provider returned an error: {}", error);
+        Error::Api(Box::new(error))
+    }
+
+    /// Sends a prepared request and parses the provider's response
+    async fn send(&self, request: HttpRequest) -> Result<Message> {
         // Send request and get response
         debug!("Sending HTTP request");
-        let response = match client.execute(request).await {
+        let started = Instant::now();
+        let captured = self
+            .capture
+            .as_ref()
+            .map(|capture| (capture, request.clone()));
+        let response = self.transport.send(request).await;
+        if let Some((capture, request)) = captured {
+            capture.record(&request, response.as_ref().ok());
+        }
+        let response = match response {
             Ok(resp) => {
-                info!("Received response with status: {}", resp.status());
-                trace!("Response headers: {:#?}", resp.headers());
+                info!("Received response with status: {}", resp.status);
+                trace!("Response body: {}", resp.body);
                 resp
             }
             Err(e) => {
                 error!("HTTP request failed: {}", e);
-                return Err(e.into());
+                return Err(e);
             }
         };
 
-        // Get response text
-        debug!("Reading response body");
-        let response_text = match response.text().await {
-            Ok(text) => {
-                trace!("Response body: {}", text);
-                text
-            }
-            Err(e) => {
-                error!("Failed to read response body: {}", e);
-                return Err(e.into());
-            }
-        };
+        if !(200..300).contains(&response.status) {
+            warn!("Provider responded with status {}", response.status);
+            return Err(self.api_error(response));
+        }
+
+        let total = started.elapsed();
+        let latency = Latency::new(response.time_to_first_byte.unwrap_or(total), total);
+        debug!(
+            "Request took {:?} ({:?} to first byte)",
+            latency.total, latency.time_to_first_byte
+        );
 
         // Parse response using provider
         debug!("Parsing response");
-        let message = match self.provider.parse(response_text) {
+        let message = match self.provider.parse(response.body) {
             Ok(msg) => {
                 info!("Successfully parsed response into message");
                 debug!("Message role: {}", msg.role_str());
@@ -127,6 +892,8 @@ impl<M: ModelInfo> LLMService<M> for HTTPLlmService<M> {
             }
         };
 
-        Ok(message)
+        Ok(message
+            .with_metadata(Latency::METADATA_KEY, latency.to_metadata())
+            .with_created_at(self.clock.now()))
     }
 }
@@ -0,0 +1,251 @@
+//! Conversation locale
+//!
+//! [`Chat::with_locale`] tells the model which language to reply in and how
+//! to write numbers and dates, through a system segment tagged
+//! [`Locale::SEGMENT_TAG`], so multilingual products don't keep their own
+//! prompt snippet per language. Replies generated from the chat record the
+//! locale under [`Locale::METADATA_KEY`] (see [`Message::locale`]).
+//!
+//! Common locales come with formatting conventions (see [`BUILT_IN`]); any
+//! other BCP 47 tag works too, with formats given explicitly or left to the
+//! model.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::Chat;
+//! use language_barrier_core::locale::Locale;
+//!
+//! let chat = Chat::default().with_locale("de-DE");
+//! assert_eq!(
+//!     chat.system_text(),
+//!     "Reply in German (de-DE) unless the user asks for another language. \
+//!      Write numbers like 1.234,56 and dates like 31.12.2025."
+//! );
+//!
+//! let chat = Chat::default().with_locale(Locale::new("sv-SE").with_date_format("2025-12-31"));
+//! assert!(chat.system_text().starts_with("Reply in Swedish (sv-SE)"));
+//! ```
+//!
+//! [`Chat::with_locale`]: crate::Chat::with_locale
+//! [`Message::locale`]: crate::Message::locale
+
+use serde::{Deserialize, Serialize};
+
+/// Built-in locales: tag, number example and date example
+pub const BUILT_IN: &[(&str, &str, &str)] = &[
+    ("en-US", "1,234.56", "12/31/2025"),
+    ("en-GB", "1,234.56", "31/12/2025"),
+    ("de-DE", "1.234,56", "31.12.2025"),
+    ("fr-FR", "1 234,56", "31/12/2025"),
+    ("es-ES", "1.234,56", "31/12/2025"),
+    ("it-IT", "1.234,56", "31/12/2025"),
+    ("nl-NL", "1.234,56", "31-12-2025"),
+    ("pt-BR", "1.234,56", "31/12/2025"),
+    ("ja-JP", "1,234.56", "2025/12/31"),
+    ("zh-CN", "1,234.56", "2025-12-31"),
+    ("ko-KR", "1,234.56", "2025. 12. 31."),
+];
+
+/// Names of common languages, by ISO 639-1 code
+const LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("hi", "Hindi"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nb", "Norwegian"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+/// The language and formatting conventions of a conversation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locale {
+    /// The BCP 47 language tag, such as `de-DE`
+    pub tag: String,
+    /// An example of how to write numbers, such as `1.234,56`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number_format: Option<String>,
+    /// An example of how to write dates, such as `31.12.2025`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_format: Option<String>,
+}
+
+impl Locale {
+    /// The message metadata key under which the chat's locale is stored
+    pub const METADATA_KEY: &'static str = "locale";
+
+    /// The tag of the system segment holding the locale instructions
+    pub const SEGMENT_TAG: &'static str = "locale";
+
+    /// Creates a locale, with the built-in formats if `tag` has them
+    pub fn new(tag: impl Into<String>) -> Self {
+        let tag = tag.into();
+        let built_in = BUILT_IN
+            .iter()
+            .find(|(known, ..)| known.eq_ignore_ascii_case(&tag));
+        Self {
+            number_format: built_in.map(|(_, number, _)| (*number).to_string()),
+            date_format: built_in.map(|(.., date)| (*date).to_string()),
+            tag,
+        }
+    }
+
+    /// Sets how to write numbers, by example, and returns a new instance
+    #[must_use]
+    pub fn with_number_format(self, example: impl Into<String>) -> Self {
+        Self {
+            number_format: Some(example.into()),
+            ..self
+        }
+    }
+
+    /// Sets how to write dates, by example, and returns a new instance
+    #[must_use]
+    pub fn with_date_format(self, example: impl Into<String>) -> Self {
+        Self {
+            date_format: Some(example.into()),
+            ..self
+        }
+    }
+
+    /// Returns the English name of the tag's language, if it's a common one
+    pub fn language(&self) -> Option<&'static str> {
+        let code = self.tag.split(['-', '_']).next()?;
+        LANGUAGES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(code))
+            .map(|(_, name)| *name)
+    }
+
+    /// Returns the instructions given to the model
+    pub fn instructions(&self) -> String {
+        let language = match self.language() {
+            Some(name) => format!("{name} ({})", self.tag),
+            None => format!("the language with tag {}", self.tag),
+        };
+        let mut text = format!("Reply in {language} unless the user asks for another language.");
+
+        let formats: Vec<String> = [
+            self.number_format
+                .as_ref()
+                .map(|n| format!("numbers like {n}")),
+            self.date_format.as_ref().map(|d| format!("dates like {d}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !formats.is_empty() {
+            text.push_str(&format!(" Write {}.", formats.join(" and ")));
+        }
+        text
+    }
+
+    /// Converts this locale into a metadata value
+    #[must_use]
+    pub fn to_metadata(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl From<&str> for Locale {
+    fn from(tag: &str) -> Self {
+        Self::new(tag)
+    }
+}
+
+impl From<String> for Locale {
+    fn from(tag: String) -> Self {
+        Self::new(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "openai")]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "openai")]
+    use crate::provider::openai::OpenAIProvider;
+    #[cfg(feature = "openai")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use crate::Chat;
+    #[cfg(feature = "openai")]
+    use crate::{Message, OpenAi};
+    #[cfg(feature = "openai")]
+    use std::sync::Arc;
+
+    #[test]
+    fn test_unknown_tags_and_replacement() {
+        let locale = Locale::new("tlh");
+        assert_eq!(locale.language(), None);
+        assert_eq!(
+            locale.instructions(),
+            "Reply in the language with tag tlh unless the user asks for another language."
+        );
+        assert_eq!(Locale::new("pt-PT").language(), Some("Portuguese"));
+        assert_eq!(Locale::new("pt-PT").number_format, None);
+
+        let chat = Chat::default()
+            .with_system_prompt("You are a travel agent.")
+            .with_locale("fr-FR")
+            .with_locale("ja-JP");
+        assert_eq!(chat.system_segments.len(), 1);
+        assert!(chat.system_text().contains("Japanese (ja-JP)"));
+        assert_eq!(
+            chat.locale.unwrap().date_format.as_deref(),
+            Some("2025/12/31")
+        );
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_replies_record_the_locale() {
+        let reply = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hallo!" },
+                "finish_reason": "stop"
+            }]
+        });
+        let transport =
+            Arc::new(MockTransport::new().with_response(MockResponse::ok(reply.to_string())));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            transport.clone(),
+        );
+        let chat = Chat::default()
+            .with_locale("de-DE")
+            .add_message(Message::user("Hi"));
+
+        let message = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(message.locale(), Some(Locale::new("de-DE")));
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&transport.requests()[0].body).unwrap();
+        assert!(
+            body["messages"][0]["content"]
+                .as_str()
+                .unwrap()
+                .starts_with("Reply in German")
+        );
+    }
+}
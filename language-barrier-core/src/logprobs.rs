@@ -0,0 +1,134 @@
+//! Token log probabilities and classification confidence
+//!
+//! Chats configured with [`Chat::with_logprobs`] ask the provider for the log
+//! probability of every token it generates. OpenAI returns them, and they are
+//! recorded on the reply under [`TokenLogprob::METADATA_KEY`]; other
+//! providers ignore the setting.
+//!
+//! For a chat with a choice output, [`Message::classification`] turns the
+//! logprobs of the chosen label's tokens into a confidence between 0 and 1:
+//! the probability the model gave to writing that label.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::Message;
+//! use language_barrier_core::logprobs::TokenLogprob;
+//!
+//! let tokens = [(r#"{"choice":""#, 0.0), ("esc", -0.1), ("alate", -0.05), (r#""}"#, 0.0)];
+//! let logprobs: Vec<TokenLogprob> = tokens
+//!     .iter()
+//!     .map(|(token, logprob)| TokenLogprob::new(*token, *logprob))
+//!     .collect();
+//! let reply = Message::assistant(r#"{"choice":"escalate"}"#)
+//!     .with_metadata(TokenLogprob::METADATA_KEY, serde_json::to_value(&logprobs).unwrap());
+//!
+//! let classification = reply.classification::<String>().unwrap();
+//! assert_eq!(classification.label, "escalate");
+//! assert!((classification.confidence.unwrap() - (-0.15f64).exp()).abs() < 1e-9);
+//! ```
+//!
+//! [`Chat::with_logprobs`]: crate::Chat::with_logprobs
+//! [`Message::classification`]: crate::Message::classification
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// The log probability of one generated token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    /// The token's text
+    pub token: String,
+    /// The natural log of the token's probability
+    pub logprob: f64,
+    /// The most likely tokens at this position, including this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+impl TokenLogprob {
+    /// The message metadata key holding a reply's token logprobs
+    pub const METADATA_KEY: &'static str = "logprobs";
+
+    /// Creates a token logprob with no alternatives
+    #[must_use]
+    pub fn new(token: impl Into<String>, logprob: f64) -> Self {
+        Self {
+            token: token.into(),
+            logprob,
+            top_logprobs: Vec::new(),
+        }
+    }
+
+    /// Returns the token's probability
+    pub fn probability(&self) -> f64 {
+        self.logprob.exp()
+    }
+}
+
+/// An alternative token the model could have generated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopLogprob {
+    /// The token's text
+    pub token: String,
+    /// The natural log of the token's probability
+    pub logprob: f64,
+}
+
+/// A typed label with the model's confidence in it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification<T> {
+    /// The label the model chose
+    pub label: T,
+    /// The probability the model gave to the label, from 0 to 1
+    ///
+    /// `None` when the reply carries no logprobs, as with providers that
+    /// don't return them.
+    pub confidence: Option<f64>,
+}
+
+/// Returns the probability of the tokens spelling `label` as the string
+/// value of `field` in the JSON the tokens make up
+pub(crate) fn label_confidence(logprobs: &[TokenLogprob], field: &str, label: &str) -> Option<f64> {
+    let text: String = logprobs.iter().map(|t| t.token.as_str()).collect();
+    let after_key = text.find(&format!("\"{field}\""))? + field.len() + 2;
+    let start = after_key + text[after_key..].find(&format!("\"{label}\""))? + 1;
+    span_confidence(logprobs, start..start + label.len())
+}
+
+/// Returns the joint probability of the tokens overlapping a byte span of
+/// their concatenated text
+fn span_confidence(logprobs: &[TokenLogprob], span: Range<usize>) -> Option<f64> {
+    let mut offset = 0;
+    let mut total = None;
+    for token in logprobs {
+        let range = offset..offset + token.token.len();
+        offset = range.end;
+        if range.start < span.end && span.start < range.end {
+            *total.get_or_insert(0.0) += token.logprob;
+        }
+    }
+    total.map(|logprob: f64| logprob.exp().clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_the_label_tokens_count() {
+        let logprobs = [
+            TokenLogprob::new("{\"", -0.3),
+            TokenLogprob::new("choice", -0.2),
+            TokenLogprob::new("\":\"", 0.0),
+            TokenLogprob::new("rej", -0.5),
+            TokenLogprob::new("ect\"}", -0.25),
+        ];
+        let confidence = label_confidence(&logprobs, "choice", "reject").unwrap();
+        assert!((confidence - (-0.75f64).exp()).abs() < 1e-9);
+
+        assert_eq!(label_confidence(&logprobs, "choice", "approve"), None);
+        assert_eq!(label_confidence(&logprobs, "verdict", "reject"), None);
+    }
+}
@@ -0,0 +1,427 @@
+//! Long-term memory across conversations
+//!
+//! [`ConversationMemory`] keeps salient facts about a user (or any other
+//! scope) in a [`MemoryStore`]. After each turn, a [`MemoryExtractor`],
+//! usually a cheap model behind [`LlmMemoryExtractor`], pulls durable facts
+//! out of the exchange and stores them under short keys, so a later fact
+//! with the same key replaces an earlier one. Before each turn, the memories
+//! most relevant to the user's latest message are added to the chat as a
+//! system segment tagged [`MEMORY_TAG`].
+//!
+//! Wrap a service in a [`MemoryService`] to do both around every call.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::memory::{InMemoryStore, Memory, MemoryStore};
+//!
+//! # tokio_test::block_on(async {
+//! let store = InMemoryStore::new();
+//! store
+//!     .upsert("user-42", vec![
+//!         Memory::new("home_city", "Lives in Lisbon"),
+//!         Memory::new("diet", "Is vegetarian"),
+//!     ])
+//!     .await
+//!     .unwrap();
+//!
+//! let found = store.search("user-42", "Any vegetarian places near me?", 5).await.unwrap();
+//! assert_eq!(found, vec![Memory::new("diet", "Is vegetarian")]);
+//! # });
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::chat::SystemSegment;
+use crate::error::Result;
+use crate::llm_service::LLMService;
+use crate::salvage::Continuation;
+use crate::{Chat, Message, ModelInfo};
+
+/// The tag of the system segment recalled memories are added as
+pub const MEMORY_TAG: &str = "memory";
+
+/// One remembered fact
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Memory {
+    /// A short identifier; storing a memory replaces any with the same key
+    pub key: String,
+    /// The fact itself
+    pub text: String,
+}
+
+impl Memory {
+    /// Creates a memory
+    pub fn new(key: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            text: text.into(),
+        }
+    }
+}
+
+/// Where memories are kept
+///
+/// Memories are grouped by scope, typically a user or tenant ID, and never
+/// shared between scopes. Implement this to keep memories in a database or
+/// a vector index; [`InMemoryStore`] is the default.
+#[async_trait]
+pub trait MemoryStore: Send + Sync {
+    /// Stores `memories`, replacing existing ones with the same keys
+    async fn upsert(&self, scope: &str, memories: Vec<Memory>) -> Result<()>;
+
+    /// Returns up to `limit` memories relevant to `query`, most relevant first
+    async fn search(&self, scope: &str, query: &str, limit: usize) -> Result<Vec<Memory>>;
+
+    /// Removes the memory with `key`, returning whether there was one
+    async fn forget(&self, scope: &str, key: &str) -> Result<bool>;
+}
+
+/// A memory store held in process memory
+///
+/// Relevance is the number of words a memory shares with the query,
+/// ignoring case and words shorter than three letters. Ties go to the more
+/// recently stored memory, and memories sharing no words are never returned.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    scopes: Mutex<HashMap<String, Vec<Memory>>>,
+}
+
+impl InMemoryStore {
+    /// Creates an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn scopes(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<Memory>>> {
+        self.scopes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl MemoryStore for InMemoryStore {
+    async fn upsert(&self, scope: &str, memories: Vec<Memory>) -> Result<()> {
+        let mut scopes = self.scopes();
+        let stored = scopes.entry(scope.to_string()).or_default();
+        for memory in memories {
+            stored.retain(|existing| existing.key != memory.key);
+            stored.push(memory);
+        }
+        Ok(())
+    }
+
+    async fn search(&self, scope: &str, query: &str, limit: usize) -> Result<Vec<Memory>> {
+        let scopes = self.scopes();
+        let Some(stored) = scopes.get(scope) else {
+            return Ok(Vec::new());
+        };
+
+        let query = words(query);
+        let mut scored: Vec<(usize, usize, &Memory)> = stored
+            .iter()
+            .enumerate()
+            .map(|(index, memory)| {
+                let text = format!("{} {}", memory.key.replace('_', " "), memory.text);
+                (words(&text).intersection(&query).count(), index, memory)
+            })
+            .filter(|(score, _, _)| *score > 0)
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+
+        Ok(scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, _, memory)| memory.clone())
+            .collect())
+    }
+
+    async fn forget(&self, scope: &str, key: &str) -> Result<bool> {
+        let mut scopes = self.scopes();
+        let Some(stored) = scopes.get_mut(scope) else {
+            return Ok(false);
+        };
+        let before = stored.len();
+        stored.retain(|memory| memory.key != key);
+        Ok(stored.len() < before)
+    }
+}
+
+/// Lowercased words of at least three letters
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Pulls facts worth remembering out of a conversation turn
+#[async_trait]
+pub trait MemoryExtractor: Send + Sync {
+    /// Returns the facts in `exchange`: the user's message and the reply
+    async fn extract(&self, exchange: &[Message]) -> Result<Vec<Memory>>;
+}
+
+/// A [`MemoryExtractor`] that asks a language model for the facts
+///
+/// The model is asked for one `key: fact` line per durable fact about the
+/// user, or `NONE`. A small, cheap model is usually enough.
+pub struct LlmMemoryExtractor<M> {
+    service: Arc<dyn LLMService<M> + Send + Sync>,
+}
+
+impl<M: ModelInfo> LlmMemoryExtractor<M> {
+    /// Creates an extractor that sends its prompt through `service`
+    pub fn new(service: Arc<dyn LLMService<M> + Send + Sync>) -> Self {
+        Self { service }
+    }
+
+    fn prompt(exchange: &[Message]) -> Chat {
+        let mut transcript = String::new();
+        for msg in exchange {
+            transcript.push_str(&format!("{}: {}\n", msg.role_str(), msg.text_content()));
+        }
+
+        Chat::default()
+            .with_system_prompt(
+                "You maintain long-term memory about a user. From the conversation below, \
+                 list facts about the user that will still matter in future conversations: \
+                 preferences, circumstances, goals. Write one fact per line as \
+                 `key: fact`, where the key is a short snake_case name such as home_city. \
+                 Reply with NONE if there is nothing worth remembering.",
+            )
+            .with_max_output_tokens(256)
+            .with_temperature(0.0)
+            .add_message(Message::user(transcript))
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo> MemoryExtractor for LlmMemoryExtractor<M> {
+    async fn extract(&self, exchange: &[Message]) -> Result<Vec<Memory>> {
+        let reply = self
+            .service
+            .generate_next_message(&Self::prompt(exchange))
+            .await?
+            .text_content();
+
+        Ok(reply
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim().trim_start_matches(['-', '*']).trim();
+                let (key, text) = line.split_once(':')?;
+                let key = key
+                    .trim()
+                    .trim_matches('`')
+                    .to_lowercase()
+                    .replace(' ', "_");
+                let text = text.trim();
+                (!key.is_empty() && !text.is_empty()).then(|| Memory::new(key, text))
+            })
+            .collect())
+    }
+}
+
+/// Recalls and records memories for one scope
+#[derive(Clone)]
+pub struct ConversationMemory {
+    store: Arc<dyn MemoryStore>,
+    extractor: Arc<dyn MemoryExtractor>,
+    scope: String,
+    limit: usize,
+}
+
+impl ConversationMemory {
+    /// The default number of memories recalled per turn
+    pub const DEFAULT_LIMIT: usize = 5;
+
+    /// Creates a memory for `scope`
+    pub fn new(
+        store: Arc<dyn MemoryStore>,
+        extractor: Arc<dyn MemoryExtractor>,
+        scope: impl Into<String>,
+    ) -> Self {
+        Self {
+            store,
+            extractor,
+            scope: scope.into(),
+            limit: Self::DEFAULT_LIMIT,
+        }
+    }
+
+    /// Sets how many memories are recalled per turn
+    #[must_use]
+    pub fn with_limit(self, limit: usize) -> Self {
+        Self { limit, ..self }
+    }
+
+    /// Returns `chat` with the memories relevant to its latest user message
+    ///
+    /// Memories are added as a system segment tagged [`MEMORY_TAG`],
+    /// replacing any recalled earlier. A chat with no relevant memories is
+    /// returned unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns the store's error if the search fails.
+    pub async fn recall(&self, chat: &Chat) -> Result<Chat> {
+        let Some(query) = last_user_message(chat).map(Message::text_content) else {
+            return Ok(chat.clone());
+        };
+
+        let memories = self.store.search(&self.scope, &query, self.limit).await?;
+        if memories.is_empty() {
+            return Ok(chat.clone());
+        }
+        debug!("Recalled {} memories for {}", memories.len(), self.scope);
+
+        let mut text = String::from("Things you remember about the user:");
+        for memory in &memories {
+            text.push_str(&format!("\n- {}", memory.text));
+        }
+        Ok(chat
+            .clone()
+            .with_system_segment(SystemSegment::new(text).with_tag(MEMORY_TAG)))
+    }
+
+    /// Extracts and stores the facts in the latest turn of `chat` and its `reply`
+    ///
+    /// Returns how many memories were stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns the extractor's or the store's error.
+    pub async fn remember(&self, chat: &Chat, reply: &Message) -> Result<usize> {
+        let mut exchange: Vec<Message> = last_user_message(chat).into_iter().cloned().collect();
+        exchange.push(reply.clone());
+
+        let memories = self.extractor.extract(&exchange).await?;
+        let count = memories.len();
+        if count > 0 {
+            debug!("Storing {} memories for {}", count, self.scope);
+            self.store.upsert(&self.scope, memories).await?;
+        }
+        Ok(count)
+    }
+}
+
+fn last_user_message(chat: &Chat) -> Option<&Message> {
+    chat.history
+        .iter()
+        .rev()
+        .find(|msg| matches!(msg, Message::User { .. }))
+}
+
+/// A service that recalls memories before each call and records new ones after
+///
+/// Failing to record memories is logged and doesn't fail the call.
+pub struct MemoryService<S> {
+    inner: S,
+    memory: ConversationMemory,
+}
+
+impl<S> MemoryService<S> {
+    /// Wraps `inner` with `memory`
+    pub fn new(inner: S, memory: ConversationMemory) -> Self {
+        Self { inner, memory }
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo, S: LLMService<M> + Sync> LLMService<M> for MemoryService<S> {
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        let recalled = self.memory.recall(chat).await?;
+        let reply = self.inner.generate_next_message(&recalled).await?;
+        if let Err(e) = self.memory.remember(chat, &reply).await {
+            warn!("Failed to record memories: {}", e);
+        }
+        Ok(reply)
+    }
+
+    fn continuation(&self) -> Continuation {
+        self.inner.continuation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Claude;
+
+    /// Replies with the system text it was sent, then with fixed facts
+    struct Scripted;
+
+    #[async_trait]
+    impl LLMService<Claude> for Scripted {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            if chat
+                .system_prompt
+                .starts_with("You maintain long-term memory")
+            {
+                return Ok(Message::assistant(
+                    "- home_city: Lives in Lisbon\n- Diet: Is vegetarian\nnot a fact",
+                ));
+            }
+            Ok(Message::assistant(chat.system_text()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_replaces_by_key_and_ranks_by_overlap() {
+        let store = InMemoryStore::new();
+        store
+            .upsert(
+                "a",
+                vec![
+                    Memory::new("pet", "Has a cat named Miso"),
+                    Memory::new("city", "Lives in Porto"),
+                ],
+            )
+            .await
+            .unwrap();
+        store
+            .upsert("a", vec![Memory::new("city", "Lives in Lisbon")])
+            .await
+            .unwrap();
+
+        let found = store
+            .search("a", "Where in Lisbon does my cat live?", 5)
+            .await
+            .unwrap();
+        assert_eq!(found[0], Memory::new("city", "Lives in Lisbon"));
+        assert_eq!(found.len(), 2);
+        assert!(store.search("b", "cat", 5).await.unwrap().is_empty());
+
+        assert!(store.forget("a", "pet").await.unwrap());
+        assert!(!store.forget("a", "pet").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_service_recalls_then_remembers() {
+        let store = Arc::new(InMemoryStore::new());
+        let extractor = Arc::new(LlmMemoryExtractor::new(Arc::new(Scripted)));
+        let memory = ConversationMemory::new(store.clone(), extractor, "user-1");
+        let service = MemoryService::new(Scripted, memory);
+
+        let chat = Chat::default().add_message(Message::user("I'm vegetarian, living in Lisbon."));
+        let first = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(first.text_content(), "");
+
+        let chat = Chat::default().add_message(Message::user("Suggest vegetarian dinner spots"));
+        let second = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(
+            second.text_content(),
+            "Things you remember about the user:\n- Is vegetarian"
+        );
+        assert_eq!(
+            store.search("user-1", "lisbon", 5).await.unwrap(),
+            vec![Memory::new("home_city", "Lives in Lisbon")]
+        );
+    }
+}
@@ -1,5 +1,23 @@
+use crate::chat::OutputSchema;
+use crate::error::{Error, Result};
+use crate::feedback::Feedback;
+use crate::graceful::ReplyFailure;
+use crate::locale::Locale;
+use crate::logprobs::{self, Classification, TokenLogprob};
+use crate::moderation::ModerationVerdict;
+use crate::prompts::PromptRef;
+use crate::usage::{Latency, StreamTiming, Usage};
+#[cfg(feature = "multimodal")]
+use base64::Engine;
+#[cfg(feature = "multimodal")]
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+#[cfg(feature = "multimodal")]
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Represents the content of a message, which can be text or other structured data
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -73,11 +91,26 @@ pub enum ContentPart {
         text: String,
     },
     /// Image part
+    #[cfg(feature = "multimodal")]
     #[serde(rename = "image_url")]
     ImageUrl {
         /// The image URL and metadata
         image_url: ImageUrl,
     },
+    /// Document part (e.g. a PDF), carried inline as base64 data
+    #[cfg(feature = "multimodal")]
+    #[serde(rename = "document")]
+    Document {
+        /// The document data and metadata
+        document: Document,
+    },
+    /// Audio part (e.g. a spoken reply), carried inline as base64 data
+    #[cfg(feature = "multimodal")]
+    #[serde(rename = "audio")]
+    Audio {
+        /// The audio data and metadata
+        audio: Audio,
+    },
 }
 
 impl ContentPart {
@@ -103,12 +136,95 @@ impl ContentPart {
     ///
     /// let part = ContentPart::image_url("https://example.com/image.jpg");
     /// ```
+    #[cfg(feature = "multimodal")]
     pub fn image_url(url: impl Into<String>) -> Self {
         ContentPart::ImageUrl {
             image_url: ImageUrl::new(url),
         }
     }
 
+    /// Creates a new image part from raw image bytes
+    ///
+    /// The bytes are embedded as a base64 `data:` URL, which providers unpack
+    /// into their inline image format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::ContentPart;
+    ///
+    /// let part = ContentPart::image_bytes(b"\x89PNG", "image/png");
+    /// ```
+    #[cfg(feature = "multimodal")]
+    pub fn image_bytes(bytes: impl AsRef<[u8]>, mime_type: impl AsRef<str>) -> Self {
+        let url = format!(
+            "data:{};base64,{}",
+            mime_type.as_ref(),
+            BASE64.encode(bytes.as_ref())
+        );
+        Self::image_url(url)
+    }
+
+    /// Creates a new document part from raw bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::ContentPart;
+    ///
+    /// let part = ContentPart::document(b"%PDF-1.7", "application/pdf");
+    /// ```
+    #[cfg(feature = "multimodal")]
+    pub fn document(bytes: impl AsRef<[u8]>, mime_type: impl Into<String>) -> Self {
+        ContentPart::Document {
+            document: Document::new(bytes, mime_type),
+        }
+    }
+
+    /// Creates a new audio part from raw bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::ContentPart;
+    ///
+    /// let part = ContentPart::audio(b"RIFF", "wav");
+    /// ```
+    #[cfg(feature = "multimodal")]
+    pub fn audio(bytes: impl AsRef<[u8]>, format: impl Into<String>) -> Self {
+        ContentPart::Audio {
+            audio: Audio::new(bytes).with_format(format),
+        }
+    }
+
+    /// Returns the text of a text part, or `None` for any other part
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::ContentPart;
+    ///
+    /// assert_eq!(ContentPart::text("Hello").as_text(), Some("Hello"));
+    /// ```
+    #[must_use]
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ContentPart::Text { text } => Some(text),
+            #[cfg(feature = "multimodal")]
+            _ => None,
+        }
+    }
+
+    /// Returns the text of a text part for editing, or `None` for any other
+    /// part
+    pub fn as_text_mut(&mut self) -> Option<&mut String> {
+        match self {
+            ContentPart::Text { text } => Some(text),
+            #[cfg(feature = "multimodal")]
+            _ => None,
+        }
+    }
+
     /// Returns true if the part is empty
     ///
     /// # Examples
@@ -126,12 +242,18 @@ impl ContentPart {
     pub fn is_empty(&self) -> bool {
         match self {
             ContentPart::Text { text } => text.is_empty(),
+            #[cfg(feature = "multimodal")]
             ContentPart::ImageUrl { .. } => false,
+            #[cfg(feature = "multimodal")]
+            ContentPart::Document { document } => document.data.is_empty(),
+            #[cfg(feature = "multimodal")]
+            ContentPart::Audio { audio } => audio.data.is_empty(),
         }
     }
 }
 
 /// Represents an image URL with optional metadata
+#[cfg(feature = "multimodal")]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ImageUrl {
     /// The URL of the image
@@ -141,6 +263,7 @@ pub struct ImageUrl {
     pub detail: Option<String>,
 }
 
+#[cfg(feature = "multimodal")]
 impl ImageUrl {
     /// Creates a new image URL
     ///
@@ -173,6 +296,132 @@ impl ImageUrl {
         self.detail = Some(detail.into());
         self
     }
+
+    /// Splits a base64 `data:` URL into its MIME type and payload
+    ///
+    /// Returns `None` for ordinary (e.g. `https://`) URLs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::ImageUrl;
+    ///
+    /// let image_url = ImageUrl::new("data:image/png;base64,iVBORw0K");
+    /// assert_eq!(image_url.data_url_parts(), Some(("image/png", "iVBORw0K")));
+    ///
+    /// let image_url = ImageUrl::new("https://example.com/image.jpg");
+    /// assert_eq!(image_url.data_url_parts(), None);
+    /// ```
+    #[must_use]
+    pub fn data_url_parts(&self) -> Option<(&str, &str)> {
+        let rest = self.url.strip_prefix("data:")?;
+        let (header, data) = rest.split_once(',')?;
+        let mime_type = header.strip_suffix(";base64")?;
+        Some((mime_type, data))
+    }
+}
+
+/// Represents an inline document attachment
+#[cfg(feature = "multimodal")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Document {
+    /// The document contents, base64 encoded
+    pub data: String,
+    /// The MIME type of the document (e.g. `application/pdf`)
+    pub mime_type: String,
+    /// Optional file name or title (for some providers)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[cfg(feature = "multimodal")]
+impl Document {
+    /// Creates a new document from raw bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Document;
+    ///
+    /// let document = Document::new(b"%PDF-1.7", "application/pdf");
+    /// assert_eq!(document.mime_type, "application/pdf");
+    /// ```
+    pub fn new(bytes: impl AsRef<[u8]>, mime_type: impl Into<String>) -> Self {
+        Self {
+            data: BASE64.encode(bytes.as_ref()),
+            mime_type: mime_type.into(),
+            name: None,
+        }
+    }
+
+    /// Sets the name and returns self for method chaining
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+}
+
+/// Represents inline audio, either sent to a model or spoken by one
+///
+/// A spoken reply's transcript is carried as a separate text part of the
+/// same message, so `text_content` still returns what was said.
+#[cfg(feature = "multimodal")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Audio {
+    /// The audio contents, base64 encoded
+    pub data: String,
+    /// The audio encoding (e.g. `wav`, `mp3`), when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// The provider's ID for generated audio, used to refer back to it in
+    /// later turns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// When the provider stops accepting `id`, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+#[cfg(feature = "multimodal")]
+impl Audio {
+    /// Creates new audio from raw bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Audio;
+    ///
+    /// let audio = Audio::new(b"RIFF").with_format("wav");
+    /// assert_eq!(audio.format.as_deref(), Some("wav"));
+    /// assert_eq!(audio.bytes().unwrap(), b"RIFF");
+    /// ```
+    pub fn new(bytes: impl AsRef<[u8]>) -> Self {
+        Self {
+            data: BASE64.encode(bytes.as_ref()),
+            format: None,
+            id: None,
+            expires_at: None,
+        }
+    }
+
+    /// Sets the format and returns self for method chaining
+    #[must_use]
+    pub fn with_format(mut self, format: impl Into<String>) -> Self {
+        self.format = Some(format.into());
+        self
+    }
+
+    /// Decodes the audio contents
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Other` if `data` isn't valid base64.
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        BASE64
+            .decode(&self.data)
+            .map_err(|e| Error::Other(format!("Invalid base64 audio data: {e}")))
+    }
 }
 
 /// Represents a function definition within a tool call
@@ -196,8 +445,75 @@ pub struct ToolCall {
     pub function: Function,
 }
 
+/// Why the model stopped generating a reply
+///
+/// Providers record this on replies under [`FinishReason::METADATA_KEY`];
+/// read it with [`Message::finish_reason`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The reply ended on its own or at a stop sequence
+    Stop,
+    /// The reply hit the output token limit and was cut off
+    Length,
+    /// The model stopped to call tools
+    ToolCalls,
+    /// The provider withheld or cut off the reply for safety reasons
+    ContentFilter,
+    /// The model declined to answer
+    ///
+    /// Reported by OpenAI's `refusal` field and Anthropic's `refusal` stop
+    /// reason, or recorded by a caller that recognized a refusal in the
+    /// reply's text.
+    Refusal,
+    /// Any other reason, as the provider spelled it
+    Other(String),
+}
+
+impl FinishReason {
+    /// The message metadata key holding the finish reason
+    pub const METADATA_KEY: &'static str = "finish_reason";
+
+    /// Maps a provider's stop or finish reason to a `FinishReason`
+    ///
+    /// Understands the vocabularies of OpenAI, Anthropic, Gemini and
+    /// Mistral.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::FinishReason;
+    ///
+    /// assert_eq!(FinishReason::from_provider("max_tokens"), FinishReason::Length);
+    /// assert_eq!(FinishReason::from_provider("MAX_TOKENS"), FinishReason::Length);
+    /// assert_eq!(FinishReason::from_provider("end_turn"), FinishReason::Stop);
+    /// assert_eq!(FinishReason::from_provider("refusal"), FinishReason::Refusal);
+    /// ```
+    pub fn from_provider(reason: &str) -> Self {
+        match reason.to_ascii_lowercase().as_str() {
+            "stop" | "end_turn" | "stop_sequence" => Self::Stop,
+            "length" | "max_tokens" | "model_length" => Self::Length,
+            "tool_calls" | "tool_use" | "function_call" => Self::ToolCalls,
+            "content_filter" | "safety" | "recitation" | "blocklist" | "prohibited_content"
+            | "spii" => Self::ContentFilter,
+            "refusal" => Self::Refusal,
+            _ => Self::Other(reason.to_string()),
+        }
+    }
+
+    /// Returns the finish reason as a metadata value
+    pub fn to_metadata(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
 /// Represents a message in a conversation
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+///
+/// Two messages are equal if they have the same role, content and metadata.
+/// Message IDs are left out of the comparison, so a message equals its copy
+/// in a [`Chat`](crate::Chat)'s history even after the chat has given the
+/// copy an ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "role")]
 pub enum Message {
     /// Message from the system (instructions)
@@ -250,6 +566,71 @@ pub enum Message {
     },
 }
 
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        let same = match (self, other) {
+            (
+                Message::System { content, .. },
+                Message::System {
+                    content: other_content,
+                    ..
+                },
+            ) => content == other_content,
+            (
+                Message::User { content, name, .. },
+                Message::User {
+                    content: other_content,
+                    name: other_name,
+                    ..
+                },
+            ) => content == other_content && name == other_name,
+            (
+                Message::Assistant {
+                    content,
+                    tool_calls,
+                    ..
+                },
+                Message::Assistant {
+                    content: other_content,
+                    tool_calls: other_tool_calls,
+                    ..
+                },
+            ) => content == other_content && tool_calls == other_tool_calls,
+            (
+                Message::Tool {
+                    tool_call_id,
+                    content,
+                    ..
+                },
+                Message::Tool {
+                    tool_call_id: other_tool_call_id,
+                    content: other_content,
+                    ..
+                },
+            ) => tool_call_id == other_tool_call_id && content == other_content,
+            _ => false,
+        };
+        same && same_metadata(self.metadata(), other.metadata())
+    }
+}
+
+/// Compares two metadata maps, leaving out message IDs
+fn same_metadata(
+    a: &HashMap<String, serde_json::Value>,
+    b: &HashMap<String, serde_json::Value>,
+) -> bool {
+    let len = |metadata: &HashMap<String, serde_json::Value>| {
+        metadata
+            .iter()
+            .filter(|(key, _)| key.as_str() != Message::MESSAGE_ID_KEY)
+            .count()
+    };
+    len(a) == len(b)
+        && a.iter()
+            .filter(|(key, _)| key.as_str() != Message::MESSAGE_ID_KEY)
+            .all(|(key, value)| b.get(key) == Some(value))
+}
+
 impl Message {
     /// Creates a new system message
     ///
@@ -305,7 +686,8 @@ impl Message {
     ///
     /// # Examples
     ///
-    /// ```
+    #[cfg_attr(feature = "multimodal", doc = "```")]
+    #[cfg_attr(not(feature = "multimodal"), doc = "```ignore")]
     /// use language_barrier_core::message::{Message, Content, ContentPart};
     ///
     /// let parts = vec![
@@ -412,6 +794,152 @@ impl Message {
         }
     }
 
+    /// Appends a content part and returns a new message
+    ///
+    /// Plain text content is promoted to a list of parts, keeping the
+    /// original text as the first part. Only user and assistant messages
+    /// carry structured content; system and tool messages are returned
+    /// unchanged.
+    ///
+    /// # Examples
+    ///
+    #[cfg_attr(feature = "multimodal", doc = "```")]
+    #[cfg_attr(not(feature = "multimodal"), doc = "```ignore")]
+    /// use language_barrier_core::message::{Content, ContentPart, Message};
+    ///
+    /// let msg = Message::user("Look at this:")
+    ///     .with_part(ContentPart::image_url("https://example.com/image.jpg"));
+    ///
+    /// if let Message::User { content: Content::Parts(parts), .. } = msg {
+    ///     assert_eq!(parts.len(), 2);
+    /// }
+    /// ```
+    #[must_use]
+    pub fn with_part(self, part: ContentPart) -> Self {
+        fn push(content: Content, part: ContentPart) -> Content {
+            let mut parts = match content {
+                Content::Text(text) if text.is_empty() => Vec::new(),
+                Content::Text(text) => vec![ContentPart::Text { text }],
+                Content::Parts(parts) => parts,
+            };
+            parts.push(part);
+            Content::Parts(parts)
+        }
+
+        match self {
+            Message::User {
+                content,
+                name,
+                metadata,
+            } => Message::User {
+                content: push(content, part),
+                name,
+                metadata,
+            },
+            Message::Assistant {
+                content,
+                tool_calls,
+                metadata,
+            } => Message::Assistant {
+                content: Some(push(content.unwrap_or(Content::Parts(Vec::new())), part)),
+                tool_calls,
+                metadata,
+            },
+            other => other,
+        }
+    }
+
+    /// Appends a text part and returns a new message
+    #[must_use]
+    pub fn with_text(self, text: impl Into<String>) -> Self {
+        self.with_part(ContentPart::text(text))
+    }
+
+    /// Appends an image referenced by URL and returns a new message
+    #[cfg(feature = "multimodal")]
+    #[must_use]
+    pub fn with_image_url(self, url: impl Into<String>) -> Self {
+        self.with_part(ContentPart::image_url(url))
+    }
+
+    /// Appends an image from raw bytes and returns a new message
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Message;
+    ///
+    /// let png_bytes: &[u8] = b"\x89PNG";
+    /// let msg = Message::user("What's in this picture?").with_image_bytes(png_bytes, "image/png");
+    /// ```
+    #[cfg(feature = "multimodal")]
+    #[must_use]
+    pub fn with_image_bytes(self, bytes: impl AsRef<[u8]>, mime_type: impl AsRef<str>) -> Self {
+        self.with_part(ContentPart::image_bytes(bytes, mime_type))
+    }
+
+    /// Reads an image from disk and appends it, returning a new message
+    ///
+    /// The MIME type is inferred from the file extension (`png`, `jpg`/`jpeg`,
+    /// `gif` or `webp`).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Io` if the file can't be read, and `Error::Other` if
+    /// the extension isn't a supported image type.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use language_barrier_core::message::Message;
+    ///
+    /// # fn main() -> language_barrier_core::Result<()> {
+    /// let msg = Message::user("Describe this chart")
+    ///     .with_image_path("chart.png")?
+    ///     .with_document(std::fs::read("report.pdf")?, "application/pdf");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "multimodal")]
+    pub fn with_image_path(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        let mime_type = match extension.as_deref() {
+            Some("png") => "image/png",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("gif") => "image/gif",
+            Some("webp") => "image/webp",
+            _ => {
+                return Err(Error::Other(format!(
+                    "Unsupported image type for '{}'",
+                    path.display()
+                )));
+            }
+        };
+
+        let bytes = std::fs::read(path)?;
+        Ok(self.with_image_bytes(bytes, mime_type))
+    }
+
+    /// Appends a document (e.g. a PDF) from raw bytes and returns a new message
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Message;
+    ///
+    /// let msg = Message::user("Summarize this").with_document(b"%PDF-1.7", "application/pdf");
+    /// ```
+    #[cfg(feature = "multimodal")]
+    #[must_use]
+    pub fn with_document(self, bytes: impl AsRef<[u8]>, mime_type: impl Into<String>) -> Self {
+        self.with_part(ContentPart::document(bytes, mime_type))
+    }
+
     /// Adds metadata and returns a new message
     ///
     /// # Examples
@@ -471,6 +999,550 @@ impl Message {
             }
         }
     }
+
+    /// Returns the text of this message, ignoring non-text parts
+    ///
+    /// Text parts are joined with newlines. Messages with no text (such as an
+    /// assistant message that only makes tool calls) return an empty string.
+    ///
+    /// # Examples
+    ///
+    #[cfg_attr(feature = "multimodal", doc = "```")]
+    #[cfg_attr(not(feature = "multimodal"), doc = "```ignore")]
+    /// use language_barrier_core::message::{ContentPart, Message};
+    ///
+    /// let msg = Message::user_with_parts(vec![
+    ///     ContentPart::text("What's in"),
+    ///     ContentPart::image_url("https://example.com/cat.jpg"),
+    ///     ContentPart::text("this picture?"),
+    /// ]);
+    /// assert_eq!(msg.text_content(), "What's in\nthis picture?");
+    /// ```
+    pub fn text_content(&self) -> String {
+        let content = match self {
+            Message::System { content, .. } | Message::Tool { content, .. } => {
+                return content.clone();
+            }
+            Message::User { content, .. } => Some(content),
+            Message::Assistant { content, .. } => content.as_ref(),
+        };
+
+        match content {
+            Some(Content::Text(text)) => text.clone(),
+            Some(Content::Parts(parts)) => parts
+                .iter()
+                .filter_map(ContentPart::as_text)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::new(),
+        }
+    }
+
+    /// Parses the reply to a chat with an output schema
+    ///
+    /// The reply is read from the text, or from the first tool call's
+    /// arguments when there is no text, which is how Anthropic returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidStructuredOutput` if the reply isn't JSON that
+    /// deserializes to `T`.
+    pub fn structured_output<T: DeserializeOwned>(&self) -> Result<T> {
+        let text = self.text_content();
+        let json = match self {
+            Message::Assistant { tool_calls, .. } if text.trim().is_empty() => tool_calls
+                .first()
+                .map_or(text.as_str(), |call| call.function.arguments.as_str()),
+            _ => text.as_str(),
+        };
+        serde_json::from_str(json).map_err(|e| Error::InvalidStructuredOutput(e.to_string()))
+    }
+
+    /// Parses the pick from the reply to a chat with a choice output
+    ///
+    /// See [`Chat::with_choice_output`](crate::Chat::with_choice_output).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidStructuredOutput` if the reply has no choice or
+    /// the choice doesn't deserialize to `T`.
+    pub fn choice<T: DeserializeOwned>(&self) -> Result<T> {
+        let mut reply: serde_json::Map<String, serde_json::Value> = self.structured_output()?;
+        let choice = reply
+            .remove(OutputSchema::CHOICE_FIELD)
+            .ok_or_else(|| Error::InvalidStructuredOutput("the reply has no choice".to_string()))?;
+        serde_json::from_value(choice).map_err(|e| Error::InvalidStructuredOutput(e.to_string()))
+    }
+
+    /// Parses the pick from the reply to a chat with a choice output, with
+    /// the model's confidence in it
+    ///
+    /// The confidence comes from the reply's token logprobs, so it's only
+    /// set for chats configured with
+    /// [`Chat::with_logprobs`](crate::Chat::with_logprobs) on a provider that
+    /// returns them. See the [`logprobs`](crate::logprobs) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidStructuredOutput` if the reply has no choice or
+    /// the choice doesn't deserialize to `T`.
+    pub fn classification<T: DeserializeOwned>(&self) -> Result<Classification<T>> {
+        let label = self.choice()?;
+        let confidence = self.choice::<String>().ok().and_then(|raw| {
+            let logprobs = self.logprobs()?;
+            logprobs::label_confidence(&logprobs, OutputSchema::CHOICE_FIELD, &raw)
+        });
+        Ok(Classification { label, confidence })
+    }
+
+    /// Returns the token logprobs recorded on this message, if any
+    pub fn logprobs(&self) -> Option<Vec<TokenLogprob>> {
+        self.metadata()
+            .get(TokenLogprob::METADATA_KEY)
+            .and_then(|logprobs| serde_json::from_value(logprobs.clone()).ok())
+    }
+
+    /// The message metadata key marking a message as pinned
+    pub const PINNED_KEY: &'static str = "pinned";
+
+    /// Marks this message as pinned so history compression leaves it intact
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Message;
+    ///
+    /// let msg = Message::user("Always answer in French.").pinned();
+    /// assert!(msg.is_pinned());
+    /// assert!(!Message::user("Hi").is_pinned());
+    /// ```
+    #[must_use]
+    pub fn pinned(self) -> Self {
+        self.with_metadata(Self::PINNED_KEY, serde_json::Value::Bool(true))
+    }
+
+    /// Returns true if this message is pinned
+    pub fn is_pinned(&self) -> bool {
+        self.metadata()
+            .get(Self::PINNED_KEY)
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// The message metadata key marking a message as ephemeral
+    pub const EPHEMERAL_KEY: &'static str = "ephemeral";
+
+    /// Marks this message as ephemeral, the first thing compaction may drop
+    ///
+    /// Use this for context that only matters for a turn or two, such as
+    /// retrieved search results.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Message;
+    ///
+    /// let msg = Message::user("Search results: ...").ephemeral();
+    /// assert!(msg.is_ephemeral());
+    /// assert!(!Message::user("Hi").is_ephemeral());
+    /// ```
+    #[must_use]
+    pub fn ephemeral(self) -> Self {
+        self.with_metadata(Self::EPHEMERAL_KEY, serde_json::Value::Bool(true))
+    }
+
+    /// Returns true if this message is ephemeral
+    pub fn is_ephemeral(&self) -> bool {
+        self.metadata()
+            .get(Self::EPHEMERAL_KEY)
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// The message metadata key marking a reply as cut off before it ended
+    pub const TRUNCATED_KEY: &'static str = "truncated";
+
+    /// Marks this message as a reply that was cut off before it ended
+    ///
+    /// See [`salvage`](crate::salvage) for finishing such replies.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Message;
+    ///
+    /// let msg = Message::assistant("The three largest cities are").truncated();
+    /// assert!(msg.is_truncated());
+    /// assert!(!Message::assistant("Done.").is_truncated());
+    /// ```
+    #[must_use]
+    pub fn truncated(self) -> Self {
+        self.with_metadata(Self::TRUNCATED_KEY, serde_json::Value::Bool(true))
+    }
+
+    /// Returns true if this message is a reply that was cut off
+    pub fn is_truncated(&self) -> bool {
+        self.metadata()
+            .get(Self::TRUNCATED_KEY)
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// Returns the metadata attached to this message
+    pub fn metadata(&self) -> &HashMap<String, serde_json::Value> {
+        match self {
+            Message::System { metadata, .. }
+            | Message::User { metadata, .. }
+            | Message::Assistant { metadata, .. }
+            | Message::Tool { metadata, .. } => metadata,
+        }
+    }
+
+    /// Returns the token usage recorded on this message, if any
+    ///
+    /// Providers store typed usage under [`Usage::METADATA_KEY`]. Messages
+    /// recorded before typed usage existed only carry the flat
+    /// `input_tokens`/`output_tokens` (or `prompt_tokens`/`completion_tokens`)
+    /// keys, which are read as a fallback.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Message, Usage};
+    ///
+    /// let msg = Message::assistant("Hi!")
+    ///     .with_metadata(Usage::METADATA_KEY, Usage::new(12, 3).to_metadata());
+    /// assert_eq!(msg.usage(), Some(Usage::new(12, 3)));
+    ///
+    /// assert_eq!(Message::user("Hello").usage(), None);
+    /// ```
+    pub fn usage(&self) -> Option<Usage> {
+        let metadata = self.metadata();
+        if let Some(usage) = metadata.get(Usage::METADATA_KEY) {
+            return serde_json::from_value(usage.clone()).ok();
+        }
+
+        let count = |keys: [&str; 2]| {
+            keys.iter()
+                .find_map(|key| metadata.get(*key).and_then(serde_json::Value::as_u64))
+        };
+        let input = count(["input_tokens", "prompt_tokens"]);
+        let output = count(["output_tokens", "completion_tokens"]);
+        if input.is_none() && output.is_none() {
+            return None;
+        }
+
+        Some(Usage::new(input.unwrap_or(0), output.unwrap_or(0)))
+    }
+
+    /// Returns the request latency recorded on this message, if any
+    pub fn latency(&self) -> Option<Latency> {
+        self.metadata()
+            .get(Latency::METADATA_KEY)
+            .and_then(|latency| serde_json::from_value(latency.clone()).ok())
+    }
+
+    /// Returns the streaming timing recorded on this message, if any
+    pub fn stream_timing(&self) -> Option<StreamTiming> {
+        self.metadata()
+            .get(StreamTiming::METADATA_KEY)
+            .and_then(|timing| serde_json::from_value(timing.clone()).ok())
+    }
+
+    /// Returns why the model stopped generating this reply, if the provider
+    /// said
+    pub fn finish_reason(&self) -> Option<FinishReason> {
+        self.metadata()
+            .get(FinishReason::METADATA_KEY)
+            .and_then(|reason| serde_json::from_value(reason.clone()).ok())
+    }
+
+    /// Returns true if this is a reply the model declined to give
+    ///
+    /// Only replies with [`FinishReason::Refusal`] count; text that merely
+    /// sounds like a refusal isn't inspected.
+    pub fn is_refusal(&self) -> bool {
+        self.finish_reason() == Some(FinishReason::Refusal)
+    }
+
+    /// Returns the moderation verdict recorded on this reply, if it was
+    /// moderated
+    ///
+    /// See [`moderation`](crate::moderation).
+    pub fn moderation(&self) -> Option<ModerationVerdict> {
+        self.metadata()
+            .get(ModerationVerdict::METADATA_KEY)
+            .and_then(|verdict| serde_json::from_value(verdict.clone()).ok())
+    }
+
+    /// Returns the error this message stands in for, if it's a fallback
+    /// message
+    ///
+    /// See [`graceful`](crate::graceful).
+    pub fn failure(&self) -> Option<ReplyFailure> {
+        self.metadata()
+            .get(ReplyFailure::METADATA_KEY)
+            .and_then(|failure| serde_json::from_value(failure.clone()).ok())
+    }
+
+    /// Returns true if this is a fallback message rather than a reply from
+    /// the model
+    pub fn is_fallback(&self) -> bool {
+        self.metadata().contains_key(ReplyFailure::METADATA_KEY)
+    }
+
+    /// Returns the prompt template recorded on this message, if any
+    ///
+    /// `HTTPLlmService` records it on replies to chats configured with
+    /// [`Chat::with_prompt`](crate::Chat::with_prompt).
+    pub fn prompt(&self) -> Option<PromptRef> {
+        self.metadata()
+            .get(PromptRef::METADATA_KEY)
+            .and_then(|prompt| serde_json::from_value(prompt.clone()).ok())
+    }
+
+    /// Returns the conversation locale recorded on this message, if any
+    ///
+    /// `HTTPLlmService` records it on replies to chats configured with
+    /// [`Chat::with_locale`](crate::Chat::with_locale).
+    pub fn locale(&self) -> Option<Locale> {
+        self.metadata()
+            .get(Locale::METADATA_KEY)
+            .and_then(|locale| serde_json::from_value(locale.clone()).ok())
+    }
+
+    /// The message metadata key holding the creation time in Unix milliseconds
+    pub const CREATED_AT_KEY: &'static str = "created_at";
+
+    /// Records when this message was created
+    ///
+    /// The time is stored in the metadata as milliseconds since the Unix
+    /// epoch; sub-millisecond precision is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::Message;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+    /// let msg = Message::user("Hello").with_created_at(at);
+    /// assert_eq!(msg.created_at(), Some(at));
+    /// ```
+    #[must_use]
+    pub fn with_created_at(self, at: SystemTime) -> Self {
+        let millis = at.duration_since(UNIX_EPOCH).map_or(0, |since| {
+            u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+        });
+        self.with_metadata(Self::CREATED_AT_KEY, serde_json::json!(millis))
+    }
+
+    /// Records the current time as this message's creation time
+    ///
+    /// Responses from `HTTPLlmService` are stamped automatically; use this
+    /// for messages you add yourself.
+    #[must_use]
+    pub fn timestamped(self) -> Self {
+        self.with_created_at(SystemTime::now())
+    }
+
+    /// Returns when this message was created, if it was recorded
+    pub fn created_at(&self) -> Option<SystemTime> {
+        self.metadata()
+            .get(Self::CREATED_AT_KEY)
+            .and_then(serde_json::Value::as_u64)
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis))
+    }
+
+    /// The message metadata key holding the message's ID
+    pub const MESSAGE_ID_KEY: &'static str = "message_id";
+
+    /// Sets this message's ID
+    ///
+    /// Messages added to a [`Chat`](crate::Chat) without one are given one;
+    /// see [`ids`](crate::ids).
+    #[must_use]
+    pub fn with_message_id(self, id: impl Into<String>) -> Self {
+        self.with_metadata(Self::MESSAGE_ID_KEY, serde_json::Value::String(id.into()))
+    }
+
+    /// Returns this message's ID, if it has one
+    pub fn message_id(&self) -> Option<&str> {
+        self.metadata()
+            .get(Self::MESSAGE_ID_KEY)
+            .and_then(serde_json::Value::as_str)
+    }
+
+    /// The message metadata key holding feedback on the message
+    pub const FEEDBACK_KEY: &'static str = "feedback";
+
+    /// Adds `feedback` to what's been given on this message
+    ///
+    /// To add feedback to a message already in a chat, use
+    /// [`Chat::with_feedback`](crate::Chat::with_feedback).
+    #[must_use]
+    pub fn with_feedback(self, feedback: Feedback) -> Self {
+        let mut all = self.feedback();
+        all.push(feedback);
+        self.with_metadata(Self::FEEDBACK_KEY, serde_json::json!(all))
+    }
+
+    /// Returns the feedback given on this message, oldest first
+    pub fn feedback(&self) -> Vec<Feedback> {
+        self.metadata()
+            .get(Self::FEEDBACK_KEY)
+            .and_then(|feedback| serde_json::from_value(feedback.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// The message metadata key holding the ID a client gave the message
+    pub const CLIENT_ID_KEY: &'static str = "client_id";
+
+    /// Records the ID the client submitted this message with, so a
+    /// resubmission can be recognized (see [`Chat::add_turn`])
+    ///
+    /// [`Chat::add_turn`]: crate::Chat::add_turn
+    #[must_use]
+    pub fn with_client_id(self, id: impl Into<String>) -> Self {
+        self.with_metadata(Self::CLIENT_ID_KEY, serde_json::Value::String(id.into()))
+    }
+
+    /// Returns the ID the client submitted this message with, if any
+    pub fn client_id(&self) -> Option<&str> {
+        self.metadata()
+            .get(Self::CLIENT_ID_KEY)
+            .and_then(serde_json::Value::as_str)
+    }
+
+    /// The message metadata key holding cached token counts
+    pub const TOKEN_COUNTS_KEY: &'static str = "token_counts";
+
+    /// Returns the token count cached for `tokenizer`, if the text hasn't
+    /// changed since it was cached
+    ///
+    /// Counts are stored with a fingerprint of the message text, so editing
+    /// the message, even through its public fields, invalidates them.
+    /// Checking the fingerprint hashes the text, which is far cheaper than
+    /// running a real tokenizer over it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::message::{Content, Message};
+    ///
+    /// let mut msg = Message::user("How far is the Moon?");
+    /// msg.cache_token_count("cl100k", 6);
+    /// assert_eq!(msg.cached_token_count("cl100k"), Some(6));
+    /// assert_eq!(msg.cached_token_count("gemini"), None);
+    ///
+    /// if let Message::User { content, .. } = &mut msg {
+    ///     *content = Content::text("And the Sun?");
+    /// }
+    /// assert_eq!(msg.cached_token_count("cl100k"), None);
+    /// ```
+    pub fn cached_token_count(&self, tokenizer: &str) -> Option<usize> {
+        let counts = self.metadata().get(Self::TOKEN_COUNTS_KEY)?;
+        if counts.get("fingerprint")?.as_str()? != self.text_fingerprint() {
+            return None;
+        }
+        counts
+            .get(tokenizer)?
+            .as_u64()
+            .and_then(|tokens| usize::try_from(tokens).ok())
+    }
+
+    /// Caches `tokens` as the count of this message's text under `tokenizer`
+    ///
+    /// `tokenizer` names the tokenizer or model family that produced the
+    /// count. Counts cached for other tokenizers are kept while the text is
+    /// unchanged.
+    pub fn cache_token_count(&mut self, tokenizer: &str, tokens: usize) {
+        let fingerprint = self.text_fingerprint();
+        let metadata = self.metadata_mut();
+        let counts = metadata
+            .entry(Self::TOKEN_COUNTS_KEY.to_string())
+            .or_insert_with(|| serde_json::json!({}));
+        if counts
+            .get("fingerprint")
+            .and_then(serde_json::Value::as_str)
+            != Some(&fingerprint)
+        {
+            *counts = serde_json::json!({ "fingerprint": fingerprint });
+        }
+        counts[tokenizer] = serde_json::json!(tokens);
+    }
+
+    /// Returns the message with its content replaced by `text`, without
+    /// tool calls, feedback, or what the provider reported about generating
+    /// the old text
+    pub(crate) fn edited(self, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let mut edited = match self {
+            Message::System { metadata, .. } => Message::System {
+                content: text,
+                metadata,
+            },
+            Message::User { name, metadata, .. } => Message::User {
+                content: Content::Text(text),
+                name,
+                metadata,
+            },
+            Message::Assistant { metadata, .. } => Message::Assistant {
+                content: Some(Content::Text(text)),
+                tool_calls: Vec::new(),
+                metadata,
+            },
+            Message::Tool {
+                tool_call_id,
+                metadata,
+                ..
+            } => Message::Tool {
+                tool_call_id,
+                content: text,
+                metadata,
+            },
+        };
+        // An edited reply wasn't generated, so it can't have been cut off
+        let metadata = edited.metadata_mut();
+        for key in [
+            Self::FEEDBACK_KEY,
+            FinishReason::METADATA_KEY,
+            Self::TRUNCATED_KEY,
+            Usage::METADATA_KEY,
+            Latency::METADATA_KEY,
+            TokenLogprob::METADATA_KEY,
+        ] {
+            metadata.remove(key);
+        }
+        edited
+    }
+
+    /// Returns true if the messages are equal apart from cached token counts
+    pub(crate) fn same_as(&self, other: &Message) -> bool {
+        if self == other {
+            return true;
+        }
+        let mut this = self.clone();
+        let mut other = other.clone();
+        this.metadata_mut().remove(Self::TOKEN_COUNTS_KEY);
+        other.metadata_mut().remove(Self::TOKEN_COUNTS_KEY);
+        this == other
+    }
+
+    fn text_fingerprint(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.text_content().hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn metadata_mut(&mut self) -> &mut HashMap<String, serde_json::Value> {
+        match self {
+            Message::System { metadata, .. }
+            | Message::User { metadata, .. }
+            | Message::Assistant { metadata, .. }
+            | Message::Tool { metadata, .. } => metadata,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -478,6 +1550,7 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[cfg(feature = "multimodal")]
     #[test]
     fn test_content_serialization() {
         let text_content = Content::text("Hello, world!");
@@ -607,4 +1680,148 @@ mod tests {
             _ => panic!("Expected Tool variant"),
         }
     }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_fluent_attachments() {
+        let msg = Message::user("caption")
+            .with_image_bytes([1u8, 2, 3], "image/png")
+            .with_document(b"%PDF", "application/pdf");
+
+        let Message::User {
+            content: Content::Parts(parts),
+            ..
+        } = msg
+        else {
+            panic!("Expected User variant with parts");
+        };
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], ContentPart::text("caption"));
+        match &parts[1] {
+            ContentPart::ImageUrl { image_url } => {
+                assert_eq!(image_url.url, "data:image/png;base64,AQID");
+                assert_eq!(image_url.data_url_parts(), Some(("image/png", "AQID")));
+            }
+            _ => panic!("Expected image part"),
+        }
+        match &parts[2] {
+            ContentPart::Document { document } => {
+                assert_eq!(document.data, "JVBERg==");
+                assert_eq!(document.mime_type, "application/pdf");
+            }
+            _ => panic!("Expected document part"),
+        }
+
+        // Attachments on an empty caption don't leave an empty text part behind
+        let msg = Message::user("").with_image_url("https://example.com/image.jpg");
+        assert!(matches!(
+            msg,
+            Message::User { content: Content::Parts(ref parts), .. } if parts.len() == 1
+        ));
+
+        // Tool messages only carry text
+        let msg = Message::tool("call_123", "42").with_text("ignored");
+        assert_eq!(msg, Message::tool("call_123", "42"));
+    }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_with_image_path() {
+        let path = std::env::temp_dir().join(format!("lb-test-{}.PNG", std::process::id()));
+        std::fs::write(&path, [1u8, 2, 3]).unwrap();
+
+        let msg = Message::user("caption").with_image_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let Message::User {
+            content: Content::Parts(parts),
+            ..
+        } = msg
+        else {
+            panic!("Expected User variant with parts");
+        };
+        assert_eq!(
+            parts[1],
+            ContentPart::image_url("data:image/png;base64,AQID")
+        );
+
+        let result = Message::user("caption").with_image_path("notes.txt");
+        assert!(matches!(result, Err(Error::Other(_))));
+
+        let result = Message::user("caption").with_image_path("/nonexistent/image.png");
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_created_at_and_latency_survive_serialization() {
+        let at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let latency = Latency::new(Duration::from_millis(300), Duration::from_millis(900));
+        let msg = Message::assistant("Hi")
+            .with_created_at(at)
+            .with_metadata(Latency::METADATA_KEY, latency.to_metadata());
+
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["created_at"], 1_700_000_000_123u64);
+        assert_eq!(
+            json["latency"],
+            json!({"time_to_first_byte_ms": 300, "total_ms": 900})
+        );
+
+        let parsed: Message = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.created_at(), Some(at));
+        assert_eq!(parsed.latency(), Some(latency));
+        assert_eq!(Message::user("Hello").created_at(), None);
+    }
+
+    #[test]
+    fn test_choice_is_read_from_text_or_tool_call() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Verdict {
+            Approve,
+            Reject,
+        }
+
+        let call = ToolCall {
+            id: "toolu_1".to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "choice".to_string(),
+                arguments: r#"{"choice":"reject"}"#.to_string(),
+            },
+        };
+        let reply = Message::assistant_with_tool_calls(vec![call]);
+        assert_eq!(reply.choice::<Verdict>().unwrap(), Verdict::Reject);
+
+        let reply = Message::assistant(r#"{"choice":"approve"}"#);
+        assert_eq!(reply.choice::<Verdict>().unwrap(), Verdict::Approve);
+
+        for text in [
+            r#"{"choice":"maybe"}"#,
+            r#"{"answer":"approve"}"#,
+            "Approve",
+        ] {
+            assert!(matches!(
+                Message::assistant(text).choice::<Verdict>(),
+                Err(Error::InvalidStructuredOutput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_equality_ignores_message_ids() {
+        let msg = Message::user("Hi").with_metadata("tag", json!("greeting"));
+
+        assert_eq!(msg.clone().with_message_id("msg_1"), msg);
+        assert_eq!(
+            msg.clone().with_message_id("msg_1"),
+            msg.clone().with_message_id("msg_2")
+        );
+        assert_ne!(msg.clone().with_message_id("msg_1"), Message::user("Hi"));
+        assert_ne!(
+            msg,
+            Message::assistant("Hi").with_metadata("tag", json!("greeting"))
+        );
+    }
 }
@@ -0,0 +1,399 @@
+//! Shrinking tool schemas before they're sent
+//!
+//! Tool parameter schemas are sent with every request, and schemas derived
+//! with `schemars` carry a lot the model doesn't need: titles repeating the
+//! property name, examples, long doc comments, and enums spelled out as one
+//! `oneOf` branch per value. With a dozen tools they can outweigh the
+//! conversation. A [`SchemaMinifier`] set with
+//! [`Chat::with_schema_minifier`] rewrites the chat's tool schemas as the
+//! request is built, leaving the chat itself unchanged. How much it removes
+//! is chosen with a [`MinifyLevel`]; what it saves is measured with
+//! [`SchemaMinifier::savings`].
+//!
+//! The tool's own description is never touched, since it's what the model
+//! reads to pick a tool.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::minify::{MinifyLevel, SchemaMinifier};
+//! use language_barrier_core::tool::LlmToolInfo;
+//! use language_barrier_core::token::Tokenizer;
+//! use serde_json::json;
+//!
+//! let tool = LlmToolInfo {
+//!     name: "set_mode".to_string(),
+//!     description: "Sets the thermostat mode".to_string(),
+//!     parameters: json!({
+//!         "title": "SetModeRequest",
+//!         "type": "object",
+//!         "properties": {
+//!             "mode": {
+//!                 "title": "Mode",
+//!                 "oneOf": [
+//!                     { "type": "string", "enum": ["heat"] },
+//!                     { "type": "string", "enum": ["cool"] }
+//!                 ],
+//!                 "examples": ["heat"]
+//!             }
+//!         }
+//!     }),
+//! };
+//!
+//! let minifier = SchemaMinifier::new().with_level(MinifyLevel::Light);
+//! let minified = minifier.minify(&tool);
+//! assert_eq!(
+//!     minified.parameters,
+//!     json!({
+//!         "type": "object",
+//!         "properties": { "mode": { "type": "string", "enum": ["heat", "cool"] } }
+//!     })
+//! );
+//!
+//! // Compact JSON has no whitespace, so count roughly four characters a token
+//! struct Approximate;
+//!
+//! impl Tokenizer for Approximate {
+//!     fn name(&self) -> &str {
+//!         "chars/4"
+//!     }
+//!
+//!     fn count(&self, text: &str) -> usize {
+//!         text.len().div_ceil(4)
+//!     }
+//! }
+//!
+//! let savings = minifier.savings(&[tool], &Approximate);
+//! assert!(savings.after < savings.before / 2);
+//! ```
+//!
+//! [`Chat::with_schema_minifier`]: crate::Chat::with_schema_minifier
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::tool::LlmToolInfo;
+use crate::token::Tokenizer;
+
+/// Keywords that only annotate a schema, never constrain it
+const ANNOTATIONS: [&str; 5] = ["title", "examples", "example", "$comment", "$schema"];
+
+/// Keywords whose value maps names to schemas
+const SCHEMA_MAPS: [&str; 4] = ["properties", "patternProperties", "definitions", "$defs"];
+
+/// Keywords whose value is a schema, or a list of schemas for `items`
+const SUBSCHEMAS: [&str; 8] = [
+    "items",
+    "additionalProperties",
+    "additionalItems",
+    "contains",
+    "not",
+    "if",
+    "then",
+    "else",
+];
+
+/// Keywords whose value is a list of schemas
+const SCHEMA_LISTS: [&str; 4] = ["anyOf", "oneOf", "allOf", "prefixItems"];
+
+/// How much a [`SchemaMinifier`] removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MinifyLevel {
+    /// Strips annotations (titles, examples, comments) and collapses enums
+    /// whose branches are bare values
+    ///
+    /// Never changes what the model is told about a field.
+    Light,
+    /// Also drops descriptions longer than the minifier's limit
+    #[default]
+    Balanced,
+    /// Also drops every description below the top level, leaving field
+    /// names and types to speak for themselves
+    Aggressive,
+}
+
+/// Rewrites tool parameter schemas to use fewer tokens
+///
+/// See the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaMinifier {
+    level: MinifyLevel,
+    max_description_len: usize,
+}
+
+impl Default for SchemaMinifier {
+    fn default() -> Self {
+        Self {
+            level: MinifyLevel::default(),
+            max_description_len: Self::DEFAULT_MAX_DESCRIPTION_LEN,
+        }
+    }
+}
+
+impl SchemaMinifier {
+    /// The default length, in characters, of the longest description kept
+    /// at [`MinifyLevel::Balanced`]
+    pub const DEFAULT_MAX_DESCRIPTION_LEN: usize = 120;
+
+    /// Creates a minifier at [`MinifyLevel::Balanced`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how much is removed
+    #[must_use]
+    pub fn with_level(self, level: MinifyLevel) -> Self {
+        Self { level, ..self }
+    }
+
+    /// Sets the length, in characters, of the longest description kept at
+    /// [`MinifyLevel::Balanced`]
+    #[must_use]
+    pub fn with_max_description_len(self, max_description_len: usize) -> Self {
+        Self {
+            max_description_len,
+            ..self
+        }
+    }
+
+    /// Returns `tool` with its parameter schema minified
+    #[must_use]
+    pub fn minify(&self, tool: &LlmToolInfo) -> LlmToolInfo {
+        let mut parameters = tool.parameters.clone();
+        self.minify_schema(&mut parameters, true);
+        LlmToolInfo {
+            parameters,
+            ..tool.clone()
+        }
+    }
+
+    /// Counts the tokens of `tools`' schemas before and after minifying
+    #[must_use]
+    pub fn savings(&self, tools: &[LlmToolInfo], tokenizer: &impl Tokenizer) -> SchemaSavings {
+        let count = |tool: &LlmToolInfo| tokenizer.count(&tool.parameters.to_string());
+        SchemaSavings {
+            before: tools.iter().map(count).sum(),
+            after: tools.iter().map(|tool| count(&self.minify(tool))).sum(),
+        }
+    }
+
+    fn minify_schema(&self, schema: &mut Value, top_level: bool) {
+        let Value::Object(schema) = schema else {
+            return;
+        };
+
+        for annotation in ANNOTATIONS {
+            schema.remove(annotation);
+        }
+        let drop_description = match self.level {
+            MinifyLevel::Light => false,
+            MinifyLevel::Balanced => schema
+                .get("description")
+                .and_then(Value::as_str)
+                .is_some_and(|text| text.chars().count() > self.max_description_len),
+            MinifyLevel::Aggressive => !top_level,
+        };
+        if drop_description {
+            schema.remove("description");
+        }
+
+        for (keyword, value) in schema.iter_mut() {
+            let keyword = keyword.as_str();
+            if SCHEMA_MAPS.contains(&keyword) {
+                if let Value::Object(named) = value {
+                    named
+                        .values_mut()
+                        .for_each(|schema| self.minify_schema(schema, false));
+                }
+            } else if SUBSCHEMAS.contains(&keyword) || SCHEMA_LISTS.contains(&keyword) {
+                match value {
+                    Value::Array(schemas) => schemas
+                        .iter_mut()
+                        .for_each(|schema| self.minify_schema(schema, false)),
+                    schema => self.minify_schema(schema, false),
+                }
+            }
+        }
+
+        for keyword in ["oneOf", "anyOf"] {
+            collapse_enum(schema, keyword);
+        }
+    }
+}
+
+/// Replaces a `oneOf`/`anyOf` whose branches are each a single value with
+/// an `enum` of the values
+fn collapse_enum(schema: &mut Map<String, Value>, keyword: &str) {
+    let Some(Value::Array(branches)) = schema.get(keyword) else {
+        return;
+    };
+    if branches.is_empty() || schema.contains_key("enum") {
+        return;
+    }
+
+    let mut values = Vec::with_capacity(branches.len());
+    let mut types = Vec::new();
+    for branch in branches {
+        let Value::Object(branch) = branch else {
+            return;
+        };
+        if branch
+            .keys()
+            .any(|key| !matches!(key.as_str(), "type" | "const" | "enum"))
+        {
+            return;
+        }
+        match (branch.get("const"), branch.get("enum")) {
+            (Some(value), None) => values.push(value.clone()),
+            (None, Some(Value::Array(single))) if single.len() == 1 => {
+                values.push(single[0].clone());
+            }
+            _ => return,
+        }
+        if let Some(branch_type) = branch.get("type")
+            && !types.contains(branch_type)
+        {
+            types.push(branch_type.clone());
+        }
+    }
+
+    schema.remove(keyword);
+    match types.as_slice() {
+        [] => {}
+        [single] => {
+            schema.insert("type".to_string(), single.clone());
+        }
+        _ => {
+            schema.insert("type".to_string(), Value::Array(types));
+        }
+    }
+    schema.insert("enum".to_string(), Value::Array(values));
+}
+
+/// Tokens of tool schemas before and after minifying, from
+/// [`SchemaMinifier::savings`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SchemaSavings {
+    /// Tokens of the original schemas
+    pub before: usize,
+    /// Tokens of the minified schemas
+    pub after: usize,
+}
+
+impl SchemaSavings {
+    /// Tokens removed by minifying
+    #[must_use]
+    pub fn saved(&self) -> usize {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(parameters: Value) -> LlmToolInfo {
+        LlmToolInfo {
+            name: "search".to_string(),
+            description: "Searches the catalog".to_string(),
+            parameters,
+        }
+    }
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "description": "A catalog search",
+            "properties": {
+                "title": { "type": "string", "description": "Words in the item's title" },
+                "notes": {
+                    "type": "string",
+                    "description": "Free-form notes that are passed along to the search backend verbatim"
+                },
+                "sort": {
+                    "anyOf": [
+                        { "const": "price", "description": "Cheapest first" },
+                        { "const": "rating" }
+                    ]
+                }
+            },
+            "required": ["title"]
+        })
+    }
+
+    #[test]
+    fn test_levels_drop_progressively_more() {
+        let minify = |level| {
+            SchemaMinifier::new()
+                .with_level(level)
+                .with_max_description_len(40)
+                .minify(&tool(schema()))
+                .parameters
+        };
+
+        let light = minify(MinifyLevel::Light);
+        // A property named like an annotation is a property, not a title
+        assert!(light["properties"]["title"].is_object());
+        assert!(light["properties"]["notes"]["description"].is_string());
+        // A branch with its own description isn't collapsed
+        assert!(light["properties"]["sort"]["anyOf"].is_array());
+
+        let balanced = minify(MinifyLevel::Balanced);
+        assert!(balanced["properties"]["title"]["description"].is_string());
+        assert!(balanced["properties"]["notes"].get("description").is_none());
+
+        let aggressive = minify(MinifyLevel::Aggressive);
+        assert_eq!(aggressive["description"], "A catalog search");
+        assert!(aggressive["properties"]["title"].get("description").is_none());
+        assert_eq!(
+            aggressive["properties"]["sort"],
+            json!({ "enum": ["price", "rating"] })
+        );
+        assert_eq!(aggressive["required"], json!(["title"]));
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_requests_carry_minified_schemas() {
+        use crate::llm_service::{HTTPLlmService, LLMService};
+        use crate::provider::openai::OpenAIProvider;
+        use crate::transport::mock::{MockResponse, MockTransport};
+        use crate::{Chat, Message, OpenAi};
+        use std::sync::Arc;
+
+        let reply = r#"{"id":"1","object":"chat.completion","created":0,"model":"gpt-4o",
+            "choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#;
+        let transport = Arc::new(MockTransport::new().with_fallback(MockResponse::ok(reply)));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::new()),
+            transport.clone(),
+        );
+        let chat = Chat::default()
+            .with_tools(vec![tool(schema())])
+            .with_schema_minifier(SchemaMinifier::new().with_level(MinifyLevel::Aggressive))
+            .add_message(Message::user("Find a lamp"));
+
+        service.generate_next_message(&chat).await.unwrap();
+
+        let body: Value = serde_json::from_slice(&transport.requests()[0].body).unwrap();
+        let sent = &body["tools"][0]["function"]["parameters"]["properties"];
+        assert!(sent["title"].get("description").is_none());
+        // The chat keeps the full schema
+        let tools = chat.tools.unwrap();
+        assert!(tools[0].parameters["properties"]["title"]["description"].is_string());
+    }
+
+    #[test]
+    fn test_mixed_branches_are_not_collapsed() {
+        let parameters = json!({
+            "oneOf": [{ "const": 1 }, { "type": "object", "properties": {} }]
+        });
+        let minified = SchemaMinifier::new()
+            .with_level(MinifyLevel::Aggressive)
+            .minify(&tool(parameters.clone()));
+        assert_eq!(minified.parameters, parameters);
+    }
+}
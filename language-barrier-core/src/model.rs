@@ -1,5 +1,10 @@
 use std::fmt;
 
+use serde::Serialize;
+
+use crate::error::{ChatConfigError, Error};
+use crate::lifecycle::{self, ModelLifecycle};
+
 /// Model that can be converted to a string ID for API requests
 pub trait ModelInfo: Send + Sync + fmt::Debug + Clone + Copy {
     /// Context window size in tokens
@@ -10,6 +15,70 @@ pub trait ModelInfo: Send + Sync + fmt::Debug + Clone + Copy {
     /// context-dependent.  for example if you set the right headers
     /// for anthropic, 3.7 can output 128k instead of 64k.
     fn max_output_tokens(&self) -> usize;
+
+    /// The output limit requests to this model are held to, if the provider
+    /// documents one
+    ///
+    /// Defaults to [`max_output_tokens`](Self::max_output_tokens). Models
+    /// without a documented limit return `None`, and their requests go out
+    /// with the budget the chat asks for.
+    fn output_token_limit(&self) -> Option<usize> {
+        Some(self.max_output_tokens())
+    }
+
+    /// Features this model supports beyond plain text chat
+    fn capabilities(&self) -> Vec<ModelCapability>;
+
+    /// Whether the model supports the given capability
+    fn supports(&self, capability: ModelCapability) -> bool {
+        self.capabilities().contains(&capability)
+    }
+
+    /// When the model is deprecated and retired
+    ///
+    /// Models with a provider ID look it up with
+    /// [`lifecycle`](crate::lifecycle::lifecycle), so registered dates
+    /// override built-in ones.
+    fn lifecycle(&self) -> ModelLifecycle {
+        ModelLifecycle::ACTIVE
+    }
+}
+
+/// A feature a model may or may not support
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::model::{ModelCapability, ModelInfo, OpenAi};
+///
+/// assert!(OpenAi::GPT4o.supports(ModelCapability::Vision));
+/// assert!(!OpenAi::GPT35Turbo.supports(ModelCapability::Vision));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModelCapability {
+    /// Accepts image (and document) inputs
+    Vision,
+    /// Can call tools / functions
+    Tools,
+    /// Can be constrained to emit valid JSON
+    JsonMode,
+    /// Produces extended reasoning before answering
+    Thinking,
+    /// Accepts audio inputs and can reply with speech
+    Audio,
+}
+
+impl fmt::Display for ModelCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Vision => "vision",
+            Self::Tools => "tools",
+            Self::JsonMode => "JSON mode",
+            Self::Thinking => "thinking",
+            Self::Audio => "audio",
+        };
+        f.write_str(name)
+    }
 }
 
 /// Sonnet 3.5 has two published tags.
@@ -20,12 +89,13 @@ pub enum Sonnet35Version {
 }
 
 /// Represents an Anthropic Claude model
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Claude {
     Sonnet35 { version: Sonnet35Version },
     Sonnet37 { use_extended_thinking: bool },
     Haiku35,
     Haiku3,
+    #[default]
     Opus3,
 }
 
@@ -55,9 +125,21 @@ pub enum OllamaModelSize {
     _1B,
 }
 
-impl Default for Claude {
-    fn default() -> Self {
-        Self::Opus3
+impl Claude {
+    /// Returns the ID the Anthropic API knows this model by
+    pub(crate) fn anthropic_model_id(&self) -> &'static str {
+        match self {
+            Self::Sonnet37 { .. } => "claude-3-7-sonnet-latest",
+            Self::Sonnet35 {
+                version: Sonnet35Version::V1,
+            } => "claude-3-5-sonnet-20240620",
+            Self::Sonnet35 {
+                version: Sonnet35Version::V2,
+            } => "claude-3-5-sonnet-20241022",
+            Self::Opus3 => "claude-3-opus-latest",
+            Self::Haiku3 => "claude-3-haiku-20240307",
+            Self::Haiku35 => "claude-3-5-haiku-latest",
+        }
     }
 }
 
@@ -76,6 +158,25 @@ impl ModelInfo for Claude {
             Self::Haiku3 | Self::Opus3 => 4096,
         }
     }
+
+    fn capabilities(&self) -> Vec<ModelCapability> {
+        use ModelCapability::*;
+        match self {
+            // JSON mode is emulated with a forced tool call, which extended
+            // thinking doesn't allow
+            Self::Sonnet37 {
+                use_extended_thinking: true,
+            } => vec![Vision, Tools, Thinking],
+            Self::Sonnet37 { .. } => vec![Vision, Tools, JsonMode, Thinking],
+            Self::Sonnet35 { .. } | Self::Haiku35 | Self::Haiku3 | Self::Opus3 => {
+                vec![Vision, Tools, JsonMode]
+            }
+        }
+    }
+
+    fn lifecycle(&self) -> ModelLifecycle {
+        lifecycle::lifecycle(self.anthropic_model_id())
+    }
 }
 
 /// Represents a Google Gemini model
@@ -89,6 +190,8 @@ pub enum Gemini {
     Flash20Lite,
     /// Gemini 2.5 Flash Preview
     Flash25Preview,
+    /// Gemini 2.0 Flash Live, for realtime sessions only
+    Flash20Live,
 }
 
 impl ModelInfo for Gemini {
@@ -99,20 +202,42 @@ impl ModelInfo for Gemini {
 
     fn max_output_tokens(&self) -> usize {
         match self {
-            Self::Flash15 | Self::Flash20 | Self::Flash20Lite => 8_192,
+            Self::Flash15 | Self::Flash20 | Self::Flash20Lite | Self::Flash20Live => 8_192,
             Self::Flash25Preview => 65_536,
         }
     }
+
+    fn capabilities(&self) -> Vec<ModelCapability> {
+        use ModelCapability::*;
+        match self {
+            Self::Flash15 | Self::Flash20 | Self::Flash20Lite => vec![Vision, Tools, JsonMode],
+            Self::Flash25Preview => vec![Vision, Tools, JsonMode, Thinking],
+            Self::Flash20Live => vec![Vision, Tools, Audio],
+        }
+    }
+
+    fn lifecycle(&self) -> ModelLifecycle {
+        lifecycle::lifecycle(&self.gemini_model_id())
+    }
 }
 
-// Implement the GeminiModelInfo trait from provider/gemini.rs
-impl crate::provider::gemini::GeminiModelInfo for Gemini {
+/// Trait to get Gemini-specific model IDs
+///
+/// Lives here rather than in the provider module so model IDs stay
+/// available when the provider's feature is off.
+pub trait GeminiModelInfo {
+    /// Returns the Gemini model ID for this model
+    fn gemini_model_id(&self) -> String;
+}
+
+impl GeminiModelInfo for Gemini {
     fn gemini_model_id(&self) -> String {
         match self {
             Self::Flash15 => "gemini-1.5-flash",
             Self::Flash20 => "gemini-2.0-flash",
             Self::Flash20Lite => "gemini-2.0-flash-lite",
             Self::Flash25Preview => "gemini-2.5-flash-preview-04-17",
+            Self::Flash20Live => "gemini-2.0-flash-live-001",
         }
         .to_string()
     }
@@ -125,6 +250,10 @@ pub enum OpenAi {
     GPT4o,
     /// GPT-4o-mini model
     GPT4oMini,
+    /// GPT-4o audio model, which can listen and reply with speech
+    GPT4oAudio,
+    /// GPT-4o realtime model, for realtime sessions only
+    GPT4oRealtime,
     /// GPT-4 Turbo model
     GPT4Turbo,
     /// GPT-3.5 Turbo model
@@ -142,7 +271,12 @@ pub enum OpenAi {
 impl ModelInfo for OpenAi {
     fn context_window(&self) -> usize {
         match self {
-            Self::O1Mini | Self::GPT4o | Self::GPT4oMini | Self::GPT4Turbo => 128_000,
+            Self::O1Mini
+            | Self::GPT4o
+            | Self::GPT4oMini
+            | Self::GPT4oAudio
+            | Self::GPT4oRealtime
+            | Self::GPT4Turbo => 128_000,
             Self::GPT35Turbo => 16_000,
             _ => 200_000,
         }
@@ -150,19 +284,50 @@ impl ModelInfo for OpenAi {
 
     fn max_output_tokens(&self) -> usize {
         match self {
-            Self::GPT4o | Self::GPT4oMini | Self::GPT4Turbo | Self::GPT35Turbo => 4_096,
+            Self::GPT4o | Self::GPT4oMini | Self::GPT4oAudio => 16_384,
+            Self::GPT4Turbo | Self::GPT35Turbo => 4_096,
+            Self::GPT4oRealtime => 4_096,
             Self::O1Mini => 65_536,
             _ => 100_000,
         }
     }
+
+    fn capabilities(&self) -> Vec<ModelCapability> {
+        use ModelCapability::*;
+        match self {
+            Self::GPT4o | Self::GPT4oMini | Self::GPT4Turbo => vec![Vision, Tools, JsonMode],
+            Self::GPT35Turbo => vec![Tools, JsonMode],
+            Self::GPT4oAudio | Self::GPT4oRealtime => vec![Tools, Audio],
+            // o1-mini was released without function calling or image input
+            Self::O1Mini => vec![Thinking],
+            Self::O3Mini => vec![Tools, JsonMode, Thinking],
+            Self::O1 | Self::O1Pro | Self::O3 | Self::O4Mini => {
+                vec![Vision, Tools, JsonMode, Thinking]
+            }
+        }
+    }
+
+    fn lifecycle(&self) -> ModelLifecycle {
+        lifecycle::lifecycle(&self.openai_model_id())
+    }
+}
+
+/// Trait to get OpenAI-specific model IDs
+///
+/// Lives here rather than in the provider module so model IDs stay
+/// available when the provider's feature is off.
+pub trait OpenAIModelInfo {
+    /// Returns the OpenAI model ID for this model
+    fn openai_model_id(&self) -> String;
 }
 
-// Implement the OpenAIModelInfo trait from provider/openai.rs
-impl crate::provider::openai::OpenAIModelInfo for OpenAi {
+impl OpenAIModelInfo for OpenAi {
     fn openai_model_id(&self) -> String {
         match self {
             Self::GPT4o => "gpt-4o",
             Self::GPT4oMini => "gpt-4o-mini",
+            Self::GPT4oAudio => "gpt-4o-audio-preview",
+            Self::GPT4oRealtime => "gpt-4o-realtime-preview",
             Self::GPT4Turbo => "gpt-4-turbo",
             Self::GPT35Turbo => "gpt-3.5-turbo",
             Self::O4Mini => "o4-mini-2025-04-16",
@@ -204,10 +369,37 @@ impl ModelInfo for Mistral {
         // All Mistral models have the same max output tokens
         4_096
     }
+
+    // Mistral doesn't publish output limits; a reply can use whatever the
+    // context window leaves
+    fn output_token_limit(&self) -> Option<usize> {
+        None
+    }
+
+    fn capabilities(&self) -> Vec<ModelCapability> {
+        use ModelCapability::*;
+        match self {
+            Self::Small => vec![Vision, Tools, JsonMode],
+            Self::Large | Self::Nemo | Self::Codestral => vec![Tools, JsonMode],
+            Self::Embed => vec![],
+        }
+    }
+
+    fn lifecycle(&self) -> ModelLifecycle {
+        lifecycle::lifecycle(&self.mistral_model_id())
+    }
+}
+
+/// Trait to get Mistral-specific model IDs
+///
+/// Lives here rather than in the provider module so model IDs stay
+/// available when the provider's feature is off.
+pub trait MistralModelInfo {
+    /// Returns the Mistral model ID for this model
+    fn mistral_model_id(&self) -> String;
 }
 
-// Implement the MistralModelInfo trait from provider/mistral.rs
-impl crate::provider::mistral::MistralModelInfo for Mistral {
+impl MistralModelInfo for Mistral {
     fn mistral_model_id(&self) -> String {
         match self {
             Self::Large => "mistral-large-latest",
@@ -254,4 +446,304 @@ impl ModelInfo for Ollama {
             Self::Custom { .. } => 4_096, // Default for unknown models
         }
     }
+
+    fn output_token_limit(&self) -> Option<usize> {
+        match self {
+            // Nothing is known about a custom model's limit
+            Self::Custom { .. } => None,
+            _ => Some(self.max_output_tokens()),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<ModelCapability> {
+        use ModelCapability::*;
+        match self {
+            // Llama 3 (as opposed to 3.1+) has no tool template in Ollama
+            Self::Llama3 { .. } => vec![JsonMode],
+            Self::Llava => vec![Vision, JsonMode],
+            Self::Mistral { .. } => vec![Tools, JsonMode],
+            // Unknown models are given the benefit of the doubt
+            Self::Custom { .. } => vec![Vision, Tools, JsonMode],
+        }
+    }
+
+    fn lifecycle(&self) -> ModelLifecycle {
+        lifecycle::lifecycle(&self.ollama_model_id())
+    }
+}
+
+/// Trait for providing Ollama-specific model IDs
+///
+/// Lives here rather than in the provider module so model IDs stay
+/// available when the provider's feature is off.
+pub trait OllamaModelInfo {
+    /// Returns the Ollama model ID for this model
+    fn ollama_model_id(&self) -> String;
+}
+
+impl OllamaModelInfo for Ollama {
+    fn ollama_model_id(&self) -> String {
+        match self {
+            Self::Llama3 { size } => match size {
+                OllamaModelSize::_8B => "llama3:8b",
+                OllamaModelSize::_7B => "llama3",
+                OllamaModelSize::_3B => "llama3:3b",
+                OllamaModelSize::_1B => "llama3:1b",
+            },
+            Self::Llava => "llava",
+            Self::Mistral { size } => match size {
+                OllamaModelSize::_8B => "mistral:8b",
+                OllamaModelSize::_7B => "mistral",
+                OllamaModelSize::_3B => "mistral:3b",
+                OllamaModelSize::_1B => "mistral:1b",
+            },
+            Self::Custom { name } => name,
+        }
+        .to_string()
+    }
+}
+
+/// A hosted model provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Provider {
+    /// Anthropic
+    Anthropic,
+    /// `OpenAI`
+    OpenAi,
+    /// Google Gemini
+    Gemini,
+    /// Mistral AI
+    Mistral,
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Anthropic => "Anthropic",
+            Self::OpenAi => "OpenAI",
+            Self::Gemini => "Gemini",
+            Self::Mistral => "Mistral",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A model from any hosted provider, chosen at runtime
+///
+/// Use this when the model comes from configuration, typically through
+/// [`resolve_model`](crate::alias::resolve_model), and convert it to the
+/// provider's own model type with `try_into` to build a service.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::model::{AnyModel, OpenAi};
+///
+/// let model = AnyModel::from_id("gpt-4o-mini").unwrap();
+/// assert_eq!(model, AnyModel::OpenAi(OpenAi::GPT4oMini));
+/// assert_eq!(model.to_string(), "gpt-4o-mini");
+///
+/// let model: OpenAi = model.try_into().unwrap();
+/// assert_eq!(model, OpenAi::GPT4oMini);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyModel {
+    /// An Anthropic model
+    Claude(Claude),
+    /// An `OpenAI` model
+    OpenAi(OpenAi),
+    /// A Google Gemini model
+    Gemini(Gemini),
+    /// A Mistral AI model
+    Mistral(Mistral),
+}
+
+impl AnyModel {
+    /// Every model, in the order IDs are matched
+    ///
+    /// Ollama models are left out: which ones exist depends on the server.
+    pub const ALL: &'static [AnyModel] = &[
+        Self::Claude(Claude::Sonnet37 {
+            use_extended_thinking: false,
+        }),
+        Self::Claude(Claude::Sonnet35 {
+            version: Sonnet35Version::V2,
+        }),
+        Self::Claude(Claude::Sonnet35 {
+            version: Sonnet35Version::V1,
+        }),
+        Self::Claude(Claude::Haiku35),
+        Self::Claude(Claude::Haiku3),
+        Self::Claude(Claude::Opus3),
+        Self::OpenAi(OpenAi::GPT4o),
+        Self::OpenAi(OpenAi::GPT4oMini),
+        Self::OpenAi(OpenAi::GPT4oAudio),
+        Self::OpenAi(OpenAi::GPT4oRealtime),
+        Self::OpenAi(OpenAi::GPT4Turbo),
+        Self::OpenAi(OpenAi::GPT35Turbo),
+        Self::OpenAi(OpenAi::O1),
+        Self::OpenAi(OpenAi::O1Mini),
+        Self::OpenAi(OpenAi::O1Pro),
+        Self::OpenAi(OpenAi::O3),
+        Self::OpenAi(OpenAi::O3Mini),
+        Self::OpenAi(OpenAi::O4Mini),
+        Self::Gemini(Gemini::Flash15),
+        Self::Gemini(Gemini::Flash20),
+        Self::Gemini(Gemini::Flash20Lite),
+        Self::Gemini(Gemini::Flash25Preview),
+        Self::Gemini(Gemini::Flash20Live),
+        Self::Mistral(Mistral::Large),
+        Self::Mistral(Mistral::Small),
+        Self::Mistral(Mistral::Nemo),
+        Self::Mistral(Mistral::Codestral),
+        Self::Mistral(Mistral::Embed),
+    ];
+
+    /// Finds the model the provider knows by `id`
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|model| model.id() == id)
+    }
+
+    /// Returns the ID the provider knows this model by
+    pub fn id(&self) -> String {
+        match self {
+            Self::Claude(model) => model.anthropic_model_id().to_string(),
+            Self::OpenAi(model) => model.openai_model_id(),
+            Self::Gemini(model) => model.gemini_model_id(),
+            Self::Mistral(model) => model.mistral_model_id(),
+        }
+    }
+
+    /// Returns the provider serving this model
+    pub fn provider(&self) -> Provider {
+        match self {
+            Self::Claude(_) => Provider::Anthropic,
+            Self::OpenAi(_) => Provider::OpenAi,
+            Self::Gemini(_) => Provider::Gemini,
+            Self::Mistral(_) => Provider::Mistral,
+        }
+    }
+
+    fn wrong_provider(self, expected: &str) -> Error {
+        Error::ChatConfig(ChatConfigError::WrongModelProvider {
+            model: self.id(),
+            expected: expected.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for AnyModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id())
+    }
+}
+
+impl ModelInfo for AnyModel {
+    fn context_window(&self) -> usize {
+        match self {
+            Self::Claude(model) => model.context_window(),
+            Self::OpenAi(model) => model.context_window(),
+            Self::Gemini(model) => model.context_window(),
+            Self::Mistral(model) => model.context_window(),
+        }
+    }
+
+    fn max_output_tokens(&self) -> usize {
+        match self {
+            Self::Claude(model) => model.max_output_tokens(),
+            Self::OpenAi(model) => model.max_output_tokens(),
+            Self::Gemini(model) => model.max_output_tokens(),
+            Self::Mistral(model) => model.max_output_tokens(),
+        }
+    }
+
+    fn output_token_limit(&self) -> Option<usize> {
+        match self {
+            Self::Claude(model) => model.output_token_limit(),
+            Self::OpenAi(model) => model.output_token_limit(),
+            Self::Gemini(model) => model.output_token_limit(),
+            Self::Mistral(model) => model.output_token_limit(),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<ModelCapability> {
+        match self {
+            Self::Claude(model) => model.capabilities(),
+            Self::OpenAi(model) => model.capabilities(),
+            Self::Gemini(model) => model.capabilities(),
+            Self::Mistral(model) => model.capabilities(),
+        }
+    }
+
+    fn lifecycle(&self) -> ModelLifecycle {
+        lifecycle::lifecycle(&self.id())
+    }
+}
+
+impl From<Claude> for AnyModel {
+    fn from(model: Claude) -> Self {
+        Self::Claude(model)
+    }
+}
+
+impl From<OpenAi> for AnyModel {
+    fn from(model: OpenAi) -> Self {
+        Self::OpenAi(model)
+    }
+}
+
+impl From<Gemini> for AnyModel {
+    fn from(model: Gemini) -> Self {
+        Self::Gemini(model)
+    }
+}
+
+impl From<Mistral> for AnyModel {
+    fn from(model: Mistral) -> Self {
+        Self::Mistral(model)
+    }
+}
+
+impl TryFrom<AnyModel> for Claude {
+    type Error = Error;
+
+    fn try_from(model: AnyModel) -> Result<Self, Error> {
+        match model {
+            AnyModel::Claude(model) => Ok(model),
+            other => Err(other.wrong_provider("Claude")),
+        }
+    }
+}
+
+impl TryFrom<AnyModel> for OpenAi {
+    type Error = Error;
+
+    fn try_from(model: AnyModel) -> Result<Self, Error> {
+        match model {
+            AnyModel::OpenAi(model) => Ok(model),
+            other => Err(other.wrong_provider("OpenAI")),
+        }
+    }
+}
+
+impl TryFrom<AnyModel> for Gemini {
+    type Error = Error;
+
+    fn try_from(model: AnyModel) -> Result<Self, Error> {
+        match model {
+            AnyModel::Gemini(model) => Ok(model),
+            other => Err(other.wrong_provider("Gemini")),
+        }
+    }
+}
+
+impl TryFrom<AnyModel> for Mistral {
+    type Error = Error;
+
+    fn try_from(model: AnyModel) -> Result<Self, Error> {
+        match model {
+            AnyModel::Mistral(model) => Ok(model),
+            other => Err(other.wrong_provider("Mistral")),
+        }
+    }
 }
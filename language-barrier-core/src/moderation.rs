@@ -0,0 +1,482 @@
+//! Screening replies before they're delivered
+//!
+//! A [`Moderation`] set with [`Chat::with_moderation`] checks the text of
+//! every reply with a [`Moderator`] before the service returns it. The
+//! moderator is either a provider's moderation API ([`OpenAIModerator`]) or
+//! a local classifier: any `Fn(&str) -> Vec<ModerationCategory>` closure
+//! is one.
+//!
+//! A flagged reply is handled by the moderation's [`ModerationAction`]: it's
+//! blocked (failing the call with `Error::ReplyBlocked`), its text is
+//! replaced with [`REDACTED`], or it's returned as is. Replies that aren't
+//! blocked carry the [`ModerationVerdict`], read with
+//! [`Message::moderation`].
+//!
+//! Moderation fails closed: if the moderator itself fails, so does the call.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::moderation::{
+//!     Moderation, ModerationAction, ModerationCategory, REDACTED,
+//! };
+//! use language_barrier_core::Message;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let classifier = |text: &str| {
+//!     vec![ModerationCategory::new("profanity", text.contains("darn"))]
+//! };
+//! let moderation = Moderation::new(classifier).with_action(ModerationAction::Redact);
+//!
+//! let reply = moderation.review(Message::assistant("Well, darn.")).await.unwrap();
+//! assert_eq!(reply.text_content(), REDACTED);
+//! let verdict = reply.moderation().unwrap();
+//! assert_eq!(verdict.flagged_categories().collect::<Vec<_>>(), ["profanity"]);
+//! assert_eq!(verdict.action, Some(ModerationAction::Redact));
+//! # }
+//! ```
+//!
+//! [`Chat::with_moderation`]: crate::Chat::with_moderation
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::message::{Content, Message};
+
+#[cfg(feature = "openai")]
+pub use openai::OpenAIModerator;
+
+/// Replaces the text of a redacted reply
+pub const REDACTED: &str = "[REDACTED]";
+
+/// What happens to a flagged reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// Fail the call instead of returning the reply
+    Block,
+    /// Replace the reply's text with [`REDACTED`], keeping its tool calls
+    Redact,
+    /// Return the reply unchanged, with the verdict attached
+    Annotate,
+}
+
+/// A moderator's judgement of one category, such as `"harassment"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModerationCategory {
+    /// The category's name, as the moderator reports it
+    pub name: String,
+    /// Whether the text falls in this category
+    pub flagged: bool,
+    /// How confident the moderator is, from 0 to 1, if it says
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub score: Option<f64>,
+}
+
+impl ModerationCategory {
+    /// Creates a category without a score
+    pub fn new(name: impl Into<String>, flagged: bool) -> Self {
+        Self {
+            name: name.into(),
+            flagged,
+            score: None,
+        }
+    }
+
+    /// Sets the moderator's confidence
+    #[must_use]
+    pub fn with_score(self, score: f64) -> Self {
+        Self {
+            score: Some(score),
+            ..self
+        }
+    }
+}
+
+/// The outcome of moderating a reply
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModerationVerdict {
+    /// Every category the moderator judged
+    pub categories: Vec<ModerationCategory>,
+    /// What was done to the reply, if it was flagged
+    pub action: Option<ModerationAction>,
+}
+
+impl ModerationVerdict {
+    /// The metadata key moderated replies store their verdict under
+    pub const METADATA_KEY: &'static str = "moderation";
+
+    /// Returns true if any category was flagged
+    pub fn flagged(&self) -> bool {
+        self.categories.iter().any(|category| category.flagged)
+    }
+
+    /// Returns the names of the flagged categories
+    pub fn flagged_categories(&self) -> impl Iterator<Item = &str> {
+        self.categories
+            .iter()
+            .filter(|category| category.flagged)
+            .map(|category| category.name.as_str())
+    }
+
+    /// Converts this verdict into a metadata value
+    #[must_use]
+    pub fn to_metadata(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// Classifies text for moderation
+#[async_trait]
+pub trait Moderator: Send + Sync {
+    /// Judges `text` in each category the moderator knows
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text couldn't be classified, e.g. because a
+    /// moderation API failed.
+    async fn classify(&self, text: &str) -> Result<Vec<ModerationCategory>>;
+}
+
+/// A local classifier hook
+#[async_trait]
+impl<F> Moderator for F
+where
+    F: Fn(&str) -> Vec<ModerationCategory> + Send + Sync,
+{
+    async fn classify(&self, text: &str) -> Result<Vec<ModerationCategory>> {
+        Ok(self(text))
+    }
+}
+
+/// A moderator and what to do with the replies it flags
+#[derive(Clone)]
+pub struct Moderation {
+    moderator: Arc<dyn Moderator>,
+    action: ModerationAction,
+}
+
+impl fmt::Debug for Moderation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Moderation")
+            .field("action", &self.action)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Moderation {
+    /// Creates a moderation that blocks the replies `moderator` flags
+    pub fn new(moderator: impl Moderator + 'static) -> Self {
+        Self::shared(Arc::new(moderator))
+    }
+
+    /// Creates a moderation from a moderator shared with other chats
+    pub fn shared(moderator: Arc<dyn Moderator>) -> Self {
+        Self {
+            moderator,
+            action: ModerationAction::Block,
+        }
+    }
+
+    /// Handles flagged replies with `action` instead of blocking them
+    #[must_use]
+    pub fn with_action(self, action: ModerationAction) -> Self {
+        Self { action, ..self }
+    }
+
+    /// Returns what is done with flagged replies
+    pub fn action(&self) -> ModerationAction {
+        self.action
+    }
+
+    /// Moderates `reply`, returning it with its verdict attached
+    ///
+    /// A reply without text, such as one that only calls tools, isn't
+    /// moderated and is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ReplyBlocked` if the reply is flagged and the action
+    /// is [`ModerationAction::Block`], or the moderator's error if it fails.
+    pub async fn review(&self, mut reply: Message) -> Result<Message> {
+        let text = reply.text_content();
+        if text.trim().is_empty() {
+            return Ok(reply);
+        }
+
+        let categories = self.moderator.classify(&text).await?;
+        let mut verdict = ModerationVerdict {
+            categories,
+            action: None,
+        };
+        if verdict.flagged() {
+            let flagged: Vec<String> = verdict.flagged_categories().map(String::from).collect();
+            warn!("Moderation flagged the reply: {}", flagged.join(", "));
+            verdict.action = Some(self.action);
+            match self.action {
+                ModerationAction::Block => {
+                    return Err(Error::ReplyBlocked {
+                        categories: flagged,
+                    });
+                }
+                ModerationAction::Redact => {
+                    if let Message::Assistant { content, .. } = &mut reply {
+                        *content = Some(Content::Text(REDACTED.to_string()));
+                    }
+                }
+                ModerationAction::Annotate => {}
+            }
+        }
+
+        Ok(reply.with_metadata(ModerationVerdict::METADATA_KEY, verdict.to_metadata()))
+    }
+}
+
+#[cfg(feature = "openai")]
+mod openai {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use tracing::debug;
+    use url::Url;
+
+    use super::{ModerationCategory, Moderator};
+    use crate::error::{ApiError, Error, Result};
+    use crate::provider::openai::OpenAIConfig;
+    use crate::transport::{HeaderValue, HttpRequest, Method, ReqwestTransport, Transport};
+
+    /// OpenAI's moderation endpoint
+    ///
+    /// Uses the config's API key, base URL and organization. The moderation
+    /// API is free, but each reply costs a round trip.
+    pub struct OpenAIModerator {
+        config: OpenAIConfig,
+        model: String,
+        transport: Arc<dyn Transport>,
+    }
+
+    #[derive(Deserialize)]
+    struct ModerationResponse {
+        results: Vec<ModerationResult>,
+    }
+
+    #[derive(Deserialize)]
+    struct ModerationResult {
+        categories: BTreeMap<String, bool>,
+        #[serde(default)]
+        category_scores: BTreeMap<String, f64>,
+    }
+
+    impl OpenAIModerator {
+        /// The moderation model used unless another is set
+        pub const DEFAULT_MODEL: &'static str = "omni-moderation-latest";
+
+        /// Creates a moderator sending requests with its own HTTP client
+        pub fn new(config: OpenAIConfig) -> Self {
+            Self {
+                config,
+                model: Self::DEFAULT_MODEL.to_string(),
+                transport: Arc::new(ReqwestTransport::new()),
+            }
+        }
+
+        /// Uses moderation model `model`
+        #[must_use]
+        pub fn with_model(self, model: impl Into<String>) -> Self {
+            Self {
+                model: model.into(),
+                ..self
+            }
+        }
+
+        /// Sends requests through `transport`
+        #[must_use]
+        pub fn with_transport(self, transport: Arc<dyn Transport>) -> Self {
+            Self { transport, ..self }
+        }
+
+        fn request(&self, text: &str) -> Result<HttpRequest> {
+            let url = format!("{}/moderations", self.config.base_url.trim_end_matches('/'));
+            let url = Url::parse(&url)?;
+            let mut request = HttpRequest::new(Method::POST, url);
+
+            let mut auth = HeaderValue::from_str(&format!("Bearer {}", self.config.api_key))
+                .map_err(|_| Error::Authentication("Invalid API key format".into()))?;
+            auth.set_sensitive(true);
+            request.headers.insert("Authorization", auth);
+            request
+                .headers
+                .insert("Content-Type", HeaderValue::from_static("application/json"));
+            if let Some(org) = &self.config.organization
+                && let Ok(value) = HeaderValue::from_str(org)
+            {
+                request.headers.insert("OpenAI-Organization", value);
+            }
+
+            request.body = serde_json::to_vec(&serde_json::json!({
+                "model": self.model,
+                "input": text,
+            }))?;
+            Ok(request)
+        }
+    }
+
+    #[async_trait]
+    impl Moderator for OpenAIModerator {
+        async fn classify(&self, text: &str) -> Result<Vec<ModerationCategory>> {
+            debug!("Moderating {} characters with {}", text.len(), self.model);
+            let response = self.transport.send(self.request(text)?).await?;
+            if !(200..300).contains(&response.status) {
+                let error = ApiError::new(response.status, &response.headers, response.body);
+                return Err(Error::Api(Box::new(error)));
+            }
+
+            let parsed: ModerationResponse = serde_json::from_str(&response.body)?;
+            let result = parsed
+                .results
+                .into_iter()
+                .next()
+                .ok_or_else(|| Error::Other("Moderation response has no results".into()))?;
+            Ok(result
+                .categories
+                .into_iter()
+                .map(|(name, flagged)| {
+                    let score = result.category_scores.get(&name).copied();
+                    ModerationCategory {
+                        name,
+                        flagged,
+                        score,
+                    }
+                })
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "openai")]
+    use crate::Chat;
+    #[cfg(feature = "openai")]
+    use crate::OpenAi;
+    #[cfg(feature = "openai")]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "openai")]
+    use crate::provider::openai::{OpenAIConfig, OpenAIProvider};
+    #[cfg(feature = "openai")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+
+    #[cfg(feature = "openai")]
+    fn completion(text: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1_700_000_000u64,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": text},
+                "finish_reason": "stop"
+            }]
+        })
+    }
+
+    #[cfg(feature = "openai")]
+    fn openai_moderator(transport: Arc<MockTransport>) -> OpenAIModerator {
+        let config = OpenAIConfig {
+            api_key: "sk-test".into(),
+            ..OpenAIConfig::default()
+        };
+        OpenAIModerator::new(config).with_transport(transport)
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_openai_moderator_parses_categories() {
+        let transport = Arc::new(
+            MockTransport::new().with_fallback(MockResponse::ok(
+                serde_json::json!({
+                    "id": "modr-1",
+                    "model": "omni-moderation-latest",
+                    "results": [{
+                        "flagged": true,
+                        "categories": {"violence": true, "harassment": false},
+                        "category_scores": {"violence": 0.91, "harassment": 0.02}
+                    }]
+                })
+                .to_string(),
+            )),
+        );
+        let moderator = openai_moderator(transport.clone());
+
+        let categories = moderator.classify("text").await.unwrap();
+        assert_eq!(
+            categories,
+            [
+                ModerationCategory::new("harassment", false).with_score(0.02),
+                ModerationCategory::new("violence", true).with_score(0.91),
+            ]
+        );
+
+        let request = &transport.requests()[0];
+        assert!(request.url.as_str().ends_with("/v1/moderations"));
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["input"], "text");
+        assert_eq!(body["model"], OpenAIModerator::DEFAULT_MODEL);
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_flagged_replies_are_blocked_or_annotated() {
+        let transport = Arc::new(
+            MockTransport::new()
+                .with_fallback(MockResponse::ok(completion("Go away, idiot.").to_string())),
+        );
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            transport,
+        );
+        let classifier =
+            |text: &str| vec![ModerationCategory::new("insult", text.contains("idiot"))];
+        let chat = Chat::default()
+            .with_moderation(Moderation::new(classifier))
+            .add_message(Message::user("Hi"));
+
+        let error = service.generate_next_message(&chat).await.unwrap_err();
+        assert!(matches!(error, Error::ReplyBlocked { categories } if categories == ["insult"]));
+
+        let chat = chat
+            .with_moderation(Moderation::new(classifier).with_action(ModerationAction::Annotate));
+        let reply = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(reply.text_content(), "Go away, idiot.");
+        let verdict = reply.moderation().unwrap();
+        assert!(verdict.flagged());
+        assert_eq!(verdict.action, Some(ModerationAction::Annotate));
+    }
+
+    #[tokio::test]
+    async fn test_clean_and_empty_replies() {
+        let moderation = Moderation::new(|_: &str| vec![ModerationCategory::new("insult", false)]);
+
+        let reply = moderation
+            .review(Message::assistant("Hello!"))
+            .await
+            .unwrap();
+        let verdict = reply.moderation().unwrap();
+        assert!(!verdict.flagged());
+        assert_eq!(verdict.action, None);
+
+        let reply = moderation.review(Message::assistant("")).await.unwrap();
+        assert!(reply.moderation().is_none());
+    }
+}
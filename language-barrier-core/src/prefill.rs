@@ -0,0 +1,170 @@
+//! Prefilled assistant turns
+//!
+//! [`Chat::with_assistant_prefix`] puts words in the model's mouth: every
+//! reply starts with the given text, which is handy for forcing an output
+//! format. Providers that support it (see
+//! [`HTTPProvider::supports_prefill`]) get a request ending in an
+//! unfinished assistant turn holding the prefix, and the model writes the
+//! rest; `HTTPLlmService` puts the prefix back in front of the reply.
+//!
+//! Elsewhere the prefill is emulated: a system segment tagged
+//! [`SEGMENT_TAG`] tells the model to start its reply with the prefix, and
+//! whitespace before it is trimmed. Emulation can't force the model, so a
+//! reply that doesn't start with the prefix is returned as written, with a
+//! warning logged.
+//!
+//! [`Chat::with_assistant_prefix`]: crate::Chat::with_assistant_prefix
+//! [`HTTPProvider::supports_prefill`]: crate::provider::HTTPProvider::supports_prefill
+
+use tracing::warn;
+
+use crate::chat::{Chat, SystemSegment};
+use crate::message::{Content, ContentPart, Message};
+
+/// The tag of the system segment holding the emulated prefill instructions
+pub const SEGMENT_TAG: &str = "assistant_prefix";
+
+/// Returns the chat to send for a reply starting with `prefix`
+pub(crate) fn request_chat(chat: &Chat, prefix: &str, native: bool) -> Chat {
+    if native {
+        // Anthropic rejects a prefill that ends in whitespace
+        chat.clone()
+            .add_message(Message::assistant(prefix.trim_end()))
+    } else {
+        chat.clone().with_system_segment(
+            SystemSegment::new(format!(
+                "Begin your reply with exactly this text, then continue from it:\n{prefix}"
+            ))
+            .with_tag(SEGMENT_TAG),
+        )
+    }
+}
+
+/// Returns the reply with `prefix` in front, as the caller expects it
+pub(crate) fn complete_reply(reply: Message, prefix: &str, native: bool) -> Message {
+    let Message::Assistant {
+        content,
+        tool_calls,
+        metadata,
+    } = reply
+    else {
+        return reply;
+    };
+
+    let content = if native {
+        let prefix = prefix.trim_end();
+        match content {
+            None => Some(Content::Text(prefix.to_string())),
+            Some(Content::Text(text)) => Some(Content::Text(format!("{prefix}{text}"))),
+            Some(Content::Parts(mut parts)) => {
+                match parts.first_mut() {
+                    Some(ContentPart::Text { text }) => text.insert_str(0, prefix),
+                    _ => parts.insert(0, ContentPart::text(prefix)),
+                }
+                Some(Content::Parts(parts))
+            }
+        }
+    } else {
+        match content {
+            Some(Content::Text(text)) if text.trim_start().starts_with(prefix) => {
+                Some(Content::Text(text.trim_start().to_string()))
+            }
+            Some(Content::Text(text)) => {
+                warn!("Reply doesn't start with the requested prefix");
+                Some(Content::Text(text))
+            }
+            content => content,
+        }
+    };
+
+    Message::Assistant {
+        content,
+        tool_calls,
+        metadata,
+    }
+}
+
+#[cfg(all(test, any(feature = "anthropic", feature = "openai")))]
+mod tests {
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use crate::{Chat, Message};
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+
+    fn chat() -> Chat {
+        Chat::default()
+            .with_assistant_prefix(r#"{"result":"#)
+            .add_message(Message::user("What is 6 times 7? Answer in JSON."))
+    }
+
+    fn body(transport: &MockTransport) -> Value {
+        serde_json::from_slice(&transport.requests()[0].body).unwrap()
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[tokio::test]
+    async fn test_anthropic_continues_from_the_prefix() {
+        use crate::Claude;
+        use crate::provider::anthropic::AnthropicProvider;
+        let reply = json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-3-5-haiku-20241022",
+            "stop_reason": "end_turn",
+            "content": [{ "type": "text", "text": " 42}" }],
+            "usage": { "input_tokens": 1, "output_tokens": 1 }
+        });
+        let transport =
+            Arc::new(MockTransport::new().with_response(MockResponse::ok(reply.to_string())));
+        let service = HTTPLlmService::new_with_transport(
+            Claude::Haiku35,
+            Arc::new(AnthropicProvider::default()),
+            transport.clone(),
+        );
+
+        let reply = service.generate_next_message(&chat()).await.unwrap();
+        assert_eq!(reply.text_content(), r#"{"result": 42}"#);
+        let body = body(&transport);
+        let last = body["messages"].as_array().unwrap().last().unwrap();
+        assert_eq!(last["role"], "assistant");
+        assert!(last["content"].to_string().contains(r#"{\"result\":"#));
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_prefill_is_emulated_elsewhere() {
+        use crate::OpenAi;
+        use crate::provider::openai::OpenAIProvider;
+        let reply = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "\n{\"result\": 42}" },
+                "finish_reason": "stop"
+            }]
+        });
+        let transport =
+            Arc::new(MockTransport::new().with_response(MockResponse::ok(reply.to_string())));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            transport.clone(),
+        );
+
+        let reply = service.generate_next_message(&chat()).await.unwrap();
+        assert_eq!(reply.text_content(), r#"{"result": 42}"#);
+        let body = body(&transport);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.last().unwrap()["role"], "user");
+        assert!(
+            messages[0]["content"]
+                .to_string()
+                .contains("Begin your reply with exactly this text")
+        );
+    }
+}
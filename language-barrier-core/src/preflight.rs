@@ -0,0 +1,189 @@
+//! Checking provider configuration at startup
+//!
+//! Most failed deployments are misconfigured rather than broken: a key
+//! from the wrong project, a base URL missing its `/v1`, a gateway that
+//! rejects unsigned requests. [`HTTPLlmService::preflight`] finds these
+//! before the first real call by sending the provider's cheapest
+//! authenticated request (listing models, see
+//! [`HTTPProvider::preflight_request`]) and reporting whether the endpoint
+//! answered, whether the credentials were accepted, and how far the local
+//! clock is from the provider's, which matters for signed requests and
+//! short-lived tokens.
+//!
+//! [`PreflightReport::run`] checks several services at once and collects
+//! the results under names of the caller's choosing.
+//!
+//! # Examples
+//!
+#![cfg_attr(all(feature = "anthropic", feature = "openai"), doc = "```no_run")]
+#![cfg_attr(not(all(feature = "anthropic", feature = "openai")), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::preflight::{Preflight, PreflightReport};
+//! use language_barrier_core::provider::anthropic::AnthropicProvider;
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::{Claude, OpenAi};
+//!
+//! # tokio_test::block_on(async {
+//! let openai = HTTPLlmService::new(OpenAi::GPT4o, Arc::new(OpenAIProvider::new()));
+//! let anthropic = HTTPLlmService::new(Claude::Haiku35, Arc::new(AnthropicProvider::new()));
+//!
+//! let report = PreflightReport::run([
+//!     ("openai", &openai as &dyn Preflight),
+//!     ("anthropic", &anthropic),
+//! ])
+//! .await;
+//! if !report.passed() {
+//!     eprintln!("{}", serde_json::to_string_pretty(&report).unwrap());
+//! }
+//! # });
+//! ```
+//!
+//! [`HTTPLlmService::preflight`]: crate::HTTPLlmService::preflight
+//! [`HTTPProvider::preflight_request`]: crate::provider::HTTPProvider::preflight_request
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::Serialize;
+
+use crate::model::Provider;
+use crate::transport::HeaderMap;
+
+/// How far the local clock may be from the provider's before it's reported
+pub const CLOCK_SKEW_TOLERANCE: Duration = Duration::from_secs(60);
+
+/// What one service's preflight found
+///
+/// Fields are `None` when the check didn't get far enough to tell.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PreflightCheck {
+    /// The hosted provider, or `None` for a local or custom endpoint
+    pub provider: Option<Provider>,
+    /// The model, as its `Debug` name
+    pub model: String,
+    /// The URL checked, with credentials redacted
+    pub endpoint: Option<String>,
+    /// Whether the endpoint answered at all
+    pub reachable: Option<bool>,
+    /// Whether the credentials were accepted
+    pub credentials_valid: Option<bool>,
+    /// How long the check took, in milliseconds
+    pub latency_ms: Option<u64>,
+    /// The provider's clock minus ours, in seconds, from its `Date` header
+    pub clock_skew_secs: Option<i64>,
+    /// What's wrong, in words; empty if the check passed
+    pub problems: Vec<String>,
+}
+
+impl PreflightCheck {
+    /// Whether no problems were found
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Something that can check its own configuration
+#[async_trait]
+pub trait Preflight: Send + Sync {
+    /// Checks the endpoint, credentials and clock; never fails, but
+    /// reports problems in the result
+    async fn preflight(&self) -> PreflightCheck;
+}
+
+/// Preflight results for several services
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PreflightReport {
+    /// Each service's result, by the name it was checked under
+    pub checks: BTreeMap<String, PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// Checks every service concurrently
+    pub async fn run<'a, I, N>(services: I) -> Self
+    where
+        I: IntoIterator<Item = (N, &'a dyn Preflight)>,
+        N: Into<String>,
+    {
+        let (names, checks): (Vec<String>, Vec<_>) = services
+            .into_iter()
+            .map(|(name, service)| (name.into(), service.preflight()))
+            .unzip();
+        Self {
+            checks: names.into_iter().zip(join_all(checks).await).collect(),
+        }
+    }
+
+    /// Whether every check passed
+    pub fn passed(&self) -> bool {
+        self.checks.values().all(PreflightCheck::passed)
+    }
+}
+
+/// The provider's clock minus `now`, in whole seconds, from a `Date` header
+pub(crate) fn clock_skew(headers: &HeaderMap, now: SystemTime) -> Option<i64> {
+    let date = headers.get("date")?.to_str().ok()?;
+    let theirs = httpdate::parse_http_date(date).ok()?;
+    Some(match theirs.duration_since(now) {
+        Ok(ahead) => ahead.as_secs() as i64,
+        Err(behind) => -(behind.duration().as_secs() as i64),
+    })
+}
+
+#[cfg(all(test, feature = "anthropic"))]
+mod tests {
+    use super::*;
+    use crate::Claude;
+    use crate::llm_service::HTTPLlmService;
+    use crate::provider::anthropic::{AnthropicConfig, AnthropicProvider};
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use std::sync::Arc;
+
+    fn service(transport: Arc<MockTransport>) -> HTTPLlmService<Claude> {
+        HTTPLlmService::new_with_transport(
+            Claude::Haiku35,
+            Arc::new(AnthropicProvider::with_config(AnthropicConfig {
+                api_key: "secret-key".to_string(),
+                ..AnthropicConfig::default()
+            })),
+            transport,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_preflight_reports_rejected_keys_and_skew() {
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(300));
+        let transport = Arc::new(MockTransport::new().with_response(
+            MockResponse::status(401, r#"{"type":"error"}"#).with_header("date", &date),
+        ));
+        let check = service(transport.clone()).preflight().await;
+
+        let request = &transport.requests()[0];
+        assert_eq!(request.method.as_str(), "GET");
+        assert_eq!(request.url.path(), "/v1/models");
+        assert_eq!(request.headers["x-api-key"], "secret-key");
+        assert_eq!(check.provider, Some(Provider::Anthropic));
+        assert_eq!(check.reachable, Some(true));
+        assert_eq!(check.credentials_valid, Some(false));
+        assert!((299..=300).contains(&check.clock_skew_secs.unwrap()));
+        assert_eq!(check.problems.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_report_collects_checks_by_name() {
+        let healthy = service(Arc::new(
+            MockTransport::new().with_response(MockResponse::ok(r#"{"data":[]}"#)),
+        ));
+        let broken = service(Arc::new(MockTransport::new()));
+
+        let report =
+            PreflightReport::run([("healthy", &healthy as &dyn Preflight), ("broken", &broken)])
+                .await;
+        assert!(report.checks["healthy"].passed());
+        assert_eq!(report.checks["healthy"].credentials_valid, Some(true));
+        assert_eq!(report.checks["broken"].reachable, Some(false));
+        assert!(!report.passed());
+    }
+}
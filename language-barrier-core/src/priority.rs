@@ -0,0 +1,424 @@
+//! Queueing calls by priority under a rate limit
+//!
+//! When a service is at its rate limit, calls either fail or wait their
+//! turn, and a burst of batch work can leave a user waiting behind
+//! thousands of background requests. A [`PriorityLimiter`] allows so many
+//! calls per window and queues the rest by the chat's [`Priority`]: a
+//! waiting interactive call always goes before a waiting background one,
+//! so batch jobs only use the capacity interactive traffic leaves.
+//!
+//! Each priority's queue is bounded; a call that finds its queue full fails
+//! at once with `Error::RateLimit`. How long calls waited is kept per
+//! priority in [`QueueStats`].
+//!
+//! By default the window is counted in process memory. Replicas that
+//! should share one limit plug in a shared
+//! [`RateLimitBackend`](crate::rate_limit::RateLimitBackend) with
+//! [`PriorityLimiter::with_backend`]; the in-process window can instead be
+//! kept across restarts with [`PriorityLimiter::with_store`].
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::priority::{Priority, PriorityLimiter};
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::{Chat, Message, OpenAi};
+//!
+//! let service = HTTPLlmService::new(OpenAi::GPT4oMini, Arc::new(OpenAIProvider::new()));
+//! let limited = PriorityLimiter::new(service, 500, Duration::from_secs(60))
+//!     .with_queue_depth(Priority::Background, 10_000);
+//!
+//! let nightly = Chat::default()
+//!     .with_priority(Priority::Background)
+//!     .add_message(Message::user("Summarize this ticket: ..."));
+//! // limited.generate_next_message(&nightly).await?;
+//! assert_eq!(limited.stats(Priority::Background).admitted, 0);
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::error::{Error, Result};
+use crate::limit_state::LimitStore;
+use crate::llm_service::LLMService;
+use crate::rate_limit::{self, Acquire, InProcessBackend, RateLimitBackend};
+use crate::salvage::Continuation;
+use crate::{Chat, Message, ModelInfo};
+
+/// How urgently a chat's calls should be served
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Priority {
+    /// A user is waiting for the reply
+    #[default]
+    Interactive,
+    /// Batch or scheduled work that can wait for spare capacity
+    Background,
+}
+
+impl Priority {
+    /// Every priority, most urgent first
+    pub const ALL: [Priority; 2] = [Priority::Interactive, Priority::Background];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How calls of one priority have fared in the queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Calls let through, whether at once or after waiting
+    pub admitted: u64,
+    /// Calls turned away because the queue was full
+    pub rejected: u64,
+    /// Calls waiting now
+    pub queued: usize,
+    /// Time admitted calls spent waiting, in total
+    pub total_wait: Duration,
+    /// The longest any admitted call waited
+    pub max_wait: Duration,
+}
+
+impl QueueStats {
+    /// The average time an admitted call waited
+    pub fn mean_wait(&self) -> Duration {
+        match u32::try_from(self.admitted) {
+            Ok(0) => Duration::ZERO,
+            Ok(admitted) => self.total_wait / admitted,
+            Err(_) => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Scheduler {
+    /// Tickets of waiting calls, per priority
+    waiting: [VecDeque<u64>; 2],
+    next_ticket: u64,
+    stats: [QueueStats; 2],
+}
+
+impl Scheduler {
+    /// Whether `ticket` is the waiting call that goes next
+    fn is_next(&self, ticket: u64) -> bool {
+        self.waiting.iter().find_map(VecDeque::front) == Some(&ticket)
+    }
+
+    fn remove(&mut self, priority: Priority, ticket: u64) {
+        self.waiting[priority.index()].retain(|&waiting| waiting != ticket);
+        self.stats[priority.index()].queued = self.waiting[priority.index()].len();
+    }
+}
+
+/// A service that allows a number of calls per window, queueing the rest
+/// by priority
+pub struct PriorityLimiter<S> {
+    inner: S,
+    requests: usize,
+    window: Duration,
+    depths: [usize; 2],
+    scheduler: Mutex<Scheduler>,
+    changed: Notify,
+    backend: Arc<dyn RateLimitBackend>,
+    key: String,
+}
+
+impl<S> PriorityLimiter<S> {
+    /// The default number of calls each priority may have waiting
+    pub const DEFAULT_QUEUE_DEPTH: usize = 100;
+
+    /// The key calls are counted under unless set with
+    /// [`with_backend`](Self::with_backend)
+    pub const DEFAULT_KEY: &'static str = "default";
+
+    /// Wraps `inner`, allowing `requests` calls to start in any `window`
+    pub fn new(inner: S, requests: usize, window: Duration) -> Self {
+        Self {
+            inner,
+            requests,
+            window,
+            depths: [Self::DEFAULT_QUEUE_DEPTH; 2],
+            scheduler: Mutex::default(),
+            changed: Notify::new(),
+            backend: Arc::new(InProcessBackend::new(requests, window)),
+            key: Self::DEFAULT_KEY.to_string(),
+        }
+    }
+
+    /// Sets how many calls of `priority` may wait at once
+    #[must_use]
+    pub fn with_queue_depth(self, priority: Priority, depth: usize) -> Self {
+        let mut depths = self.depths;
+        depths[priority.index()] = depth;
+        Self { depths, ..self }
+    }
+
+    /// Takes a token from `backend` under `key` for each call, instead of
+    /// counting calls in process memory
+    ///
+    /// The limit given to [`new`](Self::new) is then the backend's to
+    /// enforce. Limiters sharing a backend and key share its budget.
+    #[must_use]
+    pub fn with_backend(self, backend: Arc<dyn RateLimitBackend>, key: impl Into<String>) -> Self {
+        Self {
+            backend,
+            key: key.into(),
+            ..self
+        }
+    }
+
+    /// Counts calls in process memory, keeping the window in `store` under
+    /// `key` so it survives restarts
+    ///
+    /// This replaces any backend set with [`with_backend`](Self::with_backend);
+    /// see [`InProcessBackend::with_store`].
+    #[must_use]
+    pub fn with_store(self, store: Arc<dyn LimitStore>, key: impl Into<String>) -> Self {
+        let backend = InProcessBackend::new(self.requests, self.window).with_store(store, key);
+        Self {
+            backend: Arc::new(backend),
+            key: Self::DEFAULT_KEY.to_string(),
+            ..self
+        }
+    }
+
+    /// Returns the queue statistics for `priority`
+    pub fn stats(&self, priority: Priority) -> QueueStats {
+        self.scheduler().stats[priority.index()]
+    }
+
+    fn scheduler(&self) -> MutexGuard<'_, Scheduler> {
+        self.scheduler
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits until a call of `priority` may start
+    async fn acquire(&self, priority: Priority) -> Result<()> {
+        let started = Instant::now();
+        let ticket = {
+            let mut scheduler = self.scheduler();
+            let waiting = &mut scheduler.waiting[priority.index()];
+            if waiting.len() >= self.depths[priority.index()] {
+                warn!("{:?} queue is full, rejecting the call", priority);
+                scheduler.stats[priority.index()].rejected += 1;
+                return Err(Error::RateLimit(format!(
+                    "{priority:?} queue is full ({} calls waiting)",
+                    self.depths[priority.index()]
+                )));
+            }
+            let ticket = scheduler.next_ticket;
+            scheduler.next_ticket += 1;
+            scheduler.waiting[priority.index()].push_back(ticket);
+            scheduler.stats[priority.index()].queued += 1;
+            ticket
+        };
+        // Leaves the queue if the call is dropped while waiting
+        let guard = Waiting {
+            limiter: self,
+            priority,
+            ticket,
+        };
+
+        loop {
+            let changed = self.changed.notified();
+            // Only the call at the head of the queue asks the backend
+            if !self.scheduler().is_next(ticket) {
+                changed.await;
+                continue;
+            }
+
+            match self.backend.acquire(&self.key, 1).await? {
+                Acquire::Granted => {
+                    let waited = started.elapsed();
+                    let mut scheduler = self.scheduler();
+                    let stats = &mut scheduler.stats[priority.index()];
+                    stats.admitted += 1;
+                    stats.total_wait += waited;
+                    stats.max_wait = stats.max_wait.max(waited);
+                    drop(scheduler);
+                    if !waited.is_zero() {
+                        debug!("{:?} call waited {:?} in the queue", priority, waited);
+                    }
+                    // Dropping the guard dequeues the ticket and wakes the next call
+                    drop(guard);
+                    return Ok(());
+                }
+                Acquire::RetryAfter(wait) => {
+                    tokio::select! {
+                        _ = changed => {}
+                        _ = tokio::time::sleep(wait) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Gives back the token of a call that was never sent
+    async fn release(&self) {
+        if let Err(e) = self.backend.release(&self.key, 1).await {
+            warn!("Couldn't release a rate-limit token: {}", e);
+        }
+        self.changed.notify_waiters();
+    }
+}
+
+/// A call's place in the queue
+struct Waiting<'a, S> {
+    limiter: &'a PriorityLimiter<S>,
+    priority: Priority,
+    ticket: u64,
+}
+
+impl<S> Drop for Waiting<'_, S> {
+    fn drop(&mut self) {
+        self.limiter.scheduler().remove(self.priority, self.ticket);
+        self.limiter.changed.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo, S: LLMService<M> + Sync> LLMService<M> for PriorityLimiter<S> {
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        self.acquire(chat.priority).await?;
+        let result = self.inner.generate_next_message(chat).await;
+        if let Err(e) = &result
+            && rate_limit::never_sent(e)
+        {
+            self.release().await;
+        }
+        result
+    }
+
+    fn continuation(&self) -> Continuation {
+        self.inner.continuation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Claude;
+    use std::sync::Arc;
+
+    /// Replies with the text of the chat's last message
+    struct Echo;
+
+    #[async_trait]
+    impl LLMService<Claude> for Echo {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            let last = chat.history.last().map(Message::text_content);
+            Ok(Message::assistant(last.unwrap_or_default()))
+        }
+    }
+
+    fn chat(text: &str, priority: Priority) -> Chat {
+        Chat::default()
+            .with_priority(priority)
+            .add_message(Message::user(text))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interactive_calls_jump_the_queue() {
+        let limiter = Arc::new(
+            PriorityLimiter::new(Echo, 1, Duration::from_secs(10))
+                .with_queue_depth(Priority::Background, 1),
+        );
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let call = |text: &'static str, priority| {
+            let limiter = limiter.clone();
+            let order = order.clone();
+            tokio::spawn(async move {
+                let reply = limiter.generate_next_message(&chat(text, priority)).await?;
+                order.lock().unwrap().push(reply.text_content());
+                Ok::<_, Error>(())
+            })
+        };
+
+        call("first", Priority::Background).await.unwrap().unwrap();
+        let background = call("background", Priority::Background);
+        tokio::task::yield_now().await;
+        let interactive = call("interactive", Priority::Interactive);
+        tokio::task::yield_now().await;
+        assert!(matches!(
+            call("overflow", Priority::Background).await.unwrap(),
+            Err(Error::RateLimit(_))
+        ));
+
+        background.await.unwrap().unwrap();
+        interactive.await.unwrap().unwrap();
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["first", "interactive", "background"]
+        );
+
+        let stats = limiter.stats(Priority::Background);
+        assert_eq!((stats.admitted, stats.rejected, stats.queued), (2, 1, 0));
+        assert_eq!(stats.max_wait, Duration::from_secs(20));
+        assert_eq!(
+            limiter.stats(Priority::Interactive).max_wait,
+            Duration::from_secs(10)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stored_window_survives_a_restart() {
+        let store = Arc::new(crate::limit_state::InMemoryLimitStore::new());
+        let limiter = || {
+            PriorityLimiter::new(Echo, 1, Duration::from_secs(60)).with_store(store.clone(), "echo")
+        };
+        let chat = chat("Hi", Priority::Interactive);
+
+        limiter().generate_next_message(&chat).await.unwrap();
+
+        // A new limiter picks up the call the old one let through
+        let restarted = limiter();
+        restarted.generate_next_message(&chat).await.unwrap();
+        let waited = restarted.stats(Priority::Interactive).max_wait;
+        assert!(waited > Duration::from_secs(59), "{waited:?}");
+    }
+
+    /// Rejects every chat before sending it
+    struct Invalid;
+
+    #[async_trait]
+    impl LLMService<Claude> for Invalid {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            Err(crate::error::ChatConfigError::EmptyHistory.into())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limiters_share_a_backend_and_release_unsent_calls() {
+        let backend = Arc::new(InProcessBackend::new(1, Duration::from_secs(60)));
+        let chat = chat("Hi", Priority::Interactive);
+
+        let invalid = PriorityLimiter::new(Invalid, 1, Duration::from_secs(60))
+            .with_backend(backend.clone(), "shared");
+        assert!(invalid.generate_next_message(&chat).await.is_err());
+
+        // The rejected call gave its token back, so this one goes at once
+        let first = PriorityLimiter::new(Echo, 1, Duration::from_secs(60))
+            .with_backend(backend.clone(), "shared");
+        first.generate_next_message(&chat).await.unwrap();
+        assert_eq!(first.stats(Priority::Interactive).max_wait, Duration::ZERO);
+
+        let second = PriorityLimiter::new(Echo, 1, Duration::from_secs(60))
+            .with_backend(backend, "shared");
+        second.generate_next_message(&chat).await.unwrap();
+        assert_eq!(
+            second.stats(Priority::Interactive).max_wait,
+            Duration::from_secs(60)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Named generation profiles
+//!
+//! A [`GenerationProfile`] bundles sampling settings under a name, so every
+//! application in a team can ask for `"precise"` instead of repeating a
+//! temperature. Three profiles are built in ([`PRECISE`], [`BALANCED`] and
+//! [`CREATIVE`]); more can be added at startup with [`register_profile`] or
+//! loaded from a JSON config with [`load_profiles`]. A registered profile
+//! replaces a built-in one of the same name.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::Chat;
+//! use language_barrier_core::profile::load_profiles;
+//!
+//! load_profiles(r#"{ "support-agent": { "temperature": 0.3, "max_output_tokens": 512 } }"#)
+//!     .unwrap();
+//!
+//! let chat = Chat::default().with_profile("support-agent").unwrap();
+//! assert_eq!(chat.temperature, Some(0.3));
+//! assert_eq!(chat.max_output_tokens, 512);
+//!
+//! let chat = Chat::default().with_profile("precise").unwrap();
+//! assert_eq!(chat.temperature, Some(0.0));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat::Chat;
+use crate::error::{ChatConfigError, Error, Result};
+
+/// Name of the built-in deterministic profile
+pub const PRECISE: &str = "precise";
+/// Name of the built-in general-purpose profile
+pub const BALANCED: &str = "balanced";
+/// Name of the built-in high-variety profile
+pub const CREATIVE: &str = "creative";
+
+static PROFILES: RwLock<Option<HashMap<String, GenerationProfile>>> = RwLock::new(None);
+
+/// Sampling settings applied to a chat together
+///
+/// Settings left as `None` keep whatever the chat already has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationProfile {
+    /// The sampling temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// The output token budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<usize>,
+}
+
+impl GenerationProfile {
+    /// Creates a profile that changes nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Temperature 0, for extraction, classification and code
+    pub fn precise() -> Self {
+        Self::new().with_temperature(0.0)
+    }
+
+    /// Temperature 0.7, a reasonable default for conversation
+    pub fn balanced() -> Self {
+        Self::new().with_temperature(0.7)
+    }
+
+    /// Temperature 1.0, for brainstorming and creative writing
+    pub fn creative() -> Self {
+        Self::new().with_temperature(1.0)
+    }
+
+    /// Sets the sampling temperature
+    #[must_use]
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        Self {
+            temperature: Some(temperature),
+            ..self
+        }
+    }
+
+    /// Sets the output token budget
+    #[must_use]
+    pub fn with_max_output_tokens(self, n: usize) -> Self {
+        Self {
+            max_output_tokens: Some(n),
+            ..self
+        }
+    }
+
+    /// Applies the profile's settings to `chat`
+    #[must_use]
+    pub fn apply(&self, chat: Chat) -> Chat {
+        let chat = match self.temperature {
+            Some(temperature) => chat.with_temperature(temperature),
+            None => chat,
+        };
+        match self.max_output_tokens {
+            Some(n) => chat.with_max_output_tokens(n),
+            None => chat,
+        }
+    }
+}
+
+/// Registers a profile under `name`, replacing any existing one
+pub fn register_profile(name: impl Into<String>, profile: GenerationProfile) {
+    PROFILES
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(name.into(), profile);
+}
+
+/// Registers every profile in a JSON object mapping names to profiles
+///
+/// # Errors
+///
+/// Returns `Error::Serialization` if `json` isn't such an object; no
+/// profiles are registered in that case.
+pub fn load_profiles(json: &str) -> Result<()> {
+    let profiles: HashMap<String, GenerationProfile> = serde_json::from_str(json)?;
+    for (name, profile) in profiles {
+        register_profile(name, profile);
+    }
+    Ok(())
+}
+
+/// Looks up a registered or built-in profile
+pub fn profile(name: &str) -> Option<GenerationProfile> {
+    let registered = PROFILES
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .and_then(|profiles| profiles.get(name).copied());
+
+    registered.or(match name {
+        PRECISE => Some(GenerationProfile::precise()),
+        BALANCED => Some(GenerationProfile::balanced()),
+        CREATIVE => Some(GenerationProfile::creative()),
+        _ => None,
+    })
+}
+
+/// Looks up a profile, failing if there's none by that name
+pub(crate) fn require_profile(name: &str) -> Result<GenerationProfile> {
+    profile(name).ok_or_else(|| {
+        Error::ChatConfig(ChatConfigError::UnknownProfile {
+            name: name.to_string(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_apply_only_their_settings() {
+        let chat = Chat::default().with_max_output_tokens(300);
+
+        let creative = GenerationProfile::creative().apply(chat.clone());
+        assert_eq!(creative.temperature, Some(1.0));
+        assert_eq!(creative.max_output_tokens, 300);
+
+        let short = GenerationProfile::new()
+            .with_max_output_tokens(50)
+            .apply(chat.with_temperature(0.2));
+        assert_eq!(short.temperature, Some(0.2));
+        assert_eq!(short.max_output_tokens, 50);
+    }
+
+    #[test]
+    fn test_registered_profiles_extend_built_ins() {
+        load_profiles(r#"{"test-support": {"temperature": 0.4}}"#).unwrap();
+        assert_eq!(
+            profile("test-support"),
+            Some(GenerationProfile::new().with_temperature(0.4))
+        );
+        assert_eq!(profile(BALANCED), Some(GenerationProfile::balanced()));
+
+        assert!(load_profiles(r#"{"broken": {"temperature": "hot"}}"#).is_err());
+        assert!(profile("broken").is_none());
+        assert!(matches!(
+            Chat::default().with_profile("no-such-profile"),
+            Err(Error::ChatConfig(ChatConfigError::UnknownProfile { .. }))
+        ));
+    }
+}
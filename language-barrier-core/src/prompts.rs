@@ -0,0 +1,337 @@
+//! A library of named, versioned prompt templates
+//!
+//! Prompts are identified as `name@version` (for example
+//! `support_agent@v3`) and contain `{{variable}}` placeholders. Register
+//! them once at startup, either embedded in the binary with
+//! [`include_str!`] or read from a directory with [`load_prompt_dir`], then
+//! apply one to a chat with [`Chat::with_prompt`]. Replies generated from
+//! that chat record the prompt under [`PromptRef::METADATA_KEY`] (see
+//! [`Message::prompt`]), so answers can be traced back to the exact
+//! template version that produced them.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::Chat;
+//! use language_barrier_core::prompts::{PromptTemplate, register_prompt};
+//!
+//! register_prompt(PromptTemplate::new(
+//!     "support_agent",
+//!     "v3",
+//!     "You are a support agent for {{product}}. Answer in {{language}}.",
+//! ));
+//!
+//! let chat = Chat::default()
+//!     .with_prompt("support_agent@v3", [("product", "Acme Cloud"), ("language", "French")])
+//!     .unwrap();
+//! assert_eq!(
+//!     chat.system_prompt,
+//!     "You are a support agent for Acme Cloud. Answer in French."
+//! );
+//! assert_eq!(chat.prompt.unwrap().to_string(), "support_agent@v3");
+//! ```
+//!
+//! [`Chat::with_prompt`]: crate::Chat::with_prompt
+//! [`Message::prompt`]: crate::Message::prompt
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ChatConfigError, Error, Result};
+
+static PROMPTS: RwLock<Option<HashMap<String, Vec<PromptTemplate>>>> = RwLock::new(None);
+
+/// Identifies one version of a prompt
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PromptRef {
+    /// The prompt's name
+    pub name: String,
+    /// The prompt's version
+    pub version: String,
+}
+
+impl PromptRef {
+    /// The message metadata key under which the producing prompt is stored
+    pub const METADATA_KEY: &'static str = "prompt";
+
+    /// Creates a reference to `name` at `version`
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+        }
+    }
+
+    /// Converts this reference into a metadata value
+    #[must_use]
+    pub fn to_metadata(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for PromptRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+/// A prompt template with `{{variable}}` placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTemplate {
+    /// Which prompt and version this is
+    pub id: PromptRef,
+    /// The template text
+    pub template: String,
+}
+
+impl PromptTemplate {
+    /// Creates a template for `name` at `version`
+    pub fn new(
+        name: impl Into<String>,
+        version: impl Into<String>,
+        template: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: PromptRef::new(name, version),
+            template: template.into(),
+        }
+    }
+
+    /// Fills in the template's placeholders
+    ///
+    /// Whitespace inside the braces is ignored, so `{{ name }}` and
+    /// `{{name}}` are the same placeholder. Variables the template doesn't
+    /// use are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatConfigError::MissingPromptVariable` if a placeholder has
+    /// no value.
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<String> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let variable = rest[start + 2..start + 2 + len].trim();
+            let value = vars.get(variable).ok_or_else(|| {
+                Error::ChatConfig(ChatConfigError::MissingPromptVariable {
+                    prompt: self.id.to_string(),
+                    variable: variable.to_string(),
+                })
+            })?;
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(value);
+            rest = &rest[start + 2 + len + 2..];
+        }
+
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+/// Registers a prompt, replacing any with the same name and version
+pub fn register_prompt(prompt: PromptTemplate) {
+    let mut prompts = PROMPTS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let versions = prompts
+        .get_or_insert_with(HashMap::new)
+        .entry(prompt.id.name.clone())
+        .or_default();
+    versions.retain(|existing| existing.id.version != prompt.id.version);
+    versions.push(prompt);
+}
+
+/// Registers every prompt file in `dir`
+///
+/// Files are named `<name>@<version>.<extension>`, for example
+/// `support_agent@v3.txt`, and contain the template text. Files without an
+/// `@` in their name are skipped. Returns the number of prompts registered.
+///
+/// # Errors
+///
+/// Returns `Error::Io` if the directory or a prompt file can't be read.
+pub fn load_prompt_dir(dir: impl AsRef<Path>) -> Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let Some((name, version)) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.split_once('@'))
+        else {
+            continue;
+        };
+        register_prompt(PromptTemplate::new(
+            name,
+            version,
+            fs::read_to_string(&path)?,
+        ));
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Looks up a prompt by `name@version`, or by bare `name` for its latest
+/// version
+///
+/// Versions are ordered by their numeric parts, so `v10` is later than
+/// `v9`.
+pub fn prompt(id: &str) -> Option<PromptTemplate> {
+    let prompts = PROMPTS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let prompts = prompts.as_ref()?;
+
+    match id.split_once('@') {
+        Some((name, version)) => prompts
+            .get(name)?
+            .iter()
+            .find(|prompt| prompt.id.version == version)
+            .cloned(),
+        None => prompts
+            .get(id)?
+            .iter()
+            .max_by(|a, b| compare_versions(&a.id.version, &b.id.version))
+            .cloned(),
+    }
+}
+
+/// Looks up and renders a prompt for [`Chat::with_prompt`]
+///
+/// [`Chat::with_prompt`]: crate::Chat::with_prompt
+pub(crate) fn render_prompt(
+    id: &str,
+    vars: &HashMap<String, String>,
+) -> Result<(PromptRef, String)> {
+    let template = prompt(id)
+        .ok_or_else(|| Error::ChatConfig(ChatConfigError::UnknownPrompt { id: id.to_string() }))?;
+    let text = template.render(vars)?;
+    Ok((template.id, text))
+}
+
+/// Compares versions by their runs of digits, falling back to the text
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let numbers = |version: &str| -> Vec<u64> {
+        version
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    };
+    numbers(a).cmp(&numbers(b)).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        let template =
+            PromptTemplate::new("greet", "v1", "Hello {{ name }}, welcome to {{place}}!");
+        assert_eq!(
+            template
+                .render(&vars(&[
+                    ("name", "Ada"),
+                    ("place", "Paris"),
+                    ("unused", "x")
+                ]))
+                .unwrap(),
+            "Hello Ada, welcome to Paris!"
+        );
+        assert!(matches!(
+            template.render(&vars(&[("name", "Ada")])),
+            Err(Error::ChatConfig(ChatConfigError::MissingPromptVariable { variable, .. }))
+                if variable == "place"
+        ));
+    }
+
+    #[test]
+    fn test_lookup_by_version_and_latest() {
+        for version in ["v2", "v10", "v9"] {
+            register_prompt(PromptTemplate::new("test_lookup", version, version));
+        }
+
+        assert_eq!(prompt("test_lookup@v9").unwrap().template, "v9");
+        assert_eq!(prompt("test_lookup").unwrap().id.version, "v10");
+        assert!(prompt("test_lookup@v3").is_none());
+        assert!(prompt("missing").is_none());
+    }
+
+    #[test]
+    fn test_load_prompt_dir() {
+        let dir = std::env::temp_dir().join(format!("prompts-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("test_dir_agent@v1.txt"), "Be {{tone}}.").unwrap();
+        fs::write(dir.join("README.md"), "not a prompt").unwrap();
+
+        assert_eq!(load_prompt_dir(&dir).unwrap(), 1);
+        let (id, text) = render_prompt("test_dir_agent", &vars(&[("tone", "brief")])).unwrap();
+        assert_eq!(id, PromptRef::new("test_dir_agent", "v1"));
+        assert_eq!(text, "Be brief.");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_replies_record_the_prompt_version() {
+        use crate::llm_service::{HTTPLlmService, LLMService};
+        use crate::provider::openai::OpenAIProvider;
+        use crate::transport::mock::{MockResponse, MockTransport};
+        use crate::{Chat, Message, OpenAi};
+        use std::sync::Arc;
+
+        let reply = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Bonjour"},
+                "finish_reason": "stop"
+            }]
+        });
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            Arc::new(MockTransport::new().with_fallback(MockResponse::ok(reply.to_string()))),
+        );
+        register_prompt(PromptTemplate::new(
+            "test_reply",
+            "v2",
+            "Answer in {{language}}.",
+        ));
+
+        let chat = Chat::default()
+            .with_prompt("test_reply", [("language", "French")])
+            .unwrap()
+            .add_message(Message::user("Hello"));
+        let message = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(message.prompt(), Some(PromptRef::new("test_reply", "v2")));
+
+        let plain = Chat::default().add_message(Message::user("Hello"));
+        let message = service.generate_next_message(&plain).await.unwrap();
+        assert_eq!(message.prompt(), None);
+    }
+}
@@ -1,23 +1,47 @@
+use crate::auth::{AuthProvider, Credential, set_bearer};
+use crate::chat::SystemSegment;
 use crate::error::{Error, Result};
-use crate::message::{Content, ContentPart, Message};
-use crate::model::Sonnet35Version;
-use crate::provider::HTTPProvider;
+#[cfg(feature = "multimodal")]
+use crate::message::ImageUrl;
+use crate::message::{Content, ContentPart, FinishReason, Message};
+use crate::model::Provider;
+use crate::provider::{
+    HTTPProvider, KeySource, check_api_key, check_base_url, check_header_value, classify_error,
+    payload_body, resolve_base_url,
+};
+use crate::secret::{Secret, deserialize_env_ref};
+use crate::signing::RequestSigner;
+use crate::tool::ToolChoice;
+use crate::transport::{HeaderValue, HttpRequest, Method};
+use crate::usage::Usage;
 use crate::{Chat, Claude, LlmToolInfo};
-use reqwest::{Method, Request, Url};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
 use tracing::{debug, error, info, instrument, trace, warn};
+use url::Url;
 
 /// Configuration for the Anthropic provider
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct AnthropicConfig {
-    /// API key for authentication
+    /// API key for authentication; `${env:VAR}` in a config file reads it
+    /// from `VAR`
+    #[serde(deserialize_with = "deserialize_env_ref")]
     pub api_key: String,
     /// Base URL for the API
     pub base_url: String,
     /// API version header
     pub api_version: String,
+    /// Credential strategy used instead of `api_key`, for tokens that
+    /// expire (see [`crate::auth`])
+    #[serde(skip)]
+    pub auth: Option<Arc<dyn AuthProvider>>,
+    /// Signs each request after its body is serialized, for gateways that
+    /// require it (see [`crate::signing`])
+    #[serde(skip)]
+    pub signer: Option<Arc<dyn RequestSigner>>,
 }
 
 impl Default for AnthropicConfig {
@@ -26,8 +50,161 @@ impl Default for AnthropicConfig {
             api_key: env::var("ANTHROPIC_API_KEY").unwrap_or_default(),
             base_url: "https://api.anthropic.com/v1".to_string(),
             api_version: "2023-06-01".to_string(),
+            auth: None,
+            signer: None,
+        }
+    }
+}
+
+impl AnthropicConfig {
+    /// Starts an [`AnthropicConfigBuilder`], which validates the config when
+    /// built
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::provider::anthropic::AnthropicConfig;
+    ///
+    /// let config = AnthropicConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://api.anthropic.com/v1")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> AnthropicConfigBuilder {
+        AnthropicConfigBuilder::default()
+    }
+
+    /// Sets the credential strategy used instead of `api_key` (see
+    /// [`crate::auth`]) and returns a new instance
+    #[must_use]
+    pub fn with_auth(self, auth: Arc<dyn AuthProvider>) -> Self {
+        Self {
+            auth: Some(auth),
+            ..self
+        }
+    }
+
+    /// Returns the credential strategy used instead of `api_key`, if any
+    pub fn auth(&self) -> Option<&Arc<dyn AuthProvider>> {
+        self.auth.as_ref()
+    }
+
+    /// Sets the signer for each request (see [`crate::signing`]) and
+    /// returns a new instance
+    #[must_use]
+    pub fn with_signer(self, signer: Arc<dyn RequestSigner>) -> Self {
+        Self {
+            signer: Some(signer),
+            ..self
         }
     }
+
+    /// Returns the request signer, if any
+    pub fn signer(&self) -> Option<&Arc<dyn RequestSigner>> {
+        self.signer.as_ref()
+    }
+
+    /// Checks the fields a request depends on, naming the first one that
+    /// can't work
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` for a base URL that isn't an absolute
+    /// http(s) URL, an API key that's missing (without `auth`) or has stray
+    /// whitespace, or a value that can't be sent in a header.
+    pub fn validate(&self) -> Result<()> {
+        check_base_url(Provider::Anthropic, &self.base_url)?;
+        check_api_key(
+            Provider::Anthropic,
+            &self.api_key,
+            "ANTHROPIC_API_KEY",
+            self.auth.is_some(),
+        )?;
+        check_header_value(Provider::Anthropic, "api_version", &self.api_version)?;
+        Ok(())
+    }
+}
+
+/// Builds an [`AnthropicConfig`], validating it on
+/// [`build`](AnthropicConfigBuilder::build)
+///
+/// Unset fields fall back to the environment: the API key to
+/// `ANTHROPIC_API_KEY` and the base URL to `ANTHROPIC_BASE_URL`, then to the
+/// public API.
+#[derive(Debug, Clone, Default)]
+pub struct AnthropicConfigBuilder {
+    api_key: KeySource,
+    base_url: Option<String>,
+    api_version: Option<String>,
+    auth: Option<Arc<dyn AuthProvider>>,
+    signer: Option<Arc<dyn RequestSigner>>,
+}
+
+impl AnthropicConfigBuilder {
+    /// Sets the API key
+    #[must_use]
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = KeySource::Value(key.into());
+        self
+    }
+
+    /// Reads the API key from the environment variable `var` at build time
+    #[must_use]
+    pub fn api_key_env(mut self, var: impl Into<String>) -> Self {
+        self.api_key = KeySource::Env(var.into());
+        self
+    }
+
+    /// Sets the base URL for the API
+    #[must_use]
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Sets the API version header
+    #[must_use]
+    pub fn api_version(mut self, version: impl Into<String>) -> Self {
+        self.api_version = Some(version.into());
+        self
+    }
+
+    /// Sets the credential strategy used instead of the API key
+    #[must_use]
+    pub fn auth(mut self, auth: Arc<dyn AuthProvider>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sets the request signer
+    #[must_use]
+    pub fn signer(mut self, signer: Arc<dyn RequestSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Resolves the environment fallbacks and validates the config
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` if the variable named by
+    /// [`api_key_env`](Self::api_key_env) isn't set, or if
+    /// [`AnthropicConfig::validate`] rejects the result.
+    pub fn build(self) -> Result<AnthropicConfig> {
+        let defaults = AnthropicConfig::default();
+        let config = AnthropicConfig {
+            api_key: self
+                .api_key
+                .resolve(Provider::Anthropic, "ANTHROPIC_API_KEY")?,
+            base_url: resolve_base_url(self.base_url, "ANTHROPIC_BASE_URL", &defaults.base_url),
+            api_version: self.api_version.unwrap_or(defaults.api_version),
+            auth: self.auth,
+            signer: self.signer,
+        };
+        config.validate()?;
+        Ok(config)
+    }
 }
 
 /// Implementation of the Anthropic provider
@@ -67,11 +244,12 @@ impl AnthropicProvider {
     /// ```
     /// use language_barrier_core::provider::anthropic::{AnthropicProvider, AnthropicConfig};
     ///
-    /// let config = AnthropicConfig {
-    ///     api_key: "your-api-key".to_string(),
-    ///     base_url: "https://api.anthropic.com/v1".to_string(),
-    ///     api_version: "2023-06-01".to_string(),
-    /// };
+    /// let config = AnthropicConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://api.anthropic.com/v1")
+    ///     .api_version("2023-06-01")
+    ///     .build()
+    ///     .unwrap();
     ///
     /// let provider = AnthropicProvider::with_config(config);
     /// ```
@@ -84,6 +262,17 @@ impl AnthropicProvider {
 
         Self { config }
     }
+
+    /// Creates a provider after checking `config` with
+    /// [`AnthropicConfig::validate`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` naming the first invalid field.
+    pub fn try_with_config(config: AnthropicConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self::with_config(config))
+    }
 }
 
 impl Default for AnthropicProvider {
@@ -93,7 +282,7 @@ impl Default for AnthropicProvider {
 }
 
 impl HTTPProvider<Claude> for AnthropicProvider {
-    fn accept(&self, model: Claude, chat: &Chat) -> Result<Request> {
+    fn accept(&self, model: Claude, chat: &Chat) -> Result<HttpRequest> {
         info!("Creating request for Claude model: {:?}", model);
         debug!("Messages in chat history: {}", chat.history.len());
 
@@ -110,8 +299,8 @@ impl HTTPProvider<Claude> for AnthropicProvider {
             }
         };
 
-        let mut request = Request::new(Method::POST, url);
-        debug!("Created request: {} {}", request.method(), request.url());
+        let mut request = HttpRequest::new(Method::POST, url);
+        debug!("Created request: {} {}", request.method, request.url);
 
         // Set headers
         debug!("Setting request headers");
@@ -139,15 +328,13 @@ impl HTTPProvider<Claude> for AnthropicProvider {
             }
         };
 
-        request.headers_mut().insert("x-api-key", api_key_header);
+        request.headers.insert("x-api-key", api_key_header);
+        request.headers.insert("Content-Type", content_type_header);
         request
-            .headers_mut()
-            .insert("Content-Type", content_type_header);
-        request
-            .headers_mut()
+            .headers
             .insert("anthropic-version", api_version_header);
 
-        trace!("Request headers set: {:#?}", request.headers());
+        trace!("Request headers set: {:#?}", request.headers);
 
         // Create the request payload
         debug!("Creating request payload");
@@ -168,7 +355,7 @@ impl HTTPProvider<Claude> for AnthropicProvider {
 
         // Set the request body
         debug!("Serializing request payload");
-        let body_bytes = match serde_json::to_vec(&payload) {
+        let body_bytes = match payload_body(&payload, chat, Provider::Anthropic) {
             Ok(bytes) => {
                 debug!("Payload serialized successfully ({} bytes)", bytes.len());
                 bytes
@@ -179,7 +366,7 @@ impl HTTPProvider<Claude> for AnthropicProvider {
             }
         };
 
-        *request.body_mut() = Some(body_bytes.into());
+        request.body = body_bytes;
         info!("Request created successfully");
 
         Ok(request)
@@ -189,29 +376,25 @@ impl HTTPProvider<Claude> for AnthropicProvider {
         info!("Parsing response from Anthropic API");
         trace!("Raw response: {}", raw_response_text);
 
-        // First check if it's an error response
-        if raw_response_text.contains("\"error\"") {
-            let error_response: serde_json::Value = match serde_json::from_str(&raw_response_text) {
-                Ok(err) => err,
-                Err(e) => {
-                    error!("Failed to parse error response: {}", e);
-                    error!("Raw response: {}", raw_response_text);
-                    return Err(Error::Serialization(e));
-                }
+        // First check if it's an error response. Matching on the body's
+        // "type" rather than its text keeps tool inputs that mention "error"
+        // from being mistaken for failures.
+        if let Ok(body) = serde_json::from_str::<serde_json::Value>(&raw_response_text)
+            && body.get("type").and_then(|t| t.as_str()) == Some("error")
+        {
+            let error = body.get("error");
+            let field = |name| {
+                error
+                    .and_then(|e| e.get(name))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
             };
-
-            if let Some(error) = error_response.get("error") {
-                if let Some(message) = error.get("message") {
-                    let error_message = message.as_str().unwrap_or("Unknown error");
-                    error!("Anthropic API returned an error: {}", error_message);
-                    return Err(Error::ProviderUnavailable(error_message.to_string()));
-                }
-            }
-
-            error!("Unknown error format in response: {}", raw_response_text);
-            return Err(Error::ProviderUnavailable(
-                "Unknown error from Anthropic API".to_string(),
-            ));
+            let message = match field("message") {
+                "" => "Unknown error from Anthropic API",
+                message => message,
+            };
+            error!("Anthropic API returned an error: {}", message);
+            return Err(classify_error([field("type")], message.to_string()));
         }
 
         debug!("Deserializing response JSON");
@@ -243,23 +426,60 @@ impl HTTPProvider<Claude> for AnthropicProvider {
 
         Ok(message)
     }
+
+    fn auth(&self) -> Option<Arc<dyn AuthProvider>> {
+        self.config.auth.clone()
+    }
+
+    // Keys go in `x-api-key`; tokens (e.g. on Vertex AI) replace it
+    fn authorize(&self, request: &mut HttpRequest, credential: &Credential) -> Result<()> {
+        match credential {
+            Credential::ApiKey(key) => {
+                let mut value = HeaderValue::from_str(key.inner())
+                    .map_err(|_| Error::Authentication("Invalid API key format".into()))?;
+                value.set_sensitive(true);
+                request.headers.insert("x-api-key", value);
+                Ok(())
+            }
+            Credential::Bearer(token) => {
+                request.headers.remove("x-api-key");
+                set_bearer(request, token)
+            }
+        }
+    }
+
+    fn idempotency_header(&self) -> Option<&'static str> {
+        Some("Idempotency-Key")
+    }
+
+    fn supports_prefill(&self) -> bool {
+        true
+    }
+
+    fn signer(&self) -> Option<Arc<dyn RequestSigner>> {
+        self.config.signer.clone()
+    }
+
+    fn preflight_request(&self) -> Result<Option<HttpRequest>> {
+        let url = Url::parse(&format!("{}/models", self.config.base_url))?;
+        let mut request = HttpRequest::new(Method::GET, url);
+        let version = HeaderValue::from_str(&self.config.api_version)
+            .map_err(|_| Error::Other("Invalid API version format".into()))?;
+        request.headers.insert("anthropic-version", version);
+        let key = Credential::ApiKey(Secret::new(self.config.api_key.clone()));
+        self.authorize(&mut request, &key)?;
+        Ok(Some(request))
+    }
+
+    fn provider(&self) -> Option<Provider> {
+        Some(Provider::Anthropic)
+    }
 }
 
 impl AnthropicProvider {
     #[instrument(level = "debug")]
-    fn id_for_model(model: Claude) -> &'static str {
-        let model_id = match model {
-            Claude::Sonnet37 { .. } => "claude-3-7-sonnet-latest",
-            Claude::Sonnet35 {
-                version: Sonnet35Version::V1,
-            } => "claude-3-5-sonnet-20240620",
-            Claude::Sonnet35 {
-                version: Sonnet35Version::V2,
-            } => "claude-3-5-sonnet-20241022",
-            Claude::Opus3 => "claude-3-opus-latest",
-            Claude::Haiku3 => "claude-3-haiku-20240307",
-            Claude::Haiku35 => "claude-3-5-haiku-latest",
-        };
+    pub(crate) fn id_for_model(model: Claude) -> &'static str {
+        let model_id = model.anthropic_model_id();
 
         debug!("Mapped Claude model to Anthropic model ID: {}", model_id);
         model_id
@@ -276,14 +496,27 @@ impl AnthropicProvider {
         debug!("Messages in history: {}", chat.history.len());
         debug!("Max output tokens: {}", chat.max_output_tokens);
 
-        // Convert system prompt if present
-        let system = if chat.system_prompt.is_empty() {
-            debug!("No system prompt provided");
-            None
+        let max_tokens = chat.max_output_tokens_for(&model)?;
+
+        // Convert system prompt if present.  Plain prompts are sent as a string;
+        // structured segments become an array of text blocks.
+        let system = if chat.system_segments.is_empty() {
+            if chat.system_prompt.is_empty() {
+                debug!("No system prompt provided");
+                None
+            } else {
+                debug!("Including system prompt in request");
+                trace!("System prompt: {}", chat.system_prompt);
+                Some(AnthropicSystemPrompt::Text(chat.system_prompt.clone()))
+            }
         } else {
-            debug!("Including system prompt in request");
-            trace!("System prompt: {}", chat.system_prompt);
-            Some(chat.system_prompt.clone())
+            let blocks: Vec<AnthropicSystemBlock> = chat
+                .system_blocks()
+                .iter()
+                .map(AnthropicSystemBlock::from)
+                .collect();
+            debug!("Including {} system prompt blocks in request", blocks.len());
+            Some(AnthropicSystemPrompt::Blocks(blocks))
         };
 
         // Convert messages
@@ -305,49 +538,41 @@ impl AnthropicProvider {
         debug!("Using model ID: {}", model_id);
 
         // Convert tool descriptions if a tool registry is provided
-        let tools = chat
+        let mut tools: Option<Vec<AnthropicTool>> = chat
             .tools
             .as_ref()
             .map(|tools| tools.iter().map(AnthropicTool::from).collect());
-        debug!("Tools configured: {:?}", tools);
 
-        // Note: For Anthropic, tool_choice is handled through the prompt and context
-        // We don't modify the tools list based on the choice
+        // Anthropic has no response format, so an output schema becomes a
+        // tool the model is forced to call; its arguments are the reply
+        if let Some(output) = &chat.output_schema {
+            tools.get_or_insert_with(Vec::new).push(AnthropicTool {
+                name: output.name.clone(),
+                description: "Reply by calling this tool with your answer".to_string(),
+                input_schema: output.schema.clone(),
+            });
+        }
+        debug!("Tools configured: {:?}", tools);
 
-        // Convert tool_choice to Anthropic's format
+        // Anthropic rejects `tool_choice` unless tools are also provided
         debug!("Processing tool_choice: {:?}", chat.tool_choice);
-        let tool_choice = if let Some(choice) = &chat.tool_choice {
-            let anthropic_choice = match choice {
-                crate::tool::ToolChoice::Auto => {
-                    debug!("Setting tool_choice to type:auto");
-                    Some(serde_json::json!({ "type": "auto" }))
-                }
-                // Anthropic uses "any" instead of "required" for what we call "Any"
-                crate::tool::ToolChoice::Any => {
-                    debug!("Setting tool_choice to type:any");
-                    Some(serde_json::json!({ "type": "any" }))
-                }
-                crate::tool::ToolChoice::None => {
-                    debug!("Setting tool_choice to type:none");
-                    Some(serde_json::json!({ "type": "none" }))
-                }
-                crate::tool::ToolChoice::Specific(name) => {
-                    // For specific tool, use this format
-                    debug!("Setting tool_choice to specific tool: {}", name);
-                    Some(serde_json::json!({
-                        "type": "function",
-                        "function": { "name": name }
-                    }))
-                }
-            };
-            debug!("Anthropic tool_choice value: {:?}", anthropic_choice);
-            anthropic_choice
+        let tool_choice = if let Some(output) = &chat.output_schema {
+            Some(AnthropicToolChoice::from(&ToolChoice::Specific(
+                output.name.clone(),
+            )))
         } else if tools.is_some() {
-            // Default to auto if tools are present but no choice specified
-            debug!("No tool_choice specified but tools are present, defaulting to type:auto");
-            Some(serde_json::json!({ "type": "auto" }))
+            let choice = chat.tool_choice.clone().unwrap_or_default();
+            let mut anthropic_choice = AnthropicToolChoice::from(&choice);
+            // `none` takes no parallelism setting since no tools will be called
+            if choice != ToolChoice::None {
+                anthropic_choice.disable_parallel_tool_use =
+                    chat.parallel_tool_calls.map(|enabled| !enabled);
+            }
+            Some(anthropic_choice)
         } else {
-            debug!("No tool_choice and no tools, setting to None");
+            if chat.tool_choice.is_some() {
+                warn!("tool_choice set without any tools, omitting it from the request");
+            }
             None
         };
 
@@ -359,8 +584,8 @@ impl AnthropicProvider {
             model: model_id,
             messages,
             system,
-            max_tokens: Some(chat.max_output_tokens),
-            temperature: None,
+            max_tokens: Some(max_tokens),
+            temperature: chat.temperature,
             top_p: None,
             top_k: None,
             tools,
@@ -397,6 +622,12 @@ pub(crate) enum AnthropicContentPart {
         /// The source of the image
         source: AnthropicImageSource,
     },
+    /// Document content (e.g. a PDF)
+    #[serde(rename = "document")]
+    Document {
+        /// The source of the document, which shares the image source shape
+        source: AnthropicImageSource,
+    },
     /// Tool result content (for tool responses)
     #[serde(rename = "tool_result")]
     ToolResult(AnthropicToolResponse),
@@ -419,18 +650,51 @@ impl AnthropicContentPart {
     }
 
     /// Create a new image content part
-    fn image(url: String) -> Self {
+    ///
+    /// `data:` URLs are unpacked into their MIME type and base64 payload.
+    #[cfg(feature = "multimodal")]
+    fn image(image_url: &ImageUrl) -> Self {
+        let (media_type, data) = match image_url.data_url_parts() {
+            Some((media_type, data)) => (media_type.to_string(), data.to_string()),
+            None => ("image/jpeg".to_string(), image_url.url.clone()),
+        };
+
         AnthropicContentPart::Image {
             source: AnthropicImageSource {
                 type_field: "base64".to_string(),
-                media_type: "image/jpeg".to_string(),
-                data: url,
+                media_type,
+                data,
             },
         }
     }
 }
 
-/// Represents the source of an image in an Anthropic message
+impl AnthropicContentPart {
+    /// Converts a message part, or returns `None` for audio, which Anthropic
+    /// doesn't accept
+    fn from_part(part: &ContentPart) -> Option<Self> {
+        match part {
+            ContentPart::Text { text } => Some(AnthropicContentPart::text(text.clone())),
+            #[cfg(feature = "multimodal")]
+            ContentPart::ImageUrl { image_url } => Some(AnthropicContentPart::image(image_url)),
+            #[cfg(feature = "multimodal")]
+            ContentPart::Document { document } => Some(AnthropicContentPart::Document {
+                source: AnthropicImageSource {
+                    type_field: "base64".to_string(),
+                    media_type: document.mime_type.clone(),
+                    data: document.data.clone(),
+                },
+            }),
+            #[cfg(feature = "multimodal")]
+            ContentPart::Audio { .. } => {
+                warn!("Anthropic doesn't support audio parts; dropping one");
+                None
+            }
+        }
+    }
+}
+
+/// Represents the source of an image or document in an Anthropic message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct AnthropicImageSource {
     /// The type of the image source (base64 or url)
@@ -443,11 +707,11 @@ pub(crate) struct AnthropicImageSource {
 }
 
 /// Represents a tool response in an Anthropic message
+///
+/// The `"type": "tool_result"` tag is written by the enclosing
+/// [`AnthropicContentPart::ToolResult`] variant.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct AnthropicToolResponse {
-    /// The type of the tool response
-    #[serde(rename = "type")]
-    pub type_field: String,
     /// The ID of the tool call (Anthropic API uses `tool_use_id`, but we use `tool_call_id` internally)
     #[serde(rename = "tool_use_id")]
     pub tool_call_id: String,
@@ -475,7 +739,7 @@ pub(crate) struct AnthropicRequest {
     pub messages: Vec<AnthropicMessage>,
     /// The system prompt (optional)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub system: Option<String>,
+    pub system: Option<AnthropicSystemPrompt>,
     /// The maximum number of tokens to generate
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
@@ -493,7 +757,52 @@ pub(crate) struct AnthropicRequest {
     pub tools: Option<Vec<AnthropicTool>>,
     /// Tool choice mode
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_choice: Option<serde_json::Value>,
+    pub tool_choice: Option<AnthropicToolChoice>,
+}
+
+/// Represents the system prompt in the Anthropic API format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum AnthropicSystemPrompt {
+    /// A single plain-text system prompt
+    Text(String),
+    /// An ordered list of system prompt blocks
+    Blocks(Vec<AnthropicSystemBlock>),
+}
+
+/// Represents one text block of a structured Anthropic system prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AnthropicSystemBlock {
+    /// The type of the block (always "text")
+    #[serde(rename = "type")]
+    pub type_field: String,
+    /// The text content
+    pub text: String,
+    /// Prompt caching directive, set for cacheable segments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<AnthropicCacheControl>,
+}
+
+/// Represents a prompt caching directive in the Anthropic API format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AnthropicCacheControl {
+    /// The cache type (currently only "ephemeral")
+    #[serde(rename = "type")]
+    pub type_field: String,
+}
+
+/// Represents the `tool_choice` setting in the Anthropic API format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AnthropicToolChoice {
+    /// The choice mode (auto, any, tool or none)
+    #[serde(rename = "type")]
+    pub type_field: String,
+    /// The tool to call, only set when `type` is `tool`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Limits the model to at most one tool call per response when true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_parallel_tool_use: Option<bool>,
 }
 
 /// Represents a response from the Anthropic API
@@ -536,6 +845,20 @@ pub(crate) enum AnthropicResponseContent {
         /// The input to the tool
         input: serde_json::Value,
     },
+    /// Extended thinking content
+    #[serde(rename = "thinking")]
+    Thinking {
+        /// The model's reasoning
+        thinking: String,
+        /// Signature to send back with the block on the next turn
+        signature: String,
+    },
+    /// Thinking content encrypted by the safety systems
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking {
+        /// The encrypted reasoning
+        data: String,
+    },
 }
 
 /// Represents usage information in an Anthropic response
@@ -545,6 +868,26 @@ pub(crate) struct AnthropicUsage {
     pub input_tokens: u32,
     /// Number of tokens in the output
     pub output_tokens: u32,
+    /// Number of input tokens read from the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
+    /// Number of input tokens written to the prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+}
+
+impl From<&AnthropicUsage> for Usage {
+    fn from(usage: &AnthropicUsage) -> Self {
+        // Anthropic reports cache reads and writes separately from `input_tokens`
+        let cache_read = u64::from(usage.cache_read_input_tokens.unwrap_or(0));
+        let cache_creation = u64::from(usage.cache_creation_input_tokens.unwrap_or(0));
+
+        Usage::new(
+            u64::from(usage.input_tokens) + cache_read + cache_creation,
+            u64::from(usage.output_tokens),
+        )
+        .with_cached_tokens(cache_read)
+    }
 }
 
 /// Convert from our Message to Anthropic's message format
@@ -563,12 +906,7 @@ impl From<&Message> for AnthropicMessage {
                 Content::Text(text) => vec![AnthropicContentPart::text(text.clone())],
                 Content::Parts(parts) => parts
                     .iter()
-                    .map(|part| match part {
-                        ContentPart::Text { text } => AnthropicContentPart::text(text.clone()),
-                        ContentPart::ImageUrl { image_url } => {
-                            AnthropicContentPart::image(image_url.url.clone())
-                        }
-                    })
+                    .filter_map(AnthropicContentPart::from_part)
                     .collect(),
             },
             Message::Assistant {
@@ -577,16 +915,14 @@ impl From<&Message> for AnthropicMessage {
                 ..
             } => {
                 // Start with any textual or multimodal content parts, if provided
+                // Anthropic rejects empty text blocks, which tool-call-only turns
+                // often carry, so those are dropped.
                 let mut parts: Vec<AnthropicContentPart> = match content {
+                    Some(Content::Text(text)) if text.is_empty() => Vec::new(),
                     Some(Content::Text(text)) => vec![AnthropicContentPart::text(text.clone())],
                     Some(Content::Parts(parts)) => parts
                         .iter()
-                        .map(|part| match part {
-                            ContentPart::Text { text } => AnthropicContentPart::text(text.clone()),
-                            ContentPart::ImageUrl { image_url } => {
-                                AnthropicContentPart::image(image_url.url.clone())
-                            }
-                        })
+                        .filter_map(AnthropicContentPart::from_part)
                         .collect(),
                     None => Vec::new(),
                 };
@@ -595,8 +931,13 @@ impl From<&Message> for AnthropicMessage {
                 for call in tool_calls {
                     let parsed_args: serde_json::Value =
                         serde_json::from_str(&call.function.arguments).unwrap_or_else(|_| {
-                            // Fallback to a raw string if JSON parsing fails
-                            serde_json::Value::String(call.function.arguments.clone())
+                            // `input` must be an object, so fall back to an empty one
+                            // if the stored arguments aren't valid JSON
+                            warn!(
+                                "Tool call {} has invalid JSON arguments, sending empty input",
+                                call.id
+                            );
+                            serde_json::json!({})
                         });
 
                     parts.push(AnthropicContentPart::ToolUse {
@@ -621,7 +962,6 @@ impl From<&Message> for AnthropicMessage {
             } => {
                 // For tool messages, add a tool_result part
                 vec![AnthropicContentPart::ToolResult(AnthropicToolResponse {
-                    type_field: "tool_result".to_string(),
                     tool_call_id: tool_call_id.clone(),
                     content: content.clone(),
                 })]
@@ -642,6 +982,10 @@ impl From<&AnthropicResponseContent> for ContentPart {
                 let text = format!("{{\"id\":\"{id}\",\"name\":\"{name}\",\"input\":{input}}}");
                 ContentPart::text(text)
             }
+            AnthropicResponseContent::Thinking { thinking, .. } => {
+                ContentPart::text(thinking.clone())
+            }
+            AnthropicResponseContent::RedactedThinking { .. } => ContentPart::text(String::new()),
         }
     }
 }
@@ -652,6 +996,7 @@ impl From<&AnthropicResponse> for Message {
         // Extract text content and tool calls from response content
         let mut text_content = Vec::new();
         let mut tool_calls = Vec::new();
+        let mut thinking = Vec::new();
 
         for content_part in &response.content {
             match content_part {
@@ -673,6 +1018,11 @@ impl From<&AnthropicResponse> for Message {
 
                     tool_calls.push(tool_call);
                 }
+                // Reasoning isn't part of the reply; it's kept in metadata
+                AnthropicResponseContent::Thinking { thinking: text, .. } => {
+                    thinking.push(text.clone());
+                }
+                AnthropicResponseContent::RedactedThinking { .. } => {}
             }
         }
 
@@ -682,7 +1032,10 @@ impl From<&AnthropicResponse> for Message {
         } else if text_content.len() == 1 {
             match &text_content[0] {
                 ContentPart::Text { text } => Some(Content::Text(text.clone())),
-                ContentPart::ImageUrl { .. } => Some(Content::Parts(text_content)),
+                #[cfg(feature = "multimodal")]
+                ContentPart::ImageUrl { .. }
+                | ContentPart::Document { .. }
+                | ContentPart::Audio { .. } => Some(Content::Parts(text_content)),
             }
         } else {
             Some(Content::Parts(text_content))
@@ -730,11 +1083,55 @@ impl From<&AnthropicResponse> for Message {
             "output_tokens",
             serde_json::Value::Number(response.usage.output_tokens.into()),
         );
+        msg = msg.with_metadata(
+            Usage::METADATA_KEY,
+            Usage::from(&response.usage).to_metadata(),
+        );
+        if let Some(reason) = &response.stop_reason {
+            msg = msg.with_metadata(
+                FinishReason::METADATA_KEY,
+                FinishReason::from_provider(reason).to_metadata(),
+            );
+        }
+        if !thinking.is_empty() {
+            msg = msg.with_metadata("thinking", serde_json::Value::String(thinking.join("\n\n")));
+        }
 
         msg
     }
 }
 
+impl From<&SystemSegment> for AnthropicSystemBlock {
+    fn from(segment: &SystemSegment) -> Self {
+        AnthropicSystemBlock {
+            type_field: "text".to_string(),
+            text: segment.text.clone(),
+            cache_control: segment.cacheable.then(|| AnthropicCacheControl {
+                type_field: "ephemeral".to_string(),
+            }),
+        }
+    }
+}
+
+impl From<&ToolChoice> for AnthropicToolChoice {
+    fn from(choice: &ToolChoice) -> Self {
+        let (type_field, name) = match choice {
+            ToolChoice::Auto => ("auto", None),
+            // Anthropic uses "any" for what OpenAI calls "required"
+            ToolChoice::Any => ("any", None),
+            ToolChoice::None => ("none", None),
+            ToolChoice::Specific(name) => ("tool", Some(name.clone())),
+        };
+        debug!("Mapped tool_choice {:?} to type:{}", choice, type_field);
+
+        AnthropicToolChoice {
+            type_field: type_field.to_string(),
+            name,
+            disable_parallel_tool_use: None,
+        }
+    }
+}
+
 impl From<&LlmToolInfo> for AnthropicTool {
     fn from(value: &LlmToolInfo) -> Self {
         AnthropicTool {
@@ -748,9 +1145,41 @@ impl From<&LlmToolInfo> for AnthropicTool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::arbitrary::{assert_lossless, response_message, round_trip};
+    use proptest::prelude::*;
 
     use crate::message::{Content, ContentPart, Message};
 
+    #[test]
+    fn test_config_builder() {
+        let config = AnthropicConfig::builder()
+            .api_key("sk-ant-test")
+            .base_url("https://gateway.example.com/v1")
+            .build()
+            .unwrap();
+        assert_eq!(config.api_key, "sk-ant-test");
+        assert_eq!(config.base_url, "https://gateway.example.com/v1");
+        assert_eq!(config.api_version, "2023-06-01");
+
+        let unset = AnthropicConfig::builder()
+            .api_key_env("LANGUAGE_BARRIER_TEST_UNSET_KEY")
+            .build();
+        match unset {
+            Err(Error::ProviderConfig(e)) => {
+                assert_eq!(e.field, "api_key");
+                assert!(e.problem.contains("LANGUAGE_BARRIER_TEST_UNSET_KEY"));
+            }
+            other => panic!("expected a config error, got {other:?}"),
+        }
+
+        let bad_url = AnthropicConfig::builder()
+            .api_key("sk-ant-test")
+            .base_url("not a url")
+            .build();
+        assert!(matches!(bad_url, Err(Error::ProviderConfig(e)) if e.field == "base_url"));
+    }
+
+    #[cfg(feature = "multimodal")]
     #[test]
     fn test_message_to_anthropic_conversion() {
         // Test simple text message
@@ -810,7 +1239,6 @@ mod tests {
         // Verify tool content
         match &anthropic_msg.content[0] {
             AnthropicContentPart::ToolResult(tool_result) => {
-                assert_eq!(tool_result.type_field, "tool_result");
                 assert_eq!(tool_result.tool_call_id, "tool_call_123");
                 assert_eq!(tool_result.content, "The weather is sunny.");
             }
@@ -833,6 +1261,8 @@ mod tests {
             usage: AnthropicUsage {
                 input_tokens: 10,
                 output_tokens: 20,
+                cache_read_input_tokens: None,
+                cache_creation_input_tokens: None,
             },
         };
 
@@ -887,6 +1317,8 @@ mod tests {
             usage: AnthropicUsage {
                 input_tokens: 15,
                 output_tokens: 30,
+                cache_read_input_tokens: None,
+                cache_creation_input_tokens: None,
             },
         };
 
@@ -910,6 +1342,7 @@ mod tests {
                             ContentPart::Text { text } => {
                                 assert_eq!(text, "Here's the information:")
                             }
+                            #[cfg(feature = "multimodal")]
                             _ => panic!("Expected text content"),
                         }
                     }
@@ -933,6 +1366,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_anthropic_response_usage_includes_cache_tokens() {
+        let response = AnthropicResponse {
+            id: "msg_123".to_string(),
+            type_field: "message".to_string(),
+            role: "assistant".to_string(),
+            model: "claude-3-7-sonnet-latest".to_string(),
+            stop_reason: Some("end_turn".to_string()),
+            content: vec![AnthropicResponseContent::Text {
+                text: "Cached!".to_string(),
+            }],
+            usage: AnthropicUsage {
+                input_tokens: 10,
+                output_tokens: 20,
+                cache_read_input_tokens: Some(1_000),
+                cache_creation_input_tokens: Some(50),
+            },
+        };
+
+        let msg = Message::from(&response);
+        assert_eq!(
+            msg.usage(),
+            Some(Usage::new(1_060, 20).with_cached_tokens(1_000))
+        );
+    }
+
     #[test]
     fn test_anthropic_response_with_tool_use_only() {
         // Test response with only tool use (no text)
@@ -952,6 +1411,8 @@ mod tests {
             usage: AnthropicUsage {
                 input_tokens: 5,
                 output_tokens: 10,
+                cache_read_input_tokens: None,
+                cache_creation_input_tokens: None,
             },
         };
 
@@ -1018,6 +1479,8 @@ mod tests {
             usage: AnthropicUsage {
                 input_tokens: 5,
                 output_tokens: 15,
+                cache_read_input_tokens: None,
+                cache_creation_input_tokens: None,
             },
         };
 
@@ -1097,6 +1560,8 @@ mod tests {
             api_key: "test-api-key".to_string(),
             base_url: "https://api.anthropic.com/v1".to_string(),
             api_version: "2023-06-01".to_string(),
+            auth: None,
+            signer: None,
         };
         let provider = AnthropicProvider::with_config(config);
 
@@ -1115,13 +1580,325 @@ mod tests {
         let request = provider.accept(model, &chat).unwrap();
 
         // Verify the request
-        assert_eq!(request.method(), "POST");
+        assert_eq!(request.method, "POST");
         assert_eq!(
-            request.url().as_str(),
+            request.url.as_str(),
             "https://api.anthropic.com/v1/messages"
         );
-        assert_eq!(request.headers()["x-api-key"], "test-api-key");
-        assert_eq!(request.headers()["anthropic-version"], "2023-06-01");
-        assert_eq!(request.headers()["Content-Type"], "application/json");
+        assert_eq!(request.headers["x-api-key"], "test-api-key");
+        assert_eq!(request.headers["anthropic-version"], "2023-06-01");
+        assert_eq!(request.headers["Content-Type"], "application/json");
+    }
+
+    const CALL_ID: &str = "toolu_01A09q90qw90lq917835lq9";
+
+    /// Helper to build a fresh `Chat` with the weather tool registered.
+    fn base_chat_with_tool() -> Chat {
+        Chat::default().with_tools(vec![LlmToolInfo {
+            name: "get_weather".to_string(),
+            description: "Get current temperature for a given location.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "location": { "type": "string" }
+                },
+                "required": ["location"]
+            }),
+        }])
+    }
+
+    /// Helper that builds the assistant turn requesting the weather tool.
+    fn weather_call(content: Option<Content>) -> Message {
+        use crate::message::{Function, ToolCall};
+
+        Message::Assistant {
+            content,
+            tool_calls: vec![ToolCall {
+                id: CALL_ID.to_string(),
+                tool_type: "function".to_string(),
+                function: Function {
+                    name: "get_weather".to_string(),
+                    arguments: "{\"location\":\"Paris, France\"}".to_string(),
+                },
+            }],
+            metadata: HashMap::default(),
+        }
+    }
+
+    /// Stage-2: the assistant's earlier tool call is resent as a `tool_use`
+    /// block carrying the original ID, name and parsed input.
+    #[test]
+    fn test_stage2_assistant_tool_use_serialization() {
+        let chat = base_chat_with_tool()
+            .add_message(Message::user("What is the weather like in Paris today?"))
+            .add_message(weather_call(Some(Content::Text(
+                "Let me check that for you.".to_string(),
+            ))));
+
+        let request = AnthropicProvider::new()
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+
+        assert_eq!(request.messages.len(), 2);
+        let assistant = serde_json::to_value(&request.messages[1]).unwrap();
+        assert_eq!(
+            assistant,
+            serde_json::json!({
+                "role": "assistant",
+                "content": [
+                    { "type": "text", "text": "Let me check that for you." },
+                    {
+                        "type": "tool_use",
+                        "id": CALL_ID,
+                        "name": "get_weather",
+                        "input": { "location": "Paris, France" }
+                    }
+                ]
+            })
+        );
+    }
+
+    /// Stage-2b: a tool-call-only turn with an empty text body must not emit an
+    /// empty text block, which the API rejects.
+    #[test]
+    fn test_stage2_empty_text_is_dropped() {
+        let msg = weather_call(Some(Content::Text(String::new())));
+        let anthropic_msg = AnthropicMessage::from(&msg);
+
+        assert_eq!(anthropic_msg.content.len(), 1);
+        assert!(matches!(
+            anthropic_msg.content[0],
+            AnthropicContentPart::ToolUse { .. }
+        ));
+    }
+
+    /// Stage-3: the tool result follows as a user-side `tool_result` block that
+    /// references the `tool_use` ID, with a single `type` key.
+    #[test]
+    fn test_stage3_tool_result_serialization() {
+        let chat = base_chat_with_tool()
+            .add_message(Message::user("What is the weather like in Paris today?"))
+            .add_message(weather_call(None))
+            .add_message(Message::tool(CALL_ID, "10C"));
+
+        let request = AnthropicProvider::new()
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+
+        let roles: Vec<_> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+
+        let body = serde_json::to_string(&request.messages[2]).unwrap();
+        assert_eq!(
+            body,
+            format!(
+                r#"{{"role":"user","content":[{{"type":"tool_result","tool_use_id":"{CALL_ID}","content":"10C"}}]}}"#
+            )
+        );
+    }
+
+    /// Stage-4: the whole exchange, including the final answer, serializes in
+    /// order with every `tool_use` paired to its `tool_result`.
+    #[test]
+    fn test_stage4_full_conversation_serialization() {
+        let chat = base_chat_with_tool()
+            .add_message(Message::user("What is the weather like in Paris today?"))
+            .add_message(weather_call(None))
+            .add_message(Message::tool(CALL_ID, "10C"))
+            .add_message(Message::assistant("It is 10°C in Paris today."));
+
+        let request = AnthropicProvider::new()
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+
+        let roles: Vec<_> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user", "assistant"]);
+
+        let payload = serde_json::to_value(&request).unwrap();
+        assert_eq!(payload["messages"][1]["content"][0]["id"], CALL_ID);
+        assert_eq!(payload["messages"][2]["content"][0]["tool_use_id"], CALL_ID);
+        assert_eq!(
+            payload["messages"][3]["content"][0]["text"],
+            "It is 10°C in Paris today."
+        );
+    }
+
+    #[test]
+    fn test_tool_choice_serialization() {
+        let cases = [
+            (ToolChoice::Auto, serde_json::json!({ "type": "auto" })),
+            (ToolChoice::Any, serde_json::json!({ "type": "any" })),
+            (ToolChoice::None, serde_json::json!({ "type": "none" })),
+            (
+                ToolChoice::Specific("get_weather".to_string()),
+                serde_json::json!({ "type": "tool", "name": "get_weather" }),
+            ),
+        ];
+
+        for (choice, expected) in cases {
+            let chat = base_chat_with_tool().with_tool_choice(choice);
+            let request = AnthropicProvider::new()
+                .create_request_payload(Claude::Haiku35, &chat)
+                .expect("payload generation failed");
+
+            let payload = serde_json::to_value(&request).unwrap();
+            assert_eq!(payload["tool_choice"], expected);
+        }
+    }
+
+    #[test]
+    fn test_tool_choice_defaults_to_auto_with_tools() {
+        let chat = base_chat_with_tool();
+        let request = AnthropicProvider::new()
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+
+        let choice = request.tool_choice.expect("tool_choice should be set");
+        assert_eq!(choice.type_field, "auto");
+        assert_eq!(choice.disable_parallel_tool_use, None);
+    }
+
+    #[test]
+    fn test_tool_choice_omitted_without_tools() {
+        let chat = Chat::default().with_tool_choice(ToolChoice::Any);
+        let request = AnthropicProvider::new()
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+
+        let payload = serde_json::to_value(&request).unwrap();
+        assert!(payload.get("tool_choice").is_none());
+        assert!(payload.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_tool_choice_disable_parallel_tool_use_serialization() {
+        let choice = AnthropicToolChoice {
+            disable_parallel_tool_use: Some(true),
+            ..AnthropicToolChoice::from(&ToolChoice::Any)
+        };
+
+        assert_eq!(
+            serde_json::to_string(&choice).unwrap(),
+            r#"{"type":"any","disable_parallel_tool_use":true}"#
+        );
+    }
+
+    #[test]
+    fn test_parallel_tool_calls_maps_to_disable_parallel_tool_use() {
+        let provider = AnthropicProvider::new();
+
+        let chat = base_chat_with_tool().with_parallel_tool_calls(false);
+        let request = provider
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+        let payload = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            payload["tool_choice"],
+            serde_json::json!({ "type": "auto", "disable_parallel_tool_use": true })
+        );
+
+        let chat = base_chat_with_tool()
+            .with_tool_choice(ToolChoice::None)
+            .with_parallel_tool_calls(false);
+        let request = provider
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+        let choice = request.tool_choice.expect("tool_choice should be set");
+        assert_eq!(choice.disable_parallel_tool_use, None);
+    }
+
+    #[test]
+    fn test_system_segments_serialize_as_blocks() {
+        let chat = Chat::default()
+            .with_system_prompt("You are a helpful assistant.")
+            .with_system_segment(SystemSegment::new("Reference docs: ...").cacheable())
+            .add_message(Message::user("Hello"));
+
+        let request = AnthropicProvider::new()
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+        let payload = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(
+            payload["system"],
+            serde_json::json!([
+                { "type": "text", "text": "You are a helpful assistant." },
+                {
+                    "type": "text",
+                    "text": "Reference docs: ...",
+                    "cache_control": { "type": "ephemeral" }
+                }
+            ])
+        );
+
+        // A plain prompt keeps the simple string form
+        let chat = Chat::default().with_system_prompt("You are a helpful assistant.");
+        let request = AnthropicProvider::new()
+            .create_request_payload(Claude::Haiku35, &chat)
+            .expect("payload generation failed");
+        let payload = serde_json::to_value(&request).unwrap();
+        assert_eq!(payload["system"], "You are a helpful assistant.");
+    }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_inline_attachments_conversion() {
+        let msg = Message::user("What's in these?")
+            .with_image_bytes([1u8, 2, 3], "image/png")
+            .with_document(b"%PDF", "application/pdf");
+        let anthropic_msg = AnthropicMessage::from(&msg);
+
+        let serialized = serde_json::to_value(&anthropic_msg.content).unwrap();
+        assert_eq!(
+            serialized,
+            serde_json::json!([
+                { "type": "text", "text": "What's in these?" },
+                {
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": "image/png", "data": "AQID" }
+                },
+                {
+                    "type": "document",
+                    "source": {
+                        "type": "base64",
+                        "media_type": "application/pdf",
+                        "data": "JVBERg=="
+                    }
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_request_payload_snapshots() {
+        let provider = AnthropicProvider::default();
+        for (name, chat) in crate::provider::corpus::chats() {
+            let payload = provider
+                .create_request_payload(Claude::Haiku3, &chat)
+                .unwrap();
+            insta::assert_json_snapshot!(name, payload);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_response_round_trip_is_lossless(message in response_message()) {
+            let provider = AnthropicProvider::default();
+            let returned = round_trip(&provider, Claude::Haiku3, &message, "messages", |message| {
+                serde_json::json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-3-haiku-20240307",
+                "stop_reason": "end_turn",
+                "content": message["content"],
+                "usage": {"input_tokens": 1, "output_tokens": 1}
+            })
+            })
+            .unwrap();
+            assert_lossless(&message, &returned, true);
+        }
     }
 }
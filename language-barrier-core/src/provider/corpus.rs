@@ -0,0 +1,82 @@
+//! Chats shared by every provider's request snapshot tests
+//!
+//! Each provider renders these through its payload builder and compares the
+//! result with the snapshots under `src/provider/snapshots`. After an
+//! intended wire-format change, review and accept the new snapshots with
+//! `cargo insta review`.
+
+use serde_json::json;
+
+use crate::Chat;
+#[cfg(feature = "multimodal")]
+use crate::message::ContentPart;
+use crate::message::{Function, Message, ToolCall};
+use crate::tool::LlmToolInfo;
+
+/// Returns the corpus as `(name, chat)` pairs
+pub(crate) fn chats() -> Vec<(&'static str, Chat)> {
+    vec![
+        ("simple", simple()),
+        #[cfg(feature = "multimodal")]
+        ("multimodal", multimodal()),
+        ("tools", tools()),
+        ("choice_output", choice_output()),
+    ]
+}
+
+/// A system prompt, sampling settings and one user turn
+fn simple() -> Chat {
+    Chat::default()
+        .with_system_prompt("You are a helpful assistant.")
+        .with_max_output_tokens(256)
+        .with_temperature(0.5)
+        .add_message(Message::user("Hello, how are you?"))
+}
+
+#[cfg(feature = "multimodal")]
+/// A user turn with text, a linked image and an inline image
+fn multimodal() -> Chat {
+    Chat::default().add_message(Message::user_with_parts(vec![
+        ContentPart::text("What is in these pictures?"),
+        ContentPart::image_url("https://example.com/cat.png"),
+        ContentPart::image_bytes([0x89, b'P', b'N', b'G'], "image/png"),
+    ]))
+}
+
+/// A complete tool-calling exchange: call, result, and final answer
+fn tools() -> Chat {
+    let weather = LlmToolInfo {
+        name: "get_weather".to_string(),
+        description: "Get current temperature for a given location.".to_string(),
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "location": { "type": "string", "description": "City and country" }
+            },
+            "required": ["location"]
+        }),
+    };
+    let call = ToolCall {
+        id: "call_weather_1".to_string(),
+        tool_type: "function".to_string(),
+        function: Function {
+            name: "get_weather".to_string(),
+            arguments: r#"{"location":"Paris, France"}"#.to_string(),
+        },
+    };
+
+    Chat::default()
+        .with_system_prompt("Use tools when they help.")
+        .with_tools(vec![weather])
+        .add_message(Message::user("What's the weather in Paris?"))
+        .add_message(Message::assistant_with_tool_calls(vec![call]))
+        .add_message(Message::tool("call_weather_1", r#"{"temperature_c":10}"#))
+        .add_message(Message::assistant("It's 10°C in Paris."))
+}
+
+/// A reply constrained to one of a few choices
+fn choice_output() -> Chat {
+    Chat::default()
+        .with_choice_output(["approve", "reject", "escalate"])
+        .add_message(Message::user("Refund $40 for a damaged parcel?"))
+}
@@ -1,19 +1,48 @@
-use crate::error::{Error, Result};
-use crate::message::{Content, ContentPart, Message};
-use crate::provider::HTTPProvider;
+use crate::auth::{AuthProvider, Credential, set_bearer};
+use crate::error::{ApiError, Error, QuotaViolation, Result};
+use crate::message::{Content, ContentPart, FinishReason, Message};
+use crate::model::Provider;
+use crate::provider::{
+    HTTPProvider, KeySource, check_api_key, check_base_url, classify_error, payload_body,
+    resolve_base_url,
+};
+use crate::secret::{Secret, deserialize_env_ref};
+use crate::signing::RequestSigner;
+use crate::transport::{HeaderValue, HttpRequest, Method};
+use crate::usage::Usage;
 use crate::{Chat, Gemini, LlmToolInfo};
-use reqwest::{Method, Request, Url};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{debug, error, info, instrument, trace, warn};
+use url::Url;
+
+#[cfg(feature = "multimodal")]
+use crate::provider::RequestPreparer;
+
+#[cfg(feature = "multimodal")]
+pub mod files;
 
 /// Configuration for the Gemini provider
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct GeminiConfig {
-    /// API key for authentication
+    /// API key for authentication; `${env:VAR}` in a config file reads it
+    /// from `VAR`
+    #[serde(deserialize_with = "deserialize_env_ref")]
     pub api_key: String,
     /// Base URL for the API
     pub base_url: String,
+    /// Credential strategy used instead of `api_key`, for tokens that
+    /// expire (see [`crate::auth`])
+    #[serde(skip)]
+    pub auth: Option<Arc<dyn AuthProvider>>,
+    /// Signs each request after its body is serialized, for gateways that
+    /// require it (see [`crate::signing`])
+    #[serde(skip)]
+    pub signer: Option<Arc<dyn RequestSigner>>,
 }
 
 impl Default for GeminiConfig {
@@ -21,15 +50,157 @@ impl Default for GeminiConfig {
         Self {
             api_key: env::var("GEMINI_API_KEY").unwrap_or_default(),
             base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            auth: None,
+            signer: None,
         }
     }
 }
 
+impl GeminiConfig {
+    /// Starts a [`GeminiConfigBuilder`], which validates the config when built
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::provider::gemini::GeminiConfig;
+    ///
+    /// let config = GeminiConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://generativelanguage.googleapis.com/v1beta")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> GeminiConfigBuilder {
+        GeminiConfigBuilder::default()
+    }
+
+    /// Sets the credential strategy used instead of `api_key` (see
+    /// [`crate::auth`]) and returns a new instance
+    #[must_use]
+    pub fn with_auth(self, auth: Arc<dyn AuthProvider>) -> Self {
+        Self {
+            auth: Some(auth),
+            ..self
+        }
+    }
+
+    /// Returns the credential strategy used instead of `api_key`, if any
+    pub fn auth(&self) -> Option<&Arc<dyn AuthProvider>> {
+        self.auth.as_ref()
+    }
+
+    /// Sets the signer for each request (see [`crate::signing`]) and
+    /// returns a new instance
+    #[must_use]
+    pub fn with_signer(self, signer: Arc<dyn RequestSigner>) -> Self {
+        Self {
+            signer: Some(signer),
+            ..self
+        }
+    }
+
+    /// Returns the request signer, if any
+    pub fn signer(&self) -> Option<&Arc<dyn RequestSigner>> {
+        self.signer.as_ref()
+    }
+
+    /// Checks the fields a request depends on, naming the first one that
+    /// can't work
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` for a base URL that isn't an absolute
+    /// http(s) URL, an API key that's missing (without `auth`) or has stray
+    /// whitespace, or a value that can't be sent in a header.
+    pub fn validate(&self) -> Result<()> {
+        check_base_url(Provider::Gemini, &self.base_url)?;
+        check_api_key(
+            Provider::Gemini,
+            &self.api_key,
+            "GEMINI_API_KEY",
+            self.auth.is_some(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Builds a [`GeminiConfig`], validating it on [`build`](GeminiConfigBuilder::build)
+///
+/// Unset fields fall back to the environment: the API key to
+/// `GEMINI_API_KEY`, the base URL to `GEMINI_BASE_URL` and then the public
+/// API.
+#[derive(Debug, Clone, Default)]
+pub struct GeminiConfigBuilder {
+    api_key: KeySource,
+    base_url: Option<String>,
+    auth: Option<Arc<dyn AuthProvider>>,
+    signer: Option<Arc<dyn RequestSigner>>,
+}
+
+impl GeminiConfigBuilder {
+    /// Sets the API key
+    #[must_use]
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = KeySource::Value(key.into());
+        self
+    }
+
+    /// Reads the API key from the environment variable `var` at build time
+    #[must_use]
+    pub fn api_key_env(mut self, var: impl Into<String>) -> Self {
+        self.api_key = KeySource::Env(var.into());
+        self
+    }
+
+    /// Sets the base URL for the API
+    #[must_use]
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Sets the credential strategy used instead of the API key
+    #[must_use]
+    pub fn auth(mut self, auth: Arc<dyn AuthProvider>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sets the request signer
+    #[must_use]
+    pub fn signer(mut self, signer: Arc<dyn RequestSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Resolves the environment fallbacks and validates the config
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` if the variable named by
+    /// [`api_key_env`](Self::api_key_env) isn't set, or if
+    /// [`GeminiConfig::validate`] rejects the result.
+    pub fn build(self) -> Result<GeminiConfig> {
+        let defaults = GeminiConfig::default();
+        let config = GeminiConfig {
+            api_key: self.api_key.resolve(Provider::Gemini, "GEMINI_API_KEY")?,
+            base_url: resolve_base_url(self.base_url, "GEMINI_BASE_URL", &defaults.base_url),
+            auth: self.auth,
+            signer: self.signer,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
 /// Implementation of the Gemini provider
 #[derive(Debug, Clone)]
 pub struct GeminiProvider {
     /// Configuration for the provider
     config: GeminiConfig,
+    /// Moves large attachments into the Files API before each request
+    #[cfg(feature = "multimodal")]
+    uploads: Option<Arc<files::FileUploads>>,
 }
 
 impl GeminiProvider {
@@ -51,7 +222,11 @@ impl GeminiProvider {
         debug!("API key set: {}", !config.api_key.is_empty());
         debug!("Base URL: {}", config.base_url);
 
-        Self { config }
+        Self {
+            config,
+            #[cfg(feature = "multimodal")]
+            uploads: None,
+        }
     }
 
     /// Creates a new GeminiProvider with custom configuration
@@ -61,10 +236,11 @@ impl GeminiProvider {
     /// ```
     /// use language_barrier_core::provider::gemini::{GeminiProvider, GeminiConfig};
     ///
-    /// let config = GeminiConfig {
-    ///     api_key: "your-api-key".to_string(),
-    ///     base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
-    /// };
+    /// let config = GeminiConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://generativelanguage.googleapis.com/v1beta")
+    ///     .build()
+    ///     .unwrap();
     ///
     /// let provider = GeminiProvider::with_config(config);
     /// ```
@@ -74,7 +250,35 @@ impl GeminiProvider {
         debug!("API key set: {}", !config.api_key.is_empty());
         debug!("Base URL: {}", config.base_url);
 
-        Self { config }
+        Self {
+            config,
+            #[cfg(feature = "multimodal")]
+            uploads: None,
+        }
+    }
+
+    /// Creates a provider after checking `config` with
+    /// [`GeminiConfig::validate`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` naming the first invalid field.
+    pub fn try_with_config(config: GeminiConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self::with_config(config))
+    }
+
+    /// Uploads large attachments with `uploads` before each request,
+    /// sending references to them instead, and returns a new instance
+    ///
+    /// See [`files`] for an example.
+    #[cfg(feature = "multimodal")]
+    #[must_use]
+    pub fn with_file_uploads(self, uploads: Arc<files::FileUploads>) -> Self {
+        Self {
+            uploads: Some(uploads),
+            ..self
+        }
     }
 }
 
@@ -85,7 +289,7 @@ impl Default for GeminiProvider {
 }
 
 impl HTTPProvider<Gemini> for GeminiProvider {
-    fn accept(&self, model: Gemini, chat: &Chat) -> Result<Request> {
+    fn accept(&self, model: Gemini, chat: &Chat) -> Result<HttpRequest> {
         info!("Creating request for Gemini model: {:?}", model);
         debug!("Messages in chat history: {}", chat.history.len());
 
@@ -107,8 +311,8 @@ impl HTTPProvider<Gemini> for GeminiProvider {
             }
         };
 
-        let mut request = Request::new(Method::POST, url);
-        debug!("Created request: {} {}", request.method(), request.url());
+        let mut request = HttpRequest::new(Method::POST, url);
+        debug!("Created request: {} {}", request.method, request.url);
 
         // Set headers
         debug!("Setting request headers");
@@ -120,11 +324,9 @@ impl HTTPProvider<Gemini> for GeminiProvider {
             }
         };
 
-        request
-            .headers_mut()
-            .insert("Content-Type", content_type_header);
+        request.headers.insert("Content-Type", content_type_header);
 
-        trace!("Request headers set: {:#?}", request.headers());
+        trace!("Request headers set: {:#?}", request.headers);
 
         // Create the request payload
         debug!("Creating request payload");
@@ -150,7 +352,7 @@ impl HTTPProvider<Gemini> for GeminiProvider {
 
         // Set the request body
         debug!("Serializing request payload");
-        let body_bytes = match serde_json::to_vec(&payload) {
+        let body_bytes = match payload_body(&payload, chat, Provider::Gemini) {
             Ok(bytes) => {
                 debug!("Payload serialized successfully ({} bytes)", bytes.len());
                 bytes
@@ -161,7 +363,7 @@ impl HTTPProvider<Gemini> for GeminiProvider {
             }
         };
 
-        *request.body_mut() = Some(body_bytes.into());
+        request.body = body_bytes;
         info!("Request created successfully");
 
         Ok(request)
@@ -173,11 +375,15 @@ impl HTTPProvider<Gemini> for GeminiProvider {
 
         // First try to parse as an error response
         if let Ok(error_response) = serde_json::from_str::<GeminiErrorResponse>(&raw_response_text)
+            && let Some(error) = error_response.error
         {
-            if let Some(error) = error_response.error {
-                error!("Gemini API returned an error: {}", error.message);
-                return Err(Error::ProviderUnavailable(error.message));
-            }
+            error!("Gemini API returned an error: {}", error.message);
+            let reasons = error
+                .details
+                .iter()
+                .filter_map(|detail| detail.get("reason").and_then(|r| r.as_str()));
+            let kinds = std::iter::once(error.status.as_str()).chain(reasons);
+            return Err(classify_error(kinds, error.message.clone()));
         }
 
         // If not an error, parse as a successful response
@@ -209,13 +415,67 @@ impl HTTPProvider<Gemini> for GeminiProvider {
 
         Ok(message)
     }
-}
 
-// Trait to get Gemini-specific model IDs
-pub trait GeminiModelInfo {
-    fn gemini_model_id(&self) -> String;
+    fn auth(&self) -> Option<Arc<dyn AuthProvider>> {
+        self.config.auth.clone()
+    }
+
+    // The static key travels in the query string; credentials replace it
+    fn authorize(&self, request: &mut HttpRequest, credential: &Credential) -> Result<()> {
+        let pairs: Vec<(String, String)> = request
+            .url
+            .query_pairs()
+            .filter(|(name, _)| name != "key")
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        if pairs.is_empty() {
+            request.url.set_query(None);
+        } else {
+            request.url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+
+        match credential {
+            Credential::ApiKey(key) => {
+                let mut value = HeaderValue::from_str(key.inner())
+                    .map_err(|_| Error::Authentication("Invalid API key format".into()))?;
+                value.set_sensitive(true);
+                request.headers.insert("x-goog-api-key", value);
+                Ok(())
+            }
+            Credential::Bearer(token) => set_bearer(request, token),
+        }
+    }
+
+    fn signer(&self) -> Option<Arc<dyn RequestSigner>> {
+        self.config.signer.clone()
+    }
+
+    #[cfg(feature = "multimodal")]
+    fn preparer(&self) -> Option<Arc<dyn RequestPreparer>> {
+        self.uploads
+            .clone()
+            .map(|uploads| uploads as Arc<dyn RequestPreparer>)
+    }
+
+    fn preflight_request(&self) -> Result<Option<HttpRequest>> {
+        let url = Url::parse(&format!("{}/models", self.config.base_url))?;
+        let mut request = HttpRequest::new(Method::GET, url);
+        let key = Credential::ApiKey(Secret::new(self.config.api_key.clone()));
+        self.authorize(&mut request, &key)?;
+        Ok(Some(request))
+    }
+
+    fn provider(&self) -> Option<Provider> {
+        Some(Provider::Gemini)
+    }
+
+    fn error_details(&self, error: ApiError) -> ApiError {
+        with_error_details(error)
+    }
 }
 
+pub use crate::model::GeminiModelInfo;
+
 impl GeminiProvider {
     /// Creates a request payload from a Chat object
     ///
@@ -228,17 +488,24 @@ impl GeminiProvider {
         debug!("Messages in history: {}", chat.history.len());
         debug!("Max output tokens: {}", chat.max_output_tokens);
 
-        // Convert system prompt if present
-        let system_instruction = if !chat.system_prompt.is_empty() {
-            debug!("Including system prompt in request");
-            trace!("System prompt: {}", chat.system_prompt);
+        let max_output_tokens = chat.max_output_tokens_for(&model)?;
+
+        // Convert system prompt if present, one part per segment
+        let system_parts: Vec<GeminiPart> = chat
+            .system_blocks()
+            .into_iter()
+            .map(|segment| GeminiPart::text(segment.text))
+            .collect();
+        let system_instruction = if system_parts.is_empty() {
+            debug!("No system prompt provided");
+            None
+        } else {
+            debug!("Including {} system prompt parts in request", system_parts.len());
+            trace!("System prompt: {}", chat.system_text());
             Some(GeminiContent {
-                parts: vec![GeminiPart::text(chat.system_prompt.clone())],
+                parts: system_parts,
                 role: None,
             })
-        } else {
-            debug!("No system prompt provided");
-            None
         };
 
         // Convert messages to contents
@@ -247,6 +514,13 @@ impl GeminiProvider {
         let mut current_role_str: Option<&'static str> = None;
         let mut current_parts: Vec<GeminiPart> = Vec::new();
 
+        // Gemini keys function responses by function *name*, while our tool
+        // messages only carry the originating call ID.  Track the names of the
+        // tool calls seen so far so each tool result can be matched with the
+        // most recent call that shares its ID (Gemini call IDs are synthesized
+        // per response, so they repeat across turns).
+        let mut function_names: HashMap<&str, &str> = HashMap::new();
+
         for msg in &chat.history {
             // Get the current role string
             let msg_role_str = msg.role_str();
@@ -256,15 +530,9 @@ impl GeminiProvider {
                 && current_role_str != Some(msg_role_str)
                 && !current_parts.is_empty()
             {
-                let role = match current_role_str {
-                    Some("user") => Some("user".to_string()),
-                    Some("assistant") => Some("model".to_string()),
-                    _ => None,
-                };
-
                 contents.push(GeminiContent {
                     parts: std::mem::take(&mut current_parts),
-                    role,
+                    role: Self::gemini_role(current_role_str),
                 });
             }
 
@@ -280,70 +548,68 @@ impl GeminiProvider {
                         current_parts.push(GeminiPart::text(text.clone()));
                     }
                     Content::Parts(parts) => {
-                        for part in parts {
-                            match part {
-                                ContentPart::Text { text } => {
-                                    current_parts.push(GeminiPart::text(text.clone()));
-                                }
-                                ContentPart::ImageUrl { image_url } => {
-                                    current_parts.push(GeminiPart::inline_data(
-                                        image_url.url.clone(),
-                                        "image/jpeg".to_string(),
-                                    ));
-                                }
-                            }
-                        }
+                        current_parts.extend(parts.iter().map(GeminiPart::from));
                     }
                 },
-                Message::Assistant { content, .. } => {
+                Message::Assistant {
+                    content,
+                    tool_calls,
+                    ..
+                } => {
                     if let Some(content_data) = content {
                         match content_data {
                             Content::Text(text) => {
-                                current_parts.push(GeminiPart::text(text.clone()));
+                                // Tool-call-only turns sometimes carry an empty text
+                                // body, which Gemini rejects as an empty part.
+                                if !text.is_empty() {
+                                    current_parts.push(GeminiPart::text(text.clone()));
+                                }
                             }
                             Content::Parts(parts) => {
-                                for part in parts {
-                                    match part {
-                                        ContentPart::Text { text } => {
-                                            current_parts.push(GeminiPart::text(text.clone()));
-                                        }
-                                        ContentPart::ImageUrl { image_url } => {
-                                            current_parts.push(GeminiPart::inline_data(
-                                                image_url.url.clone(),
-                                                "image/jpeg".to_string(),
-                                            ));
-                                        }
-                                    }
-                                }
+                                current_parts.extend(parts.iter().map(GeminiPart::from));
                             }
                         }
                     }
+
+                    // Replay any tool calls as `functionCall` parts so the model
+                    // sees the call it made before the matching response.
+                    for call in tool_calls {
+                        function_names.insert(call.id.as_str(), call.function.name.as_str());
+                        let args = serde_json::from_str(&call.function.arguments)
+                            .unwrap_or_else(|_| serde_json::json!({}));
+                        current_parts
+                            .push(GeminiPart::function_call(call.function.name.clone(), args));
+                    }
                 }
                 Message::Tool {
                     tool_call_id,
                     content,
                     ..
                 } => {
-                    // For Gemini, include both the tool call ID and the content
-                    current_parts.push(GeminiPart::text(format!(
-                        "Tool result for call {}: {}",
-                        tool_call_id, content
-                    )));
+                    let Some(name) = function_names.get(tool_call_id.as_str()) else {
+                        error!(
+                            "Tool result references unknown tool call ID: {}",
+                            tool_call_id
+                        );
+                        return Err(Error::Other(format!(
+                            "Tool result for call '{}' has no matching assistant tool call",
+                            tool_call_id
+                        )));
+                    };
+
+                    current_parts.push(GeminiPart::function_response(
+                        (*name).to_string(),
+                        Self::function_response_payload(content),
+                    ));
                 }
             }
         }
 
         // Add any remaining parts
         if !current_parts.is_empty() {
-            let role = match current_role_str {
-                Some("user") => Some("user".to_string()),
-                Some("assistant") => Some("model".to_string()),
-                _ => None,
-            };
-
             contents.push(GeminiContent {
                 parts: current_parts,
-                role,
+                role: Self::gemini_role(current_role_str),
             });
         }
 
@@ -351,11 +617,19 @@ impl GeminiProvider {
 
         // Create generation config
         let generation_config = Some(GeminiGenerationConfig {
-            max_output_tokens: Some(chat.max_output_tokens),
-            temperature: None,
+            max_output_tokens: Some(max_output_tokens),
+            temperature: chat.temperature,
             top_p: None,
             top_k: None,
             stop_sequences: None,
+            response_mime_type: chat
+                .output_schema
+                .as_ref()
+                .map(|_| "application/json".to_string()),
+            response_schema: chat
+                .output_schema
+                .as_ref()
+                .map(|output| Self::response_schema(&output.schema)),
         });
 
         // Convert tool descriptions if a tool registry is provided
@@ -421,6 +695,44 @@ impl GeminiProvider {
         info!("Request payload created successfully");
         Ok(request)
     }
+
+    /// Copies a JSON schema without the keywords Gemini's schema subset rejects
+    fn response_schema(schema: &serde_json::Value) -> serde_json::Value {
+        match schema {
+            serde_json::Value::Object(map) => map
+                .iter()
+                .filter(|(key, _)| key.as_str() != "additionalProperties")
+                .map(|(key, value)| (key.clone(), Self::response_schema(value)))
+                .collect(),
+            serde_json::Value::Array(items) => items.iter().map(Self::response_schema).collect(),
+            other => other.clone(),
+        }
+    }
+
+    /// Maps one of our message roles onto the role Gemini expects.
+    ///
+    /// Gemini only knows about `user` and `model`; function responses are sent
+    /// back on the user side of the conversation.
+    fn gemini_role(role: Option<&str>) -> Option<String> {
+        match role {
+            Some("user") | Some("tool") => Some("user".to_string()),
+            Some("assistant") => Some("model".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Converts the text of a tool result into a `functionResponse.response` value.
+    ///
+    /// Gemini requires the response to be a JSON object.  Tool output that is
+    /// already an object is passed through untouched; anything else (plain text,
+    /// arrays, scalars) is wrapped as `{"content": ...}`.
+    fn function_response_payload(content: &str) -> serde_json::Value {
+        match serde_json::from_str::<serde_json::Value>(content) {
+            Ok(value @ serde_json::Value::Object(_)) => value,
+            Ok(value) => serde_json::json!({ "content": value }),
+            Err(_) => serde_json::json!({ "content": content }),
+        }
+    }
 }
 
 /// Represents a content part in Gemini API format
@@ -437,6 +749,14 @@ pub(crate) struct GeminiPart {
     /// The function call (optional)
     #[serde(skip_serializing_if = "Option::is_none", rename = "functionCall")]
     pub function_call: Option<GeminiFunctionCall>,
+
+    /// The function response (optional)
+    #[serde(skip_serializing_if = "Option::is_none", rename = "functionResponse")]
+    pub function_response: Option<GeminiFunctionResponse>,
+
+    /// Whether the part is the model's reasoning rather than its reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought: Option<bool>,
 }
 
 /// Represents a function call in the Gemini API format
@@ -448,6 +768,15 @@ pub(crate) struct GeminiFunctionCall {
     pub args: serde_json::Value,
 }
 
+/// Represents the result of a function call sent back to the Gemini API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct GeminiFunctionResponse {
+    /// The name of the function that produced this response
+    pub name: String,
+    /// The function output, which must be a JSON object
+    pub response: serde_json::Value,
+}
+
 impl GeminiPart {
     /// Create a new text part
     fn text(text: String) -> Self {
@@ -455,15 +784,67 @@ impl GeminiPart {
             text: Some(text),
             inline_data: None,
             function_call: None,
+            function_response: None,
+            thought: None,
         }
     }
 
     /// Create a new inline data part
+    #[cfg(feature = "multimodal")]
     fn inline_data(data: String, mime_type: String) -> Self {
         GeminiPart {
             text: None,
             inline_data: Some(GeminiInlineData { data, mime_type }),
             function_call: None,
+            function_response: None,
+            thought: None,
+        }
+    }
+
+    /// Create a new function call part
+    fn function_call(name: String, args: serde_json::Value) -> Self {
+        GeminiPart {
+            text: None,
+            inline_data: None,
+            function_call: Some(GeminiFunctionCall { name, args }),
+            function_response: None,
+            thought: None,
+        }
+    }
+
+    /// Create a new function response part
+    fn function_response(name: String, response: serde_json::Value) -> Self {
+        GeminiPart {
+            text: None,
+            inline_data: None,
+            function_call: None,
+            function_response: Some(GeminiFunctionResponse { name, response }),
+            thought: None,
+        }
+    }
+}
+
+impl From<&ContentPart> for GeminiPart {
+    fn from(part: &ContentPart) -> Self {
+        match part {
+            ContentPart::Text { text } => GeminiPart::text(text.clone()),
+            // `data:` URLs are unpacked into their MIME type and base64 payload
+            #[cfg(feature = "multimodal")]
+            ContentPart::ImageUrl { image_url } => match image_url.data_url_parts() {
+                Some((mime_type, data)) => {
+                    GeminiPart::inline_data(data.to_string(), mime_type.to_string())
+                }
+                None => GeminiPart::inline_data(image_url.url.clone(), "image/jpeg".to_string()),
+            },
+            #[cfg(feature = "multimodal")]
+            ContentPart::Document { document } => {
+                GeminiPart::inline_data(document.data.clone(), document.mime_type.clone())
+            }
+            #[cfg(feature = "multimodal")]
+            ContentPart::Audio { audio } => {
+                let format = audio.format.as_deref().unwrap_or("wav");
+                GeminiPart::inline_data(audio.data.clone(), format!("audio/{format}"))
+            }
         }
     }
 }
@@ -478,7 +859,7 @@ pub(crate) struct GeminiInlineData {
 }
 
 /// Represents a content object in Gemini API format
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub(crate) struct GeminiContent {
     /// The parts of the content
     pub parts: Vec<GeminiPart>,
@@ -505,6 +886,12 @@ pub(crate) struct GeminiGenerationConfig {
     /// Sequences that will stop generation
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
+    /// The MIME type of the reply, `application/json` for structured output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    /// The schema the reply must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
 }
 
 /// Represents a function declaration in the Gemini API format
@@ -528,17 +915,6 @@ impl From<&LlmToolInfo> for GeminiFunctionDeclaration {
     }
 }
 
-/// Represents a function in the Gemini API format (tools are called functions in Gemini)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct GeminiFunction {
-    /// The name of the function
-    pub name: String,
-    /// The description of the function
-    pub description: String,
-    /// The parameters definition
-    pub parameters: serde_json::Value,
-}
-
 /// Represents a tool in the Gemini API format
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct GeminiTool {
@@ -602,7 +978,8 @@ pub(crate) struct GeminiResponse {
 /// Represents a candidate in a Gemini response
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct GeminiCandidate {
-    /// The content of the candidate
+    /// The content of the candidate, absent when the response was blocked
+    #[serde(default)]
     pub content: GeminiContent,
     /// The finish reason (using camelCase as in the API)
     #[serde(skip_serializing_if = "Option::is_none", rename = "finishReason")]
@@ -637,6 +1014,20 @@ pub(crate) struct GeminiUsageMetadata {
     /// Total token count
     #[serde(rename = "totalTokenCount", default)]
     pub total_token_count: u32,
+    /// Token count served from cached content
+    #[serde(
+        rename = "cachedContentTokenCount",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cached_content_token_count: Option<u32>,
+    /// Token count spent on thinking
+    #[serde(
+        rename = "thoughtsTokenCount",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub thoughts_token_count: Option<u32>,
     /// Detailed token breakdown for the prompt
     #[serde(
         rename = "promptTokensDetails",
@@ -651,6 +1042,20 @@ pub(crate) struct GeminiUsageMetadata {
     pub candidates_tokens_details: Option<Vec<GeminiTokenDetails>>,
 }
 
+impl From<&GeminiUsageMetadata> for Usage {
+    fn from(usage: &GeminiUsageMetadata) -> Self {
+        // Gemini reports thinking separately from `candidatesTokenCount`
+        let thoughts = u64::from(usage.thoughts_token_count.unwrap_or(0));
+
+        Usage::new(
+            u64::from(usage.prompt_token_count),
+            u64::from(usage.candidates_token_count) + thoughts,
+        )
+        .with_cached_tokens(u64::from(usage.cached_content_token_count.unwrap_or(0)))
+        .with_reasoning_tokens(thoughts)
+    }
+}
+
 /// Represents an error response from the Gemini API
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct GeminiErrorResponse {
@@ -667,6 +1072,77 @@ pub(crate) struct GeminiError {
     pub message: String,
     /// The error status
     pub status: String,
+    /// Structured details, such as the reason an API key was rejected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<serde_json::Value>,
+}
+
+impl GeminiError {
+    /// The quotas listed in a `QuotaFailure` detail
+    fn quota(&self) -> Vec<QuotaViolation> {
+        self.detail("QuotaFailure")
+            .and_then(|detail| detail.get("violations")?.as_array())
+            .into_iter()
+            .flatten()
+            .map(|violation| {
+                let text = |name| {
+                    violation
+                        .get(name)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                // Sent as a string, but accept a number too
+                let limit = violation.get("quotaValue").and_then(|v| {
+                    v.as_u64()
+                        .or_else(|| v.as_str().and_then(|v| v.parse().ok()))
+                });
+                let dimensions = violation
+                    .get("quotaDimensions")
+                    .and_then(|v| v.as_object())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                    .collect();
+                QuotaViolation {
+                    metric: text("quotaMetric"),
+                    id: text("quotaId"),
+                    limit,
+                    dimensions,
+                }
+            })
+            .collect()
+    }
+
+    /// The delay in a `RetryInfo` detail, given like `"43s"` or `"0.5s"`
+    fn retry_delay(&self) -> Option<Duration> {
+        let delay = self.detail("RetryInfo")?.get("retryDelay")?.as_str()?;
+        let seconds: f64 = delay.strip_suffix('s')?.parse().ok()?;
+        Duration::try_from_secs_f64(seconds).ok()
+    }
+
+    /// The detail whose `@type` ends in `.{kind}`
+    fn detail(&self, kind: &str) -> Option<&serde_json::Value> {
+        self.details.iter().find(|detail| {
+            detail
+                .get("@type")
+                .and_then(|t| t.as_str())
+                .and_then(|t| t.rsplit_once('.'))
+                .is_some_and(|(_, name)| name == kind)
+        })
+    }
+}
+
+/// Adds the quota and retry details of a Gemini error body to `error`
+pub(crate) fn with_error_details(error: ApiError) -> ApiError {
+    match serde_json::from_str::<GeminiErrorResponse>(&error.body) {
+        Ok(GeminiErrorResponse {
+            error: Some(details),
+        }) => error
+            .with_quota(details.quota())
+            .with_retry_delay(details.retry_delay()),
+        _ => error,
+    }
 }
 
 /// Convert from Gemini's response to our message format
@@ -684,9 +1160,16 @@ impl From<&GeminiResponse> for Message {
         let mut text_content_parts = Vec::new();
         let mut tool_calls = Vec::new();
         let mut tool_call_id_counter = 0;
+        let mut thoughts = Vec::new();
 
         // Process each part of the response
         for part in &candidate.content.parts {
+            // Reasoning isn't part of the reply; it's kept in metadata
+            if part.thought == Some(true) {
+                thoughts.extend(part.text.clone());
+                continue;
+            }
+
             // Handle function calls
             if let Some(function_call) = &part.function_call {
                 tool_call_id_counter += 1;
@@ -724,6 +1207,7 @@ impl From<&GeminiResponse> for Message {
             // If there's only one text part, use simple Text content
             match &text_content_parts[0] {
                 ContentPart::Text { text } => Some(Content::Text(text.clone())),
+                #[cfg(feature = "multimodal")]
                 _ => Some(Content::Parts(text_content_parts)),
             }
         } else if !text_content_parts.is_empty() {
@@ -768,6 +1252,16 @@ impl From<&GeminiResponse> for Message {
                 "total_tokens",
                 serde_json::Value::Number(usage.total_token_count.into()),
             );
+            msg = msg.with_metadata(Usage::METADATA_KEY, Usage::from(usage).to_metadata());
+        }
+        if let Some(reason) = &candidate.finish_reason {
+            msg = msg.with_metadata(
+                FinishReason::METADATA_KEY,
+                FinishReason::from_provider(reason).to_metadata(),
+            );
+        }
+        if !thoughts.is_empty() {
+            msg = msg.with_metadata("thinking", serde_json::Value::String(thoughts.join("\n\n")));
         }
 
         msg
@@ -777,8 +1271,11 @@ impl From<&GeminiResponse> for Message {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::arbitrary::{assert_lossless, response_message, round_trip};
+    use proptest::prelude::*;
 
     // Tests will be implemented as we get more information about the API
+    #[cfg(feature = "multimodal")]
     #[test]
     fn test_gemini_part_serialization() {
         let text_part = GeminiPart::text("Hello, world!".to_string());
@@ -809,4 +1306,280 @@ mod tests {
         assert_eq!(error.code, 400);
         assert_eq!(error.status, "INVALID_ARGUMENT");
     }
+
+    #[tokio::test]
+    async fn test_quota_errors_tell_throttling_from_exhaustion() {
+        use crate::ApiErrorKind;
+        use crate::error::QuotaPeriod;
+        use crate::llm_service::{HTTPLlmService, LLMService};
+        use crate::transport::mock::{MockResponse, MockTransport};
+
+        let quota_error = |quota_id: &str| {
+            serde_json::json!({"error": {
+                "code": 429,
+                "message": "You exceeded your current quota.",
+                "status": "RESOURCE_EXHAUSTED",
+                "details": [
+                    {
+                        "@type": "type.googleapis.com/google.rpc.QuotaFailure",
+                        "violations": [{
+                            "quotaMetric": "generativelanguage.googleapis.com/generate_content_free_tier_requests",
+                            "quotaId": quota_id,
+                            "quotaDimensions": {"location": "global", "model": "gemini-2.0-flash"},
+                            "quotaValue": "200"
+                        }]
+                    },
+                    {"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "43s"}
+                ]
+            }})
+            .to_string()
+        };
+        let transport = MockTransport::new()
+            .with_response(MockResponse::status(
+                429,
+                quota_error("GenerateRequestsPerMinutePerProjectPerModel-FreeTier"),
+            ))
+            .with_response(MockResponse::status(
+                429,
+                quota_error("GenerateRequestsPerDayPerProjectPerModel-FreeTier"),
+            ));
+        let service = HTTPLlmService::new_with_transport(
+            Gemini::Flash20,
+            Arc::new(GeminiProvider::new()),
+            Arc::new(transport),
+        );
+        let chat = Chat::default().add_message(Message::user("Hello"));
+
+        let error = service.generate_next_message(&chat).await.unwrap_err();
+        let api = error.api().unwrap();
+        assert_eq!(api.kind, ApiErrorKind::RateLimit);
+        assert_eq!(api.quota[0].period(), QuotaPeriod::Minute);
+        assert_eq!(api.quota[0].limit, Some(200));
+        assert_eq!(api.quota[0].dimension("model"), Some("gemini-2.0-flash"));
+        assert_eq!(api.retry_after(), Some(Duration::from_secs(43)));
+        assert!(!api.is_quota_exhausted());
+
+        let error = service.generate_next_message(&chat).await.unwrap_err();
+        let api = error.api().unwrap();
+        assert_eq!(api.quota[0].period(), QuotaPeriod::Day);
+        assert!(api.is_quota_exhausted());
+    }
+
+    /// Helper that builds the assistant turn requesting the weather tool.
+    fn weather_call(id: &str) -> Message {
+        use crate::message::{Function, ToolCall};
+
+        Message::assistant_with_tool_calls(vec![ToolCall {
+            id: id.to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "get_weather".to_string(),
+                arguments: "{\"location\":\"Paris, France\"}".to_string(),
+            },
+        }])
+    }
+
+    /// Stage-2: the assistant's tool call must be replayed as a `functionCall`
+    /// part on the `model` side, with no empty text part alongside it.
+    #[test]
+    fn test_stage2_assistant_tool_call_serialization() {
+        let chat = Chat::default()
+            .add_message(Message::user("What is the weather like in Paris today?"))
+            .add_message(weather_call("gemini_call_1"));
+
+        let request = GeminiProvider::new()
+            .create_request_payload(Gemini::Flash20, &chat)
+            .expect("payload generation failed");
+
+        assert_eq!(request.contents.len(), 2);
+        assert_eq!(request.contents[0].role.as_deref(), Some("user"));
+
+        let model_turn = &request.contents[1];
+        assert_eq!(model_turn.role.as_deref(), Some("model"));
+        assert_eq!(model_turn.parts.len(), 1);
+        let call = model_turn.parts[0]
+            .function_call
+            .as_ref()
+            .expect("functionCall missing");
+        assert_eq!(call.name, "get_weather");
+        assert_eq!(call.args, serde_json::json!({"location": "Paris, France"}));
+    }
+
+    /// Stage-3: the tool result is sent back as a `functionResponse` part on
+    /// the `user` side, keyed by the name of the function that was called.
+    #[test]
+    fn test_stage3_tool_response_serialization() {
+        let chat = Chat::default()
+            .add_message(Message::user("What is the weather like in Paris today?"))
+            .add_message(weather_call("gemini_call_1"))
+            .add_message(Message::tool("gemini_call_1", "{\"temperature\":\"10C\"}"));
+
+        let request = GeminiProvider::new()
+            .create_request_payload(Gemini::Flash20, &chat)
+            .expect("payload generation failed");
+
+        let roles: Vec<_> = request
+            .contents
+            .iter()
+            .map(|c| c.role.as_deref().unwrap())
+            .collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+
+        let serialized = serde_json::to_value(&request.contents[2]).unwrap();
+        assert_eq!(
+            serialized,
+            serde_json::json!({
+                "role": "user",
+                "parts": [{
+                    "functionResponse": {
+                        "name": "get_weather",
+                        "response": {"temperature": "10C"}
+                    }
+                }]
+            })
+        );
+    }
+
+    /// Stage-4: a full exchange with a final answer, then a second tool round
+    /// trip.  Gemini call IDs are synthesized per response, so the second call
+    /// reuses `gemini_call_1` and must resolve to the newer function name.
+    #[test]
+    fn test_stage4_multi_turn_serialization() {
+        use crate::message::{Function, ToolCall};
+
+        let second_call = Message::assistant_with_tool_calls(vec![ToolCall {
+            id: "gemini_call_1".to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "get_forecast".to_string(),
+                arguments: "{\"days\":3}".to_string(),
+            },
+        }]);
+
+        let chat = Chat::default()
+            .add_message(Message::user("What is the weather like in Paris today?"))
+            .add_message(weather_call("gemini_call_1"))
+            .add_message(Message::tool("gemini_call_1", "10C"))
+            .add_message(Message::assistant("It is 10°C in Paris."))
+            .add_message(Message::user("And the next few days?"))
+            .add_message(second_call)
+            .add_message(Message::tool("gemini_call_1", "[\"rain\",\"sun\",\"sun\"]"));
+
+        let request = GeminiProvider::new()
+            .create_request_payload(Gemini::Flash20, &chat)
+            .expect("payload generation failed");
+
+        let roles: Vec<_> = request
+            .contents
+            .iter()
+            .map(|c| c.role.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            roles,
+            vec!["user", "model", "user", "model", "user", "model", "user"]
+        );
+
+        // Plain-text tool output is wrapped, since `response` must be an object.
+        let first = request.contents[2].parts[0]
+            .function_response
+            .as_ref()
+            .unwrap();
+        assert_eq!(first.name, "get_weather");
+        assert_eq!(first.response, serde_json::json!({"content": "10C"}));
+
+        let second = request.contents[6].parts[0]
+            .function_response
+            .as_ref()
+            .unwrap();
+        assert_eq!(second.name, "get_forecast");
+        assert_eq!(
+            second.response,
+            serde_json::json!({"content": ["rain", "sun", "sun"]})
+        );
+    }
+
+    #[test]
+    fn test_tool_response_without_matching_call_is_rejected() {
+        let chat = Chat::default()
+            .add_message(Message::user("What is the weather like in Paris today?"))
+            .add_message(Message::tool("gemini_call_7", "10C"));
+
+        let result = GeminiProvider::new().create_request_payload(Gemini::Flash20, &chat);
+        assert!(matches!(result, Err(Error::Other(_))));
+    }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_inline_attachments_conversion() {
+        let chat = Chat::default().add_message(
+            Message::user("What's in these?")
+                .with_image_bytes([1u8, 2, 3], "image/png")
+                .with_document(b"%PDF", "application/pdf"),
+        );
+
+        let request = GeminiProvider::new()
+            .create_request_payload(Gemini::Flash20, &chat)
+            .expect("payload generation failed");
+
+        let serialized = serde_json::to_value(&request.contents[0].parts).unwrap();
+        assert_eq!(
+            serialized,
+            serde_json::json!([
+                { "text": "What's in these?" },
+                { "inline_data": { "data": "AQID", "mime_type": "image/png" } },
+                { "inline_data": { "data": "JVBERg==", "mime_type": "application/pdf" } }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_request_payload_snapshots() {
+        let provider = GeminiProvider::default();
+        for (name, chat) in crate::provider::corpus::chats() {
+            let payload = provider
+                .create_request_payload(Gemini::Flash20, &chat)
+                .unwrap();
+            insta::assert_json_snapshot!(name, payload);
+        }
+    }
+
+    #[test]
+    fn test_provider_extras_are_merged_into_the_payload() {
+        let chat = Chat::default()
+            .with_max_output_tokens(100)
+            .add_message(Message::user("Hi"))
+            .with_provider_extra(
+                Provider::Gemini,
+                serde_json::json!({
+                    "generation_config": { "seed": 7 },
+                    "cached_content": "cachedContents/abc"
+                }),
+            )
+            .unwrap()
+            .with_provider_extra(Provider::OpenAi, serde_json::json!({ "store": true }))
+            .unwrap();
+
+        let request = GeminiProvider::default()
+            .accept(Gemini::Flash20, &chat)
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["generation_config"]["max_output_tokens"], 100);
+        assert_eq!(body["generation_config"]["seed"], 7);
+        assert_eq!(body["cached_content"], "cachedContents/abc");
+        assert!(body.get("store").is_none());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_response_round_trip_is_lossless(message in response_message()) {
+            let provider = GeminiProvider::default();
+            let returned = round_trip(&provider, Gemini::Flash20, &message, "contents", |message| {
+                serde_json::json!({ "candidates": [{ "content": message }] })
+            })
+            .unwrap();
+            assert_lossless(&message, &returned, false);
+        }
+    }
 }
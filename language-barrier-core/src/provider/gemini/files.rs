@@ -0,0 +1,648 @@
+//! The Gemini Files API
+//!
+//! Gemini rejects requests over 20 MB, so large images, audio and video have
+//! to be uploaded first and referenced by URI. [`GeminiFiles`] uploads,
+//! looks up and deletes files; [`FileUploads`] does it for you, replacing
+//! every inline attachment over a threshold in an outgoing
+//! `generateContent` request with a reference to an uploaded copy.
+//!
+//! Uploaded files expire after 48 hours.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::provider::gemini::GeminiProvider;
+//! use language_barrier_core::provider::gemini::files::{FileUploads, GeminiFiles};
+//! use language_barrier_core::transport::ReqwestTransport;
+//! use language_barrier_core::Gemini;
+//!
+//! # async fn example() -> language_barrier_core::error::Result<()> {
+//! let provider = GeminiProvider::new();
+//! let files = GeminiFiles::new(provider.clone(), Arc::new(ReqwestTransport::new()));
+//! let uploads = Arc::new(FileUploads::new(files).with_threshold(8 * 1024 * 1024));
+//! let service = HTTPLlmService::new(
+//!     Gemini::Flash20,
+//!     Arc::new(provider.with_file_uploads(uploads.clone())),
+//! );
+//!
+//! // ... chat with `service`, then remove the uploads
+//! uploads.delete_uploaded().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use super::{GeminiErrorResponse, GeminiProvider, with_error_details};
+use crate::error::{ApiError, Error, Result};
+use crate::model::Provider;
+use crate::provider::{HTTPProvider, RequestPreparer};
+use crate::trace_context;
+use crate::transport::{HeaderValue, HttpRequest, HttpResponse, Method, Transport};
+
+/// Whether an uploaded file can be used yet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FileState {
+    /// The API didn't say
+    #[default]
+    StateUnspecified,
+    /// Still being processed; videos take a while
+    Processing,
+    /// Ready to be referenced in requests
+    Active,
+    /// Processing failed
+    Failed,
+}
+
+/// A file uploaded to the Gemini Files API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiFile {
+    /// The resource name, such as `files/abc-123`
+    pub name: String,
+    /// The URI requests reference the file by
+    pub uri: String,
+    /// The file's MIME type
+    pub mime_type: String,
+    /// The file's size, as a decimal string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<String>,
+    /// Whether the file can be used yet
+    #[serde(default)]
+    pub state: FileState,
+    /// When the file will be deleted, as an RFC 3339 timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_time: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UploadResponse {
+    file: GeminiFile,
+}
+
+/// A client for the Gemini Files API
+#[derive(Clone)]
+pub struct GeminiFiles {
+    provider: GeminiProvider,
+    transport: Arc<dyn Transport>,
+    poll_interval: Duration,
+    max_wait: Duration,
+}
+
+impl GeminiFiles {
+    /// Creates a client that authenticates like `provider` and sends
+    /// requests through `transport`
+    pub fn new(provider: GeminiProvider, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            provider,
+            transport,
+            poll_interval: Duration::from_secs(1),
+            max_wait: Duration::from_secs(120),
+        }
+    }
+
+    /// Sets how often [`wait_until_active`](Self::wait_until_active) checks
+    /// on a file, and how long it waits in total
+    #[must_use]
+    pub fn with_polling(self, poll_interval: Duration, max_wait: Duration) -> Self {
+        Self {
+            poll_interval,
+            max_wait,
+            ..self
+        }
+    }
+
+    /// Uploads `bytes` as a file of type `mime_type`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Api` if the API rejects the upload.
+    pub async fn upload(&self, bytes: Vec<u8>, mime_type: &str) -> Result<GeminiFile> {
+        let mut url = self.url("files")?;
+        let path = format!("/upload{}", url.path());
+        url.set_path(&path);
+        url.query_pairs_mut().append_pair("uploadType", "media");
+
+        let mut request = HttpRequest::new(Method::POST, url);
+        request.headers.insert(
+            "Content-Type",
+            HeaderValue::from_str(mime_type)
+                .map_err(|_| Error::Other(format!("Invalid MIME type {mime_type:?}")))?,
+        );
+        request
+            .headers
+            .insert("X-Goog-Upload-Protocol", HeaderValue::from_static("raw"));
+        request.body = bytes;
+
+        let size = request.body.len();
+        let response = self.send(request).await?;
+        let file = serde_json::from_str::<UploadResponse>(&response.body)?.file;
+        info!("Uploaded {} bytes to Gemini as {}", size, file.name);
+        Ok(file)
+    }
+
+    /// Looks up a file by its resource name
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Api` if there's no such file.
+    pub async fn get(&self, name: &str) -> Result<GeminiFile> {
+        let request = HttpRequest::new(Method::GET, self.url(name)?);
+        let response = self.send(request).await?;
+        Ok(serde_json::from_str(&response.body)?)
+    }
+
+    /// Deletes a file by its resource name
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Api` if there's no such file.
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        let request = HttpRequest::new(Method::DELETE, self.url(name)?);
+        self.send(request).await?;
+        debug!("Deleted Gemini file {}", name);
+        Ok(())
+    }
+
+    /// Waits until `file` has finished processing
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Other` if processing fails or takes longer than the
+    /// configured maximum wait, and `Error::Api` if a lookup fails.
+    pub async fn wait_until_active(&self, file: GeminiFile) -> Result<GeminiFile> {
+        let mut file = file;
+        let mut waited = Duration::ZERO;
+        while file.state == FileState::Processing {
+            if waited >= self.max_wait {
+                return Err(Error::Other(format!(
+                    "Gemini file {} still processing after {:?}",
+                    file.name, waited
+                )));
+            }
+            tokio::time::sleep(self.poll_interval).await;
+            waited += self.poll_interval;
+            file = self.get(&file.name).await?;
+        }
+
+        if file.state == FileState::Failed {
+            return Err(Error::Other(format!(
+                "Gemini failed to process file {}",
+                file.name
+            )));
+        }
+        Ok(file)
+    }
+
+    fn url(&self, path: &str) -> Result<Url> {
+        let mut url = Url::parse(&format!("{}/{}", self.provider.config.base_url, path))?;
+        if self.provider.auth().is_none() {
+            url.query_pairs_mut()
+                .append_pair("key", &self.provider.config.api_key);
+        }
+        Ok(url)
+    }
+
+    async fn send(&self, mut request: HttpRequest) -> Result<HttpResponse> {
+        if let Some(auth) = self.provider.auth() {
+            let credential = auth.credential().await?;
+            self.provider.authorize(&mut request, &credential)?;
+        }
+        trace_context::propagate(&mut request);
+
+        let response = self.transport.send(request).await?;
+        if !(200..300).contains(&response.status) {
+            let mut error = ApiError::new(response.status, &response.headers, response.body)
+                .with_provider(Some(Provider::Gemini));
+            error = with_error_details(error);
+            if let Ok(GeminiErrorResponse {
+                error: Some(details),
+            }) = serde_json::from_str(&error.body)
+            {
+                error = error.with_message(details.message);
+            }
+            return Err(Error::Api(Box::new(error)));
+        }
+        Ok(response)
+    }
+}
+
+impl fmt::Debug for GeminiFiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeminiFiles")
+            .field("poll_interval", &self.poll_interval)
+            .field("max_wait", &self.max_wait)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Moves large inline attachments into the Files API before each request
+///
+/// Every `inline_data` part of a `generateContent` request whose base64
+/// text is longer than the threshold is uploaded, and the part is replaced
+/// with a `file_data` reference to the upload. Other requests pass through
+/// untouched. Install it with [`GeminiProvider::with_file_uploads`]; it
+/// runs before the request is authorized and signed.
+///
+/// Uploads are remembered by content, so an attachment that stays in the
+/// chat is uploaded once rather than on every turn. They're reused for a
+/// little less than the 48 hours Gemini keeps them, then uploaded again.
+/// Nothing is deleted until [`delete_uploaded`](Self::delete_uploaded) is
+/// called.
+pub struct FileUploads {
+    files: GeminiFiles,
+    threshold: usize,
+    // Held across each upload, so concurrent requests don't upload the same
+    // attachment twice
+    uploads: tokio::sync::Mutex<HashMap<UploadKey, Upload>>,
+}
+
+/// Identifies an attachment by its content
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UploadKey {
+    mime_type: String,
+    len: usize,
+    hash: u64,
+}
+
+impl UploadKey {
+    fn new(data: &str, mime_type: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        Self {
+            mime_type: mime_type.to_string(),
+            len: data.len(),
+            hash: hasher.finish(),
+        }
+    }
+}
+
+struct Upload {
+    file: GeminiFile,
+    uploaded_at: Instant,
+}
+
+impl FileUploads {
+    /// The default threshold, comfortably under the 20 MB request limit
+    pub const DEFAULT_THRESHOLD: usize = 15 * 1024 * 1024;
+
+    /// How long an upload is reused, an hour short of Gemini's 48
+    pub const REUSE_FOR: Duration = Duration::from_secs(47 * 60 * 60);
+
+    /// Uploads through `files`
+    pub fn new(files: GeminiFiles) -> Self {
+        Self {
+            files,
+            threshold: Self::DEFAULT_THRESHOLD,
+            uploads: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the base64 length above which attachments are uploaded
+    #[must_use]
+    pub fn with_threshold(self, threshold: usize) -> Self {
+        Self { threshold, ..self }
+    }
+
+    /// Returns the files uploaded so far that haven't been deleted
+    pub async fn uploaded(&self) -> Vec<GeminiFile> {
+        let uploads = self.uploads.lock().await;
+        uploads.values().map(|upload| upload.file.clone()).collect()
+    }
+
+    /// Deletes every file uploaded so far
+    ///
+    /// Files Gemini has already removed count as deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from the API. Files that couldn't be
+    /// deleted are kept, so a later call tries them again.
+    pub async fn delete_uploaded(&self) -> Result<()> {
+        let mut uploads = self.uploads.lock().await;
+        let mut first_error = None;
+        let keys: Vec<UploadKey> = uploads.keys().cloned().collect();
+        for key in keys {
+            let name = uploads[&key].file.name.clone();
+            match self.files.delete(&name).await {
+                Ok(()) => {}
+                Err(Error::Api(error)) if error.status == 404 => {
+                    debug!("Gemini file {} was already gone", name);
+                }
+                Err(e) => {
+                    warn!("Failed to delete Gemini file {}: {}", name, e);
+                    first_error.get_or_insert(e);
+                    continue;
+                }
+            }
+            uploads.remove(&key);
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    async fn upload_part(
+        &self,
+        uploads: &mut HashMap<UploadKey, Upload>,
+        part: &mut serde_json::Value,
+    ) -> Result<()> {
+        let Some(inline) = part.get("inline_data") else {
+            return Ok(());
+        };
+        let (Some(data), Some(mime_type)) = (
+            inline.get("data").and_then(serde_json::Value::as_str),
+            inline.get("mime_type").and_then(serde_json::Value::as_str),
+        ) else {
+            return Ok(());
+        };
+        if data.len() <= self.threshold {
+            return Ok(());
+        }
+
+        let key = UploadKey::new(data, mime_type);
+        let reusable = uploads
+            .get(&key)
+            .filter(|upload| upload.uploaded_at.elapsed() < Self::REUSE_FOR);
+        let file = match reusable {
+            Some(upload) => {
+                debug!("Reusing Gemini file {}", upload.file.name);
+                upload.file.clone()
+            }
+            None => {
+                let Ok(bytes) = BASE64.decode(data) else {
+                    warn!("Leaving oversized inline data inline: it isn't valid base64");
+                    return Ok(());
+                };
+                let file = self.files.upload(bytes, mime_type).await?;
+                let file = self.files.wait_until_active(file).await?;
+                let upload = Upload {
+                    file: file.clone(),
+                    uploaded_at: Instant::now(),
+                };
+                uploads.insert(key, upload);
+                file
+            }
+        };
+        *part = serde_json::json!({
+            "file_data": { "mime_type": file.mime_type, "file_uri": file.uri }
+        });
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FileUploads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileUploads")
+            .field("files", &self.files)
+            .field("threshold", &self.threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl RequestPreparer for FileUploads {
+    async fn prepare(&self, request: &mut HttpRequest) -> Result<()> {
+        let generates =
+            request.method == Method::POST && request.url.path().ends_with("generateContent");
+        let body = generates
+            .then(|| serde_json::from_slice::<serde_json::Value>(&request.body).ok())
+            .flatten();
+        let Some(mut body) = body else {
+            return Ok(());
+        };
+
+        let mut uploads = self.uploads.lock().await;
+        let mut uploaded = false;
+        let mut contents = Vec::new();
+        for (key, value) in body.as_object_mut().into_iter().flatten() {
+            match key.as_str() {
+                "contents" => contents.extend(value.as_array_mut().into_iter().flatten()),
+                "system_instruction" => contents.push(value),
+                _ => {}
+            }
+        }
+        for content in contents {
+            let parts = content
+                .get_mut("parts")
+                .and_then(serde_json::Value::as_array_mut)
+                .into_iter()
+                .flatten();
+            for part in parts {
+                let before = part.get("inline_data").is_some();
+                self.upload_part(&mut uploads, part).await?;
+                uploaded |= before && part.get("inline_data").is_none();
+            }
+        }
+        if uploaded {
+            request.body = serde_json::to_vec(&body)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    use crate::message::{ContentPart, Message};
+    use crate::provider::gemini::GeminiConfig;
+    use crate::signing::RequestSigner;
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use crate::{Chat, Gemini};
+
+    fn provider() -> GeminiProvider {
+        GeminiProvider::with_config(GeminiConfig {
+            api_key: "test-key".to_string(),
+            ..GeminiConfig::default()
+        })
+    }
+
+    fn file(state: &str) -> serde_json::Value {
+        serde_json::json!({
+            "name": "files/abc",
+            "uri": "https://generativelanguage.googleapis.com/v1beta/files/abc",
+            "mimeType": "video/mp4",
+            "sizeBytes": "12",
+            "state": state
+        })
+    }
+
+    fn reply() -> MockResponse {
+        let reply = serde_json::json!({
+            "candidates": [{ "content": { "role": "model", "parts": [{ "text": "A cat" }] } }]
+        });
+        MockResponse::ok(reply.to_string())
+    }
+
+    fn uploaded(state: &str) -> MockResponse {
+        MockResponse::ok(serde_json::json!({ "file": file(state) }).to_string())
+    }
+
+    fn uploads(mock: &Arc<MockTransport>) -> Arc<FileUploads> {
+        let files = GeminiFiles::new(provider(), mock.clone())
+            .with_polling(Duration::from_millis(1), Duration::from_secs(1));
+        Arc::new(FileUploads::new(files).with_threshold(8))
+    }
+
+    fn service(mock: &Arc<MockTransport>, provider: GeminiProvider) -> HTTPLlmService<Gemini> {
+        HTTPLlmService::new_with_transport(Gemini::Flash20, Arc::new(provider), mock.clone())
+    }
+
+    fn video_chat() -> Chat {
+        Chat::default().add_message(Message::user_with_parts(vec![
+            ContentPart::text("What's in this video?"),
+            ContentPart::document(b"video frames", "video/mp4"),
+            ContentPart::image_bytes(b"tiny", "image/png"),
+        ]))
+    }
+
+    /// Signs with the body length, standing in for a real MAC
+    #[derive(Debug)]
+    struct LengthSigner;
+
+    impl RequestSigner for LengthSigner {
+        fn sign(&self, request: &mut HttpRequest) -> Result<()> {
+            let signature = HeaderValue::from(request.body.len());
+            request.headers.insert("x-signature", signature);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_attachments_are_uploaded_and_referenced() {
+        let mock = Arc::new(MockTransport::new().with_responses([
+            uploaded("PROCESSING"),
+            MockResponse::ok(file("ACTIVE").to_string()),
+            reply(),
+        ]));
+        let service = service(&mock, provider().with_file_uploads(uploads(&mock)));
+
+        let chat = video_chat();
+        let message = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(message.text_content(), "A cat");
+
+        let requests = mock.requests();
+        assert_eq!(
+            requests[0].url.as_str(),
+            "https://generativelanguage.googleapis.com/upload/v1beta/files?key=test-key&uploadType=media"
+        );
+        assert_eq!(requests[0].body, b"video frames");
+        assert_eq!(requests[1].method, Method::GET);
+
+        let body: serde_json::Value = serde_json::from_slice(&requests[2].body).unwrap();
+        let parts = &body["contents"][0]["parts"];
+        assert_eq!(
+            parts[1],
+            serde_json::json!({ "file_data": {
+                "mime_type": "video/mp4",
+                "file_uri": "https://generativelanguage.googleapis.com/v1beta/files/abc"
+            }})
+        );
+        assert_eq!(parts[2]["inline_data"]["data"], "dGlueQ==");
+    }
+
+    #[tokio::test]
+    async fn test_attachments_are_uploaded_once_per_content() {
+        let mock =
+            Arc::new(MockTransport::new().with_responses([uploaded("ACTIVE"), reply(), reply()]));
+        let uploads = uploads(&mock);
+        let service = service(&mock, provider().with_file_uploads(uploads.clone()));
+
+        let chat = video_chat();
+        let reply = service.generate_next_message(&chat).await.unwrap();
+        let chat = chat
+            .add_message(reply)
+            .add_message(Message::user("Is it a kitten?"));
+        service.generate_next_message(&chat).await.unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        let body: serde_json::Value = serde_json::from_slice(&requests[2].body).unwrap();
+        assert_eq!(
+            body["contents"][0]["parts"][1]["file_data"]["file_uri"],
+            "https://generativelanguage.googleapis.com/v1beta/files/abc"
+        );
+        assert_eq!(uploads.uploaded().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_signature_covers_the_uploaded_body() {
+        let mock = Arc::new(MockTransport::new().with_responses([uploaded("ACTIVE"), reply()]));
+        let config = GeminiConfig {
+            api_key: "test-key".to_string(),
+            ..GeminiConfig::default()
+        }
+        .with_signer(Arc::new(LengthSigner));
+        let provider = GeminiProvider::with_config(config).with_file_uploads(uploads(&mock));
+
+        service(&mock, provider)
+            .generate_next_message(&video_chat())
+            .await
+            .unwrap();
+
+        let request = &mock.requests()[1];
+        assert!(request.body.windows(9).any(|window| window == b"file_data"));
+        assert_eq!(
+            request.headers["x-signature"],
+            HeaderValue::from(request.body.len())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_uploaded_keeps_files_it_could_not_delete() {
+        let mock = Arc::new(MockTransport::new().with_responses([
+            uploaded("ACTIVE"),
+            reply(),
+            MockResponse::status(500, "{}"),
+            MockResponse::status(404, "{}"),
+        ]));
+        let uploads = uploads(&mock);
+        service(&mock, provider().with_file_uploads(uploads.clone()))
+            .generate_next_message(&video_chat())
+            .await
+            .unwrap();
+
+        assert!(uploads.delete_uploaded().await.is_err());
+        assert_eq!(uploads.uploaded().await.len(), 1);
+
+        // Already gone counts as deleted
+        uploads.delete_uploaded().await.unwrap();
+        assert!(uploads.uploaded().await.is_empty());
+        let requests = mock.requests();
+        assert_eq!(requests[3].method, Method::DELETE);
+        assert_eq!(requests[3].url.path(), "/v1beta/files/abc");
+    }
+
+    #[tokio::test]
+    async fn test_api_errors_carry_the_gemini_message() {
+        let error = serde_json::json!({
+            "error": { "code": 404, "message": "File files/gone not found", "status": "NOT_FOUND" }
+        });
+        let mock = Arc::new(
+            MockTransport::new().with_fallback(MockResponse::status(404, error.to_string())),
+        );
+        let files = GeminiFiles::new(provider(), mock.clone());
+
+        match files.delete("files/gone").await {
+            Err(Error::Api(api)) => {
+                assert_eq!(api.status, 404);
+                assert_eq!(api.message, "File files/gone not found");
+            }
+            other => panic!("Expected an API error, got {other:?}"),
+        }
+        assert_eq!(mock.requests()[0].method, Method::DELETE);
+    }
+}
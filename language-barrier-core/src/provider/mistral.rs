@@ -1,19 +1,40 @@
+use crate::auth::{AuthProvider, Credential};
 use crate::error::{Error, Result};
-use crate::message::{Content, ContentPart, Message};
-use crate::provider::HTTPProvider;
+use crate::message::{Content, FinishReason, Message};
+use crate::model::Provider;
+use crate::provider::{
+    HTTPProvider, KeySource, check_api_key, check_base_url, classify_error, payload_body,
+    resolve_base_url,
+};
+use crate::secret::{Secret, deserialize_env_ref};
+use crate::signing::RequestSigner;
+use crate::transport::{HttpRequest, Method};
+use crate::usage::Usage;
 use crate::{Chat, LlmToolInfo, Mistral};
-use reqwest::{Method, Request, Url};
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::Arc;
 use tracing::{debug, error, info, instrument, trace, warn};
+use url::Url;
 
 /// Configuration for the Mistral provider
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct MistralConfig {
-    /// API key for authentication
+    /// API key for authentication; `${env:VAR}` in a config file reads it
+    /// from `VAR`
+    #[serde(deserialize_with = "deserialize_env_ref")]
     pub api_key: String,
     /// Base URL for the API
     pub base_url: String,
+    /// Credential strategy used instead of `api_key`, for tokens that
+    /// expire (see [`crate::auth`])
+    #[serde(skip)]
+    pub auth: Option<Arc<dyn AuthProvider>>,
+    /// Signs each request after its body is serialized, for gateways that
+    /// require it (see [`crate::signing`])
+    #[serde(skip)]
+    pub signer: Option<Arc<dyn RequestSigner>>,
 }
 
 impl Default for MistralConfig {
@@ -21,8 +42,147 @@ impl Default for MistralConfig {
         Self {
             api_key: env::var("MISTRAL_API_KEY").unwrap_or_default(),
             base_url: "https://api.mistral.ai/v1".to_string(),
+            auth: None,
+            signer: None,
+        }
+    }
+}
+
+impl MistralConfig {
+    /// Starts a [`MistralConfigBuilder`], which validates the config when built
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::provider::mistral::MistralConfig;
+    ///
+    /// let config = MistralConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://api.mistral.ai/v1")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> MistralConfigBuilder {
+        MistralConfigBuilder::default()
+    }
+
+    /// Sets the credential strategy used instead of `api_key` (see
+    /// [`crate::auth`]) and returns a new instance
+    #[must_use]
+    pub fn with_auth(self, auth: Arc<dyn AuthProvider>) -> Self {
+        Self {
+            auth: Some(auth),
+            ..self
+        }
+    }
+
+    /// Returns the credential strategy used instead of `api_key`, if any
+    pub fn auth(&self) -> Option<&Arc<dyn AuthProvider>> {
+        self.auth.as_ref()
+    }
+
+    /// Sets the signer for each request (see [`crate::signing`]) and
+    /// returns a new instance
+    #[must_use]
+    pub fn with_signer(self, signer: Arc<dyn RequestSigner>) -> Self {
+        Self {
+            signer: Some(signer),
+            ..self
         }
     }
+
+    /// Returns the request signer, if any
+    pub fn signer(&self) -> Option<&Arc<dyn RequestSigner>> {
+        self.signer.as_ref()
+    }
+
+    /// Checks the fields a request depends on, naming the first one that
+    /// can't work
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` for a base URL that isn't an absolute
+    /// http(s) URL, an API key that's missing (without `auth`) or has stray
+    /// whitespace, or a value that can't be sent in a header.
+    pub fn validate(&self) -> Result<()> {
+        check_base_url(Provider::Mistral, &self.base_url)?;
+        check_api_key(
+            Provider::Mistral,
+            &self.api_key,
+            "MISTRAL_API_KEY",
+            self.auth.is_some(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Builds a [`MistralConfig`], validating it on [`build`](MistralConfigBuilder::build)
+///
+/// Unset fields fall back to the environment: the API key to
+/// `MISTRAL_API_KEY`, the base URL to `MISTRAL_BASE_URL` and then the public
+/// API.
+#[derive(Debug, Clone, Default)]
+pub struct MistralConfigBuilder {
+    api_key: KeySource,
+    base_url: Option<String>,
+    auth: Option<Arc<dyn AuthProvider>>,
+    signer: Option<Arc<dyn RequestSigner>>,
+}
+
+impl MistralConfigBuilder {
+    /// Sets the API key
+    #[must_use]
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = KeySource::Value(key.into());
+        self
+    }
+
+    /// Reads the API key from the environment variable `var` at build time
+    #[must_use]
+    pub fn api_key_env(mut self, var: impl Into<String>) -> Self {
+        self.api_key = KeySource::Env(var.into());
+        self
+    }
+
+    /// Sets the base URL for the API
+    #[must_use]
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Sets the credential strategy used instead of the API key
+    #[must_use]
+    pub fn auth(mut self, auth: Arc<dyn AuthProvider>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sets the request signer
+    #[must_use]
+    pub fn signer(mut self, signer: Arc<dyn RequestSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Resolves the environment fallbacks and validates the config
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` if the variable named by
+    /// [`api_key_env`](Self::api_key_env) isn't set, or if
+    /// [`MistralConfig::validate`] rejects the result.
+    pub fn build(self) -> Result<MistralConfig> {
+        let defaults = MistralConfig::default();
+        let config = MistralConfig {
+            api_key: self.api_key.resolve(Provider::Mistral, "MISTRAL_API_KEY")?,
+            base_url: resolve_base_url(self.base_url, "MISTRAL_BASE_URL", &defaults.base_url),
+            auth: self.auth,
+            signer: self.signer,
+        };
+        config.validate()?;
+        Ok(config)
+    }
 }
 
 /// Implementation of the Mistral provider
@@ -61,10 +221,11 @@ impl MistralProvider {
     /// ```
     /// use language_barrier_core::provider::mistral::{MistralProvider, MistralConfig};
     ///
-    /// let config = MistralConfig {
-    ///     api_key: "your-api-key".to_string(),
-    ///     base_url: "https://api.mistral.ai/v1".to_string(),
-    /// };
+    /// let config = MistralConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://api.mistral.ai/v1")
+    ///     .build()
+    ///     .unwrap();
     ///
     /// let provider = MistralProvider::with_config(config);
     /// ```
@@ -76,6 +237,17 @@ impl MistralProvider {
 
         Self { config }
     }
+
+    /// Creates a provider after checking `config` with
+    /// [`MistralConfig::validate`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` naming the first invalid field.
+    pub fn try_with_config(config: MistralConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self::with_config(config))
+    }
 }
 
 impl Default for MistralProvider {
@@ -84,13 +256,10 @@ impl Default for MistralProvider {
     }
 }
 
-/// Trait to get Mistral-specific model IDs
-pub trait MistralModelInfo {
-    fn mistral_model_id(&self) -> String;
-}
+pub use crate::model::MistralModelInfo;
 
 impl HTTPProvider<Mistral> for MistralProvider {
-    fn accept(&self, model: Mistral, chat: &Chat) -> Result<Request> {
+    fn accept(&self, model: Mistral, chat: &Chat) -> Result<HttpRequest> {
         info!("Creating request for Mistral model: {:?}", model);
         debug!("Messages in chat history: {}", chat.history.len());
 
@@ -107,8 +276,8 @@ impl HTTPProvider<Mistral> for MistralProvider {
             }
         };
 
-        let mut request = Request::new(Method::POST, url);
-        debug!("Created request: {} {}", request.method(), request.url());
+        let mut request = HttpRequest::new(Method::POST, url);
+        debug!("Created request: {} {}", request.method, request.url);
 
         // Set headers
         debug!("Setting request headers");
@@ -130,12 +299,10 @@ impl HTTPProvider<Mistral> for MistralProvider {
             }
         };
 
-        request.headers_mut().insert("Authorization", auth_header);
-        request
-            .headers_mut()
-            .insert("Content-Type", content_type_header);
+        request.headers.insert("Authorization", auth_header);
+        request.headers.insert("Content-Type", content_type_header);
 
-        trace!("Request headers set: {:#?}", request.headers());
+        trace!("Request headers set: {:#?}", request.headers);
 
         // Create the request payload
         debug!("Creating request payload");
@@ -155,7 +322,7 @@ impl HTTPProvider<Mistral> for MistralProvider {
 
         // Set the request body
         debug!("Serializing request payload");
-        let body_bytes = match serde_json::to_vec(&payload) {
+        let body_bytes = match payload_body(&payload, chat, Provider::Mistral) {
             Ok(bytes) => {
                 debug!("Payload serialized successfully ({} bytes)", bytes.len());
                 bytes
@@ -166,7 +333,7 @@ impl HTTPProvider<Mistral> for MistralProvider {
             }
         };
 
-        *request.body_mut() = Some(body_bytes.into());
+        request.body = body_bytes;
         info!("Request created successfully");
 
         Ok(request)
@@ -176,14 +343,24 @@ impl HTTPProvider<Mistral> for MistralProvider {
         info!("Parsing response from Mistral API");
         trace!("Raw response: {}", raw_response_text);
 
-        // First try to parse as an error response
-        if let Ok(error_response) = serde_json::from_str::<MistralErrorResponse>(&raw_response_text)
-        {
-            if error_response.error.is_some() {
-                let error = error_response.error.unwrap();
-                error!("Mistral API returned an error: {}", error.message);
-                return Err(Error::ProviderUnavailable(error.message));
-            }
+        // First try to parse as an error response. Mistral sends errors both
+        // nested under "error" and as a bare top-level object.
+        let error = serde_json::from_str::<MistralErrorResponse>(&raw_response_text)
+            .ok()
+            .and_then(|response| response.error)
+            .or_else(|| serde_json::from_str::<MistralError>(&raw_response_text).ok());
+        if let Some(error) = error {
+            error!("Mistral API returned an error: {}", error.message);
+            // Bare 401 bodies carry only a message such as "Unauthorized"
+            let kinds = [
+                Some(error.error_type.as_str()),
+                error.code.as_deref(),
+                Some(error.message.as_str()),
+            ];
+            return Err(classify_error(
+                kinds.into_iter().flatten(),
+                error.message.clone(),
+            ));
         }
 
         // If not an error, parse as a successful response
@@ -224,6 +401,26 @@ impl HTTPProvider<Mistral> for MistralProvider {
 
         Ok(message)
     }
+
+    fn auth(&self) -> Option<Arc<dyn AuthProvider>> {
+        self.config.auth.clone()
+    }
+
+    fn signer(&self) -> Option<Arc<dyn RequestSigner>> {
+        self.config.signer.clone()
+    }
+
+    fn preflight_request(&self) -> Result<Option<HttpRequest>> {
+        let url = Url::parse(&format!("{}/models", self.config.base_url))?;
+        let mut request = HttpRequest::new(Method::GET, url);
+        let key = Credential::ApiKey(Secret::new(self.config.api_key.clone()));
+        self.authorize(&mut request, &key)?;
+        Ok(Some(request))
+    }
+
+    fn provider(&self) -> Option<Provider> {
+        Some(Provider::Mistral)
+    }
 }
 
 impl MistralProvider {
@@ -245,12 +442,13 @@ impl MistralProvider {
         debug!("Converting messages to Mistral format");
         let mut messages: Vec<MistralMessage> = Vec::new();
 
-        // Add system prompt if present
-        if !chat.system_prompt.is_empty() {
+        // Add system prompt if present, with any segments joined into one message
+        let system_text = chat.system_text();
+        if !system_text.is_empty() {
             debug!("Adding system prompt");
             messages.push(MistralMessage {
                 role: "system".to_string(),
-                content: chat.system_prompt.clone(),
+                content: system_text,
                 name: None,
                 tool_calls: None,
                 tool_call_id: None,
@@ -296,19 +494,31 @@ impl MistralProvider {
             None
         };
 
+        // Only meaningful alongside tools; the API rejects it otherwise
+        let parallel_tool_calls = tools.as_ref().and(chat.parallel_tool_calls);
+
+        let response_format = chat.output_schema.as_ref().map(|output| {
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "name": output.name, "schema": output.schema, "strict": true }
+            })
+        });
+
         // Create the request
         debug!("Creating MistralRequest");
         let request = MistralRequest {
             model: model_id,
             messages,
-            temperature: None,
+            temperature: chat.temperature,
             top_p: None,
-            max_tokens: Some(chat.max_output_tokens),
+            max_tokens: Some(chat.max_output_tokens_for(&model)?),
             stream: None,
             random_seed: None,
             safe_prompt: None,
             tools,
             tool_choice,
+            parallel_tool_calls,
+            response_format,
         };
 
         info!("Request payload created successfully");
@@ -417,6 +627,12 @@ pub(crate) struct MistralRequest {
     /// Tool choice strategy (auto, none, or a specific tool)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<serde_json::Value>,
+    /// Whether the model may call several tools in one response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+    /// Constrains the reply to JSON matching a schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
 }
 
 /// Represents a response from the Mistral API
@@ -458,6 +674,15 @@ pub(crate) struct MistralUsage {
     pub total_tokens: u32,
 }
 
+impl From<&MistralUsage> for Usage {
+    fn from(usage: &MistralUsage) -> Self {
+        Usage::new(
+            u64::from(usage.prompt_tokens),
+            u64::from(usage.completion_tokens),
+        )
+    }
+}
+
 /// Represents an error response from the Mistral API
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct MistralErrorResponse {
@@ -470,8 +695,8 @@ pub(crate) struct MistralErrorResponse {
 pub(crate) struct MistralError {
     /// The error message
     pub message: String,
-    /// The error type
-    #[serde(rename = "type")]
+    /// The error type, absent from some authentication failures
+    #[serde(rename = "type", default)]
     pub error_type: String,
     /// The error code
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -499,10 +724,7 @@ impl From<&Message> for MistralMessage {
                         // A more complete implementation would handle multimodal content
                         parts
                             .iter()
-                            .filter_map(|part| match part {
-                                ContentPart::Text { text } => Some(text.clone()),
-                                _ => None,
-                            })
+                            .filter_map(|part| part.as_text().map(str::to_string))
                             .collect::<Vec<String>>()
                             .join("\n")
                     }
@@ -520,10 +742,7 @@ impl From<&Message> for MistralMessage {
                         // Concatenate text parts
                         parts
                             .iter()
-                            .filter_map(|part| match part {
-                                ContentPart::Text { text } => Some(text.clone()),
-                                _ => None,
-                            })
+                            .filter_map(|part| part.as_text().map(str::to_string))
                             .collect::<Vec<String>>()
                             .join("\n")
                     }
@@ -664,6 +883,13 @@ impl From<&MistralResponse> for Message {
                 "total_tokens",
                 serde_json::Value::Number(usage.total_tokens.into()),
             );
+            msg = msg.with_metadata(Usage::METADATA_KEY, Usage::from(usage).to_metadata());
+        }
+        if let Some(reason) = &choice.finish_reason {
+            msg = msg.with_metadata(
+                FinishReason::METADATA_KEY,
+                FinishReason::from_provider(reason).to_metadata(),
+            );
         }
 
         msg
@@ -673,6 +899,8 @@ impl From<&MistralResponse> for Message {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::arbitrary::{assert_lossless, response_message, round_trip};
+    use proptest::prelude::*;
 
     #[test]
     fn test_message_conversion() {
@@ -714,4 +942,35 @@ mod tests {
         assert_eq!(error.error_type, "invalid_request_error");
         assert_eq!(error.code, Some("model_not_found".to_string()));
     }
+
+    #[test]
+    fn test_request_payload_snapshots() {
+        let provider = MistralProvider::default();
+        for (name, chat) in crate::provider::corpus::chats() {
+            let payload = provider
+                .create_request_payload(Mistral::Small, &chat)
+                .unwrap();
+            insta::assert_json_snapshot!(name, payload);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_response_round_trip_is_lossless(message in response_message()) {
+            let provider = MistralProvider::default();
+            let returned = round_trip(&provider, Mistral::Small, &message, "messages", |message| {
+                serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "mistral-small-latest",
+                "choices": [{"index": 0, "message": message, "finish_reason": "stop"}]
+            })
+            })
+            .unwrap();
+            assert_lossless(&message, &returned, true);
+        }
+    }
 }
@@ -1,13 +1,54 @@
-use crate::error::Result;
+// The shared request and config helpers below serve the hosted providers,
+// so they go unused in builds with none of them
+#![cfg_attr(
+    not(any(
+        feature = "anthropic",
+        feature = "openai",
+        feature = "gemini",
+        feature = "mistral"
+    )),
+    allow(dead_code)
+)]
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::debug;
+use url::Url;
+
+use crate::auth::{AuthProvider, Credential, set_bearer};
+use crate::chat::merge_json;
+use crate::error::{ApiError, Error, ProviderConfigError, Result};
+use crate::model::Provider;
+use crate::salvage::Continuation;
+use crate::signing::RequestSigner;
 use crate::{Chat, Message, ModelInfo};
 
-use reqwest::Request;
+use crate::transport::{HeaderValue, HttpRequest};
 
-// Include the provider-specific modules
+// Include the provider-specific modules, each behind its own feature
+#[cfg(feature = "anthropic")]
 pub mod anthropic;
+#[cfg(all(
+    test,
+    any(
+        feature = "anthropic",
+        feature = "openai",
+        feature = "gemini",
+        feature = "mistral",
+        feature = "ollama"
+    )
+))]
+mod corpus;
+#[cfg(feature = "gemini")]
 pub mod gemini;
+#[cfg(feature = "mistral")]
 pub mod mistral;
+#[cfg(feature = "ollama")]
 pub mod ollama;
+#[cfg(feature = "openai")]
 pub mod openai;
 
 /// An `HTTPProvider` can take a chat and turn it into an http request.
@@ -18,7 +59,7 @@ pub trait HTTPProvider<M: ModelInfo>: Send + Sync {
     ///
     /// Returns an error if the chat cannot be converted to a request, for example
     /// if the provider configuration is invalid or if serialization fails.
-    fn accept(&self, model: M, chat: &Chat) -> Result<Request>;
+    fn accept(&self, model: M, chat: &Chat) -> Result<HttpRequest>;
 
     /// Parses a raw HTTP response into a message
     ///
@@ -27,4 +68,267 @@ pub trait HTTPProvider<M: ModelInfo>: Send + Sync {
     /// Returns an error if the response cannot be parsed, for example if the
     /// response is not valid JSON or if it contains an error status.
     fn parse(&self, raw_response_text: String) -> Result<Message>;
+
+    /// The hosted provider this talks to, or `None` for a local or custom
+    /// endpoint
+    fn provider(&self) -> Option<Provider> {
+        None
+    }
+
+    /// Adds the structured details this provider puts in error bodies, such
+    /// as the quotas a request ran into, to an error built from its
+    /// response
+    fn error_details(&self, error: ApiError) -> ApiError {
+        error
+    }
+
+    /// The header this provider reads idempotency keys from, if it
+    /// deduplicates requests at all
+    fn idempotency_header(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether requests may end with an unfinished assistant turn for the
+    /// model to continue
+    fn supports_prefill(&self) -> bool {
+        false
+    }
+
+    /// How this provider is asked to continue a cut-off reply
+    fn continuation(&self) -> Continuation {
+        if self.supports_prefill() {
+            Continuation::Prefill
+        } else {
+            Continuation::Prompt
+        }
+    }
+
+    /// Whether requests should carry the `X-Title` and `HTTP-Referer`
+    /// attribution headers from the registered [`AppInfo`]
+    ///
+    /// Every provider gets the app's `User-Agent`; the attribution headers
+    /// are an OpenAI-compatible gateway convention.
+    ///
+    /// [`AppInfo`]: crate::app_info::AppInfo
+    fn sends_attribution_headers(&self) -> bool {
+        false
+    }
+
+    /// The strategy that supplies credentials for this provider's requests,
+    /// if its config has one
+    ///
+    /// Without one, `accept` authenticates with the config's static key.
+    fn auth(&self) -> Option<Arc<dyn AuthProvider>> {
+        None
+    }
+
+    /// Places a credential from [`auth`](Self::auth) on a request built by
+    /// `accept`, replacing the static key
+    ///
+    /// The default sends both kinds of credential as `Authorization:
+    /// Bearer`, which is what OpenAI-style APIs expect.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Authentication` if the credential isn't a valid
+    /// header value.
+    fn authorize(&self, request: &mut HttpRequest, credential: &Credential) -> Result<()> {
+        match credential {
+            Credential::ApiKey(key) | Credential::Bearer(key) => set_bearer(request, key),
+        }
+    }
+
+    /// The signer applied to each request just before it's sent, if the
+    /// config has one
+    fn signer(&self) -> Option<Arc<dyn RequestSigner>> {
+        None
+    }
+
+    /// The preparer that finishes each request built by `accept`, if the
+    /// provider has one
+    ///
+    /// It runs before the request is authorized and signed, so a signature
+    /// covers the body it leaves.
+    fn preparer(&self) -> Option<Arc<dyn RequestPreparer>> {
+        None
+    }
+
+    /// A cheap authenticated request, such as listing models, that shows
+    /// whether the endpoint is reachable and the static key is accepted
+    ///
+    /// Returns `None` if the provider has no such request. See
+    /// [`HTTPLlmService::preflight`](crate::HTTPLlmService::preflight).
+    fn preflight_request(&self) -> Result<Option<HttpRequest>> {
+        Ok(None)
+    }
+}
+
+/// Finishes a request that `accept` can't, because the work is
+/// asynchronous, such as uploading attachments
+#[async_trait]
+pub trait RequestPreparer: Send + Sync {
+    /// Changes `request` in place
+    ///
+    /// # Errors
+    ///
+    /// An error aborts the call and is returned to the caller.
+    async fn prepare(&self, request: &mut HttpRequest) -> Result<()>;
+}
+
+/// Checks that `base_url` is an absolute http(s) URL
+pub(crate) fn check_base_url(
+    provider: Provider,
+    base_url: &str,
+) -> std::result::Result<(), ProviderConfigError> {
+    let problem = match Url::parse(base_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => return Ok(()),
+        Ok(url) => format!("must use http or https, got {:?}", url.scheme()),
+        Err(e) => format!("must be an absolute http(s) URL, got {base_url:?} ({e})"),
+    };
+    Err(ProviderConfigError {
+        provider: provider.to_string(),
+        field: "base_url",
+        problem,
+    })
+}
+
+/// Checks that an API key is set, unless an auth strategy replaces it, and
+/// that it can be sent in a header
+pub(crate) fn check_api_key(
+    provider: Provider,
+    key: &str,
+    env_var: &str,
+    has_auth: bool,
+) -> std::result::Result<(), ProviderConfigError> {
+    let problem = if key.is_empty() {
+        if has_auth {
+            return Ok(());
+        }
+        format!("is empty; set {env_var} or configure `auth`")
+    } else if key.trim() != key {
+        "has leading or trailing whitespace".to_string()
+    } else {
+        return check_header_value(provider, "api_key", key);
+    };
+    Err(ProviderConfigError {
+        provider: provider.to_string(),
+        field: "api_key",
+        problem,
+    })
+}
+
+/// Checks that `value` can be sent as an HTTP header value
+pub(crate) fn check_header_value(
+    provider: Provider,
+    field: &'static str,
+    value: &str,
+) -> std::result::Result<(), ProviderConfigError> {
+    HeaderValue::from_str(value)
+        .map(|_| ())
+        .map_err(|_| ProviderConfigError {
+            provider: provider.to_string(),
+            field,
+            problem: "must be a valid HTTP header value, without newlines or control characters"
+                .to_string(),
+        })
+}
+
+/// Where a config builder reads its API key from
+#[derive(Debug, Clone, Default)]
+pub(crate) enum KeySource {
+    /// The provider's standard environment variable, empty if unset
+    #[default]
+    DefaultEnv,
+    /// A key given directly
+    Value(String),
+    /// A named environment variable, which must be set
+    Env(String),
+}
+
+impl KeySource {
+    /// Resolves the key, reading `default_env` for [`KeySource::DefaultEnv`]
+    pub(crate) fn resolve(
+        &self,
+        provider: Provider,
+        default_env: &str,
+    ) -> std::result::Result<String, ProviderConfigError> {
+        match self {
+            Self::DefaultEnv => Ok(std::env::var(default_env).unwrap_or_default()),
+            Self::Value(key) => Ok(key.clone()),
+            Self::Env(var) => std::env::var(var).map_err(|_| ProviderConfigError {
+                provider: provider.to_string(),
+                field: "api_key",
+                problem: format!("reads {var}, which is not set"),
+            }),
+        }
+    }
+}
+
+/// Picks a config builder's base URL: the one given, else `env_var`, else
+/// `default`
+pub(crate) fn resolve_base_url(given: Option<String>, env_var: &str, default: &str) -> String {
+    given
+        .or_else(|| std::env::var(env_var).ok().filter(|url| !url.is_empty()))
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Serializes a request payload with the chat's extra fields for `provider`
+/// merged in
+///
+/// See [`Chat::with_provider_extra`].
+pub(crate) fn payload_body(
+    payload: &impl Serialize,
+    chat: &Chat,
+    provider: Provider,
+) -> serde_json::Result<Vec<u8>> {
+    let Some(extra) = chat.provider_extras.get(&provider) else {
+        return serde_json::to_vec(payload);
+    };
+    let mut body = serde_json::to_value(payload)?;
+    if let Value::Object(fields) = &mut body {
+        debug!(
+            "Merging {} extra payload field(s) for {}",
+            extra.len(),
+            provider
+        );
+        merge_json(fields, extra.clone());
+    }
+    serde_json::to_vec(&body)
+}
+
+/// Maps the type, code or status strings of a provider error to an [`Error`]
+///
+/// Rate limits and quota exhaustion become `RateLimit`, capacity errors
+/// `Overloaded`, bad or missing credentials `Authentication`, and oversized
+/// prompts `ContextLengthExceeded`. Anything else is `ProviderUnavailable`.
+pub(crate) fn classify_error<'a>(
+    kinds: impl IntoIterator<Item = &'a str>,
+    message: String,
+) -> Error {
+    const RATE_LIMIT: &[&str] = &["rate_limit", "resource_exhausted", "insufficient_quota"];
+    const AUTHENTICATION: &[&str] = &[
+        "authentication",
+        "invalid_api_key",
+        "api_key_invalid",
+        "unauthenticated",
+        "unauthorized",
+        "permission_denied",
+    ];
+
+    for kind in kinds {
+        let kind = kind.to_ascii_lowercase();
+        if RATE_LIMIT.iter().any(|k| kind.contains(k)) {
+            return Error::RateLimit(message);
+        }
+        if kind.contains("overloaded") {
+            return Error::Overloaded(message);
+        }
+        if AUTHENTICATION.iter().any(|k| kind.contains(k)) {
+            return Error::Authentication(message);
+        }
+        if kind.contains("context_length") {
+            return Error::ContextLengthExceeded(message);
+        }
+    }
+    Error::ProviderUnavailable(message)
 }
@@ -1,16 +1,20 @@
+use crate::clock::{Clock, SystemClock};
 use crate::error::{Error, Result};
 use crate::message::{Content, ContentPart, Function, Message, ToolCall};
 
 use crate::Chat;
-use crate::model::{ModelInfo, Ollama, OllamaModelSize};
+use crate::model::{ModelInfo, Ollama};
 use crate::provider::HTTPProvider;
 use crate::tool::{LlmToolInfo, ToolChoice};
+use crate::transport::{HttpRequest, Method};
+use crate::usage::Usage;
 use async_trait::async_trait;
-use reqwest::{Client, Request, Url, header};
+use reqwest::{Client, Url, header};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
 use thiserror::Error;
 use tracing::{debug, error, info, instrument};
 
@@ -62,7 +66,8 @@ impl From<ProviderError> for Error {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct OllamaConfig {
     pub base_url: Url,
     // Ollama doesn't typically use API keys directly in headers for local instances.
@@ -83,6 +88,7 @@ impl Default for OllamaConfig {
 pub struct OllamaProvider {
     config: OllamaConfig,
     client: Client,
+    clock: Arc<dyn Clock>,
 }
 
 impl OllamaProvider {
@@ -96,9 +102,19 @@ impl OllamaProvider {
         Self {
             config,
             client: Client::new(),
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Takes the time tool call IDs are made from from `clock`
+    ///
+    /// Ollama doesn't give tool calls IDs, so they're made from the time the
+    /// response was parsed. See [`clock`](crate::clock).
+    #[must_use]
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
     /// Returns the Ollama model ID for the given model.
     fn id_for_model(&self, model: &Ollama) -> String {
         model.ollama_model_id()
@@ -171,7 +187,7 @@ impl OllamaProvider {
             }
         });
 
-        let mut format_option: Option<String> = None;
+        let mut format_option: Option<Value> = None;
         if let Some(tc) = tool_choice {
             match tc {
                 ToolChoice::Auto => {
@@ -182,7 +198,7 @@ impl OllamaProvider {
                     // Require model to use tools - closest equivalent is JSON mode for some models
                     // Ollama doesn't have a direct equivalent for "required" tool choice
                     // Setting format to "json" may encourage structured outputs
-                    format_option = Some("json".to_string());
+                    format_option = Some(Value::from("json"));
                 }
                 ToolChoice::None => {
                     // Force model not to use tools
@@ -222,10 +238,7 @@ impl OllamaProvider {
 
 impl Default for OllamaProvider {
     fn default() -> Self {
-        Self {
-            config: OllamaConfig::default(),
-            client: Client::new(),
-        }
+        Self::with_config(OllamaConfig::default())
     }
 }
 
@@ -278,7 +291,7 @@ pub(crate) struct OllamaChatRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system: Option<String>, // System prompt
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub format: Option<String>, // e.g., "json"
+    pub format: Option<Value>, // "json" or a JSON schema
     #[serde(skip_serializing_if = "Option::is_none")]
     pub options: Option<OllamaRequestOptions>,
     pub stream: bool, // For this implementation, typically false
@@ -298,7 +311,8 @@ pub(crate) struct OllamaResponseFunctionCall {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct OllamaResponseToolCall {
-    #[serde(rename = "type")]
+    // Omitted by recent Ollama versions
+    #[serde(rename = "type", default)]
     pub type_field: String, // "function"
     pub function: OllamaResponseFunctionCall,
     // Ollama API does not seem to provide an 'id' for the tool call in the response.
@@ -341,33 +355,7 @@ pub(crate) struct OllamaChatResponse {
 
 // HTTPProvider, and From implementations will be added subsequently.
 
-/// Trait for providing Ollama-specific model IDs
-pub trait OllamaModelInfo {
-    /// Returns the Ollama model ID for this model
-    fn ollama_model_id(&self) -> String;
-}
-
-impl OllamaModelInfo for Ollama {
-    fn ollama_model_id(&self) -> String {
-        match self {
-            Self::Llama3 { size } => match size {
-                OllamaModelSize::_8B => "llama3:8b",
-                OllamaModelSize::_7B => "llama3",
-                OllamaModelSize::_3B => "llama3:3b",
-                OllamaModelSize::_1B => "llama3:1b",
-            },
-            Self::Llava => "llava",
-            Self::Mistral { size } => match size {
-                OllamaModelSize::_8B => "mistral:8b",
-                OllamaModelSize::_7B => "mistral",
-                OllamaModelSize::_3B => "mistral:3b",
-                OllamaModelSize::_1B => "mistral:1b",
-            },
-            Self::Custom { name } => name,
-        }
-        .to_string()
-    }
-}
+pub use crate::model::OllamaModelInfo;
 
 #[async_trait]
 pub trait Provider<M: ModelInfo>: Send + Sync {
@@ -512,6 +500,7 @@ impl From<&Message> for OllamaMessage {
         };
 
         let mut content_texts = Vec::new();
+        #[cfg_attr(not(feature = "multimodal"), allow(unused_mut))]
         let mut image_data: Vec<String> = Vec::new();
         let mut assistant_tool_calls: Vec<OllamaResponseToolCall> = Vec::new();
 
@@ -524,9 +513,26 @@ impl From<&Message> for OllamaMessage {
                         for part in parts {
                             match part {
                                 ContentPart::Text { text } => content_texts.push(text.clone()),
+                                #[cfg(feature = "multimodal")]
                                 ContentPart::ImageUrl { image_url } => {
-                                    // Using the URL directly
-                                    image_data.push(image_url.url.clone());
+                                    // Ollama expects bare base64, so unpack `data:` URLs
+                                    let data = image_url
+                                        .data_url_parts()
+                                        .map_or(image_url.url.as_str(), |(_, data)| data);
+                                    image_data.push(data.to_string());
+                                }
+                                #[cfg(feature = "multimodal")]
+                                ContentPart::Document { document } => {
+                                    tracing::warn!(
+                                        "Ollama doesn't support document attachments, dropping {} part",
+                                        document.mime_type
+                                    );
+                                }
+                                #[cfg(feature = "multimodal")]
+                                ContentPart::Audio { .. } => {
+                                    tracing::warn!(
+                                        "Ollama doesn't support audio parts, dropping one"
+                                    );
                                 }
                             }
                         }
@@ -544,6 +550,10 @@ impl From<&Message> for OllamaMessage {
                         Content::Text(text) => content_texts.push(text.clone()),
                         Content::Parts(parts) => {
                             for part in parts {
+                                #[cfg_attr(
+                                    not(feature = "multimodal"),
+                                    allow(irrefutable_let_patterns)
+                                )]
                                 if let ContentPart::Text { text } = part {
                                     content_texts.push(text.clone());
                                 }
@@ -597,7 +607,7 @@ impl From<&Message> for OllamaMessage {
 #[async_trait]
 impl HTTPProvider<Ollama> for OllamaProvider {
     #[instrument(skip(self, model, chat), level = "debug")]
-    fn accept(&self, model: Ollama, chat: &Chat) -> Result<Request> {
+    fn accept(&self, model: Ollama, chat: &Chat) -> Result<HttpRequest> {
         info!("Creating HTTP request for Ollama model: {:?}", model);
         debug!("Number of messages in chat: {}", chat.history.len());
 
@@ -619,15 +629,13 @@ impl HTTPProvider<Ollama> for OllamaProvider {
             ollama_messages.len()
         );
 
-        // Extract system prompt
-        let system_prompt = if chat.system_prompt.is_empty() {
+        // Extract system prompt, with any segments joined into one string
+        let system_text = chat.system_text();
+        let system_prompt = if system_text.is_empty() {
             None
         } else {
-            debug!(
-                "Using system prompt from chat: {} chars",
-                chat.system_prompt.len()
-            );
-            Some(chat.system_prompt.clone())
+            debug!("Using system prompt from chat: {} chars", system_text.len());
+            Some(system_text)
         };
 
         // Handle tool configuration
@@ -643,13 +651,14 @@ impl HTTPProvider<Ollama> for OllamaProvider {
             None
         };
 
-        // Handle format option based on tool_choice
+        // An output schema constrains the reply exactly; otherwise handle
+        // the format option based on tool_choice
         let format = match chat.tool_choice {
             Some(ToolChoice::Any) => {
                 debug!(
                     "Using ToolChoice::Any - setting json format to encourage structured outputs"
                 );
-                Some("json".to_string())
+                Some(Value::from("json"))
             }
             Some(ToolChoice::Auto) => {
                 debug!("Using ToolChoice::Auto - letting the model decide");
@@ -666,13 +675,18 @@ impl HTTPProvider<Ollama> for OllamaProvider {
             }
             None => None,
         };
+        let format = chat
+            .output_schema
+            .as_ref()
+            .map(|output| output.schema.clone())
+            .or(format);
 
         // Create options
         let options = Some(OllamaRequestOptions {
-            temperature: None, // TODO: Get from chat config when added
-            top_k: None,       // TODO: Get from chat config when added
-            top_p: None,       // TODO: Get from chat config when added
-            num_predict: Some(chat.max_output_tokens as u32),
+            temperature: chat.temperature,
+            top_k: None, // TODO: Get from chat config when added
+            top_p: None, // TODO: Get from chat config when added
+            num_predict: Some(chat.max_output_tokens_for(&model)? as u32),
             stop: None, // TODO: Get from chat config when added
         });
 
@@ -691,17 +705,19 @@ impl HTTPProvider<Ollama> for OllamaProvider {
         debug!("Created Ollama request payload");
 
         // Build the HTTP request with JSON payload
-        let request = self
-            .client
-            .post(url)
-            .header(header::CONTENT_TYPE, "application/json")
-            .header(header::ACCEPT, "application/json")
-            .json(&payload)
-            .build()
-            .map_err(|e| {
-                error!("Failed to build request: {}", e);
-                crate::error::Error::Request(e)
-            })?;
+        let mut request = HttpRequest::new(Method::POST, url);
+        request.headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        request.headers.insert(
+            header::ACCEPT,
+            header::HeaderValue::from_static("application/json"),
+        );
+        request.body = serde_json::to_vec(&payload).map_err(|e| {
+            error!("Failed to serialize request: {}", e);
+            crate::error::Error::Serialization(e)
+        })?;
 
         debug!("Built Ollama HTTP request successfully");
         Ok(request)
@@ -749,16 +765,15 @@ impl HTTPProvider<Ollama> for OllamaProvider {
                 // First, prepare tool calls if present
                 let mut tool_calls = Vec::new();
                 if let Some(tool_calls_data) = ollama_response.message.tool_calls {
-                    for tool_call in tool_calls_data {
-                        // In a real UUID, would use uuid::Uuid::new_v4().to_string()
-                        // Using a timestamp-based string for now
-                        let tool_call_id = format!(
-                            "tc-{}",
-                            std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap_or_default()
-                                .as_micros()
-                        );
+                    let micros = self
+                        .clock
+                        .now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_micros();
+                    for (index, tool_call) in tool_calls_data.into_iter().enumerate() {
+                        // The index keeps calls parsed in the same microsecond apart
+                        let tool_call_id = format!("tc-{micros}-{index}");
 
                         tool_calls.push(ToolCall {
                             id: tool_call_id,
@@ -827,9 +842,24 @@ impl HTTPProvider<Ollama> for OllamaProvider {
             message_with_meta
         };
 
+        let usage = Usage::new(
+            u64::from(ollama_response.prompt_eval_count.unwrap_or(0)),
+            u64::from(ollama_response.eval_count.unwrap_or(0)),
+        );
+        let message_with_meta = if usage.is_empty() {
+            message_with_meta
+        } else {
+            message_with_meta.with_metadata(Usage::METADATA_KEY, usage.to_metadata())
+        };
+
         info!("Successfully parsed Ollama response");
         Ok(message_with_meta)
     }
+
+    fn preflight_request(&self) -> Result<Option<HttpRequest>> {
+        let url = self.config.base_url.join("tags")?;
+        Ok(Some(HttpRequest::new(Method::GET, url)))
+    }
 }
 
 // From implementations for request/response structs are defined above.
@@ -837,8 +867,12 @@ impl HTTPProvider<Ollama> for OllamaProvider {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::OllamaModelSize;
+    use crate::arbitrary::{assert_lossless, response_message, round_trip};
+    use proptest::prelude::*;
     use serde_json::json;
 
+    #[cfg(feature = "multimodal")]
     #[test]
     fn test_message_to_ollama_conversion() {
         // 1. User message with simple text
@@ -1190,7 +1224,39 @@ mod tests {
             Some("Respond in JSON format.".to_string())
         );
         assert_eq!(payload_json_mode.messages.len(), 1);
-        assert_eq!(payload_json_mode.format, Some("json".to_string()));
+        assert_eq!(payload_json_mode.format, Some(Value::from("json")));
         assert!(payload_json_mode.tools.is_none()); // Any choice without tools just sets format for Ollama
     }
+
+    #[test]
+    fn test_request_payload_snapshots() {
+        let provider = OllamaProvider::default();
+        let model = Ollama::Llama3 {
+            size: OllamaModelSize::_8B,
+        };
+        for (name, chat) in crate::provider::corpus::chats() {
+            let request = provider.accept(model, &chat).unwrap();
+            let payload: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            insta::assert_json_snapshot!(name, payload);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_response_round_trip_is_lossless(message in response_message()) {
+            let provider = OllamaProvider::default();
+            let returned = round_trip(&provider, Ollama::Llama3 { size: OllamaModelSize::_8B }, &message, "messages", |message| {
+                json!({
+                "model": "llama3",
+                "created_at": "2024-01-01T00:00:00Z",
+                "message": message,
+                "done": true
+            })
+            })
+            .unwrap();
+            assert_lossless(&message, &returned, false);
+        }
+    }
 }
@@ -1,21 +1,45 @@
+use crate::auth::{AuthProvider, Credential};
 use crate::error::{Error, Result};
-use crate::message::{Content, ContentPart, Message};
-use crate::provider::HTTPProvider;
+use crate::logprobs::TokenLogprob;
+#[cfg(feature = "multimodal")]
+use crate::message::{Audio, ImageUrl};
+use crate::message::{Content, ContentPart, FinishReason, Message};
+use crate::model::Provider;
+use crate::provider::{
+    HTTPProvider, KeySource, check_api_key, check_base_url, check_header_value, classify_error,
+    payload_body, resolve_base_url,
+};
+use crate::secret::{Secret, deserialize_env_ref};
+use crate::signing::RequestSigner;
+use crate::transport::{HttpRequest, Method};
+use crate::usage::Usage;
 use crate::{Chat, LlmToolInfo, OpenAi};
-use reqwest::{Method, Request, Url};
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::Arc;
 use tracing::{debug, error, info, instrument, trace, warn};
+use url::Url;
 
 /// Configuration for the OpenAI provider
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct OpenAIConfig {
-    /// API key for authentication
+    /// API key for authentication; `${env:VAR}` in a config file reads it
+    /// from `VAR`
+    #[serde(deserialize_with = "deserialize_env_ref")]
     pub api_key: String,
     /// Base URL for the API
     pub base_url: String,
     /// Organization ID (optional)
     pub organization: Option<String>,
+    /// Credential strategy used instead of `api_key`, for tokens that
+    /// expire (see [`crate::auth`])
+    #[serde(skip)]
+    pub auth: Option<Arc<dyn AuthProvider>>,
+    /// Signs each request after its body is serialized, for gateways that
+    /// require it (see [`crate::signing`])
+    #[serde(skip)]
+    pub signer: Option<Arc<dyn RequestSigner>>,
 }
 
 impl Default for OpenAIConfig {
@@ -24,10 +48,162 @@ impl Default for OpenAIConfig {
             api_key: env::var("OPENAI_API_KEY").unwrap_or_default(),
             base_url: "https://api.openai.com/v1".to_string(),
             organization: env::var("OPENAI_ORGANIZATION").ok(),
+            auth: None,
+            signer: None,
         }
     }
 }
 
+impl OpenAIConfig {
+    /// Starts an [`OpenAIConfigBuilder`], which validates the config when built
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::provider::openai::OpenAIConfig;
+    ///
+    /// let config = OpenAIConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://api.openai.com/v1")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> OpenAIConfigBuilder {
+        OpenAIConfigBuilder::default()
+    }
+
+    /// Sets the credential strategy used instead of `api_key` (see
+    /// [`crate::auth`]) and returns a new instance
+    #[must_use]
+    pub fn with_auth(self, auth: Arc<dyn AuthProvider>) -> Self {
+        Self {
+            auth: Some(auth),
+            ..self
+        }
+    }
+
+    /// Returns the credential strategy used instead of `api_key`, if any
+    pub fn auth(&self) -> Option<&Arc<dyn AuthProvider>> {
+        self.auth.as_ref()
+    }
+
+    /// Sets the signer for each request (see [`crate::signing`]) and
+    /// returns a new instance
+    #[must_use]
+    pub fn with_signer(self, signer: Arc<dyn RequestSigner>) -> Self {
+        Self {
+            signer: Some(signer),
+            ..self
+        }
+    }
+
+    /// Returns the request signer, if any
+    pub fn signer(&self) -> Option<&Arc<dyn RequestSigner>> {
+        self.signer.as_ref()
+    }
+
+    /// Checks the fields a request depends on, naming the first one that
+    /// can't work
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` for a base URL that isn't an absolute
+    /// http(s) URL, an API key that's missing (without `auth`) or has stray
+    /// whitespace, or a value that can't be sent in a header.
+    pub fn validate(&self) -> Result<()> {
+        check_base_url(Provider::OpenAi, &self.base_url)?;
+        check_api_key(
+            Provider::OpenAi,
+            &self.api_key,
+            "OPENAI_API_KEY",
+            self.auth.is_some(),
+        )?;
+        if let Some(organization) = &self.organization {
+            check_header_value(Provider::OpenAi, "organization", organization)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds an [`OpenAIConfig`], validating it on [`build`](OpenAIConfigBuilder::build)
+///
+/// Unset fields fall back to the environment: the API key to
+/// `OPENAI_API_KEY`, the base URL to `OPENAI_BASE_URL` and then the public
+/// API, the organization to
+/// `OPENAI_ORGANIZATION`.
+#[derive(Debug, Clone, Default)]
+pub struct OpenAIConfigBuilder {
+    api_key: KeySource,
+    base_url: Option<String>,
+    organization: Option<String>,
+    auth: Option<Arc<dyn AuthProvider>>,
+    signer: Option<Arc<dyn RequestSigner>>,
+}
+
+impl OpenAIConfigBuilder {
+    /// Sets the API key
+    #[must_use]
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = KeySource::Value(key.into());
+        self
+    }
+
+    /// Reads the API key from the environment variable `var` at build time
+    #[must_use]
+    pub fn api_key_env(mut self, var: impl Into<String>) -> Self {
+        self.api_key = KeySource::Env(var.into());
+        self
+    }
+
+    /// Sets the base URL for the API
+    #[must_use]
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Sets the organization ID
+    #[must_use]
+    pub fn organization(mut self, organization: impl Into<String>) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Sets the credential strategy used instead of the API key
+    #[must_use]
+    pub fn auth(mut self, auth: Arc<dyn AuthProvider>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Sets the request signer
+    #[must_use]
+    pub fn signer(mut self, signer: Arc<dyn RequestSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Resolves the environment fallbacks and validates the config
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` if the variable named by
+    /// [`api_key_env`](Self::api_key_env) isn't set, or if
+    /// [`OpenAIConfig::validate`] rejects the result.
+    pub fn build(self) -> Result<OpenAIConfig> {
+        let defaults = OpenAIConfig::default();
+        let config = OpenAIConfig {
+            api_key: self.api_key.resolve(Provider::OpenAi, "OPENAI_API_KEY")?,
+            base_url: resolve_base_url(self.base_url, "OPENAI_BASE_URL", &defaults.base_url),
+            organization: self.organization.or(defaults.organization),
+            auth: self.auth,
+            signer: self.signer,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
 /// Implementation of the OpenAI provider
 #[derive(Debug, Clone)]
 pub struct OpenAIProvider {
@@ -65,11 +241,11 @@ impl OpenAIProvider {
     /// ```
     /// use language_barrier_core::provider::openai::{OpenAIProvider, OpenAIConfig};
     ///
-    /// let config = OpenAIConfig {
-    ///     api_key: "your-api-key".to_string(),
-    ///     base_url: "https://api.openai.com/v1".to_string(),
-    ///     organization: None,
-    /// };
+    /// let config = OpenAIConfig::builder()
+    ///     .api_key("your-api-key")
+    ///     .base_url("https://api.openai.com/v1")
+    ///     .build()
+    ///     .unwrap();
     ///
     /// let provider = OpenAIProvider::with_config(config);
     /// ```
@@ -82,6 +258,17 @@ impl OpenAIProvider {
 
         Self { config }
     }
+
+    /// Creates a provider after checking `config` with
+    /// [`OpenAIConfig::validate`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProviderConfig` naming the first invalid field.
+    pub fn try_with_config(config: OpenAIConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self::with_config(config))
+    }
 }
 
 impl Default for OpenAIProvider {
@@ -91,7 +278,7 @@ impl Default for OpenAIProvider {
 }
 
 impl HTTPProvider<OpenAi> for OpenAIProvider {
-    fn accept(&self, model: OpenAi, chat: &Chat) -> Result<Request> {
+    fn accept(&self, model: OpenAi, chat: &Chat) -> Result<HttpRequest> {
         info!("Creating request for OpenAI model: {:?}", model);
         debug!("Messages in chat history: {}", chat.history.len());
 
@@ -108,8 +295,8 @@ impl HTTPProvider<OpenAi> for OpenAIProvider {
             }
         };
 
-        let mut request = Request::new(Method::POST, url);
-        debug!("Created request: {} {}", request.method(), request.url());
+        let mut request = HttpRequest::new(Method::POST, url);
+        debug!("Created request: {} {}", request.method, request.url);
 
         // Set headers
         debug!("Setting request headers");
@@ -131,16 +318,14 @@ impl HTTPProvider<OpenAi> for OpenAIProvider {
             }
         };
 
-        request.headers_mut().insert("Authorization", auth_header);
-        request
-            .headers_mut()
-            .insert("Content-Type", content_type_header);
+        request.headers.insert("Authorization", auth_header);
+        request.headers.insert("Content-Type", content_type_header);
 
         // Add organization header if present
         if let Some(org) = &self.config.organization {
             match org.parse() {
                 Ok(header) => {
-                    request.headers_mut().insert("OpenAI-Organization", header);
+                    request.headers.insert("OpenAI-Organization", header);
                     debug!("Added organization header");
                 }
                 Err(e) => {
@@ -150,7 +335,7 @@ impl HTTPProvider<OpenAi> for OpenAIProvider {
             }
         }
 
-        trace!("Request headers set: {:#?}", request.headers());
+        trace!("Request headers set: {:#?}", request.headers);
 
         // Create the request payload
         debug!("Creating request payload");
@@ -170,7 +355,7 @@ impl HTTPProvider<OpenAi> for OpenAIProvider {
 
         // Set the request body
         debug!("Serializing request payload");
-        let body_bytes = match serde_json::to_vec(&payload) {
+        let body_bytes = match payload_body(&payload, chat, Provider::OpenAi) {
             Ok(bytes) => {
                 debug!("Payload serialized successfully ({} bytes)", bytes.len());
                 bytes
@@ -181,7 +366,7 @@ impl HTTPProvider<OpenAi> for OpenAIProvider {
             }
         };
 
-        *request.body_mut() = Some(body_bytes.into());
+        request.body = body_bytes;
         info!("Request created successfully");
 
         Ok(request)
@@ -193,11 +378,11 @@ impl HTTPProvider<OpenAi> for OpenAIProvider {
 
         // First try to parse as an error response
         if let Ok(error_response) = serde_json::from_str::<OpenAIErrorResponse>(&raw_response_text)
+            && let Some(error) = error_response.error
         {
-            if let Some(error) = error_response.error {
-                error!("OpenAI API returned an error: {}", error.message);
-                return Err(Error::ProviderUnavailable(error.message));
-            }
+            error!("OpenAI API returned an error: {}", error.message);
+            let kinds = [Some(error.error_type.as_str()), error.code.as_deref()];
+            return Err(classify_error(kinds.into_iter().flatten(), error.message));
         }
 
         // If not an error, parse as a successful response
@@ -237,13 +422,44 @@ impl HTTPProvider<OpenAi> for OpenAIProvider {
 
         Ok(message)
     }
-}
 
-// Trait to get OpenAI-specific model IDs
-pub trait OpenAIModelInfo {
-    fn openai_model_id(&self) -> String;
+    fn auth(&self) -> Option<Arc<dyn AuthProvider>> {
+        self.config.auth.clone()
+    }
+
+    fn idempotency_header(&self) -> Option<&'static str> {
+        Some("Idempotency-Key")
+    }
+
+    // Gateways such as OpenRouter are reached through this provider
+    fn sends_attribution_headers(&self) -> bool {
+        true
+    }
+
+    fn signer(&self) -> Option<Arc<dyn RequestSigner>> {
+        self.config.signer.clone()
+    }
+
+    fn preflight_request(&self) -> Result<Option<HttpRequest>> {
+        let url = Url::parse(&format!("{}/models", self.config.base_url))?;
+        let mut request = HttpRequest::new(Method::GET, url);
+        if let Some(org) = self.config.organization.as_deref()
+            && let Ok(header) = org.parse()
+        {
+            request.headers.insert("OpenAI-Organization", header);
+        }
+        let key = Credential::ApiKey(Secret::new(self.config.api_key.clone()));
+        self.authorize(&mut request, &key)?;
+        Ok(Some(request))
+    }
+
+    fn provider(&self) -> Option<Provider> {
+        Some(Provider::OpenAi)
+    }
 }
 
+pub use crate::model::OpenAIModelInfo;
+
 impl OpenAIProvider {
     /// Creates a request payload from a Chat object
     ///
@@ -263,16 +479,18 @@ impl OpenAIProvider {
         debug!("Converting messages to OpenAI format");
         let mut messages: Vec<OpenAIMessage> = Vec::new();
 
-        // Add system prompt if present
-        if !chat.system_prompt.is_empty() {
-            debug!("Adding system prompt");
+        // Add system prompt if present, one system message per segment
+        for segment in chat.system_blocks() {
+            debug!("Adding system prompt segment");
             messages.push(OpenAIMessage {
                 role: "system".to_string(),
-                content: Some(chat.system_prompt.clone()),
+                content: Some(OpenAIContent::Text(segment.text)),
                 function_call: None,
                 name: None,
                 tool_calls: None,
                 tool_call_id: None,
+                audio: None,
+                refusal: None,
             });
         }
 
@@ -283,22 +501,14 @@ impl OpenAIProvider {
         }
 
         // OpenAI requires that every message with role "tool" directly follows the
-        // corresponding assistant message that contains a matching `tool_call`.
-        // When callers accidentally provide the messages in a different order
-        // (for example: user → *tool* → assistant) the API rejects the request
-        // with a 400:
+        // corresponding assistant message that contains a matching `tool_call`,
+        // otherwise the API rejects the request with a 400:
         //   "messages with role 'tool' must be a response to a preceeding message \
         //    with 'tool_calls'".
         //
-        // To make the client more robust we perform a best-effort re-ordering pass
-        // so that each tool response is immediately preceded by its initiating
-        // assistant message.  If we *cannot* find the corresponding assistant
-        // message we treat this as a programming error and bail out early with
-        // `Error::Other`.
-
-        // Note: The ordering of assistant and tool messages is left untouched.
-        // Responsibility for providing messages in a valid order lies with the
-        // caller.  We simply forward the history as‐is.
+        // The history is forwarded as-is. Callers that can't guarantee a valid
+        // order should opt in to `ToolOrderNormalizer` (or
+        // `Chat::normalize_tool_order`) before sending.
 
         debug!("Converted {} messages for the request", messages.len());
 
@@ -333,26 +543,58 @@ impl OpenAIProvider {
             None
         };
 
+        // Only meaningful alongside tools; the API rejects it otherwise
+        let parallel_tool_calls = tools.as_ref().and(chat.parallel_tool_calls);
+
+        // Spoken replies come alongside text, never instead of it
+        #[cfg(not(feature = "multimodal"))]
+        let audio: Option<OpenAIAudioOptions> = None;
+        #[cfg(feature = "multimodal")]
+        let audio = chat.audio_output.as_ref().map(|audio| OpenAIAudioOptions {
+            voice: audio.voice.clone(),
+            format: audio.format.clone(),
+        });
+        let modalities = audio
+            .as_ref()
+            .map(|_| vec!["text".to_string(), "audio".to_string()]);
+
         // Create the request
         debug!("Creating OpenAIRequest");
 
         // Check if this is an O-series model (starts with "o-")
         let is_o_series = model_id.starts_with("o");
+        let max_output_tokens = chat.max_output_tokens_for(&model)?;
+
+        // O-series models only accept their default temperature
+        let temperature = match chat.temperature {
+            Some(temperature) if is_o_series => {
+                warn!("Ignoring temperature {} for {}", temperature, model_id);
+                None
+            }
+            temperature => temperature,
+        };
+
+        let response_format = chat.output_schema.as_ref().map(|output| {
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": { "name": output.name, "schema": output.schema, "strict": true }
+            })
+        });
 
         let request = OpenAIRequest {
             model: model_id,
             messages,
-            temperature: None,
+            temperature,
             top_p: None,
             n: None,
             // For O-series models, use max_completion_tokens instead of max_tokens
             max_tokens: if is_o_series {
                 None
             } else {
-                Some(chat.max_output_tokens)
+                Some(max_output_tokens)
             },
             max_completion_tokens: if is_o_series {
-                Some(chat.max_output_tokens)
+                Some(max_output_tokens)
             } else {
                 None
             },
@@ -361,6 +603,12 @@ impl OpenAIProvider {
             stream: None,
             tools,
             tool_choice,
+            parallel_tool_calls,
+            modalities,
+            audio,
+            response_format,
+            logprobs: chat.logprobs.map(|_| true),
+            top_logprobs: chat.logprobs.filter(|top| *top > 0),
         };
 
         info!("Request payload created successfully");
@@ -368,6 +616,113 @@ impl OpenAIProvider {
     }
 }
 
+/// Message content in the OpenAI API format: plain text or an array of parts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum OpenAIContent {
+    /// Plain text content
+    Text(String),
+    /// Multimodal content parts
+    Parts(Vec<OpenAIContentPart>),
+}
+
+impl OpenAIContent {
+    /// Returns the text of this content, joining text parts with newlines
+    pub fn text(&self) -> Option<String> {
+        match self {
+            OpenAIContent::Text(text) => Some(text.clone()),
+            OpenAIContent::Parts(parts) => {
+                let text = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        OpenAIContentPart::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                (!text.is_empty()).then_some(text)
+            }
+        }
+    }
+}
+
+/// A content part in the OpenAI API format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum OpenAIContentPart {
+    /// A text part
+    Text { text: String },
+    /// An image, by URL or `data:` URL
+    ImageUrl { image_url: OpenAIImageUrl },
+    /// Base64 encoded audio
+    InputAudio { input_audio: OpenAIInputAudio },
+}
+
+/// Audio input in the OpenAI API format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OpenAIInputAudio {
+    /// The audio contents, base64 encoded
+    pub data: String,
+    /// The audio encoding: `wav` or `mp3`
+    pub format: String,
+}
+
+/// Generated audio in the OpenAI API format
+///
+/// Responses carry every field; requests refer back to earlier audio by
+/// `id` alone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OpenAIAudio {
+    /// The ID to refer to this audio by in later turns
+    pub id: String,
+    /// The audio contents, base64 encoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// When `id` expires, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// What was said
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<String>,
+}
+
+/// Spoken output options in the OpenAI API format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OpenAIAudioOptions {
+    /// The voice to speak with
+    pub voice: String,
+    /// The audio encoding to return
+    pub format: String,
+}
+
+/// An image reference in the OpenAI API format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct OpenAIImageUrl {
+    /// The image URL
+    pub url: String,
+    /// How closely the model looks at the image: `low`, `high` or `auto`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[cfg(feature = "multimodal")]
+impl From<&ImageUrl> for OpenAIImageUrl {
+    fn from(image_url: &ImageUrl) -> Self {
+        if let Some(detail) = &image_url.detail
+            && !matches!(detail.as_str(), "low" | "high" | "auto")
+        {
+            warn!(
+                "OpenAI image detail should be low, high or auto, not {:?}",
+                detail
+            );
+        }
+        Self {
+            url: image_url.url.clone(),
+            detail: image_url.detail.clone(),
+        }
+    }
+}
+
 /// Represents a message in the OpenAI API format
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct OpenAIMessage {
@@ -375,7 +730,7 @@ pub(crate) struct OpenAIMessage {
     pub role: String,
     /// The content of the message
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
+    pub content: Option<OpenAIContent>,
     /// The function call (deprecated in favor of tool_calls)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub function_call: Option<OpenAIFunctionCall>,
@@ -388,6 +743,19 @@ pub(crate) struct OpenAIMessage {
     /// Tool call ID
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+    /// Spoken audio generated by the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<OpenAIAudio>,
+    /// The model's explanation when it declines to answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+}
+
+impl OpenAIMessage {
+    /// Returns the text of the message content, if any
+    pub fn text(&self) -> Option<String> {
+        self.content.as_ref().and_then(OpenAIContent::text)
+    }
 }
 
 /// Represents a tool function in the OpenAI API format
@@ -480,6 +848,24 @@ pub(crate) struct OpenAIRequest {
     /// Tool choice strategy (auto, none, or a specific tool)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<serde_json::Value>,
+    /// Whether the model may call several tools in one response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+    /// Output types to generate, e.g. `["text", "audio"]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modalities: Option<Vec<String>>,
+    /// Spoken output options, required when `modalities` includes audio
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<OpenAIAudioOptions>,
+    /// Constrains the reply to JSON matching a schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+    /// Whether to return the log probability of each generated token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    /// How many alternatives to report at each position, with `logprobs`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
 }
 
 /// Represents a response from the OpenAI API
@@ -508,6 +894,17 @@ pub(crate) struct OpenAIChoice {
     pub message: OpenAIMessage,
     /// The reason generation stopped
     pub finish_reason: Option<String>,
+    /// Token logprobs, when the request asked for them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<OpenAILogprobs>,
+}
+
+/// Represents the logprobs of a choice in an OpenAI response
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct OpenAILogprobs {
+    /// Logprobs of the content tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Vec<TokenLogprob>>,
 }
 
 /// Represents usage statistics in an OpenAI response
@@ -519,6 +916,48 @@ pub(crate) struct OpenAIUsage {
     pub completion_tokens: u32,
     /// Total number of tokens
     pub total_tokens: u32,
+    /// Breakdown of the prompt tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<OpenAIPromptTokensDetails>,
+    /// Breakdown of the completion tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<OpenAICompletionTokensDetails>,
+}
+
+/// Represents the prompt token breakdown in an OpenAI response
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct OpenAIPromptTokensDetails {
+    /// Number of prompt tokens served from the cache
+    #[serde(default)]
+    pub cached_tokens: u32,
+}
+
+/// Represents the completion token breakdown in an OpenAI response
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct OpenAICompletionTokensDetails {
+    /// Number of completion tokens spent on reasoning
+    #[serde(default)]
+    pub reasoning_tokens: u32,
+}
+
+impl From<&OpenAIUsage> for Usage {
+    fn from(usage: &OpenAIUsage) -> Self {
+        let cached = usage
+            .prompt_tokens_details
+            .as_ref()
+            .map_or(0, |details| details.cached_tokens);
+        let reasoning = usage
+            .completion_tokens_details
+            .as_ref()
+            .map_or(0, |details| details.reasoning_tokens);
+
+        Usage::new(
+            u64::from(usage.prompt_tokens),
+            u64::from(usage.completion_tokens),
+        )
+        .with_cached_tokens(u64::from(cached))
+        .with_reasoning_tokens(u64::from(reasoning))
+    }
 }
 
 /// Represents an error response from the OpenAI API
@@ -541,6 +980,44 @@ pub(crate) struct OpenAIError {
     pub code: Option<String>,
 }
 
+/// Converts user content parts, keeping plain text when there is no media
+fn user_parts(parts: &[ContentPart]) -> Option<OpenAIContent> {
+    let mut converted = Vec::with_capacity(parts.len());
+    for part in parts {
+        match part {
+            ContentPart::Text { text } => {
+                converted.push(OpenAIContentPart::Text { text: text.clone() })
+            }
+            #[cfg(feature = "multimodal")]
+            ContentPart::ImageUrl { image_url } => converted.push(OpenAIContentPart::ImageUrl {
+                image_url: OpenAIImageUrl::from(image_url),
+            }),
+            #[cfg(feature = "multimodal")]
+            ContentPart::Document { .. } => {
+                warn!("OpenAI chat completions don't accept document parts; dropping one");
+            }
+            #[cfg(feature = "multimodal")]
+            ContentPart::Audio { audio } => converted.push(OpenAIContentPart::InputAudio {
+                input_audio: OpenAIInputAudio {
+                    data: audio.data.clone(),
+                    format: audio.format.clone().unwrap_or_else(|| "wav".to_string()),
+                },
+            }),
+        }
+    }
+
+    let has_media = converted
+        .iter()
+        .any(|part| !matches!(part, OpenAIContentPart::Text { .. }));
+    if has_media {
+        Some(OpenAIContent::Parts(converted))
+    } else {
+        OpenAIContent::Parts(converted)
+            .text()
+            .map(OpenAIContent::Text)
+    }
+}
+
 /// Convert from our Message to OpenAI's message format
 impl From<&Message> for OpenAIMessage {
     fn from(msg: &Message) -> Self {
@@ -553,29 +1030,19 @@ impl From<&Message> for OpenAIMessage {
         .to_string();
 
         let (content, name, function_call, tool_calls, tool_call_id) = match msg {
-            Message::System { content, .. } => (Some(content.clone()), None, None, None, None),
+            Message::System { content, .. } => (
+                Some(OpenAIContent::Text(content.clone())),
+                None,
+                None,
+                None,
+                None,
+            ),
             Message::User { content, name, .. } => {
-                let content_str = match content {
-                    Content::Text(text) => Some(text.clone()),
-                    Content::Parts(parts) => {
-                        // For text parts, concatenate them
-                        let combined_text = parts
-                            .iter()
-                            .filter_map(|part| match part {
-                                ContentPart::Text { text } => Some(text.clone()),
-                                _ => None,
-                            })
-                            .collect::<Vec<String>>()
-                            .join("\n");
-
-                        if combined_text.is_empty() {
-                            None
-                        } else {
-                            Some(combined_text)
-                        }
-                    }
+                let content = match content {
+                    Content::Text(text) => Some(OpenAIContent::Text(text.clone())),
+                    Content::Parts(parts) => user_parts(parts),
                 };
-                (content_str, name.clone(), None, None, None)
+                (content, name.clone(), None, None, None)
             }
             Message::Assistant {
                 content,
@@ -583,22 +1050,19 @@ impl From<&Message> for OpenAIMessage {
                 ..
             } => {
                 let content_str = match content {
-                    Some(Content::Text(text)) => Some(text.clone()),
+                    Some(Content::Text(text)) => Some(OpenAIContent::Text(text.clone())),
                     Some(Content::Parts(parts)) => {
                         // For text parts, concatenate them
                         let combined_text = parts
                             .iter()
-                            .filter_map(|part| match part {
-                                ContentPart::Text { text } => Some(text.clone()),
-                                _ => None,
-                            })
+                            .filter_map(|part| part.as_text().map(str::to_string))
                             .collect::<Vec<String>>()
                             .join("\n");
 
                         if combined_text.is_empty() {
                             None
                         } else {
-                            Some(combined_text)
+                            Some(OpenAIContent::Text(combined_text))
                         }
                     }
                     None => None,
@@ -631,7 +1095,7 @@ impl From<&Message> for OpenAIMessage {
                 content,
                 ..
             } => (
-                Some(content.clone()),
+                Some(OpenAIContent::Text(content.clone())),
                 None,
                 None,
                 None,
@@ -639,6 +1103,26 @@ impl From<&Message> for OpenAIMessage {
             ),
         };
 
+        // Earlier spoken replies are referred to by ID rather than resent
+        let audio = match msg {
+            Message::Assistant {
+                content: Some(Content::Parts(parts)),
+                ..
+            } => parts.iter().find_map(|part| match part {
+                #[cfg(feature = "multimodal")]
+                ContentPart::Audio {
+                    audio: Audio { id: Some(id), .. },
+                } => Some(OpenAIAudio {
+                    id: id.clone(),
+                    data: None,
+                    expires_at: None,
+                    transcript: None,
+                }),
+                _ => None,
+            }),
+            _ => None,
+        };
+
         OpenAIMessage {
             role,
             content,
@@ -646,6 +1130,8 @@ impl From<&Message> for OpenAIMessage {
             name,
             tool_calls,
             tool_call_id,
+            audio,
+            refusal: None,
         }
     }
 }
@@ -664,10 +1150,29 @@ impl From<&OpenAIResponse> for Message {
         // Create appropriate Message variant based on role
         let mut msg = match message.role.as_str() {
             "assistant" => {
-                let content = message
-                    .content
-                    .as_ref()
-                    .map(|text| Content::Text(text.clone()));
+                let content = match &message.audio {
+                    // A spoken reply has no text content; its transcript stands in
+                    Some(audio) => {
+                        #[cfg_attr(not(feature = "multimodal"), allow(unused_mut))]
+                        let mut parts: Vec<ContentPart> =
+                            audio.transcript.iter().map(ContentPart::text).collect();
+                        #[cfg(feature = "multimodal")]
+                        parts.push(ContentPart::Audio {
+                            audio: Audio {
+                                data: audio.data.clone().unwrap_or_default(),
+                                format: None,
+                                id: Some(audio.id.clone()),
+                                expires_at: audio.expires_at,
+                            },
+                        });
+                        Some(Content::Parts(parts))
+                    }
+                    // A refusal comes without content; its explanation stands in
+                    None => message
+                        .text()
+                        .or_else(|| message.refusal.clone())
+                        .map(Content::Text),
+                };
 
                 // Handle tool calls if present
                 if let Some(openai_tool_calls) = &message.tool_calls {
@@ -734,19 +1239,19 @@ impl From<&OpenAIResponse> for Message {
             }
             "user" => {
                 if let Some(name) = &message.name {
-                    if let Some(content) = &message.content {
+                    if let Some(content) = message.text() {
                         Message::user_with_name(name, content)
                     } else {
                         Message::user_with_name(name, "")
                     }
-                } else if let Some(content) = &message.content {
+                } else if let Some(content) = message.text() {
                     Message::user(content)
                 } else {
                     Message::user("")
                 }
             }
             "system" => {
-                if let Some(content) = &message.content {
+                if let Some(content) = message.text() {
                     Message::system(content)
                 } else {
                     Message::system("")
@@ -754,14 +1259,14 @@ impl From<&OpenAIResponse> for Message {
             }
             "tool" => {
                 if let Some(tool_call_id) = &message.tool_call_id {
-                    if let Some(content) = &message.content {
+                    if let Some(content) = message.text() {
                         Message::tool(tool_call_id, content)
                     } else {
                         Message::tool(tool_call_id, "")
                     }
                 } else {
                     // This shouldn't happen, but fall back to user message
-                    if let Some(content) = &message.content {
+                    if let Some(content) = message.text() {
                         Message::user(content)
                     } else {
                         Message::user("")
@@ -770,7 +1275,7 @@ impl From<&OpenAIResponse> for Message {
             }
             _ => {
                 // Default to user for unknown roles
-                if let Some(content) = &message.content {
+                if let Some(content) = message.text() {
                     Message::user(content)
                 } else {
                     Message::user("")
@@ -792,6 +1297,28 @@ impl From<&OpenAIResponse> for Message {
                 "total_tokens",
                 serde_json::Value::Number(usage.total_tokens.into()),
             );
+            msg = msg.with_metadata(Usage::METADATA_KEY, Usage::from(usage).to_metadata());
+        }
+
+        if let Some(reason) = &choice.finish_reason {
+            msg = msg.with_metadata(
+                FinishReason::METADATA_KEY,
+                FinishReason::from_provider(reason).to_metadata(),
+            );
+        }
+        // A refusal finishes with "stop"; the refusal field tells them apart
+        if message.refusal.is_some() {
+            msg = msg.with_metadata(
+                FinishReason::METADATA_KEY,
+                FinishReason::Refusal.to_metadata(),
+            );
+        }
+
+        if let Some(logprobs) = choice.logprobs.as_ref().and_then(|l| l.content.as_ref()) {
+            msg = msg.with_metadata(
+                TokenLogprob::METADATA_KEY,
+                serde_json::to_value(logprobs).unwrap_or_default(),
+            );
         }
 
         msg
@@ -801,6 +1328,54 @@ impl From<&OpenAIResponse> for Message {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::arbitrary::{assert_lossless, response_message, round_trip};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_config_deserializes_with_env_refs() {
+        let config: OpenAIConfig = serde_json::from_value(serde_json::json!({
+            "api_key": "sk-test",
+            "base_url": "https://gateway.example.com/v1",
+        }))
+        .unwrap();
+        assert_eq!(config.api_key, "sk-test");
+        assert_eq!(config.base_url, "https://gateway.example.com/v1");
+        assert!(config.auth.is_none());
+
+        let unset = serde_json::from_value::<OpenAIConfig>(serde_json::json!({
+            "api_key": "${env:LANGUAGE_BARRIER_TEST_UNSET_KEY}",
+        }));
+        let message = unset.unwrap_err().to_string();
+        assert!(message.contains("LANGUAGE_BARRIER_TEST_UNSET_KEY"), "{message}");
+    }
+
+    #[test]
+    fn test_config_validation_names_the_field() {
+        let field = |config: OpenAIConfig| match OpenAIProvider::try_with_config(config) {
+            Err(Error::ProviderConfig(e)) => {
+                assert_eq!(e.provider, "OpenAI");
+                e.field
+            }
+            other => panic!("expected a config error, got {other:?}"),
+        };
+        let config = OpenAIConfig {
+            api_key: "sk-test".to_string(),
+            ..OpenAIConfig::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let base_url = "api.openai.com/v1".to_string();
+        assert_eq!(field(OpenAIConfig { base_url, ..config.clone() }), "base_url");
+        let api_key = String::new();
+        assert_eq!(field(OpenAIConfig { api_key, ..config.clone() }), "api_key");
+        let api_key = "sk-test\n".to_string();
+        assert_eq!(field(OpenAIConfig { api_key, ..config.clone() }), "api_key");
+        let organization = Some("org\r\nx".to_string());
+        assert_eq!(
+            field(OpenAIConfig { organization, ..config }),
+            "organization"
+        );
+    }
 
     #[test]
     fn test_message_conversion() {
@@ -809,7 +1384,7 @@ mod tests {
         let openai_msg = OpenAIMessage::from(&msg);
 
         assert_eq!(openai_msg.role, "user");
-        assert_eq!(openai_msg.content, Some("Hello, world!".to_string()));
+        assert_eq!(openai_msg.text(), Some("Hello, world!".to_string()));
 
         // Test system message
         let msg = Message::system("You are a helpful assistant.");
@@ -817,7 +1392,7 @@ mod tests {
 
         assert_eq!(openai_msg.role, "system");
         assert_eq!(
-            openai_msg.content,
+            openai_msg.text(),
             Some("You are a helpful assistant.".to_string())
         );
 
@@ -826,10 +1401,7 @@ mod tests {
         let openai_msg = OpenAIMessage::from(&msg);
 
         assert_eq!(openai_msg.role, "assistant");
-        assert_eq!(
-            openai_msg.content,
-            Some("I can help with that.".to_string())
-        );
+        assert_eq!(openai_msg.text(), Some("I can help with that.".to_string()));
 
         // Test assistant message with tool calls
         let tool_call = crate::message::ToolCall {
@@ -851,7 +1423,7 @@ mod tests {
 
         assert_eq!(openai_msg.role, "assistant");
         assert_eq!(
-            openai_msg.content,
+            openai_msg.text(),
             Some("I'll check the weather".to_string())
         );
         assert!(openai_msg.tool_calls.is_some());
@@ -932,7 +1504,7 @@ mod tests {
         assert_eq!(request.messages.len(), 1);
         let msg = &request.messages[0];
         assert_eq!(msg.role, "user");
-        assert_eq!(msg.content.as_deref(), Some("What is the weather like in Paris today?"));
+        assert_eq!(msg.text().as_deref(), Some("What is the weather like in Paris today?"));
         assert!(msg.tool_calls.is_none());
         assert!(msg.tool_call_id.is_none());
 
@@ -944,6 +1516,35 @@ mod tests {
         assert_eq!(request.tool_choice, Some(serde_json::json!("auto")));
     }
 
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_user_images_serialize_as_image_url_parts() {
+        use crate::message::ContentPart;
+
+        let msg = Message::user_with_parts(vec![
+            ContentPart::text("What's in this picture?"),
+            ContentPart::ImageUrl {
+                image_url: ImageUrl::new("https://example.com/cat.png").with_detail("high"),
+            },
+        ]);
+
+        let json = serde_json::to_value(OpenAIMessage::from(&msg)).unwrap();
+        assert_eq!(
+            json["content"],
+            serde_json::json!([
+                {"type": "text", "text": "What's in this picture?"},
+                {
+                    "type": "image_url",
+                    "image_url": {"url": "https://example.com/cat.png", "detail": "high"}
+                }
+            ])
+        );
+
+        // Text-only messages keep the plain string form
+        let json = serde_json::to_value(OpenAIMessage::from(&Message::user("Hi"))).unwrap();
+        assert_eq!(json["content"], "Hi");
+    }
+
     /// Stage-2: assistant responds with a tool call (no content).  We expect
     /// the serialized payload to include the assistant message with the
     /// correct `tool_calls` structure.
@@ -1031,7 +1632,7 @@ mod tests {
         // Validate the tool message fields
         let tool = &request.messages[2];
         assert_eq!(tool.tool_call_id.as_deref(), Some(CALL_ID));
-        assert_eq!(tool.content.as_deref(), Some("10C"));
+        assert_eq!(tool.text().as_deref(), Some("10C"));
     }
 
     /// Stage-4: the assistant provides the final answer after the tool call.
@@ -1074,10 +1675,247 @@ mod tests {
 
         let assistant_after_tool = &request.messages[3];
         assert_eq!(assistant_after_tool.role, "assistant");
-        assert_eq!(assistant_after_tool.content.as_deref(), Some("The weather in Paris today is 10°C. Let me know if you need more details or the forecast for the coming days!"));
+        assert_eq!(assistant_after_tool.text().as_deref(), Some("The weather in Paris today is 10°C. Let me know if you need more details or the forecast for the coming days!"));
         assert!(assistant_after_tool.tool_calls.is_none());
     }
 
+    #[test]
+    fn test_parallel_tool_calls_serialization() {
+        use crate::model::OpenAi;
+
+        let provider = OpenAIProvider::new();
+
+        let chat = base_chat_with_tool().with_parallel_tool_calls(false);
+        let request = provider
+            .create_request_payload(OpenAi::GPT4o, &chat)
+            .expect("payload generation failed");
+        let payload = serde_json::to_value(&request).unwrap();
+        assert_eq!(payload["parallel_tool_calls"], serde_json::json!(false));
+
+        // Left unset by default, and dropped when there are no tools to call
+        let request = provider
+            .create_request_payload(OpenAi::GPT4o, &base_chat_with_tool())
+            .expect("payload generation failed");
+        assert!(request.parallel_tool_calls.is_none());
+
+        let chat = crate::Chat::default().with_parallel_tool_calls(false);
+        let request = provider
+            .create_request_payload(OpenAi::GPT4o, &chat)
+            .expect("payload generation failed");
+        assert!(request.parallel_tool_calls.is_none());
+    }
+
+    #[test]
+    fn test_system_segments_become_system_messages() {
+        use crate::chat::SystemSegment;
+        use crate::message::Message;
+        use crate::model::OpenAi;
+
+        let chat = crate::Chat::default()
+            .with_system_prompt("You are a helpful assistant.")
+            .with_system_segment(SystemSegment::new("Speak like a pirate.").with_tag("persona"))
+            .add_message(Message::user("Hello"));
+
+        let request = OpenAIProvider::new()
+            .create_request_payload(OpenAi::GPT4o, &chat)
+            .expect("payload generation failed");
+
+        let system: Vec<_> = request
+            .messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| m.text().unwrap())
+            .collect();
+        assert_eq!(
+            system,
+            vec!["You are a helpful assistant.", "Speak like a pirate."]
+        );
+        assert_eq!(request.messages[2].role, "user");
+    }
+
+    #[test]
+    fn test_max_output_tokens_clamped_to_model_limit() {
+        use crate::chat::OutputTokenPolicy;
+        use crate::model::OpenAi;
+
+        let provider = OpenAIProvider::new();
+        let chat = base_chat_with_tool().with_max_output_tokens(50_000);
+
+        let request = provider
+            .create_request_payload(OpenAi::GPT4o, &chat)
+            .expect("payload generation failed");
+        assert_eq!(request.max_tokens, Some(16_384));
+
+        let request = provider
+            .create_request_payload(OpenAi::O3Mini, &chat)
+            .expect("payload generation failed");
+        assert_eq!(request.max_completion_tokens, Some(50_000));
+
+        let chat = chat.with_output_token_policy(OutputTokenPolicy::Reject);
+        assert!(provider.create_request_payload(OpenAi::GPT4o, &chat).is_err());
+    }
+
+    #[test]
+    fn test_response_usage_details_become_typed_usage() {
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1_700_000_000u64,
+            "model": "o3-mini",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "42" },
+                "finish_reason": "stop"
+            }],
+            "usage": {
+                "prompt_tokens": 120,
+                "completion_tokens": 80,
+                "total_tokens": 200,
+                "prompt_tokens_details": { "cached_tokens": 100 },
+                "completion_tokens_details": { "reasoning_tokens": 64 }
+            }
+        }))
+        .unwrap();
+
+        let msg = Message::from(&response);
+        let usage = Usage::new(120, 80)
+            .with_cached_tokens(100)
+            .with_reasoning_tokens(64);
+        assert_eq!(msg.usage(), Some(usage));
+        assert_eq!(msg.metadata()["total_tokens"], 200);
+    }
+
+    #[test]
+    fn test_logprobs_request_and_classification() {
+        use crate::model::OpenAi;
+
+        let chat = crate::Chat::default()
+            .with_choice_output(["approve", "reject"])
+            .with_logprobs(2);
+        let request = OpenAIProvider::new()
+            .create_request_payload(OpenAi::GPT4o, &chat)
+            .expect("payload generation failed");
+        assert_eq!(request.logprobs, Some(true));
+        assert_eq!(request.top_logprobs, Some(2));
+
+        let token = |token: &str, logprob: f64| {
+            serde_json::json!({
+                "token": token, "logprob": logprob, "bytes": [], "top_logprobs": []
+            })
+        };
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1_700_000_000u64,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": r#"{"choice":"reject"}"# },
+                "logprobs": { "content": [
+                    token(r#"{""#, 0.0),
+                    token("choice", 0.0),
+                    token(r#"":""#, 0.0),
+                    token("reject", -0.2),
+                    token(r#""}"#, -0.01),
+                ]},
+                "finish_reason": "stop"
+            }]
+        }))
+        .unwrap();
+
+        let classification = Message::from(&response).classification::<String>().unwrap();
+        assert_eq!(classification.label, "reject");
+        let confidence = classification.confidence.unwrap();
+        assert!((confidence - (-0.2f64).exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_refusal_is_a_finish_reason() {
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1_700_000_000u64,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "refusal": "I can't help with that."
+                },
+                "finish_reason": "stop"
+            }]
+        }))
+        .unwrap();
+
+        let message = Message::from(&response);
+        assert!(message.is_refusal());
+        assert_eq!(message.text_content(), "I can't help with that.");
+    }
+
+    #[cfg(feature = "multimodal")]
+    #[test]
+    fn test_audio_output_request_and_response() {
+        use crate::chat::AudioOutput;
+        use crate::model::OpenAi;
+
+        let chat = crate::Chat::default()
+            .with_audio_output(AudioOutput::new("alloy", "wav"))
+            .add_message(Message::user("Say hello"));
+        let request = OpenAIProvider::new()
+            .create_request_payload(OpenAi::GPT4oAudio, &chat)
+            .expect("payload generation failed");
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["model"], "gpt-4o-audio-preview");
+        assert_eq!(json["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(
+            json["audio"],
+            serde_json::json!({"voice": "alloy", "format": "wav"})
+        );
+
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1_700_000_000u64,
+            "model": "gpt-4o-audio-preview",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "audio": {
+                        "id": "audio_abc",
+                        "expires_at": 1_700_003_600u64,
+                        "data": "UklGRg==",
+                        "transcript": "Hello!"
+                    }
+                },
+                "finish_reason": "stop"
+            }]
+        }))
+        .unwrap();
+
+        let msg = Message::from(&response);
+        assert_eq!(msg.text_content(), "Hello!");
+        let Message::Assistant {
+            content: Some(Content::Parts(parts)),
+            ..
+        } = &msg
+        else {
+            panic!("Expected assistant message with parts");
+        };
+        let ContentPart::Audio { audio } = &parts[1] else {
+            panic!("Expected audio part");
+        };
+        assert_eq!(audio.id.as_deref(), Some("audio_abc"));
+        assert_eq!(audio.bytes().unwrap(), b"RIFF");
+
+        // Replaying the reply refers to the audio by ID only
+        let json = serde_json::to_value(OpenAIMessage::from(&msg)).unwrap();
+        assert_eq!(json["audio"], serde_json::json!({"id": "audio_abc"}));
+        assert_eq!(json["content"], "Hello!");
+    }
+
     // JSON to test against follows
     // {
     //   "model": "gpt-4.1",
@@ -1136,4 +1974,35 @@ mod tests {
     //     }
     //   ]
     // }
+
+    #[test]
+    fn test_request_payload_snapshots() {
+        let provider = OpenAIProvider::default();
+        for (name, chat) in crate::provider::corpus::chats() {
+            let payload = provider
+                .create_request_payload(OpenAi::GPT4o, &chat)
+                .unwrap();
+            insta::assert_json_snapshot!(name, payload);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_response_round_trip_is_lossless(message in response_message()) {
+            let provider = OpenAIProvider::default();
+            let returned = round_trip(&provider, OpenAi::GPT4o, &message, "messages", |message| {
+                serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-4o",
+                "choices": [{"index": 0, "message": message, "finish_reason": "stop"}]
+            })
+            })
+            .unwrap();
+            assert_lossless(&message, &returned, true);
+        }
+    }
 }
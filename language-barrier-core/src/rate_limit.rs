@@ -0,0 +1,301 @@
+//! Where rate-limit tokens come from
+//!
+//! A [`PriorityLimiter`](crate::priority::PriorityLimiter) decides which
+//! waiting call goes next; a [`RateLimitBackend`] decides whether it may go
+//! yet. The default, [`InProcessBackend`], counts calls in a sliding window
+//! in process memory, which is right for one replica. With several replicas
+//! behind a load balancer each would allow the full limit, so the provider
+//! sees several times the intended rate.
+//!
+//! To share one limit between replicas, implement `RateLimitBackend` on top
+//! of a shared store and pass it to
+//! [`PriorityLimiter::with_backend`](crate::priority::PriorityLimiter::with_backend).
+//! The limiter only ever asks for tokens on behalf of the call at the head
+//! of its queue, so priorities still hold within each replica. A backend
+//! implements two calls:
+//!
+//! - [`acquire`](RateLimitBackend::acquire) takes tokens from a key's
+//!   budget if there are enough, or says how long to wait before asking
+//!   again. With Redis this is one Lua script: trim the key's sorted set to
+//!   the window, compare its size, and add the new entries, or return the
+//!   oldest entry's expiry. It must be atomic, since every replica calls it.
+//! - [`release`](RateLimitBackend::release) gives tokens back. The limiter
+//!   releases a call's token when the call failed before anything was sent
+//!   to the provider, such as a chat the preflight checks rejected.
+//!
+//! Backend errors fail the call they were asked for; a limiter that can't
+//! reach its shared store doesn't know whether it may send.
+//!
+//! # Examples
+//!
+//! A backend sharing a fixed-window counter, standing in for Redis
+//! `INCRBY`/`EXPIRE`:
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::collections::HashMap;
+//! use std::sync::{Arc, Mutex};
+//! use std::time::{Duration, Instant};
+//! use async_trait::async_trait;
+//! use language_barrier_core::Result;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::priority::PriorityLimiter;
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::rate_limit::{Acquire, RateLimitBackend};
+//! use language_barrier_core::OpenAi;
+//!
+//! /// Allows `limit` tokens per minute per key
+//! struct FixedWindow {
+//!     limit: u32,
+//!     counters: Mutex<HashMap<String, (Instant, u32)>>,
+//! }
+//!
+//! #[async_trait]
+//! impl RateLimitBackend for FixedWindow {
+//!     async fn acquire(&self, key: &str, tokens: u32) -> Result<Acquire> {
+//!         let mut counters = self.counters.lock().unwrap();
+//!         let now = Instant::now();
+//!         let (started, used) = counters.entry(key.to_string()).or_insert((now, 0));
+//!         if now.duration_since(*started) >= Duration::from_secs(60) {
+//!             (*started, *used) = (now, 0);
+//!         }
+//!         if *used + tokens > self.limit {
+//!             return Ok(Acquire::RetryAfter(*started + Duration::from_secs(60) - now));
+//!         }
+//!         *used += tokens;
+//!         Ok(Acquire::Granted)
+//!     }
+//!
+//!     async fn release(&self, key: &str, tokens: u32) -> Result<()> {
+//!         if let Some((_, used)) = self.counters.lock().unwrap().get_mut(key) {
+//!             *used = used.saturating_sub(tokens);
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let shared = Arc::new(FixedWindow { limit: 500, counters: Mutex::default() });
+//! let service = HTTPLlmService::new(OpenAi::GPT4oMini, Arc::new(OpenAIProvider::new()));
+//! let limited = PriorityLimiter::new(service, 500, Duration::from_secs(60))
+//!     .with_backend(shared, "openai:gpt-4o-mini");
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::limit_state::{self, LimitStore, Persistence};
+
+/// The answer to a request for tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Acquire {
+    /// The tokens were taken
+    Granted,
+    /// Not enough tokens; ask again after this long
+    RetryAfter(Duration),
+}
+
+/// A source of rate-limit tokens, possibly shared between processes
+///
+/// See the [module docs](self) for how to implement one.
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    /// Takes `tokens` from `key`'s budget if it has that many left
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend can't be reached, or if `tokens` is
+    /// more than the budget could ever hold.
+    async fn acquire(&self, key: &str, tokens: u32) -> Result<Acquire>;
+
+    /// Returns `tokens` taken from `key`'s budget that weren't used
+    async fn release(&self, key: &str, tokens: u32) -> Result<()>;
+}
+
+/// Returns true if a call failing with `error` never reached the provider,
+/// so its token can be released
+pub(crate) fn never_sent(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::ChatConfig(_)
+            | Error::RequestTooLarge { .. }
+            | Error::ContentBlocked { .. }
+            | Error::UnsupportedCapability { .. }
+    )
+}
+
+/// A backend allowing so many tokens per sliding window for each key, in
+/// process memory
+///
+/// The window is lost when the process restarts, unless the backend is
+/// given a store with [`with_store`](Self::with_store) (see
+/// [`limit_state`](crate::limit_state)).
+#[derive(Debug)]
+pub struct InProcessBackend {
+    requests: usize,
+    window: Duration,
+    /// Times tokens were taken inside the window, oldest first, per key
+    windows: Mutex<HashMap<String, VecDeque<Instant>>>,
+    persistence: Option<Persistence>,
+}
+
+impl InProcessBackend {
+    /// Allows `requests` tokens to be taken in any `window`, for each key
+    pub fn new(requests: usize, window: Duration) -> Self {
+        Self {
+            requests,
+            window,
+            windows: Mutex::default(),
+            persistence: None,
+        }
+    }
+
+    /// Keeps the windows in `store` under `key`, so they survive restarts
+    ///
+    /// The windows are loaded on the first call and saved after each
+    /// change. Backends in different processes sharing a key don't share
+    /// their windows; each restores whichever were saved last.
+    #[must_use]
+    pub fn with_store(self, store: Arc<dyn LimitStore>, key: impl Into<String>) -> Self {
+        Self {
+            persistence: Some(Persistence::new(store, key)),
+            ..self
+        }
+    }
+
+    fn windows(&self) -> MutexGuard<'_, HashMap<String, VecDeque<Instant>>> {
+        self.windows
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Adds the saved windows to the tokens taken since
+    async fn restore(&self) {
+        let Some(persistence) = &self.persistence else {
+            return;
+        };
+        persistence
+            .restore(|saved: HashMap<String, Vec<SystemTime>>| {
+                let mut windows = self.windows();
+                let now = Instant::now();
+                for (key, times) in saved {
+                    let window = windows.entry(key).or_default();
+                    window.extend(
+                        times
+                            .into_iter()
+                            .filter_map(limit_state::monotonic)
+                            .filter(|&taken| now.duration_since(taken) < self.window),
+                    );
+                    window.make_contiguous().sort();
+                }
+            })
+            .await;
+    }
+
+    async fn save(&self) {
+        let Some(persistence) = &self.persistence else {
+            return;
+        };
+        let saved: HashMap<String, Vec<SystemTime>> = self
+            .windows()
+            .iter()
+            .map(|(key, window)| {
+                let times = window
+                    .iter()
+                    .map(|&taken| limit_state::wall_clock(taken))
+                    .collect();
+                (key.clone(), times)
+            })
+            .collect();
+        persistence.save(&saved).await;
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for InProcessBackend {
+    async fn acquire(&self, key: &str, tokens: u32) -> Result<Acquire> {
+        let tokens = tokens as usize;
+        if tokens > self.requests {
+            return Err(Error::RateLimit(format!(
+                "{tokens} tokens asked for, but only {} are allowed per window",
+                self.requests
+            )));
+        }
+        self.restore().await;
+
+        let answer = {
+            let mut windows = self.windows();
+            let window = windows.entry(key.to_string()).or_default();
+            let now = Instant::now();
+            while window
+                .front()
+                .is_some_and(|&taken| now.duration_since(taken) >= self.window)
+            {
+                window.pop_front();
+            }
+
+            let over = (window.len() + tokens).saturating_sub(self.requests);
+            if over == 0 {
+                window.extend(std::iter::repeat_n(now, tokens));
+                Acquire::Granted
+            } else {
+                // Enough tokens are free once the oldest `over` have expired
+                let free_at = window[over - 1] + self.window;
+                Acquire::RetryAfter(free_at.duration_since(now))
+            }
+        };
+
+        if answer == Acquire::Granted {
+            self.save().await;
+        }
+        Ok(answer)
+    }
+
+    async fn release(&self, key: &str, tokens: u32) -> Result<()> {
+        {
+            let mut windows = self.windows();
+            if let Some(window) = windows.get_mut(key) {
+                let keep = window.len().saturating_sub(tokens as usize);
+                window.truncate(keep);
+                debug!("Released {} rate-limit tokens for {}", tokens, key);
+            }
+        }
+        self.save().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_in_process_backend_windows_each_key() {
+        let backend = InProcessBackend::new(2, Duration::from_secs(10));
+
+        assert_eq!(backend.acquire("a", 1).await.unwrap(), Acquire::Granted);
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert_eq!(backend.acquire("a", 1).await.unwrap(), Acquire::Granted);
+        assert_eq!(
+            backend.acquire("a", 2).await.unwrap(),
+            Acquire::RetryAfter(Duration::from_secs(10))
+        );
+        assert_eq!(
+            backend.acquire("a", 1).await.unwrap(),
+            Acquire::RetryAfter(Duration::from_secs(6))
+        );
+        assert_eq!(backend.acquire("b", 2).await.unwrap(), Acquire::Granted);
+
+        backend.release("a", 1).await.unwrap();
+        assert_eq!(backend.acquire("a", 1).await.unwrap(), Acquire::Granted);
+        assert!(matches!(
+            backend.acquire("a", 3).await,
+            Err(Error::RateLimit(_))
+        ));
+    }
+}
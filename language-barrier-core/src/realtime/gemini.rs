@@ -0,0 +1,508 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tracing::{debug, info, instrument, trace, warn};
+
+use super::{RealtimeEvent, RealtimeInput, RealtimeProvider, handshake};
+use crate::error::{Error, Result};
+use crate::message::{Function, Message, ToolCall};
+use crate::provider::gemini::{
+    GeminiConfig, GeminiFunctionDeclaration, GeminiModelInfo, GeminiTool,
+};
+use crate::usage::Usage;
+use crate::{Chat, Gemini};
+
+/// The MIME type of audio sent to Gemini Live
+const INPUT_AUDIO_MIME_TYPE: &str = "audio/pcm;rate=16000";
+
+/// Runs sessions against the Gemini Live API
+///
+/// Audio input is 16-bit PCM at 16kHz; spoken replies are 16-bit PCM at
+/// 24kHz. Gemini Live replies with either text or speech, not both, so a chat
+/// with an [`AudioOutput`](crate::chat::AudioOutput) gets speech plus a
+/// transcript and its `format` is ignored.
+#[derive(Debug, Clone)]
+pub struct GeminiLiveProvider {
+    /// Configuration for the provider
+    config: GeminiConfig,
+}
+
+impl GeminiLiveProvider {
+    /// Creates a new provider with default configuration
+    ///
+    /// This method will use the GEMINI_API_KEY environment variable for authentication.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::realtime::gemini::GeminiLiveProvider;
+    ///
+    /// let provider = GeminiLiveProvider::new();
+    /// ```
+    #[instrument(level = "debug")]
+    pub fn new() -> Self {
+        info!("Creating new GeminiLiveProvider with default configuration");
+        Self::with_config(GeminiConfig::default())
+    }
+
+    /// Creates a new provider with custom configuration
+    ///
+    /// The WebSocket endpoint is derived from `base_url`, whose last path
+    /// segment is taken as the API version.
+    #[instrument(skip(config), level = "debug")]
+    pub fn with_config(config: GeminiConfig) -> Self {
+        debug!("API key set: {}", !config.api_key.is_empty());
+        debug!("Base URL: {}", config.base_url);
+        Self { config }
+    }
+}
+
+impl Default for GeminiLiveProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RealtimeProvider<Gemini> for GeminiLiveProvider {
+    fn connect_request(&self, _model: Gemini) -> Result<Request> {
+        let base_url = self.config.base_url.trim_end_matches('/');
+        let (host, version) = base_url
+            .rsplit_once('/')
+            .ok_or_else(|| Error::Other(format!("Invalid Gemini base URL: {base_url}")))?;
+        let host = host
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+        let url = format!(
+            "{host}/ws/google.ai.generativelanguage.{version}.GenerativeService.BidiGenerateContent?key={}",
+            self.config.api_key
+        );
+        handshake(&url, &[])
+    }
+
+    fn setup(&self, model: Gemini, chat: &Chat) -> Result<Vec<String>> {
+        let (response_modalities, speech_config, output_audio_transcription) =
+            match &chat.audio_output {
+                Some(audio) => (
+                    vec!["AUDIO".to_string()],
+                    Some(serde_json::json!({
+                        "voiceConfig": { "prebuiltVoiceConfig": { "voiceName": audio.voice } }
+                    })),
+                    Some(serde_json::json!({})),
+                ),
+                None => (vec!["TEXT".to_string()], None, None),
+            };
+
+        let system_text = chat.system_text();
+        let setup = GeminiLiveClientMessage::Setup(GeminiLiveSetup {
+            model: format!("models/{}", model.gemini_model_id()),
+            generation_config: GeminiLiveGenerationConfig {
+                response_modalities,
+                speech_config,
+                temperature: chat.temperature,
+                max_output_tokens: Some(chat.max_output_tokens),
+            },
+            system_instruction: (!system_text.is_empty()).then(|| GeminiLiveContent {
+                role: None,
+                parts: vec![GeminiLivePart::text(system_text)],
+            }),
+            tools: chat.tools.as_ref().map(|tools| {
+                vec![GeminiTool {
+                    function_declarations: tools
+                        .iter()
+                        .map(GeminiFunctionDeclaration::from)
+                        .collect(),
+                }]
+            }),
+            output_audio_transcription,
+        });
+        let mut messages = vec![setup];
+
+        // Earlier turns seed the conversation without asking for a reply
+        let turns: Vec<GeminiLiveContent> = chat
+            .history
+            .iter()
+            .filter_map(|msg| {
+                let role = match msg {
+                    Message::User { .. } => "user",
+                    Message::Assistant { .. } => "model",
+                    Message::System { .. } | Message::Tool { .. } => {
+                        trace!("Skipping {} message in realtime history", msg.role_str());
+                        return None;
+                    }
+                };
+                let text = msg.text_content();
+                (!text.is_empty()).then(|| GeminiLiveContent {
+                    role: Some(role.to_string()),
+                    parts: vec![GeminiLivePart::text(text)],
+                })
+            })
+            .collect();
+        if !turns.is_empty() {
+            debug!("Seeding realtime session with {} turns", turns.len());
+            messages.push(GeminiLiveClientMessage::ClientContent {
+                turns,
+                turn_complete: false,
+            });
+        }
+
+        messages
+            .iter()
+            .map(|message| serde_json::to_string(message).map_err(Error::from))
+            .collect()
+    }
+
+    fn encode(&self, input: &RealtimeInput) -> Result<Vec<String>> {
+        let message = match input {
+            RealtimeInput::Text(text) => GeminiLiveClientMessage::ClientContent {
+                turns: vec![GeminiLiveContent {
+                    role: Some("user".to_string()),
+                    parts: vec![GeminiLivePart::text(text.clone())],
+                }],
+                turn_complete: true,
+            },
+            RealtimeInput::Audio(bytes) => {
+                GeminiLiveClientMessage::RealtimeInput(GeminiLiveRealtimeInput {
+                    audio: Some(GeminiLiveBlob {
+                        mime_type: INPUT_AUDIO_MIME_TYPE.to_string(),
+                        data: BASE64.encode(bytes),
+                    }),
+                    audio_stream_end: None,
+                })
+            }
+            RealtimeInput::CommitAudio => {
+                GeminiLiveClientMessage::RealtimeInput(GeminiLiveRealtimeInput {
+                    audio: None,
+                    audio_stream_end: Some(true),
+                })
+            }
+            // Gemini replies on its own once a turn is complete
+            RealtimeInput::CreateResponse => {
+                trace!("Gemini Live has no explicit response request; ignoring");
+                return Ok(Vec::new());
+            }
+            RealtimeInput::ToolResult {
+                call_id,
+                name,
+                output,
+            } => GeminiLiveClientMessage::ToolResponse {
+                function_responses: vec![GeminiLiveFunctionResponse {
+                    id: call_id.clone(),
+                    name: name.clone(),
+                    response: serde_json::json!({ "output": output }),
+                }],
+            },
+        };
+        Ok(vec![serde_json::to_string(&message)?])
+    }
+
+    fn decode(&self, frame: &str) -> Result<Vec<RealtimeEvent>> {
+        let message: GeminiLiveServerMessage = serde_json::from_str(frame)?;
+        let mut events = Vec::new();
+
+        if message.setup_complete.is_some() {
+            events.push(RealtimeEvent::SessionStarted { id: None });
+        }
+
+        if let Some(tool_call) = message.tool_call {
+            for call in tool_call.function_calls {
+                events.push(RealtimeEvent::ToolCall(ToolCall {
+                    id: call.id.unwrap_or_else(|| call.name.clone()),
+                    tool_type: "function".to_string(),
+                    function: Function {
+                        name: call.name,
+                        arguments: call.args.to_string(),
+                    },
+                }));
+            }
+        }
+
+        if let Some(content) = message.server_content {
+            if content.interrupted {
+                events.push(RealtimeEvent::SpeechStarted);
+            }
+            let parts = content
+                .model_turn
+                .map(|turn| turn.parts)
+                .unwrap_or_default();
+            for part in parts {
+                if let Some(text) = part.text {
+                    events.push(RealtimeEvent::TextDelta(text));
+                }
+                if let Some(blob) = part.inline_data {
+                    match BASE64.decode(&blob.data) {
+                        Ok(bytes) => events.push(RealtimeEvent::AudioDelta(bytes)),
+                        Err(e) => warn!("Dropping audio chunk with invalid base64: {}", e),
+                    }
+                }
+            }
+            if let Some(transcription) = content.output_transcription {
+                events.push(RealtimeEvent::TranscriptDelta(transcription.text));
+            }
+            if content.turn_complete {
+                events.push(RealtimeEvent::TurnComplete {
+                    usage: message.usage_metadata.map(|usage| {
+                        Usage::new(usage.prompt_token_count, usage.response_token_count)
+                    }),
+                    timing: None,
+                });
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+/// A message sent to the Gemini Live API
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum GeminiLiveClientMessage {
+    Setup(GeminiLiveSetup),
+    #[serde(rename_all = "camelCase")]
+    ClientContent {
+        turns: Vec<GeminiLiveContent>,
+        turn_complete: bool,
+    },
+    RealtimeInput(GeminiLiveRealtimeInput),
+    #[serde(rename_all = "camelCase")]
+    ToolResponse {
+        function_responses: Vec<GeminiLiveFunctionResponse>,
+    },
+}
+
+/// Session configuration in the Gemini Live API format
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveSetup {
+    pub model: String,
+    pub generation_config: GeminiLiveGenerationConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<GeminiLiveContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<GeminiTool>>,
+    /// Present (and empty) to receive transcripts of spoken replies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_audio_transcription: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveGenerationConfig {
+    pub response_modalities: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speech_config: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct GeminiLiveContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<GeminiLivePart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLivePart {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<GeminiLiveBlob>,
+}
+
+impl GeminiLivePart {
+    fn text(text: String) -> Self {
+        Self {
+            text: Some(text),
+            inline_data: None,
+        }
+    }
+}
+
+/// Inline media in the Gemini Live API format
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveBlob {
+    pub mime_type: String,
+    pub data: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveRealtimeInput {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<GeminiLiveBlob>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_stream_end: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GeminiLiveFunctionResponse {
+    pub id: String,
+    pub name: String,
+    pub response: serde_json::Value,
+}
+
+/// A message received from the Gemini Live API
+///
+/// Each message carries one of several optional payloads.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveServerMessage {
+    pub setup_complete: Option<serde_json::Value>,
+    pub server_content: Option<GeminiLiveServerContent>,
+    pub tool_call: Option<GeminiLiveToolCall>,
+    pub usage_metadata: Option<GeminiLiveUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveServerContent {
+    pub model_turn: Option<GeminiLiveContent>,
+    #[serde(default)]
+    pub turn_complete: bool,
+    #[serde(default)]
+    pub interrupted: bool,
+    pub output_transcription: Option<GeminiLiveTranscription>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GeminiLiveTranscription {
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveToolCall {
+    #[serde(default)]
+    pub function_calls: Vec<GeminiLiveFunctionCall>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GeminiLiveFunctionCall {
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiLiveUsage {
+    #[serde(default)]
+    pub prompt_token_count: u64,
+    #[serde(default)]
+    pub response_token_count: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::AudioOutput;
+
+    fn provider() -> GeminiLiveProvider {
+        GeminiLiveProvider::with_config(GeminiConfig {
+            api_key: "test-key".to_string(),
+            base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+            auth: None,
+            signer: None,
+        })
+    }
+
+    #[test]
+    fn test_setup_and_connect_request() {
+        let chat = Chat::default()
+            .with_system_prompt("Be brief.")
+            .with_audio_output(AudioOutput::new("Puck", "pcm16"))
+            .add_message(Message::user("Hi"));
+
+        let frames = provider().setup(Gemini::Flash20Live, &chat).unwrap();
+        assert_eq!(frames.len(), 2);
+
+        let setup: serde_json::Value = serde_json::from_str(&frames[0]).unwrap();
+        let setup = &setup["setup"];
+        assert_eq!(setup["model"], "models/gemini-2.0-flash-live-001");
+        assert_eq!(
+            setup["generationConfig"]["responseModalities"],
+            serde_json::json!(["AUDIO"])
+        );
+        assert_eq!(
+            setup["generationConfig"]["speechConfig"]["voiceConfig"]["prebuiltVoiceConfig"]["voiceName"],
+            "Puck"
+        );
+        assert_eq!(setup["systemInstruction"]["parts"][0]["text"], "Be brief.");
+
+        let history: serde_json::Value = serde_json::from_str(&frames[1]).unwrap();
+        assert_eq!(history["clientContent"]["turnComplete"], false);
+        assert_eq!(history["clientContent"]["turns"][0]["role"], "user");
+
+        let request = provider().connect_request(Gemini::Flash20Live).unwrap();
+        assert_eq!(
+            request.uri().to_string(),
+            "wss://generativelanguage.googleapis.com/ws/google.ai.generativelanguage.v1beta.GenerativeService.BidiGenerateContent?key=test-key"
+        );
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let frames = provider()
+            .encode(&RealtimeInput::Audio(vec![1, 2, 3]))
+            .unwrap();
+        let audio: serde_json::Value = serde_json::from_str(&frames[0]).unwrap();
+        assert_eq!(
+            audio,
+            serde_json::json!({
+                "realtimeInput": {"audio": {"mimeType": "audio/pcm;rate=16000", "data": "AQID"}}
+            })
+        );
+        assert!(
+            provider()
+                .encode(&RealtimeInput::CreateResponse)
+                .unwrap()
+                .is_empty()
+        );
+
+        let events = provider()
+            .decode(
+                &serde_json::json!({
+                    "serverContent": {
+                        "modelTurn": {"parts": [{"inlineData": {"mimeType": "audio/pcm", "data": "AQID"}}]},
+                        "outputTranscription": {"text": "Hi"},
+                        "turnComplete": true
+                    },
+                    "usageMetadata": {"promptTokenCount": 7, "responseTokenCount": 3}
+                })
+                .to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                RealtimeEvent::AudioDelta(vec![1, 2, 3]),
+                RealtimeEvent::TranscriptDelta("Hi".to_string()),
+                RealtimeEvent::TurnComplete {
+                    usage: Some(Usage::new(7, 3)),
+                    timing: None,
+                },
+            ]
+        );
+
+        let events = provider()
+            .decode(
+                &serde_json::json!({
+                    "toolCall": {"functionCalls": [{"id": "fc_1", "name": "get_weather", "args": {"city": "Paris"}}]}
+                })
+                .to_string(),
+            )
+            .unwrap();
+        let RealtimeEvent::ToolCall(call) = &events[0] else {
+            panic!("Expected a tool call");
+        };
+        assert_eq!(call.id, "fc_1");
+        assert_eq!(call.function.arguments, r#"{"city":"Paris"}"#);
+    }
+}
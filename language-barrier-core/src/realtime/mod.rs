@@ -0,0 +1,541 @@
+//! Bidirectional streaming sessions over WebSocket
+//!
+//! Realtime APIs keep one connection open for a whole conversation: the
+//! client streams text and audio in as it arrives, and the server streams
+//! text, speech and tool calls back as they're generated. That doesn't fit
+//! the request/response shape of [`HTTPProvider`](crate::provider::HTTPProvider),
+//! so realtime providers implement [`RealtimeProvider`] instead and run over a
+//! [`RealtimeSession`].
+//!
+//! This module is only available with the `realtime` cargo feature.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, trace, warn};
+
+use crate::error::{Error, Result};
+use crate::message::{Content, Message, ToolCall};
+use crate::usage::{StreamTiming, Usage};
+use crate::{Chat, ModelInfo};
+
+pub mod gemini;
+pub mod openai;
+
+/// Something sent to the model during a session
+#[derive(Debug, Clone, PartialEq)]
+pub enum RealtimeInput {
+    /// A complete user text message
+    Text(String),
+    /// A chunk of raw user audio, in the encoding the provider expects
+    Audio(Vec<u8>),
+    /// Marks the end of the audio sent so far, e.g. when the user lets go of
+    /// a push-to-talk button
+    CommitAudio,
+    /// Asks the model to respond to everything sent so far
+    CreateResponse,
+    /// The result of a tool call the model asked for
+    ToolResult {
+        /// The ID of the call being answered
+        call_id: String,
+        /// The name of the tool that was called
+        name: String,
+        /// The tool output
+        output: String,
+    },
+}
+
+/// Something the model sent during a session
+#[derive(Debug, Clone, PartialEq)]
+pub enum RealtimeEvent {
+    /// The server accepted the session configuration
+    SessionStarted {
+        /// The provider's session ID, if it sends one
+        id: Option<String>,
+    },
+    /// The next piece of a text reply
+    TextDelta(String),
+    /// The next chunk of a spoken reply, as raw audio
+    AudioDelta(Vec<u8>),
+    /// The next piece of the transcript of a spoken reply
+    TranscriptDelta(String),
+    /// The model wants a tool called; answer with [`RealtimeInput::ToolResult`]
+    ToolCall(ToolCall),
+    /// The user started speaking; stop playing any reply still queued
+    SpeechStarted,
+    /// The model finished its turn
+    TurnComplete {
+        /// Token usage for the turn, if the provider reported it
+        usage: Option<Usage>,
+        /// How quickly the turn started and streamed, measured by the
+        /// session from the input that prompted it
+        ///
+        /// Providers leave this `None`; [`RealtimeSession::next_event`]
+        /// fills it in when the turn produced any text or audio.
+        timing: Option<StreamTiming>,
+    },
+    /// The server reported a problem; the session stays open
+    Error(String),
+}
+
+/// A provider that can run a realtime session
+///
+/// Implementations only translate between the crate's types and the
+/// provider's wire format; [`RealtimeSession`] owns the connection.
+pub trait RealtimeProvider<M: ModelInfo>: Send + Sync {
+    /// Builds the WebSocket handshake request for `model`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider configuration is invalid.
+    fn connect_request(&self, model: M) -> Result<Request>;
+
+    /// Returns the frames that configure a new session from `chat`
+    ///
+    /// The chat's system prompt, tools, sampling settings and audio output
+    /// become the session configuration, and its text history seeds the
+    /// conversation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration can't be serialized.
+    fn setup(&self, model: M, chat: &Chat) -> Result<Vec<String>>;
+
+    /// Returns the frames that send `input`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input can't be serialized.
+    fn encode(&self, input: &RealtimeInput) -> Result<Vec<String>>;
+
+    /// Parses one frame from the server into zero or more events
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame isn't valid JSON.
+    fn decode(&self, frame: &str) -> Result<Vec<RealtimeEvent>>;
+}
+
+/// An open realtime session with a model
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use language_barrier_core::realtime::openai::OpenAIRealtimeProvider;
+/// use language_barrier_core::realtime::{RealtimeEvent, RealtimeInput, RealtimeSession};
+/// use language_barrier_core::{Chat, OpenAi};
+///
+/// # async fn run() -> language_barrier_core::Result<()> {
+/// let chat = Chat::default().with_system_prompt("You are a helpful assistant.");
+/// let provider = Arc::new(OpenAIRealtimeProvider::new());
+/// let mut session = RealtimeSession::connect(OpenAi::GPT4oRealtime, provider, &chat).await?;
+///
+/// session.send(RealtimeInput::Text("Hello!".into())).await?;
+/// session.send(RealtimeInput::CreateResponse).await?;
+///
+/// while let Some(event) = session.next_event().await {
+///     match event? {
+///         RealtimeEvent::TextDelta(text) => print!("{text}"),
+///         RealtimeEvent::TurnComplete { .. } => break,
+///         _ => {}
+///     }
+/// }
+/// session.close().await
+/// # }
+/// ```
+pub struct RealtimeSession<M: ModelInfo> {
+    provider: Arc<dyn RealtimeProvider<M>>,
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    pending: VecDeque<RealtimeEvent>,
+    salvage: bool,
+    turn: Option<TurnClock>,
+}
+
+/// When the turn in progress was prompted, and when its first content came
+#[derive(Debug, Clone, Copy)]
+struct TurnClock {
+    started: Instant,
+    first_token: Option<Instant>,
+}
+
+impl TurnClock {
+    /// Records the arrival of `event`, returning the turn's timing if it's
+    /// the end of the turn
+    fn observe(&mut self, event: &RealtimeEvent) -> Option<StreamTiming> {
+        match event {
+            RealtimeEvent::TextDelta(_)
+            | RealtimeEvent::AudioDelta(_)
+            | RealtimeEvent::TranscriptDelta(_)
+            | RealtimeEvent::ToolCall(_) => {
+                self.first_token.get_or_insert_with(Instant::now);
+                None
+            }
+            RealtimeEvent::TurnComplete { .. } => {
+                let first_token = self.first_token?;
+                Some(StreamTiming::new(
+                    first_token - self.started,
+                    self.started.elapsed(),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<M: ModelInfo> RealtimeSession<M> {
+    /// Opens a session with `model` and configures it from `chat`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnsupportedCapability` if the chat needs something the
+    /// model can't do, or `Error::WebSocket` if the connection fails.
+    pub async fn connect(
+        model: M,
+        provider: Arc<dyn RealtimeProvider<M>>,
+        chat: &Chat,
+    ) -> Result<Self> {
+        chat.check_capabilities(&model)?;
+
+        let request = provider.connect_request(model)?;
+        info!("Opening realtime session with {:?}", model);
+        debug!("Connecting to {}", request.uri().host().unwrap_or_default());
+        let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+
+        let mut session = Self {
+            provider,
+            socket,
+            pending: VecDeque::new(),
+            salvage: false,
+            turn: None,
+        };
+        let frames = session.provider.setup(model, chat)?;
+        session.send_frames(frames).await?;
+        Ok(session)
+    }
+
+    /// Keeps the text of a reply whose stream dies partway
+    ///
+    /// With this set, [`collect_reply`](Self::collect_reply) returns the
+    /// text received so far, marked [`truncated`](Message::truncated),
+    /// instead of an error. See [`salvage`](crate::salvage) for finishing it.
+    #[must_use]
+    pub fn with_partial_salvage(self) -> Self {
+        Self {
+            salvage: true,
+            ..self
+        }
+    }
+
+    /// Sends input to the model
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::WebSocket` if the connection has failed.
+    pub async fn send(&mut self, input: RealtimeInput) -> Result<()> {
+        let frames = self.provider.encode(&input)?;
+        self.send_frames(frames).await?;
+        // The turn's clock starts with the first input that could prompt it
+        self.turn.get_or_insert(TurnClock {
+            started: Instant::now(),
+            first_token: None,
+        });
+        Ok(())
+    }
+
+    /// Waits for the next event from the model
+    ///
+    /// Returns `None` once the server closes the session.
+    pub async fn next_event(&mut self) -> Option<Result<RealtimeEvent>> {
+        loop {
+            if let Some(mut event) = self.pending.pop_front() {
+                self.time(&mut event);
+                return Some(Ok(event));
+            }
+
+            let text = match self.socket.next().await? {
+                Ok(Frame::Text(text)) => text,
+                // Some servers send JSON in binary frames
+                Ok(Frame::Binary(bytes)) => match String::from_utf8(bytes) {
+                    Ok(text) => text,
+                    Err(_) => {
+                        warn!("Ignoring non-UTF-8 binary frame");
+                        continue;
+                    }
+                },
+                Ok(Frame::Close(frame)) => {
+                    debug!("Realtime session closed by server: {:?}", frame);
+                    return None;
+                }
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e.into())),
+            };
+
+            trace!("Realtime frame: {}", text);
+            match self.provider.decode(&text) {
+                Ok(events) => self.pending.extend(events),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Collects events until the model finishes its turn, as one message
+    ///
+    /// The reply holds the turn's text, or the transcript of a spoken reply,
+    /// and its tool calls and usage. Audio and other events are dropped;
+    /// use [`next_event`](Self::next_event) to play speech as it arrives.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the connection drops, the server closes the session or
+    /// reports an error before the turn is complete. With
+    /// [`with_partial_salvage`](Self::with_partial_salvage), a turn that
+    /// already produced text is returned as a truncated reply instead.
+    pub async fn collect_reply(&mut self) -> Result<Message> {
+        let mut text = String::new();
+        let mut transcript = String::new();
+        let mut tool_calls = Vec::new();
+
+        let failure = loop {
+            match self.next_event().await {
+                Some(Ok(RealtimeEvent::TextDelta(delta))) => text.push_str(&delta),
+                Some(Ok(RealtimeEvent::TranscriptDelta(delta))) => transcript.push_str(&delta),
+                Some(Ok(RealtimeEvent::ToolCall(call))) => tool_calls.push(call),
+                Some(Ok(RealtimeEvent::TurnComplete { usage, timing })) => {
+                    let mut reply = reply(text, transcript, tool_calls);
+                    if let Some(usage) = usage {
+                        reply = reply.with_metadata(Usage::METADATA_KEY, usage.to_metadata());
+                    }
+                    if let Some(timing) = timing {
+                        reply =
+                            reply.with_metadata(StreamTiming::METADATA_KEY, timing.to_metadata());
+                    }
+                    return Ok(reply);
+                }
+                Some(Ok(RealtimeEvent::Error(message))) => break Error::Other(message),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => break e,
+                None => break Error::Other("Realtime session closed mid-turn".into()),
+            }
+        };
+
+        if self.salvage && !(text.is_empty() && transcript.is_empty()) {
+            warn!("Reply cut off, keeping the partial text: {}", failure);
+            return Ok(reply(text, transcript, tool_calls).truncated());
+        }
+        Err(failure)
+    }
+
+    /// Closes the session
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::WebSocket` if the close handshake fails.
+    pub async fn close(mut self) -> Result<()> {
+        info!("Closing realtime session");
+        self.socket.close(None).await?;
+        Ok(())
+    }
+
+    /// Times the turn in progress, filling in the timing of a completed one
+    fn time(&mut self, event: &mut RealtimeEvent) {
+        let Some(turn) = &mut self.turn else {
+            return;
+        };
+        let measured = turn.observe(event);
+        if let RealtimeEvent::TurnComplete { timing, .. } = event {
+            *timing = timing.or(measured);
+            self.turn = None;
+        }
+    }
+
+    async fn send_frames(&mut self, frames: Vec<String>) -> Result<()> {
+        for frame in frames {
+            trace!("Sending realtime frame: {}", frame);
+            self.socket.send(Frame::Text(frame)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the message for a turn, preferring text over a speech transcript
+fn reply(text: String, transcript: String, tool_calls: Vec<ToolCall>) -> Message {
+    let text = if text.is_empty() { transcript } else { text };
+    match (text.is_empty(), tool_calls.is_empty()) {
+        (true, false) => Message::assistant_with_tool_calls(tool_calls),
+        (_, true) => Message::assistant(text),
+        (false, false) => Message::Assistant {
+            content: Some(Content::Text(text)),
+            tool_calls,
+            metadata: Default::default(),
+        },
+    }
+}
+
+/// Builds a handshake request for `url` with extra headers
+fn handshake(url: &str, headers: &[(&'static str, String)]) -> Result<Request> {
+    let mut request = url.into_client_request()?;
+    for (name, value) in headers {
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| Error::Other(format!("Invalid {name} header: {e}")))?;
+        request.headers_mut().insert(*name, value);
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OpenAi;
+    use tokio::net::TcpListener;
+
+    /// Sends input text as-is and reports every frame as a text delta,
+    /// except `done`, which ends the turn
+    struct EchoProvider {
+        url: String,
+    }
+
+    impl RealtimeProvider<OpenAi> for EchoProvider {
+        fn connect_request(&self, _model: OpenAi) -> Result<Request> {
+            handshake(&self.url, &[("Authorization", "Bearer test".to_string())])
+        }
+
+        fn setup(&self, _model: OpenAi, chat: &Chat) -> Result<Vec<String>> {
+            Ok(vec![chat.system_text()])
+        }
+
+        fn encode(&self, input: &RealtimeInput) -> Result<Vec<String>> {
+            match input {
+                RealtimeInput::Text(text) => Ok(vec![text.clone()]),
+                _ => Ok(Vec::new()),
+            }
+        }
+
+        fn decode(&self, frame: &str) -> Result<Vec<RealtimeEvent>> {
+            if frame == "done" {
+                return Ok(vec![RealtimeEvent::TurnComplete {
+                    usage: Some(Usage::new(5, 20)),
+                    timing: None,
+                }]);
+            }
+            Ok(vec![RealtimeEvent::TextDelta(frame.to_string())])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        // Echoes the setup frame and one input frame, then hangs up
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            for _ in 0..2 {
+                let frame = socket.next().await.unwrap().unwrap();
+                socket.send(frame).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+        });
+
+        let chat = Chat::default().with_system_prompt("setup");
+        let provider = Arc::new(EchoProvider { url });
+        let mut session = RealtimeSession::connect(OpenAi::GPT4oRealtime, provider, &chat)
+            .await
+            .unwrap();
+        session
+            .send(RealtimeInput::Text("ping".to_string()))
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = session.next_event().await {
+            events.push(event.unwrap());
+        }
+        assert_eq!(
+            events,
+            vec![
+                RealtimeEvent::TextDelta("setup".to_string()),
+                RealtimeEvent::TextDelta("ping".to_string()),
+            ]
+        );
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_turns_are_timed_from_the_prompt() {
+        use std::time::Duration;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        // Thinks before the first token, streams a while, then ends the turn
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            socket.next().await.unwrap().unwrap();
+            socket.next().await.unwrap().unwrap();
+            for (pause, frame) in [(100, "Par"), (100, "is"), (0, "done")] {
+                tokio::time::sleep(Duration::from_millis(pause)).await;
+                socket.send(Frame::Text(frame.to_string())).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+        });
+
+        let provider = Arc::new(EchoProvider { url });
+        let mut session =
+            RealtimeSession::connect(OpenAi::GPT4oRealtime, provider, &Chat::default())
+                .await
+                .unwrap();
+        session
+            .send(RealtimeInput::Text("Capital of France?".to_string()))
+            .await
+            .unwrap();
+        let reply = session.collect_reply().await.unwrap();
+        server.await.unwrap();
+
+        assert_eq!(reply.text_content(), "Paris");
+        let timing = reply.stream_timing().unwrap();
+        assert!(timing.time_to_first_token >= Duration::from_millis(100));
+        assert!(timing.generation_time() >= Duration::from_millis(100));
+        let rate = timing.tokens_per_second(reply.usage().unwrap().output_tokens);
+        assert!(rate.unwrap() <= 200.0);
+    }
+
+    #[tokio::test]
+    async fn test_partial_reply_is_salvaged() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        // Starts a reply, then drops the connection mid-turn
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            socket.next().await.unwrap().unwrap();
+            socket
+                .send(Frame::Text("The capital of France".to_string()))
+                .await
+                .unwrap();
+            drop(socket);
+        });
+
+        let provider = Arc::new(EchoProvider { url });
+        let mut session =
+            RealtimeSession::connect(OpenAi::GPT4oRealtime, provider, &Chat::default())
+                .await
+                .unwrap()
+                .with_partial_salvage();
+        let reply = session.collect_reply().await.unwrap();
+        assert_eq!(reply.text_content(), "The capital of France");
+        assert!(reply.is_truncated());
+        server.await.unwrap();
+    }
+}
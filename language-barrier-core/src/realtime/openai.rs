@@ -0,0 +1,478 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tracing::{debug, info, instrument, trace, warn};
+
+use super::{RealtimeEvent, RealtimeInput, RealtimeProvider, handshake};
+use crate::error::{Error, Result};
+use crate::message::{Function, Message, ToolCall};
+use crate::provider::openai::{OpenAIConfig, OpenAIModelInfo};
+use crate::tool::ToolChoice;
+use crate::usage::Usage;
+use crate::{Chat, OpenAi};
+
+/// Runs sessions against OpenAI's Realtime API
+///
+/// Audio in both directions is 16-bit PCM at 24kHz unless the chat's
+/// [`AudioOutput`](crate::chat::AudioOutput) asks for another output format
+/// (`g711_ulaw` or `g711_alaw`).
+#[derive(Debug, Clone)]
+pub struct OpenAIRealtimeProvider {
+    /// Configuration for the provider
+    config: OpenAIConfig,
+}
+
+impl OpenAIRealtimeProvider {
+    /// Creates a new provider with default configuration
+    ///
+    /// This method will use the OPENAI_API_KEY environment variable for authentication.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::realtime::openai::OpenAIRealtimeProvider;
+    ///
+    /// let provider = OpenAIRealtimeProvider::new();
+    /// ```
+    #[instrument(level = "debug")]
+    pub fn new() -> Self {
+        info!("Creating new OpenAIRealtimeProvider with default configuration");
+        Self::with_config(OpenAIConfig::default())
+    }
+
+    /// Creates a new provider with custom configuration
+    ///
+    /// The WebSocket URL is derived from `base_url` by switching its scheme
+    /// to `wss`.
+    #[instrument(skip(config), level = "debug")]
+    pub fn with_config(config: OpenAIConfig) -> Self {
+        debug!("API key set: {}", !config.api_key.is_empty());
+        debug!("Base URL: {}", config.base_url);
+        Self { config }
+    }
+
+    /// Creates the `session.update` event that configures a session
+    fn session_update(&self, chat: &Chat) -> OpenAIClientEvent {
+        let (modalities, voice, output_audio_format) = match &chat.audio_output {
+            Some(audio) => (
+                vec!["text".to_string(), "audio".to_string()],
+                Some(audio.voice.clone()),
+                Some(audio.format.clone()),
+            ),
+            None => (vec!["text".to_string()], None, None),
+        };
+
+        let tools = chat.tools.as_ref().map(|tools| {
+            tools
+                .iter()
+                .map(|tool| OpenAIRealtimeTool {
+                    r#type: "function".to_string(),
+                    name: tool.name.clone(),
+                    description: tool.description.clone(),
+                    parameters: tool.parameters.clone(),
+                })
+                .collect()
+        });
+
+        let tool_choice = chat.tool_choice.as_ref().map(|choice| match choice {
+            ToolChoice::Auto => serde_json::json!("auto"),
+            ToolChoice::Any => serde_json::json!("required"),
+            ToolChoice::None => serde_json::json!("none"),
+            ToolChoice::Specific(name) => serde_json::json!({ "type": "function", "name": name }),
+        });
+
+        let instructions = chat.system_text();
+        OpenAIClientEvent::SessionUpdate {
+            session: OpenAIRealtimeSession {
+                modalities,
+                instructions: (!instructions.is_empty()).then_some(instructions),
+                voice,
+                output_audio_format,
+                tools,
+                tool_choice,
+                temperature: chat.temperature,
+                max_response_output_tokens: Some(chat.max_output_tokens),
+            },
+        }
+    }
+}
+
+impl Default for OpenAIRealtimeProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RealtimeProvider<OpenAi> for OpenAIRealtimeProvider {
+    fn connect_request(&self, model: OpenAi) -> Result<Request> {
+        let base_url = self
+            .config
+            .base_url
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+        let url = format!("{}/realtime?model={}", base_url, model.openai_model_id());
+
+        let mut headers = vec![
+            ("Authorization", format!("Bearer {}", self.config.api_key)),
+            ("OpenAI-Beta", "realtime=v1".to_string()),
+        ];
+        if let Some(org) = &self.config.organization {
+            headers.push(("OpenAI-Organization", org.clone()));
+        }
+        handshake(&url, &headers)
+    }
+
+    fn setup(&self, _model: OpenAi, chat: &Chat) -> Result<Vec<String>> {
+        let mut events = vec![self.session_update(chat)];
+
+        // Earlier turns become conversation items; only text carries over
+        for msg in &chat.history {
+            let (role, content_type) = match msg {
+                Message::User { .. } => ("user", "input_text"),
+                Message::Assistant { .. } => ("assistant", "text"),
+                Message::System { .. } | Message::Tool { .. } => {
+                    trace!("Skipping {} message in realtime history", msg.role_str());
+                    continue;
+                }
+            };
+            let text = msg.text_content();
+            if text.is_empty() {
+                continue;
+            }
+            events.push(OpenAIClientEvent::message(role, content_type, text));
+        }
+        debug!("Seeding realtime session with {} events", events.len());
+
+        events
+            .iter()
+            .map(|event| serde_json::to_string(event).map_err(Error::from))
+            .collect()
+    }
+
+    fn encode(&self, input: &RealtimeInput) -> Result<Vec<String>> {
+        let event = match input {
+            RealtimeInput::Text(text) => {
+                OpenAIClientEvent::message("user", "input_text", text.clone())
+            }
+            RealtimeInput::Audio(bytes) => OpenAIClientEvent::InputAudioBufferAppend {
+                audio: BASE64.encode(bytes),
+            },
+            RealtimeInput::CommitAudio => OpenAIClientEvent::InputAudioBufferCommit,
+            RealtimeInput::CreateResponse => OpenAIClientEvent::ResponseCreate,
+            RealtimeInput::ToolResult {
+                call_id, output, ..
+            } => OpenAIClientEvent::ConversationItemCreate {
+                item: OpenAIRealtimeItem::FunctionCallOutput {
+                    call_id: call_id.clone(),
+                    output: output.clone(),
+                },
+            },
+        };
+        Ok(vec![serde_json::to_string(&event)?])
+    }
+
+    fn decode(&self, frame: &str) -> Result<Vec<RealtimeEvent>> {
+        let event = match serde_json::from_str::<OpenAIServerEvent>(frame)? {
+            OpenAIServerEvent::SessionCreated { session } => {
+                RealtimeEvent::SessionStarted { id: session.id }
+            }
+            OpenAIServerEvent::TextDelta { delta } => RealtimeEvent::TextDelta(delta),
+            OpenAIServerEvent::AudioDelta { delta } => match BASE64.decode(&delta) {
+                Ok(bytes) => RealtimeEvent::AudioDelta(bytes),
+                Err(e) => {
+                    warn!("Dropping audio delta with invalid base64: {}", e);
+                    return Ok(Vec::new());
+                }
+            },
+            OpenAIServerEvent::AudioTranscriptDelta { delta } => {
+                RealtimeEvent::TranscriptDelta(delta)
+            }
+            OpenAIServerEvent::FunctionCallArgumentsDone {
+                call_id,
+                name,
+                arguments,
+            } => RealtimeEvent::ToolCall(ToolCall {
+                id: call_id,
+                tool_type: "function".to_string(),
+                function: Function { name, arguments },
+            }),
+            OpenAIServerEvent::SpeechStarted => RealtimeEvent::SpeechStarted,
+            OpenAIServerEvent::ResponseDone { response } => RealtimeEvent::TurnComplete {
+                usage: response
+                    .usage
+                    .map(|usage| Usage::new(usage.input_tokens, usage.output_tokens)),
+                timing: None,
+            },
+            OpenAIServerEvent::Error { error } => {
+                warn!("OpenAI realtime error: {}", error.message);
+                RealtimeEvent::Error(error.message)
+            }
+            OpenAIServerEvent::Unknown => return Ok(Vec::new()),
+        };
+        Ok(vec![event])
+    }
+}
+
+/// An event sent to the OpenAI Realtime API
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub(crate) enum OpenAIClientEvent {
+    #[serde(rename = "session.update")]
+    SessionUpdate { session: OpenAIRealtimeSession },
+    #[serde(rename = "conversation.item.create")]
+    ConversationItemCreate { item: OpenAIRealtimeItem },
+    #[serde(rename = "input_audio_buffer.append")]
+    InputAudioBufferAppend { audio: String },
+    #[serde(rename = "input_audio_buffer.commit")]
+    InputAudioBufferCommit,
+    #[serde(rename = "response.create")]
+    ResponseCreate,
+}
+
+impl OpenAIClientEvent {
+    /// Creates a text message item
+    fn message(role: &str, content_type: &str, text: String) -> Self {
+        Self::ConversationItemCreate {
+            item: OpenAIRealtimeItem::Message {
+                role: role.to_string(),
+                content: vec![OpenAIRealtimeContent {
+                    r#type: content_type.to_string(),
+                    text,
+                }],
+            },
+        }
+    }
+}
+
+/// Session configuration in the OpenAI Realtime API format
+#[derive(Debug, Serialize)]
+pub(crate) struct OpenAIRealtimeSession {
+    /// Output types to generate
+    pub modalities: Vec<String>,
+    /// The system prompt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// The voice to speak with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+    /// The encoding of spoken output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_audio_format: Option<String>,
+    /// Tools available to the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<OpenAIRealtimeTool>>,
+    /// Tool choice strategy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    /// Temperature (randomness)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Maximum number of tokens per response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_response_output_tokens: Option<usize>,
+}
+
+/// A tool in the OpenAI Realtime API format, which is flatter than the chat
+/// completions one
+#[derive(Debug, Serialize)]
+pub(crate) struct OpenAIRealtimeTool {
+    pub r#type: String,
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// A conversation item in the OpenAI Realtime API format
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum OpenAIRealtimeItem {
+    Message {
+        role: String,
+        content: Vec<OpenAIRealtimeContent>,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+}
+
+/// A content part of a conversation item
+#[derive(Debug, Serialize)]
+pub(crate) struct OpenAIRealtimeContent {
+    pub r#type: String,
+    pub text: String,
+}
+
+/// An event received from the OpenAI Realtime API
+///
+/// Only the events that map onto a [`RealtimeEvent`] are parsed; the rest
+/// are ignored.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum OpenAIServerEvent {
+    #[serde(rename = "session.created")]
+    SessionCreated { session: OpenAIRealtimeSessionInfo },
+    #[serde(rename = "response.text.delta")]
+    TextDelta { delta: String },
+    #[serde(rename = "response.audio.delta")]
+    AudioDelta { delta: String },
+    #[serde(rename = "response.audio_transcript.delta")]
+    AudioTranscriptDelta { delta: String },
+    #[serde(rename = "response.function_call_arguments.done")]
+    FunctionCallArgumentsDone {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    #[serde(rename = "input_audio_buffer.speech_started")]
+    SpeechStarted,
+    #[serde(rename = "response.done")]
+    ResponseDone { response: OpenAIRealtimeResponse },
+    #[serde(rename = "error")]
+    Error { error: OpenAIRealtimeError },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OpenAIRealtimeSessionInfo {
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OpenAIRealtimeResponse {
+    pub usage: Option<OpenAIRealtimeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OpenAIRealtimeUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OpenAIRealtimeError {
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::AudioOutput;
+    use crate::tool::LlmToolInfo;
+
+    fn provider() -> OpenAIRealtimeProvider {
+        OpenAIRealtimeProvider::with_config(OpenAIConfig {
+            api_key: "sk-test".to_string(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            organization: None,
+            auth: None,
+            signer: None,
+        })
+    }
+
+    #[test]
+    fn test_setup_configures_session_and_seeds_history() {
+        let chat = Chat::default()
+            .with_system_prompt("Be brief.")
+            .with_audio_output(AudioOutput::new("alloy", "pcm16"))
+            .with_tools(vec![LlmToolInfo {
+                name: "get_weather".to_string(),
+                description: "Gets the weather".to_string(),
+                parameters: serde_json::json!({"type": "object"}),
+            }])
+            .add_message(Message::user("Hi"))
+            .add_message(Message::assistant("Hello!"));
+
+        let frames: Vec<serde_json::Value> = provider()
+            .setup(OpenAi::GPT4oRealtime, &chat)
+            .unwrap()
+            .iter()
+            .map(|frame| serde_json::from_str(frame).unwrap())
+            .collect();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0]["type"], "session.update");
+        let session = &frames[0]["session"];
+        assert_eq!(session["instructions"], "Be brief.");
+        assert_eq!(session["modalities"], serde_json::json!(["text", "audio"]));
+        assert_eq!(session["voice"], "alloy");
+        assert_eq!(session["tools"][0]["name"], "get_weather");
+        assert_eq!(session["tools"][0]["type"], "function");
+
+        assert_eq!(frames[1]["item"]["content"][0]["type"], "input_text");
+        assert_eq!(frames[2]["item"]["role"], "assistant");
+        assert_eq!(frames[2]["item"]["content"][0]["text"], "Hello!");
+
+        let request = provider().connect_request(OpenAi::GPT4oRealtime).unwrap();
+        assert_eq!(
+            request.uri().to_string(),
+            "wss://api.openai.com/v1/realtime?model=gpt-4o-realtime-preview"
+        );
+        assert_eq!(request.headers()["Authorization"], "Bearer sk-test");
+    }
+
+    #[test]
+    fn test_encode_inputs() {
+        let encode = |input| {
+            let frames = provider().encode(&input).unwrap();
+            serde_json::from_str::<serde_json::Value>(&frames[0]).unwrap()
+        };
+
+        let audio = encode(RealtimeInput::Audio(vec![1, 2, 3]));
+        assert_eq!(
+            audio,
+            serde_json::json!({"type": "input_audio_buffer.append", "audio": "AQID"})
+        );
+        assert_eq!(
+            encode(RealtimeInput::CreateResponse),
+            serde_json::json!({"type": "response.create"})
+        );
+
+        let result = encode(RealtimeInput::ToolResult {
+            call_id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            output: "10C".to_string(),
+        });
+        assert_eq!(result["item"]["type"], "function_call_output");
+        assert_eq!(result["item"]["call_id"], "call_1");
+    }
+
+    #[test]
+    fn test_decode_server_events() {
+        let decode = |frame: serde_json::Value| provider().decode(&frame.to_string()).unwrap();
+
+        assert_eq!(
+            decode(serde_json::json!({"type": "response.audio.delta", "delta": "AQID"})),
+            vec![RealtimeEvent::AudioDelta(vec![1, 2, 3])]
+        );
+        assert_eq!(
+            decode(serde_json::json!({
+                "type": "response.function_call_arguments.done",
+                "call_id": "call_1",
+                "name": "get_weather",
+                "arguments": "{\"location\":\"Paris\"}"
+            })),
+            vec![RealtimeEvent::ToolCall(ToolCall {
+                id: "call_1".to_string(),
+                tool_type: "function".to_string(),
+                function: Function {
+                    name: "get_weather".to_string(),
+                    arguments: "{\"location\":\"Paris\"}".to_string(),
+                },
+            })]
+        );
+        assert_eq!(
+            decode(serde_json::json!({
+                "type": "response.done",
+                "response": {"usage": {"input_tokens": 10, "output_tokens": 5, "total_tokens": 15}}
+            })),
+            vec![RealtimeEvent::TurnComplete {
+                usage: Some(Usage::new(10, 5)),
+                timing: None,
+            }]
+        );
+        assert!(decode(serde_json::json!({"type": "rate_limits.updated"})).is_empty());
+    }
+}
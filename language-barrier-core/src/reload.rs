@@ -0,0 +1,272 @@
+//! Swapping provider settings without a restart
+//!
+//! A long-lived agent service shouldn't have to be restarted to rotate an
+//! API key, point at a new gateway or loosen a rate limit. A
+//! [`ReloadableService`] holds the service that currently handles calls and
+//! lets it be replaced at any time, either programmatically with
+//! [`ReloadableService::replace`] or from a config file with
+//! [`ReloadableService::watch`].
+//!
+//! The swap is atomic: each call picks up the current service when it
+//! starts and keeps it until it finishes, so in-flight requests complete on
+//! the old settings while new ones use the new settings. A config file that
+//! fails to load is logged and ignored, leaving the previous service in
+//! place.
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```no_run")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::provider::openai::{OpenAIConfig, OpenAIProvider};
+//! use language_barrier_core::reload::{ReloadableService, SharedService};
+//! use language_barrier_core::OpenAi;
+//!
+//! # async fn example() -> language_barrier_core::Result<()> {
+//! fn build(text: &str) -> language_barrier_core::Result<SharedService<OpenAi>> {
+//!     let config: OpenAIConfig = serde_json::from_str(text)?;
+//!     let provider = OpenAIProvider::try_with_config(config)?;
+//!     Ok(Arc::new(HTTPLlmService::new(OpenAi::GPT4o, Arc::new(provider))))
+//! }
+//!
+//! let service = Arc::new(ReloadableService::new(build(
+//!     &std::fs::read_to_string("openai.json")?,
+//! )?));
+//!
+//! // Re-reads openai.json whenever it changes
+//! let _watcher = service.watch("openai.json", Duration::from_secs(5), build);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::error::Result;
+use crate::llm_service::LLMService;
+use crate::salvage::Continuation;
+use crate::{Chat, Message, ModelInfo};
+
+/// A shared, swappable service
+pub type SharedService<M> = Arc<dyn LLMService<M> + Send + Sync>;
+
+/// A service whose underlying service can be replaced while it's in use
+///
+/// See the [module docs](self).
+pub struct ReloadableService<M> {
+    current: RwLock<SharedService<M>>,
+    generation: AtomicU64,
+}
+
+impl<M: ModelInfo> ReloadableService<M> {
+    /// Creates a service that starts out sending calls to `service`
+    pub fn new(service: SharedService<M>) -> Self {
+        Self {
+            current: RwLock::new(service),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// The service new calls go to
+    pub fn current(&self) -> SharedService<M> {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Sends new calls to `service`; calls already running finish on the
+    /// service they started with
+    pub fn replace(&self, service: SharedService<M>) {
+        *self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = service;
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        info!("Swapped in service generation {}", generation);
+    }
+
+    /// How many times the service has been replaced
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Polls the file at `path` every `interval` and, when it changes,
+    /// replaces the service with the one `build` makes from its contents
+    ///
+    /// The file's current contents are assumed to match the current service;
+    /// only later changes trigger a reload. If the file can't be read or
+    /// `build` fails, the error is logged and the current service is kept.
+    /// Watching stops when the returned [`ConfigWatcher`] is dropped.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn watch<F>(
+        self: &Arc<Self>,
+        path: impl Into<PathBuf>,
+        interval: Duration,
+        build: F,
+    ) -> ConfigWatcher
+    where
+        M: 'static,
+        F: Fn(&str) -> Result<SharedService<M>> + Send + Sync + 'static,
+    {
+        let path = path.into();
+        let service = Arc::downgrade(self);
+        let task = tokio::spawn(async move {
+            let mut seen = stamp(&path).await;
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                let Some(service) = service.upgrade() else {
+                    return;
+                };
+                let current = stamp(&path).await;
+                if current.is_none() || current == seen {
+                    continue;
+                }
+                seen = current;
+
+                debug!("Config file {} changed, reloading", path.display());
+                let loaded = tokio::fs::read_to_string(&path)
+                    .await
+                    .map_err(Into::into)
+                    .and_then(|text| build(&text));
+                match loaded {
+                    Ok(next) => service.replace(next),
+                    Err(e) => warn!(
+                        "Keeping the current service, {} failed to load: {}",
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        });
+        ConfigWatcher { task }
+    }
+}
+
+/// What identifies a version of the watched file
+async fn stamp(path: &PathBuf) -> Option<(SystemTime, u64)> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[async_trait]
+impl<M: ModelInfo> LLMService<M> for ReloadableService<M> {
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        let service = self.current();
+        service.generate_next_message(chat).await
+    }
+
+    fn continuation(&self) -> Continuation {
+        self.current().continuation()
+    }
+}
+
+/// Stops watching a config file when dropped
+///
+/// Returned by [`ReloadableService::watch`].
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    task: JoinHandle<()>,
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Claude;
+    use crate::error::Error;
+    use tokio::sync::Notify;
+
+    /// Replies with its name, after `gate` is notified if there is one
+    struct Named(&'static str, Option<Arc<Notify>>);
+
+    #[async_trait]
+    impl LLMService<Claude> for Named {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            if let Some(gate) = &self.1 {
+                gate.notified().await;
+            }
+            Ok(Message::assistant(self.0))
+        }
+    }
+
+    fn chat() -> Chat {
+        Chat::default().add_message(Message::user("Hi"))
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_calls_finish_on_the_old_service() {
+        let gate = Arc::new(Notify::new());
+        let service = Arc::new(ReloadableService::new(Arc::new(Named(
+            "old",
+            Some(gate.clone()),
+        ))));
+
+        let in_flight = tokio::spawn({
+            let service = service.clone();
+            async move { service.generate_next_message(&chat()).await }
+        });
+        tokio::task::yield_now().await;
+
+        service.replace(Arc::new(Named("new", None)));
+        assert_eq!(service.generation(), 1);
+        let reply = service.generate_next_message(&chat()).await.unwrap();
+        assert_eq!(reply.text_content(), "new");
+
+        gate.notify_one();
+        let reply = in_flight.await.unwrap().unwrap();
+        assert_eq!(reply.text_content(), "old");
+    }
+
+    #[tokio::test]
+    async fn test_watch_reloads_on_change_and_keeps_service_on_error() {
+        let path = std::env::temp_dir().join(format!(
+            "language-barrier-reload-{}.txt",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(&path, "old").unwrap();
+
+        let service = Arc::new(ReloadableService::new(Arc::new(Named("old", None))));
+        let _watcher = service.watch(&path, Duration::from_millis(10), |text| {
+            match text.trim() {
+                "broken!" => Err(Error::Other("unparseable config".to_string())),
+                "new" => Ok(Arc::new(Named("new", None)) as SharedService<Claude>),
+                other => panic!("unexpected config {other:?}"),
+            }
+        });
+        std::fs::write(&path, "broken!").unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(service.generation(), 0);
+        let reply = service.generate_next_message(&chat()).await.unwrap();
+        assert_eq!(reply.text_content(), "old");
+
+        std::fs::write(&path, "new").unwrap();
+        for _ in 0..200 {
+            if service.generation() > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(service.generation(), 1);
+        let reply = service.generate_next_message(&chat()).await.unwrap();
+        assert_eq!(reply.text_content(), "new");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
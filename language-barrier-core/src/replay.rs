@@ -0,0 +1,411 @@
+//! Comparing and replaying conversations
+//!
+//! [`Chat::diff`] lists how two conversations differ, message by message.
+//! [`Replay`] re-runs a recorded conversation's user turns against another
+//! model or system prompt and reports where the new replies diverge from
+//! the recorded ones, which makes a recorded session into a regression test
+//! for prompt changes.
+//!
+//! Messages are compared by role, text and tool calls; metadata and tool
+//! call IDs are ignored. Text is scored with [`text_similarity`], from 0
+//! for nothing in common to 1 for the same words in the same order.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::replay::MessageDiff;
+//! use language_barrier_core::{Chat, Message};
+//!
+//! let before = Chat::default()
+//!     .add_message(Message::user("Where is the Louvre?"))
+//!     .add_message(Message::assistant("The Louvre is in Paris."));
+//! let after = before
+//!     .clone()
+//!     .with_system_prompt("Be brief.")
+//!     .with_history(vec![
+//!         Message::user("Where is the Louvre?"),
+//!         Message::assistant("It is in Paris."),
+//!     ]);
+//!
+//! let diff = before.diff(&after);
+//! assert!(diff.system_prompt_changed);
+//! let MessageDiff::Changed { index, similarity, .. } = &diff.messages[0] else {
+//!     panic!("expected a changed message");
+//! };
+//! assert_eq!(*index, 1);
+//! assert!((similarity - 2.0 / 3.0).abs() < 1e-9);
+//! ```
+//!
+//! [`Chat::diff`]: crate::Chat::diff
+
+use serde_json::Value;
+use tracing::debug;
+
+use crate::error::Result;
+use crate::llm_service::LLMService;
+use crate::message::{Message, ToolCall};
+use crate::{Chat, ModelInfo};
+
+/// How two conversations differ
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatDiff {
+    /// The system prompts, including segments, differ
+    pub system_prompt_changed: bool,
+    /// The tools offered to the model differ
+    pub tools_changed: bool,
+    /// The messages that differ, in history order
+    pub messages: Vec<MessageDiff>,
+}
+
+impl ChatDiff {
+    /// Returns true if the conversations are the same
+    pub fn is_empty(&self) -> bool {
+        !self.system_prompt_changed && !self.tools_changed && self.messages.is_empty()
+    }
+}
+
+/// How one position in two histories differs
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageDiff {
+    /// Only the other history has a message here
+    Added { index: usize, message: Message },
+    /// Only this history has a message here
+    Removed { index: usize, message: Message },
+    /// Both histories have a message here, and they differ
+    Changed {
+        index: usize,
+        before: Message,
+        after: Message,
+        /// The similarity of the two texts
+        similarity: f64,
+        /// Both messages make the same tool calls
+        tool_calls_match: bool,
+    },
+}
+
+/// Returns how `other` differs from `chat`
+pub(crate) fn diff(chat: &Chat, other: &Chat) -> ChatDiff {
+    let len = chat.history.len().max(other.history.len());
+    let messages = (0..len)
+        .filter_map(
+            |index| match (chat.history.get(index), other.history.get(index)) {
+                (Some(before), Some(after)) => compare(index, before, after),
+                (Some(message), None) => Some(MessageDiff::Removed {
+                    index,
+                    message: message.clone(),
+                }),
+                (None, Some(message)) => Some(MessageDiff::Added {
+                    index,
+                    message: message.clone(),
+                }),
+                (None, None) => None,
+            },
+        )
+        .collect();
+
+    ChatDiff {
+        system_prompt_changed: chat.system_text() != other.system_text(),
+        tools_changed: tool_names(chat) != tool_names(other),
+        messages,
+    }
+}
+
+fn compare(index: usize, before: &Message, after: &Message) -> Option<MessageDiff> {
+    let before_text = before.text_content();
+    let after_text = after.text_content();
+    let tool_calls_match = same_tool_calls(before, after);
+    if before.role_str() == after.role_str() && before_text == after_text && tool_calls_match {
+        return None;
+    }
+    Some(MessageDiff::Changed {
+        index,
+        before: before.clone(),
+        after: after.clone(),
+        similarity: text_similarity(&before_text, &after_text),
+        tool_calls_match,
+    })
+}
+
+fn tool_names(chat: &Chat) -> Vec<(&str, &Value)> {
+    chat.tools
+        .iter()
+        .flatten()
+        .map(|tool| (tool.name.as_str(), &tool.parameters))
+        .collect()
+}
+
+/// Returns true if both messages call the same tools with the same
+/// arguments, in the same order
+fn same_tool_calls(a: &Message, b: &Message) -> bool {
+    let calls = |message: &Message| -> Vec<(String, Value)> {
+        let Message::Assistant { tool_calls, .. } = message else {
+            return Vec::new();
+        };
+        tool_calls.iter().map(call_key).collect()
+    };
+    calls(a) == calls(b)
+}
+
+fn call_key(call: &ToolCall) -> (String, Value) {
+    let arguments = serde_json::from_str(&call.function.arguments)
+        .unwrap_or_else(|_| Value::String(call.function.arguments.clone()));
+    (call.function.name.clone(), arguments)
+}
+
+/// Returns how alike two texts are, from 0 to 1
+///
+/// Twice the length of the longest common subsequence of words, divided by
+/// the total number of words. Two empty texts are identical.
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<&str> = a.split_whitespace().collect();
+    let b: Vec<&str> = b.split_whitespace().collect();
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    let mut previous = vec![0usize; b.len() + 1];
+    for word in &a {
+        let mut current = vec![0usize; b.len() + 1];
+        for (j, other) in b.iter().enumerate() {
+            current[j + 1] = if word == other {
+                previous[j] + 1
+            } else {
+                current[j].max(previous[j + 1])
+            };
+        }
+        previous = current;
+    }
+    2.0 * previous[b.len()] as f64 / (a.len() + b.len()) as f64
+}
+
+/// One recorded reply and its replayed counterpart
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayedTurn {
+    /// The position of the recorded reply in the recorded history
+    pub index: usize,
+    /// The reply in the recording
+    pub recorded: Message,
+    /// The reply generated during the replay
+    pub replayed: Message,
+    /// The similarity of the two texts
+    pub similarity: f64,
+    /// Both replies make the same tool calls
+    pub tool_calls_match: bool,
+    /// The replies differ by more than the replay allows
+    pub diverged: bool,
+}
+
+/// The result of a replay
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    /// Every replayed turn, in history order
+    pub turns: Vec<ReplayedTurn>,
+}
+
+impl ReplayReport {
+    /// Returns the turns that diverged
+    pub fn divergences(&self) -> impl Iterator<Item = &ReplayedTurn> {
+        self.turns.iter().filter(|turn| turn.diverged)
+    }
+
+    /// Returns true if no turn diverged
+    pub fn is_consistent(&self) -> bool {
+        self.divergences().next().is_none()
+    }
+}
+
+/// Re-runs a recorded conversation against another model or prompt
+///
+/// Every assistant reply in the recording is generated again from the
+/// history before it. The recorded reply, not the new one, is then kept in
+/// the history, so later turns see the same context (and recorded tool
+/// results still answer the recorded calls). A turn diverges if its text
+/// similarity falls below the threshold or its tool calls differ.
+///
+/// # Examples
+///
+#[cfg_attr(feature = "openai", doc = "```no_run")]
+#[cfg_attr(not(feature = "openai"), doc = "```ignore")]
+/// use language_barrier_core::llm_service::HTTPLlmService;
+/// use language_barrier_core::provider::openai::OpenAIProvider;
+/// use language_barrier_core::replay::Replay;
+/// use language_barrier_core::{Chat, OpenAi};
+/// use std::sync::Arc;
+///
+/// # async fn run(recorded: Chat) -> language_barrier_core::Result<()> {
+/// let service = HTTPLlmService::new(OpenAi::GPT4oMini, Arc::new(OpenAIProvider::new()));
+/// let report = Replay::new()
+///     .with_system_prompt("You are a concise support agent.")
+///     .run(&service, &recorded)
+///     .await?;
+/// for turn in report.divergences() {
+///     println!("turn {} diverged ({:.2})", turn.index, turn.similarity);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    system_prompt: Option<String>,
+    threshold: f64,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            system_prompt: None,
+            threshold: Self::DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl Replay {
+    /// The text similarity below which a turn diverges by default
+    pub const DEFAULT_THRESHOLD: f64 = 0.8;
+
+    /// Creates a replay that keeps the recorded system prompt
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the recorded system prompt, keeping its segments
+    pub fn with_system_prompt(self, prompt: impl Into<String>) -> Self {
+        Self {
+            system_prompt: Some(prompt.into()),
+            ..self
+        }
+    }
+
+    /// Sets the text similarity below which a turn diverges
+    pub fn with_similarity_threshold(self, threshold: f64) -> Self {
+        Self { threshold, ..self }
+    }
+
+    /// Replays `recorded` through `service`
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error `service` returns.
+    pub async fn run<M, S>(&self, service: &S, recorded: &Chat) -> Result<ReplayReport>
+    where
+        M: ModelInfo,
+        S: LLMService<M> + Sync,
+    {
+        let mut chat = recorded.clone().with_history(Vec::new());
+        if let Some(prompt) = &self.system_prompt {
+            chat = chat.with_system_prompt(prompt.clone());
+        }
+
+        let mut turns = Vec::new();
+        for (index, message) in recorded.history.iter().enumerate() {
+            if matches!(message, Message::Assistant { .. }) {
+                debug!("Replaying turn {}", index);
+                let replayed = service.generate_next_message(&chat).await?;
+                let similarity = text_similarity(&message.text_content(), &replayed.text_content());
+                let tool_calls_match = same_tool_calls(message, &replayed);
+                turns.push(ReplayedTurn {
+                    index,
+                    recorded: message.clone(),
+                    replayed,
+                    similarity,
+                    tool_calls_match,
+                    diverged: similarity < self.threshold || !tool_calls_match,
+                });
+            }
+            chat = chat.add_message(message.clone());
+        }
+
+        Ok(ReplayReport { turns })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Claude;
+    use crate::message::Function;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Returns scripted replies in order and records the chats it saw
+    struct Scripted {
+        replies: Mutex<Vec<Message>>,
+        seen: Mutex<Vec<Chat>>,
+    }
+
+    #[async_trait]
+    impl LLMService<Claude> for Scripted {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            self.seen.lock().unwrap().push(chat.clone());
+            Ok(self.replies.lock().unwrap().remove(0))
+        }
+    }
+
+    fn call(id: &str, city: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            tool_type: "function".to_string(),
+            function: Function {
+                name: "get_weather".to_string(),
+                arguments: format!(r#"{{"city": "{city}"}}"#),
+            },
+        }
+    }
+
+    #[test]
+    fn test_diff_ignores_call_ids_and_reports_length_changes() {
+        let before = Chat::default()
+            .add_message(Message::user("Weather?"))
+            .add_message(Message::assistant_with_tool_calls(vec![call("a", "Oslo")]));
+        let after = Chat::default()
+            .add_message(Message::user("Weather?"))
+            .add_message(Message::assistant_with_tool_calls(vec![call("b", "Oslo")]))
+            .add_message(Message::tool("b", "3°C"));
+
+        let diff = before.diff(&after);
+        assert!(!diff.system_prompt_changed && !diff.tools_changed);
+        assert_eq!(diff.messages.len(), 1);
+        assert!(matches!(
+            diff.messages[0],
+            MessageDiff::Added { index: 2, .. }
+        ));
+        assert!(before.diff(&before).is_empty());
+
+        assert_eq!(text_similarity("", ""), 1.0);
+        assert_eq!(text_similarity("a b", "c d"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_replay_keeps_the_recorded_context() {
+        let recorded = Chat::default()
+            .with_system_prompt("You are a weather bot.")
+            .add_message(Message::user("Weather in Oslo?"))
+            .add_message(Message::assistant_with_tool_calls(vec![call("a", "Oslo")]))
+            .add_message(Message::tool("a", "3°C"))
+            .add_message(Message::assistant("It is 3°C in Oslo."));
+        let service = Scripted {
+            replies: Mutex::new(vec![
+                Message::assistant_with_tool_calls(vec![call("x", "Bergen")]),
+                Message::assistant("It is 3°C in Oslo right now."),
+            ]),
+            seen: Mutex::new(Vec::new()),
+        };
+
+        let report = Replay::new()
+            .with_system_prompt("You are a terse weather bot.")
+            .with_similarity_threshold(0.5)
+            .run(&service, &recorded)
+            .await
+            .unwrap();
+
+        assert_eq!(report.turns.len(), 2);
+        assert!(!report.turns[0].tool_calls_match && report.turns[0].diverged);
+        assert!(!report.turns[1].diverged);
+        assert_eq!(report.divergences().count(), 1);
+        assert!(!report.is_consistent());
+
+        let seen = service.seen.lock().unwrap();
+        assert_eq!(seen[0].system_prompt, "You are a terse weather bot.");
+        assert_eq!(seen[1].history, recorded.history[..3]);
+    }
+}
@@ -0,0 +1,305 @@
+//! Salvaging and continuing cut-off replies
+//!
+//! A reply can stop before it's finished in two ways: the model hits the
+//! output token limit, and the provider reports
+//! [`FinishReason::Length`], or a streamed reply dies partway through a
+//! dropped connection or a provider abort. In the second case the text
+//! received so far can be kept as an assistant message marked
+//! [`truncated`](Message::truncated) instead of being thrown away; realtime
+//! sessions do this with
+//! [`with_partial_salvage`](crate::realtime::RealtimeSession::with_partial_salvage).
+//!
+//! Either way, [`Chat::continue_last`] asks the model to pick up where the
+//! reply stopped and stitches the continuation onto it, so the history
+//! holds one complete reply. How it asks depends on the provider; see
+//! [`Continuation`].
+
+use tracing::{debug, info};
+
+use crate::chat::Chat;
+use crate::error::{ChatConfigError, Error, Result};
+use crate::llm_service::LLMService;
+use crate::message::{Content, FinishReason, Message};
+use crate::model::ModelInfo;
+
+/// The user turn asking the model to continue a cut-off reply
+pub const CONTINUE_PROMPT: &str = "Your previous reply was cut off. Continue it exactly where \
+     it stopped, without repeating anything or adding a preamble.";
+
+/// The shortest overlap between a reply and its continuation that
+/// [`stitch`] removes; shorter matches are likely coincidence
+const MIN_OVERLAP: usize = 8;
+
+/// How a provider is asked to continue a cut-off reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Continuation {
+    /// Send the reply back followed by a user turn with [`CONTINUE_PROMPT`]
+    #[default]
+    Prompt,
+    /// End the request with the reply as an unfinished assistant turn, which
+    /// the model extends; Anthropic supports this
+    Prefill,
+}
+
+/// Returns true if `message` is a reply that stopped before it was finished
+fn is_cut_off(message: &Message) -> bool {
+    matches!(message, Message::Assistant { .. })
+        && (message.is_truncated() || message.finish_reason() == Some(FinishReason::Length))
+}
+
+/// Finishes the cut-off reply at the end of `chat`; see
+/// [`Chat::continue_last`]
+pub(crate) async fn continue_last<M, S>(service: &S, chat: Chat) -> Result<Chat>
+where
+    M: ModelInfo,
+    S: LLMService<M> + Sync,
+{
+    let mut history = chat.history.clone();
+    let Some(partial) = history.pop().filter(is_cut_off) else {
+        return Err(ChatConfigError::NothingToContinue.into());
+    };
+
+    // A prefilled turn can't carry tool calls, which would need results first
+    let has_tool_calls =
+        matches!(&partial, Message::Assistant { tool_calls, .. } if !tool_calls.is_empty());
+    let style = if has_tool_calls {
+        Continuation::Prompt
+    } else {
+        service.continuation()
+    };
+    info!("Continuing a cut-off reply with {:?}", style);
+
+    let (prefix, mut request) = match style {
+        Continuation::Prompt => {
+            let request = chat.clone().add_message(Message::user(CONTINUE_PROMPT));
+            (partial.text_content(), request)
+        }
+        Continuation::Prefill => {
+            // Anthropic rejects a prefill that ends in whitespace
+            let prefix = partial.text_content().trim_end().to_string();
+            let request = chat
+                .clone()
+                .with_history(history.clone())
+                .add_message(Message::assistant(prefix.clone()));
+            (prefix, request)
+        }
+    };
+    // The reply already starts with any assistant prefix
+    request.assistant_prefix = None;
+    let continuation = service.generate_next_message(&request).await?;
+
+    history.push(merge(partial, &prefix, continuation)?);
+    Ok(chat.with_history(history))
+}
+
+/// Joins a cut-off reply, whose text is `prefix`, and its continuation into
+/// one message
+///
+/// Fails if the continuation isn't an assistant reply, which only a
+/// misbehaving [`LLMService`] returns.
+fn merge(partial: Message, prefix: &str, continuation: Message) -> Result<Message> {
+    let text = stitch(prefix, &continuation.text_content());
+    let finish_reason = continuation.finish_reason();
+    let truncated = continuation.is_truncated();
+    let role = continuation.role_str();
+    let (
+        Message::Assistant {
+            tool_calls: mut calls,
+            metadata: mut merged,
+            ..
+        },
+        Message::Assistant {
+            tool_calls,
+            metadata,
+            ..
+        },
+    ) = (partial, continuation)
+    else {
+        return Err(Error::Other(format!(
+            "expected an assistant reply to continue, got a {role} message"
+        )));
+    };
+    calls.extend(tool_calls);
+    merged.extend(metadata);
+    merged.remove(Message::TRUNCATED_KEY);
+    if finish_reason.is_none() {
+        merged.remove(FinishReason::METADATA_KEY);
+    }
+
+    let message = Message::Assistant {
+        content: (!text.is_empty()).then_some(Content::Text(text)),
+        tool_calls: calls,
+        metadata: merged,
+    };
+    Ok(if truncated {
+        message.truncated()
+    } else {
+        message
+    })
+}
+
+/// Appends `continuation` to `partial`, dropping text the model repeated
+///
+/// The longest end of `partial` that `continuation` starts with is removed
+/// from the continuation, as long as it's at least [`MIN_OVERLAP`] bytes.
+pub(crate) fn stitch(partial: &str, continuation: &str) -> String {
+    let overlap = continuation
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .rfind(|&len| len >= MIN_OVERLAP && partial.ends_with(&continuation[..len]))
+        .unwrap_or(0);
+    if overlap > 0 {
+        debug!("Dropping {} repeated bytes from the continuation", overlap);
+    }
+    format!("{partial}{}", &continuation[overlap..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use crate::llm_service::HTTPLlmService;
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use crate::transport::mock::{MockResponse, MockTransport};
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use serde_json::{Value, json};
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use std::sync::Arc;
+
+    #[cfg(feature = "openai")]
+    fn openai_reply(content: &str, finish_reason: &str) -> MockResponse {
+        MockResponse::ok(
+            json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": finish_reason
+                }]
+            })
+            .to_string(),
+        )
+    }
+
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    fn last_message(transport: &MockTransport) -> Value {
+        let request = transport.requests().pop().unwrap();
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        body["messages"].as_array().unwrap().last().unwrap().clone()
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_length_cut_off_is_continued_with_a_prompt() {
+        use crate::OpenAi;
+        use crate::provider::openai::OpenAIProvider;
+        let transport = Arc::new(MockTransport::new().with_responses([
+            openai_reply("Paris is the capital of", "length"),
+            openai_reply("capital of France.", "stop"),
+        ]));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            transport.clone(),
+        );
+        let chat = Chat::default().add_message(Message::user("What is Paris?"));
+        let reply = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(reply.finish_reason(), Some(FinishReason::Length));
+
+        let chat = chat
+            .add_message(reply)
+            .continue_last(&service)
+            .await
+            .unwrap();
+        assert_eq!(last_message(&transport)["content"], CONTINUE_PROMPT);
+        assert_eq!(chat.history.len(), 2);
+        assert_eq!(
+            chat.history[1].text_content(),
+            "Paris is the capital of France."
+        );
+        assert_eq!(chat.history[1].finish_reason(), Some(FinishReason::Stop));
+
+        assert!(matches!(
+            chat.continue_last(&service).await,
+            Err(crate::Error::ChatConfig(ChatConfigError::NothingToContinue))
+        ));
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[tokio::test]
+    async fn test_anthropic_continues_by_prefill() {
+        use crate::Claude;
+        use crate::provider::anthropic::AnthropicProvider;
+        let reply = json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-3-5-haiku-20241022",
+            "stop_reason": "end_turn",
+            "content": [{ "type": "text", "text": " France." }],
+            "usage": { "input_tokens": 1, "output_tokens": 1 }
+        });
+        let transport =
+            Arc::new(MockTransport::new().with_response(MockResponse::ok(reply.to_string())));
+        let service = HTTPLlmService::new_with_transport(
+            Claude::Haiku35,
+            Arc::new(AnthropicProvider::default()),
+            transport.clone(),
+        );
+        let chat = Chat::default()
+            .add_message(Message::user("What is Paris?"))
+            .add_message(Message::assistant("Paris is the capital of ").truncated());
+
+        let chat = chat.continue_last(&service).await.unwrap();
+        let prefill = last_message(&transport);
+        assert_eq!(prefill["role"], "assistant");
+        assert!(
+            prefill["content"]
+                .to_string()
+                .contains("\"Paris is the capital of\"")
+        );
+        assert_eq!(
+            chat.history[1].text_content(),
+            "Paris is the capital of France."
+        );
+        assert!(!chat.history[1].is_truncated());
+    }
+
+    /// Answers every request with a user message, as no real service would
+    struct WrongRole;
+
+    #[async_trait::async_trait]
+    impl LLMService<crate::Claude> for WrongRole {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            Ok(Message::user("France."))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_assistant_continuation_is_an_error() {
+        let chat = Chat::default()
+            .add_message(Message::user("What is Paris?"))
+            .add_message(Message::assistant("Paris is the capital of ").truncated());
+
+        let result = chat.continue_last(&WrongRole).await;
+        assert!(
+            matches!(result, Err(Error::Other(ref message)) if message.contains("user message"))
+        );
+    }
+
+    #[test]
+    fn test_stitch_drops_repeated_text() {
+        assert_eq!(
+            stitch("Paris is the capital of", "capital of France."),
+            "Paris is the capital of France."
+        );
+        assert_eq!(stitch("Hello wor", "ld!"), "Hello world!");
+        assert_eq!(
+            stitch("First line.\n", "Second line."),
+            "First line.\nSecond line."
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 
 /// A wrapper type for sensitive information like API keys
@@ -74,6 +74,52 @@ impl<T> Secret<T> {
     }
 }
 
+/// Resolves a `${env:VAR}` reference to the value of `VAR`, passing any
+/// other string through unchanged
+///
+/// # Errors
+///
+/// Returns the name of the variable if it isn't set.
+#[cfg_attr(
+    not(any(
+        feature = "anthropic",
+        feature = "openai",
+        feature = "gemini",
+        feature = "mistral"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn resolve_env_ref(value: &str) -> Result<String, String> {
+    match value
+        .strip_prefix("${env:")
+        .and_then(|rest| rest.strip_suffix('}'))
+    {
+        Some(var) => std::env::var(var).map_err(|_| var.to_string()),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Deserializes a secret string field, resolving a `${env:VAR}` reference
+/// so config files don't have to hold the secret itself
+#[cfg_attr(
+    not(any(
+        feature = "anthropic",
+        feature = "openai",
+        feature = "gemini",
+        feature = "mistral"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn deserialize_env_ref<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    resolve_env_ref(&value).map_err(|var| {
+        serde::de::Error::custom(format!("environment variable {var} is not set"))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,4 +141,17 @@ mod tests {
         let secret = Secret("api-key-123");
         assert_eq!(secret.inner(), &"api-key-123");
     }
+
+    #[test]
+    fn test_resolve_env_ref() {
+        assert_eq!(resolve_env_ref("sk-plain").unwrap(), "sk-plain");
+        assert_eq!(
+            resolve_env_ref("${env:PATH}").unwrap(),
+            std::env::var("PATH").unwrap()
+        );
+        assert_eq!(
+            resolve_env_ref("${env:LANGUAGE_BARRIER_TEST_UNSET_KEY}").unwrap_err(),
+            "LANGUAGE_BARRIER_TEST_UNSET_KEY"
+        );
+    }
 }
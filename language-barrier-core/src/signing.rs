@@ -0,0 +1,125 @@
+//! Signing requests for gateways that verify them
+//!
+//! Some on-premises gateways only accept requests carrying a signature over
+//! the body, usually an HMAC with a timestamp header so a captured request
+//! can't be replayed later. Giving a provider config a [`RequestSigner`],
+//! with its `with_signer` method or its builder's `signer`, makes
+//! [`HTTPLlmService`] hand it every request after the payload is serialized
+//! and all headers are set, just before sending.
+//!
+//! The signer sees the request with its credentials, idempotency key and
+//! app headers in place. A transport rewriter
+//! ([`HTTPLlmService::with_request_rewriter`]) runs after it, so it must not
+//! change anything the signature covers.
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::{SystemTime, UNIX_EPOCH};
+//! use language_barrier_core::Result;
+//! use language_barrier_core::provider::openai::{OpenAIConfig, OpenAIProvider};
+//! use language_barrier_core::signing::RequestSigner;
+//! use language_barrier_core::transport::{HeaderValue, HttpRequest};
+//!
+//! #[derive(Debug)]
+//! struct GatewaySigner {
+//!     key: Vec<u8>,
+//! }
+//!
+//! impl GatewaySigner {
+//!     fn mac(&self, message: &[u8]) -> String {
+//!         // Compute the HMAC of `message` with `self.key` here
+//!         # let _ = (&self.key, message);
+//!         "c2lnbmF0dXJl".to_string()
+//!     }
+//! }
+//!
+//! impl RequestSigner for GatewaySigner {
+//!     fn sign(&self, request: &mut HttpRequest) -> Result<()> {
+//!         let timestamp = SystemTime::now()
+//!             .duration_since(UNIX_EPOCH)
+//!             .unwrap_or_default()
+//!             .as_secs()
+//!             .to_string();
+//!         let mut message = timestamp.clone().into_bytes();
+//!         message.extend_from_slice(&request.body);
+//!         let signature = self.mac(&message);
+//!
+//!         request.headers.insert("x-timestamp", HeaderValue::from_str(&timestamp).unwrap());
+//!         request.headers.insert("x-signature", HeaderValue::from_str(&signature).unwrap());
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let config = OpenAIConfig::builder()
+//!     .api_key("gateway-key")
+//!     .base_url("https://llm-gateway.internal.example.com/v1")
+//!     .signer(Arc::new(GatewaySigner { key: b"shared-secret".to_vec() }))
+//!     .build()
+//!     .unwrap();
+//! let provider = OpenAIProvider::with_config(config);
+//! ```
+//!
+//! [`HTTPLlmService`]: crate::HTTPLlmService
+//! [`HTTPLlmService::with_request_rewriter`]: crate::HTTPLlmService::with_request_rewriter
+
+use std::fmt;
+
+use crate::error::Result;
+use crate::transport::HttpRequest;
+
+/// Signs outgoing provider requests
+pub trait RequestSigner: Send + Sync + fmt::Debug {
+    /// Adds a signature to `request`, typically as headers
+    ///
+    /// # Errors
+    ///
+    /// An error aborts the call and is returned to the caller.
+    fn sign(&self, request: &mut HttpRequest) -> Result<()>;
+}
+
+#[cfg(all(test, feature = "mistral"))]
+mod tests {
+    use super::*;
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    use crate::provider::mistral::{MistralConfig, MistralProvider};
+    use crate::transport::HeaderValue;
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use crate::{Chat, Message, Mistral};
+    use std::sync::Arc;
+
+    /// Signs with the body length, standing in for a real MAC
+    #[derive(Debug)]
+    struct LengthSigner;
+
+    impl RequestSigner for LengthSigner {
+        fn sign(&self, request: &mut HttpRequest) -> Result<()> {
+            let signature = HeaderValue::from(request.body.len());
+            request.headers.insert("x-signature", signature);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signer_sees_the_serialized_body() {
+        let transport =
+            Arc::new(MockTransport::new().with_response(MockResponse::status(500, "{}")));
+        let service = HTTPLlmService::new_with_transport(
+            Mistral::Small,
+            Arc::new(MistralProvider::with_config(
+                MistralConfig::default().with_signer(Arc::new(LengthSigner)),
+            )),
+            transport.clone(),
+        );
+
+        let chat = Chat::default().add_message(Message::user("Hi"));
+        assert!(service.generate_next_message(&chat).await.is_err());
+        let request = &transport.requests()[0];
+        assert_eq!(
+            request.headers["x-signature"],
+            request.body.len().to_string()
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Generating replies to suggested messages before they're chosen
+//!
+//! A UI that offers suggested replies ("Tell me more", "Summarize this")
+//! can have the answer to each ready before the user picks one. A
+//! [`SpeculativeService`] starts a [`Priority::Background`] generation for
+//! every candidate given to [`prefetch`](SpeculativeService::prefetch); when
+//! the chat it's next asked about ends in one of the candidates, the
+//! prefetched reply is returned at once, or awaited if it's still coming.
+//! Any other message cancels the outstanding generations, as does
+//! [`cancel`](SpeculativeService::cancel) when the user starts typing.
+//!
+//! Speculation spends tokens on replies that are mostly thrown away, so
+//! offer it for a few likely candidates rather than every suggestion.
+//! Generations that finished before being discarded are still billed; see
+//! [`SpeculationStats::discarded`].
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```no_run")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use language_barrier_core::llm_service::{HTTPLlmService, LLMService};
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::speculate::SpeculativeService;
+//! use language_barrier_core::{Chat, Message, OpenAi};
+//!
+//! # async fn example() -> language_barrier_core::Result<()> {
+//! let service = HTTPLlmService::new(OpenAi::GPT4oMini, Arc::new(OpenAIProvider::new()));
+//! let service = SpeculativeService::new(Arc::new(service));
+//!
+//! let chat = Chat::default().add_message(Message::user("What's a monad?"));
+//! let chat = chat.clone().add_message(service.generate_next_message(&chat).await?);
+//!
+//! // While the user reads the answer, prepare replies to the suggestions
+//! service.prefetch(&chat, [Message::user("Give an example"), Message::user("Why?")]);
+//!
+//! // The user clicks "Give an example": the reply is already on its way
+//! let chat = chat.add_message(Message::user("Give an example"));
+//! let reply = service.generate_next_message(&chat).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use tokio::sync::OnceCell;
+use tokio::task::AbortHandle;
+use tracing::debug;
+
+use crate::error::Result;
+use crate::llm_service::LLMService;
+use crate::priority::Priority;
+use crate::reload::SharedService;
+use crate::salvage::Continuation;
+use crate::{Chat, Message, ModelInfo};
+
+/// How speculative generations have been used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpeculationStats {
+    /// Generations started for candidates
+    pub issued: u64,
+    /// Calls answered by a speculative generation
+    pub accepted: u64,
+    /// Generations cancelled or thrown away unused
+    pub discarded: u64,
+}
+
+/// A generation for one candidate message
+struct Speculation {
+    /// The history the reply continues: the chat plus the candidate
+    history: Vec<Message>,
+    /// The reply, once the generation succeeds
+    reply: Arc<OnceCell<Message>>,
+    task: AbortHandle,
+}
+
+#[derive(Default)]
+struct State {
+    speculations: Vec<Speculation>,
+    stats: SpeculationStats,
+}
+
+impl State {
+    /// Cancels every outstanding speculation
+    fn discard(&mut self) {
+        for speculation in self.speculations.drain(..) {
+            speculation.task.abort();
+            self.stats.discarded += 1;
+        }
+    }
+}
+
+/// A service that can generate replies to candidate messages ahead of time
+///
+/// See the [module docs](self).
+pub struct SpeculativeService<M> {
+    inner: SharedService<M>,
+    state: Mutex<State>,
+}
+
+impl<M: ModelInfo + 'static> SpeculativeService<M> {
+    /// Wraps `inner`, which makes both speculative and ordinary calls
+    pub fn new(inner: SharedService<M>) -> Self {
+        Self {
+            inner,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Starts generating a reply to `chat` followed by each candidate,
+    /// cancelling any earlier speculation
+    ///
+    /// The generations are sent at [`Priority::Background`], so under a
+    /// [`PriorityLimiter`](crate::priority::PriorityLimiter) they only use
+    /// capacity interactive calls leave. A speculation that fails is
+    /// dropped; if its candidate is chosen, the call is made as usual.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn prefetch(&self, chat: &Chat, candidates: impl IntoIterator<Item = Message>) {
+        let mut state = self.state();
+        state.discard();
+
+        for candidate in candidates {
+            let speculative = chat
+                .clone()
+                .with_priority(Priority::Background)
+                .add_message(candidate);
+            let history = speculative.history.clone();
+            let reply = Arc::new(OnceCell::new());
+            let task = tokio::spawn({
+                let inner = self.inner.clone();
+                let reply = reply.clone();
+                async move {
+                    let result = reply
+                        .get_or_try_init(|| inner.generate_next_message(&speculative))
+                        .await;
+                    if let Err(e) = result {
+                        debug!("Speculative generation failed: {}", e);
+                    }
+                }
+            });
+            state.speculations.push(Speculation {
+                history,
+                reply,
+                task: task.abort_handle(),
+            });
+            state.stats.issued += 1;
+        }
+    }
+
+    /// Cancels every outstanding speculation, e.g. because the user started
+    /// typing their own message
+    pub fn cancel(&self) {
+        self.state().discard();
+    }
+
+    /// Returns how speculative generations have been used so far
+    pub fn stats(&self) -> SpeculationStats {
+        self.state().stats
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo + 'static> LLMService<M> for SpeculativeService<M> {
+    /// Returns the speculative reply if `chat` ends in a prefetched
+    /// candidate, and otherwise calls the wrapped service
+    ///
+    /// Either way, every other outstanding speculation is cancelled.
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        let accepted = {
+            let mut state = self.state();
+            let accepted = state
+                .speculations
+                .iter()
+                .position(|speculation| speculation.history == chat.history)
+                .map(|index| state.speculations.swap_remove(index));
+            state.discard();
+            if accepted.is_some() {
+                state.stats.accepted += 1;
+            }
+            accepted
+        };
+
+        match accepted {
+            Some(speculation) => {
+                debug!("Answering with a speculative reply");
+                // If the speculation failed, this makes the call instead
+                speculation
+                    .reply
+                    .get_or_try_init(|| self.inner.generate_next_message(chat))
+                    .await
+                    .cloned()
+            }
+            None => self.inner.generate_next_message(chat).await,
+        }
+    }
+
+    fn continuation(&self) -> Continuation {
+        self.inner.continuation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Claude;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+
+    /// Replies with the text of the chat's last message and its priority,
+    /// once `gate` is notified
+    #[derive(Default)]
+    struct Echo {
+        calls: AtomicUsize,
+        gate: Notify,
+    }
+
+    #[async_trait]
+    impl LLMService<Claude> for Echo {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.gate.notified().await;
+            let last = chat.history.last().map(Message::text_content);
+            Ok(Message::assistant(format!(
+                "{} ({:?})",
+                last.unwrap_or_default(),
+                chat.priority
+            )))
+        }
+    }
+
+    fn setup() -> (Arc<Echo>, SpeculativeService<Claude>, Chat) {
+        let echo = Arc::new(Echo::default());
+        let service = SpeculativeService::new(echo.clone());
+        let chat = Chat::default().add_message(Message::user("Hi"));
+        (echo, service, chat)
+    }
+
+    #[tokio::test]
+    async fn test_accepted_candidate_uses_the_speculative_reply() {
+        let (echo, service, chat) = setup();
+        service.prefetch(&chat, [Message::user("More"), Message::user("Why?")]);
+        tokio::task::yield_now().await;
+        assert_eq!(echo.calls.load(Ordering::SeqCst), 2);
+        echo.gate.notify_waiters();
+
+        let chat = chat.add_message(Message::user("More"));
+        let reply = service.generate_next_message(&chat).await.unwrap();
+
+        assert_eq!(reply.text_content(), "More (Background)");
+        assert_eq!(echo.calls.load(Ordering::SeqCst), 2);
+        let stats = service.stats();
+        assert_eq!((stats.issued, stats.accepted, stats.discarded), (2, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_other_messages_cancel_speculation() {
+        let (echo, service, chat) = setup();
+        service.prefetch(&chat, [Message::user("More")]);
+        tokio::task::yield_now().await;
+
+        let chat = chat.add_message(Message::user("Something else"));
+        let call = service.generate_next_message(&chat);
+        let notify = async {
+            tokio::task::yield_now().await;
+            echo.gate.notify_waiters();
+        };
+        let (reply, ()) = tokio::join!(call, notify);
+
+        assert_eq!(reply.unwrap().text_content(), "Something else (Interactive)");
+        assert_eq!(echo.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(service.stats().discarded, 1);
+        assert_eq!(service.stats().accepted, 0);
+    }
+}
@@ -0,0 +1,345 @@
+//! Persisting conversations shared between processes
+//!
+//! A conversation driven from several places at once (a webhook handler
+//! answering the user while a background job appends tool results) must
+//! not interleave turns. A [`ChatStore`] keeps each conversation's history
+//! as a [`StoredChat`] with a version, and saves with compare-and-swap: a
+//! save only succeeds if the stored version is still the one the chat was
+//! loaded at, its [`Chat::version`]. Otherwise it fails with
+//! `Error::VersionConflict`, and the caller reloads, re-applies its turn and
+//! tries again. [`ChatStore::save_chat`] records the new version on the
+//! chat, so the same chat can be saved again after its next turn.
+//!
+//! Only the history is stored. The rest of a chat (system prompt, tools,
+//! filters) is configuration the app rebuilds, and
+//! [`StoredChat::restore`] puts the stored history and version into it.
+//!
+//! Implement `ChatStore` to keep conversations in a database, doing the
+//! compare-and-swap in one statement (e.g. `UPDATE ... WHERE version = ?`);
+//! [`InMemoryChatStore`] is the default.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::store::{ChatStore, InMemoryChatStore};
+//! use language_barrier_core::{Chat, Error, Message};
+//!
+//! # tokio_test::block_on(async {
+//! let store = InMemoryChatStore::new();
+//! let mut chat = Chat::default()
+//!     .with_conversation_id("conv-1")
+//!     .add_message(Message::user("Hi"));
+//! store.save_chat(&mut chat).await.unwrap();
+//! assert_eq!(chat.version, 1);
+//!
+//! // Two workers load the same version
+//! let stored = store.load("conv-1").await.unwrap().unwrap();
+//! let mut webhook = stored.restore(Chat::default()).add_message(Message::assistant("Hello!"));
+//! let mut job = stored.restore(Chat::default()).add_message(Message::tool("call_1", "done"));
+//!
+//! assert_eq!(store.save_chat(&mut webhook).await.unwrap(), 2);
+//! assert_eq!(webhook.version, 2);
+//!
+//! // The job's copy is now stale, so its save is refused
+//! let error = store.save_chat(&mut job).await.unwrap_err();
+//! assert!(matches!(error, Error::VersionConflict { expected: 1, actual: 2, .. }));
+//! assert_eq!(job.version, 1);
+//! # });
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::error::{Error, Result};
+use crate::{Chat, Message};
+
+/// A conversation's history as stored, with its version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredChat {
+    /// Identifies the conversation
+    pub id: String,
+    /// The version this copy was loaded at, or 0 if it hasn't been stored
+    pub version: u64,
+    /// The conversation's messages
+    pub history: Vec<Message>,
+}
+
+impl StoredChat {
+    /// Puts the stored history into `chat`, replacing its own, and gives it
+    /// the stored conversation's ID and version
+    ///
+    /// The history is restored whole, however long it is; trimming it to a
+    /// context window is up to whoever sends it.
+    pub fn restore(&self, chat: Chat) -> Chat {
+        let mut chat = chat
+            .with_untrimmed_history(self.history.clone())
+            .with_conversation_id(self.id.clone());
+        chat.version = self.version;
+        chat
+    }
+}
+
+impl From<&Chat> for StoredChat {
+    /// Takes `chat`'s conversation ID, version and history
+    fn from(chat: &Chat) -> Self {
+        Self {
+            id: chat.conversation_id.clone(),
+            version: chat.version,
+            history: chat.history.clone(),
+        }
+    }
+}
+
+/// Where conversations are kept
+///
+/// See the [module docs](self).
+#[async_trait]
+pub trait ChatStore: Send + Sync {
+    /// Returns the stored conversation with `id`, if there is one
+    async fn load(&self, id: &str) -> Result<Option<StoredChat>>;
+
+    /// Stores `chat` if the stored version is still `chat.version`,
+    /// returning the new version
+    ///
+    /// A chat with version 0 is only stored if there's no conversation
+    /// with its ID yet, or it was deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::VersionConflict` if the conversation was saved or
+    /// deleted since `chat` was loaded, or was created by someone else.
+    async fn save(&self, chat: &StoredChat) -> Result<u64>;
+
+    /// Removes the conversation with `id`, returning whether there was one
+    ///
+    /// Versions keep counting up through a delete: a copy loaded before it
+    /// can't be saved afterwards, even over a conversation created again
+    /// with the same ID.
+    async fn delete(&self, id: &str) -> Result<bool>;
+
+    /// Stores `chat` under its conversation ID and, if that succeeds, sets
+    /// its version to the new one
+    ///
+    /// # Errors
+    ///
+    /// As [`save`](Self::save); `chat` is left unchanged.
+    async fn save_chat(&self, chat: &mut Chat) -> Result<u64> {
+        let version = self.save(&StoredChat::from(&*chat)).await?;
+        chat.version = version;
+        Ok(version)
+    }
+}
+
+/// A conversation in an [`InMemoryChatStore`], or the version it was
+/// deleted at
+#[derive(Debug)]
+enum Slot {
+    Stored(StoredChat),
+    Deleted(u64),
+}
+
+impl Slot {
+    fn version(&self) -> u64 {
+        match self {
+            Slot::Stored(chat) => chat.version,
+            Slot::Deleted(version) => *version,
+        }
+    }
+}
+
+/// A chat store held in process memory
+///
+/// Useful for tests and for sharing conversations between tasks of one
+/// process. A deleted conversation leaves its version behind, which a
+/// conversation created again with the same ID counts on from.
+#[derive(Debug, Default)]
+pub struct InMemoryChatStore {
+    chats: Mutex<HashMap<String, Slot>>,
+}
+
+impl InMemoryChatStore {
+    /// Creates an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn chats(&self) -> MutexGuard<'_, HashMap<String, Slot>> {
+        self.chats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl ChatStore for InMemoryChatStore {
+    async fn load(&self, id: &str) -> Result<Option<StoredChat>> {
+        Ok(match self.chats().get(id) {
+            Some(Slot::Stored(chat)) => Some(chat.clone()),
+            Some(Slot::Deleted(_)) | None => None,
+        })
+    }
+
+    async fn save(&self, chat: &StoredChat) -> Result<u64> {
+        let mut chats = self.chats();
+        let slot = chats.get(&chat.id);
+        let actual = slot.map_or(0, Slot::version);
+        // A new or deleted conversation can only be created
+        let expected = match slot {
+            Some(Slot::Stored(_)) => actual,
+            Some(Slot::Deleted(_)) | None => 0,
+        };
+        if chat.version != expected {
+            warn!(
+                "Conversation {} is at version {}, not {}",
+                chat.id, actual, chat.version
+            );
+            return Err(Error::VersionConflict {
+                id: chat.id.clone(),
+                expected: chat.version,
+                actual,
+            });
+        }
+
+        let version = actual + 1;
+        debug!("Saving conversation {} at version {}", chat.id, version);
+        chats.insert(
+            chat.id.clone(),
+            Slot::Stored(StoredChat {
+                version,
+                ..chat.clone()
+            }),
+        );
+        Ok(version)
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        let mut chats = self.chats();
+        let Some(Slot::Stored(chat)) = chats.get(id) else {
+            return Ok(false);
+        };
+        let version = chat.version + 1;
+        debug!("Deleting conversation {} at version {}", id, version);
+        chats.insert(id.to_string(), Slot::Deleted(version));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(id: &str) -> Chat {
+        Chat::default()
+            .with_conversation_id(id)
+            .add_message(Message::user("Hi"))
+    }
+
+    fn conflict(result: Result<u64>) -> Option<(u64, u64)> {
+        match result {
+            Err(Error::VersionConflict {
+                expected, actual, ..
+            }) => Some((expected, actual)),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_creating_twice_conflicts() {
+        let store = InMemoryChatStore::new();
+        let fresh = StoredChat::from(&chat("a"));
+
+        assert_eq!(store.save(&fresh).await.unwrap(), 1);
+        assert_eq!(conflict(store.save(&fresh).await), Some((0, 1)));
+
+        let stored = store.load("a").await.unwrap().unwrap();
+        assert_eq!(stored.version, 1);
+        let restored = stored.restore(Chat::default());
+        assert_eq!(restored.history, chat("a").history);
+        assert_eq!(restored.conversation_id, "a");
+        assert_eq!(restored.version, 1);
+        assert!(store.load("b").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_saving_moves_the_chat_to_the_new_version() {
+        let store = InMemoryChatStore::new();
+        let mut chat = chat("a");
+        assert_eq!(store.save_chat(&mut chat).await.unwrap(), 1);
+
+        let mut stale = store
+            .load("a")
+            .await
+            .unwrap()
+            .unwrap()
+            .restore(Chat::default());
+
+        // The same chat can be saved turn after turn without reloading
+        chat = chat.add_message(Message::assistant("Hello!"));
+        assert_eq!(store.save_chat(&mut chat).await.unwrap(), 2);
+        chat = chat.add_message(Message::user("Thanks"));
+        assert_eq!(store.save_chat(&mut chat).await.unwrap(), 3);
+        assert_eq!(chat.version, 3);
+
+        // A copy loaded before those saves is refused and keeps its version
+        stale = stale.add_message(Message::assistant("Hey"));
+        assert_eq!(conflict(store.save_chat(&mut stale).await), Some((1, 3)));
+        assert_eq!(stale.version, 1);
+        let stored = store.load("a").await.unwrap().unwrap();
+        assert_eq!(stored.history.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stale_saves_after_a_delete_conflict() {
+        let store = InMemoryChatStore::new();
+        let mut chat = chat("a");
+        store.save_chat(&mut chat).await.unwrap();
+        let stale = store.load("a").await.unwrap().unwrap();
+
+        assert!(store.delete("a").await.unwrap());
+        assert!(!store.delete("a").await.unwrap());
+        assert!(store.load("a").await.unwrap().is_none());
+        assert_eq!(conflict(store.save(&stale).await), Some((1, 2)));
+
+        // Created again, the conversation counts on from the delete, so the
+        // copy from before it still can't be saved
+        let mut recreated = self::chat("a");
+        assert_eq!(store.save_chat(&mut recreated).await.unwrap(), 3);
+        assert_eq!(conflict(store.save(&stale).await), Some((1, 3)));
+        assert_eq!(conflict(store.save_chat(&mut chat).await), Some((1, 3)));
+    }
+
+    #[tokio::test]
+    async fn test_long_histories_round_trip_whole() {
+        let store = InMemoryChatStore::new();
+        // Well past the context window that trims a chat's own history
+        let turn = "word ".repeat(1_000);
+        let history: Vec<Message> = (0..200)
+            .map(|i| {
+                if i % 2 == 0 {
+                    Message::user(turn.clone())
+                } else {
+                    Message::assistant(turn.clone())
+                }
+            })
+            .collect();
+        let stored = StoredChat {
+            id: "long".to_string(),
+            version: 0,
+            history,
+        };
+        store.save(&stored).await.unwrap();
+
+        let restored = store
+            .load("long")
+            .await
+            .unwrap()
+            .unwrap()
+            .restore(Chat::default());
+        assert_eq!(restored.history.len(), 200);
+        assert_eq!(restored.history, stored.history);
+    }
+}
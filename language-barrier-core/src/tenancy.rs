@@ -0,0 +1,436 @@
+//! Serving many customers from one set of providers
+//!
+//! A SaaS backend usually makes calls on behalf of its customers, each with
+//! its own API key, its own request rate and its own spending limit.
+//! Rather than wiring up a service per customer, name the customer on the
+//! chat with [`Chat::with_tenant`] and send it through a [`TenantService`].
+//! The tenant's [`TenantConfig`] picks the service that handles the call
+//! (typically one built with the tenant's key) and sets its limits:
+//!
+//! - a rate limit of so many requests per window; calls over it fail with
+//!   `Error::RateLimit` without reaching the provider
+//! - a token budget and a cost budget; once either is used up, calls fail
+//!   with `Error::BudgetExceeded`
+//!
+//! Usage reported by each reply is totalled per tenant and read back with
+//! [`TenantService::usage`], for billing or dashboards. For totals per day
+//! and model, exported for a billing pipeline, see [`billing`](crate::billing).
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::provider::openai::{OpenAIConfig, OpenAIProvider};
+//! use language_barrier_core::tenancy::{TenantConfig, TenantService};
+//! use language_barrier_core::{Chat, Message, OpenAi, Pricing};
+//!
+//! let shared = HTTPLlmService::new(OpenAi::GPT4oMini, Arc::new(OpenAIProvider::new()));
+//! let acme = HTTPLlmService::new(
+//!     OpenAi::GPT4oMini,
+//!     Arc::new(OpenAIProvider::with_config(
+//!         OpenAIConfig::builder().api_key("acme-key").build().unwrap(),
+//!     )),
+//! );
+//!
+//! let service = TenantService::new(Arc::new(shared))
+//!     .with_tenant(
+//!         "acme",
+//!         TenantConfig::new()
+//!             .with_service(Arc::new(acme))
+//!             .with_rate_limit(60, Duration::from_secs(60))
+//!             .with_cost_budget(25.0, Pricing::new(0.15, 0.6)),
+//!     )
+//!     .with_default_config(TenantConfig::new().with_token_budget(100_000));
+//!
+//! let chat = Chat::default()
+//!     .with_tenant("acme")
+//!     .add_message(Message::user("Hello!"));
+//! // service.generate_next_message(&chat).await?;
+//! assert_eq!(service.usage("acme").requests, 0);
+//! ```
+//!
+//! [`Chat::with_tenant`]: crate::Chat::with_tenant
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::error::{ChatConfigError, Error, Result};
+use crate::llm_service::LLMService;
+use crate::salvage::Continuation;
+use crate::usage::{Pricing, Usage};
+use crate::{Chat, Message, ModelInfo};
+
+/// How one tenant's calls are handled and limited
+pub struct TenantConfig<M> {
+    service: Option<Arc<dyn LLMService<M> + Send + Sync>>,
+    rate_limit: Option<(usize, Duration)>,
+    token_budget: Option<u64>,
+    cost_budget: Option<f64>,
+    pricing: Option<Pricing>,
+}
+
+impl<M> TenantConfig<M> {
+    /// Creates a config that uses the shared service, without limits
+    pub fn new() -> Self {
+        Self {
+            service: None,
+            rate_limit: None,
+            token_budget: None,
+            cost_budget: None,
+            pricing: None,
+        }
+    }
+
+    /// Sends the tenant's calls through `service` instead of the shared one,
+    /// e.g. one built with the tenant's own API key
+    #[must_use]
+    pub fn with_service(self, service: Arc<dyn LLMService<M> + Send + Sync>) -> Self {
+        Self {
+            service: Some(service),
+            ..self
+        }
+    }
+
+    /// Allows at most `requests` calls in any `window`
+    #[must_use]
+    pub fn with_rate_limit(self, requests: usize, window: Duration) -> Self {
+        Self {
+            rate_limit: Some((requests, window)),
+            ..self
+        }
+    }
+
+    /// Stops calls once the tenant's replies have used `tokens` input and
+    /// output tokens
+    ///
+    /// The budget is checked before each call, so the call that crosses it
+    /// still completes.
+    #[must_use]
+    pub fn with_token_budget(self, tokens: u64) -> Self {
+        Self {
+            token_budget: Some(tokens),
+            ..self
+        }
+    }
+
+    /// Stops calls once the tenant's replies have cost `limit` at `pricing`
+    ///
+    /// Like the token budget, this is checked before each call.
+    #[must_use]
+    pub fn with_cost_budget(self, limit: f64, pricing: Pricing) -> Self {
+        Self {
+            cost_budget: Some(limit),
+            pricing: Some(pricing),
+            ..self
+        }
+    }
+
+    /// Prices the tenant's usage, for [`TenantUsage::cost`], without a
+    /// cost budget
+    #[must_use]
+    pub fn with_pricing(self, pricing: Pricing) -> Self {
+        Self {
+            pricing: Some(pricing),
+            ..self
+        }
+    }
+}
+
+impl<M> Default for TenantConfig<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a tenant has used so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TenantUsage {
+    /// Calls admitted, including ones that failed
+    pub requests: u64,
+    /// Tokens reported by the replies
+    pub usage: Usage,
+    /// What the usage cost, if the tenant has pricing
+    pub cost: f64,
+}
+
+#[derive(Debug, Default)]
+struct TenantState {
+    /// Start times of the calls inside the rate limit window
+    recent: VecDeque<Instant>,
+    usage: TenantUsage,
+}
+
+/// A service that routes and limits calls by the chat's tenant
+///
+/// Chats must name a tenant with [`Chat::with_tenant`]. Tenants without a
+/// config of their own get the default config, if one is set, with their
+/// own counters; otherwise they're rejected.
+pub struct TenantService<M> {
+    service: Arc<dyn LLMService<M> + Send + Sync>,
+    tenants: HashMap<String, TenantConfig<M>>,
+    default_config: Option<TenantConfig<M>>,
+    state: Mutex<HashMap<String, TenantState>>,
+}
+
+impl<M: ModelInfo> TenantService<M> {
+    /// Creates a tenant service that sends calls through `service` unless a
+    /// tenant's config names another
+    pub fn new(service: Arc<dyn LLMService<M> + Send + Sync>) -> Self {
+        Self {
+            service,
+            tenants: HashMap::new(),
+            default_config: None,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Configures `tenant`, replacing any earlier config for it
+    #[must_use]
+    pub fn with_tenant(self, tenant: impl Into<String>, config: TenantConfig<M>) -> Self {
+        let mut tenants = self.tenants;
+        tenants.insert(tenant.into(), config);
+        Self { tenants, ..self }
+    }
+
+    /// Accepts tenants without a config of their own, applying `config` to
+    /// each of them separately
+    #[must_use]
+    pub fn with_default_config(self, config: TenantConfig<M>) -> Self {
+        Self {
+            default_config: Some(config),
+            ..self
+        }
+    }
+
+    /// Returns what `tenant` has used since it was last reset
+    pub fn usage(&self, tenant: &str) -> TenantUsage {
+        self.state()
+            .get(tenant)
+            .map(|state| state.usage)
+            .unwrap_or_default()
+    }
+
+    /// Returns what every tenant that made a call has used
+    pub fn usage_by_tenant(&self) -> HashMap<String, TenantUsage> {
+        self.state()
+            .iter()
+            .map(|(tenant, state)| (tenant.clone(), state.usage))
+            .collect()
+    }
+
+    /// Clears `tenant`'s usage, restoring its budgets, e.g. at the start of
+    /// a billing period
+    pub fn reset_usage(&self, tenant: &str) {
+        if let Some(state) = self.state().get_mut(tenant) {
+            state.usage = TenantUsage::default();
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, HashMap<String, TenantState>> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn config(&self, tenant: &str) -> Result<&TenantConfig<M>> {
+        self.tenants
+            .get(tenant)
+            .or(self.default_config.as_ref())
+            .ok_or_else(|| {
+                ChatConfigError::UnknownTenant {
+                    tenant: tenant.to_string(),
+                }
+                .into()
+            })
+    }
+
+    /// Checks `tenant`'s limits and counts the call if they allow it
+    fn admit(&self, tenant: &str, config: &TenantConfig<M>) -> Result<()> {
+        let mut states = self.state();
+        let state = states.entry(tenant.to_string()).or_default();
+        let used = state.usage;
+
+        if config
+            .token_budget
+            .is_some_and(|budget| used.usage.total_tokens() >= budget)
+        {
+            return Err(budget_exceeded(tenant, "token"));
+        }
+        if config.cost_budget.is_some_and(|budget| used.cost >= budget) {
+            return Err(budget_exceeded(tenant, "cost"));
+        }
+
+        let now = Instant::now();
+        if let Some((requests, window)) = config.rate_limit {
+            while state
+                .recent
+                .front()
+                .is_some_and(|&start| now.duration_since(start) >= window)
+            {
+                state.recent.pop_front();
+            }
+            if state.recent.len() >= requests {
+                warn!("Tenant {} is over its rate limit", tenant);
+                return Err(Error::RateLimit(format!(
+                    "tenant {tenant} is limited to {requests} requests per {window:?}"
+                )));
+            }
+            state.recent.push_back(now);
+        }
+        state.usage.requests += 1;
+        Ok(())
+    }
+
+    /// Adds a reply's usage to `tenant`'s totals
+    fn record(&self, tenant: &str, config: &TenantConfig<M>, usage: Usage) {
+        let mut states = self.state();
+        let totals = &mut states.entry(tenant.to_string()).or_default().usage;
+        totals.usage += usage;
+        if let Some(pricing) = &config.pricing {
+            totals.cost += usage.cost(pricing);
+        }
+    }
+}
+
+fn budget_exceeded(tenant: &str, budget: &str) -> Error {
+    warn!("Tenant {} has used up its {} budget", tenant, budget);
+    Error::BudgetExceeded {
+        tenant: tenant.to_string(),
+        budget: budget.to_string(),
+    }
+}
+
+#[async_trait]
+impl<M: ModelInfo> LLMService<M> for TenantService<M> {
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        let tenant = chat
+            .tenant
+            .as_deref()
+            .ok_or(ChatConfigError::MissingTenant)?;
+        let config = self.config(tenant)?;
+        self.admit(tenant, config)?;
+
+        debug!("Generating for tenant {}", tenant);
+        let service = config.service.as_ref().unwrap_or(&self.service);
+        let reply = service.generate_next_message(chat).await?;
+        if let Some(usage) = reply.usage() {
+            self.record(tenant, config, usage);
+        }
+        Ok(reply)
+    }
+
+    fn continuation(&self) -> Continuation {
+        self.service.continuation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Claude;
+
+    /// Replies with its name, using ten input and five output tokens
+    struct Named(&'static str);
+
+    #[async_trait]
+    impl LLMService<Claude> for Named {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            let usage = serde_json::to_value(Usage::new(10, 5)).unwrap();
+            Ok(Message::assistant(self.0).with_metadata(Usage::METADATA_KEY, usage))
+        }
+    }
+
+    fn chat(tenant: &str) -> Chat {
+        Chat::default()
+            .with_tenant(tenant)
+            .add_message(Message::user("Hi"))
+    }
+
+    #[tokio::test]
+    async fn test_tenants_are_routed_and_totalled_separately() {
+        let service = TenantService::new(Arc::new(Named("shared")))
+            .with_tenant(
+                "acme",
+                TenantConfig::new()
+                    .with_service(Arc::new(Named("acme")))
+                    .with_pricing(Pricing::new(1_000_000.0, 0.0)),
+            )
+            .with_default_config(TenantConfig::new());
+
+        let reply = service.generate_next_message(&chat("acme")).await.unwrap();
+        assert_eq!(reply.text_content(), "acme");
+        let reply = service.generate_next_message(&chat("other")).await.unwrap();
+        assert_eq!(reply.text_content(), "shared");
+
+        assert_eq!(
+            service.usage("acme"),
+            TenantUsage {
+                requests: 1,
+                usage: Usage::new(10, 5),
+                cost: 10.0,
+            }
+        );
+        assert_eq!(service.usage_by_tenant().len(), 2);
+        service.reset_usage("acme");
+        assert_eq!(service.usage("acme"), TenantUsage::default());
+
+        let strict = TenantService::new(Arc::new(Named("shared")));
+        assert!(matches!(
+            strict.generate_next_message(&chat("acme")).await,
+            Err(Error::ChatConfig(ChatConfigError::UnknownTenant { .. }))
+        ));
+        assert!(matches!(
+            strict.generate_next_message(&Chat::default()).await,
+            Err(Error::ChatConfig(ChatConfigError::MissingTenant))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limits_and_budgets_stop_calls() {
+        let service = TenantService::new(Arc::new(Named("shared")))
+            .with_tenant(
+                "limited",
+                TenantConfig::new().with_rate_limit(2, Duration::from_secs(60)),
+            )
+            .with_tenant("budgeted", TenantConfig::new().with_token_budget(30));
+
+        for _ in 0..2 {
+            service
+                .generate_next_message(&chat("limited"))
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            service.generate_next_message(&chat("limited")).await,
+            Err(Error::RateLimit(_))
+        ));
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(
+            service
+                .generate_next_message(&chat("limited"))
+                .await
+                .is_ok()
+        );
+
+        for _ in 0..2 {
+            service
+                .generate_next_message(&chat("budgeted"))
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            service.generate_next_message(&chat("budgeted")).await,
+            Err(Error::BudgetExceeded { budget, .. }) if budget == "token"
+        ));
+        assert_eq!(service.usage("budgeted").requests, 2);
+    }
+}
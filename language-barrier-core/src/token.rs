@@ -1,3 +1,9 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::message::{ContentPart, Message};
+
 /// A simple token counter for tracking token usage in conversations
 ///
 /// This is a very basic implementation that provides a minimal token counting
@@ -72,6 +78,41 @@ impl TokenCounter {
         self.total += Self::count_tokens(text);
     }
 
+    /// The tokenizer name under which message counts are cached
+    pub const TOKENIZER: &'static str = "whitespace";
+
+    /// Counts the tokens in a message's text, caching the count on the message
+    ///
+    /// A message that hasn't changed since it was last counted isn't
+    /// tokenized again; see [`Message::cached_token_count`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::{Message, TokenCounter};
+    ///
+    /// let mut msg = Message::user("Hello, world!");
+    /// assert_eq!(TokenCounter::count_message(&mut msg), 2);
+    /// assert_eq!(msg.cached_token_count(TokenCounter::TOKENIZER), Some(2));
+    /// ```
+    pub fn count_message(msg: &mut Message) -> usize {
+        if let Some(tokens) = msg.cached_token_count(Self::TOKENIZER) {
+            return tokens;
+        }
+        let tokens = Self::count_tokens(&msg.text_content());
+        msg.cache_token_count(Self::TOKENIZER, tokens);
+        tokens
+    }
+
+    /// Subtracts a message's tokens from the total, using its cached count
+    /// if it has a current one
+    pub fn subtract_message(&mut self, msg: &Message) {
+        let tokens = msg
+            .cached_token_count(Self::TOKENIZER)
+            .unwrap_or_else(|| Self::count_tokens(&msg.text_content()));
+        self.total = self.total.saturating_sub(tokens);
+    }
+
     /// Subtracts the token count of the given text from the total
     ///
     /// Will not go below zero (saturates at zero).
@@ -128,6 +169,139 @@ impl TokenCounter {
     }
 }
 
+/// Counts tokens the way a model family does
+///
+/// [`TokenCounter`] is the crate's whitespace approximation; implement this
+/// over a real tokenizer (e.g. `tiktoken-rs`) for counts that match the
+/// provider's.
+pub trait Tokenizer {
+    /// The name counts are cached under on messages; see
+    /// [`Message::cached_token_count`]
+    fn name(&self) -> &str;
+
+    /// Counts the tokens in `text`
+    fn count(&self, text: &str) -> usize;
+
+    /// Counts the tokens an attachment (an image, document or audio part)
+    /// costs
+    ///
+    /// What an attachment costs depends on the provider and on the image's
+    /// size or the document's page count, so the default is a flat
+    /// estimate of [`ATTACHMENT_ESTIMATE`] tokens. Override it for real
+    /// counts.
+    fn count_attachment(&self, _part: &ContentPart) -> usize {
+        ATTACHMENT_ESTIMATE
+    }
+}
+
+/// The default estimate for one attachment, about what a one-megapixel
+/// image costs
+pub const ATTACHMENT_ESTIMATE: usize = 1_000;
+
+impl Tokenizer for TokenCounter {
+    fn name(&self) -> &str {
+        Self::TOKENIZER
+    }
+
+    fn count(&self, text: &str) -> usize {
+        Self::count_tokens(text)
+    }
+}
+
+/// Where a chat's prompt tokens go, from [`Chat::token_report`]
+///
+/// [`Chat::token_report`]: crate::Chat::token_report
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TokenReport {
+    /// The tokenizer that counted
+    pub tokenizer: String,
+    /// The system prompt and its segments
+    pub system: usize,
+    /// The tool definitions: names, descriptions and parameter schemas
+    pub tools: usize,
+    /// The text of the history, including tool calls and tool results
+    pub history: usize,
+    /// Images, documents and audio in the history
+    pub attachments: usize,
+    /// Each message of the history, in order
+    pub messages: Vec<MessageTokens>,
+}
+
+/// The tokens of one message in a [`TokenReport`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MessageTokens {
+    /// The message's position in the history
+    pub index: usize,
+    /// The message's role, e.g. `"user"`
+    pub role: &'static str,
+    /// Tokens of the text, tool calls and tool results
+    pub text: usize,
+    /// Tokens of the attachments
+    pub attachments: usize,
+}
+
+impl MessageTokens {
+    /// The message's tokens in total
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.text + self.attachments
+    }
+}
+
+impl TokenReport {
+    /// The prompt's tokens in total
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.system + self.tools + self.history + self.attachments
+    }
+
+    /// The `n` messages with the most tokens, largest first
+    #[must_use]
+    pub fn largest_messages(&self, n: usize) -> Vec<&MessageTokens> {
+        let mut messages: Vec<&MessageTokens> = self.messages.iter().collect();
+        messages.sort_by_key(|message| std::cmp::Reverse(message.total()));
+        messages.truncate(n);
+        messages
+    }
+}
+
+impl fmt::Display for TokenReport {
+    /// Prints each section's tokens and share of the total, then the five
+    /// largest messages
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        let share = |tokens: usize| match total {
+            0 => 0.0,
+            total => tokens as f64 * 100.0 / total as f64,
+        };
+
+        writeln!(f, "{} prompt tokens ({})", total, self.tokenizer)?;
+        for (section, tokens) in [
+            ("system", self.system),
+            ("tools", self.tools),
+            ("history", self.history),
+            ("attachments", self.attachments),
+        ] {
+            writeln!(f, "  {section:<12} {tokens:>8} {:>5.1}%", share(tokens))?;
+        }
+        let largest = self.largest_messages(5);
+        if !largest.is_empty() {
+            writeln!(f, "Largest messages:")?;
+        }
+        for message in largest {
+            writeln!(
+                f,
+                "  #{:<4} {:<10} {:>8} {:>5.1}%",
+                message.index,
+                message.role,
+                message.total(),
+                share(message.total())
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,4 +353,21 @@ mod tests {
         assert!(counter.under_budget(3));
         assert!(!counter.under_budget(1));
     }
+
+    #[test]
+    fn test_message_counts_are_cached_until_edited() {
+        let mut msg = Message::user("one two three");
+        assert_eq!(TokenCounter::count_message(&mut msg), 3);
+        msg.cache_token_count("other", 7);
+        assert_eq!(msg.cached_token_count(TokenCounter::TOKENIZER), Some(3));
+
+        let mut msg = msg.with_text("four");
+        assert_eq!(msg.cached_token_count("other"), None);
+        assert_eq!(TokenCounter::count_message(&mut msg), 4);
+
+        let mut counter = TokenCounter::default();
+        counter.observe("one two three four five");
+        counter.subtract_message(&msg);
+        assert_eq!(counter.total(), 1);
+    }
 }
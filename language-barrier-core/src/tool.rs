@@ -1,7 +1,18 @@
+//! Tools the model can call
+//!
+//! The [`Tool`] and [`ToolDefinition`] traits, which derive parameter
+//! schemas with `schemars`, need the `tools` feature. [`LlmToolInfo`] and
+//! [`ToolChoice`] describe tools on the wire and are always available, so
+//! chats and replies that mention tools still round-trip without it.
+
+#[cfg(feature = "tools")]
 use schemars::JsonSchema;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::Serialize;
+#[cfg(feature = "tools")]
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
+#[cfg(feature = "tools")]
 use crate::error::Result;
 
 /// Defines the contract for tools that can be used by LLMs
@@ -37,6 +48,7 @@ use crate::error::Result;
 ///     }
 /// }
 /// ```
+#[cfg(feature = "tools")]
 pub trait Tool
 where
     Self: JsonSchema,
@@ -100,6 +112,7 @@ where
 ///     }
 /// }
 /// ```
+#[cfg(feature = "tools")]
 pub trait ToolDefinition {
     /// The input type that this tool accepts
     type Input: DeserializeOwned + JsonSchema + Send + Sync + 'static;
@@ -137,13 +150,14 @@ pub struct LlmToolInfo {
 /// - OpenAI/Mistral: Maps to "auto", "required", "none", or a function object
 /// - Anthropic: Maps to "auto", "any", "none", or a function object
 /// - Gemini: Maps to function_calling_config modes and allowed_function_names
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum ToolChoice {
     /// Allow the model to choose which tool to use (or none)
     /// 
     /// - OpenAI/Mistral: "auto"
     /// - Anthropic: "auto"
     /// - Gemini: mode="auto"
+    #[default]
     Auto,
     /// Require the model to use one of the available tools
     /// 
@@ -165,12 +179,6 @@ pub enum ToolChoice {
     Specific(String),
 }
 
-impl Default for ToolChoice {
-    fn default() -> Self {
-        Self::Auto
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,328 @@
+//! Emulated tool calling
+//!
+//! Some models, mostly local ones, have no native function calling (see
+//! [`ModelCapability::Tools`]). A service built with
+//! [`HTTPLlmService::with_tool_emulation`] calls tools on them anyway,
+//! ReAct-style: the chat's tools are described in a system segment tagged
+//! [`SEGMENT_TAG`], along with a syntax for calling them, and calls written
+//! in that syntax are parsed out of the reply into [`ToolCall`]s.
+//!
+//! Nothing changes for the caller. Tools are still set with
+//! [`Chat::with_tools`], calls still arrive in the reply's `tool_calls`, and
+//! results still go back as [`Message::tool`] messages; the service renders
+//! earlier calls and results as text for the model.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::tool_emulation::{render_call, parse_calls};
+//!
+//! let text = format!(
+//!     "Let me check.\n{}",
+//!     render_call("get_weather", r#"{"location":"Paris"}"#)
+//! );
+//! let (rest, calls) = parse_calls(&text);
+//! assert_eq!(rest, "Let me check.");
+//! assert_eq!(calls[0].function.name, "get_weather");
+//! assert_eq!(calls[0].function.arguments, r#"{"location":"Paris"}"#);
+//! ```
+//!
+//! [`ModelCapability::Tools`]: crate::ModelCapability::Tools
+//! [`HTTPLlmService::with_tool_emulation`]: crate::HTTPLlmService::with_tool_emulation
+//! [`Chat::with_tools`]: crate::Chat::with_tools
+//! [`Message::tool`]: crate::Message::tool
+
+use std::collections::HashMap;
+
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::chat::{Chat, SystemSegment};
+use crate::message::{Content, Function, Message, ToolCall};
+use crate::tool::{LlmToolInfo, ToolChoice};
+
+/// The tag of the system segment describing the emulated tools
+pub const SEGMENT_TAG: &str = "tools";
+
+const CALL_OPEN: &str = "<tool_call>";
+const CALL_CLOSE: &str = "</tool_call>";
+
+/// Writes one tool call in the emulated syntax
+pub fn render_call(name: &str, arguments: &str) -> String {
+    let arguments: Value = serde_json::from_str(arguments).unwrap_or_else(|_| json!({}));
+    let call = json!({ "name": name, "arguments": arguments });
+    format!("{CALL_OPEN}{call}{CALL_CLOSE}")
+}
+
+/// Splits the tool calls out of a reply, returning the remaining text and
+/// the calls
+///
+/// Blocks that aren't a JSON object with a `name` are left in the text.
+pub fn parse_calls(text: &str) -> (String, Vec<ToolCall>) {
+    let mut rest = String::new();
+    let mut calls = Vec::new();
+    let mut remaining = text;
+    while let Some(start) = remaining.find(CALL_OPEN) {
+        let body_start = start + CALL_OPEN.len();
+        let Some(len) = remaining[body_start..].find(CALL_CLOSE) else {
+            break;
+        };
+        let end = body_start + len + CALL_CLOSE.len();
+        match parse_call(&remaining[body_start..body_start + len]) {
+            Some(call) => {
+                rest.push_str(&remaining[..start]);
+                calls.push(call);
+            }
+            None => rest.push_str(&remaining[..end]),
+        }
+        remaining = &remaining[end..];
+    }
+    rest.push_str(remaining);
+    (rest.trim().to_string(), calls)
+}
+
+fn parse_call(body: &str) -> Option<ToolCall> {
+    let call: Value = serde_json::from_str(body.trim()).ok()?;
+    let name = call.get("name")?.as_str()?;
+    let arguments = call.get("arguments").cloned().unwrap_or_else(|| json!({}));
+    Some(ToolCall {
+        id: format!("call_{}", Uuid::new_v4().simple()),
+        tool_type: "function".to_string(),
+        function: Function {
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        },
+    })
+}
+
+/// Returns the instructions describing `tools` and how to call them
+fn instructions(tools: &[LlmToolInfo], choice: Option<&ToolChoice>) -> String {
+    let mut text = String::from("You can call these tools:\n");
+    for tool in tools {
+        text.push_str(&format!(
+            "- {}: {} Arguments (JSON schema): {}\n",
+            tool.name, tool.description, tool.parameters
+        ));
+    }
+    text.push_str(&format!(
+        "\nTo call a tool, write one line per call in exactly this form and stop:\n\
+         {}\n\
+         Results come back in <tool_result> tags. Once you have what you need, answer normally.",
+        render_call("tool_name", r#"{"argument":"value"}"#)
+    ));
+    match choice {
+        Some(ToolChoice::Any) => text.push_str(" You must call at least one tool first."),
+        Some(ToolChoice::Specific(name)) => {
+            text.push_str(&format!(" You must call {name} first."));
+        }
+        _ => {}
+    }
+    text
+}
+
+/// Returns `chat` with its tools moved into the system prompt and earlier
+/// calls and results written out as text
+pub(crate) fn emulated_chat(chat: &Chat) -> Chat {
+    let mut names = HashMap::new();
+    let history = chat
+        .history
+        .iter()
+        .map(|message| match message {
+            Message::Assistant {
+                tool_calls,
+                metadata,
+                ..
+            } if !tool_calls.is_empty() => {
+                let mut lines = vec![message.text_content()];
+                for call in tool_calls {
+                    names.insert(call.id.clone(), call.function.name.clone());
+                    lines.push(render_call(&call.function.name, &call.function.arguments));
+                }
+                Message::Assistant {
+                    content: Some(Content::Text(lines.join("\n").trim().to_string())),
+                    tool_calls: Vec::new(),
+                    metadata: metadata.clone(),
+                }
+            }
+            Message::Tool {
+                tool_call_id,
+                content,
+                ..
+            } => {
+                let name = names.get(tool_call_id).map_or("unknown", String::as_str);
+                Message::user(format!(
+                    "<tool_result name=\"{name}\">{content}</tool_result>"
+                ))
+            }
+            other => other.clone(),
+        })
+        .collect();
+
+    let tools = chat.sent_tools();
+    let mut emulated = chat.clone();
+    emulated.tools = None;
+    emulated.tool_choice = None;
+    emulated.parallel_tool_calls = None;
+    let emulated = emulated.with_history(history);
+    if tools.is_empty() || chat.tool_choice == Some(ToolChoice::None) {
+        return emulated;
+    }
+    emulated.with_system_segment(
+        SystemSegment::new(instructions(&tools, chat.tool_choice.as_ref())).with_tag(SEGMENT_TAG),
+    )
+}
+
+/// Moves the tool calls written in a reply's text into its `tool_calls`
+pub(crate) fn parse_reply(reply: Message) -> Message {
+    match reply {
+        Message::Assistant {
+            content: Some(Content::Text(text)),
+            mut tool_calls,
+            metadata,
+        } => {
+            let (rest, calls) = parse_calls(&text);
+            if calls.is_empty() {
+                return Message::Assistant {
+                    content: Some(Content::Text(text)),
+                    tool_calls,
+                    metadata,
+                };
+            }
+            tool_calls.extend(calls);
+            Message::Assistant {
+                content: (!rest.is_empty()).then_some(Content::Text(rest)),
+                tool_calls,
+                metadata,
+            }
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "ollama")]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "ollama")]
+    use crate::model::{Ollama, OllamaModelSize};
+    #[cfg(feature = "ollama")]
+    use crate::provider::ollama::OllamaProvider;
+    #[cfg(feature = "ollama")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+    #[cfg(feature = "ollama")]
+    use crate::{Error, ModelCapability, ModelInfo};
+    #[cfg(feature = "ollama")]
+    use std::sync::Arc;
+
+    #[cfg(feature = "ollama")]
+    fn reply(content: &str) -> MockResponse {
+        MockResponse::ok(
+            json!({
+                "model": "llama3",
+                "created_at": "2024-01-01T00:00:00Z",
+                "message": { "role": "assistant", "content": content },
+                "done": true
+            })
+            .to_string(),
+        )
+    }
+
+    #[cfg(feature = "ollama")]
+    fn weather() -> LlmToolInfo {
+        LlmToolInfo {
+            name: "get_weather".to_string(),
+            description: "Gets the current weather.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": { "location": { "type": "string" } }
+            }),
+        }
+    }
+
+    #[test]
+    fn test_malformed_calls_stay_in_the_text() {
+        let (rest, calls) = parse_calls("<tool_call>{oops}</tool_call> and <tool_call>");
+        assert_eq!(rest, "<tool_call>{oops}</tool_call> and <tool_call>");
+        assert!(calls.is_empty());
+
+        let (rest, calls) = parse_calls(&format!(
+            "{}\n{}",
+            render_call("a", "{}"),
+            render_call("b", "not json")
+        ));
+        assert_eq!(rest, "");
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[1].function.arguments, "{}");
+        assert_ne!(calls[0].id, calls[1].id);
+    }
+
+    #[cfg(feature = "ollama")]
+    #[tokio::test]
+    async fn test_tools_are_emulated_on_request() {
+        let model = Ollama::Llama3 {
+            size: OllamaModelSize::_8B,
+        };
+        assert!(!model.supports(ModelCapability::Tools));
+
+        let chat = Chat::default()
+            .with_tools(vec![weather()])
+            .add_message(Message::user("Weather in Paris?"));
+
+        let transport = Arc::new(MockTransport::new().with_fallback(reply("Sunny.")));
+        let service = HTTPLlmService::new_with_transport(
+            model,
+            Arc::new(OllamaProvider::default()),
+            transport.clone(),
+        );
+        assert!(matches!(
+            service.generate_next_message(&chat).await,
+            Err(Error::UnsupportedCapability { .. })
+        ));
+
+        let transport = Arc::new(MockTransport::new().with_responses([
+            reply(&format!(
+                "Checking.\n{}",
+                render_call("get_weather", r#"{"location":"Paris"}"#)
+            )),
+            reply("It's sunny in Paris."),
+        ]));
+        let service = HTTPLlmService::new_with_transport(
+            model,
+            Arc::new(OllamaProvider::default()),
+            transport.clone(),
+        )
+        .with_tool_emulation();
+
+        let message = service.generate_next_message(&chat).await.unwrap();
+        let Message::Assistant { tool_calls, .. } = &message else {
+            panic!("expected an assistant message");
+        };
+        assert_eq!(message.text_content(), "Checking.");
+        assert_eq!(tool_calls[0].function.name, "get_weather");
+
+        let chat = chat
+            .add_message(message.clone())
+            .add_message(Message::tool_from_call(&tool_calls[0], "22°C, sunny"));
+        let message = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(message.text_content(), "It's sunny in Paris.");
+
+        let requests = transport.requests();
+        let first: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert!(first.get("tools").is_none());
+        assert!(
+            first["system"]
+                .as_str()
+                .unwrap()
+                .contains("get_weather: Gets the current weather.")
+        );
+        let second: Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(
+            second["messages"][2],
+            json!({
+                "role": "user",
+                "content": "<tool_result name=\"get_weather\">22°C, sunny</tool_result>"
+            })
+        );
+    }
+}
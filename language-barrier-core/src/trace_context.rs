@@ -0,0 +1,346 @@
+//! W3C trace context on provider requests
+//!
+//! Requests sent through [`HTTPLlmService`] carry the caller's trace as
+//! [`traceparent` and `tracestate`] headers, so an LLM gateway's logs (or a
+//! provider's, if it records them) join the application's distributed
+//! trace.
+//!
+//! The context comes from, in order:
+//!
+//! 1. A [`TraceContext::scope`] around the call, for apps that forward the
+//!    `traceparent` of an inbound request without a tracing backend
+//! 2. The [`TraceContextSource`] registered with
+//!    [`set_trace_context_source`]. With the `otel` feature,
+//!    [`OpenTelemetrySource`] reads the current `tracing` span's
+//!    OpenTelemetry context.
+//!
+//! Requests made outside any trace are sent without the headers.
+//!
+//! # Examples
+//!
+//! ```
+//! use language_barrier_core::trace_context::{TraceContext, current};
+//!
+//! # tokio_test::block_on(async {
+//! let inbound = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+//! let context: TraceContext = inbound.parse().unwrap();
+//!
+//! context
+//!     .scope(async {
+//!         // Every provider request made in here carries `inbound`
+//!         assert_eq!(current().unwrap().traceparent(), inbound);
+//!     })
+//!     .await;
+//! # });
+//! ```
+//!
+//! [`HTTPLlmService`]: crate::HTTPLlmService
+//! [`traceparent` and `tracestate`]: https://www.w3.org/TR/trace-context/
+
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use http::header::HeaderName;
+use tracing::{trace, warn};
+
+use crate::transport::{HeaderValue, HttpRequest};
+
+/// The header carrying the trace and parent span IDs
+pub const TRACEPARENT: &str = "traceparent";
+/// The header carrying vendor-specific trace data
+pub const TRACESTATE: &str = "tracestate";
+
+static SOURCE: RwLock<Option<Arc<dyn TraceContextSource>>> = RwLock::new(None);
+
+tokio::task_local! {
+    static SCOPED: TraceContext;
+}
+
+/// The trace a request belongs to and the span it was made from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// The trace's ID; never zero
+    pub trace_id: u128,
+    /// The ID of the span the request is made from; never zero
+    pub parent_id: u64,
+    /// Whether the caller is recording the trace
+    pub sampled: bool,
+    /// The `tracestate` value to forward, if any
+    pub state: Option<String>,
+}
+
+impl TraceContext {
+    /// A sampled context for `trace_id` and `parent_id`, or `None` if
+    /// either is zero, which W3C reserves for "no trace"
+    pub fn new(trace_id: u128, parent_id: u64) -> Option<Self> {
+        (trace_id != 0 && parent_id != 0).then_some(Self {
+            trace_id,
+            parent_id,
+            sampled: true,
+            state: None,
+        })
+    }
+
+    /// Sets the `tracestate` value to forward
+    #[must_use]
+    pub fn with_state(self, state: impl Into<String>) -> Self {
+        Self {
+            state: Some(state.into()),
+            ..self
+        }
+    }
+
+    /// The `traceparent` header value
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.parent_id,
+            u8::from(self.sampled)
+        )
+    }
+
+    /// Adds the `traceparent` header to `request`, plus `tracestate` if
+    /// there's state to forward
+    ///
+    /// A state that isn't a valid header value is logged and skipped.
+    pub fn apply(&self, request: &mut HttpRequest) {
+        let traceparent = HeaderValue::from_str(&self.traceparent())
+            .expect("a formatted traceparent is a valid header value");
+        request
+            .headers
+            .insert(HeaderName::from_static(TRACEPARENT), traceparent);
+
+        if let Some(state) = &self.state {
+            match HeaderValue::from_str(state) {
+                Ok(value) => {
+                    request
+                        .headers
+                        .insert(HeaderName::from_static(TRACESTATE), value);
+                }
+                Err(e) => warn!("Skipping invalid tracestate {:?}: {}", state, e),
+            }
+        }
+    }
+
+    /// Runs `future` with this as the trace context of every provider
+    /// request it makes, ahead of any registered source
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        SCOPED.scope(self, future).await
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.traceparent())
+    }
+}
+
+/// A `traceparent` value that couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid traceparent {0:?}")]
+pub struct InvalidTraceparent(pub String);
+
+impl FromStr for TraceContext {
+    type Err = InvalidTraceparent;
+
+    /// Parses a `traceparent` header value
+    ///
+    /// Follows the W3C rules for versions: `ff` is invalid, and a later
+    /// version than `00` is read as `00` if its first four fields are.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidTraceparent(value.to_string());
+        let fields: Vec<&str> = value.trim().split('-').collect();
+        let [version, trace_id, parent_id, flags, rest @ ..] = fields.as_slice() else {
+            return Err(invalid());
+        };
+
+        let hex = |field: &str, len: usize| {
+            field.len() == len
+                && field
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        };
+        if !hex(version, 2) || !hex(trace_id, 32) || !hex(parent_id, 16) || !hex(flags, 2) {
+            return Err(invalid());
+        }
+        if *version == "ff" || (*version == "00" && !rest.is_empty()) {
+            return Err(invalid());
+        }
+
+        let flags = u8::from_str_radix(flags, 16).map_err(|_| invalid())?;
+        let trace_id = u128::from_str_radix(trace_id, 16).map_err(|_| invalid())?;
+        let parent_id = u64::from_str_radix(parent_id, 16).map_err(|_| invalid())?;
+        let mut context = Self::new(trace_id, parent_id).ok_or_else(invalid)?;
+        context.sampled = flags & 1 == 1;
+        Ok(context)
+    }
+}
+
+/// Supplies the trace context of the code making a request
+pub trait TraceContextSource: Send + Sync {
+    /// The current trace context, or `None` outside a trace
+    fn current(&self) -> Option<TraceContext>;
+}
+
+impl<F> TraceContextSource for F
+where
+    F: Fn() -> Option<TraceContext> + Send + Sync,
+{
+    fn current(&self) -> Option<TraceContext> {
+        self()
+    }
+}
+
+/// Registers the source every subsequent provider request reads its trace
+/// context from
+pub fn set_trace_context_source(source: impl TraceContextSource + 'static) {
+    *SOURCE
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Arc::new(source));
+}
+
+/// Stops reading trace context from a registered source
+pub fn clear_trace_context_source() {
+    *SOURCE
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+/// Returns the trace context a request made here would carry, if any
+pub fn current() -> Option<TraceContext> {
+    if let Ok(context) = SCOPED.try_with(TraceContext::clone) {
+        return Some(context);
+    }
+    let source = SOURCE
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    source.and_then(|source| source.current())
+}
+
+/// Adds the current trace context, if any, to an outgoing request
+pub(crate) fn propagate(request: &mut HttpRequest) {
+    if let Some(context) = current() {
+        trace!("Propagating trace context {}", context);
+        context.apply(request);
+    }
+}
+
+/// Reads the trace context from the current `tracing` span's OpenTelemetry
+/// context
+///
+/// Needs the `tracing-opentelemetry` layer in the subscriber; without it,
+/// spans carry no OpenTelemetry context and nothing is propagated.
+///
+/// ```no_run
+/// use language_barrier_core::trace_context::{OpenTelemetrySource, set_trace_context_source};
+///
+/// set_trace_context_source(OpenTelemetrySource);
+/// ```
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenTelemetrySource;
+
+#[cfg(feature = "otel")]
+impl TraceContextSource for OpenTelemetrySource {
+    fn current(&self) -> Option<TraceContext> {
+        use opentelemetry::trace::TraceContextExt;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let context = tracing::Span::current().context();
+        let span = context.span();
+        let span = span.span_context();
+        if !span.is_valid() {
+            return None;
+        }
+
+        let mut current = TraceContext::new(
+            u128::from_be_bytes(span.trace_id().to_bytes()),
+            u64::from_be_bytes(span.span_id().to_bytes()),
+        )?;
+        current.sampled = span.is_sampled();
+        let state = span.trace_state().header();
+        if !state.is_empty() {
+            current.state = Some(state);
+        }
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "openai")]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "openai")]
+    use crate::provider::openai::OpenAIProvider;
+    #[cfg(feature = "openai")]
+    use crate::transport::mock::{MockResponse, MockTransport};
+    #[cfg(feature = "openai")]
+    use crate::{Chat, Message, OpenAi};
+
+    const INBOUND: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_traceparent_round_trips() {
+        let context: TraceContext = INBOUND.parse().unwrap();
+        assert_eq!(context.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(context.parent_id, 0x00f067aa0ba902b7);
+        assert!(context.sampled);
+        assert_eq!(context.traceparent(), INBOUND);
+
+        let unsampled: TraceContext = INBOUND.replace("-01", "-00").parse().unwrap();
+        assert!(!unsampled.sampled);
+
+        // A later version is read by its first four fields
+        let future = "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra";
+        assert_eq!(future.parse::<TraceContext>().unwrap(), context);
+    }
+
+    #[test]
+    fn test_invalid_traceparents_rejected() {
+        for value in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert!(value.parse::<TraceContext>().is_err(), "{value:?}");
+        }
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_scope_reaches_provider_requests() {
+        let reply = r#"{"id":"1","object":"chat.completion","created":0,"model":"gpt-4o",
+            "choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#;
+        let transport = Arc::new(MockTransport::new().with_fallback(MockResponse::ok(reply)));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::new()),
+            transport.clone(),
+        );
+        let chat = Chat::default().add_message(Message::user("Hello"));
+        let context = INBOUND
+            .parse::<TraceContext>()
+            .unwrap()
+            .with_state("vendor=abc");
+
+        context
+            .scope(service.generate_next_message(&chat))
+            .await
+            .unwrap();
+        service.generate_next_message(&chat).await.unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].headers[TRACEPARENT], INBOUND);
+        assert_eq!(requests[0].headers[TRACESTATE], "vendor=abc");
+        assert!(!requests[1].headers.contains_key(TRACEPARENT));
+    }
+}
@@ -0,0 +1,539 @@
+//! A scriptable transport for testing without a network
+//!
+//! [`MockTransport`] answers requests from a script of [`MockResponse`]s, in
+//! order. Responses can be delayed, streamed in chunks, or replaced with the
+//! failures real providers produce (timeouts, rate limits, truncated JSON),
+//! so retry, hedging and timeout logic can be exercised deterministically.
+//!
+//! Delays use `tokio::time`, so tests that pause the clock (`start_paused`)
+//! run instantly and see exactly the scripted timings.
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use language_barrier_core::llm_service::{HTTPLlmService, LLMService};
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::transport::mock::{MockResponse, MockTransport};
+//! use language_barrier_core::{ApiErrorKind, Chat, Message, OpenAi};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let reply = r#"{"id":"1","object":"chat.completion","created":0,"model":"gpt-4o",
+//!     "choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#;
+//! let transport = Arc::new(
+//!     MockTransport::new()
+//!         .with_response(MockResponse::rate_limited(Some(Duration::from_secs(1))))
+//!         .with_response(MockResponse::ok(reply)),
+//! );
+//! let service = HTTPLlmService::new_with_transport(
+//!     OpenAi::GPT4o,
+//!     Arc::new(OpenAIProvider::new()),
+//!     transport.clone(),
+//! );
+//!
+//! let chat = Chat::default().add_message(Message::user("Hello"));
+//! let error = service.generate_next_message(&chat).await.unwrap_err();
+//! let api = error.api().unwrap();
+//! assert_eq!(api.kind, ApiErrorKind::RateLimit);
+//! assert_eq!(api.retry_after(), Some(Duration::from_secs(1)));
+//! let message = service.generate_next_message(&chat).await.unwrap();
+//! assert_eq!(message.text_content(), "Hi");
+//! assert_eq!(transport.requests().len(), 2);
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::{debug, trace};
+
+use super::{HeaderMap, HeaderValue, HttpRequest, HttpResponse, SizeLimits, Transport};
+use crate::error::{Error, Result};
+
+/// How long a simulated step takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delay {
+    /// Always the same duration
+    Fixed(Duration),
+    /// A duration drawn uniformly from `min..=max`
+    Uniform {
+        /// The shortest delay
+        min: Duration,
+        /// The longest delay
+        max: Duration,
+    },
+}
+
+impl Delay {
+    /// No delay at all
+    pub const NONE: Delay = Delay::Fixed(Duration::ZERO);
+
+    fn sample(&self, rng: &mut SplitMix64) -> Duration {
+        match *self {
+            Delay::Fixed(duration) => duration,
+            Delay::Uniform { min, max } if max <= min => min,
+            Delay::Uniform { min, max } => {
+                let span = (max - min).as_nanos() as u64;
+                min + Duration::from_nanos(rng.next() % (span + 1))
+            }
+        }
+    }
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+impl From<Duration> for Delay {
+    fn from(duration: Duration) -> Self {
+        Self::Fixed(duration)
+    }
+}
+
+/// What a scripted response does
+#[derive(Debug, Clone, PartialEq)]
+enum Outcome {
+    /// Returns a response whose body arrives in these chunks
+    Reply {
+        status: u16,
+        headers: HeaderMap,
+        chunks: Vec<String>,
+        chunk_delay: Delay,
+    },
+    /// Fails with `Error::Timeout` after the response's delay
+    Timeout,
+    /// Fails as if the connection dropped
+    ConnectionError(String),
+}
+
+/// One scripted answer from a [`MockTransport`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockResponse {
+    outcome: Outcome,
+    delay: Option<Delay>,
+}
+
+impl MockResponse {
+    /// A `200 OK` response with `body`
+    pub fn ok(body: impl Into<String>) -> Self {
+        Self::status(200, body)
+    }
+
+    /// A response with any status code
+    pub fn status(status: u16, body: impl Into<String>) -> Self {
+        Self::streamed(status, [body.into()])
+    }
+
+    /// A response whose body arrives in `chunks`
+    ///
+    /// The transport waits [`with_chunk_delay`](Self::with_chunk_delay)
+    /// before each chunk after the first, so the response's time to first
+    /// byte and total time differ the way they do for a streamed reply.
+    pub fn streamed(status: u16, chunks: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::from_outcome(Outcome::Reply {
+            status,
+            headers: json_headers(),
+            chunks: chunks.into_iter().map(Into::into).collect(),
+            chunk_delay: Delay::NONE,
+        })
+    }
+
+    /// A `429 Too Many Requests` response, with a `Retry-After` header if
+    /// `retry_after` is set
+    pub fn rate_limited(retry_after: Option<Duration>) -> Self {
+        let body = r#"{"error":{"message":"Rate limit exceeded","type":"rate_limit_error"}}"#;
+        let response = Self::status(429, body);
+        match retry_after {
+            Some(retry_after) => {
+                response.with_header("retry-after", &retry_after.as_secs().to_string())
+            }
+            None => response,
+        }
+    }
+
+    /// A `200 OK` response whose body is cut off mid-JSON
+    pub fn malformed_json() -> Self {
+        Self::ok(r#"{"id":"truncated","choices":[{"message":{"role":"assist"#)
+    }
+
+    /// A request that fails with `Error::Timeout`
+    ///
+    /// The failure arrives after the response's delay, so give it one with
+    /// [`with_delay`](Self::with_delay) to model how long the caller waited.
+    pub fn timeout() -> Self {
+        Self::from_outcome(Outcome::Timeout)
+    }
+
+    /// A request that fails before any response arrives
+    pub fn connection_error(message: impl Into<String>) -> Self {
+        Self::from_outcome(Outcome::ConnectionError(message.into()))
+    }
+
+    /// Sets how long this response takes to start, overriding the
+    /// transport's default latency
+    pub fn with_delay(self, delay: impl Into<Delay>) -> Self {
+        Self {
+            delay: Some(delay.into()),
+            ..self
+        }
+    }
+
+    /// Sets the pause between body chunks of a streamed response
+    pub fn with_chunk_delay(self, delay: impl Into<Delay>) -> Self {
+        let outcome = match self.outcome {
+            Outcome::Reply {
+                status,
+                headers,
+                chunks,
+                ..
+            } => Outcome::Reply {
+                status,
+                headers,
+                chunks,
+                chunk_delay: delay.into(),
+            },
+            other => other,
+        };
+        Self { outcome, ..self }
+    }
+
+    /// Adds a response header
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` isn't a valid header.
+    pub fn with_header(self, name: &'static str, value: &str) -> Self {
+        let outcome = match self.outcome {
+            Outcome::Reply {
+                status,
+                mut headers,
+                chunks,
+                chunk_delay,
+            } => {
+                let value = HeaderValue::from_str(value).expect("invalid header value");
+                headers.insert(name, value);
+                Outcome::Reply {
+                    status,
+                    headers,
+                    chunks,
+                    chunk_delay,
+                }
+            }
+            other => other,
+        };
+        Self { outcome, ..self }
+    }
+
+    fn from_outcome(outcome: Outcome) -> Self {
+        Self {
+            outcome,
+            delay: None,
+        }
+    }
+}
+
+fn json_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_static("application/json"));
+    headers
+}
+
+/// A [`Transport`] that replays scripted responses
+///
+/// Each request takes the next response in the script. Once the script runs
+/// out, requests get the fallback response if one was set, and fail with
+/// `Error::Other` otherwise. Every request is recorded for inspection.
+///
+/// Random delays come from a seeded generator, so a given seed always
+/// produces the same timings.
+#[derive(Debug)]
+pub struct MockTransport {
+    script: Mutex<VecDeque<MockResponse>>,
+    fallback: Option<MockResponse>,
+    latency: Delay,
+    limits: SizeLimits,
+    rng: Mutex<SplitMix64>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    /// Creates a transport with an empty script and no latency
+    pub fn new() -> Self {
+        Self {
+            script: Mutex::new(VecDeque::new()),
+            fallback: None,
+            latency: Delay::NONE,
+            limits: SizeLimits::default(),
+            rng: Mutex::new(SplitMix64(0)),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Appends a response to the script
+    pub fn with_response(self, response: MockResponse) -> Self {
+        lock(&self.script).push_back(response);
+        self
+    }
+
+    /// Appends several responses to the script
+    pub fn with_responses(self, responses: impl IntoIterator<Item = MockResponse>) -> Self {
+        lock(&self.script).extend(responses);
+        self
+    }
+
+    /// Sets the response used once the script runs out
+    pub fn with_fallback(self, response: MockResponse) -> Self {
+        Self {
+            fallback: Some(response),
+            ..self
+        }
+    }
+
+    /// Sets the delay before responses that don't set their own
+    pub fn with_latency(self, latency: impl Into<Delay>) -> Self {
+        Self {
+            latency: latency.into(),
+            ..self
+        }
+    }
+
+    /// Enforces size limits the way [`ReqwestTransport`] does, checking the
+    /// body after each chunk
+    ///
+    /// [`ReqwestTransport`]: super::ReqwestTransport
+    pub fn with_limits(self, limits: SizeLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Seeds the generator used for [`Delay::Uniform`]
+    pub fn with_seed(self, seed: u64) -> Self {
+        *lock(&self.rng) = SplitMix64(seed);
+        self
+    }
+
+    /// Returns every request received so far, oldest first
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        lock(&self.requests).clone()
+    }
+
+    /// Returns how many scripted responses haven't been used yet
+    pub fn remaining(&self) -> usize {
+        lock(&self.script).len()
+    }
+
+    fn sample(&self, delay: Delay) -> Duration {
+        delay.sample(&mut lock(&self.rng))
+    }
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        debug!("Mock transport received {} {}", request.method, request.url);
+        self.limits.check_request(&request)?;
+        lock(&self.requests).push(request);
+
+        let next = lock(&self.script).pop_front();
+        let Some(response) = next.or_else(|| self.fallback.clone()) else {
+            return Err(Error::Other(
+                "Mock transport has no more scripted responses".to_string(),
+            ));
+        };
+
+        let started = Instant::now();
+        let delay = self.sample(response.delay.unwrap_or(self.latency));
+        tokio::time::sleep(delay).await;
+
+        match response.outcome {
+            Outcome::Reply {
+                status,
+                headers,
+                chunks,
+                chunk_delay,
+            } => {
+                let time_to_first_byte = started.elapsed();
+                let mut body = String::new();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    if i > 0 {
+                        tokio::time::sleep(self.sample(chunk_delay)).await;
+                    }
+                    trace!("Mock transport chunk: {}", chunk);
+                    body.push_str(chunk);
+                    self.limits.check_response(body.len())?;
+                }
+                debug!("Mock transport replying with status {}", status);
+                Ok(HttpResponse {
+                    status,
+                    headers,
+                    body,
+                    time_to_first_byte: Some(time_to_first_byte),
+                })
+            }
+            Outcome::Timeout => {
+                debug!("Mock transport timing out after {:?}", delay);
+                Err(Error::Timeout(format!("no response after {delay:?}")))
+            }
+            Outcome::ConnectionError(message) => {
+                debug!("Mock transport failing: {}", message);
+                Err(Error::ProviderUnavailable(message))
+            }
+        }
+    }
+}
+
+/// Locks a mutex, ignoring poisoning from a panicked test
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A small deterministic generator for sampling delays
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Method;
+    use url::Url;
+
+    fn request() -> HttpRequest {
+        HttpRequest::new(
+            Method::POST,
+            Url::parse("https://api.example.com/v1/chat").unwrap(),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_script_plays_in_order_then_falls_back() {
+        let transport = MockTransport::new()
+            .with_responses([MockResponse::ok("first"), MockResponse::status(500, "oops")])
+            .with_fallback(MockResponse::ok("fallback"));
+
+        assert_eq!(transport.send(request()).await.unwrap().body, "first");
+        let failed = transport.send(request()).await.unwrap();
+        assert_eq!((failed.status, failed.body.as_str()), (500, "oops"));
+        assert_eq!(transport.remaining(), 0);
+        assert_eq!(transport.send(request()).await.unwrap().body, "fallback");
+        assert_eq!(transport.requests().len(), 3);
+
+        let empty = MockTransport::new();
+        assert!(matches!(empty.send(request()).await, Err(Error::Other(_))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_streamed_chunks_and_latency() {
+        let transport = MockTransport::new()
+            .with_latency(Duration::from_millis(100))
+            .with_response(
+                MockResponse::streamed(200, ["{\"a\":", "1}"])
+                    .with_chunk_delay(Duration::from_millis(50)),
+            );
+
+        let started = Instant::now();
+        let response = transport.send(request()).await.unwrap();
+        assert_eq!(response.body, "{\"a\":1}");
+        assert_eq!(
+            response.time_to_first_byte,
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(started.elapsed(), Duration::from_millis(150));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failures() {
+        let transport = MockTransport::new().with_responses([
+            MockResponse::timeout().with_delay(Duration::from_secs(30)),
+            MockResponse::rate_limited(Some(Duration::from_secs(2))),
+            MockResponse::connection_error("reset by peer"),
+            MockResponse::malformed_json(),
+        ]);
+
+        let started = Instant::now();
+        assert!(matches!(
+            transport.send(request()).await,
+            Err(Error::Timeout(_))
+        ));
+        assert_eq!(started.elapsed(), Duration::from_secs(30));
+
+        let limited = transport.send(request()).await.unwrap();
+        assert_eq!(limited.status, 429);
+        assert_eq!(limited.headers["retry-after"], "2");
+
+        assert!(matches!(
+            transport.send(request()).await,
+            Err(Error::ProviderUnavailable(_))
+        ));
+
+        let malformed = transport.send(request()).await.unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(&malformed.body).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limits_stop_oversized_traffic() {
+        let limits = SizeLimits::new()
+            .with_max_request_bytes(8)
+            .with_max_response_bytes(10);
+        let transport = MockTransport::new()
+            .with_limits(limits)
+            .with_fallback(MockResponse::streamed(200, ["0123456", "789", "abc"]));
+
+        let oversized = HttpRequest {
+            body: b"0123456789".to_vec(),
+            ..request()
+        };
+        assert!(matches!(
+            transport.send(oversized).await,
+            Err(Error::RequestTooLarge { size: 10, limit: 8 })
+        ));
+        assert!(transport.requests().is_empty());
+
+        assert!(matches!(
+            transport.send(request()).await,
+            Err(Error::ResponseTooLarge { limit: 10 })
+        ));
+    }
+
+    #[test]
+    fn test_uniform_delay_is_seeded_and_bounded() {
+        let delay = Delay::Uniform {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+        };
+        let samples = |seed| {
+            let transport = MockTransport::new().with_seed(seed);
+            (0..20).map(|_| transport.sample(delay)).collect::<Vec<_>>()
+        };
+
+        let first = samples(7);
+        assert_eq!(first, samples(7));
+        assert_ne!(first, samples(8));
+        assert!(first.iter().all(|d| *d >= Duration::from_millis(10)));
+        assert!(first.iter().all(|d| *d <= Duration::from_millis(20)));
+    }
+}
@@ -0,0 +1,470 @@
+//! Sending provider requests over the wire
+//!
+//! Providers describe each call as a plain [`HttpRequest`], and a
+//! [`Transport`] sends it. The default [`ReqwestTransport`] uses `reqwest`;
+//! implement `Transport` to use another HTTP stack (hyper, a wasm `fetch`
+//! binding), or use [`mock::MockTransport`] to stub providers out in tests.
+//! [`rewrite::RewritingTransport`] adapts requests for an API gateway.
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use tracing::{debug, trace};
+use url::Url;
+
+use crate::error::{Error, Result};
+
+pub mod mock;
+pub mod rewrite;
+
+pub use http::{HeaderMap, HeaderValue, Method};
+
+/// An HTTP request built by a provider
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    /// The HTTP method
+    pub method: Method,
+    /// The full request URL, including any query parameters
+    pub url: Url,
+    /// The request headers
+    pub headers: HeaderMap,
+    /// The request body, usually JSON
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Creates a request with no headers and an empty body
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::transport::{HttpRequest, Method};
+    /// use url::Url;
+    ///
+    /// let url = Url::parse("https://api.example.com/v1/chat").unwrap();
+    /// let request = HttpRequest::new(Method::POST, url);
+    /// assert!(request.body.is_empty());
+    /// ```
+    pub fn new(method: Method, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+/// An HTTP response as returned by a [`Transport`]
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// The HTTP status code
+    pub status: u16,
+    /// The response headers
+    pub headers: HeaderMap,
+    /// The response body
+    pub body: String,
+    /// How long the server took to start responding, if the transport
+    /// measured it
+    pub time_to_first_byte: Option<Duration>,
+}
+
+/// Upper bounds on request and response sizes
+///
+/// Both limits are off by default. A request over its limit fails with
+/// `Error::RequestTooLarge` before anything is sent; a response fails with
+/// `Error::ResponseTooLarge` as soon as its declared length or the bytes
+/// read so far pass the limit, so an oversized body is never fully
+/// buffered.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::transport::{ReqwestTransport, SizeLimits};
+///
+/// let transport = ReqwestTransport::new().with_limits(
+///     SizeLimits::new()
+///         .with_max_request_bytes(2 * 1024 * 1024)
+///         .with_max_response_bytes(16 * 1024 * 1024),
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// Largest request body to send, in bytes
+    pub max_request_bytes: Option<usize>,
+    /// Largest response body to accept, in bytes
+    pub max_response_bytes: Option<usize>,
+}
+
+impl SizeLimits {
+    /// Creates limits that allow any size
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits request bodies to `bytes`
+    #[must_use]
+    pub fn with_max_request_bytes(self, bytes: usize) -> Self {
+        Self {
+            max_request_bytes: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Limits response bodies to `bytes`
+    #[must_use]
+    pub fn with_max_response_bytes(self, bytes: usize) -> Self {
+        Self {
+            max_response_bytes: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Checks a request against the request limit
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::RequestTooLarge` if the body is over the limit.
+    pub fn check_request(&self, request: &HttpRequest) -> Result<()> {
+        match self.max_request_bytes {
+            Some(limit) if request.body.len() > limit => Err(Error::RequestTooLarge {
+                size: request.body.len(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks how much of a response has been received (or declared)
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ResponseTooLarge` if `bytes` is over the limit.
+    pub fn check_response(&self, bytes: usize) -> Result<()> {
+        match self.max_response_bytes {
+            Some(limit) if bytes > limit => Err(Error::ResponseTooLarge { limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Sends provider requests and returns the raw responses
+///
+/// A transport only moves bytes: it doesn't interpret status codes or
+/// bodies, which is the provider's job.
+///
+/// # Examples
+///
+/// ```
+/// use async_trait::async_trait;
+/// use language_barrier_core::Result;
+/// use language_barrier_core::transport::{HeaderMap, HttpRequest, HttpResponse, Transport};
+///
+/// /// Answers every request with the same body
+/// struct Canned(String);
+///
+/// #[async_trait]
+/// impl Transport for Canned {
+///     async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+///         Ok(HttpResponse {
+///             status: 200,
+///             headers: HeaderMap::new(),
+///             body: self.0.clone(),
+///             time_to_first_byte: None,
+///         })
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Sends a request and reads the whole response
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request couldn't be sent or the response
+    /// couldn't be read.
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+}
+
+/// The default transport, backed by a `reqwest` client
+///
+/// The client (and its connection pool) is reused for every request, and
+/// clones share it.
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+    limits: SizeLimits,
+}
+
+impl ReqwestTransport {
+    /// Creates a transport with its own default client
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enforces request and response size limits
+    pub fn with_limits(self, limits: SizeLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Returns the underlying client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl From<Client> for ReqwestTransport {
+    fn from(client: Client) -> Self {
+        Self {
+            client,
+            limits: SizeLimits::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        self.limits.check_request(&request)?;
+        let mut outgoing = reqwest::Request::new(request.method, request.url);
+        *outgoing.headers_mut() = request.headers;
+        *outgoing.body_mut() = Some(request.body.into());
+
+        let started = Instant::now();
+        let response = self.client.execute(outgoing).await.map_err(|e| {
+            if e.is_timeout() {
+                Error::Timeout(e.to_string())
+            } else {
+                Error::Request(e)
+            }
+        })?;
+        let time_to_first_byte = started.elapsed();
+        debug!("Received response with status: {}", response.status());
+        trace!("Response headers: {:#?}", response.headers());
+
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let body = match self.limits.max_response_bytes {
+            None => response.text().await?,
+            Some(_) => read_limited(response, &self.limits).await?,
+        };
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+            time_to_first_byte: Some(time_to_first_byte),
+        })
+    }
+}
+
+/// Reads a response body chunk by chunk, giving up once it passes the
+/// response limit
+async fn read_limited(mut response: reqwest::Response, limits: &SizeLimits) -> Result<String> {
+    if let Some(declared) = response.content_length() {
+        limits.check_response(usize::try_from(declared).unwrap_or(usize::MAX))?;
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        limits.check_response(body.len())?;
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use crate::llm_service::{HTTPLlmService, LLMService};
+    #[cfg(feature = "openai")]
+    use crate::provider::openai::{OpenAIConfig, OpenAIProvider};
+    #[cfg(feature = "openai")]
+    use crate::OpenAi;
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use crate::{Chat, Message};
+    #[cfg(any(feature = "openai", feature = "anthropic"))]
+    use std::sync::Arc;
+    #[cfg(feature = "openai")]
+    use std::sync::Mutex;
+
+    /// Records requests and answers each with a fixed chat completion
+    #[cfg(feature = "openai")]
+    #[derive(Default)]
+    struct Recording {
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    #[cfg(feature = "openai")]
+    #[async_trait]
+    impl Transport for Recording {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            self.requests.lock().unwrap().push(request);
+            let body = serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-4o",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hi there"},
+                    "finish_reason": "stop"
+                }]
+            });
+            Ok(HttpResponse {
+                status: 200,
+                headers: HeaderMap::new(),
+                body: body.to_string(),
+                time_to_first_byte: None,
+            })
+        }
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_service_sends_through_custom_transport() {
+        let transport = Arc::new(Recording::default());
+        let provider = OpenAIProvider::with_config(OpenAIConfig {
+            api_key: "sk-test".to_string(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            organization: None,
+            auth: None,
+            signer: None,
+        });
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(provider),
+            transport.clone(),
+        );
+
+        let chat = Chat::default().add_message(Message::user("Hello"));
+        let reply = service.generate_next_message(&chat).await.unwrap();
+        assert_eq!(reply.text_content(), "Hi there");
+        assert!(reply.latency().is_some());
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, Method::POST);
+        assert_eq!(
+            requests[0].url.as_str(),
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(requests[0].headers["Authorization"], "Bearer sk-test");
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["messages"][0]["content"], "Hello");
+    }
+
+    #[cfg(feature = "openai")]
+    #[tokio::test]
+    async fn test_service_classifies_error_responses() {
+        use crate::ApiErrorKind;
+        use crate::transport::mock::{MockResponse, MockTransport};
+
+        let context_error = r#"{"error":{"message":"This model's maximum context length is 128000 tokens.",
+            "type":"invalid_request_error","code":"context_length_exceeded"}}"#;
+        let transport = MockTransport::new()
+            .with_response(
+                MockResponse::status(400, context_error).with_header("x-request-id", "req_123"),
+            )
+            .with_response(MockResponse::status(503, "upstream connect error"));
+        let service = HTTPLlmService::new_with_transport(
+            OpenAi::GPT4o,
+            Arc::new(OpenAIProvider::default()),
+            Arc::new(transport),
+        );
+        let chat = Chat::default().add_message(Message::user("Hello"));
+
+        let error = service.generate_next_message(&chat).await.unwrap_err();
+        let api = error.api().unwrap();
+        assert_eq!(api.kind, ApiErrorKind::ContextLengthExceeded);
+        assert_eq!(api.status, 400);
+        assert!(
+            api.message
+                .starts_with("This model's maximum context length")
+        );
+        assert_eq!(api.body, context_error);
+        assert_eq!(api.request_id(), Some("req_123"));
+
+        // Bodies the provider can't parse keep the status classification
+        let error = service.generate_next_message(&chat).await.unwrap_err();
+        let api = error.api().unwrap();
+        assert_eq!(api.kind, ApiErrorKind::Server);
+        assert!(api.kind.is_retryable());
+        assert_eq!(api.message, "upstream connect error");
+        assert_eq!(api.provider, Some(crate::model::Provider::OpenAi));
+    }
+
+    #[cfg(feature = "anthropic")]
+    #[tokio::test]
+    async fn test_service_classifies_anthropic_overload() {
+        use crate::ApiErrorKind;
+        use crate::Claude;
+        use crate::provider::anthropic::AnthropicProvider;
+        use crate::transport::mock::{MockResponse, MockTransport};
+
+        let overloaded =
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let transport = MockTransport::new()
+            .with_response(MockResponse::status(529, overloaded))
+            .with_response(MockResponse::status(500, overloaded));
+        let service = HTTPLlmService::new_with_transport(
+            Claude::Haiku35,
+            Arc::new(AnthropicProvider::new()),
+            Arc::new(transport),
+        );
+        let chat = Chat::default().add_message(Message::user("Hello"));
+
+        // By status, and by the error type whatever the status
+        for status in [529, 500] {
+            let error = service.generate_next_message(&chat).await.unwrap_err();
+            let api = error.api().unwrap();
+            assert_eq!(api.status, status);
+            assert_eq!(api.kind, ApiErrorKind::Overloaded);
+            assert!(api.kind.is_retryable());
+            assert_eq!(api.message, "Overloaded");
+            assert_eq!(api.provider, Some(crate::model::Provider::Anthropic));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_transport_abandons_oversized_responses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // Serves a chunked body with no Content-Length, so the limit can
+        // only be enforced while reading
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 4096];
+                let _ = socket.read(&mut buf).await;
+                let chunk = "x".repeat(1024);
+                let mut reply = String::from(
+                    "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n",
+                );
+                for _ in 0..8 {
+                    reply.push_str(&format!("{:x}\r\n{chunk}\r\n", chunk.len()));
+                }
+                reply.push_str("0\r\n\r\n");
+                let _ = socket.write_all(reply.as_bytes()).await;
+            }
+        });
+
+        let limited =
+            ReqwestTransport::new().with_limits(SizeLimits::new().with_max_response_bytes(4096));
+        assert!(matches!(
+            limited
+                .send(HttpRequest::new(Method::GET, url.clone()))
+                .await,
+            Err(Error::ResponseTooLarge { limit: 4096 })
+        ));
+
+        let unlimited = ReqwestTransport::new();
+        let response = unlimited
+            .send(HttpRequest::new(Method::GET, url))
+            .await
+            .unwrap();
+        assert_eq!(response.body.len(), 8 * 1024);
+    }
+}
@@ -0,0 +1,149 @@
+//! Rewriting requests on their way out
+//!
+//! Traffic that must pass through an internal LLM gateway usually needs a
+//! different host or path prefix and a few extra headers. Rather than
+//! forking a provider, wrap the transport in a [`RewritingTransport`] (or
+//! call [`HTTPLlmService::with_request_rewriter`]) with a
+//! [`RequestRewriter`]: any `Fn(&mut HttpRequest) -> Result<()>` closure.
+//!
+//! The rewriter sees the request exactly as it will be sent, after the
+//! provider has added its authentication, so it can also strip or replace
+//! credentials the gateway handles itself.
+//!
+//! # Examples
+//!
+#![cfg_attr(feature = "openai", doc = "```")]
+#![cfg_attr(not(feature = "openai"), doc = "```ignore")]
+//! use std::sync::Arc;
+//! use language_barrier_core::llm_service::HTTPLlmService;
+//! use language_barrier_core::provider::openai::OpenAIProvider;
+//! use language_barrier_core::transport::{HeaderValue, HttpRequest};
+//! use language_barrier_core::{OpenAi, Result};
+//!
+//! let service = HTTPLlmService::new(OpenAi::GPT4o, Arc::new(OpenAIProvider::new()))
+//!     .with_request_rewriter(|request: &mut HttpRequest| -> Result<()> {
+//!         let path = format!("/llm/openai{}", request.url.path());
+//!         request.url.set_path(&path);
+//!         request.url.set_host(Some("gateway.internal.example.com"))?;
+//!         request.headers.insert("x-cost-center", HeaderValue::from_static("research"));
+//!         Ok(())
+//!     });
+//! ```
+//!
+//! [`HTTPLlmService::with_request_rewriter`]: crate::HTTPLlmService::with_request_rewriter
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::trace;
+
+use super::{HttpRequest, HttpResponse, Transport};
+use crate::error::Result;
+
+/// Changes an outgoing request before it's sent
+pub trait RequestRewriter: Send + Sync {
+    /// Rewrites `request` in place
+    ///
+    /// # Errors
+    ///
+    /// An error aborts the call and is returned to the caller.
+    fn rewrite(&self, request: &mut HttpRequest) -> Result<()>;
+}
+
+impl<F> RequestRewriter for F
+where
+    F: Fn(&mut HttpRequest) -> Result<()> + Send + Sync,
+{
+    fn rewrite(&self, request: &mut HttpRequest) -> Result<()> {
+        self(request)
+    }
+}
+
+/// A transport that rewrites every request before passing it on
+#[derive(Clone)]
+pub struct RewritingTransport {
+    inner: Arc<dyn Transport>,
+    rewriter: Arc<dyn RequestRewriter>,
+}
+
+impl RewritingTransport {
+    /// Wraps `inner`, rewriting requests with `rewriter`
+    pub fn new(inner: Arc<dyn Transport>, rewriter: impl RequestRewriter + 'static) -> Self {
+        Self {
+            inner,
+            rewriter: Arc::new(rewriter),
+        }
+    }
+}
+
+impl fmt::Debug for RewritingTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RewritingTransport").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Transport for RewritingTransport {
+    async fn send(&self, mut request: HttpRequest) -> Result<HttpResponse> {
+        self.rewriter.rewrite(&mut request)?;
+        trace!("Rewrote request to {} {}", request.method, request.url);
+        self.inner.send(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use crate::transport::mock::{MockResponse, MockTransport};
+    use crate::transport::{HeaderValue, Method};
+    use url::Url;
+
+    #[tokio::test]
+    async fn test_requests_are_rewritten_before_sending() {
+        let mock = Arc::new(MockTransport::new().with_fallback(MockResponse::ok("{}")));
+        let transport = RewritingTransport::new(mock.clone(), |request: &mut HttpRequest| {
+            request.url = Url::parse("https://gateway.example.com/openai/chat/completions")?;
+            request.headers.remove("Authorization");
+            request
+                .headers
+                .insert("x-gateway-key", HeaderValue::from_static("gw-123"));
+            Ok(())
+        });
+
+        let mut request = HttpRequest::new(
+            Method::POST,
+            Url::parse("https://api.openai.com/v1/chat/completions").unwrap(),
+        );
+        request
+            .headers
+            .insert("Authorization", HeaderValue::from_static("Bearer sk"));
+        transport.send(request).await.unwrap();
+
+        let sent = &mock.requests()[0];
+        assert_eq!(
+            sent.url.as_str(),
+            "https://gateway.example.com/openai/chat/completions"
+        );
+        assert!(!sent.headers.contains_key("Authorization"));
+        assert_eq!(sent.headers["x-gateway-key"], "gw-123");
+    }
+
+    #[tokio::test]
+    async fn test_rewriter_errors_abort_the_call() {
+        let mock = Arc::new(MockTransport::new().with_fallback(MockResponse::ok("{}")));
+        let transport = RewritingTransport::new(mock.clone(), |_: &mut HttpRequest| {
+            Err(Error::Other("gateway not configured".into()))
+        });
+
+        let url = Url::parse("https://api.openai.com/v1/chat/completions").unwrap();
+        assert!(
+            transport
+                .send(HttpRequest::new(Method::POST, url))
+                .await
+                .is_err()
+        );
+        assert!(mock.requests().is_empty());
+    }
+}
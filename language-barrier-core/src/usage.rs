@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+use std::time::Duration;
+
+/// Token usage reported by a provider for a single response
+///
+/// Providers report usage in different shapes; each one maps its own fields
+/// onto this struct and stores it on the assistant message under
+/// [`Usage::METADATA_KEY`]. Counts a provider doesn't report are zero.
+///
+/// `Usage` values add together, which is how [`Chat::usage_totals`] builds a
+/// per-conversation total.
+///
+/// [`Chat::usage_totals`]: crate::chat::Chat::usage_totals
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::Usage;
+///
+/// let first = Usage::new(100, 20);
+/// let second = Usage::new(150, 30).with_cached_tokens(100);
+///
+/// let total: Usage = [first, second].into_iter().sum();
+/// assert_eq!(total.input_tokens, 250);
+/// assert_eq!(total.output_tokens, 50);
+/// assert_eq!(total.cached_tokens, 100);
+/// assert_eq!(total.total_tokens(), 300);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    /// Tokens sent to the model, including any served from the prompt cache
+    #[serde(default)]
+    pub input_tokens: u64,
+    /// Tokens generated by the model, including any reasoning tokens
+    #[serde(default)]
+    pub output_tokens: u64,
+    /// Input tokens that were read from the provider's prompt cache
+    #[serde(default)]
+    pub cached_tokens: u64,
+    /// Output tokens spent on hidden reasoning
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+}
+
+impl Usage {
+    /// The message metadata key under which providers store typed usage
+    pub const METADATA_KEY: &'static str = "usage";
+
+    /// Creates usage from input and output token counts
+    #[must_use]
+    pub fn new(input_tokens: u64, output_tokens: u64) -> Self {
+        Self {
+            input_tokens,
+            output_tokens,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the number of input tokens served from the prompt cache
+    #[must_use]
+    pub fn with_cached_tokens(self, cached_tokens: u64) -> Self {
+        Self {
+            cached_tokens,
+            ..self
+        }
+    }
+
+    /// Sets the number of output tokens spent on reasoning
+    #[must_use]
+    pub fn with_reasoning_tokens(self, reasoning_tokens: u64) -> Self {
+        Self {
+            reasoning_tokens,
+            ..self
+        }
+    }
+
+    /// Returns input plus output tokens
+    #[must_use]
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// Returns true if no tokens were recorded
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Converts this usage into a metadata value
+    #[must_use]
+    pub fn to_metadata(&self) -> serde_json::Value {
+        // A struct of plain integers always serializes
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    /// Returns what this usage costs at the given prices, in US dollars
+    ///
+    /// Cached input tokens are charged at the cached rate when the pricing
+    /// has one, and at the normal input rate otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use language_barrier_core::usage::{Pricing, Usage};
+    ///
+    /// let pricing = Pricing::new(3.0, 15.0).with_cached_input(0.3);
+    /// let usage = Usage::new(1_000_000, 100_000).with_cached_tokens(500_000);
+    ///
+    /// assert!((usage.cost(&pricing) - 3.15).abs() < 1e-9);
+    /// ```
+    #[must_use]
+    pub fn cost(&self, pricing: &Pricing) -> f64 {
+        let cached = self.cached_tokens.min(self.input_tokens);
+        let uncached = self.input_tokens - cached;
+        let cached_rate = pricing
+            .cached_input_per_million
+            .unwrap_or(pricing.input_per_million);
+
+        (uncached as f64 * pricing.input_per_million
+            + cached as f64 * cached_rate
+            + self.output_tokens as f64 * pricing.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Token prices for a model, in US dollars per million tokens
+///
+/// The library doesn't ship a price list, since prices change more often
+/// than releases; fill this in from the provider's pricing page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    /// Price of a million input tokens
+    pub input_per_million: f64,
+    /// Price of a million output tokens
+    pub output_per_million: f64,
+    /// Price of a million input tokens read from the prompt cache
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub cached_input_per_million: Option<f64>,
+}
+
+impl Pricing {
+    /// Creates pricing from input and output prices per million tokens
+    #[must_use]
+    pub fn new(input_per_million: f64, output_per_million: f64) -> Self {
+        Self {
+            input_per_million,
+            output_per_million,
+            cached_input_per_million: None,
+        }
+    }
+
+    /// Sets the price per million cached input tokens
+    #[must_use]
+    pub fn with_cached_input(self, cached_input_per_million: f64) -> Self {
+        Self {
+            cached_input_per_million: Some(cached_input_per_million),
+            ..self
+        }
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+            cached_tokens: self.cached_tokens + other.cached_tokens,
+            reasoning_tokens: self.reasoning_tokens + other.reasoning_tokens,
+        }
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for Usage {
+    fn sum<I: Iterator<Item = Usage>>(iter: I) -> Usage {
+        iter.fold(Usage::default(), Add::add)
+    }
+}
+
+/// Timing of the request that produced a response
+///
+/// Recorded by the service layer and stored on the assistant message under
+/// [`Latency::METADATA_KEY`], so responsiveness can be compared across models
+/// without wrapping every call.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::Message;
+/// use language_barrier_core::usage::Latency;
+/// use std::time::Duration;
+///
+/// let latency = Latency::new(Duration::from_millis(250), Duration::from_millis(1_200));
+/// let msg = Message::assistant("Hi!").with_metadata(Latency::METADATA_KEY, latency.to_metadata());
+///
+/// assert_eq!(msg.latency(), Some(latency));
+/// assert_eq!(latency.body_time(), Duration::from_millis(950));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Latency {
+    /// Time from sending the request until the response headers arrived
+    #[serde(rename = "time_to_first_byte_ms", with = "duration_ms")]
+    pub time_to_first_byte: Duration,
+    /// Time from sending the request until the full body was read
+    #[serde(rename = "total_ms", with = "duration_ms")]
+    pub total: Duration,
+}
+
+impl Latency {
+    /// The message metadata key under which the service layer stores latency
+    pub const METADATA_KEY: &'static str = "latency";
+
+    /// Creates a latency record
+    #[must_use]
+    pub fn new(time_to_first_byte: Duration, total: Duration) -> Self {
+        Self {
+            time_to_first_byte,
+            total,
+        }
+    }
+
+    /// Returns the time spent receiving the body after the first byte
+    #[must_use]
+    pub fn body_time(&self) -> Duration {
+        self.total.saturating_sub(self.time_to_first_byte)
+    }
+
+    /// Converts this latency into a metadata value
+    #[must_use]
+    pub fn to_metadata(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// Timing of a streamed reply
+///
+/// Recorded by streaming sessions, such as
+/// [`RealtimeSession`](crate::realtime::RealtimeSession) with the `realtime`
+/// feature, and stored on the reply under [`StreamTiming::METADATA_KEY`].
+/// Unlike [`Latency`], which ends at the first byte of the response, this
+/// measures how long the model took to produce its first content.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::usage::StreamTiming;
+/// use std::time::Duration;
+///
+/// let timing = StreamTiming::new(Duration::from_millis(400), Duration::from_millis(2_400));
+/// assert_eq!(timing.generation_time(), Duration::from_secs(2));
+/// assert_eq!(timing.tokens_per_second(100), Some(50.0));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamTiming {
+    /// Time from the request until the first text or audio of the reply
+    #[serde(rename = "time_to_first_token_ms", with = "duration_ms")]
+    pub time_to_first_token: Duration,
+    /// Time from the request until the reply was complete
+    #[serde(rename = "total_ms", with = "duration_ms")]
+    pub total: Duration,
+}
+
+impl StreamTiming {
+    /// The message metadata key under which streaming sessions store timing
+    pub const METADATA_KEY: &'static str = "stream_timing";
+
+    /// Creates a timing record
+    #[must_use]
+    pub fn new(time_to_first_token: Duration, total: Duration) -> Self {
+        Self {
+            time_to_first_token,
+            total,
+        }
+    }
+
+    /// Returns the time spent generating after the first token
+    #[must_use]
+    pub fn generation_time(&self) -> Duration {
+        self.total.saturating_sub(self.time_to_first_token)
+    }
+
+    /// Returns the output rate in tokens per second, counting the
+    /// `output_tokens` of the reply over the time after its first token
+    ///
+    /// Returns `None` if the whole reply arrived at once.
+    #[must_use]
+    pub fn tokens_per_second(&self, output_tokens: u64) -> Option<f64> {
+        let seconds = self.generation_time().as_secs_f64();
+        (seconds > 0.0).then(|| output_tokens as f64 / seconds)
+    }
+
+    /// Converts this timing into a metadata value
+    #[must_use]
+    pub fn to_metadata(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// Serializes durations as whole milliseconds
+mod duration_ms {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
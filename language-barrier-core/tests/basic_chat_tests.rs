@@ -50,15 +50,15 @@ async fn test_request_creation() {
             .add_message(Message::user("What is the capital of France?"));
 
         let request = provider.accept(model, &chat).unwrap();
-        assert_eq!(request.method(), "POST");
+        assert_eq!(request.method, "POST");
         assert_eq!(
-            request.url().as_str(),
+            request.url.as_str(),
             "https://api.anthropic.com/v1/messages"
         );
-        assert!(request.headers().contains_key("x-api-key"));
-        assert!(request.headers().contains_key("anthropic-version"));
+        assert!(request.headers.contains_key("x-api-key"));
+        assert!(request.headers.contains_key("anthropic-version"));
         assert_eq!(
-            request.headers().get("Content-Type").unwrap(),
+            request.headers.get("Content-Type").unwrap(),
             "application/json"
         );
     }
@@ -74,14 +74,14 @@ async fn test_request_creation() {
             .add_message(Message::user("What is the capital of France?"));
 
         let request = provider.accept(model, &chat).unwrap();
-        assert_eq!(request.method(), "POST");
+        assert_eq!(request.method, "POST");
         assert_eq!(
-            request.url().as_str(),
+            request.url.as_str(),
             "https://api.openai.com/v1/chat/completions"
         );
-        assert!(request.headers().contains_key("Authorization"));
+        assert!(request.headers.contains_key("Authorization"));
         assert_eq!(
-            request.headers().get("Content-Type").unwrap(),
+            request.headers.get("Content-Type").unwrap(),
             "application/json"
         );
     }
@@ -99,11 +99,11 @@ async fn test_request_creation() {
         // Since Gemini has JSON schema issues, wrap it in a match to prevent test failures
         match provider.accept(model, &chat) {
             Ok(request) => {
-                assert_eq!(request.method(), "POST");
-                assert!(request.url().as_str().contains("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent"));
-                assert!(request.url().as_str().contains("key="));
+                assert_eq!(request.method, "POST");
+                assert!(request.url.as_str().contains("https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent"));
+                assert!(request.url.as_str().contains("key="));
                 assert_eq!(
-                    request.headers().get("Content-Type").unwrap(),
+                    request.headers.get("Content-Type").unwrap(),
                     "application/json"
                 );
             }
@@ -125,14 +125,14 @@ async fn test_request_creation() {
             .add_message(Message::user("What is the capital of France?"));
 
         let request = provider.accept(model, &chat).unwrap();
-        assert_eq!(request.method(), "POST");
+        assert_eq!(request.method, "POST");
         assert_eq!(
-            request.url().as_str(),
+            request.url.as_str(),
             "https://api.mistral.ai/v1/chat/completions"
         );
-        assert!(request.headers().contains_key("Authorization"));
+        assert!(request.headers.contains_key("Authorization"));
         assert_eq!(
-            request.headers().get("Content-Type").unwrap(),
+            request.headers.get("Content-Type").unwrap(),
             "application/json"
         );
     }
@@ -156,95 +156,91 @@ async fn test_basic_chat_integration() {
     dotenv().ok();
 
     // Test with Anthropic if credentials available
-    if let Ok(api_key) = env::var("ANTHROPIC_API_KEY") {
-        if !api_key.is_empty() {
-            info!("Testing Anthropic integration");
-            let config = AnthropicConfig {
-                api_key,
-                base_url: "https://api.anthropic.com/v1".to_string(),
-                api_version: "2023-06-01".to_string(),
-            };
-            let provider = AnthropicProvider::with_config(config);
-            let model = Claude::Sonnet35 {
-                version: Sonnet35Version::V2,
-            };
-            let service = HTTPLlmService::new(model, Arc::new(provider));
+    if let Ok(api_key) = env::var("ANTHROPIC_API_KEY")
+        && !api_key.is_empty()
+    {
+        info!("Testing Anthropic integration");
+        let config = AnthropicConfig::builder()
+            .api_key(api_key)
+            .base_url("https://api.anthropic.com/v1")
+            .api_version("2023-06-01")
+            .build()
+            .unwrap();
+        let provider = AnthropicProvider::with_config(config);
+        let model = Claude::Sonnet35 {
+            version: Sonnet35Version::V2,
+        };
+        let service = HTTPLlmService::new(model, Arc::new(provider));
 
-            let chat = Chat::default()
-                .with_system_prompt(
-                    "You are a helpful AI assistant that provides very short answers.",
-                )
-                .with_max_output_tokens(100)
-                .add_message(Message::user("What is the capital of France?"));
+        let chat = Chat::default()
+            .with_system_prompt("You are a helpful AI assistant that provides very short answers.")
+            .with_max_output_tokens(100)
+            .add_message(Message::user("What is the capital of France?"));
 
-            if let Ok(response) = service.generate_next_message(&chat).await {
-                verify_chat_response(&response);
-            } else {
-                warn!("Anthropic test failed, but continuing with other providers");
-            }
+        if let Ok(response) = service.generate_next_message(&chat).await {
+            verify_chat_response(&response);
+        } else {
+            warn!("Anthropic test failed, but continuing with other providers");
         }
     }
 
     // Test with OpenAI if credentials available
-    if let Ok(api_key) = env::var("OPENAI_API_KEY") {
-        if !api_key.is_empty() {
-            info!("Testing OpenAI integration");
-            let config = OpenAIConfig {
-                api_key,
-                base_url: "https://api.openai.com/v1".to_string(),
-                organization: None,
-            };
-            let provider = OpenAIProvider::with_config(config);
-            let model = OpenAi::GPT4o;
-            let service = HTTPLlmService::new(model, Arc::new(provider));
+    if let Ok(api_key) = env::var("OPENAI_API_KEY")
+        && !api_key.is_empty()
+    {
+        info!("Testing OpenAI integration");
+        let config = OpenAIConfig::builder()
+            .api_key(api_key)
+            .base_url("https://api.openai.com/v1")
+            .build()
+            .unwrap();
+        let provider = OpenAIProvider::with_config(config);
+        let model = OpenAi::GPT4o;
+        let service = HTTPLlmService::new(model, Arc::new(provider));
 
-            let chat = Chat::default()
-                .with_system_prompt(
-                    "You are a helpful AI assistant that provides very short answers.",
-                )
-                .with_max_output_tokens(100)
-                .add_message(Message::user("What is the capital of France?"));
+        let chat = Chat::default()
+            .with_system_prompt("You are a helpful AI assistant that provides very short answers.")
+            .with_max_output_tokens(100)
+            .add_message(Message::user("What is the capital of France?"));
 
-            if let Ok(response) = service.generate_next_message(&chat).await {
-                verify_chat_response(&response);
-            } else {
-                warn!("OpenAI test failed, but continuing with other providers");
-            }
+        if let Ok(response) = service.generate_next_message(&chat).await {
+            verify_chat_response(&response);
+        } else {
+            warn!("OpenAI test failed, but continuing with other providers");
         }
     }
 
     // Test with Gemini if credentials available
-    if let Ok(api_key) = env::var("GEMINI_API_KEY") {
-        if !api_key.is_empty() {
-            info!("Testing Gemini integration");
-            // Skip test due to known issues with Gemini's handling of JSON schema
-            info!("Skipping Gemini test due to known issues with JSON schema handling");
-        }
+    if let Ok(api_key) = env::var("GEMINI_API_KEY")
+        && !api_key.is_empty()
+    {
+        info!("Testing Gemini integration");
+        // Skip test due to known issues with Gemini's handling of JSON schema
+        info!("Skipping Gemini test due to known issues with JSON schema handling");
     }
 
     // Test with Mistral if credentials available
-    if let Ok(api_key) = env::var("MISTRAL_API_KEY") {
-        if !api_key.is_empty() {
-            info!("Testing Mistral integration");
-            let config = MistralConfig {
-                api_key,
-                base_url: "https://api.mistral.ai/v1".to_string(),
-            };
-            let provider = MistralProvider::with_config(config);
-            let model = Mistral::Small; // Define the model
-            let svc = HTTPLlmService::new(model, Arc::new(provider));
-            let chat = Chat::default()
-                .with_system_prompt(
-                    "You are a helpful AI assistant that provides very short answers.",
-                )
-                .with_max_output_tokens(100)
-                .add_message(Message::user("What is the capital of France?"));
+    if let Ok(api_key) = env::var("MISTRAL_API_KEY")
+        && !api_key.is_empty()
+    {
+        info!("Testing Mistral integration");
+        let config = MistralConfig::builder()
+            .api_key(api_key)
+            .base_url("https://api.mistral.ai/v1")
+            .build()
+            .unwrap();
+        let provider = MistralProvider::with_config(config);
+        let model = Mistral::Small; // Define the model
+        let svc = HTTPLlmService::new(model, Arc::new(provider));
+        let chat = Chat::default()
+            .with_system_prompt("You are a helpful AI assistant that provides very short answers.")
+            .with_max_output_tokens(100)
+            .add_message(Message::user("What is the capital of France?"));
 
-            if let Ok(response) = svc.generate_next_message(&chat).await {
-                verify_chat_response(&response);
-            } else {
-                warn!("Mistral test failed, but continuing with other providers");
-            }
+        if let Ok(response) = svc.generate_next_message(&chat).await {
+            verify_chat_response(&response);
+        } else {
+            warn!("Mistral test failed, but continuing with other providers");
         }
     }
 }
@@ -201,7 +201,7 @@ async fn test_tool_result_conversion() {
         let request = provider.accept(Claude::Haiku3, &chat).unwrap();
 
         // Get the request body as a string
-        let body_bytes = request.body().unwrap().as_bytes().unwrap();
+        let body_bytes = &request.body;
         let body_str = std::str::from_utf8(body_bytes).unwrap();
 
         // Verify the request contains tool_result with the right content
@@ -240,7 +240,7 @@ async fn test_tool_result_conversion() {
         let request = provider.accept(OpenAi::GPT4o, &chat).unwrap();
 
         // Get the request body as a string
-        let body_bytes = request.body().unwrap().as_bytes().unwrap();
+        let body_bytes = &request.body;
         let body_str = std::str::from_utf8(body_bytes).unwrap();
 
         // Verify the request contains the tool call and result with the right content
@@ -272,7 +272,7 @@ async fn test_tool_result_conversion() {
         let request = provider.accept(Gemini::Flash20, &chat).unwrap();
 
         // Get the request body as a string
-        let body_bytes = request.body().unwrap().as_bytes().unwrap();
+        let body_bytes = &request.body;
         let body_str = std::str::from_utf8(body_bytes).unwrap();
 
         // Verify the request contains the tool call and result with the right content
@@ -304,7 +304,7 @@ async fn test_tool_result_conversion() {
         let request = provider.accept(Mistral::Small, &chat).unwrap();
 
         // Get the request body as a string
-        let body_bytes = request.body().unwrap().as_bytes().unwrap();
+        let body_bytes = &request.body;
         let body_str = std::str::from_utf8(body_bytes).unwrap();
 
         // Verify the request contains the tool call and result with the right content
@@ -0,0 +1,178 @@
+//! Parser conformance suite
+//!
+//! Every provider's `parse` must turn the recorded responses under
+//! `tests/fixtures/responses/<provider>/` into the outcome each fixture
+//! expects. Fixtures are real (sanitized) API bodies covering tool calls,
+//! refusals, errors, thinking blocks and truncated outputs; add a file to
+//! cover a new case.
+
+use std::fs;
+use std::path::Path;
+
+use language_barrier_core::model::{Claude, Gemini, Mistral, Ollama, OpenAi};
+use language_barrier_core::provider::HTTPProvider;
+use language_barrier_core::provider::anthropic::AnthropicProvider;
+use language_barrier_core::provider::gemini::GeminiProvider;
+use language_barrier_core::provider::mistral::MistralProvider;
+use language_barrier_core::provider::ollama::OllamaProvider;
+use language_barrier_core::provider::openai::OpenAIProvider;
+use language_barrier_core::{Error, Message, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// One recorded response and what parsing it must produce
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    description: String,
+    response: Value,
+    expect: Expectation,
+}
+
+#[derive(Debug, Deserialize)]
+struct Expectation {
+    /// The error variant parsing must fail with
+    error: Option<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    tool_calls: Vec<ExpectedToolCall>,
+    usage: Option<ExpectedUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpectedToolCall {
+    /// Only checked for providers that send tool-call IDs
+    id: Option<String>,
+    name: String,
+    arguments: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpectedUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+fn error_kind(error: &Error) -> &'static str {
+    match error {
+        Error::RateLimit(_) => "RateLimit",
+        Error::Overloaded(_) => "Overloaded",
+        Error::Authentication(_) => "Authentication",
+        Error::ContextLengthExceeded(_) => "ContextLengthExceeded",
+        Error::ProviderUnavailable(_) => "ProviderUnavailable",
+        Error::Serialization(_) => "Serialization",
+        _ => "Other",
+    }
+}
+
+fn check(case: &str, fixture: &Fixture, result: Result<Message>) {
+    let expect = &fixture.expect;
+    let context = format!("{case} ({})", fixture.description);
+
+    let message = match (result, &expect.error) {
+        (Err(e), Some(kind)) => {
+            assert_eq!(error_kind(&e), kind, "{context}: wrong error: {e}");
+            return;
+        }
+        (Err(e), None) => panic!("{context}: unexpected error: {e}"),
+        (Ok(message), Some(kind)) => panic!("{context}: expected {kind}, got {message:?}"),
+        (Ok(message), None) => message,
+    };
+
+    assert_eq!(message.role_str(), "assistant", "{context}: role");
+    assert_eq!(message.text_content(), expect.text, "{context}: text");
+
+    let tool_calls = match &message {
+        Message::Assistant { tool_calls, .. } => tool_calls.as_slice(),
+        _ => &[],
+    };
+    assert_eq!(
+        tool_calls.len(),
+        expect.tool_calls.len(),
+        "{context}: tool calls {tool_calls:?}"
+    );
+    for (call, expected) in tool_calls.iter().zip(&expect.tool_calls) {
+        assert_eq!(call.function.name, expected.name, "{context}: tool name");
+        let arguments: Value = serde_json::from_str(&call.function.arguments)
+            .unwrap_or_else(|e| panic!("{context}: arguments aren't JSON: {e}"));
+        assert_eq!(arguments, expected.arguments, "{context}: arguments");
+        if let Some(id) = &expected.id {
+            assert_eq!(&call.id, id, "{context}: tool call id");
+        }
+    }
+
+    if let Some(expected) = &expect.usage {
+        let usage = message
+            .usage()
+            .unwrap_or_else(|| panic!("{context}: no usage"));
+        assert_eq!(
+            usage.input_tokens, expected.input_tokens,
+            "{context}: input"
+        );
+        assert_eq!(
+            usage.output_tokens, expected.output_tokens,
+            "{context}: output"
+        );
+    }
+}
+
+/// Runs every fixture for `provider` through `parse`
+fn run_fixtures(provider: &str, parse: impl Fn(String) -> Result<Message>) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/responses")
+        .join(provider);
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("can't read {}: {e}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no fixtures for {provider}");
+
+    for path in paths {
+        let case = format!("{provider}/{}", path.file_stem().unwrap().to_string_lossy());
+        let fixture: Fixture = serde_json::from_str(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{case}: invalid fixture: {e}"));
+        check(&case, &fixture, parse(fixture.response.to_string()));
+    }
+}
+
+#[test]
+fn test_openai_fixtures() {
+    let provider = OpenAIProvider::default();
+    run_fixtures("openai", |body| {
+        HTTPProvider::<OpenAi>::parse(&provider, body)
+    });
+}
+
+#[test]
+fn test_mistral_fixtures() {
+    let provider = MistralProvider::default();
+    run_fixtures("mistral", |body| {
+        HTTPProvider::<Mistral>::parse(&provider, body)
+    });
+}
+
+#[test]
+fn test_anthropic_fixtures() {
+    let provider = AnthropicProvider::default();
+    run_fixtures("anthropic", |body| {
+        HTTPProvider::<Claude>::parse(&provider, body)
+    });
+}
+
+#[test]
+fn test_gemini_fixtures() {
+    let provider = GeminiProvider::default();
+    run_fixtures("gemini", |body| {
+        HTTPProvider::<Gemini>::parse(&provider, body)
+    });
+}
+
+#[test]
+fn test_ollama_fixtures() {
+    let provider = OllamaProvider::default();
+    run_fixtures("ollama", |body| {
+        HTTPProvider::<Ollama>::parse(&provider, body)
+    });
+}
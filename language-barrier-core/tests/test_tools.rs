@@ -1,3 +1,6 @@
+// Shared by several integration test binaries; not every binary uses every helper.
+#![allow(dead_code)]
+
 use language_barrier_core::{Result, ToolDefinition};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -1,3 +1,6 @@
+// Shared by several integration test binaries; not every binary uses every helper.
+#![allow(dead_code)]
+
 use dotenv::dotenv;
 use language_barrier_core::message::{Content, ContentPart, Message};
 use language_barrier_core::provider::anthropic::{AnthropicConfig, AnthropicProvider};
@@ -35,11 +38,14 @@ pub fn get_anthropic_provider() -> Option<AnthropicProvider> {
     }
     dotenv().ok();
     match env::var("ANTHROPIC_API_KEY") {
-        Ok(key) if !key.is_empty() => Some(AnthropicProvider::with_config(AnthropicConfig {
-            api_key: key,
-            base_url: "https://api.anthropic.com/v1".to_string(),
-            api_version: "2023-06-01".to_string(),
-        })),
+        Ok(key) if !key.is_empty() => Some(AnthropicProvider::with_config(
+            AnthropicConfig::builder()
+                .api_key(key)
+                .base_url("https://api.anthropic.com/v1")
+                .api_version("2023-06-01")
+                .build()
+                .unwrap(),
+        )),
         _ => None,
     }
 }
@@ -51,11 +57,13 @@ pub fn get_openai_provider() -> Option<OpenAIProvider> {
     }
     dotenv().ok();
     match env::var("OPENAI_API_KEY") {
-        Ok(key) if !key.is_empty() => Some(OpenAIProvider::with_config(OpenAIConfig {
-            api_key: key,
-            base_url: "https://api.openai.com/v1".to_string(),
-            organization: None,
-        })),
+        Ok(key) if !key.is_empty() => Some(OpenAIProvider::with_config(
+            OpenAIConfig::builder()
+                .api_key(key)
+                .base_url("https://api.openai.com/v1")
+                .build()
+                .unwrap(),
+        )),
         _ => None,
     }
 }
@@ -67,10 +75,13 @@ pub fn get_google_provider() -> Option<GeminiProvider> {
     }
     dotenv().ok();
     match env::var("GEMINI_API_KEY") {
-        Ok(key) if !key.is_empty() => Some(GeminiProvider::with_config(GeminiConfig {
-            api_key: key,
-            base_url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
-        })),
+        Ok(key) if !key.is_empty() => Some(GeminiProvider::with_config(
+            GeminiConfig::builder()
+                .api_key(key)
+                .base_url("https://generativelanguage.googleapis.com/v1beta")
+                .build()
+                .unwrap(),
+        )),
         _ => None,
     }
 }
@@ -82,15 +93,19 @@ pub fn get_mistral_provider() -> Option<MistralProvider> {
     }
     dotenv().ok();
     match env::var("MISTRAL_API_KEY") {
-        Ok(key) if !key.is_empty() => Some(MistralProvider::with_config(MistralConfig {
-            api_key: key,
-            base_url: "https://api.mistral.ai/v1".to_string(),
-        })),
+        Ok(key) if !key.is_empty() => Some(MistralProvider::with_config(
+            MistralConfig::builder()
+                .api_key(key)
+                .base_url("https://api.mistral.ai/v1")
+                .build()
+                .unwrap(),
+        )),
         _ => None,
     }
 }
 
 /// Extract text content from a message
+#[allow(irrefutable_let_patterns)] // text is the only part without `multimodal`
 pub fn extract_text_content(message: &Message) -> String {
     match message {
         Message::Assistant { content, .. } => match content {
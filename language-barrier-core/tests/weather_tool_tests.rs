@@ -120,7 +120,7 @@ async fn test_tool_mistral_weather(test_case: Mistral) {
         Ok(Message::Assistant { tool_calls, .. }) => {
             assert!(!tool_calls.is_empty())
         }
-        Err(e) => {
+        Err(_e) => {
             // Log the error but don't fail the test
             panic!("Expected assistant message");
             // error!("API request had an expected error: {}", e);
@@ -0,0 +1,52 @@
+use language_barrier_core::{ApiErrorKind, Error};
+
+/// An error surfaced to Swift and Kotlin
+///
+/// Each case carries the core error's message. The cases are the ones an
+/// app reacts to differently, such as asking for a new key or backing off;
+/// everything else is `Other`.
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum FfiError {
+    /// The model name or provider config can't work
+    #[error("{message}")]
+    InvalidConfig { message: String },
+    /// The API key is missing, invalid or lacks permission
+    #[error("{message}")]
+    Authentication { message: String },
+    /// Too many requests, or the quota is used up
+    #[error("{message}")]
+    RateLimit { message: String },
+    /// The conversation doesn't fit in the model's context window
+    #[error("{message}")]
+    ContextLengthExceeded { message: String },
+    /// The request timed out
+    #[error("{message}")]
+    Timeout { message: String },
+    /// Any other failure
+    #[error("{message}")]
+    Other { message: String },
+}
+
+impl From<Error> for FfiError {
+    fn from(error: Error) -> Self {
+        let message = error.to_string();
+        match error {
+            Error::ChatConfig(_)
+            | Error::ProviderConfig(_)
+            | Error::BaseUrlError(_)
+            | Error::UnsupportedModel(_) => Self::InvalidConfig { message },
+            Error::Authentication(_) => Self::Authentication { message },
+            Error::RateLimit(_) => Self::RateLimit { message },
+            Error::ContextLengthExceeded(_) => Self::ContextLengthExceeded { message },
+            Error::Timeout(_) => Self::Timeout { message },
+            Error::Api(api) => match api.kind {
+                ApiErrorKind::Authentication => Self::Authentication { message },
+                ApiErrorKind::RateLimit => Self::RateLimit { message },
+                ApiErrorKind::ContextLengthExceeded => Self::ContextLengthExceeded { message },
+                ApiErrorKind::Timeout => Self::Timeout { message },
+                _ => Self::Other { message },
+            },
+            _ => Self::Other { message },
+        }
+    }
+}
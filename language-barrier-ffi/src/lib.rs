@@ -0,0 +1,103 @@
+//! Swift and Kotlin bindings for language-barrier
+//!
+//! This crate exposes the core chat API through [UniFFI] so mobile apps can
+//! reuse the provider logic instead of re-implementing it natively. The
+//! surface is deliberately small: create a [`ChatSession`] for a model, add
+//! messages, and send, either awaiting the reply or handing it to a
+//! [`ChatListener`].
+//!
+//! Build the library for the target platform, then generate the bindings
+//! from it with the bundled CLI:
+//!
+//! ```text
+//! cargo build -p language-barrier-ffi --release
+//! cargo run -p language-barrier-ffi --features cli --bin uniffi-bindgen -- \
+//!     generate --library target/release/liblanguage_barrier_ffi.so \
+//!     --language swift --out-dir bindings/swift
+//! ```
+//!
+//! Each hosted provider is a cargo feature, all on by default, so an app
+//! that only talks to one provider can leave the others out of its binary.
+//!
+//! [UniFFI]: https://mozilla.github.io/uniffi-rs/
+
+mod error;
+mod session;
+
+pub use error::FfiError;
+pub use session::{ChatListener, ChatSession};
+
+use language_barrier_core::Message;
+
+uniffi::setup_scaffolding!("language_barrier");
+
+/// Who a message is from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+/// A message as seen from Swift and Kotlin: who sent it and its text
+///
+/// Non-text parts and tool calls stay on the Rust side; `text` holds the
+/// message's text parts joined by newlines.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub text: String,
+}
+
+impl From<&Message> for ChatMessage {
+    fn from(message: &Message) -> Self {
+        let role = match message {
+            Message::System { .. } => Role::System,
+            Message::User { .. } => Role::User,
+            Message::Assistant { .. } => Role::Assistant,
+            Message::Tool { .. } => Role::Tool,
+        };
+        Self {
+            role,
+            text: message.text_content(),
+        }
+    }
+}
+
+impl TryFrom<ChatMessage> for Message {
+    type Error = FfiError;
+
+    fn try_from(message: ChatMessage) -> Result<Self, FfiError> {
+        match message.role {
+            Role::System => Ok(Message::system(message.text)),
+            Role::User => Ok(Message::user(message.text)),
+            Role::Assistant => Ok(Message::assistant(message.text)),
+            // A tool result needs the ID of the call it answers, which the
+            // bindings don't carry
+            Role::Tool => Err(FfiError::InvalidConfig {
+                message: "tool messages can't be added through the bindings".to_string(),
+            }),
+        }
+    }
+}
+
+/// What a [`ChatSession`] talks to
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct SessionConfig {
+    /// A model ID such as `"gpt-4o"`, or an alias such as `"fast"`
+    pub model: String,
+    /// The API key; if unset, the provider's standard environment variable
+    /// is read
+    #[uniffi(default = None)]
+    pub api_key: Option<String>,
+    /// Overrides the provider's endpoint, for proxies and gateways
+    #[uniffi(default = None)]
+    pub base_url: Option<String>,
+    /// The system prompt the conversation starts with
+    #[uniffi(default = None)]
+    pub system_prompt: Option<String>,
+    /// Caps the length of each reply, in tokens
+    #[uniffi(default = None)]
+    pub max_output_tokens: Option<u32>,
+}
@@ -0,0 +1,410 @@
+use std::sync::{Arc, Mutex, OnceLock};
+
+use async_trait::async_trait;
+use language_barrier_core::alias::resolve_model;
+use language_barrier_core::idempotency::Submission;
+use language_barrier_core::model::AnyModel;
+use language_barrier_core::{Chat, HTTPLlmService, LLMService, Message, ModelInfo};
+use tokio::runtime::Runtime;
+use tracing::debug;
+
+use crate::{ChatMessage, FfiError, SessionConfig};
+
+/// Receives the outcome of [`ChatSession::send_with_listener`]
+///
+/// Implemented in Swift or Kotlin. The methods are called on a background
+/// thread, so apps hop to the main thread before touching UI.
+#[uniffi::export(with_foreign)]
+pub trait ChatListener: Send + Sync {
+    /// A piece of the reply's text
+    ///
+    /// The core has no token streaming yet, so today this is called once
+    /// with the whole text, just before [`on_complete`](Self::on_complete).
+    fn on_text(&self, text: String);
+
+    /// The reply, which has been added to the session's history
+    fn on_complete(&self, message: ChatMessage);
+
+    /// The send failed; the history is unchanged
+    fn on_error(&self, error: FfiError);
+}
+
+/// The one method a session needs from a service, so it can hold a service
+/// for any provider's model type
+#[async_trait]
+trait Backend: Send + Sync {
+    async fn generate(&self, chat: &Chat) -> language_barrier_core::Result<Message>;
+}
+
+#[async_trait]
+impl<M: ModelInfo> Backend for HTTPLlmService<M> {
+    async fn generate(&self, chat: &Chat) -> language_barrier_core::Result<Message> {
+        self.generate_next_message(chat).await
+    }
+}
+
+// A build with no providers can't start any session, but still compiles
+#[cfg(any(
+    feature = "anthropic",
+    feature = "openai",
+    feature = "gemini",
+    feature = "mistral"
+))]
+/// Builds the HTTP service for a hosted model, applying the key and base
+/// URL from `config` over the provider's defaults
+macro_rules! hosted {
+    ($model:expr, $config:expr, $provider_config:ty, $provider:ty) => {{
+        let mut builder = <$provider_config>::builder();
+        if let Some(key) = &$config.api_key {
+            builder = builder.api_key(key);
+        }
+        if let Some(url) = &$config.base_url {
+            builder = builder.base_url(url);
+        }
+        let provider = <$provider>::with_config(builder.build()?);
+        Ok(Arc::new(HTTPLlmService::new($model, Arc::new(provider))))
+    }};
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "anthropic",
+        feature = "openai",
+        feature = "gemini",
+        feature = "mistral"
+    )),
+    allow(unused_variables)
+)]
+fn backend(model: AnyModel, config: &SessionConfig) -> Result<Arc<dyn Backend>, FfiError> {
+    match model {
+        #[cfg(feature = "anthropic")]
+        AnyModel::Claude(model) => {
+            use language_barrier_core::provider::anthropic::{AnthropicConfig, AnthropicProvider};
+            hosted!(model, config, AnthropicConfig, AnthropicProvider)
+        }
+        #[cfg(feature = "openai")]
+        AnyModel::OpenAi(model) => {
+            use language_barrier_core::provider::openai::{OpenAIConfig, OpenAIProvider};
+            hosted!(model, config, OpenAIConfig, OpenAIProvider)
+        }
+        #[cfg(feature = "gemini")]
+        AnyModel::Gemini(model) => {
+            use language_barrier_core::provider::gemini::{GeminiConfig, GeminiProvider};
+            hosted!(model, config, GeminiConfig, GeminiProvider)
+        }
+        #[cfg(feature = "mistral")]
+        AnyModel::Mistral(model) => {
+            use language_barrier_core::provider::mistral::{MistralConfig, MistralProvider};
+            hosted!(model, config, MistralConfig, MistralProvider)
+        }
+        #[allow(unreachable_patterns)]
+        other => Err(FfiError::InvalidConfig {
+            message: format!(
+                "{other} is a {} model, and this build leaves that provider out",
+                other.provider()
+            ),
+        }),
+    }
+}
+
+/// The runtime that [`ChatSession::send_with_listener`] runs sends on
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("language-barrier")
+            .build()
+            .expect("the bindings' tokio runtime should start")
+    })
+}
+
+/// A conversation with one model
+///
+/// The session owns the history: messages are added to it, and each send
+/// appends the model's reply. Sends on one session run one at a time, so a
+/// reply is always generated from the history as the previous send left it.
+#[derive(uniffi::Object)]
+pub struct ChatSession {
+    backend: Arc<dyn Backend>,
+    chat: Mutex<Chat>,
+    // Held across a whole send, so sends don't interleave
+    sending: tokio::sync::Mutex<()>,
+}
+
+impl ChatSession {
+    fn with_backend(backend: Arc<dyn Backend>, chat: Chat) -> Self {
+        Self {
+            backend,
+            chat: Mutex::new(chat),
+            sending: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn chat(&self) -> std::sync::MutexGuard<'_, Chat> {
+        self.chat
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl ChatSession {
+    /// Starts a conversation with the model named in `config`
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidConfig` if the model name isn't a known ID or alias,
+    /// its provider is left out of this build, or the provider config is
+    /// invalid, for example because no API key was given or found.
+    #[uniffi::constructor]
+    pub fn new(config: SessionConfig) -> Result<Arc<Self>, FfiError> {
+        let model = resolve_model(&config.model)?;
+        debug!("Starting an FFI chat session with {}", model);
+
+        let mut chat = Chat::default();
+        if let Some(prompt) = &config.system_prompt {
+            chat = chat.with_system_prompt(prompt);
+        }
+        if let Some(max) = config.max_output_tokens {
+            chat = chat.with_max_output_tokens(max as usize);
+        }
+
+        Ok(Arc::new(Self::with_backend(backend(model, &config)?, chat)))
+    }
+
+    /// Adds a message to the end of the history
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidConfig` for a `Tool` message, which needs a tool call
+    /// ID the bindings don't carry.
+    pub fn add_message(&self, message: ChatMessage) -> Result<(), FfiError> {
+        let message = Message::try_from(message)?;
+        let mut chat = self.chat();
+        *chat = std::mem::take(&mut *chat).add_message(message);
+        Ok(())
+    }
+
+    /// The history, oldest message first
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        self.chat().history.iter().map(ChatMessage::from).collect()
+    }
+
+    /// Asks the model for the next message and adds it to the history
+    ///
+    /// # Errors
+    ///
+    /// Returns the request's error, mapped to an [`FfiError`]; the history
+    /// is unchanged in that case.
+    pub async fn send(&self) -> Result<ChatMessage, FfiError> {
+        let _turn = self.sending.lock().await;
+        let chat = self.chat().clone();
+
+        let reply = self.backend.generate(&chat).await?;
+        let message = ChatMessage::from(&reply);
+
+        let mut chat = self.chat();
+        *chat = std::mem::take(&mut *chat).add_message(reply);
+        Ok(message)
+    }
+
+    /// Adds a user turn and asks the model for the reply, unless the turn
+    /// was already submitted with `client_id`
+    ///
+    /// A retried webhook or a double-tapped send button may submit the same
+    /// turn twice. If the turn was answered, its reply is returned again;
+    /// if its first send failed, the reply is generated without adding the
+    /// message a second time.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidConfig` for a `Tool` message, or the request's error.
+    /// The message stays in the history in that case, so submitting it
+    /// again retries the reply.
+    pub async fn submit(
+        &self,
+        message: ChatMessage,
+        client_id: String,
+    ) -> Result<ChatMessage, FfiError> {
+        let message = Message::try_from(message)?.with_client_id(&client_id);
+        let _turn = self.sending.lock().await;
+
+        let chat = {
+            let mut chat = self.chat();
+            if let Some(Submission::Answered(reply)) = chat.submission(&client_id) {
+                debug!("Turn {} was already answered", client_id);
+                return Ok(ChatMessage::from(&reply));
+            }
+            *chat = std::mem::take(&mut *chat).add_turn(message);
+            chat.clone()
+        };
+
+        let reply = self.backend.generate(&chat).await?;
+        let message = ChatMessage::from(&reply);
+
+        let mut chat = self.chat();
+        *chat = std::mem::take(&mut *chat).add_message(reply);
+        Ok(message)
+    }
+
+    /// Like [`send`](Self::send), but returns at once and reports the reply
+    /// or error to `listener`
+    ///
+    /// For callers without async support.
+    pub fn send_with_listener(self: Arc<Self>, listener: Arc<dyn ChatListener>) {
+        runtime().spawn(async move {
+            match self.send().await {
+                Ok(message) => {
+                    listener.on_text(message.text.clone());
+                    listener.on_complete(message);
+                }
+                Err(error) => listener.on_error(error),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use language_barrier_core::Error;
+
+    use super::*;
+    use crate::Role;
+
+    /// Replies with how many messages it was sent, or fails
+    struct Counter {
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Backend for Counter {
+        async fn generate(&self, chat: &Chat) -> language_barrier_core::Result<Message> {
+            if self.fail {
+                return Err(Error::RateLimit("slow down".to_string()));
+            }
+            Ok(Message::assistant(format!("{} messages", chat.history.len())))
+        }
+    }
+
+    fn session(fail: bool) -> Arc<ChatSession> {
+        Arc::new(ChatSession::with_backend(
+            Arc::new(Counter { fail }),
+            Chat::default(),
+        ))
+    }
+
+    fn user(text: &str) -> ChatMessage {
+        ChatMessage {
+            role: Role::User,
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_appends_reply() {
+        let session = session(false);
+        session.add_message(user("Hi")).unwrap();
+
+        let reply = session.send().await.unwrap();
+        assert_eq!(reply.role, Role::Assistant);
+        assert_eq!(reply.text, "1 messages");
+        assert_eq!(session.messages(), vec![user("Hi"), reply]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_leaves_history() {
+        let session = session(true);
+        session.add_message(user("Hi")).unwrap();
+
+        let error = session.send().await.unwrap_err();
+        assert!(matches!(error, FfiError::RateLimit { .. }));
+        assert_eq!(session.messages(), vec![user("Hi")]);
+    }
+
+    #[tokio::test]
+    async fn test_resubmitted_turns_reuse_the_reply() {
+        let session = session(false);
+        let first = session.submit(user("Hi"), "msg-1".into()).await.unwrap();
+        let again = session.submit(user("Hi"), "msg-1".into()).await.unwrap();
+        assert_eq!(first, again);
+        assert_eq!(session.messages().len(), 2);
+
+        let failing = ChatSession::with_backend(Arc::new(Counter { fail: true }), Chat::default());
+        assert!(failing.submit(user("Hi"), "msg-1".into()).await.is_err());
+        assert!(failing.submit(user("Hi"), "msg-1".into()).await.is_err());
+        assert_eq!(failing.messages(), vec![user("Hi")]);
+    }
+
+    #[test]
+    fn test_tool_messages_rejected() {
+        let session = session(false);
+        let tool = ChatMessage {
+            role: Role::Tool,
+            text: "42".to_string(),
+        };
+        assert!(matches!(
+            session.add_message(tool),
+            Err(FfiError::InvalidConfig { .. })
+        ));
+        assert!(session.messages().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_model_is_invalid_config() {
+        let config = SessionConfig {
+            model: "no-such-model".to_string(),
+            api_key: Some("sk-test".to_string()),
+            base_url: None,
+            system_prompt: None,
+            max_output_tokens: None,
+        };
+        assert!(matches!(
+            ChatSession::new(config),
+            Err(FfiError::InvalidConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_new_session_for_hosted_model() {
+        let config = SessionConfig {
+            model: "gpt-4o".to_string(),
+            api_key: Some("sk-test".to_string()),
+            base_url: None,
+            system_prompt: Some("Be brief.".to_string()),
+            max_output_tokens: Some(256),
+        };
+        let session = ChatSession::new(config).unwrap();
+        assert!(session.messages().is_empty());
+    }
+
+    struct Channel(Mutex<mpsc::Sender<String>>);
+
+    impl ChatListener for Channel {
+        fn on_text(&self, text: String) {
+            self.0.lock().unwrap().send(format!("text: {text}")).unwrap();
+        }
+
+        fn on_complete(&self, message: ChatMessage) {
+            let event = format!("complete: {}", message.text);
+            self.0.lock().unwrap().send(event).unwrap();
+        }
+
+        fn on_error(&self, error: FfiError) {
+            self.0.lock().unwrap().send(format!("error: {error}")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_send_with_listener() {
+        let (tx, rx) = mpsc::channel();
+        let session = session(false);
+        session.add_message(user("Hi")).unwrap();
+
+        session.send_with_listener(Arc::new(Channel(Mutex::new(tx))));
+        assert_eq!(rx.recv().unwrap(), "text: 1 messages");
+        assert_eq!(rx.recv().unwrap(), "complete: 1 messages");
+    }
+}
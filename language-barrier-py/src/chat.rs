@@ -0,0 +1,141 @@
+use language_barrier_core::Chat;
+use language_barrier_core::tool::{LlmToolInfo, ToolChoice};
+use pyo3::prelude::*;
+
+use crate::message::PyMessage;
+use crate::{from_py, to_py};
+
+/// A conversation and the settings it's sent with
+///
+/// Unlike the Rust `Chat`, which returns a new chat from each method, this
+/// one is changed in place, as Python code expects.
+#[pyclass(name = "Chat", module = "language_barrier")]
+#[derive(Clone, Default)]
+pub struct PyChat(pub(crate) Chat);
+
+impl PyChat {
+    /// Applies one of `Chat`'s by-value methods in place
+    fn update(&mut self, f: impl FnOnce(Chat) -> Chat) {
+        self.0 = f(std::mem::take(&mut self.0));
+    }
+}
+
+#[pymethods]
+impl PyChat {
+    #[new]
+    #[pyo3(signature = (system_prompt=None, max_output_tokens=None, temperature=None))]
+    fn new(
+        system_prompt: Option<String>,
+        max_output_tokens: Option<usize>,
+        temperature: Option<f32>,
+    ) -> Self {
+        let mut chat = Self::default();
+        if let Some(prompt) = system_prompt {
+            chat.update(|c| c.with_system_prompt(prompt));
+        }
+        if let Some(max) = max_output_tokens {
+            chat.update(|c| c.with_max_output_tokens(max));
+        }
+        if let Some(temperature) = temperature {
+            chat.update(|c| c.with_temperature(temperature));
+        }
+        chat
+    }
+
+    /// Adds a message to the end of the history
+    fn add_message(&mut self, message: PyMessage) {
+        self.update(|c| c.add_message(message.0));
+    }
+
+    /// The history, oldest message first
+    #[getter]
+    fn messages(&self) -> Vec<PyMessage> {
+        self.0.history.iter().cloned().map(PyMessage).collect()
+    }
+
+    #[getter]
+    fn system_prompt(&self) -> String {
+        self.0.system_prompt.clone()
+    }
+
+    #[setter]
+    fn set_system_prompt(&mut self, prompt: String) {
+        self.update(|c| c.with_system_prompt(prompt));
+    }
+
+    #[getter]
+    fn max_output_tokens(&self) -> usize {
+        self.0.max_output_tokens
+    }
+
+    #[setter]
+    fn set_max_output_tokens(&mut self, max: usize) {
+        self.update(|c| c.with_max_output_tokens(max));
+    }
+
+    #[getter]
+    fn temperature(&self) -> Option<f32> {
+        self.0.temperature
+    }
+
+    #[setter]
+    fn set_temperature(&mut self, temperature: Option<f32>) {
+        self.0.temperature = temperature;
+    }
+
+    /// Offers the model a tool; `parameters` is its JSON schema, as a dict
+    fn add_tool(
+        &mut self,
+        name: String,
+        description: String,
+        parameters: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        let tool = LlmToolInfo {
+            name,
+            description,
+            parameters: from_py(parameters)?,
+        };
+        self.update(|c| c.with_tools(vec![tool]));
+        Ok(())
+    }
+
+    /// The tools offered to the model, as dicts
+    #[getter]
+    fn tools(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py(py, &self.0.tools.clone().unwrap_or_default())
+    }
+
+    /// How the model picks tools: `None` for the provider's default,
+    /// `"auto"`, `"any"`, `"none"`, or the name of the one tool it must call
+    #[getter]
+    fn tool_choice(&self) -> Option<String> {
+        self.0.tool_choice.as_ref().map(|choice| match choice {
+            ToolChoice::Auto => "auto".to_string(),
+            ToolChoice::Any => "any".to_string(),
+            ToolChoice::None => "none".to_string(),
+            ToolChoice::Specific(name) => name.clone(),
+        })
+    }
+
+    #[setter]
+    fn set_tool_choice(&mut self, choice: Option<String>) {
+        self.0.tool_choice = choice.map(|choice| match choice.as_str() {
+            "auto" => ToolChoice::Auto,
+            "any" => ToolChoice::Any,
+            "none" => ToolChoice::None,
+            _ => ToolChoice::Specific(choice),
+        });
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.history.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Chat({} message(s), {} tool(s))",
+            self.0.history.len(),
+            self.0.tools.as_ref().map_or(0, Vec::len)
+        )
+    }
+}
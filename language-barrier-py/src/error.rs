@@ -0,0 +1,76 @@
+use language_barrier_core::{ApiErrorKind, Error};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+create_exception!(
+    language_barrier,
+    LanguageBarrierError,
+    PyException,
+    "Base class of every error raised by language_barrier"
+);
+create_exception!(
+    language_barrier,
+    ConfigError,
+    LanguageBarrierError,
+    "The model name, provider config or chat can't work"
+);
+create_exception!(
+    language_barrier,
+    AuthenticationError,
+    LanguageBarrierError,
+    "The API key is missing, invalid or lacks permission"
+);
+create_exception!(
+    language_barrier,
+    RateLimitError,
+    LanguageBarrierError,
+    "Too many requests, or the quota is used up"
+);
+create_exception!(
+    language_barrier,
+    ContextLengthError,
+    LanguageBarrierError,
+    "The chat doesn't fit in the model's context window"
+);
+
+/// Raises a core error as the matching Python exception
+pub(crate) fn to_py_err(error: impl Into<Error>) -> PyErr {
+    let error = error.into();
+    let message = error.to_string();
+    let kind = match &error {
+        Error::Api(api) => Some(api.kind),
+        _ => None,
+    };
+    match (error, kind) {
+        (
+            Error::ChatConfig(_)
+            | Error::ProviderConfig(_)
+            | Error::BaseUrlError(_)
+            | Error::UnsupportedModel(_)
+            | Error::Serialization(_),
+            _,
+        ) => ConfigError::new_err(message),
+        (Error::Authentication(_), _) | (_, Some(ApiErrorKind::Authentication)) => {
+            AuthenticationError::new_err(message)
+        }
+        (Error::RateLimit(_), _) | (_, Some(ApiErrorKind::RateLimit)) => {
+            RateLimitError::new_err(message)
+        }
+        (Error::ContextLengthExceeded(_), _) | (_, Some(ApiErrorKind::ContextLengthExceeded)) => {
+            ContextLengthError::new_err(message)
+        }
+        _ => LanguageBarrierError::new_err(message),
+    }
+}
+
+/// Adds the exception types to the module
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("LanguageBarrierError", py.get_type::<LanguageBarrierError>())?;
+    m.add("ConfigError", py.get_type::<ConfigError>())?;
+    m.add("AuthenticationError", py.get_type::<AuthenticationError>())?;
+    m.add("RateLimitError", py.get_type::<RateLimitError>())?;
+    m.add("ContextLengthError", py.get_type::<ContextLengthError>())?;
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! Python bindings for language-barrier
+//!
+//! Exposes [`Chat`](language_barrier_core::Chat), the hosted providers and
+//! tool definitions to Python as the `language_barrier` module, so notebooks
+//! build exactly the requests production does. Build it with maturin:
+//!
+//! ```text
+//! cd language-barrier-py && maturin develop --release
+//! ```
+//!
+//! ```python
+//! from language_barrier import Chat, Message, Service
+//!
+//! chat = Chat(system_prompt="You are terse.")
+//! chat.add_tool("weather", "Gets the weather", {
+//!     "type": "object",
+//!     "properties": {"city": {"type": "string"}},
+//!     "required": ["city"],
+//! })
+//! chat.add_message(Message.user("Weather in Paris?"))
+//!
+//! service = Service("claude-3-5-haiku-latest")
+//! service.dry_run(chat)               # the request, as a dict, unsent
+//! reply = await service.generate(chat)
+//! chat.add_message(reply)
+//! ```
+
+mod chat;
+mod error;
+mod message;
+mod service;
+
+use pyo3::prelude::*;
+use pyo3::types::PyModule;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+pub use chat::PyChat;
+pub use message::PyMessage;
+pub use service::PyService;
+
+/// Converts a serializable value to the matching Python object, via JSON
+fn to_py<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(error::to_py_err)?;
+    let object = PyModule::import(py, "json")?.call_method1("loads", (json,))?;
+    Ok(object.unbind())
+}
+
+/// Converts a Python object (dicts, lists and scalars) to a deserializable
+/// value, via JSON
+fn from_py<T: DeserializeOwned>(object: &Bound<'_, PyAny>) -> PyResult<T> {
+    let json: String = PyModule::import(object.py(), "json")?
+        .call_method1("dumps", (object,))?
+        .extract()?;
+    serde_json::from_str(&json).map_err(error::to_py_err)
+}
+
+/// The `language_barrier` Python module
+#[pymodule]
+fn language_barrier(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMessage>()?;
+    m.add_class::<PyChat>()?;
+    m.add_class::<PyService>()?;
+    error::register(m)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use pyo3::types::PyDict;
+
+    use super::*;
+
+    /// Runs `script` with the module imported as `lb`
+    fn run(script: &str) {
+        Python::with_gil(|py| {
+            let module = pyo3::wrap_pymodule!(language_barrier)(py);
+            let globals = PyDict::new(py);
+            globals.set_item("lb", module).unwrap();
+            let script = CString::new(script).unwrap();
+            if let Err(e) = py.run(&script, Some(&globals), None) {
+                e.print(py);
+                panic!("script failed: {e}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_messages_round_trip_through_dicts() {
+        run(r#"
+m = lb.Message.from_dict({
+    "role": "assistant",
+    "tool_calls": [{"id": "call_1", "type": "function",
+                    "function": {"name": "weather", "arguments": "{\"city\": \"Paris\"}"}}],
+})
+assert m.role == "assistant"
+assert m.tool_calls[0]["function"]["name"] == "weather"
+assert lb.Message.from_dict(m.to_dict()) == m
+assert lb.Message.tool("call_1", "sunny").to_dict()["tool_call_id"] == "call_1"
+"#);
+    }
+
+    #[test]
+    fn test_chat_is_changed_in_place() {
+        run(r#"
+chat = lb.Chat(system_prompt="Be brief.", temperature=0.5)
+chat.add_message(lb.Message.user("Hi"))
+chat.add_tool("weather", "Gets the weather", {"type": "object", "properties": {}})
+chat.tool_choice = "weather"
+assert len(chat) == 1
+assert chat.messages[0].text == "Hi"
+assert chat.tools[0]["name"] == "weather"
+assert chat.tool_choice == "weather"
+assert chat.system_prompt == "Be brief."
+"#);
+    }
+
+    #[test]
+    fn test_dry_run_builds_the_production_request() {
+        run(r#"
+chat = lb.Chat()
+chat.add_message(lb.Message.user("Hi"))
+chat.add_tool("weather", "Gets the weather", {"type": "object", "properties": {}})
+service = lb.Service("gpt-4o", api_key="sk-test")
+request = service.dry_run(chat)["request"]
+assert request["url"] == "https://api.openai.com/v1/chat/completions"
+assert request["body"]["model"] == "gpt-4o"
+assert request["body"]["tools"][0]["function"]["name"] == "weather"
+assert "sk-test" not in str(request["headers"])
+"#);
+    }
+
+    #[test]
+    fn test_errors_raise_module_exceptions() {
+        run(r#"
+try:
+    lb.Service("no-such-model", api_key="sk-test")
+except lb.ConfigError as e:
+    assert isinstance(e, lb.LanguageBarrierError)
+else:
+    raise AssertionError("expected ConfigError")
+"#);
+    }
+}
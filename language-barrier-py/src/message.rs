@@ -0,0 +1,80 @@
+use language_barrier_core::Message;
+use pyo3::prelude::*;
+
+use crate::{from_py, to_py};
+
+/// A chat message
+///
+/// Build one with `Message.system`, `Message.user`, `Message.assistant` or
+/// `Message.tool`, or from the dict form `to_dict` returns, which keeps tool
+/// calls and metadata.
+#[pyclass(name = "Message", module = "language_barrier", frozen)]
+#[derive(Clone)]
+pub struct PyMessage(pub(crate) Message);
+
+#[pymethods]
+impl PyMessage {
+    /// A system message
+    #[staticmethod]
+    fn system(text: String) -> Self {
+        Self(Message::system(text))
+    }
+
+    /// A user message
+    #[staticmethod]
+    fn user(text: String) -> Self {
+        Self(Message::user(text))
+    }
+
+    /// An assistant message
+    #[staticmethod]
+    fn assistant(text: String) -> Self {
+        Self(Message::assistant(text))
+    }
+
+    /// The result of the tool call with ID `tool_call_id`
+    #[staticmethod]
+    fn tool(tool_call_id: String, content: String) -> Self {
+        Self(Message::tool(tool_call_id, content))
+    }
+
+    /// A message from its dict form
+    #[staticmethod]
+    fn from_dict(message: &Bound<'_, PyAny>) -> PyResult<Self> {
+        from_py(message).map(Self)
+    }
+
+    /// The message as a dict, in the same shape `from_dict` reads
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py(py, &self.0)
+    }
+
+    /// `"system"`, `"user"`, `"assistant"` or `"tool"`
+    #[getter]
+    fn role(&self) -> &'static str {
+        self.0.role_str()
+    }
+
+    /// The message's text parts, joined by newlines
+    #[getter]
+    fn text(&self) -> String {
+        self.0.text_content()
+    }
+
+    /// The tool calls an assistant message makes, as dicts
+    #[getter]
+    fn tool_calls(&self, py: Python<'_>) -> PyResult<PyObject> {
+        match &self.0 {
+            Message::Assistant { tool_calls, .. } => to_py(py, tool_calls),
+            _ => to_py(py, &Vec::<()>::new()),
+        }
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Message.{}({:?})", self.0.role_str(), self.0.text_content())
+    }
+}
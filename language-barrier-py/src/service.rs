@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use language_barrier_core::alias::resolve_model;
+use language_barrier_core::inspect::DryRun;
+use language_barrier_core::model::AnyModel;
+use language_barrier_core::provider::anthropic::{AnthropicConfig, AnthropicProvider};
+use language_barrier_core::provider::gemini::{GeminiConfig, GeminiProvider};
+use language_barrier_core::provider::mistral::{MistralConfig, MistralProvider};
+use language_barrier_core::provider::openai::{OpenAIConfig, OpenAIProvider};
+use language_barrier_core::{Chat, HTTPLlmService, LLMService, Message, ModelInfo, Result};
+use pyo3::prelude::*;
+use tracing::debug;
+
+use crate::chat::PyChat;
+use crate::error::to_py_err;
+use crate::message::PyMessage;
+use crate::to_py;
+
+/// What the Python service needs from an `HTTPLlmService`, for any
+/// provider's model type
+#[async_trait]
+trait Backend: Send + Sync {
+    async fn generate(&self, chat: &Chat) -> Result<Message>;
+
+    fn dry_run(&self, chat: &Chat) -> Result<DryRun>;
+}
+
+#[async_trait]
+impl<M: ModelInfo> Backend for HTTPLlmService<M> {
+    async fn generate(&self, chat: &Chat) -> Result<Message> {
+        self.generate_next_message(chat).await
+    }
+
+    fn dry_run(&self, chat: &Chat) -> Result<DryRun> {
+        HTTPLlmService::dry_run(self, chat)
+    }
+}
+
+/// Builds the HTTP service for a hosted model, applying the key and base
+/// URL over the provider's defaults
+macro_rules! hosted {
+    ($model:expr, $api_key:expr, $base_url:expr, $provider_config:ty, $provider:ty) => {{
+        let mut builder = <$provider_config>::builder();
+        if let Some(key) = $api_key {
+            builder = builder.api_key(key);
+        }
+        if let Some(url) = $base_url {
+            builder = builder.base_url(url);
+        }
+        let provider = <$provider>::with_config(builder.build()?);
+        Arc::new(HTTPLlmService::new($model, Arc::new(provider)))
+    }};
+}
+
+fn backend(
+    model: AnyModel,
+    api_key: Option<String>,
+    base_url: Option<String>,
+) -> Result<Arc<dyn Backend>> {
+    Ok(match model {
+        AnyModel::Claude(model) => {
+            hosted!(model, api_key, base_url, AnthropicConfig, AnthropicProvider)
+        }
+        AnyModel::OpenAi(model) => hosted!(model, api_key, base_url, OpenAIConfig, OpenAIProvider),
+        AnyModel::Gemini(model) => hosted!(model, api_key, base_url, GeminiConfig, GeminiProvider),
+        AnyModel::Mistral(model) => {
+            hosted!(model, api_key, base_url, MistralConfig, MistralProvider)
+        }
+    })
+}
+
+/// Sends chats to one hosted model
+///
+/// The request for a chat is built by the same code production uses, so
+/// `dry_run` shows exactly what a Rust service would send.
+#[pyclass(name = "Service", module = "language_barrier", frozen)]
+pub struct PyService {
+    model: AnyModel,
+    backend: Arc<dyn Backend>,
+}
+
+#[pymethods]
+impl PyService {
+    /// A service for `model`, a model ID such as `"gpt-4o"` or an alias
+    /// such as `"fast"`
+    ///
+    /// Without `api_key`, the provider's standard environment variable is
+    /// read. Raises `ConfigError` if the model is unknown or the provider
+    /// config is invalid.
+    #[new]
+    #[pyo3(signature = (model, api_key=None, base_url=None))]
+    fn new(model: &str, api_key: Option<String>, base_url: Option<String>) -> PyResult<Self> {
+        let model = resolve_model(model).map_err(to_py_err)?;
+        debug!("Starting a Python service for {}", model);
+        let backend = backend(model, api_key, base_url).map_err(to_py_err)?;
+        Ok(Self { model, backend })
+    }
+
+    /// The model's ID
+    #[getter]
+    fn model(&self) -> String {
+        self.model.id()
+    }
+
+    /// The request `generate` would send for `chat`, as a dict with
+    /// credentials redacted, without sending it
+    fn dry_run(&self, py: Python<'_>, chat: PyRef<'_, PyChat>) -> PyResult<PyObject> {
+        let dry_run = self.backend.dry_run(&chat.0).map_err(to_py_err)?;
+        to_py(py, &dry_run)
+    }
+
+    /// Asks the model for the next message in `chat`; returns an awaitable
+    ///
+    /// The reply isn't added to the chat; call `chat.add_message(reply)`
+    /// to keep it.
+    fn generate<'py>(
+        &self,
+        py: Python<'py>,
+        chat: PyRef<'_, PyChat>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let backend = Arc::clone(&self.backend);
+        let chat = chat.0.clone();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let reply = backend.generate(&chat).await.map_err(to_py_err)?;
+            Ok(PyMessage(reply))
+        })
+    }
+
+    /// Like `generate`, but blocks until the reply arrives, for scripts
+    /// without an event loop
+    fn generate_blocking(&self, py: Python<'_>, chat: PyRef<'_, PyChat>) -> PyResult<PyMessage> {
+        let backend = Arc::clone(&self.backend);
+        let chat = chat.0.clone();
+        let reply = py.allow_threads(move || {
+            pyo3_async_runtimes::tokio::get_runtime().block_on(backend.generate(&chat))
+        });
+        reply.map(PyMessage).map_err(to_py_err)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Service({:?})", self.model.id())
+    }
+}
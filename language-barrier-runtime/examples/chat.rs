@@ -1,111 +1,476 @@
-use std::env;
-use std::{io, io::Write};
-
-use language_barrier_core::provider::anthropic::AnthropicConfig;
-use language_barrier_core::{model::Claude, provider::anthropic::AnthropicProvider};
-use language_barrier_runtime::{
-    middleware::{FinalInterpreter, GenerateNextMessageService, ServiceBuilder},
-    ops,
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::{env, thread};
+
+use language_barrier_core::message::ToolCall;
+use language_barrier_core::provider::anthropic::AnthropicProvider;
+use language_barrier_core::tool::ToolChoice;
+use language_barrier_core::usage::{Pricing, Usage};
+use language_barrier_core::{Chat, Claude, Message, Result, ToolDefinition};
+use language_barrier_runtime::events::{self, RuntimeEvent};
+use language_barrier_runtime::middleware::{
+    EventsMiddleware, GenerateNextMessageService, Runner, ToolExecutorMiddleware,
 };
-use tower_service::Service;
-use tracing::{Level, info};
-use tracing_subscriber::FmtSubscriber;
-
-/// A simple interactive chat application using the Tower middleware architecture.
-/// This demonstrates how to build a complete chat application with the language-barrier-runtime
-/// using the free monad operations pattern and Tower middleware.
-async fn run_chat() -> language_barrier_core::error::Result<()> {
-    // Get API key from the environment
+use language_barrier_runtime::ops::{self, LlmM};
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+
+/// A terminal chat client for Claude with a weather tool, built only on
+/// the crates' public APIs.
+///
+/// The screen shows the conversation, with each reply rendered from its
+/// `TokenDelta` event (one per reply, since providers don't stream yet), a
+/// panel of the tool calls the model made, and a footer totalling tokens
+/// and cost. Enter sends, Esc quits.
+///
+/// Run with `cargo run --example chat`, or `cargo run --example chat --
+/// --smoke` to send one scripted prompt, render it off-screen and exit with
+/// failure unless the reply's text arrived as an event, the tool ran and
+/// usage was counted.
+/// Both need `ANTHROPIC_API_KEY`.
+#[tokio::main]
+async fn main() -> ExitCode {
     let _ = dotenvy::dotenv();
-    let api_key = env::var("ANTHROPIC_API_KEY")
-        .expect("ANTHROPIC_API_KEY must be set as an environment variable");
-
-    println!("=== Language Barrier Chat ===");
-    println!("Type your messages and press enter to send.");
-    println!("Type 'exit' to quit.");
-    println!();
-
-    // Initialize the Anthropic provider
-    let provider = AnthropicProvider::with_config(AnthropicConfig {
-        api_key,
-        base_url: "https://api.anthropic.com/v1".to_string(),
-        api_version: "2023-06-01".to_string(),
-    });
+    if env::var("ANTHROPIC_API_KEY").is_err() {
+        eprintln!("ANTHROPIC_API_KEY must be set as an environment variable");
+        return ExitCode::FAILURE;
+    }
 
-    // Create the model and provider arcs
-    let model = std::sync::Arc::new(Claude::Opus3);
-    let provider = std::sync::Arc::new(provider);
-
-    // Configure the middleware stack
-    let mut service = ServiceBuilder::new().service(GenerateNextMessageService::new(
-        FinalInterpreter::new(),
-        model,
-        provider,
-    ));
-
-    // Keep track of conversation history
-    let mut chat = language_barrier_core::chat::Chat::default().with_system_prompt(
-        "You are a helpful assistant. Provide clear and concise answers to the user's questions.",
-    );
-
-    // Main conversation loop
-    loop {
-        // Print prompt
-        print!("> ");
-        io::stdout().flush().unwrap();
-
-        // Read user input
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read input");
-        let input = input.trim();
-
-        // Check for exit command
-        if input.to_lowercase() == "exit" {
-            println!("Goodbye!");
-            break;
+    let result = if env::args().any(|arg| arg == "--smoke") {
+        smoke().await
+    } else {
+        interactive().await
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
         }
+    }
+}
 
-        // Create user message
-        let user_message = ops::user_message(input);
+/// Claude 3.5 Haiku's list prices, in dollars per million tokens
+const PRICING: Pricing = Pricing {
+    input_per_million: 0.8,
+    output_per_million: 4.0,
+    cached_input_per_million: Some(0.08),
+};
 
-        // Add message to chat history
-        let add_message_program =
-            ops::add_message(chat.clone(), user_message.clone()).and_then(|chat_result| {
-                // Unwrap the Result<Chat<M>> to get the updated chat
-                let updated_chat = chat_result.unwrap();
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+struct WeatherRequest {
+    location: String,
+    #[schemars(default, description = "Temperature unit: 'celsius' or 'fahrenheit'")]
+    units: Option<String>,
+}
 
-                // Create a chat program with the updated history
-                ops::generate_next_message(updated_chat)
-            });
+#[derive(Debug, Clone, Serialize)]
+struct WeatherResponse {
+    temperature: i32,
+    conditions: String,
+    location: String,
+    units: String,
+}
 
-        // Send the message and get response
-        let result = service.call(add_message_program).await??;
+#[derive(Clone)]
+struct WeatherTool;
 
-        // Print the response
-        println!("Assistant: {:?}", result);
-        println!();
+impl ToolDefinition for WeatherTool {
+    type Input = WeatherRequest;
+    type Output = WeatherResponse;
 
-        // Update the chat history with both messages
-        chat = result.add_message(user_message);
+    fn name(&self) -> String {
+        "get_weather".to_string()
     }
 
-    Ok(())
+    fn description(&self) -> String {
+        "Get current weather for a location".to_string()
+    }
 }
 
-#[tokio::main]
-pub async fn main() {
-    // Set up tracing
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::DEBUG)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set tracing subscriber");
-
-    info!("Starting Language Barrier Chat");
-
-    // Run the chat application
-    if let Err(e) = run_chat().await {
-        eprintln!("Error: {}", e);
+/// Static weather, since the example is about the client, not the forecast
+fn weather(request: WeatherRequest) -> WeatherResponse {
+    WeatherResponse {
+        temperature: 45,
+        conditions: "Partly Cloudy".to_string(),
+        location: request.location,
+        units: request.units.unwrap_or_else(|| "fahrenheit".to_string()),
+    }
+}
+
+/// The middleware stack: events on top, so every step of a turn is seen
+fn runner(sender: broadcast::Sender<RuntimeEvent>) -> Runner<Result<Chat>> {
+    Runner::new(move |loopback| {
+        let model = Arc::new(Claude::Haiku35);
+        let provider = Arc::new(AnthropicProvider::new());
+        let generate = GenerateNextMessageService::new(loopback, model, provider);
+        let tools = ToolExecutorMiddleware::new(generate, WeatherTool, Arc::new(weather));
+        EventsMiddleware::new(tools).with_sender(sender)
+    })
+}
+
+/// One user turn: generates a reply, runs the tools it asks for and
+/// generates again, until the model answers without calling a tool
+fn turn(chat: Chat) -> LlmM<Result<Chat>> {
+    ops::generate_next_message(chat).and_then(|result| {
+        let chat = match result {
+            Ok(chat) => chat,
+            Err(e) => return LlmM::pure(Err(e)),
+        };
+        let tool_calls = match chat.most_recent_message() {
+            Some(Message::Assistant { tool_calls, .. }) if !tool_calls.is_empty() => {
+                tool_calls.clone()
+            }
+            _ => return LlmM::pure(Ok(chat)),
+        };
+        run_tools(chat, tool_calls).and_then(|result| match result {
+            Ok(chat) => turn(chat),
+            Err(e) => LlmM::pure(Err(e)),
+        })
+    })
+}
+
+/// Runs tool calls in order, adding each result to the chat
+///
+/// A failed call is reported to the model as the tool's result, so it can
+/// recover instead of ending the turn.
+fn run_tools(chat: Chat, tool_calls: Vec<ToolCall>) -> LlmM<Result<Chat>> {
+    tool_calls
+        .into_iter()
+        .fold(LlmM::pure(Ok(chat)), |program, tool_call| {
+            program.and_then(move |result| match result {
+                Ok(chat) => {
+                    let id = tool_call.id.clone();
+                    ops::execute_tool(tool_call).map(move |result| {
+                        let content = match result {
+                            Ok(result) => result.content,
+                            Err(e) => format!("Error: {}", e),
+                        };
+                        Ok(chat.add_message(Message::tool(id, content)))
+                    })
+                }
+                Err(e) => LlmM::pure(Err(e)),
+            })
+        })
+}
+
+/// A tool call as shown in the tool panel
+struct ToolRow {
+    id: String,
+    name: String,
+    arguments: String,
+    result: Option<std::result::Result<String, String>>,
+}
+
+/// Everything on screen
+struct App {
+    chat: Chat,
+    /// Reply text for the turn in progress, one entry per generation
+    pending: Vec<String>,
+    busy: bool,
+    tools: Vec<ToolRow>,
+    usage: Usage,
+    generations: u64,
+    input: String,
+    error: Option<String>,
+}
+
+impl App {
+    fn new() -> Self {
+        let chat = Chat::default()
+            .with_system_prompt("You are a helpful assistant with access to weather information.")
+            .with_tool(WeatherTool)
+            .expect("the weather tool's schema is valid")
+            .with_tool_choice(ToolChoice::Auto);
+        Self {
+            chat,
+            pending: Vec::new(),
+            busy: false,
+            tools: Vec::new(),
+            usage: Usage::default(),
+            generations: 0,
+            input: String::new(),
+            error: None,
+        }
+    }
+
+    /// Starts a turn for `text`, returning the chat to send
+    fn submit(&mut self, text: String) -> Chat {
+        self.chat = self.chat.clone().add_message(ops::user_message(text));
+        self.pending.clear();
+        self.error = None;
+        self.busy = true;
+        self.chat.clone()
+    }
+
+    fn apply(&mut self, event: RuntimeEvent) {
+        match event {
+            // Text that arrives after its turn ended is already in the history
+            RuntimeEvent::GenerationStarted { .. } if self.busy => self.pending.push(String::new()),
+            RuntimeEvent::GenerationStarted { .. } => {}
+            RuntimeEvent::TokenDelta { text, .. } => {
+                if let Some(current) = self.pending.last_mut() {
+                    current.push_str(&text);
+                }
+            }
+            RuntimeEvent::ToolCallRequested { tool_call, .. } => self.tools.push(ToolRow {
+                id: tool_call.id,
+                name: tool_call.function.name,
+                arguments: tool_call.function.arguments,
+                result: None,
+            }),
+            RuntimeEvent::ToolCallCompleted {
+                tool_call_id,
+                result,
+                ..
+            } => {
+                if let Some(row) = self.tools.iter_mut().find(|row| row.id == tool_call_id) {
+                    row.result = Some(result);
+                }
+            }
+            RuntimeEvent::GenerationFinished { usage, error, .. } => {
+                self.generations += 1;
+                self.usage += usage.unwrap_or_default();
+                if error.is_some() {
+                    self.error = error;
+                }
+            }
+        }
+    }
+
+    /// Ends the turn, keeping the chat it produced
+    fn finish(&mut self, result: Result<Chat>) {
+        self.busy = false;
+        self.pending.clear();
+        match result {
+            Ok(chat) => self.chat = chat,
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, input, footer] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [messages, tools] =
+            Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)])
+                .areas(main);
+
+        let transcript = Paragraph::new(self.transcript())
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(" Chat "));
+        let overflow = transcript
+            .line_count(messages.width)
+            .saturating_sub(messages.height as usize);
+        frame.render_widget(transcript.scroll((overflow as u16, 0)), messages);
+
+        let rows: Vec<ListItem> = self.tools.iter().map(ToolRow::item).collect();
+        frame.render_widget(
+            List::new(rows).block(Block::bordered().title(" Tool calls ")),
+            tools,
+        );
+
+        let prompt = if self.busy {
+            " Waiting… "
+        } else {
+            " Message (Enter sends, Esc quits) "
+        };
+        frame.render_widget(
+            Paragraph::new(self.input.as_str()).block(Block::bordered().title(prompt)),
+            input,
+        );
+        frame.render_widget(Paragraph::new(self.footer()), footer);
+    }
+
+    fn transcript(&self) -> Vec<Line<'_>> {
+        let mut lines = Vec::new();
+        let mut push = |speaker: &'static str, color: Color, text: &str| {
+            let mut text = text.lines();
+            let first = text.next().unwrap_or_default().to_string();
+            lines.push(Line::from(vec![
+                Span::styled(speaker, Style::new().fg(color).add_modifier(Modifier::BOLD)),
+                Span::raw(first),
+            ]));
+            lines.extend(text.map(|line| Line::raw(line.to_string())));
+            lines.push(Line::default());
+        };
+
+        for message in &self.chat.history {
+            match message {
+                Message::User { .. } => push("you: ", Color::Cyan, &message.text_content()),
+                Message::Assistant { .. } => {
+                    let text = message.text_content();
+                    if !text.is_empty() {
+                        push("claude: ", Color::Green, &text);
+                    }
+                }
+                // Tool results are in the tool panel
+                Message::System { .. } | Message::Tool { .. } => {}
+            }
+        }
+        // History gets the replies once the turn ends; until then, show
+        // the text their events carried
+        for text in self.pending.iter().filter(|text| !text.is_empty()) {
+            push("claude: ", Color::Green, text);
+        }
+        if let Some(error) = &self.error {
+            push("error: ", Color::Red, error);
+        }
+        lines
+    }
+
+    fn footer(&self) -> Line<'_> {
+        Line::from(format!(
+            " {} generations · {} in / {} out tokens · ${:.4}",
+            self.generations,
+            self.usage.input_tokens,
+            self.usage.output_tokens,
+            self.usage.cost(&PRICING),
+        ))
+        .dim()
+    }
+}
+
+impl ToolRow {
+    fn item(&self) -> ListItem<'_> {
+        let (status, color) = match &self.result {
+            None => ("…", Color::Yellow),
+            Some(Ok(_)) => ("✓", Color::Green),
+            Some(Err(_)) => ("✗", Color::Red),
+        };
+        let mut lines = vec![
+            Line::from(vec![
+                Span::styled(status, color),
+                Span::raw(" "),
+                self.name.as_str().bold(),
+            ]),
+            Line::raw(format!("  {}", self.arguments)).dim(),
+        ];
+        match &self.result {
+            Some(Ok(output)) => lines.push(Line::raw(format!("  → {}", output))),
+            Some(Err(e)) => lines.push(Line::raw(format!("  → {}", e)).red()),
+            None => {}
+        }
+        ListItem::new(lines)
+    }
+}
+
+async fn interactive() -> Result<()> {
+    let (sender, mut events) = events::channel();
+    let runner = Arc::new(runner(sender));
+    let (turns_sender, mut turns) = mpsc::unbounded_channel();
+
+    // Terminal input blocks, so it's read on its own thread
+    let (keys_sender, mut keys) = mpsc::unbounded_channel();
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event
+                && key.kind == KeyEventKind::Press
+                && keys_sender.send(key).is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut terminal = ratatui::init();
+    let mut app = App::new();
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| app.draw(frame)) {
+            break Err(e);
+        }
+        tokio::select! {
+            Some(key) = keys.recv() => {
+                let KeyEvent { code, modifiers, .. } = key;
+                match code {
+                    KeyCode::Esc => break Ok(()),
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => break Ok(()),
+                    KeyCode::Enter if !app.busy && !app.input.trim().is_empty() => {
+                        let text = std::mem::take(&mut app.input);
+                        let chat = app.submit(text);
+                        let runner = runner.clone();
+                        let turns = turns_sender.clone();
+                        tokio::spawn(async move {
+                            let result = runner.run(turn(chat)).await.and_then(|result| result);
+                            let _ = turns.send(result);
+                        });
+                    }
+                    KeyCode::Char(c) => app.input.push(c),
+                    KeyCode::Backspace => {
+                        app.input.pop();
+                    }
+                    _ => {}
+                }
+            }
+            Ok(event) = events.recv() => app.apply(event),
+            Some(result) = turns.recv() => app.finish(result),
+        }
+    };
+    ratatui::restore();
+    result.map_err(|e| language_barrier_core::Error::Other(e.to_string()))
+}
+
+/// Runs one scripted turn, renders it off-screen and checks that every
+/// part of the screen got data
+async fn smoke() -> Result<()> {
+    let (sender, mut events) = events::channel();
+    let runner = runner(sender);
+    let mut app = App::new();
+
+    let chat = app.submit("What's the weather in Paris? Use the tool.".to_string());
+    let result = runner.run(turn(chat)).await.and_then(|result| result);
+    while let Ok(event) = events.try_recv() {
+        app.apply(event);
+    }
+    let delivered = app.pending.iter().any(|text| !text.is_empty());
+    app.finish(result);
+
+    let mut terminal = Terminal::new(TestBackend::new(100, 30))
+        .map_err(|e| language_barrier_core::Error::Other(e.to_string()))?;
+    terminal
+        .draw(|frame| app.draw(frame))
+        .map_err(|e| language_barrier_core::Error::Other(e.to_string()))?;
+    let screen = terminal.backend().buffer().clone();
+
+    let checks = [
+        ("turn succeeded", app.error.is_none()),
+        ("reply text delivered", delivered),
+        (
+            "tool ran",
+            app.tools
+                .iter()
+                .any(|row| matches!(row.result, Some(Ok(_)))),
+        ),
+        (
+            "usage counted",
+            app.generations >= 2 && app.usage.output_tokens > 0,
+        ),
+    ];
+    for line in screen.content.chunks(screen.area.width as usize) {
+        println!(
+            "{}",
+            line.iter().map(|cell| cell.symbol()).collect::<String>()
+        );
+    }
+    for (name, passed) in checks {
+        println!("{} {}", if passed { "ok  " } else { "FAIL" }, name);
+    }
+    if checks.iter().all(|(_, passed)| *passed) {
+        Ok(())
+    } else {
+        Err(language_barrier_core::Error::Other(
+            "smoke test failed".to_string(),
+        ))
     }
 }
@@ -0,0 +1,73 @@
+use std::env;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use language_barrier_core::llm_service::HTTPLlmService;
+use language_barrier_core::preflight::{Preflight, PreflightReport};
+use language_barrier_core::provider::anthropic::AnthropicProvider;
+use language_barrier_core::provider::gemini::GeminiProvider;
+use language_barrier_core::provider::mistral::MistralProvider;
+use language_barrier_core::provider::openai::OpenAIProvider;
+use language_barrier_core::{Claude, Gemini, Mistral, OpenAi};
+
+/// Checks every provider with an API key in the environment and prints a
+/// JSON report, exiting with failure if any check found a problem.
+///
+/// Run with `cargo run --example doctor`.
+#[tokio::main]
+async fn main() -> ExitCode {
+    let _ = dotenvy::dotenv();
+    let configured = |var: &str| env::var(var).is_ok_and(|key| !key.is_empty());
+
+    let mut services: Vec<(&str, Box<dyn Preflight>)> = Vec::new();
+    if configured("OPENAI_API_KEY") {
+        let provider = Arc::new(OpenAIProvider::new());
+        services.push((
+            "openai",
+            Box::new(HTTPLlmService::new(OpenAi::GPT4oMini, provider)),
+        ));
+    }
+    if configured("ANTHROPIC_API_KEY") {
+        let provider = Arc::new(AnthropicProvider::new());
+        services.push((
+            "anthropic",
+            Box::new(HTTPLlmService::new(Claude::Haiku35, provider)),
+        ));
+    }
+    if configured("GEMINI_API_KEY") {
+        let provider = Arc::new(GeminiProvider::new());
+        services.push((
+            "gemini",
+            Box::new(HTTPLlmService::new(Gemini::Flash20, provider)),
+        ));
+    }
+    if configured("MISTRAL_API_KEY") {
+        let provider = Arc::new(MistralProvider::new());
+        services.push((
+            "mistral",
+            Box::new(HTTPLlmService::new(Mistral::Small, provider)),
+        ));
+    }
+
+    if services.is_empty() {
+        eprintln!("No provider API keys found in the environment");
+        return ExitCode::FAILURE;
+    }
+
+    let report = PreflightReport::run(
+        services
+            .iter()
+            .map(|(name, service)| (*name, service.as_ref())),
+    )
+    .await;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).expect("report serializes")
+    );
+
+    if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -63,11 +63,14 @@ async fn run_weather_chat() -> language_barrier_core::error::Result<()> {
     println!();
 
     // Initialize the Anthropic provider
-    let provider = AnthropicProvider::with_config(AnthropicConfig {
-        api_key,
-        base_url: "https://api.anthropic.com/v1".to_string(),
-        api_version: "2023-06-01".to_string(),
-    });
+    let provider = AnthropicProvider::with_config(
+        AnthropicConfig::builder()
+            .api_key(api_key)
+            .base_url("https://api.anthropic.com/v1")
+            .api_version("2023-06-01")
+            .build()
+            .unwrap(),
+    );
 
     // Create the model and provider arcs
     let model = Arc::new(Claude::Haiku35);
@@ -148,10 +151,10 @@ async fn run_weather_chat() -> language_barrier_core::error::Result<()> {
         let result = service.call(add_message_program).await??;
         println!("{:?}", result.most_recent_message());
 
-        if let Some(Message::Assistant { tool_calls, .. }) = result.most_recent_message() {
-            if !tool_calls.is_empty() {
-                break;
-            }
+        if let Some(Message::Assistant { tool_calls, .. }) = result.most_recent_message()
+            && !tool_calls.is_empty()
+        {
+            break;
         }
 
         // Update the chat history with both messages
@@ -0,0 +1,578 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use language_barrier_core::{
+    Chat, LLMService, LlmToolInfo, Message, ModelInfo, ToolDefinition,
+    error::{Error, Result},
+};
+use tracing::{debug, warn};
+
+use crate::ops::{self, LlmM, and_then_ok};
+
+/// A model an [`Agent`] talks to instead of the pipeline's default
+///
+/// This is [`LLMService`] with the model type erased, so agents backed by
+/// different providers and models can be mixed in one workflow. Build one
+/// with [`Agent::with_model`].
+#[async_trait]
+pub trait AgentModel: Send + Sync {
+    /// Generates the next message in the conversation
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message>;
+}
+
+struct ServiceModel<M> {
+    service: Arc<dyn LLMService<M> + Send + Sync>,
+}
+
+#[async_trait]
+impl<M: ModelInfo + 'static> AgentModel for ServiceModel<M> {
+    async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+        self.service.generate_next_message(chat).await
+    }
+}
+
+/// How much of a conversation moves to the agent it is handed off to
+///
+/// Handing a long transcript over in full can overflow the receiving
+/// agent's context window; see [`ops::handoff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextPolicy {
+    /// Transfer the whole history
+    Full,
+    /// Transfer only the most recent messages, at least one
+    ///
+    /// The window starts at a user turn, so the target never opens on a
+    /// reply or a tool result whose call it can't see: it's cut back to the
+    /// first user message among the last `k`, or, if there's none, widened
+    /// to the user message before them. A history without user messages is
+    /// only cut back past leading tool results.
+    LastK(usize),
+    /// Transfer a summary written by the receiving agent's model, followed
+    /// by the pending user message if the conversation ends with one
+    Summary,
+}
+
+impl ContextPolicy {
+    const SUMMARY_PROMPT: &'static str = "You are handing a conversation over to a colleague. \
+         Summarize it so they can continue without reading it: the user's goals, \
+         decisions made, facts established, and anything still open. \
+         Reply with the summary only.";
+
+    /// Returns a program producing the transcript the receiving agent starts from
+    pub(crate) fn transfer(self, transcript: Chat, target: &Agent) -> LlmM<Result<Chat>> {
+        match self {
+            ContextPolicy::Full => LlmM::pure(Ok(transcript)),
+            ContextPolicy::LastK(k) => {
+                let history = &transcript.history;
+                let window = history.len().saturating_sub(k.max(1));
+                let is_user = |msg: &Message| matches!(msg, Message::User { .. });
+                let start = history[window..]
+                    .iter()
+                    .position(is_user)
+                    .map(|offset| window + offset)
+                    .or_else(|| history[..window].iter().rposition(is_user))
+                    .unwrap_or_else(|| {
+                        let results = history[window..]
+                            .iter()
+                            .take_while(|msg| matches!(msg, Message::Tool { .. }))
+                            .count();
+                        window + results
+                    });
+                let history = history[start..].to_vec();
+                LlmM::pure(Ok(transcript.with_history(history)))
+            }
+            ContextPolicy::Summary => {
+                let mut earlier = transcript.history.clone();
+                let pending = match earlier.last() {
+                    Some(Message::User { .. }) => earlier.pop(),
+                    _ => None,
+                };
+                if earlier.is_empty() {
+                    return LlmM::pure(Ok(transcript));
+                }
+
+                let rendered = earlier
+                    .iter()
+                    .filter_map(|msg| {
+                        let text = msg.text_content();
+                        let speaker = match msg {
+                            Message::System { .. } => "system",
+                            Message::User { .. } => "user",
+                            Message::Assistant { .. } => Agent::author(msg).unwrap_or("assistant"),
+                            Message::Tool { .. } => "tool",
+                        };
+                        (!text.is_empty()).then(|| format!("{speaker}: {text}"))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                let request = Chat::default().add_message(Message::user(rendered));
+                let summarizer = Agent {
+                    tools: Vec::new(),
+                    ..target.clone().with_system_prompt(Self::SUMMARY_PROMPT)
+                };
+
+                summarizer.reply(&request).map(move |summary| {
+                    let summary = summary?.text_content();
+                    debug!("Summarized {} messages for handoff", earlier.len());
+                    let mut history = vec![Message::user(format!(
+                        "Summary of the conversation so far:\n{summary}"
+                    ))];
+                    history.extend(pending);
+                    Ok(transcript.with_history(history))
+                })
+            }
+        }
+    }
+}
+
+/// A named participant in a multi-agent conversation
+///
+/// An agent has its own system prompt, toolbox and, optionally, its own
+/// model. Agents share one transcript: a [`Chat`] whose assistant messages
+/// are tagged with the name of the agent that wrote them. Before each turn
+/// the transcript is rendered from the agent's point of view (see
+/// [`view`](Self::view)), so each agent only ever sees its own system prompt
+/// and tools, and sees other agents' replies as attributed user messages.
+///
+/// Agents are plain values; running one produces a free-monad program (see
+/// [`respond`](Self::respond) and the [`orchestration`](crate::orchestration)
+/// patterns) that is executed by the usual middleware stack.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::{Chat, Message};
+/// use language_barrier_runtime::agent::Agent;
+///
+/// let critic = Agent::new("critic", "You point out flaws in the previous answer.");
+/// let transcript = Chat::default()
+///     .add_message(Message::user("Explain TCP slow start."))
+///     .add_message(
+///         Message::assistant("It doubles the window every RTT.")
+///             .with_metadata(Agent::METADATA_KEY, "writer".into()),
+///     );
+///
+/// let view = critic.view(&transcript);
+/// assert_eq!(view.system_prompt, "You point out flaws in the previous answer.");
+/// assert_eq!(view.history[1], Message::user("[writer]: It doubles the window every RTT."));
+/// assert_eq!(view.history[1].message_id(), transcript.history[1].message_id());
+/// ```
+#[derive(Clone)]
+pub struct Agent {
+    name: String,
+    description: String,
+    system_prompt: String,
+    tools: Vec<LlmToolInfo>,
+    model: Option<Arc<dyn AgentModel>>,
+    max_tool_rounds: usize,
+}
+
+impl fmt::Debug for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Agent")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("system_prompt", &self.system_prompt)
+            .field("tools", &self.tools)
+            .field("model", &self.model.as_ref().map(|_| "<model>"))
+            .field("max_tool_rounds", &self.max_tool_rounds)
+            .finish()
+    }
+}
+
+impl Agent {
+    /// The message metadata key holding the name of the agent that wrote it
+    pub const METADATA_KEY: &'static str = "agent";
+
+    /// The default number of tool-call rounds allowed in one turn
+    pub const DEFAULT_MAX_TOOL_ROUNDS: usize = 8;
+
+    /// Creates an agent with no tools that uses the pipeline's model
+    pub fn new(name: impl Into<String>, system_prompt: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: String::new(),
+            system_prompt: system_prompt.into(),
+            tools: Vec::new(),
+            model: None,
+            max_tool_rounds: Self::DEFAULT_MAX_TOOL_ROUNDS,
+        }
+    }
+
+    /// Replaces the agent's system prompt
+    #[must_use]
+    pub fn with_system_prompt(self, system_prompt: impl Into<String>) -> Self {
+        Self {
+            system_prompt: system_prompt.into(),
+            ..self
+        }
+    }
+
+    /// Sets the description routers use to choose this agent
+    #[must_use]
+    pub fn with_description(self, description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            ..self
+        }
+    }
+
+    /// Adds a tool to this agent's toolbox
+    ///
+    /// Tool calls are executed with the `ExecuteTool` operation, so the
+    /// pipeline needs a matching `ToolExecutorMiddleware`.
+    pub fn with_tool(self, tool: impl ToolDefinition) -> Result<Self> {
+        let info = LlmToolInfo {
+            name: tool.name(),
+            description: tool.description(),
+            parameters: tool.schema()?,
+        };
+        Ok(self.with_tools(vec![info]))
+    }
+
+    /// Adds several tools to this agent's toolbox
+    #[must_use]
+    pub fn with_tools(self, tools: Vec<LlmToolInfo>) -> Self {
+        let mut all = self.tools;
+        all.extend(tools);
+        Self { tools: all, ..self }
+    }
+
+    /// Sends this agent's turns to `service` instead of the pipeline's model
+    #[must_use]
+    pub fn with_model<M: ModelInfo + 'static>(
+        self,
+        service: Arc<dyn LLMService<M> + Send + Sync>,
+    ) -> Self {
+        Self {
+            model: Some(Arc::new(ServiceModel { service })),
+            ..self
+        }
+    }
+
+    /// Limits how many rounds of tool calls one turn may run
+    ///
+    /// When the limit is reached the turn ends with the agent's unanswered
+    /// tool calls as its last message.
+    #[must_use]
+    pub fn with_max_tool_rounds(self, rounds: usize) -> Self {
+        Self {
+            max_tool_rounds: rounds,
+            ..self
+        }
+    }
+
+    /// Returns the agent's name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the agent's description
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Returns the agent's system prompt
+    pub fn system_prompt(&self) -> &str {
+        &self.system_prompt
+    }
+
+    /// Returns the agent's toolbox
+    pub fn tools(&self) -> &[LlmToolInfo] {
+        &self.tools
+    }
+
+    /// Returns the name of the agent that wrote `msg`, if any
+    pub fn author(msg: &Message) -> Option<&str> {
+        msg.metadata()
+            .get(Self::METADATA_KEY)
+            .and_then(serde_json::Value::as_str)
+    }
+
+    /// Renders the shared transcript as this agent sees it
+    ///
+    /// The transcript's system prompt and tools are replaced by the agent's
+    /// own; system segments are kept as shared context. Replies written by
+    /// other agents become user messages prefixed with the author's name,
+    /// and their tool calls and tool results are left out. Untagged
+    /// assistant messages are kept as they are.
+    pub fn view(&self, transcript: &Chat) -> Chat {
+        let mut foreign_calls = HashSet::new();
+        let history = transcript
+            .history
+            .iter()
+            .filter_map(|msg| match msg {
+                Message::Assistant { tool_calls, .. } => match Self::author(msg) {
+                    Some(author) if author != self.name => {
+                        foreign_calls.extend(tool_calls.iter().map(|call| call.id.clone()));
+                        let text = msg.text_content();
+                        (!text.is_empty()).then(|| {
+                            let quoted = Message::user(format!("[{author}]: {text}"));
+                            // Stands for the original, so keeps its ID
+                            match msg.message_id() {
+                                Some(id) => quoted.with_message_id(id),
+                                None => quoted,
+                            }
+                        })
+                    }
+                    _ => Some(msg.clone()),
+                },
+                Message::Tool { tool_call_id, .. } if foreign_calls.contains(tool_call_id) => None,
+                _ => Some(msg.clone()),
+            })
+            .collect();
+
+        let mut view = transcript
+            .clone()
+            .with_history(history)
+            .with_system_prompt(self.system_prompt.clone());
+        view.tools = (!self.tools.is_empty()).then(|| self.tools.clone());
+        view
+    }
+
+    /// Returns a program in which this agent takes one turn
+    ///
+    /// The agent's reply is tagged with its name and appended to the
+    /// transcript. If the reply requests tools, each call is executed, its
+    /// result appended, and the agent goes again, up to the tool round limit.
+    pub fn respond(&self, transcript: Chat) -> LlmM<Result<Chat>> {
+        self.respond_within(transcript, self.max_tool_rounds)
+    }
+
+    /// Returns a program generating one reply from this agent's point of view
+    ///
+    /// Unlike [`respond`](Self::respond), the reply isn't appended to the
+    /// transcript and tool calls aren't executed.
+    pub fn reply(&self, transcript: &Chat) -> LlmM<Result<Message>> {
+        let view = self.view(transcript);
+        let generation = match &self.model {
+            Some(model) => ops::generate_with_model(view, model.clone()),
+            None => ops::generate_next_message(view),
+        };
+
+        let name = self.name.clone();
+        generation.map(move |result| {
+            let view = result?;
+            let reply = view
+                .history
+                .last()
+                .cloned()
+                .ok_or_else(|| Error::Other(format!("Agent {name} produced no message")))?;
+            Ok(reply.with_metadata(Self::METADATA_KEY, serde_json::Value::String(name)))
+        })
+    }
+
+    fn respond_within(&self, transcript: Chat, tool_rounds: usize) -> LlmM<Result<Chat>> {
+        let agent = self.clone();
+        self.reply(&transcript).and_then(move |reply| {
+            let reply = match reply {
+                Ok(reply) => reply,
+                Err(e) => return LlmM::pure(Err(e)),
+            };
+            debug!("Agent {} replied", agent.name);
+
+            let tool_calls = match &reply {
+                Message::Assistant { tool_calls, .. } => tool_calls.clone(),
+                _ => Vec::new(),
+            };
+            let transcript = transcript.add_message(reply);
+            if tool_calls.is_empty() {
+                return LlmM::pure(Ok(transcript));
+            }
+            if tool_rounds == 0 {
+                warn!(
+                    "Agent {} reached its tool round limit with {} calls pending",
+                    agent.name,
+                    tool_calls.len()
+                );
+                return LlmM::pure(Ok(transcript));
+            }
+
+            let with_results =
+                tool_calls
+                    .into_iter()
+                    .fold(LlmM::pure(Ok(transcript)), |program, tool_call| {
+                        and_then_ok(program, move |transcript| {
+                            ops::execute_tool(tool_call).map(move |result| {
+                                result.map(|result| {
+                                    transcript.add_message(Message::tool(
+                                        result.tool_call_id,
+                                        result.content,
+                                    ))
+                                })
+                            })
+                        })
+                    });
+            and_then_ok(with_results, move |transcript| {
+                agent.respond_within(transcript, tool_rounds - 1)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::{GenerateNextMessageService, Runner};
+    use language_barrier_core::Claude;
+    use language_barrier_core::provider::anthropic::AnthropicProvider;
+    use std::sync::Mutex;
+
+    /// Answers with fixed text, keeping the chats it was sent
+    #[derive(Default)]
+    struct Scripted {
+        requests: Mutex<Vec<Chat>>,
+    }
+
+    #[async_trait]
+    impl LLMService<Claude> for Scripted {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            self.requests.lock().unwrap().push(chat.clone());
+            Ok(Message::assistant("They want a refund for order 42."))
+        }
+    }
+
+    fn runner() -> Runner<Result<Chat>> {
+        Runner::new(|loopback| {
+            let model = Arc::new(Claude::Haiku35);
+            let provider = Arc::new(AnthropicProvider::new());
+            GenerateNextMessageService::new(loopback, model, provider)
+        })
+    }
+
+    fn transcript(history: Vec<Message>) -> Chat {
+        Chat::default().with_history(history)
+    }
+
+    /// Runs a transfer that needs no model call
+    fn transfer(policy: ContextPolicy, history: Vec<Message>) -> Vec<Message> {
+        let agent = Agent::new("target", "You help.");
+        let program = policy.transfer(transcript(history), &agent);
+        program.result.expect("no model call").unwrap().history
+    }
+
+    fn tool_round() -> Vec<Message> {
+        vec![
+            Message::user("Where is order 42?"),
+            Message::assistant("Let me check."),
+            Message::tool("call_1", "shipped"),
+            Message::assistant("It shipped yesterday."),
+        ]
+    }
+
+    #[test]
+    fn test_full_transfers_everything() {
+        assert_eq!(transfer(ContextPolicy::Full, tool_round()), tool_round());
+    }
+
+    #[test]
+    fn test_last_k_starts_at_a_user_turn() {
+        let mut history = tool_round();
+        history.push(Message::user("And order 43?"));
+        history.push(Message::assistant("That one is still packing."));
+
+        // The last three open on a reply, so they're cut back to the user turn
+        let transferred = transfer(ContextPolicy::LastK(3), history.clone());
+        assert_eq!(transferred, history[4..]);
+
+        // The last two hold the user turn exactly
+        assert_eq!(
+            transfer(ContextPolicy::LastK(2), history.clone()),
+            history[4..]
+        );
+        assert_eq!(transfer(ContextPolicy::LastK(10), history.clone()), history);
+    }
+
+    #[test]
+    fn test_last_k_widens_to_the_user_turn_before_the_window() {
+        // The last two are a tool result and a reply, with no user turn
+        let transferred = transfer(ContextPolicy::LastK(2), tool_round());
+        assert_eq!(transferred, tool_round());
+    }
+
+    #[test]
+    fn test_last_k_keeps_at_least_one_message() {
+        let history = vec![
+            Message::user("Hi"),
+            Message::assistant("Hello!"),
+            Message::user("Bye"),
+        ];
+        assert_eq!(
+            transfer(ContextPolicy::LastK(0), history.clone()),
+            history[2..]
+        );
+
+        // Ending on a reply, the one message widens to the turn it answers
+        assert_eq!(
+            transfer(ContextPolicy::LastK(0), tool_round()),
+            tool_round()
+        );
+    }
+
+    #[test]
+    fn test_last_k_without_user_turns_drops_leading_tool_results() {
+        let history = vec![
+            Message::assistant("Let me check."),
+            Message::tool("call_1", "shipped"),
+            Message::assistant("It shipped yesterday."),
+        ];
+        assert_eq!(
+            transfer(ContextPolicy::LastK(2), history.clone()),
+            history[2..]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_summary_replaces_the_history_and_keeps_the_pending_turn() {
+        let model = Arc::new(Scripted::default());
+        let target = Agent::new("billing", "You handle refunds.")
+            .with_model(model.clone() as Arc<dyn LLMService<Claude> + Send + Sync>);
+        let mut history = tool_round();
+        history.push(Message::user("Can I get a refund?"));
+
+        let program = ContextPolicy::Summary.transfer(transcript(history), &target);
+        let transferred = runner().run(program).await.unwrap().unwrap();
+        assert_eq!(
+            transferred.history,
+            [
+                Message::user(
+                    "Summary of the conversation so far:\nThey want a refund for order 42."
+                ),
+                Message::user("Can I get a refund?"),
+            ]
+        );
+
+        let requests = model.requests.lock().unwrap();
+        let request = &requests[0];
+        assert_eq!(request.system_prompt, ContextPolicy::SUMMARY_PROMPT);
+        assert!(request.tools.is_none());
+        let rendered = request.history[0].text_content();
+        assert!(rendered.starts_with("user: Where is order 42?"));
+        assert!(rendered.contains("tool: shipped"));
+        assert!(!rendered.contains("Can I get a refund?"));
+    }
+
+    #[test]
+    fn test_summary_of_only_a_pending_turn_is_a_no_op() {
+        let history = vec![Message::user("Can I get a refund?")];
+        assert_eq!(transfer(ContextPolicy::Summary, history.clone()), history);
+    }
+
+    #[tokio::test]
+    async fn test_handoff_continues_from_the_transferred_context() {
+        let model = Arc::new(Scripted::default());
+        let target = Agent::new("billing", "You handle refunds.")
+            .with_model(model.clone() as Arc<dyn LLMService<Claude> + Send + Sync>);
+        let mut history = tool_round();
+        history.push(Message::user("Can I get a refund?"));
+
+        let program = ops::handoff(transcript(history), target, ContextPolicy::LastK(1));
+        let chat = runner().run(program).await.unwrap().unwrap();
+        assert_eq!(chat.history.len(), 2);
+        assert_eq!(chat.history[0], Message::user("Can I get a refund?"));
+        assert_eq!(Agent::author(&chat.history[1]), Some("billing"));
+        assert_eq!(model.requests.lock().unwrap()[0].history.len(), 1);
+    }
+}
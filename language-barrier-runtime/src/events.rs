@@ -0,0 +1,134 @@
+//! Typed lifecycle events for front-ends
+//!
+//! An [`EventsMiddleware`] broadcasts a [`RuntimeEvent`] as each operation
+//! of a program starts and finishes, so a TUI or web front-end can show
+//! progress (a spinner per generation, the reply as it arrives, tool calls
+//! as they run) by subscribing to a channel instead of parsing `tracing`
+//! output.
+//!
+//! Events of one generation share its [`GenerationId`]; tool events are
+//! matched by the tool call's ID. Events serialize with an `event` tag, so
+//! they can be forwarded to a browser as-is.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use async_trait::async_trait;
+//! use language_barrier_core::message::{Function, ToolCall};
+//! use language_barrier_core::usage::Usage;
+//! use language_barrier_core::{Chat, Claude, Message, Result};
+//! use language_barrier_core::provider::anthropic::AnthropicProvider;
+//! use language_barrier_runtime::agent::AgentModel;
+//! use language_barrier_runtime::events::{self, RuntimeEvent};
+//! use language_barrier_runtime::middleware::{
+//!     EventsMiddleware, GenerateNextMessageService, Runner,
+//! };
+//! use language_barrier_runtime::ops;
+//!
+//! /// Always asks for the weather
+//! struct Forecaster;
+//!
+//! #[async_trait]
+//! impl AgentModel for Forecaster {
+//!     async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+//!         let call = ToolCall {
+//!             id: "call_1".into(),
+//!             tool_type: "function".into(),
+//!             function: Function { name: "weather".into(), arguments: "{}".into() },
+//!         };
+//!         let usage = Usage::new(12, 3);
+//!         Ok(Message::assistant_with_tool_calls(vec![call])
+//!             .with_metadata(Usage::METADATA_KEY, serde_json::to_value(usage).unwrap()))
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let (sender, mut receiver) = events::channel();
+//! let runner = Runner::new(move |loopback| {
+//!     let model = Arc::new(Claude::Haiku35);
+//!     let provider = Arc::new(AnthropicProvider::new());
+//!     let generate = GenerateNextMessageService::new(loopback, model, provider);
+//!     EventsMiddleware::new(generate).with_sender(sender)
+//! });
+//!
+//! let chat = Chat::default().add_message(Message::user("Weather?"));
+//! runner.run(ops::generate_with_model(chat, Arc::new(Forecaster))).await.unwrap().unwrap();
+//!
+//! let started = receiver.recv().await.unwrap();
+//! assert!(matches!(started, RuntimeEvent::GenerationStarted { id: 0, messages: 1, .. }));
+//! let requested = receiver.recv().await.unwrap();
+//! assert!(matches!(requested, RuntimeEvent::ToolCallRequested { id: 0, .. }));
+//! let RuntimeEvent::GenerationFinished { usage, error: None, .. } = receiver.recv().await.unwrap()
+//! else {
+//!     panic!("expected the generation to finish");
+//! };
+//! assert_eq!(usage, Some(Usage::new(12, 3)));
+//! # }
+//! ```
+//!
+//! [`EventsMiddleware`]: crate::middleware::EventsMiddleware
+
+use language_barrier_core::{
+    message::ToolCall,
+    usage::{StreamTiming, Usage},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// How many events a subscriber may fall behind before it lags
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Identifies one model call among the events of a middleware stack
+pub type GenerationId = u64;
+
+/// Something that happened while running a program
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RuntimeEvent {
+    /// A model call began
+    GenerationStarted {
+        id: GenerationId,
+        /// The operation's name, e.g. `GenerateNextMessage`
+        op: String,
+        /// How many messages the model was sent
+        messages: usize,
+    },
+    /// Text of the reply
+    ///
+    /// Providers don't stream yet, so a reply's text arrives as a single
+    /// delta just before [`GenerationFinished`](Self::GenerationFinished).
+    TokenDelta { id: GenerationId, text: String },
+    /// The reply asks for a tool to be called
+    ToolCallRequested {
+        id: GenerationId,
+        tool_call: ToolCall,
+    },
+    /// A tool call ran, with its output or error message
+    ToolCallCompleted {
+        tool_call_id: String,
+        name: String,
+        result: Result<String, String>,
+    },
+    /// A model call ended
+    GenerationFinished {
+        id: GenerationId,
+        /// The tokens the reply used, if the provider reported them
+        usage: Option<Usage>,
+        /// First-token latency and streaming time, if the reply was
+        /// streamed; with `usage`, gives its tokens per second
+        timing: Option<StreamTiming>,
+        /// Why the call failed, if it did
+        error: Option<String>,
+    },
+}
+
+/// Creates a channel for [`EventsMiddleware::with_sender`] with the
+/// default capacity
+///
+/// [`EventsMiddleware::with_sender`]: crate::middleware::EventsMiddleware::with_sender
+pub fn channel() -> (broadcast::Sender<RuntimeEvent>, broadcast::Receiver<RuntimeEvent>) {
+    broadcast::channel(DEFAULT_CAPACITY)
+}
@@ -4,8 +4,13 @@
 //! middleware to represent and execute LLM operations.
 
 // Re-export modules
+pub mod agent;
+pub mod events;
 pub mod middleware;
 pub mod ops;
+pub mod orchestration;
+pub mod trace;
+pub mod workflow;
 
 // Re-export core types for convenience
 pub use language_barrier_core;
\ No newline at end of file
@@ -0,0 +1,459 @@
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use language_barrier_core::{
+    Chat,
+    error::{Error, Result},
+    message::{Content, FinishReason, Message},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tower_service::Service;
+use tracing::{debug, warn};
+
+use crate::ops::{LlmM, LlmOp};
+
+use super::BoxFuture;
+
+/// Probabilities and timings for [`ChaosMiddleware`]
+///
+/// Every probability defaults to zero, so a default config injects nothing.
+/// Probabilities are clamped to `0.0..=1.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    /// Chance that a call is delayed before it's sent
+    pub delay_probability: f64,
+    /// Shortest injected delay
+    pub min_delay: Duration,
+    /// Longest injected delay
+    pub max_delay: Duration,
+    /// Chance that a call fails with `Error::RateLimit` without being sent
+    pub rate_limit_probability: f64,
+    /// Chance that a successful response is cut off halfway
+    pub truncate_probability: f64,
+    /// Seed for the random generator; the same seed gives the same faults
+    pub seed: Option<u64>,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            delay_probability: 0.0,
+            min_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            rate_limit_probability: 0.0,
+            truncate_probability: 0.0,
+            seed: None,
+        }
+    }
+}
+
+impl ChaosConfig {
+    /// Creates a config that injects nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays calls by a random duration in `min..=max` with `probability`
+    pub fn with_delay(self, probability: f64, min: Duration, max: Duration) -> Self {
+        Self {
+            delay_probability: probability,
+            min_delay: min,
+            max_delay: max.max(min),
+            ..self
+        }
+    }
+
+    /// Fails calls with a rate-limit error with `probability`
+    pub fn with_rate_limits(self, probability: f64) -> Self {
+        Self {
+            rate_limit_probability: probability,
+            ..self
+        }
+    }
+
+    /// Truncates successful responses with `probability`
+    pub fn with_truncation(self, probability: f64) -> Self {
+        Self {
+            truncate_probability: probability,
+            ..self
+        }
+    }
+
+    /// Seeds the random generator so runs are reproducible
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+}
+
+/// The faults picked for one call
+struct Faults {
+    delay: Option<Duration>,
+    rate_limit: bool,
+    truncate: bool,
+}
+
+/// Middleware that injects faults into model calls
+///
+/// GenerateNextMessage and GenerateWithModel operations are randomly delayed,
+/// failed with `Error::RateLimit` before reaching the model, or have the
+/// response's text cut off halfway and its tool calls dropped, as if the
+/// connection closed mid-reply. A cut-off reply is marked
+/// [`truncated`](Message::truncated) with [`FinishReason::Length`], as a
+/// provider would report it, so
+/// [`Chat::continue_last`](language_barrier_core::Chat::continue_last) can
+/// finish it. Use it in CI to check that an agent program (and the retry
+/// or fallback middleware around it) copes with a flaky provider. Other
+/// operations pass through untouched.
+///
+/// Place it outside the middleware that calls the model. Clones share one
+/// random generator, so a seeded pipeline injects the same faults in the
+/// same order on every run.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use async_trait::async_trait;
+/// use language_barrier_core::provider::anthropic::AnthropicProvider;
+/// use language_barrier_core::{Chat, Claude, FinishReason, Message, Result};
+/// use language_barrier_runtime::agent::AgentModel;
+/// use language_barrier_runtime::middleware::{
+///     ChaosConfig, ChaosMiddleware, GenerateNextMessageService, Runner,
+/// };
+/// use language_barrier_runtime::ops;
+///
+/// struct Greeter;
+///
+/// #[async_trait]
+/// impl AgentModel for Greeter {
+///     async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+///         Ok(Message::assistant("Hello there!"))
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let runner = Runner::new(|loopback| {
+///     let model = Arc::new(Claude::Haiku35);
+///     let provider = Arc::new(AnthropicProvider::new());
+///     let generate = GenerateNextMessageService::new(loopback, model, provider);
+///     ChaosMiddleware::new(generate, ChaosConfig::new().with_truncation(1.0).with_seed(7))
+/// });
+///
+/// let chat = Chat::default().add_message(Message::user("Hi"));
+/// let program = ops::generate_with_model(chat, Arc::new(Greeter));
+/// let chat = runner.run(program).await.unwrap().unwrap();
+///
+/// let reply = &chat.history[1];
+/// assert_eq!(reply.text_content(), "Hello ");
+/// assert!(reply.is_truncated());
+/// assert_eq!(reply.finish_reason(), Some(FinishReason::Length));
+/// # }
+/// ```
+pub struct ChaosMiddleware<S> {
+    inner: S,
+    config: ChaosConfig,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl<S: Clone> Clone for ChaosMiddleware<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+}
+
+impl<S> ChaosMiddleware<S> {
+    /// Creates a new ChaosMiddleware with the given fault config
+    pub fn new(inner: S, config: ChaosConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            inner,
+            config,
+            rng: Arc::new(Mutex::new(rng)),
+        }
+    }
+
+    /// Rolls the dice for one call
+    fn pick_faults(&self) -> Faults {
+        let mut rng = self
+            .rng
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut roll = |probability: f64| rng.gen_bool(probability.clamp(0.0, 1.0));
+
+        let delayed = roll(self.config.delay_probability);
+        let rate_limit = roll(self.config.rate_limit_probability);
+        let truncate = roll(self.config.truncate_probability);
+        let delay = delayed.then(|| {
+            rng.gen_range(self.config.min_delay..=self.config.max_delay.max(self.config.min_delay))
+        });
+
+        Faults {
+            delay,
+            rate_limit,
+            truncate,
+        }
+    }
+}
+
+/// Cuts the text of the chat's last assistant message in half, drops its
+/// tool calls and marks it as cut off
+fn truncate_last_message(mut chat: Chat) -> Chat {
+    if let Some(last) = chat.history.last_mut()
+        && let text = last.text_content()
+        && let Message::Assistant {
+            content,
+            tool_calls,
+            metadata,
+        } = last
+    {
+        let kept = text.chars().count() / 2;
+        *content = Some(Content::Text(text.chars().take(kept).collect()));
+        tool_calls.clear();
+        metadata.insert(Message::TRUNCATED_KEY.to_string(), true.into());
+        metadata.insert(
+            FinishReason::METADATA_KEY.to_string(),
+            FinishReason::Length.to_metadata(),
+        );
+    }
+    chat
+}
+
+/// Wraps a continuation so it sees a truncated response
+fn truncating<Next: 'static>(
+    next: Box<dyn FnOnce(Result<Chat>) -> Next + Send>,
+) -> Box<dyn FnOnce(Result<Chat>) -> Next + Send> {
+    Box::new(move |result| next(result.map(truncate_last_message)))
+}
+
+impl<S, A> Service<LlmM<A>> for ChaosMiddleware<S>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let operation = program.op.take();
+        let result = program.result;
+        let faults = match operation {
+            Some(LlmOp::GenerateNextMessage { .. } | LlmOp::GenerateWithModel { .. }) => {
+                Some(self.pick_faults())
+            }
+            _ => None,
+        };
+
+        Box::pin(async move {
+            let Some(op) = operation else {
+                // If the op is None, then there should be a result
+                return match result {
+                    Some(result) => Ok(result),
+                    None => Err(Error::Other(
+                        "Invalid program state: both op and result are None".into(),
+                    )),
+                };
+            };
+            let Some(faults) = faults else {
+                // Not our operation, repackage and pass through
+                return inner.call(LlmM::new(op)).await;
+            };
+
+            if let Some(delay) = faults.delay {
+                debug!("Chaos: delaying model call by {:?}", delay);
+                tokio::time::sleep(delay).await;
+            }
+
+            let op = match op {
+                LlmOp::GenerateNextMessage { next, .. } | LlmOp::GenerateWithModel { next, .. }
+                    if faults.rate_limit =>
+                {
+                    warn!("Chaos: failing model call with a rate limit");
+                    let error = Error::RateLimit("injected by ChaosMiddleware".to_string());
+                    return inner.call(next(Err(error))).await;
+                }
+                LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next,
+                } if faults.truncate => {
+                    warn!("Chaos: truncating model response");
+                    LlmOp::GenerateNextMessage {
+                        chat,
+                        retries,
+                        next: truncating(next),
+                    }
+                }
+                LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next,
+                } if faults.truncate => {
+                    warn!("Chaos: truncating model response");
+                    LlmOp::GenerateWithModel {
+                        chat,
+                        model,
+                        retries,
+                        next: truncating(next),
+                    }
+                }
+                op => op,
+            };
+
+            inner.call(LlmM::new(op)).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentModel;
+    use crate::middleware::{GenerateNextMessageService, Runner};
+    use crate::ops;
+    use async_trait::async_trait;
+    use language_barrier_core::Claude;
+    use language_barrier_core::message::{Function, ToolCall};
+    use language_barrier_core::provider::anthropic::AnthropicProvider;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::time::Instant;
+
+    type Program = Result<Chat>;
+
+    /// Replies with text and a tool call, counting the calls it gets
+    #[derive(Default)]
+    struct Counting(AtomicU32);
+
+    #[async_trait]
+    impl AgentModel for Counting {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            let call = ToolCall {
+                id: "call_1".to_string(),
+                tool_type: "function".to_string(),
+                function: Function {
+                    name: "lookup".to_string(),
+                    arguments: "{}".to_string(),
+                },
+            };
+            Ok(Message::Assistant {
+                content: Some(Content::Text("Hello there!".to_string())),
+                tool_calls: vec![call],
+                metadata: Default::default(),
+            })
+        }
+    }
+
+    fn runner(config: ChaosConfig) -> Runner<Program> {
+        Runner::new(|loopback| {
+            let model = Arc::new(Claude::Haiku35);
+            let provider = Arc::new(AnthropicProvider::new());
+            let generate = GenerateNextMessageService::new(loopback, model, provider);
+            ChaosMiddleware::new(generate, config.with_seed(42))
+        })
+    }
+
+    async fn run(runner: &Runner<Program>, model: Arc<Counting>) -> Program {
+        let chat = Chat::default().add_message(Message::user("Hi"));
+        runner
+            .run(ops::generate_with_model(chat, model))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delays_calls() {
+        let runner = runner(ChaosConfig::new().with_delay(
+            1.0,
+            Duration::from_secs(3),
+            Duration::from_secs(3),
+        ));
+        let model = Arc::new(Counting::default());
+
+        let started = Instant::now();
+        let chat = run(&runner, model.clone()).await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(3));
+        assert_eq!(chat.history[1].text_content(), "Hello there!");
+        assert_eq!(model.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_calls_before_they_are_sent() {
+        let runner = runner(ChaosConfig::new().with_rate_limits(1.0));
+        let model = Arc::new(Counting::default());
+
+        let error = run(&runner, model.clone()).await.unwrap_err();
+        assert!(matches!(error, Error::RateLimit(_)));
+        assert_eq!(model.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_replies_are_marked_as_cut_off() {
+        let runner = runner(ChaosConfig::new().with_truncation(1.0));
+        let chat = run(&runner, Arc::default()).await.unwrap();
+
+        let Message::Assistant { tool_calls, .. } = &chat.history[1] else {
+            panic!("expected an assistant reply");
+        };
+        assert!(tool_calls.is_empty());
+        assert_eq!(chat.history[1].text_content(), "Hello ");
+        assert!(chat.history[1].is_truncated());
+        assert_eq!(chat.history[1].finish_reason(), Some(FinishReason::Length));
+    }
+
+    #[tokio::test]
+    async fn test_default_config_injects_nothing() {
+        let runner = runner(ChaosConfig::default());
+        let chat = run(&runner, Arc::default()).await.unwrap();
+        assert!(!chat.history[1].is_truncated());
+        assert_eq!(chat.history[1].finish_reason(), None);
+    }
+
+    #[test]
+    fn test_same_seed_injects_the_same_faults() {
+        let config = ChaosConfig::new()
+            .with_delay(0.5, Duration::from_millis(1), Duration::from_millis(50))
+            .with_rate_limits(0.5)
+            .with_truncation(0.5)
+            .with_seed(7);
+        let picks = |chaos: &ChaosMiddleware<()>| {
+            (0..32)
+                .map(|_| {
+                    let faults = chaos.pick_faults();
+                    (faults.delay, faults.rate_limit, faults.truncate)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = picks(&ChaosMiddleware::new((), config.clone()));
+        assert_eq!(first, picks(&ChaosMiddleware::new((), config.clone())));
+        assert!(first.iter().any(|(delay, _, _)| delay.is_some()));
+        assert!(first.iter().any(|(_, rate_limit, _)| *rate_limit));
+        assert!(first.iter().any(|(_, _, truncate)| *truncate));
+
+        let reseeded = ChaosMiddleware::new((), config.with_seed(8));
+        assert_ne!(first, picks(&reseeded));
+    }
+}
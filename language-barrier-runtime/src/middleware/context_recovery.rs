@@ -0,0 +1,282 @@
+use std::task::{Context, Poll};
+
+use language_barrier_core::{
+    Chat,
+    compactor::{ChatHistoryCompactor, DropOldestCompactor},
+    error::{ApiErrorKind, Error, Result},
+};
+use tower_service::Service;
+use tracing::{debug, warn};
+
+use crate::ops::{LlmM, LlmOp};
+
+use super::BoxFuture;
+
+type Next<A> = Box<dyn FnOnce(Result<Chat>) -> LlmM<A> + Send>;
+
+/// Middleware that compacts the chat and retries when it overflows the
+/// model's context window
+///
+/// GenerateNextMessage and GenerateWithModel operations that fail with a
+/// context-length error are sent again once with their history compacted
+/// to `max_tokens`, instead of handing the error to the program. The
+/// compacted history is kept, so later turns continue from it. If
+/// compaction doesn't change the history, as when an already compacted
+/// retry overflows again, the error goes through as usual.
+///
+/// The retry is a new operation, so the stack must be built on a
+/// [`Runner`](super::Runner) for it to reach the model again. Place this
+/// middleware above the one that calls the model.
+#[derive(Clone)]
+pub struct ContextRecoveryMiddleware<S, C = DropOldestCompactor> {
+    inner: S,
+    compactor: C,
+    max_tokens: usize,
+}
+
+impl<S> ContextRecoveryMiddleware<S> {
+    /// Creates a new ContextRecoveryMiddleware that drops the oldest
+    /// messages until the history fits in `max_tokens`
+    pub fn new(inner: S, max_tokens: usize) -> Self {
+        Self {
+            inner,
+            compactor: DropOldestCompactor::default(),
+            max_tokens,
+        }
+    }
+}
+
+impl<S, C> ContextRecoveryMiddleware<S, C> {
+    /// Compacts with `compactor` instead of dropping the oldest messages
+    pub fn with_compactor<T: ChatHistoryCompactor>(
+        self,
+        compactor: T,
+    ) -> ContextRecoveryMiddleware<S, T> {
+        ContextRecoveryMiddleware {
+            inner: self.inner,
+            compactor,
+            max_tokens: self.max_tokens,
+        }
+    }
+}
+
+/// Returns true if `error` says the prompt didn't fit
+fn is_context_overflow(error: &Error) -> bool {
+    matches!(error, Error::ContextLengthExceeded(_))
+        || error
+            .api()
+            .is_some_and(|api| api.kind == ApiErrorKind::ContextLengthExceeded)
+}
+
+/// Wraps a continuation so an overflow becomes a retry with a compacted chat
+fn recovering<A: 'static, C: ChatHistoryCompactor + 'static>(
+    chat: Chat,
+    compactor: C,
+    max_tokens: usize,
+    retry: impl FnOnce(Chat, Next<A>) -> LlmOp<LlmM<A>> + Send + 'static,
+    next: Next<A>,
+) -> Next<A> {
+    Box::new(move |result| match result {
+        Err(e) if is_context_overflow(&e) => {
+            let compacted = chat.clone().compact_with(&compactor, max_tokens);
+            if compacted.history == chat.history {
+                warn!("Context overflow, and compaction freed nothing: {}", e);
+                return next(Err(e));
+            }
+            warn!(
+                "Context overflow, retrying with {} of {} messages: {}",
+                compacted.history.len(),
+                chat.history.len(),
+                e
+            );
+            LlmM::new(retry(compacted, next))
+        }
+        result => next(result),
+    })
+}
+
+impl<S, A, C> Service<LlmM<A>> for ContextRecoveryMiddleware<S, C>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+    C: ChatHistoryCompactor + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let compactor = self.compactor.clone();
+        let max_tokens = self.max_tokens;
+        let operation = program.op.take();
+        let result = program.result;
+
+        Box::pin(async move {
+            let op = match operation {
+                Some(LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next,
+                }) => {
+                    debug!("Guarding model call against context overflow");
+                    let next = recovering(
+                        chat.clone(),
+                        compactor,
+                        max_tokens,
+                        |chat, next| LlmOp::GenerateNextMessage {
+                            chat,
+                            retries: 0,
+                            next,
+                        },
+                        next,
+                    );
+                    LlmOp::GenerateNextMessage {
+                        chat,
+                        retries,
+                        next,
+                    }
+                }
+                Some(LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next,
+                }) => {
+                    debug!("Guarding model call against context overflow");
+                    let retry_model = model.clone();
+                    let next = recovering(
+                        chat.clone(),
+                        compactor,
+                        max_tokens,
+                        move |chat, next| LlmOp::GenerateWithModel {
+                            chat,
+                            model: retry_model,
+                            retries: 0,
+                            next,
+                        },
+                        next,
+                    );
+                    LlmOp::GenerateWithModel {
+                        chat,
+                        model,
+                        retries,
+                        next,
+                    }
+                }
+                Some(op) => op,
+                None => {
+                    // If the op is None, then there should be a result
+                    return match result {
+                        Some(result) => Ok(result),
+                        None => Err(Error::Other(
+                            "Invalid program state: both op and result are None".into(),
+                        )),
+                    };
+                }
+            };
+
+            inner.call(LlmM::new(op)).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentModel;
+    use crate::middleware::{GenerateNextMessageService, Runner};
+    use crate::ops;
+    use async_trait::async_trait;
+    use language_barrier_core::provider::anthropic::AnthropicProvider;
+    use language_barrier_core::{Claude, Message};
+    use std::sync::{Arc, Mutex};
+
+    /// Overflows on chats longer than `limit` messages, recording the length
+    /// of each chat it's sent
+    struct Cramped {
+        limit: usize,
+        sent: Mutex<Vec<usize>>,
+    }
+
+    impl Cramped {
+        fn new(limit: usize) -> Arc<Self> {
+            Arc::new(Self {
+                limit,
+                sent: Mutex::default(),
+            })
+        }
+
+        fn sent(&self) -> Vec<usize> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl AgentModel for Cramped {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            self.sent.lock().unwrap().push(chat.history.len());
+            if chat.history.len() > self.limit {
+                return Err(Error::ContextLengthExceeded("prompt is too long".into()));
+            }
+            Ok(Message::assistant("Done."))
+        }
+    }
+
+    fn turn(n: usize) -> Message {
+        Message::user(format!("Turn {n}: {}", "lorem ipsum ".repeat(50)))
+    }
+
+    /// Runs `chat` through the middleware, compacting to fit `max_tokens`
+    async fn run(chat: Chat, max_tokens: usize, model: Arc<Cramped>) -> Result<Chat> {
+        let runner = Runner::new(|loopback| {
+            let generate = GenerateNextMessageService::new(
+                loopback,
+                Arc::new(Claude::Haiku35),
+                Arc::new(AnthropicProvider::new()),
+            );
+            ContextRecoveryMiddleware::new(generate, max_tokens)
+        });
+        runner
+            .run(ops::generate_with_model(chat, model))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_overflow_is_retried_with_a_compacted_chat() {
+        let chat = (1..=4).fold(Chat::default(), |chat, n| chat.add_message(turn(n)));
+        let budget = Chat::default().add_message(turn(4)).tokens_used() + 1;
+        let model = Cramped::new(2);
+
+        let chat = run(chat, budget, model.clone()).await.unwrap();
+        assert_eq!(model.sent(), vec![4, 1]);
+        assert_eq!(chat.history.len(), 2);
+        assert_eq!(chat.history[0], turn(4));
+        assert_eq!(chat.history[1].text_content(), "Done.");
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_when_compaction_frees_nothing() {
+        let chat = (1..=4).fold(Chat::default(), |chat, n| chat.add_message(turn(n)));
+        let budget = Chat::default().add_message(turn(4)).tokens_used() + 1;
+
+        // The compacted retry overflows too, and compacting it again
+        // changes nothing
+        let model = Cramped::new(0);
+        let error = run(chat, budget, model.clone()).await.unwrap_err();
+        assert!(is_context_overflow(&error));
+        assert_eq!(model.sent(), vec![4, 1]);
+
+        // Nothing to drop in the first place
+        let model = Cramped::new(0);
+        let chat = Chat::default().add_message(turn(1));
+        run(chat, budget, model.clone()).await.unwrap_err();
+        assert_eq!(model.sent(), vec![1]);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+};
+
+use language_barrier_core::{
+    Chat, Message,
+    error::{Error, Result},
+};
+use tokio::sync::broadcast;
+use tower_service::Service;
+use tracing::trace;
+
+use crate::events::{self, GenerationId, RuntimeEvent};
+use crate::ops::{LlmM, LlmOp, ToolResult};
+
+use super::BoxFuture;
+
+/// Middleware that broadcasts a [`RuntimeEvent`] as operations start and
+/// finish
+///
+/// Subscribe with [`subscribe`](Self::subscribe), or to a channel from
+/// [`events::channel`] passed to [`with_sender`](Self::with_sender), before
+/// running programs; events sent while nobody is subscribed are dropped. A
+/// subscriber that falls more than the channel's capacity behind gets
+/// [`RecvError::Lagged`](broadcast::error::RecvError::Lagged) and skips
+/// ahead. Like [`RecorderMiddleware`](super::RecorderMiddleware), place it
+/// at the top of a [`Runner`](super::Runner)'s stack to see every operation
+/// of multi-step programs. Clones broadcast on the same channel.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::{Chat, Message};
+/// use language_barrier_runtime::events::{self, RuntimeEvent};
+/// use language_barrier_runtime::middleware::{
+///     ChaosConfig, ChaosMiddleware, EventsMiddleware, Runner,
+/// };
+/// use language_barrier_runtime::ops;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (sender, mut receiver) = events::channel();
+/// let runner = Runner::new(move |loopback| {
+///     let chaos = ChaosMiddleware::new(loopback, ChaosConfig::new().with_rate_limits(1.0));
+///     EventsMiddleware::new(chaos).with_sender(sender)
+/// });
+///
+/// let chat = Chat::default().add_message(Message::user("Hi"));
+/// assert!(runner.run(ops::generate_next_message(chat)).await.unwrap().is_err());
+///
+/// assert!(matches!(
+///     receiver.recv().await.unwrap(),
+///     RuntimeEvent::GenerationStarted { messages: 1, .. }
+/// ));
+/// assert!(matches!(
+///     receiver.recv().await.unwrap(),
+///     RuntimeEvent::GenerationFinished { error: Some(_), .. }
+/// ));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct EventsMiddleware<S> {
+    inner: S,
+    sender: broadcast::Sender<RuntimeEvent>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<S> EventsMiddleware<S> {
+    /// Creates a new EventsMiddleware with its own channel
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            sender: events::channel().0,
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Broadcasts on `sender`, which may be shared with other middleware
+    pub fn with_sender(self, sender: broadcast::Sender<RuntimeEvent>) -> Self {
+        Self { sender, ..self }
+    }
+
+    /// Returns a receiver for every event sent from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<RuntimeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Sends an event, ignoring the error for a channel nobody listens to
+fn emit(sender: &broadcast::Sender<RuntimeEvent>, event: RuntimeEvent) {
+    trace!("Emitting runtime event {:?}", event);
+    let _ = sender.send(event);
+}
+
+/// The events a finished model call produces
+fn finished(id: GenerationId, result: &Result<Chat>) -> Vec<RuntimeEvent> {
+    let reply = match result {
+        Ok(chat) => chat.history.last(),
+        Err(e) => {
+            return vec![RuntimeEvent::GenerationFinished {
+                id,
+                usage: None,
+                timing: None,
+                error: Some(e.to_string()),
+            }];
+        }
+    };
+
+    let mut events = Vec::new();
+    if let Some(reply) = reply {
+        let text = reply.text_content();
+        if !text.is_empty() {
+            events.push(RuntimeEvent::TokenDelta { id, text });
+        }
+        if let Message::Assistant { tool_calls, .. } = reply {
+            events.extend(tool_calls.iter().map(|tool_call| {
+                RuntimeEvent::ToolCallRequested {
+                    id,
+                    tool_call: tool_call.clone(),
+                }
+            }));
+        }
+    }
+    events.push(RuntimeEvent::GenerationFinished {
+        id,
+        usage: reply.and_then(Message::usage),
+        timing: reply.and_then(Message::stream_timing),
+        error: None,
+    });
+    events
+}
+
+/// Wraps a model call's continuation so it announces the call's start now
+/// and its outcome first
+fn announcing<Next: 'static>(
+    sender: broadcast::Sender<RuntimeEvent>,
+    id: GenerationId,
+    op: &str,
+    chat: &Chat,
+    next: Box<dyn FnOnce(Result<Chat>) -> Next + Send>,
+) -> Box<dyn FnOnce(Result<Chat>) -> Next + Send> {
+    emit(
+        &sender,
+        RuntimeEvent::GenerationStarted {
+            id,
+            op: op.to_string(),
+            messages: chat.history.len(),
+        },
+    );
+    Box::new(move |result| {
+        for event in finished(id, &result) {
+            emit(&sender, event);
+        }
+        next(result)
+    })
+}
+
+impl<S, A> Service<LlmM<A>> for EventsMiddleware<S>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let sender = self.sender.clone();
+        let next_id = self.next_id.clone();
+        let operation = program.op.take();
+        let result = program.result;
+
+        Box::pin(async move {
+            let Some(op) = operation else {
+                // If the op is None, then there should be a result
+                return match result {
+                    Some(result) => Ok(result),
+                    None => Err(Error::Other(
+                        "Invalid program state: both op and result are None".into(),
+                    )),
+                };
+            };
+            let id = || next_id.fetch_add(1, Ordering::Relaxed);
+
+            let op = match op {
+                LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next,
+                } => {
+                    let next = announcing(sender, id(), "GenerateNextMessage", &chat, next);
+                    LlmOp::GenerateNextMessage {
+                        chat,
+                        retries,
+                        next,
+                    }
+                }
+                LlmOp::SampleConsistent {
+                    chat,
+                    strategy,
+                    next,
+                } => {
+                    let next = announcing(sender, id(), "SampleConsistent", &chat, next);
+                    LlmOp::SampleConsistent {
+                        chat,
+                        strategy,
+                        next,
+                    }
+                }
+                LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next,
+                } => {
+                    let next = announcing(sender, id(), "GenerateWithModel", &chat, next);
+                    LlmOp::GenerateWithModel {
+                        chat,
+                        model,
+                        retries,
+                        next,
+                    }
+                }
+                LlmOp::ExecuteTool { tool_call, next } => {
+                    let tool_call_id = tool_call.id.clone();
+                    let name = tool_call.function.name.clone();
+                    LlmOp::ExecuteTool {
+                        tool_call,
+                        next: Box::new(move |result: Result<ToolResult>| {
+                            let outcome = match &result {
+                                Ok(result) => Ok(result.content.clone()),
+                                Err(e) => Err(e.to_string()),
+                            };
+                            emit(
+                                &sender,
+                                RuntimeEvent::ToolCallCompleted {
+                                    tool_call_id,
+                                    name,
+                                    result: outcome,
+                                },
+                            );
+                            next(result)
+                        }),
+                    }
+                }
+                op => op,
+            };
+
+            inner.call(LlmM::new(op)).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use language_barrier_core::usage::{StreamTiming, Usage};
+    use std::time::Duration;
+
+    #[test]
+    fn test_finished_reports_usage_and_stream_timing() {
+        let timing = StreamTiming::new(Duration::from_millis(300), Duration::from_millis(1_300));
+        let reply = Message::assistant("Paris")
+            .with_metadata(Usage::METADATA_KEY, Usage::new(10, 40).to_metadata())
+            .with_metadata(StreamTiming::METADATA_KEY, timing.to_metadata());
+        let chat = Chat::default().add_message(reply);
+
+        let events = finished(7, &Ok(chat));
+        assert_eq!(
+            events,
+            vec![
+                RuntimeEvent::TokenDelta {
+                    id: 7,
+                    text: "Paris".to_string()
+                },
+                RuntimeEvent::GenerationFinished {
+                    id: 7,
+                    usage: Some(Usage::new(10, 40)),
+                    timing: Some(timing),
+                    error: None,
+                },
+            ]
+        );
+        assert_eq!(timing.tokens_per_second(40), Some(40.0));
+    }
+}
@@ -5,11 +5,17 @@ use std::{
 
 use language_barrier_core::{
     HTTPLlmService, LLMService,
+    coalesce::RequestCoalescer,
     error::{Error, Result},
     model::ModelInfo,
     provider::HTTPProvider,
+    transport::{
+        ReqwestTransport, Transport,
+        rewrite::{RequestRewriter, RewritingTransport},
+    },
 };
 
+use reqwest::Client;
 use tower_service::Service;
 use tracing::debug;
 
@@ -20,8 +26,9 @@ use super::BoxFuture;
 /// Middleware that handles Chat operations
 ///
 /// This middleware processes Chat operations in the request pipeline.
-/// It stores a Chat instance, model and provider, creating a fresh HTTP client
-/// for each request via the stateless send_chat_request function.
+/// It stores a model, provider and transport; the transport (and its
+/// connection pool) is shared by every request the middleware (and its
+/// clones) sends.
 #[derive(Clone)]
 pub struct GenerateNextMessageService<S, M, P>
 where
@@ -32,6 +39,8 @@ where
     inner: S,
     provider: Arc<P>,
     model: Arc<M>,
+    transport: Arc<dyn Transport>,
+    coalescer: Option<RequestCoalescer>,
 }
 
 impl<S, M, P> GenerateNextMessageService<S, M, P>
@@ -46,6 +55,36 @@ where
             inner,
             provider: provider.clone(),
             model: model.clone(),
+            transport: Arc::new(ReqwestTransport::new()),
+            coalescer: None,
+        }
+    }
+
+    /// Sends requests through the given client instead of a private one
+    ///
+    /// Build the client with `HttpClientConfig` to tune pooling, and share it
+    /// with other services to reuse connections across providers.
+    pub fn with_client(self, client: Client) -> Self {
+        self.with_transport(Arc::new(ReqwestTransport::from(client)))
+    }
+
+    /// Sends requests through a custom transport
+    pub fn with_transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self { transport, ..self }
+    }
+
+    /// Rewrites every request just before it's sent, e.g. to route it
+    /// through an API gateway
+    pub fn with_request_rewriter(self, rewriter: impl RequestRewriter + 'static) -> Self {
+        let transport = Arc::new(RewritingTransport::new(self.transport.clone(), rewriter));
+        self.with_transport(transport)
+    }
+
+    /// Shares in-flight provider calls among identical concurrent requests
+    pub fn with_coalescer(self, coalescer: RequestCoalescer) -> Self {
+        Self {
+            coalescer: Some(coalescer),
+            ..self
         }
     }
 }
@@ -75,14 +114,19 @@ where
         // Clone model and provider to avoid borrowing self
         let model = self.model.clone();
         let provider = self.provider.clone();
+        let transport = self.transport.clone();
+        let coalescer = self.coalescer.clone();
 
         debug!("Executing");
 
         Box::pin(async move {
             match operation {
-                Some(LlmOp::GenerateNextMessage { chat, next }) => {
+                Some(LlmOp::GenerateNextMessage { chat, next, .. }) => {
                     debug!("Creating chat");
-                    let svc = HTTPLlmService::new(*model, provider);
+                    let mut svc = HTTPLlmService::new_with_transport(*model, provider, transport);
+                    if let Some(coalescer) = coalescer {
+                        svc = svc.with_coalescer(coalescer);
+                    }
                     debug!("Chat has tools configured: {:?}", chat.tools);
                     let response = svc.generate_next_message(&chat).await;
                     debug!("Done, delegating to next");
@@ -91,6 +135,35 @@ where
                     let next_program = next(response.map(|m| chat.add_message(m)));
                     inner.call(next_program).await
                 }
+                Some(LlmOp::SampleConsistent {
+                    chat,
+                    strategy,
+                    next,
+                }) => {
+                    let mut svc = HTTPLlmService::new_with_transport(*model, provider, transport);
+                    if let Some(coalescer) = coalescer {
+                        svc = svc.with_coalescer(coalescer);
+                    }
+                    let outcome = strategy.run(&svc, &chat).await;
+
+                    let next_program = next(outcome.map(|o| chat.add_message(o.answer)));
+                    inner.call(next_program).await
+                }
+                Some(LlmOp::GenerateWithModel {
+                    chat, model, next, ..
+                }) => {
+                    debug!("Generating with the operation's own model");
+                    let response = model.generate_next_message(&chat).await;
+
+                    let next_program = next(response.map(|m| chat.add_message(m)));
+                    inner.call(next_program).await
+                }
+                Some(LlmOp::Wait { until, next }) => {
+                    let remaining = until.remaining();
+                    debug!("Waiting {:?}", remaining);
+                    tokio::time::sleep(remaining).await;
+                    inner.call(next(())).await
+                }
                 Some(op) => {
                     // Not our operation, repackage and pass through
                     let repackaged = LlmM::new(op);
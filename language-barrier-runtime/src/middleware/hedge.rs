@@ -0,0 +1,314 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use language_barrier_core::{
+    Chat, LLMService, Message, ModelInfo,
+    error::{Error, Result},
+};
+
+use tower_service::Service;
+use tracing::{debug, warn};
+
+use crate::ops::{LlmM, LlmOp};
+
+use super::BoxFuture;
+
+/// Counters describing how often hedging kicked in and what it cost
+///
+/// Shared by all clones of a [`HedgeMiddleware`]. Wasted spend is an
+/// estimate, not billed usage: a cancelled request never reports its usage,
+/// so each one is counted at the chat's local token count for the prompt.
+/// Output tokens the provider generated before cancellation aren't counted.
+#[derive(Debug, Default)]
+pub struct HedgeStats {
+    requests: AtomicU64,
+    hedged: AtomicU64,
+    secondary_wins: AtomicU64,
+    wasted_input_tokens: AtomicU64,
+}
+
+impl HedgeStats {
+    /// Number of GenerateNextMessage operations handled
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of operations where the secondary was started
+    pub fn hedged(&self) -> u64 {
+        self.hedged.load(Ordering::Relaxed)
+    }
+
+    /// Number of hedged operations answered by the secondary
+    pub fn secondary_wins(&self) -> u64 {
+        self.secondary_wins.load(Ordering::Relaxed)
+    }
+
+    /// Estimated prompt tokens of cancelled requests
+    ///
+    /// This is [`Chat::tokens_used`] summed over each request that lost the
+    /// race, so it can differ from what the provider bills.
+    pub fn wasted_input_tokens(&self) -> u64 {
+        self.wasted_input_tokens.load(Ordering::Relaxed)
+    }
+}
+
+/// Middleware that races a secondary model against a slow primary
+///
+/// Each GenerateNextMessage operation goes to the primary service first. If
+/// it hasn't answered within the delay, the same chat is sent to the
+/// secondary service and whichever answers first wins; the other request is
+/// cancelled and its estimated prompt tokens are counted as wasted in
+/// [`HedgeStats`].
+/// If the first answer is an error, the other request is awaited instead.
+///
+/// The primary and secondary can be different providers, models, or both.
+pub struct HedgeMiddleware<S, MP, MS> {
+    inner: S,
+    primary: Arc<dyn LLMService<MP> + Send + Sync>,
+    secondary: Arc<dyn LLMService<MS> + Send + Sync>,
+    delay: Duration,
+    stats: Arc<HedgeStats>,
+}
+
+impl<S: Clone, MP, MS> Clone for HedgeMiddleware<S, MP, MS> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            primary: self.primary.clone(),
+            secondary: self.secondary.clone(),
+            delay: self.delay,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<S, MP, MS> HedgeMiddleware<S, MP, MS> {
+    /// Creates a new HedgeMiddleware that hedges after `delay`
+    pub fn new(
+        inner: S,
+        primary: Arc<dyn LLMService<MP> + Send + Sync>,
+        secondary: Arc<dyn LLMService<MS> + Send + Sync>,
+        delay: Duration,
+    ) -> Self {
+        Self {
+            inner,
+            primary,
+            secondary,
+            delay,
+            stats: Arc::new(HedgeStats::default()),
+        }
+    }
+
+    /// Returns the hedging counters shared by this middleware and its clones
+    pub fn stats(&self) -> Arc<HedgeStats> {
+        self.stats.clone()
+    }
+}
+
+/// Sends `chat` to the primary, hedging with the secondary after `delay`
+async fn hedged<MP: ModelInfo, MS: ModelInfo>(
+    primary: &(dyn LLMService<MP> + Send + Sync),
+    secondary: &(dyn LLMService<MS> + Send + Sync),
+    delay: Duration,
+    stats: &HedgeStats,
+    chat: &Chat,
+) -> Result<Message> {
+    stats.requests.fetch_add(1, Ordering::Relaxed);
+
+    let primary_call = primary.generate_next_message(chat);
+    tokio::pin!(primary_call);
+    tokio::select! {
+        result = &mut primary_call => return result,
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    debug!("Primary hasn't responded within {:?}, hedging", delay);
+    stats.hedged.fetch_add(1, Ordering::Relaxed);
+    let secondary_call = secondary.generate_next_message(chat);
+    tokio::pin!(secondary_call);
+
+    let cancelled_tokens = chat.tokens_used() as u64;
+    tokio::select! {
+        result = &mut primary_call => match result {
+            Ok(message) => {
+                debug!("Primary won the hedge, cancelling secondary");
+                stats.wasted_input_tokens.fetch_add(cancelled_tokens, Ordering::Relaxed);
+                Ok(message)
+            }
+            Err(e) => {
+                warn!("Primary failed while hedged, waiting for secondary: {}", e);
+                let result = secondary_call.await;
+                if result.is_ok() {
+                    stats.secondary_wins.fetch_add(1, Ordering::Relaxed);
+                }
+                result
+            }
+        },
+        result = &mut secondary_call => match result {
+            Ok(message) => {
+                debug!("Secondary won the hedge, cancelling primary");
+                stats.secondary_wins.fetch_add(1, Ordering::Relaxed);
+                stats.wasted_input_tokens.fetch_add(cancelled_tokens, Ordering::Relaxed);
+                Ok(message)
+            }
+            Err(e) => {
+                warn!("Secondary failed while hedged, waiting for primary: {}", e);
+                primary_call.await
+            }
+        },
+    }
+}
+
+impl<S, A, MP, MS> Service<LlmM<A>> for HedgeMiddleware<S, MP, MS>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+    MP: ModelInfo + 'static,
+    MS: ModelInfo + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let primary = self.primary.clone();
+        let secondary = self.secondary.clone();
+        let delay = self.delay;
+        let stats = self.stats.clone();
+        let operation = program.op.take();
+        let result = program.result;
+
+        Box::pin(async move {
+            match operation {
+                Some(LlmOp::GenerateNextMessage { chat, next, .. }) => {
+                    let response =
+                        hedged(primary.as_ref(), secondary.as_ref(), delay, &stats, &chat).await;
+
+                    // Continue with the result
+                    let next_program = next(response.map(|m| chat.add_message(m)));
+                    inner.call(next_program).await
+                }
+                Some(op) => {
+                    // Not our operation, repackage and pass through
+                    inner.call(LlmM::new(op)).await
+                }
+                None => {
+                    // If the op is None, then there should be a result
+                    if let Some(result) = result {
+                        Ok(result)
+                    } else {
+                        Err(Error::Other(
+                            "Invalid program state: both op and result are None".into(),
+                        ))
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use language_barrier_core::{Claude, OpenAi};
+    use tokio::time::Instant;
+
+    /// Answers `reply` after `after`, or fails if `reply` is None
+    struct Delayed {
+        after: Duration,
+        reply: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl<M: ModelInfo> LLMService<M> for Delayed {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            tokio::time::sleep(self.after).await;
+            match self.reply {
+                Some(text) => Ok(Message::assistant(text)),
+                None => Err(Error::Other("unavailable".into())),
+            }
+        }
+    }
+
+    fn after(millis: u64, reply: Option<&'static str>) -> Delayed {
+        Delayed {
+            after: Duration::from_millis(millis),
+            reply,
+        }
+    }
+
+    /// Races `primary` against `secondary` hedged after one second, returning
+    /// the reply, how long it took, and the stats
+    async fn race(primary: Delayed, secondary: Delayed) -> (Result<String>, Duration, HedgeStats) {
+        let primary: &(dyn LLMService<Claude> + Send + Sync) = &primary;
+        let secondary: &(dyn LLMService<OpenAi> + Send + Sync) = &secondary;
+        let stats = HedgeStats::default();
+        let started = Instant::now();
+        let reply = hedged(primary, secondary, Duration::from_secs(1), &stats, &chat()).await;
+        (reply.map(|m| m.text_content()), started.elapsed(), stats)
+    }
+
+    fn chat() -> Chat {
+        Chat::default().add_message(Message::user("What's the capital of France?"))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fast_primary_is_not_hedged() {
+        let (reply, took, stats) =
+            race(after(500, Some("primary")), after(0, Some("secondary"))).await;
+        assert_eq!(reply.unwrap(), "primary");
+        assert_eq!(took, Duration::from_millis(500));
+        assert_eq!((stats.requests(), stats.hedged()), (1, 0));
+        assert_eq!(stats.wasted_input_tokens(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_primary_wins_the_hedge() {
+        let (reply, took, stats) =
+            race(after(1500, Some("primary")), after(1000, Some("secondary"))).await;
+        assert_eq!(reply.unwrap(), "primary");
+        assert_eq!(took, Duration::from_millis(1500));
+        assert_eq!((stats.hedged(), stats.secondary_wins()), (1, 0));
+        assert_eq!(stats.wasted_input_tokens(), chat().tokens_used() as u64);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_secondary_wins_the_hedge() {
+        let (reply, took, stats) =
+            race(after(5000, Some("primary")), after(500, Some("secondary"))).await;
+        assert_eq!(reply.unwrap(), "secondary");
+        assert_eq!(took, Duration::from_millis(1500));
+        assert_eq!((stats.hedged(), stats.secondary_wins()), (1, 1));
+        assert!(stats.wasted_input_tokens() > 0);
+        assert_eq!(stats.wasted_input_tokens(), chat().tokens_used() as u64);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_primary_falls_through_to_the_secondary() {
+        let (reply, took, stats) = race(after(1200, None), after(1000, Some("secondary"))).await;
+        assert_eq!(reply.unwrap(), "secondary");
+        assert_eq!(took, Duration::from_millis(2000));
+        assert_eq!((stats.hedged(), stats.secondary_wins()), (1, 1));
+        // Nothing was cancelled, so nothing was wasted
+        assert_eq!(stats.wasted_input_tokens(), 0);
+
+        // A primary failing before the hedge isn't hedged at all
+        let (reply, took, stats) = race(after(200, None), after(0, Some("secondary"))).await;
+        assert!(reply.is_err());
+        assert_eq!(took, Duration::from_millis(200));
+        assert_eq!(stats.hedged(), 0);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    cell::Cell,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+};
+
+use language_barrier_core::error::{Error, Result};
+use tower::util::BoxCloneService;
+use tower_service::Service;
+use tracing::debug;
+
+use crate::ops::{LlmM, LlmOp};
+
+use super::BoxFuture;
+
+type Stack<A> = Arc<Mutex<BoxCloneService<LlmM<A>, A, Error>>>;
+
+tokio::task_local! {
+    /// Steps taken and the step limit of the program being run
+    static STEPS: (Cell<usize>, usize);
+}
+
+/// Runs programs that need more than one pass through a middleware stack
+///
+/// Each middleware handles its own operation and hands the continuation to
+/// the service below it, so a stack ending in [`FinalInterpreter`] can only
+/// run programs whose operations arrive in stack order. Multi-step programs
+/// (like the [`orchestration`](crate::orchestration) patterns, which
+/// interleave model calls and tool calls) need the continuation to start
+/// over at the top. Build the stack on a [`Loopback`] instead of a
+/// `FinalInterpreter` and it will.
+///
+/// An operation no middleware handles would loop forever, so a run fails
+/// once it takes more than [`with_max_steps`](Self::with_max_steps) trips
+/// around the stack.
+///
+/// [`FinalInterpreter`]: super::FinalInterpreter
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::Chat;
+/// use language_barrier_runtime::middleware::Runner;
+/// use language_barrier_runtime::ops;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// // A real stack would wrap the loopback in middleware, e.g.
+/// // `|loopback| GenerateNextMessageService::new(loopback, model, provider)`
+/// let runner = Runner::new(|loopback| loopback);
+///
+/// let program = ops::add_message(Chat::default(), ops::user_message("Hi"));
+/// let chat = runner.run(program).await.unwrap().unwrap();
+/// assert_eq!(chat.history.len(), 1);
+/// # }
+/// ```
+pub struct Runner<A> {
+    stack: Stack<A>,
+    max_steps: usize,
+}
+
+impl<A: Send + 'static> Runner<A> {
+    /// The default limit on trips around the stack in one run
+    pub const DEFAULT_MAX_STEPS: usize = 1000;
+
+    /// Builds a middleware stack on top of a loopback to itself
+    pub fn new<S, F>(build: F) -> Self
+    where
+        F: FnOnce(Loopback<A>) -> S,
+        S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+    {
+        let stack = Arc::new_cyclic(|stack| {
+            let loopback = Loopback {
+                stack: stack.clone(),
+            };
+            Mutex::new(BoxCloneService::new(build(loopback)))
+        });
+        Self {
+            stack,
+            max_steps: Self::DEFAULT_MAX_STEPS,
+        }
+    }
+
+    /// Limits how many times one run may loop back to the top of the stack
+    pub fn with_max_steps(self, max_steps: usize) -> Self {
+        Self { max_steps, ..self }
+    }
+
+    /// Runs a program to completion
+    pub async fn run(&self, program: LlmM<A>) -> Result<A> {
+        let mut service = lock(&self.stack).clone();
+        STEPS
+            .scope((Cell::new(0), self.max_steps), service.call(program))
+            .await
+    }
+}
+
+/// The bottom of a [`Runner`]'s stack
+///
+/// Finished programs resolve to their result; anything else is sent back to
+/// the top of the stack. Once the runner is dropped, unfinished programs fail.
+pub struct Loopback<A> {
+    stack: Weak<Mutex<BoxCloneService<LlmM<A>, A, Error>>>,
+}
+
+impl<A> Clone for Loopback<A> {
+    fn clone(&self) -> Self {
+        Self {
+            stack: self.stack.clone(),
+        }
+    }
+}
+
+impl<A: Send + 'static> Service<LlmM<A>> for Loopback<A> {
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, program: LlmM<A>) -> Self::Future {
+        let stack = self.stack.clone();
+
+        Box::pin(async move {
+            match (program.op, program.result) {
+                (None, Some(result)) => Ok(result),
+                (Some(LlmOp::Done { result }), None) => Err(match result {
+                    Err(e) => e,
+                    Ok(_) => Error::Other("Cannot extract value from Done operation".into()),
+                }),
+                (Some(op), None) => {
+                    let Some(stack) = stack.upgrade() else {
+                        return Err(Error::Other(
+                            "Runner was dropped while a program was running".into(),
+                        ));
+                    };
+                    let over_limit = STEPS
+                        .try_with(|(steps, max_steps)| {
+                            steps.set(steps.get() + 1);
+                            (steps.get() > *max_steps).then_some(*max_steps)
+                        })
+                        .ok()
+                        .flatten();
+                    if let Some(max_steps) = over_limit {
+                        return Err(Error::Other(format!(
+                            "Program exceeded {max_steps} steps; is an operation missing its middleware?"
+                        )));
+                    }
+                    debug!("Looping program back to the top of the stack");
+                    let mut service = lock(&stack).clone();
+                    service.call(LlmM::new(op)).await
+                }
+                _ => Err(Error::Other("Invalid program state".to_string())),
+            }
+        })
+    }
+}
+
+fn lock<A>(stack: &Stack<A>) -> std::sync::MutexGuard<'_, BoxCloneService<LlmM<A>, A, Error>> {
+    // The service is only ever cloned out, so a poisoned lock is still usable
+    stack
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -4,10 +4,28 @@ use futures::Future;
 use language_barrier_core::error::{Error, Result};
 use tower_service::Service;
 
+mod chaos;
+mod context_recovery;
+mod events;
 mod generate_next_message;
+mod hedge;
+mod loopback;
+mod normalize_history;
+mod recorder;
+mod refusal;
+mod retry;
 mod tool_executor;
 
+pub use chaos::{ChaosConfig, ChaosMiddleware};
+pub use context_recovery::ContextRecoveryMiddleware;
+pub use events::EventsMiddleware;
 pub use generate_next_message::GenerateNextMessageService;
+pub use hedge::{HedgeMiddleware, HedgeStats};
+pub use loopback::{Loopback, Runner};
+pub use normalize_history::NormalizeHistoryMiddleware;
+pub use recorder::RecorderMiddleware;
+pub use refusal::{CLARIFICATION_TAG, DEFAULT_REFUSAL_PHRASES, RefusalMiddleware, RefusalPolicy};
+pub use retry::{RetryMiddleware, RetryPolicy, RetryStats};
 pub use tool_executor::ToolExecutorMiddleware;
 
 // Re-export tower types for convenience
@@ -0,0 +1,142 @@
+use std::task::{Context, Poll};
+
+use language_barrier_core::{
+    ToolOrderNormalizer,
+    error::{Error, Result},
+};
+
+use tower_service::Service;
+use tracing::{debug, warn};
+
+use crate::ops::{LlmM, LlmOp};
+
+use super::BoxFuture;
+
+/// Middleware that repairs tool message placement before each model call
+///
+/// Place it in front of `GenerateNextMessageService` so that every chat sent
+/// to the provider has its tool results directly after the assistant message
+/// that requested them. Histories that can't be repaired are not sent at all;
+/// the validation error is handed to the program's continuation just like a
+/// failed request would be.
+#[derive(Clone)]
+pub struct NormalizeHistoryMiddleware<S> {
+    inner: S,
+    normalizer: ToolOrderNormalizer,
+}
+
+impl<S> NormalizeHistoryMiddleware<S> {
+    /// Creates a new NormalizeHistoryMiddleware using the default normalizer
+    pub fn new(inner: S) -> Self {
+        Self::with_normalizer(inner, ToolOrderNormalizer::new())
+    }
+
+    /// Creates a new NormalizeHistoryMiddleware with a custom normalizer
+    pub fn with_normalizer(inner: S, normalizer: ToolOrderNormalizer) -> Self {
+        Self { inner, normalizer }
+    }
+}
+
+impl<S, A> Service<LlmM<A>> for NormalizeHistoryMiddleware<S>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let normalizer = self.normalizer.clone();
+        let operation = program.op.take();
+        let result = program.result;
+
+        Box::pin(async move {
+            match operation {
+                Some(LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next,
+                }) => match normalizer.normalize(&chat.history) {
+                    Ok(history) => {
+                        debug!("Normalized history of {} messages", history.len());
+                        let chat = chat.with_history(history);
+                        inner
+                            .call(LlmM::new(LlmOp::GenerateNextMessage {
+                                chat,
+                                retries,
+                                next,
+                            }))
+                            .await
+                    }
+                    Err(e) => {
+                        warn!("Refusing to send chat with invalid history: {}", e);
+                        inner.call(next(Err(e))).await
+                    }
+                },
+                Some(LlmOp::SampleConsistent {
+                    chat,
+                    strategy,
+                    next,
+                }) => match normalizer.normalize(&chat.history) {
+                    Ok(history) => {
+                        let chat = chat.with_history(history);
+                        inner
+                            .call(LlmM::new(LlmOp::SampleConsistent {
+                                chat,
+                                strategy,
+                                next,
+                            }))
+                            .await
+                    }
+                    Err(e) => {
+                        warn!("Refusing to sample chat with invalid history: {}", e);
+                        inner.call(next(Err(e))).await
+                    }
+                },
+                Some(LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next,
+                }) => match normalizer.normalize(&chat.history) {
+                    Ok(history) => {
+                        let chat = chat.with_history(history);
+                        inner
+                            .call(LlmM::new(LlmOp::GenerateWithModel {
+                                chat,
+                                model,
+                                retries,
+                                next,
+                            }))
+                            .await
+                    }
+                    Err(e) => {
+                        warn!("Refusing to send chat with invalid history: {}", e);
+                        inner.call(next(Err(e))).await
+                    }
+                },
+                Some(op) => {
+                    // Not our operation, repackage and pass through
+                    inner.call(LlmM::new(op)).await
+                }
+                None => {
+                    // If the op is None, then there should be a result
+                    if let Some(result) = result {
+                        Ok(result)
+                    } else {
+                        Err(Error::Other(
+                            "Invalid program state: both op and result are None".into(),
+                        ))
+                    }
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use language_barrier_core::error::{Error, Result};
+use tower_service::Service;
+use tracing::debug;
+
+use crate::ops::{LlmM, LlmOp};
+use crate::trace::{Trace, TraceStep, TracedOp, TracedOutput};
+
+use super::BoxFuture;
+
+/// Middleware that records every operation into a [`Trace`]
+///
+/// Each operation is recorded with its input when it arrives and its output
+/// when the middleware below hands the result to the program's
+/// continuation. Place it at the top of the stack, on a
+/// [`Runner`](super::Runner), so it sees every operation of multi-step
+/// programs. Clones record into the same trace; see
+/// [`trace`](crate::trace) for replaying it.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use language_barrier_core::{Chat, Message};
+/// use language_barrier_runtime::middleware::{
+///     ChaosConfig, ChaosMiddleware, RecorderMiddleware, Runner,
+/// };
+/// use language_barrier_runtime::ops;
+/// use language_barrier_runtime::trace::{Replayer, Trace, TracedOutput};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let trace = Arc::new(Mutex::new(Trace::default()));
+/// let recorded = trace.clone();
+/// let runner = Runner::new(move |loopback| {
+///     let chaos = ChaosMiddleware::new(loopback, ChaosConfig::new().with_rate_limits(1.0));
+///     RecorderMiddleware::new(chaos).with_trace(recorded)
+/// });
+///
+/// let program = || ops::generate_next_message(Chat::default().add_message(Message::user("Hi")));
+/// assert!(runner.run(program()).await.unwrap().is_err());
+///
+/// let trace = trace.lock().unwrap().clone();
+/// assert!(matches!(trace.steps[0].output, TracedOutput::Error { .. }));
+/// assert!(Replayer::new(program(), trace).finish().unwrap().is_err());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RecorderMiddleware<S> {
+    inner: S,
+    trace: Arc<Mutex<Trace>>,
+}
+
+impl<S> RecorderMiddleware<S> {
+    /// Creates a new RecorderMiddleware with an empty trace
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            trace: Arc::new(Mutex::new(Trace::default())),
+        }
+    }
+
+    /// Records into `trace`, which may be shared with other recorders
+    pub fn with_trace(self, trace: Arc<Mutex<Trace>>) -> Self {
+        Self { trace, ..self }
+    }
+
+    /// Returns a handle to the trace being recorded
+    pub fn trace(&self) -> Arc<Mutex<Trace>> {
+        self.trace.clone()
+    }
+}
+
+/// Wraps a continuation so it records the operation's output first
+fn recording<T: 'static, Next: 'static>(
+    trace: Arc<Mutex<Trace>>,
+    op: TracedOp,
+    output: fn(&Result<T>) -> TracedOutput,
+    next: Box<dyn FnOnce(Result<T>) -> Next + Send>,
+) -> Box<dyn FnOnce(Result<T>) -> Next + Send> {
+    Box::new(move |result| {
+        let step = TraceStep {
+            output: output(&result),
+            op,
+        };
+        debug!("Recording {} operation", step.op.name());
+        trace
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .steps
+            .push(step);
+        next(result)
+    })
+}
+
+impl<S, A> Service<LlmM<A>> for RecorderMiddleware<S>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let trace = self.trace.clone();
+        let operation = program.op.take();
+        let result = program.result;
+
+        Box::pin(async move {
+            let Some(op) = operation else {
+                // If the op is None, then there should be a result
+                return match result {
+                    Some(result) => Ok(result),
+                    None => Err(Error::Other(
+                        "Invalid program state: both op and result are None".into(),
+                    )),
+                };
+            };
+            let Some(traced) = TracedOp::of(&op) else {
+                return inner.call(LlmM::new(op)).await;
+            };
+
+            let op = match op {
+                LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next,
+                } => LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next: recording(trace, traced, TracedOutput::chat, next),
+                },
+                LlmOp::SampleConsistent {
+                    chat,
+                    strategy,
+                    next,
+                } => LlmOp::SampleConsistent {
+                    chat,
+                    strategy,
+                    next: recording(trace, traced, TracedOutput::chat, next),
+                },
+                LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next,
+                } => LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next: recording(trace, traced, TracedOutput::chat, next),
+                },
+                LlmOp::ExecuteTool { tool_call, next } => LlmOp::ExecuteTool {
+                    tool_call,
+                    next: recording(trace, traced, TracedOutput::tool_result, next),
+                },
+                op => op,
+            };
+
+            inner.call(LlmM::new(op)).await
+        })
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use language_barrier_core::{
+    Chat, Message, SystemSegment,
+    error::{Error, Result},
+    message::FinishReason,
+};
+use tower_service::Service;
+use tracing::{debug, warn};
+
+use crate::agent::AgentModel;
+use crate::ops::{LlmM, LlmOp};
+
+use super::BoxFuture;
+
+type Next<A> = Box<dyn FnOnce(Result<Chat>) -> LlmM<A> + Send>;
+
+/// The tag of the system segment a clarifying retry adds
+pub const CLARIFICATION_TAG: &str = "refusal-clarification";
+
+/// Openings that mark a reply as a refusal, compared in lowercase
+pub const DEFAULT_REFUSAL_PHRASES: [&str; 10] = [
+    "i can't help with",
+    "i cannot help with",
+    "i can't assist with",
+    "i cannot assist with",
+    "i'm not able to help",
+    "i am not able to help",
+    "i'm unable to help",
+    "i am unable to help",
+    "i won't be able to help",
+    "i must decline",
+];
+
+/// How far into a reply a refusal phrase may start
+const REFUSAL_WINDOW: usize = 80;
+
+/// What [`RefusalMiddleware`] does when the model refuses
+#[derive(Clone)]
+pub enum RefusalPolicy {
+    /// Asks the same model again, with this preamble added to the system
+    /// prompt (e.g. explaining who the user is and why the request is
+    /// legitimate)
+    Clarify(String),
+    /// Asks this model instead
+    Fallback(Arc<dyn AgentModel>),
+}
+
+/// Middleware that retries once when the model refuses to answer
+///
+/// A reply is a refusal if the provider said so (see
+/// [`Message::is_refusal`]), or if it has no tool calls and opens with one
+/// of the refusal phrases ([`DEFAULT_REFUSAL_PHRASES`] unless changed with
+/// [`with_phrases`](Self::with_phrases)). Phrase-matched replies are
+/// recorded with [`FinishReason::Refusal`], so the program can tell a
+/// refusal from an answer either way.
+///
+/// GenerateNextMessage and GenerateWithModel operations whose reply is a
+/// refusal are sent again once, following the [`RefusalPolicy`]. A
+/// clarifying retry's preamble is removed from the chat it returns. If the
+/// retry refuses too, that refusal goes to the program.
+///
+/// The retry is a new operation, so the stack must be built on a
+/// [`Runner`](super::Runner) for it to reach the model again. Place this
+/// middleware above the one that calls the model.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use async_trait::async_trait;
+/// use language_barrier_core::{Chat, Claude, Message, Result};
+/// use language_barrier_core::message::FinishReason;
+/// use language_barrier_core::provider::anthropic::AnthropicProvider;
+/// use language_barrier_runtime::agent::AgentModel;
+/// use language_barrier_runtime::middleware::{
+///     GenerateNextMessageService, RefusalMiddleware, RefusalPolicy, Runner,
+/// };
+/// use language_barrier_runtime::ops;
+///
+/// /// Replies with fixed text
+/// struct Says(&'static str);
+///
+/// #[async_trait]
+/// impl AgentModel for Says {
+///     async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+///         Ok(Message::assistant(self.0))
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let fallback = RefusalPolicy::Fallback(Arc::new(Says("Here's how to pick a lock: ...")));
+/// let runner = Runner::new(move |loopback| {
+///     let model = Arc::new(Claude::Haiku35);
+///     let provider = Arc::new(AnthropicProvider::new());
+///     let generate = GenerateNextMessageService::new(loopback, model, provider);
+///     RefusalMiddleware::new(generate, fallback)
+/// });
+///
+/// let chat = Chat::default().add_message(Message::user("How do I pick my own lock?"));
+/// let strict = Arc::new(Says("I can't help with that."));
+/// let chat = runner.run(ops::generate_with_model(chat, strict)).await.unwrap().unwrap();
+///
+/// assert!(chat.history[1].text_content().starts_with("Here's how"));
+/// assert_ne!(chat.history[1].finish_reason(), Some(FinishReason::Refusal));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RefusalMiddleware<S> {
+    inner: S,
+    policy: RefusalPolicy,
+    phrases: Arc<Vec<String>>,
+}
+
+impl<S> RefusalMiddleware<S> {
+    /// Creates a new RefusalMiddleware that retries refusals following
+    /// `policy`
+    pub fn new(inner: S, policy: RefusalPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            phrases: Arc::new(DEFAULT_REFUSAL_PHRASES.map(String::from).to_vec()),
+        }
+    }
+
+    /// Recognizes refusals by these openings instead of the defaults
+    ///
+    /// Phrases are compared in lowercase. With no phrases, only refusals
+    /// the provider reports are retried.
+    pub fn with_phrases(self, phrases: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let phrases = phrases
+            .into_iter()
+            .map(|phrase| phrase.into().to_lowercase())
+            .collect();
+        Self {
+            phrases: Arc::new(phrases),
+            ..self
+        }
+    }
+}
+
+/// Returns true if `message` is a reply that refuses, by the provider's
+/// account or by its opening
+fn refuses(message: &Message, phrases: &[String]) -> bool {
+    let Message::Assistant { tool_calls, .. } = message else {
+        return false;
+    };
+    if message.is_refusal() {
+        return true;
+    }
+    if !tool_calls.is_empty() {
+        return false;
+    }
+    let opening: String = message
+        .text_content()
+        .trim_start()
+        .chars()
+        .take(REFUSAL_WINDOW)
+        .collect::<String>()
+        .to_lowercase()
+        .replace('\u{2019}', "'");
+    phrases
+        .iter()
+        .any(|phrase| opening.contains(phrase.as_str()))
+}
+
+/// Records the finish reason of a refusing reply at the end of `chat`,
+/// returning whether it refused
+fn mark_refusal(chat: &mut Chat, phrases: &[String]) -> bool {
+    let Some(reply) = chat.history.pop() else {
+        return false;
+    };
+    let refused = refuses(&reply, phrases);
+    let reply = if refused && !reply.is_refusal() {
+        reply.with_metadata(
+            FinishReason::METADATA_KEY,
+            FinishReason::Refusal.to_metadata(),
+        )
+    } else {
+        reply
+    };
+    chat.history.push(reply);
+    refused
+}
+
+/// Wraps a continuation so the refusals it receives are recorded
+fn marking<A: 'static>(phrases: Arc<Vec<String>>, next: Next<A>) -> Next<A> {
+    Box::new(move |result| {
+        next(result.map(|mut chat| {
+            mark_refusal(&mut chat, &phrases);
+            chat
+        }))
+    })
+}
+
+/// Wraps a continuation so a refusal becomes a retry following `policy`
+fn retrying<A: 'static>(
+    chat: Chat,
+    policy: RefusalPolicy,
+    phrases: Arc<Vec<String>>,
+    retry: impl FnOnce(Chat, Next<A>) -> LlmOp<LlmM<A>> + Send + 'static,
+    next: Next<A>,
+) -> Next<A> {
+    Box::new(move |result| {
+        let mut replied = match result {
+            Ok(replied) => replied,
+            Err(e) => return next(Err(e)),
+        };
+        if !mark_refusal(&mut replied, &phrases) {
+            return next(Ok(replied));
+        }
+
+        let next = marking(phrases, next);
+        match policy {
+            RefusalPolicy::Clarify(preamble) => {
+                warn!("Model refused, retrying with a clarification");
+                let clarified = chat
+                    .with_system_segment(SystemSegment::new(preamble).with_tag(CLARIFICATION_TAG));
+                let next: Next<A> = Box::new(move |result| {
+                    next(result.map(|chat| chat.without_system_segment(CLARIFICATION_TAG)))
+                });
+                LlmM::new(retry(clarified, next))
+            }
+            RefusalPolicy::Fallback(model) => {
+                warn!("Model refused, retrying with the fallback model");
+                LlmM::new(LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries: 0,
+                    next,
+                })
+            }
+        }
+    })
+}
+
+impl<S, A> Service<LlmM<A>> for RefusalMiddleware<S>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let policy = self.policy.clone();
+        let phrases = self.phrases.clone();
+        let operation = program.op.take();
+        let result = program.result;
+
+        Box::pin(async move {
+            let is_retry = |chat: &Chat| {
+                chat.system_segments
+                    .iter()
+                    .any(|segment| segment.tag.as_deref() == Some(CLARIFICATION_TAG))
+            };
+
+            let op = match operation {
+                // Retries pass through; their continuation already records
+                // refusals
+                Some(LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next,
+                }) if !is_retry(&chat) => {
+                    debug!("Guarding model call against refusal");
+                    let next = retrying(
+                        chat.clone(),
+                        policy,
+                        phrases,
+                        |chat, next| LlmOp::GenerateNextMessage {
+                            chat,
+                            retries: 0,
+                            next,
+                        },
+                        next,
+                    );
+                    LlmOp::GenerateNextMessage {
+                        chat,
+                        retries,
+                        next,
+                    }
+                }
+                Some(LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next,
+                }) if !is_retry(&chat)
+                    && !matches!(&policy, RefusalPolicy::Fallback(fallback) if Arc::ptr_eq(fallback, &model)) =>
+                {
+                    debug!("Guarding model call against refusal");
+                    let retry_model = model.clone();
+                    let next = retrying(
+                        chat.clone(),
+                        policy,
+                        phrases,
+                        move |chat, next| LlmOp::GenerateWithModel {
+                            chat,
+                            model: retry_model,
+                            retries: 0,
+                            next,
+                        },
+                        next,
+                    );
+                    LlmOp::GenerateWithModel {
+                        chat,
+                        model,
+                        retries,
+                        next,
+                    }
+                }
+                Some(op) => op,
+                None => {
+                    // If the op is None, then there should be a result
+                    return match result {
+                        Some(result) => Ok(result),
+                        None => Err(Error::Other(
+                            "Invalid program state: both op and result are None".into(),
+                        )),
+                    };
+                }
+            };
+
+            inner.call(LlmM::new(op)).await
+        })
+    }
+}
@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use language_barrier_core::{
+    Chat,
+    error::{ApiErrorKind, Error, Result},
+    model::Provider,
+};
+use tower_service::Service;
+use tracing::{debug, warn};
+
+use crate::ops::{Deadline, LlmM, LlmOp};
+
+use super::BoxFuture;
+
+type Next<A> = Box<dyn FnOnce(Result<Chat>) -> LlmM<A> + Send>;
+
+/// How many times, and how far apart, to retry one kind of failure
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Wait before the first retry
+    pub base_delay: Duration,
+    /// Longest wait between attempts, unless the provider asks for more
+    pub max_delay: Duration,
+    /// Growth of the wait from one retry to the next
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// Creates a policy that doubles the wait after each retry, up to 30
+    /// seconds
+    pub fn new(max_retries: u32, base_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            base_delay,
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+
+    /// Caps the wait between attempts at `max_delay`
+    #[must_use]
+    pub fn with_max_delay(self, max_delay: Duration) -> Self {
+        Self { max_delay, ..self }
+    }
+
+    /// Multiplies the wait by `multiplier` after each retry
+    #[must_use]
+    pub fn with_multiplier(self, multiplier: f64) -> Self {
+        Self { multiplier, ..self }
+    }
+
+    /// Returns the wait after `steps` multiplications of the base delay,
+    /// capped at the maximum
+    pub fn delay(&self, steps: i32) -> Duration {
+        let seconds = self.base_delay.as_secs_f64() * self.multiplier.powi(steps);
+        Duration::try_from_secs_f64(seconds)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// Retry policies by provider and kind of failure
+///
+/// A policy for a provider wins over the one for every provider.
+#[derive(Debug, Clone)]
+struct PolicyTable(HashMap<(Option<Provider>, ApiErrorKind), RetryPolicy>);
+
+impl Default for PolicyTable {
+    fn default() -> Self {
+        let seconds = Duration::from_secs;
+        Self(HashMap::from([
+            (
+                (None, ApiErrorKind::RateLimit),
+                RetryPolicy::new(3, seconds(1)),
+            ),
+            (
+                (None, ApiErrorKind::Timeout),
+                RetryPolicy::new(2, Duration::from_millis(500)).with_max_delay(seconds(8)),
+            ),
+            (
+                (None, ApiErrorKind::Server),
+                RetryPolicy::new(2, Duration::from_millis(500)).with_max_delay(seconds(8)),
+            ),
+            (
+                (None, ApiErrorKind::Overloaded),
+                RetryPolicy::new(3, seconds(2)).with_max_delay(seconds(60)),
+            ),
+            // Anthropic's 529s last while the whole fleet is busy, so hammering
+            // it at server-error pace only adds to the load
+            (
+                (Some(Provider::Anthropic), ApiErrorKind::Overloaded),
+                RetryPolicy::new(5, seconds(5)).with_max_delay(seconds(120)),
+            ),
+        ]))
+    }
+}
+
+impl PolicyTable {
+    fn get(&self, provider: Option<Provider>, kind: ApiErrorKind) -> Option<RetryPolicy> {
+        provider
+            .and_then(|provider| self.0.get(&(Some(provider), kind)))
+            .or_else(|| self.0.get(&(None, kind)))
+            .copied()
+    }
+}
+
+/// What a failure says about retrying: who failed, how, and how long they
+/// asked us to wait
+///
+/// A used-up daily quota won't come back within any policy's retries, so
+/// it isn't retried at all.
+fn classify(error: &Error) -> Option<(Option<Provider>, ApiErrorKind, Option<Duration>)> {
+    match error {
+        Error::Api(api) if api.is_quota_exhausted() => None,
+        Error::Api(api) => Some((api.provider, api.kind, api.retry_after())),
+        Error::RateLimit(_) => Some((None, ApiErrorKind::RateLimit, None)),
+        Error::Overloaded(_) => Some((None, ApiErrorKind::Overloaded, None)),
+        Error::Timeout(_) => Some((None, ApiErrorKind::Timeout, None)),
+        _ => None,
+    }
+}
+
+/// How far along its retries a call is
+#[derive(Debug, Clone, Copy, Default)]
+struct Attempt {
+    retries: u32,
+    overloaded: Option<Provider>,
+}
+
+/// Retry counts and the overload streak of each provider
+///
+/// Shared by all clones of a [`RetryMiddleware`].
+#[derive(Debug, Default)]
+pub struct RetryStats {
+    retries: AtomicU64,
+    overloads: Mutex<HashMap<Provider, u32>>,
+}
+
+impl RetryStats {
+    /// Number of retries scheduled
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Number of overload errors from `provider` since a retry to it last
+    /// got through
+    pub fn overload_streak(&self, provider: Provider) -> u32 {
+        self.overloads().get(&provider).copied().unwrap_or(0)
+    }
+
+    fn overloads(&self) -> MutexGuard<'_, HashMap<Provider, u32>> {
+        self.overloads
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Middleware that retries model calls that failed for reasons on the
+/// provider's side
+///
+/// GenerateNextMessage and GenerateWithModel operations that fail with a
+/// retryable error (rate limits, timeouts, server errors, overload) wait
+/// and are sent again, following the [`RetryPolicy`] for the provider and
+/// kind of failure. A wait grows by the policy's multiplier with each
+/// retry and is never shorter than a `Retry-After` the provider sent. Once
+/// the retries run out, the last error goes to the program, as does a
+/// rate limit from a used-up daily quota
+/// ([`ApiError::is_quota_exhausted`](language_barrier_core::ApiError::is_quota_exhausted)).
+///
+/// Overload backs off adaptively: each overload error from a provider
+/// lengthens the wait before the next retry to that provider, including
+/// retries of other calls, until a retried call gets through. By default
+/// Anthropic's `overloaded_error` is retried 5 times starting at 5 seconds,
+/// more patiently than a plain server error; change the table with
+/// [`with_policy`](Self::with_policy) and
+/// [`with_provider_policy`](Self::with_provider_policy).
+///
+/// The retry and its wait are new operations, so the stack must be built
+/// on a [`Runner`](super::Runner) and include a
+/// [`GenerateNextMessageService`](super::GenerateNextMessageService) to
+/// carry out the wait. A retry goes out with its count in
+/// [`LlmOp::GenerateNextMessage`]'s `retries`, and this middleware passes
+/// on operations with a nonzero count unchanged. Place this middleware
+/// above the one that calls the model.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicU32, Ordering};
+/// use std::time::Duration;
+///
+/// use async_trait::async_trait;
+/// use language_barrier_core::model::Provider;
+/// use language_barrier_core::provider::anthropic::AnthropicProvider;
+/// use language_barrier_core::transport::HeaderMap;
+/// use language_barrier_core::{ApiError, ApiErrorKind, Chat, Claude, Error, Message, Result};
+/// use language_barrier_runtime::agent::AgentModel;
+/// use language_barrier_runtime::middleware::{
+///     GenerateNextMessageService, RetryMiddleware, RetryPolicy, Runner,
+/// };
+/// use language_barrier_runtime::ops;
+///
+/// /// Overloaded for the first two calls
+/// struct Busy(AtomicU32);
+///
+/// #[async_trait]
+/// impl AgentModel for Busy {
+///     async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+///         if self.0.fetch_add(1, Ordering::Relaxed) < 2 {
+///             let body = r#"{"type":"error","error":{"type":"overloaded_error"}}"#;
+///             let error = ApiError::new(529, &HeaderMap::new(), body)
+///                 .with_provider(Some(Provider::Anthropic));
+///             return Err(Error::Api(Box::new(error)));
+///         }
+///         Ok(Message::assistant("Hello!"))
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// let mut stats = None;
+/// let runner = Runner::new(|loopback| {
+///     let model = Arc::new(Claude::Haiku35);
+///     let provider = Arc::new(AnthropicProvider::new());
+///     let generate = GenerateNextMessageService::new(loopback, model, provider);
+///     let retry = RetryMiddleware::new(generate).with_provider_policy(
+///         Provider::Anthropic,
+///         ApiErrorKind::Overloaded,
+///         RetryPolicy::new(3, Duration::from_secs(10)).with_max_delay(Duration::from_secs(60)),
+///     );
+///     stats = Some(retry.stats());
+///     retry
+/// });
+/// let stats = stats.unwrap();
+///
+/// let started = tokio::time::Instant::now();
+/// let chat = Chat::default().add_message(Message::user("Hi"));
+/// let model = Arc::new(Busy(AtomicU32::new(0)));
+/// let chat = runner.run(ops::generate_with_model(chat, model)).await.unwrap().unwrap();
+///
+/// assert_eq!(chat.history[1].text_content(), "Hello!");
+/// // 10s, then 40s: the second overload doubles the wait once for the
+/// // retry and once for the provider's streak
+/// assert_eq!(started.elapsed(), Duration::from_secs(50));
+/// assert_eq!(stats.retries(), 2);
+/// assert_eq!(stats.overload_streak(Provider::Anthropic), 0);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RetryMiddleware<S> {
+    inner: S,
+    policies: Arc<PolicyTable>,
+    stats: Arc<RetryStats>,
+}
+
+impl<S> RetryMiddleware<S> {
+    /// Creates a new RetryMiddleware with the default policies
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            policies: Arc::new(PolicyTable::default()),
+            stats: Arc::new(RetryStats::default()),
+        }
+    }
+
+    /// Retries `kind` failures from any provider following `policy`
+    #[must_use]
+    pub fn with_policy(self, kind: ApiErrorKind, policy: RetryPolicy) -> Self {
+        self.with_entry(None, kind, policy)
+    }
+
+    /// Retries `kind` failures from `provider` following `policy`, instead
+    /// of the policy for every provider
+    #[must_use]
+    pub fn with_provider_policy(
+        self,
+        provider: Provider,
+        kind: ApiErrorKind,
+        policy: RetryPolicy,
+    ) -> Self {
+        self.with_entry(Some(provider), kind, policy)
+    }
+
+    fn with_entry(
+        self,
+        provider: Option<Provider>,
+        kind: ApiErrorKind,
+        policy: RetryPolicy,
+    ) -> Self {
+        let mut policies = (*self.policies).clone();
+        policies.0.insert((provider, kind), policy);
+        Self {
+            policies: Arc::new(policies),
+            ..self
+        }
+    }
+
+    /// Returns the retry counters shared by this middleware and its clones
+    pub fn stats(&self) -> Arc<RetryStats> {
+        self.stats.clone()
+    }
+}
+
+/// Wraps a continuation so a retryable failure becomes a wait and a retry
+fn retrying<A, R>(
+    chat: Chat,
+    attempt: Attempt,
+    policies: Arc<PolicyTable>,
+    stats: Arc<RetryStats>,
+    retry: R,
+    next: Next<A>,
+) -> Next<A>
+where
+    A: 'static,
+    R: Fn(Chat, u32, Next<A>) -> LlmOp<LlmM<A>> + Clone + Send + 'static,
+{
+    Box::new(move |result| {
+        let e = match result {
+            Ok(chat) => {
+                if let Some(provider) = attempt.overloaded {
+                    debug!("{} recovered from overload", provider);
+                    stats.overloads().remove(&provider);
+                }
+                return next(Ok(chat));
+            }
+            Err(e) => e,
+        };
+        let Some((provider, kind, retry_after)) = classify(&e) else {
+            return next(Err(e));
+        };
+        let Some(policy) = policies.get(provider, kind) else {
+            return next(Err(e));
+        };
+        if attempt.retries >= policy.max_retries {
+            warn!("Giving up after {} retries: {}", attempt.retries, e);
+            return next(Err(e));
+        }
+
+        // Each overload in the provider's streak doubles (or otherwise
+        // multiplies) the wait once more
+        let mut steps = attempt.retries as i32;
+        let overloaded = match (kind, provider) {
+            (ApiErrorKind::Overloaded, Some(provider)) => {
+                let mut overloads = stats.overloads();
+                let streak = overloads.entry(provider).or_default();
+                *streak += 1;
+                steps += *streak as i32 - 1;
+                Some(provider)
+            }
+            _ => attempt.overloaded,
+        };
+        let delay = policy
+            .delay(steps)
+            .max(retry_after.unwrap_or(Duration::ZERO));
+        stats.retries.fetch_add(1, Ordering::Relaxed);
+
+        warn!(
+            "{}, retry {} of {} in {:?}",
+            e,
+            attempt.retries + 1,
+            policy.max_retries,
+            delay
+        );
+
+        // The retry's continuation counts its attempts, and the count on
+        // the operation tells the middleware not to wrap it again
+        let retries = attempt.retries + 1;
+        let next = retrying(
+            chat.clone(),
+            Attempt {
+                retries,
+                overloaded,
+            },
+            policies,
+            stats,
+            retry.clone(),
+            next,
+        );
+        LlmM::new(LlmOp::Wait {
+            until: Deadline::After(delay),
+            next: Box::new(move |()| LlmM::new(retry(chat, retries, next))),
+        })
+    })
+}
+
+impl<S, A> Service<LlmM<A>> for RetryMiddleware<S>
+where
+    S: Service<LlmM<A>, Response = A, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    A: Send + 'static,
+{
+    type Response = A;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut program: LlmM<A>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let policies = self.policies.clone();
+        let stats = self.stats.clone();
+        let operation = program.op.take();
+        let result = program.result;
+
+        // A retry coming back through the stack already counts its attempts
+        let op = match operation {
+            Some(LlmOp::GenerateNextMessage {
+                chat,
+                retries: 0,
+                next,
+            }) => {
+                let next = retrying(
+                    chat.clone(),
+                    Attempt::default(),
+                    policies,
+                    stats,
+                    |chat, retries, next| LlmOp::GenerateNextMessage {
+                        chat,
+                        retries,
+                        next,
+                    },
+                    next,
+                );
+                LlmOp::GenerateNextMessage {
+                    chat,
+                    retries: 0,
+                    next,
+                }
+            }
+            Some(LlmOp::GenerateWithModel {
+                chat,
+                model,
+                retries: 0,
+                next,
+            }) => {
+                let retry_model = model.clone();
+                let next = retrying(
+                    chat.clone(),
+                    Attempt::default(),
+                    policies,
+                    stats,
+                    move |chat, retries, next| LlmOp::GenerateWithModel {
+                        chat,
+                        model: retry_model.clone(),
+                        retries,
+                        next,
+                    },
+                    next,
+                );
+                LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries: 0,
+                    next,
+                }
+            }
+            Some(op) => op,
+            None => {
+                // If the op is None, then there should be a result
+                return Box::pin(async move {
+                    match result {
+                        Some(result) => Ok(result),
+                        None => Err(Error::Other(
+                            "Invalid program state: both op and result are None".into(),
+                        )),
+                    }
+                });
+            }
+        };
+
+        Box::pin(async move { inner.call(LlmM::new(op)).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentModel;
+    use crate::middleware::{GenerateNextMessageService, Loopback, Runner};
+    use crate::ops;
+    use async_trait::async_trait;
+    use language_barrier_core::provider::anthropic::AnthropicProvider;
+    use language_barrier_core::transport::{HeaderMap, HeaderValue};
+    use language_barrier_core::{ApiError, Claude, Message};
+    use std::sync::atomic::AtomicU32;
+    use tokio::time::Instant;
+
+    type Program = Result<Chat>;
+    type Retry =
+        RetryMiddleware<GenerateNextMessageService<Loopback<Program>, Claude, AnthropicProvider>>;
+
+    /// Fails with `error` for the first `failures` calls, then answers
+    struct Flaky {
+        calls: AtomicU32,
+        failures: u32,
+        error: fn() -> Error,
+    }
+
+    impl Flaky {
+        fn new(failures: u32, error: fn() -> Error) -> Arc<Self> {
+            Arc::new(Self {
+                calls: AtomicU32::new(0),
+                failures,
+                error,
+            })
+        }
+
+        fn calls(&self) -> u32 {
+            self.calls.load(Ordering::Relaxed)
+        }
+    }
+
+    #[async_trait]
+    impl AgentModel for Flaky {
+        async fn generate_next_message(&self, _chat: &Chat) -> Result<Message> {
+            if self.calls.fetch_add(1, Ordering::Relaxed) < self.failures {
+                return Err((self.error)());
+            }
+            Ok(Message::assistant("Hello!"))
+        }
+    }
+
+    fn api_error(status: u16, headers: &HeaderMap, body: &str) -> Error {
+        let error = ApiError::new(status, headers, body).with_provider(Some(Provider::Anthropic));
+        Error::Api(Box::new(error))
+    }
+
+    fn server_error() -> Error {
+        api_error(500, &HeaderMap::new(), "boom")
+    }
+
+    fn overloaded() -> Error {
+        let body = r#"{"type":"error","error":{"type":"overloaded_error"}}"#;
+        api_error(529, &HeaderMap::new(), body)
+    }
+
+    fn rate_limited() -> Error {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        api_error(429, &headers, "slow down")
+    }
+
+    /// A runner whose retry middleware `configure` sets up, and its stats
+    fn runner(configure: impl FnOnce(Retry) -> Retry) -> (Runner<Program>, Arc<RetryStats>) {
+        let mut stats = None;
+        let runner = Runner::new(|loopback| {
+            let model = Arc::new(Claude::Haiku35);
+            let provider = Arc::new(AnthropicProvider::new());
+            let generate = GenerateNextMessageService::new(loopback, model, provider);
+            let retry = configure(RetryMiddleware::new(generate));
+            stats = Some(retry.stats());
+            retry
+        });
+        (runner, stats.unwrap())
+    }
+
+    async fn run(runner: &Runner<Program>, model: Arc<Flaky>) -> Result<Chat> {
+        let chat = Chat::default().add_message(Message::user("Hi"));
+        runner
+            .run(ops::generate_with_model(chat, model))
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_provider_policies_win_over_the_default() {
+        let policies = PolicyTable::default();
+        let anthropic = policies.get(Some(Provider::Anthropic), ApiErrorKind::Overloaded);
+        assert_eq!(anthropic.unwrap().max_retries, 5);
+        let openai = policies.get(Some(Provider::OpenAi), ApiErrorKind::Overloaded);
+        assert_eq!(openai.unwrap().max_retries, 3);
+        assert_eq!(policies.get(None, ApiErrorKind::Overloaded), openai);
+        assert_eq!(policies.get(None, ApiErrorKind::InvalidRequest), None);
+
+        let policy = RetryPolicy::new(3, Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(10), Duration::from_secs(30));
+        assert_eq!(
+            policy.with_multiplier(f64::MAX).delay(2),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_run_out() {
+        let (runner, stats) = runner(|retry| retry);
+        let model = Flaky::new(u32::MAX, server_error);
+
+        let error = run(&runner, model.clone()).await.unwrap_err();
+        assert_eq!(error.api().unwrap().status, 500);
+        assert_eq!(model.calls(), 3);
+        assert_eq!(stats.retries(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_waits_at_least_as_long_as_retry_after() {
+        let (runner, stats) = runner(|retry| retry);
+        let model = Flaky::new(1, rate_limited);
+
+        let started = Instant::now();
+        run(&runner, model.clone()).await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(7));
+        assert_eq!(stats.retries(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_overload_streak_lasts_until_a_retry_gets_through() {
+        let (runner, stats) = runner(|retry| {
+            retry.with_provider_policy(
+                Provider::Anthropic,
+                ApiErrorKind::Overloaded,
+                RetryPolicy::new(1, Duration::from_secs(10)),
+            )
+        });
+
+        // Gives up while the provider is still overloaded
+        run(&runner, Flaky::new(u32::MAX, overloaded))
+            .await
+            .unwrap_err();
+        assert_eq!(stats.overload_streak(Provider::Anthropic), 1);
+
+        // The next call's first retry waits longer for the streak, and its
+        // success ends the streak
+        let started = Instant::now();
+        run(&runner, Flaky::new(1, overloaded)).await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(20));
+        assert_eq!(stats.overload_streak(Provider::Anthropic), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_calls_in_one_conversation_count_their_own_retries() {
+        let (runner, stats) = runner(|retry| {
+            retry.with_policy(
+                ApiErrorKind::Server,
+                RetryPolicy::new(1, Duration::from_secs(1)),
+            )
+        });
+        let chat = Chat::default().add_message(Message::user("Hi"));
+        let first = Flaky::new(u32::MAX, server_error);
+        let second = Flaky::new(u32::MAX, server_error);
+
+        let (a, b) = tokio::join!(
+            runner.run(ops::generate_with_model(chat.clone(), first.clone())),
+            runner.run(ops::generate_with_model(chat, second.clone())),
+        );
+        assert!(a.unwrap().is_err());
+        assert!(b.unwrap().is_err());
+        assert_eq!((first.calls(), second.calls()), (2, 2));
+        assert_eq!(stats.retries(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_counted_retries_pass_through() {
+        let (runner, stats) = runner(|retry| retry);
+        let model = Flaky::new(u32::MAX, server_error);
+        let chat = Chat::default().add_message(Message::user("Hi"));
+
+        // A counted retry is passed on unwrapped, so it isn't retried again
+        let program = LlmM::new(LlmOp::GenerateWithModel {
+            chat,
+            model: model.clone(),
+            retries: 1,
+            next: Box::new(LlmM::pure),
+        });
+        assert!(runner.run(program).await.unwrap().is_err());
+        assert_eq!(model.calls(), 1);
+        assert_eq!(stats.retries(), 0);
+    }
+}
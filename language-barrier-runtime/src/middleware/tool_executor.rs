@@ -23,6 +23,17 @@ pub struct ToolExecutorMiddleware<S, T: ToolDefinition> {
     auto_execute: bool,
 }
 
+impl<S: Clone, T: ToolDefinition + Clone> Clone for ToolExecutorMiddleware<S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            def: self.def.clone(),
+            f: self.f.clone(),
+            auto_execute: self.auto_execute,
+        }
+    }
+}
+
 impl<S, T> ToolExecutorMiddleware<S, T>
 where
     T: ToolDefinition + Clone + Send + Sync + 'static,
@@ -113,7 +124,7 @@ where
 
         Box::pin(async move {
             match operation {
-                Some(LlmOp::GenerateNextMessage { chat, next }) => {
+                Some(LlmOp::GenerateNextMessage { chat, retries, next }) => {
                     // For now, auto-execute mode only works in specific cases with the final API usage,
                     // so we just use a simple pass-through for all GenerateNextMessage operations
                     tracing::debug!("Auto-execute mode not fully implemented for generic types, passing through");
@@ -121,6 +132,7 @@ where
                     // Just pass through
                     let pass_through = LlmM::new(LlmOp::GenerateNextMessage { 
                         chat, 
+                        retries,
                         next 
                     });
                     inner.call(pass_through).await
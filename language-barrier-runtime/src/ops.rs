@@ -1,13 +1,18 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use language_barrier_core::{
     chat::Chat,
+    consistency::SelfConsistency,
     error::Result,
     message::{Content, Message, ToolCall},
 };
 use std::marker::Send;
 
+use crate::agent::{Agent, AgentModel, ContextPolicy};
+
 /// Tool execution result
 #[derive(Debug, Clone)]
 pub struct ToolResult {
@@ -20,10 +25,52 @@ pub enum ExecuteToolBehavior {
     AutoContinue,
 }
 
+/// When a [`LlmOp::Wait`] ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    /// This long after the interpreter reaches the operation
+    After(Duration),
+    /// At this wall-clock time, or at once if it has passed
+    At(SystemTime),
+}
+
+impl Deadline {
+    /// Returns how long is left to wait, from now
+    pub fn remaining(&self) -> Duration {
+        match self {
+            Deadline::After(duration) => *duration,
+            Deadline::At(time) => time
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        }
+    }
+}
+
 /// Free monad operations for the LLM runtime
 pub enum LlmOp<Next> {
     GenerateNextMessage {
         chat: Chat,
+        /// Retries of this call already made; see [`LlmOp::GenerateWithModel`]
+        retries: u32,
+        next: Box<dyn FnOnce(Result<Chat>) -> Next + Send>,
+    },
+    /// Sample several responses and append the most consistent one
+    SampleConsistent {
+        chat: Chat,
+        strategy: SelfConsistency,
+        next: Box<dyn FnOnce(Result<Chat>) -> Next + Send>,
+    },
+    /// Generate the next message with a specific model instead of the pipeline's
+    GenerateWithModel {
+        chat: Chat,
+        model: Arc<dyn AgentModel>,
+        /// Retries of this call already made
+        ///
+        /// Zero for a new call. A [`RetryMiddleware`](crate::middleware::RetryMiddleware)
+        /// sends a retry with the count raised and passes it on unwrapped,
+        /// since its continuation already counts attempts. A middleware that
+        /// rebuilds the operation keeps the count.
+        retries: u32,
         next: Box<dyn FnOnce(Result<Chat>) -> Next + Send>,
     },
     /// Execute a specific tool call
@@ -31,6 +78,11 @@ pub enum LlmOp<Next> {
         tool_call: ToolCall,
         next: Box<dyn FnOnce(Result<ToolResult>) -> Next + Send>,
     },
+    /// Pause the program until the deadline
+    Wait {
+        until: Deadline,
+        next: Box<dyn FnOnce(()) -> Next + Send>,
+    },
     /// Terminal operation
     Done { result: Result<Chat> },
 }
@@ -43,11 +95,28 @@ impl<Next: fmt::Debug> fmt::Debug for LlmOp<Next> {
                 .field("chat", chat)
                 .field("next", &"<function>")
                 .finish(),
+            LlmOp::SampleConsistent { chat, strategy, .. } => f
+                .debug_struct("SampleConsistent")
+                .field("chat", chat)
+                .field("strategy", strategy)
+                .field("next", &"<function>")
+                .finish(),
+            LlmOp::GenerateWithModel { chat, .. } => f
+                .debug_struct("GenerateWithModel")
+                .field("chat", chat)
+                .field("model", &"<model>")
+                .field("next", &"<function>")
+                .finish(),
             LlmOp::ExecuteTool { tool_call, .. } => f
                 .debug_struct("ExecuteTool")
                 .field("tool_call", tool_call)
                 .field("next", &"<function>")
                 .finish(),
+            LlmOp::Wait { until, .. } => f
+                .debug_struct("Wait")
+                .field("until", until)
+                .field("next", &"<function>")
+                .finish(),
             LlmOp::Done { result } => f.debug_struct("Done").field("result", result).finish(),
         }
     }
@@ -85,16 +154,43 @@ impl<A: 'static> LlmM<A> {
         match (self.op, self.result) {
             (None, Some(result)) => f(result),
             (Some(op), None) => match op {
-                LlmOp::GenerateNextMessage { chat, next } => {
-                    LlmM::new(LlmOp::GenerateNextMessage {
-                        chat,
-                        next: Box::new(move |res| next(res).and_then(f)),
-                    })
-                }
+                LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next,
+                } => LlmM::new(LlmOp::GenerateNextMessage {
+                    chat,
+                    retries,
+                    next: Box::new(move |res| next(res).and_then(f)),
+                }),
+                LlmOp::SampleConsistent {
+                    chat,
+                    strategy,
+                    next,
+                } => LlmM::new(LlmOp::SampleConsistent {
+                    chat,
+                    strategy,
+                    next: Box::new(move |res| next(res).and_then(f)),
+                }),
+                LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next,
+                } => LlmM::new(LlmOp::GenerateWithModel {
+                    chat,
+                    model,
+                    retries,
+                    next: Box::new(move |res| next(res).and_then(f)),
+                }),
                 LlmOp::ExecuteTool { tool_call, next } => LlmM::new(LlmOp::ExecuteTool {
                     tool_call,
                     next: Box::new(move |res| next(res).and_then(f)),
                 }),
+                LlmOp::Wait { until, next } => LlmM::new(LlmOp::Wait {
+                    until,
+                    next: Box::new(move |()| next(()).and_then(f)),
+                }),
                 LlmOp::Done { result } => LlmM::new(LlmOp::Done { result }),
             },
             _ => panic!("Invalid LlmM state: both op and result are None or Some"),
@@ -114,10 +210,38 @@ impl<A: 'static> LlmM<A> {
 pub fn generate_next_message(chat: Chat) -> LlmM<Result<Chat>> {
     LlmM::new(LlmOp::GenerateNextMessage {
         chat,
+        retries: 0,
+        next: Box::new(LlmM::pure),
+    })
+}
+
+pub fn sample_consistent(chat: Chat, strategy: SelfConsistency) -> LlmM<Result<Chat>> {
+    LlmM::new(LlmOp::SampleConsistent {
+        chat,
+        strategy,
+        next: Box::new(LlmM::pure),
+    })
+}
+
+pub fn generate_with_model(chat: Chat, model: Arc<dyn AgentModel>) -> LlmM<Result<Chat>> {
+    LlmM::new(LlmOp::GenerateWithModel {
+        chat,
+        model,
+        retries: 0,
         next: Box::new(LlmM::pure),
     })
 }
 
+/// Hands the conversation to `target`, which responds next
+///
+/// The policy decides how much of `chat` the target receives. The result is
+/// the transferred conversation with the target's turn appended, so later
+/// turns continue from the reduced context rather than the full history.
+pub fn handoff(chat: Chat, target: Agent, policy: ContextPolicy) -> LlmM<Result<Chat>> {
+    let transferred = policy.transfer(chat, &target);
+    and_then_ok(transferred, move |chat| target.respond(chat))
+}
+
 pub fn execute_tool(tool_call: ToolCall) -> LlmM<Result<ToolResult>> {
     LlmM::new(LlmOp::ExecuteTool {
         tool_call,
@@ -125,6 +249,75 @@ pub fn execute_tool(tool_call: ToolCall) -> LlmM<Result<ToolResult>> {
     })
 }
 
+/// Pauses the program for `duration`
+///
+/// The wait is an operation, so middleware sees it like any other: a
+/// recorder leaves it out of the trace, and replaying a trace skips it.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use async_trait::async_trait;
+/// use language_barrier_core::{Chat, Claude, Message, Result};
+/// use language_barrier_core::provider::anthropic::AnthropicProvider;
+/// use language_barrier_runtime::agent::AgentModel;
+/// use language_barrier_runtime::middleware::{GenerateNextMessageService, Runner};
+/// use language_barrier_runtime::ops;
+///
+/// /// Checks on a deployment
+/// struct Watcher;
+///
+/// #[async_trait]
+/// impl AgentModel for Watcher {
+///     async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+///         Ok(Message::assistant(format!("Check {}: still deploying", chat.history.len())))
+///     }
+/// }
+///
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// let runner = Runner::new(|loopback| {
+///     let model = Arc::new(Claude::Haiku35);
+///     let provider = Arc::new(AnthropicProvider::new());
+///     GenerateNextMessageService::new(loopback, model, provider)
+/// });
+///
+/// // Check, wait ten minutes, then check again
+/// let chat = Chat::default().add_message(Message::user("Is the deploy done?"));
+/// let program = ops::generate_with_model(chat, Arc::new(Watcher)).and_then(|checked| {
+///     ops::delay(Duration::from_secs(600)).and_then(move |()| match checked {
+///         Ok(chat) => ops::generate_with_model(chat, Arc::new(Watcher)),
+///         Err(e) => ops::done(Err(e)),
+///     })
+/// });
+///
+/// let started = tokio::time::Instant::now();
+/// let chat = runner.run(program).await.unwrap().unwrap();
+/// assert_eq!(chat.history.len(), 3);
+/// assert!(started.elapsed() >= Duration::from_secs(600));
+/// # }
+/// ```
+pub fn delay(duration: Duration) -> LlmM<()> {
+    LlmM::new(LlmOp::Wait {
+        until: Deadline::After(duration),
+        next: Box::new(LlmM::pure),
+    })
+}
+
+/// Pauses the program until `time`
+///
+/// The time is compared with the clock when the interpreter reaches the
+/// operation; a time already past doesn't wait at all.
+pub fn schedule_at(time: SystemTime) -> LlmM<()> {
+    LlmM::new(LlmOp::Wait {
+        until: Deadline::At(time),
+        next: Box::new(LlmM::pure),
+    })
+}
+
 pub fn done(result: Result<Chat>) -> LlmM<Result<Chat>> {
     LlmM::new(LlmOp::Done { result })
 }
@@ -142,3 +335,16 @@ pub fn user_message(text: impl Into<String>) -> Message {
 pub fn add_message(chat: Chat, message: Message) -> LlmM<Result<Chat>> {
     LlmM::pure(Ok(chat.add_message(message)))
 }
+
+/// Continues with `f` if the program succeeded, short-circuiting on errors
+pub(crate) fn and_then_ok<A, B, F>(program: LlmM<Result<A>>, f: F) -> LlmM<Result<B>>
+where
+    A: 'static,
+    B: 'static,
+    F: 'static + FnOnce(A) -> LlmM<Result<B>> + Send,
+{
+    program.and_then(move |result| match result {
+        Ok(value) => f(value),
+        Err(e) => LlmM::pure(Err(e)),
+    })
+}
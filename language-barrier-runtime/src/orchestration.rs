@@ -0,0 +1,278 @@
+//! Multi-agent orchestration patterns
+//!
+//! Each pattern takes a set of [`Agent`]s and a shared transcript and returns
+//! a free-monad program, so it runs on the same middleware stack as any other
+//! program and composes with them through `and_then`.
+
+use language_barrier_core::{
+    Chat,
+    error::{Error, Result},
+};
+use tracing::debug;
+
+use crate::agent::Agent;
+use crate::ops::{LlmM, and_then_ok};
+
+/// Lets each agent take one turn, in order, on the shared transcript
+///
+/// Every agent sees the replies of the agents before it, so this is a
+/// pipeline in which each agent hands its work to the next (for example
+/// drafter, reviewer, editor). Stops at the first failed turn.
+pub fn sequential(agents: Vec<Agent>, transcript: Chat) -> LlmM<Result<Chat>> {
+    agents
+        .into_iter()
+        .fold(LlmM::pure(Ok(transcript)), |program, agent| {
+            and_then_ok(program, move |transcript| agent.respond(transcript))
+        })
+}
+
+/// Asks a router agent which agent should answer, then lets that agent respond
+///
+/// The router's system prompt is extended with the name and description of
+/// every candidate and an instruction to reply with a name only. The router's
+/// reply isn't added to the transcript. Its answer is matched against the
+/// candidate names ignoring case, falling back to the first name it
+/// mentions.
+///
+/// # Errors
+///
+/// The program fails with `Error::Other` if the router doesn't name a
+/// candidate.
+pub fn route(router: &Agent, agents: Vec<Agent>, transcript: Chat) -> LlmM<Result<Chat>> {
+    let mut prompt = format!(
+        "{}\n\nChoose the agent best suited to respond next:\n",
+        router.system_prompt()
+    );
+    for agent in &agents {
+        prompt.push_str(&format!("- {}: {}\n", agent.name(), agent.description()));
+    }
+    prompt.push_str("\nReply with only the name of the agent.");
+
+    let choice = router
+        .clone()
+        .with_system_prompt(prompt)
+        .reply(&transcript)
+        .map(|reply| reply.map(|msg| msg.text_content()));
+
+    and_then_ok(choice, move |choice| match choose(&agents, &choice) {
+        Some(agent) => {
+            debug!("Router chose agent {}", agent.name());
+            agent.clone().respond(transcript)
+        }
+        None => LlmM::pure(Err(Error::Other(format!(
+            "Router reply {choice:?} does not name one of {} agents",
+            agents.len()
+        )))),
+    })
+}
+
+/// Runs `rounds` rounds of debate between agents, then lets a judge conclude
+///
+/// In each round every debater responds once, in order, seeing all earlier
+/// arguments. The judge then takes a final turn over the whole transcript.
+pub fn debate(
+    debaters: Vec<Agent>,
+    judge: Agent,
+    rounds: usize,
+    transcript: Chat,
+) -> LlmM<Result<Chat>> {
+    let turns = std::iter::repeat_n(debaters, rounds).flatten().collect();
+    and_then_ok(sequential(turns, transcript), move |transcript| {
+        judge.respond(transcript)
+    })
+}
+
+/// Returns the agent named in a router reply
+fn choose<'a>(agents: &'a [Agent], reply: &str) -> Option<&'a Agent> {
+    let reply = reply.trim().trim_matches(|c: char| !c.is_alphanumeric());
+    agents
+        .iter()
+        .find(|agent| agent.name().eq_ignore_ascii_case(reply))
+        .or_else(|| {
+            let reply = reply.to_lowercase();
+            agents
+                .iter()
+                .filter_map(|agent| {
+                    reply
+                        .find(&agent.name().to_lowercase())
+                        .map(|position| (position, agent))
+                })
+                .min_by_key(|(position, _)| *position)
+                .map(|(_, agent)| agent)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::{GenerateNextMessageService, Runner};
+    use async_trait::async_trait;
+    use language_barrier_core::provider::anthropic::AnthropicProvider;
+    use language_barrier_core::{Claude, LLMService, Message};
+    use std::sync::{Arc, Mutex};
+
+    /// Answers every request with the same text, keeping the chats it was sent
+    struct Scripted {
+        reply: Option<&'static str>,
+        requests: Mutex<Vec<Chat>>,
+    }
+
+    impl Scripted {
+        fn replying(reply: &'static str) -> Arc<Self> {
+            Arc::new(Self {
+                reply: Some(reply),
+                requests: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn failing() -> Arc<Self> {
+            Arc::new(Self {
+                reply: None,
+                requests: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn requests(&self) -> Vec<Chat> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl LLMService<Claude> for Scripted {
+        async fn generate_next_message(&self, chat: &Chat) -> Result<Message> {
+            self.requests.lock().unwrap().push(chat.clone());
+            self.reply
+                .map(Message::assistant)
+                .ok_or_else(|| Error::Other("model unavailable".into()))
+        }
+    }
+
+    fn agent(name: &str, model: &Arc<Scripted>) -> Agent {
+        Agent::new(name, format!("You are {name}."))
+            .with_description(format!("Handles {name} questions"))
+            .with_model(model.clone() as Arc<dyn LLMService<Claude> + Send + Sync>)
+    }
+
+    async fn run(program: LlmM<Result<Chat>>) -> Result<Chat> {
+        let runner = Runner::new(|loopback| {
+            let model = Arc::new(Claude::Haiku35);
+            let provider = Arc::new(AnthropicProvider::new());
+            GenerateNextMessageService::new(loopback, model, provider)
+        });
+        runner.run(program).await.unwrap()
+    }
+
+    fn transcript() -> Chat {
+        Chat::default().add_message(Message::user("Should we ship on Friday?"))
+    }
+
+    fn authors(chat: &Chat) -> Vec<Option<&str>> {
+        chat.history.iter().map(Agent::author).collect()
+    }
+
+    #[tokio::test]
+    async fn test_sequential_passes_each_reply_to_the_next_agent() {
+        let (drafter, editor) = (Scripted::replying("Draft"), Scripted::replying("Edit"));
+        let agents = vec![agent("drafter", &drafter), agent("editor", &editor)];
+
+        let chat = run(sequential(agents, transcript())).await.unwrap();
+        assert_eq!(authors(&chat), [None, Some("drafter"), Some("editor")]);
+        assert_eq!(editor.requests()[0].history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sequential_stops_at_the_first_failed_turn() {
+        let (drafter, editor) = (Scripted::failing(), Scripted::replying("Edit"));
+        let agents = vec![agent("drafter", &drafter), agent("editor", &editor)];
+
+        assert!(run(sequential(agents, transcript())).await.is_err());
+        assert_eq!(drafter.requests().len(), 1);
+        assert!(editor.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_route_hands_the_transcript_to_the_chosen_agent() {
+        let router = Scripted::replying("Ops.");
+        let (billing, ops) = (Scripted::replying("Refund"), Scripted::replying("Deploy"));
+        let agents = vec![agent("billing", &billing), agent("ops", &ops)];
+
+        let chat = run(route(&agent("router", &router), agents, transcript()))
+            .await
+            .unwrap();
+        assert_eq!(authors(&chat), [None, Some("ops")]);
+        assert!(billing.requests().is_empty());
+
+        let prompt = &router.requests()[0].system_prompt;
+        assert!(prompt.starts_with("You are router."));
+        assert!(prompt.contains("- billing: Handles billing questions\n"));
+        assert!(prompt.contains("- ops: Handles ops questions\n"));
+    }
+
+    #[tokio::test]
+    async fn test_route_falls_back_to_the_first_name_mentioned() {
+        let router = Scripted::replying("I'd ask ops rather than billing");
+        let (billing, ops) = (Scripted::replying("Refund"), Scripted::replying("Deploy"));
+        let agents = vec![agent("billing", &billing), agent("ops", &ops)];
+
+        let chat = run(route(&agent("router", &router), agents, transcript()))
+            .await
+            .unwrap();
+        assert_eq!(authors(&chat), [None, Some("ops")]);
+    }
+
+    #[tokio::test]
+    async fn test_route_fails_when_no_agent_is_named() {
+        let router = Scripted::replying("Nobody can help with that");
+        let billing = Scripted::replying("Refund");
+
+        let result = run(route(
+            &agent("router", &router),
+            vec![agent("billing", &billing)],
+            transcript(),
+        ))
+        .await;
+        assert!(matches!(result, Err(Error::Other(_))));
+        assert!(billing.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_debate_runs_every_round_then_the_judge() {
+        let (pro, con) = (Scripted::replying("Yes"), Scripted::replying("No"));
+        let judge = Scripted::replying("Ship Monday");
+        let debaters = vec![agent("pro", &pro), agent("con", &con)];
+
+        let chat = run(debate(debaters, agent("judge", &judge), 2, transcript()))
+            .await
+            .unwrap();
+        assert_eq!(
+            authors(&chat),
+            [
+                None,
+                Some("pro"),
+                Some("con"),
+                Some("pro"),
+                Some("con"),
+                Some("judge"),
+            ]
+        );
+        assert_eq!(judge.requests().len(), 1);
+        assert_eq!(judge.requests()[0].history.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_debate_without_rounds_goes_straight_to_the_judge() {
+        let pro = Scripted::replying("Yes");
+        let judge = Scripted::replying("Ship Monday");
+
+        let chat = run(debate(
+            vec![agent("pro", &pro)],
+            agent("judge", &judge),
+            0,
+            transcript(),
+        ))
+        .await
+        .unwrap();
+        assert_eq!(authors(&chat), [None, Some("judge")]);
+        assert!(pro.requests().is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+//! Recording and replaying program executions
+//!
+//! A [`RecorderMiddleware`] captures every operation a program performs,
+//! with its input and output, into a [`Trace`]. Traces serialize, so one
+//! saved from a failed production run can be loaded elsewhere and fed to a
+//! [`Replayer`], which runs the same program again without a model or
+//! tools: each operation is answered from the trace. Step through the
+//! replay one operation at a time to see where an agent went wrong.
+//!
+//! The replay follows the program's own logic, so changes to the program
+//! (a different branch, an extra call) show up as a
+//! [`diverged`](Replayer::step) error at the first operation the trace
+//! doesn't match.
+//!
+//! [`RecorderMiddleware`]: crate::middleware::RecorderMiddleware
+
+use std::fmt;
+
+use language_barrier_core::{
+    Chat,
+    error::{Error, Result},
+    message::{Message, ToolCall},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::ops::{LlmM, LlmOp, ToolResult};
+
+/// A recorded program execution
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Trace {
+    /// Every operation, in the order it ran
+    pub steps: Vec<TraceStep>,
+}
+
+/// One recorded operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// What the program asked for
+    pub op: TracedOp,
+    /// What it got back
+    pub output: TracedOutput,
+}
+
+/// The kind and input of a recorded operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum TracedOp {
+    /// A `GenerateNextMessage` operation, with the history it was sent
+    GenerateNextMessage { history: Vec<Message> },
+    /// A `SampleConsistent` operation, with the history it was sent
+    SampleConsistent { history: Vec<Message> },
+    /// A `GenerateWithModel` operation, with the history it was sent
+    GenerateWithModel { history: Vec<Message> },
+    /// An `ExecuteTool` operation
+    ExecuteTool { tool_call: ToolCall },
+}
+
+impl TracedOp {
+    /// Returns the operation's name
+    pub fn name(&self) -> &'static str {
+        match self {
+            TracedOp::GenerateNextMessage { .. } => "GenerateNextMessage",
+            TracedOp::SampleConsistent { .. } => "SampleConsistent",
+            TracedOp::GenerateWithModel { .. } => "GenerateWithModel",
+            TracedOp::ExecuteTool { .. } => "ExecuteTool",
+        }
+    }
+
+    /// Records the input of `op`, if it's an operation that gets an output
+    pub(crate) fn of<Next>(op: &LlmOp<Next>) -> Option<Self> {
+        match op {
+            LlmOp::GenerateNextMessage { chat, .. } => Some(TracedOp::GenerateNextMessage {
+                history: chat.history.clone(),
+            }),
+            LlmOp::SampleConsistent { chat, .. } => Some(TracedOp::SampleConsistent {
+                history: chat.history.clone(),
+            }),
+            LlmOp::GenerateWithModel { chat, .. } => Some(TracedOp::GenerateWithModel {
+                history: chat.history.clone(),
+            }),
+            LlmOp::ExecuteTool { tool_call, .. } => Some(TracedOp::ExecuteTool {
+                tool_call: tool_call.clone(),
+            }),
+            LlmOp::Wait { .. } | LlmOp::Done { .. } => None,
+        }
+    }
+}
+
+/// The recorded output of an operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "output")]
+pub enum TracedOutput {
+    /// The history of the chat a model operation returned
+    Chat { history: Vec<Message> },
+    /// The result of a tool call
+    ToolResult {
+        tool_call_id: String,
+        content: String,
+    },
+    /// The operation failed
+    Error { message: String },
+}
+
+impl TracedOutput {
+    pub(crate) fn chat(result: &Result<Chat>) -> Self {
+        match result {
+            Ok(chat) => TracedOutput::Chat {
+                history: chat.history.clone(),
+            },
+            Err(e) => TracedOutput::error(e),
+        }
+    }
+
+    pub(crate) fn tool_result(result: &Result<ToolResult>) -> Self {
+        match result {
+            Ok(result) => TracedOutput::ToolResult {
+                tool_call_id: result.tool_call_id.clone(),
+                content: result.content.clone(),
+            },
+            Err(e) => TracedOutput::error(e),
+        }
+    }
+
+    fn error(error: &Error) -> Self {
+        TracedOutput::Error {
+            message: error.to_string(),
+        }
+    }
+
+    /// Returns the chat the operation on `chat` returned
+    fn into_chat(self, chat: Chat) -> Result<Chat> {
+        match self {
+            TracedOutput::Chat { history } => Ok(chat.with_history(history)),
+            TracedOutput::Error { message } => Err(Error::Other(message)),
+            TracedOutput::ToolResult { .. } => Err(Error::Other(
+                "Trace holds a tool result for a model call".into(),
+            )),
+        }
+    }
+
+    fn into_tool_result(self) -> Result<ToolResult> {
+        match self {
+            TracedOutput::ToolResult {
+                tool_call_id,
+                content,
+            } => Ok(ToolResult {
+                tool_call_id,
+                content,
+            }),
+            TracedOutput::Error { message } => Err(Error::Other(message)),
+            TracedOutput::Chat { .. } => {
+                Err(Error::Other("Trace holds a chat for a tool call".into()))
+            }
+        }
+    }
+}
+
+/// Re-runs a program offline, answering its operations from a [`Trace`]
+///
+/// Recorded errors come back as `Error::Other` with the original message.
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_core::{Chat, Message};
+/// use language_barrier_runtime::ops;
+/// use language_barrier_runtime::trace::{Replayer, Trace, TraceStep, TracedOp, TracedOutput};
+///
+/// let chat = Chat::default().add_message(Message::user("Hi"));
+/// let trace = Trace {
+///     steps: vec![TraceStep {
+///         op: TracedOp::GenerateNextMessage { history: chat.history.clone() },
+///         output: TracedOutput::Chat {
+///             history: vec![Message::user("Hi"), Message::assistant("Hello!")],
+///         },
+///     }],
+/// };
+///
+/// let mut replayer = Replayer::new(ops::generate_next_message(chat), trace);
+/// assert_eq!(replayer.next_step().unwrap().op.name(), "GenerateNextMessage");
+/// assert!(replayer.step().unwrap());
+/// assert!(!replayer.step().unwrap());
+/// let chat = replayer.finish().unwrap().unwrap();
+/// assert_eq!(chat.history[1].text_content(), "Hello!");
+/// ```
+pub struct Replayer<A> {
+    program: Option<LlmM<A>>,
+    trace: Trace,
+    position: usize,
+}
+
+impl<A> fmt::Debug for Replayer<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replayer")
+            .field("position", &self.position)
+            .field("steps", &self.trace.steps.len())
+            .finish()
+    }
+}
+
+impl<A: 'static> Replayer<A> {
+    /// Prepares to replay `program` against `trace`
+    pub fn new(program: LlmM<A>, trace: Trace) -> Self {
+        Self {
+            program: Some(program),
+            trace,
+            position: 0,
+        }
+    }
+
+    /// Returns how many recorded operations have been replayed
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the recorded operation the program should perform next
+    pub fn next_step(&self) -> Option<&TraceStep> {
+        self.trace.steps.get(self.position)
+    }
+
+    /// Returns the operation the program is about to perform, if it hasn't
+    /// finished
+    pub fn pending(&self) -> Option<&LlmOp<LlmM<A>>> {
+        self.program
+            .as_ref()
+            .and_then(|program| program.op.as_ref())
+    }
+
+    /// Replays one operation, returning false once the program has finished
+    ///
+    /// # Errors
+    ///
+    /// Fails if the program performs an operation other than the one
+    /// recorded next, or runs past the end of the trace.
+    pub fn step(&mut self) -> Result<bool> {
+        let Some(mut program) = self.program.take() else {
+            return Ok(false);
+        };
+        let op = match program.op.take() {
+            finished @ (Some(LlmOp::Done { .. }) | None) => {
+                program.op = finished;
+                self.program = Some(program);
+                return Ok(false);
+            }
+            // Waits aren't recorded, and replay doesn't wait
+            Some(LlmOp::Wait { next, .. }) => {
+                self.program = Some(next(()));
+                return Ok(true);
+            }
+            Some(op) => op,
+        };
+
+        let expected = self.trace.steps.get(self.position);
+        let actual = TracedOp::of(&op).map(|op| op.name());
+        let Some(step) = expected.filter(|step| Some(step.op.name()) == actual) else {
+            let recorded = expected.map_or("the end of the trace", |step| step.op.name());
+            let error = Error::Other(format!(
+                "Replay diverged at step {}: the program ran {}, the trace has {}",
+                self.position,
+                actual.unwrap_or("Done"),
+                recorded
+            ));
+            self.program = Some(LlmM::new(op));
+            return Err(error);
+        };
+        let output = step.output.clone();
+        self.position += 1;
+
+        self.program = Some(match op {
+            LlmOp::GenerateNextMessage { chat, next, .. }
+            | LlmOp::SampleConsistent { chat, next, .. }
+            | LlmOp::GenerateWithModel { chat, next, .. } => next(output.into_chat(chat)),
+            LlmOp::ExecuteTool { next, .. } => next(output.into_tool_result()),
+            LlmOp::Wait { .. } | LlmOp::Done { .. } => {
+                unreachable!("Wait and Done are handled above")
+            }
+        });
+        Ok(true)
+    }
+
+    /// Replays the rest of the program and returns its result
+    ///
+    /// # Errors
+    ///
+    /// Fails if the replay diverges from the trace, or the program ends in
+    /// a failed `Done`.
+    pub fn finish(mut self) -> Result<A> {
+        while self.step()? {}
+        match self
+            .program
+            .take()
+            .map(|program| (program.op, program.result))
+        {
+            Some((None, Some(result))) => Ok(result),
+            Some((Some(LlmOp::Done { result }), None)) => Err(match result {
+                Err(e) => e,
+                Ok(_) => Error::Other("Cannot extract value from Done operation".into()),
+            }),
+            _ => Err(Error::Other("Invalid program state".to_string())),
+        }
+    }
+}
@@ -0,0 +1,617 @@
+//! Workflows: DAGs of LLM calls, tool calls, transforms and branches
+//!
+//! A [`Workflow`] declares named steps and the steps each one depends on.
+//! Running it executes every step once all of its dependencies are done, so
+//! independent LLM and tool calls run in parallel. Values flow between steps
+//! as JSON; steps read them back as any deserializable type with
+//! [`Values::get`].
+//!
+//! Branch steps choose which of their dependents run. A dependent the branch
+//! didn't choose is skipped, and so is everything downstream of it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use language_barrier_core::{
+    Chat,
+    error::{Error, Result},
+    message::ToolCall,
+};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::middleware::Runner;
+use crate::ops::{self, LlmM};
+
+type StepFn<T> = Arc<dyn Fn(&Values) -> Result<T> + Send + Sync>;
+
+/// Named JSON values produced by workflow steps
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Values {
+    values: HashMap<String, Value>,
+}
+
+impl Values {
+    /// Creates an empty set of values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a value, serializing it to JSON
+    pub fn with<T: Serialize>(self, name: impl Into<String>, value: T) -> Result<Self> {
+        let mut values = self.values;
+        values.insert(name.into(), serde_json::to_value(value)?);
+        Ok(Self { values })
+    }
+
+    /// Returns the value named `name` as a `T`
+    pub fn get<T: DeserializeOwned>(&self, name: &str) -> Result<T> {
+        let value = self
+            .values
+            .get(name)
+            .ok_or_else(|| Error::Other(format!("Workflow has no value named {name}")))?;
+        Ok(serde_json::from_value(value.clone())?)
+    }
+
+    /// Returns the raw JSON value named `name`
+    pub fn raw(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// Returns true if a value named `name` exists
+    pub fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    fn only(&self, names: &[String]) -> Self {
+        let values = names
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.values.get(name)?.clone())))
+            .collect();
+        Self { values }
+    }
+}
+
+/// The result of running a [`Workflow`]
+#[derive(Debug, Clone, Default)]
+pub struct WorkflowOutput {
+    /// The inputs and the output of every step that ran
+    pub values: Values,
+    /// Steps that were skipped because a branch didn't choose them
+    pub skipped: Vec<String>,
+}
+
+#[derive(Clone)]
+enum Step {
+    Input,
+    Llm(StepFn<Chat>),
+    Tool(StepFn<ToolCall>),
+    Transform(StepFn<Value>),
+    Branch(StepFn<String>),
+}
+
+impl Step {
+    fn kind(&self) -> &'static str {
+        match self {
+            Step::Input => "input",
+            Step::Llm(_) => "llm",
+            Step::Tool(_) => "tool",
+            Step::Transform(_) => "transform",
+            Step::Branch(_) => "branch",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    name: String,
+    deps: Vec<String>,
+    step: Step,
+}
+
+/// A directed acyclic graph of steps run by the runtime
+///
+/// Steps are declared with the `with_*` methods, each naming the steps it
+/// reads from. Every step sees only its dependencies' values.
+///
+/// - LLM steps build a chat, which is sent with `GenerateNextMessage`; the
+///   step's value is the reply text
+/// - Tool steps build a tool call, which is sent with `ExecuteTool`; the
+///   step's value is the result, parsed as JSON when it is valid JSON
+/// - Transforms compute a value locally
+/// - Branches return the name of the dependent step to run next
+///
+/// # Examples
+///
+/// ```
+/// use language_barrier_runtime::workflow::Workflow;
+///
+/// let workflow = Workflow::new()
+///     .with_input("topic")
+///     .with_transform("prompt", ["topic"], |values| {
+///         Ok(format!("Write a haiku about {}", values.get::<String>("topic")?))
+///     })
+///     .with_branch("length", ["prompt"], |values| {
+///         let long = values.get::<String>("prompt")?.len() > 40;
+///         Ok(if long { "shorten" } else { "keep" }.to_string())
+///     })
+///     .with_transform("shorten", ["length"], |_| Ok("short"))
+///     .with_transform("keep", ["length"], |_| Ok("kept"));
+///
+/// assert!(workflow.validate().is_ok());
+/// assert!(workflow.to_dot().contains("\"prompt\" -> \"length\";"));
+/// ```
+#[derive(Clone, Default)]
+pub struct Workflow {
+    nodes: Vec<Node>,
+}
+
+impl fmt::Debug for Workflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        for node in &self.nodes {
+            list.entry(&format_args!(
+                "{} ({}) <- {:?}",
+                node.name,
+                node.step.kind(),
+                node.deps
+            ));
+        }
+        list.finish()
+    }
+}
+
+impl Workflow {
+    /// Creates an empty workflow
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares an input that must be supplied to [`run`](Self::run)
+    #[must_use]
+    pub fn with_input(self, name: impl Into<String>) -> Self {
+        self.with_node(name.into(), Vec::new(), Step::Input)
+    }
+
+    /// Adds a step that sends the chat built by `f` to the model
+    #[must_use]
+    pub fn with_llm_step<D, F>(self, name: impl Into<String>, deps: D, f: F) -> Self
+    where
+        D: IntoIterator,
+        D::Item: Into<String>,
+        F: Fn(&Values) -> Result<Chat> + Send + Sync + 'static,
+    {
+        self.with_node(name.into(), names(deps), Step::Llm(Arc::new(f)))
+    }
+
+    /// Adds a step that executes the tool call built by `f`
+    #[must_use]
+    pub fn with_tool_step<D, F>(self, name: impl Into<String>, deps: D, f: F) -> Self
+    where
+        D: IntoIterator,
+        D::Item: Into<String>,
+        F: Fn(&Values) -> Result<ToolCall> + Send + Sync + 'static,
+    {
+        self.with_node(name.into(), names(deps), Step::Tool(Arc::new(f)))
+    }
+
+    /// Adds a step that computes its value from its dependencies
+    #[must_use]
+    pub fn with_transform<D, F, T>(self, name: impl Into<String>, deps: D, f: F) -> Self
+    where
+        D: IntoIterator,
+        D::Item: Into<String>,
+        F: Fn(&Values) -> Result<T> + Send + Sync + 'static,
+        T: Serialize,
+    {
+        let transform = move |values: &Values| Ok(serde_json::to_value(f(values)?)?);
+        self.with_node(
+            name.into(),
+            names(deps),
+            Step::Transform(Arc::new(transform)),
+        )
+    }
+
+    /// Adds a step that picks which of its dependents runs
+    ///
+    /// `f` returns the name of one dependent step; the others are skipped.
+    /// The branch's own value is the chosen name.
+    #[must_use]
+    pub fn with_branch<D, F>(self, name: impl Into<String>, deps: D, f: F) -> Self
+    where
+        D: IntoIterator,
+        D::Item: Into<String>,
+        F: Fn(&Values) -> Result<String> + Send + Sync + 'static,
+    {
+        self.with_node(name.into(), names(deps), Step::Branch(Arc::new(f)))
+    }
+
+    fn with_node(self, name: String, deps: Vec<String>, step: Step) -> Self {
+        let mut nodes = self.nodes;
+        nodes.push(Node { name, deps, step });
+        Self { nodes }
+    }
+
+    /// Checks that names are unique, dependencies exist and there are no cycles
+    pub fn validate(&self) -> Result<()> {
+        let mut seen = HashSet::new();
+        for node in &self.nodes {
+            if !seen.insert(node.name.as_str()) {
+                return Err(Error::Other(format!(
+                    "Workflow step {} is declared twice",
+                    node.name
+                )));
+            }
+        }
+        for node in &self.nodes {
+            if let Some(dep) = node.deps.iter().find(|dep| !seen.contains(dep.as_str())) {
+                return Err(Error::Other(format!(
+                    "Workflow step {} depends on unknown step {dep}",
+                    node.name
+                )));
+            }
+        }
+
+        let mut done: HashSet<&str> = HashSet::new();
+        while done.len() < self.nodes.len() {
+            let ready: Vec<&str> = self
+                .nodes
+                .iter()
+                .filter(|node| !done.contains(node.name.as_str()))
+                .filter(|node| node.deps.iter().all(|dep| done.contains(dep.as_str())))
+                .map(|node| node.name.as_str())
+                .collect();
+            if ready.is_empty() {
+                let mut stuck: Vec<&str> = self
+                    .nodes
+                    .iter()
+                    .map(|node| node.name.as_str())
+                    .filter(|name| !done.contains(name))
+                    .collect();
+                stuck.sort_unstable();
+                return Err(Error::Other(format!(
+                    "Workflow has a cycle through {}",
+                    stuck.join(", ")
+                )));
+            }
+            done.extend(ready);
+        }
+        Ok(())
+    }
+
+    /// Renders the workflow as a Graphviz DOT digraph
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph workflow {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let shape = match node.step {
+                Step::Input => "ellipse",
+                Step::Llm(_) => "box",
+                Step::Tool(_) => "component",
+                Step::Transform(_) => "note",
+                Step::Branch(_) => "diamond",
+            };
+            dot.push_str(&format!(
+                "    \"{}\" [shape={shape}, label=\"{}\\n({})\"];\n",
+                escape(&node.name),
+                escape(&node.name),
+                node.step.kind()
+            ));
+        }
+        for node in &self.nodes {
+            for dep in &node.deps {
+                dot.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    escape(dep),
+                    escape(&node.name)
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Runs the workflow, sending LLM and tool steps through `runner`
+    ///
+    /// Steps start as soon as their dependencies finish, so independent
+    /// steps run concurrently. The first failing step fails the run.
+    pub async fn run(
+        &self,
+        runner: &Runner<Result<Value>>,
+        inputs: Values,
+    ) -> Result<WorkflowOutput> {
+        self.validate()?;
+
+        let mut values = Values::new();
+        let mut skipped: HashSet<String> = HashSet::new();
+        let mut chosen: HashMap<String, String> = HashMap::new();
+        let mut started: HashSet<String> = HashSet::new();
+        let mut running = FuturesUnordered::new();
+
+        for node in &self.nodes {
+            if let Step::Input = node.step {
+                let value = inputs.raw(&node.name).cloned().ok_or_else(|| {
+                    Error::Other(format!("Workflow input {} was not supplied", node.name))
+                })?;
+                values.values.insert(node.name.clone(), value);
+                started.insert(node.name.clone());
+            }
+        }
+
+        loop {
+            let mut rescan = false;
+            for node in &self.nodes {
+                if started.contains(&node.name) {
+                    continue;
+                }
+                let finished = |dep: &String| values.contains(dep) || skipped.contains(dep);
+                if !node.deps.iter().all(finished) {
+                    continue;
+                }
+                started.insert(node.name.clone());
+
+                let not_chosen = node.deps.iter().any(|dep| {
+                    skipped.contains(dep)
+                        || chosen.get(dep).is_some_and(|choice| *choice != node.name)
+                });
+                if not_chosen {
+                    debug!("Skipping workflow step {}", node.name);
+                    skipped.insert(node.name.clone());
+                    rescan = true;
+                    continue;
+                }
+
+                let inputs = values.only(&node.deps);
+                let program = match &node.step {
+                    Step::Input => continue,
+                    Step::Transform(f) => LlmM::pure(f(&inputs)),
+                    Step::Branch(f) => LlmM::pure(f(&inputs).map(Value::String)),
+                    Step::Llm(f) => match f(&inputs) {
+                        Ok(chat) => ops::generate_next_message(chat).map(|result| {
+                            let chat = result?;
+                            let reply = chat.most_recent_message().map(|msg| msg.text_content());
+                            Ok(Value::String(reply.unwrap_or_default()))
+                        }),
+                        Err(e) => LlmM::pure(Err(e)),
+                    },
+                    Step::Tool(f) => match f(&inputs) {
+                        Ok(call) => ops::execute_tool(call).map(|result| {
+                            let content = result?.content;
+                            Ok(serde_json::from_str(&content).unwrap_or(Value::String(content)))
+                        }),
+                        Err(e) => LlmM::pure(Err(e)),
+                    },
+                };
+
+                debug!("Starting workflow step {}", node.name);
+                let name = node.name.clone();
+                let is_branch = matches!(node.step, Step::Branch(_));
+                running.push(async move {
+                    let result = runner.run(program).await.and_then(|result| result);
+                    (name, is_branch, result)
+                });
+            }
+            // Skipping a step can finish the dependencies of one declared
+            // before it
+            if rescan {
+                continue;
+            }
+
+            let Some((name, is_branch, result)) = running.next().await else {
+                break;
+            };
+            let value = match result {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("Workflow step {} failed: {}", name, e);
+                    return Err(e);
+                }
+            };
+            debug!("Workflow step {} finished", name);
+            if is_branch && let Value::String(choice) = &value {
+                chosen.insert(name.clone(), choice.clone());
+            }
+            values.values.insert(name, value);
+        }
+
+        let mut skipped: Vec<String> = skipped.into_iter().collect();
+        skipped.sort_unstable();
+        Ok(WorkflowOutput { values, skipped })
+    }
+}
+
+fn names<D>(deps: D) -> Vec<String>
+where
+    D: IntoIterator,
+    D::Item: Into<String>,
+{
+    deps.into_iter().map(Into::into).collect()
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::{BoxFuture, Loopback};
+    use crate::ops::{LlmOp, ToolResult};
+    use language_barrier_core::Message;
+    use language_barrier_core::message::Function;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+    use tower_service::Service;
+
+    type Program = Result<Value>;
+
+    /// Answers model calls with the prompt in upper case, failing on "fail",
+    /// and tool calls with their arguments, counting overlapping model calls
+    #[derive(Clone)]
+    struct Scripted {
+        inner: Loopback<Program>,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl Service<LlmM<Program>> for Scripted {
+        type Response = Program;
+        type Error = Error;
+        type Future = BoxFuture<Result<Program>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, mut program: LlmM<Program>) -> Self::Future {
+            let mut inner = self.inner.clone();
+            let in_flight = self.in_flight.clone();
+            let max_in_flight = self.max_in_flight.clone();
+            Box::pin(async move {
+                let next_program = match program.op.take() {
+                    Some(LlmOp::GenerateNextMessage { chat, next, .. }) => {
+                        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_in_flight.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                        let prompt = chat.most_recent_message().map(Message::text_content);
+                        let prompt = prompt.unwrap_or_default();
+                        if prompt == "fail" {
+                            next(Err(Error::Other("model failed".to_string())))
+                        } else {
+                            next(Ok(
+                                chat.add_message(Message::assistant(prompt.to_uppercase()))
+                            ))
+                        }
+                    }
+                    Some(LlmOp::ExecuteTool { tool_call, next }) => next(Ok(ToolResult {
+                        tool_call_id: tool_call.id,
+                        content: tool_call.function.arguments,
+                    })),
+                    Some(op) => return Err(Error::Other(format!("Unexpected {op:?}"))),
+                    None => {
+                        return program
+                            .result
+                            .ok_or_else(|| Error::Other("Empty program".to_string()));
+                    }
+                };
+                inner.call(next_program).await
+            })
+        }
+    }
+
+    fn runner() -> (Runner<Program>, Arc<AtomicUsize>) {
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let runner = Runner::new(|inner| Scripted {
+            inner,
+            in_flight: Arc::default(),
+            max_in_flight: max_in_flight.clone(),
+        });
+        (runner, max_in_flight)
+    }
+
+    fn ask(values: &Values, name: &str) -> Result<Chat> {
+        let prompt = values.get::<String>(name)?;
+        Ok(Chat::default().add_message(Message::user(prompt)))
+    }
+
+    fn inputs(topic: &str) -> Values {
+        Values::new().with("topic", topic).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_independent_steps_run_in_parallel() {
+        let (runner, max_in_flight) = runner();
+        let workflow = Workflow::new()
+            .with_input("topic")
+            .with_llm_step("first", ["topic"], |values| ask(values, "topic"))
+            .with_llm_step("second", ["topic"], |values| ask(values, "topic"))
+            .with_tool_step("lookup", ["first", "second"], |values| {
+                let arguments = serde_json::json!({
+                    "first": values.get::<String>("first")?,
+                    "second": values.get::<String>("second")?,
+                });
+                Ok(ToolCall {
+                    id: "call_1".to_string(),
+                    tool_type: "function".to_string(),
+                    function: Function {
+                        name: "lookup".to_string(),
+                        arguments: arguments.to_string(),
+                    },
+                })
+            });
+
+        let output = workflow.run(&runner, inputs("rust")).await.unwrap();
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+        assert_eq!(output.values.get::<String>("first").unwrap(), "RUST");
+        assert_eq!(
+            output.values.raw("lookup").unwrap(),
+            &serde_json::json!({ "first": "RUST", "second": "RUST" })
+        );
+        assert!(output.skipped.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_branch_runs_only_the_chosen_step() {
+        let (runner, _) = runner();
+        let workflow = Workflow::new()
+            .with_input("topic")
+            .with_branch("pick", ["topic"], |values| {
+                let topic = values.get::<String>("topic")?;
+                Ok(if topic == "rust" { "llm" } else { "local" }.to_string())
+            })
+            .with_llm_step("llm", ["pick", "topic"], |values| ask(values, "topic"))
+            .with_transform("local", ["pick"], |_| Ok("local"));
+
+        let output = workflow.run(&runner, inputs("rust")).await.unwrap();
+        assert_eq!(output.values.get::<String>("pick").unwrap(), "llm");
+        assert_eq!(output.values.get::<String>("llm").unwrap(), "RUST");
+        assert_eq!(output.skipped, ["local"]);
+
+        let output = workflow.run(&runner, inputs("go")).await.unwrap();
+        assert_eq!(output.values.get::<String>("local").unwrap(), "local");
+        assert_eq!(output.skipped, ["llm"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_skips_reach_steps_declared_before_their_dependencies() {
+        let (runner, _) = runner();
+        // The skipped chain is declared in reverse, so each skip finishes
+        // the dependency of a step the scan has already passed
+        let workflow = Workflow::new()
+            .with_input("topic")
+            .with_transform("summary", ["draft"], |_| Ok("summary"))
+            .with_transform("draft", ["outline"], |_| Ok("draft"))
+            .with_transform("outline", ["pick"], |_| Ok("outline"))
+            .with_branch("pick", ["topic"], |_| Ok("quick".to_string()))
+            .with_transform("quick", ["pick"], |_| Ok("quick"));
+
+        let output = workflow.run(&runner, inputs("rust")).await.unwrap();
+        assert_eq!(output.values.get::<String>("quick").unwrap(), "quick");
+        assert_eq!(output.skipped, ["draft", "outline", "summary"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failing_steps_fail_the_run() {
+        let (runner, _) = runner();
+        let workflow =
+            Workflow::new()
+                .with_input("topic")
+                .with_llm_step("answer", ["topic"], |values| ask(values, "topic"));
+        let error = workflow.run(&runner, inputs("fail")).await.unwrap_err();
+        assert!(error.to_string().contains("model failed"));
+
+        let workflow =
+            Workflow::new()
+                .with_input("topic")
+                .with_transform("parse", ["topic"], |values| values.get::<u32>("topic"));
+        assert!(workflow.run(&runner, inputs("rust")).await.is_err());
+
+        let error = workflow.run(&runner, Values::new()).await.unwrap_err();
+        assert!(error.to_string().contains("topic was not supplied"));
+    }
+}